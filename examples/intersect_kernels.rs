@@ -0,0 +1,48 @@
+//! Compares the kernels intersections use to count proposals against an index's values.
+//!
+//! Counts triangles in an R-MAT graph, whose skewed degrees pair short adjacency lists with long
+//! ones, by intersecting the out-neighbors of the endpoints of each edge. Each kernel is timed on
+//! the same intersections, and the adaptive kernel should be close to the best of them.
+//!
+//!     cargo run --release --example intersect_kernels [scale] [edges] [seed]
+
+extern crate alg3_dynamic;
+
+use std::time::Instant;
+
+use alg3_dynamic::io::rmat;
+use alg3_dynamic::wings_rule::{IntersectKernel, count_occurrences};
+
+fn main() {
+
+    let mut args = std::env::args().skip(1);
+    let scale = args.next().map(|x| x.parse().expect("malformed scale")).unwrap_or(16);
+    let edges = args.next().map(|x| x.parse().expect("malformed edges")).unwrap_or(1 << 20);
+    let seed = args.next().map(|x| x.parse().expect("malformed seed")).unwrap_or(0);
+
+    let mut graph = rmat(scale, edges, seed);
+    graph.sort();
+    graph.dedup();
+
+    let mut adjacency = vec![Vec::new(); 1 << scale];
+    for &(src, dst) in graph.iter() {
+        adjacency[src as usize].push(dst);
+    }
+
+    let kernels = [IntersectKernel::Adaptive, IntersectKernel::Merge, IntersectKernel::Gallop, IntersectKernel::Search, IntersectKernel::Hash];
+    let mut counts = Vec::new();
+    for &kernel in kernels.iter() {
+        let timer = Instant::now();
+        let mut triangles = 0;
+        for &(src, dst) in graph.iter() {
+            let proposals = &adjacency[src as usize];
+            let mut temp = vec![0; proposals.len()];
+            count_occurrences(kernel, proposals, &adjacency[dst as usize], &mut temp);
+            triangles += temp.iter().filter(|&&count| count > 0).count();
+        }
+        println!("{:?}\t{:?}\ttriangles: {}", kernel, timer.elapsed(), triangles);
+        counts.push(triangles);
+    }
+
+    assert!(counts.iter().all(|&count| count == counts[0]), "kernels disagree: {:?}", counts);
+}
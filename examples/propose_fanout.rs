@@ -0,0 +1,54 @@
+//! Times proposals from keys with many values, to many prefixes each.
+//!
+//! Builds an index in which each of a few hub vertices has many out-neighbors, with some updates
+//! to each hub uncommitted, and proposes the out-neighbors of the hub each prefix ends at, as
+//! extending a path by an edge does. Proposals are made both as repeated values and as counted
+//! values, which should propose the same extensions.
+//!
+//!     cargo run --release --example propose_fanout [hubs] [fanout] [prefixes]
+
+extern crate alg3_dynamic;
+
+use std::time::Instant;
+
+use alg3_dynamic::Node;
+use alg3_dynamic::wings_rule::Index;
+
+fn main() {
+
+    let mut args = std::env::args().skip(1);
+    let hubs = args.next().map(|x| x.parse().expect("malformed hubs")).unwrap_or(16);
+    let fanout = args.next().map(|x| x.parse().expect("malformed fanout")).unwrap_or(1 << 12);
+    let prefixes = args.next().map(|x| x.parse().expect("malformed prefixes")).unwrap_or(1 << 8);
+
+    // hub `h` has out-neighbors `hubs ..` with stride `h + 1`, and an uncommitted update in every tenth.
+    let mut snapshot = Vec::new();
+    let mut updates = Vec::new();
+    for hub in 0 .. hubs {
+        for value in 0 .. fanout {
+            let edge = (hub as Node, (hubs + value * (hub + 1)) as Node);
+            if value % 10 == 0 { updates.push((edge, 1)); }
+            else { snapshot.push(edge); }
+        }
+    }
+    let mut index = Index::<Node, u64>::from_snapshot(snapshot);
+    index.update(1, &mut updates);
+
+    // prefixes `[src, hub]`, each extended by the out-neighbors of its hub.
+    let records = (0 .. hubs * prefixes).map(|x| vec![(hubs * (fanout + 1) * hubs + x) as Node, (x % hubs) as Node]).collect::<Vec<_>>();
+
+    let timer = Instant::now();
+    let mut repeated = records.iter().map(|prefix| (prefix.clone(), Vec::<Node>::new(), 1i64)).collect::<Vec<_>>();
+    index.forward_propose(&mut repeated, &|prefix: &Vec<Node>| prefix[1], &1);
+    let proposed = repeated.iter().map(|x| x.1.len()).sum::<usize>();
+    println!("repeated\t{:?}\tproposals: {}", timer.elapsed(), proposed);
+
+    let timer = Instant::now();
+    let mut counted = records.iter().map(|prefix| (prefix.clone(), Vec::<(Node, i32)>::new(), 1i64)).collect::<Vec<_>>();
+    index.forward_propose(&mut counted, &|prefix: &Vec<Node>| prefix[1], &1);
+    let proposed_counted = counted.iter().map(|x| x.1.iter().map(|y| y.1 as usize).sum::<usize>()).sum::<usize>();
+    println!("counted\t{:?}\tproposals: {}", timer.elapsed(), proposed_counted);
+
+    assert_eq!(proposed, proposed_counted, "repeated and counted proposals disagree");
+    assert_eq!(proposed, hubs * prefixes * fanout, "proposals missing from the index");
+}
@@ -0,0 +1,153 @@
+//! Per-anchor delivery of matches.
+//!
+//! An `AnchorRegistry` holds a channel for each registered anchor vertex. Routing a match stream
+//! through the registry sends every change to a match involving an anchor to that anchor's channel,
+//! tagged with its timestamp. Changes are exchanged by anchor, so all changes for one anchor are
+//! delivered by the same worker, in the order that worker processes them.
+//!
+//! Anchors may be registered and unregistered while the dataflow runs; changes are delivered for
+//! the anchors registered when they are routed. The registry is shared by the workers of a process
+//! by cloning it into each worker, and an anchor is unregistered once its receiver is dropped.
+//!
+//! The registry is not shared with the workers of other processes, which would find the matches of
+//! anchors registered elsewhere, and be routed changes for them, without knowing the anchors or
+//! holding their channels. Routing anchors therefore requires every worker to run in one process:
+//! each worker routing through a registry records itself there, and the first worker to see a
+//! match checks that all of its peers have. Matches are only produced once every worker has built
+//! the dataflow, so by then the workers of the process have all routed through the registry.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+
+use ::{Node, Weight};
+use faults;
+
+/// A change to a match involving an anchor: the time, the match, and its diff.
+pub type AnchorUpdate<T> = (T, Vec<Node>, i64);
+
+/// A shared map from anchor vertices to the channels delivering their matches.
+///
+/// A registry serves the workers of one process; see the module documentation.
+pub struct AnchorRegistry<T> {
+    channels: Arc<Mutex<HashMap<Node, Sender<AnchorUpdate<T>>>>>,
+    // the indices of the workers routing matches through the registry.
+    workers: Arc<Mutex<Vec<usize>>>,
+}
+
+impl<T> Clone for AnchorRegistry<T> {
+    fn clone(&self) -> Self { AnchorRegistry { channels: self.channels.clone(), workers: self.workers.clone() } }
+}
+
+impl<T: Send> AnchorRegistry<T> {
+    /// Allocates a registry with no anchors.
+    pub fn new() -> Self {
+        AnchorRegistry { channels: Arc::new(Mutex::new(HashMap::new())), workers: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Registers `anchor`, returning the receiver of changes to its matches.
+    ///
+    /// Registering an anchor again replaces its channel, disconnecting the previous receiver.
+    pub fn register(&self, anchor: Node) -> Receiver<AnchorUpdate<T>> {
+        let (sender, receiver) = channel();
+        faults::lock(&self.channels).insert(anchor, sender);
+        receiver
+    }
+
+    /// Unregisters `anchor`, returning true if it was registered.
+    pub fn unregister(&self, anchor: Node) -> bool {
+        faults::lock(&self.channels).remove(&anchor).is_some()
+    }
+
+    /// The registered anchors, in sorted order.
+    pub fn anchors(&self) -> Vec<Node> {
+        let mut anchors = faults::lock(&self.channels).keys().cloned().collect::<Vec<_>>();
+        anchors.sort();
+        anchors
+    }
+
+    /// True if `anchor` is registered.
+    pub fn contains(&self, anchor: Node) -> bool {
+        faults::lock(&self.channels).contains_key(&anchor)
+    }
+}
+
+/// Extension method routing matches to anchor channels.
+pub trait RouteAnchors<G: Scope, W: Weight> {
+    /// Delivers each change to a match to the channel of each registered anchor it involves.
+    ///
+    /// Returns the delivered `(anchor, match, diff)` triples, which may be probed to learn when
+    /// all changes at a time have been delivered. Channels receive diffs as `i64`, whatever the
+    /// weight of the stream.
+    ///
+    /// Every worker must route through clones of the same registry, in one process; a worker
+    /// seeing a match before all of its peers have routed through the registry panics, rather than
+    /// drop the changes of anchors registered in other processes.
+    fn route_anchors(&self, registry: &AnchorRegistry<G::Timestamp>) -> Stream<G, (Node, Vec<Node>, W)>
+        where G::Timestamp: Send;
+}
+
+impl<G: Scope, W: Weight> RouteAnchors<G, W> for Stream<G, (Vec<Node>, W)> {
+    fn route_anchors(&self, registry: &AnchorRegistry<G::Timestamp>) -> Stream<G, (Node, Vec<Node>, W)>
+        where G::Timestamp: Send {
+
+        let filter = registry.clone();
+        let deliver = registry.clone();
+
+        let peers = self.scope().peers();
+        faults::lock(&registry.workers).push(self.scope().index());
+        let mut checked = false;
+
+        let mut buffer = Vec::new();
+        let mut anchors = Vec::new();
+
+        // find the anchors of each match at the worker producing it.
+        let tagged = self.unary(Pipeline, "FindAnchors", move |_,_| move |input, output| {
+            input.for_each(|time, data| {
+                if !checked {
+                    let mut workers = faults::lock(&filter.workers).clone();
+                    workers.sort();
+                    workers.dedup();
+                    assert!(workers.len() == peers, "only {} of {} workers route anchors through this registry; anchor registries serve the workers of one process", workers.len(), peers);
+                    checked = true;
+                }
+                data.swap(&mut buffer);
+                let channels = faults::lock(&filter.channels);
+                if channels.is_empty() { buffer.clear(); return; }
+                let mut session = output.session(&time);
+                for (embedding, diff) in buffer.drain(..) {
+                    anchors.clear();
+                    anchors.extend(embedding.iter().filter(|v| channels.contains_key(v)).cloned());
+                    anchors.sort();
+                    anchors.dedup();
+                    for &anchor in anchors.iter() {
+                        session.give((anchor, embedding.clone(), diff));
+                    }
+                }
+            });
+        });
+
+        // deliver changes for each anchor from the worker owning it.
+        let mut buffer = Vec::new();
+        let exchange = Exchange::new(|x: &(Node, Vec<Node>, W)| x.0 as u64);
+        tagged.unary(exchange, "DeliverAnchors", move |_,_| move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let mut channels = faults::lock(&deliver.channels);
+                let mut session = output.session(&time);
+                for (anchor, embedding, diff) in buffer.drain(..) {
+                    let delivered = match channels.get(&anchor) {
+                        Some(sender) => sender.send((time.time().clone(), embedding.clone(), diff.to_i64())).is_ok(),
+                        None => continue,
+                    };
+                    if delivered { session.give((anchor, embedding, diff)); }
+                    else { channels.remove(&anchor); }
+                }
+            });
+        })
+    }
+}
@@ -0,0 +1,401 @@
+//! Command line entry point for common workflows.
+//!
+//!     wings run --graph <path> (--plan <path> | --query <rule>) [--base N] [--batch N] [--batches N] [timely args]
+//!     wings triangles --graph <path> [--base N] [--batch N] [--batches N] [timely args]
+//!     wings bench --gen rmat [--scale S] [--edges N] [--seed S] [--plan <path>] [...] [timely args]
+//!     wings dot [--plan <path> | --query <rule>] [--graph <path> [--base N] | --gen rmat [...]]
+//!     wings rescale --checkpoint <dir> --to <dir> --to-workers M [--placement W,W,..]
+//!
+//! The first `--base` edges are loaded as the base graph, and the remaining edges are introduced
+//! in batches of `--batch` updates. Graph files may be change logs of `+ src dst` and `- src dst`
+//! lines. Each worker reports one JSON object per line for each epoch, and a summary once its
+//! input is exhausted. `rescale` re-partitions a checkpoint of every worker's index snapshots for
+//! `M` workers, so that a deployment can change its number of workers without replaying its
+//! input; see `rescale`. Given `--graph` or `--gen`, `dot` labels the plan with the costs it is
+//! estimated to have on the degrees of the first `--base` edges; see `Plan::to_dot_with_costs`.
+//!
+//! Options:
+//!
+//! * `--query RULE`: build the plan from a rule such as `'cycle3(a,b,c) :- edge(a,b), edge(b,c),
+//!   edge(c,a)'` rather than read it from a plan file; see `wings_plan::parser`.
+//! * `--schema PATH`: check the relations the query names against the schema declared in `PATH`.
+//! * `--plan PATH.json`: read a plan document (see `wings_plan::document`); needs the `json`
+//!   feature.
+//! * `--bulk N`: load the base graph in epochs of `N` edges that only maintain the indices.
+//! * `--by-time`: make each batch hold the updates of one change log time.
+//! * `--malformed skip`, or `--malformed N` to skip at most `N` lines: continue past malformed
+//!   lines, which otherwise stop the run with their file and line number; each worker then reports
+//!   an `input` object counting the lines read and skipped, and the largest node id seen.
+//! * `--chunks N`: pipeline each batch over `N` epochs, overlapping index maintenance and matching.
+//! * `--bloom BITS`: consult Bloom filters over index keys in intersections; `bench` compares this
+//!   against running without them.
+//! * `--intersect adaptive`: check edges between bound vertices in whichever of the forward and
+//!   reverse index is smaller for each prefix.
+//! * `--semantics set`: report for each epoch the set difference of its matches and those of the
+//!   previous epoch; `--semantics unordered` counts matches on the same vertices once.
+//! * `--undirected`: have each edge of the graph join its endpoints both ways.
+//! * `--count subgraphs`: report each matching subgraph once, rather than once per automorphism of
+//!   the pattern; with `--undirected` this counts e.g. each undirected triangle once.
+//! * `--placement 0,2`: hold indices and extend prefixes only on the listed workers.
+//! * `--index-copies N`: hold the indices `N` times, each copy routing keys to workers differently,
+//!   with consecutive stages of the plan reading different copies.
+//! * `--packed-prefixes`: exchange prefixes with their vertices packed into as few bits as the
+//!   largest of them needs, which reduces the data sent for graphs with small vertex ids.
+//! * `--digests`: have each reported epoch carry an order-independent digest of the worker's
+//!   changes to the matches of each query node; the wrapping sums of the digests over all workers
+//!   can be compared between runs, e.g. with different numbers of workers.
+//! * `--deterministic`: process prefixes and report changes in sorted order on each worker, so
+//!   that two runs with the same input and workers can be compared trace by trace.
+//! * `--changefeed`: also write every change to a match as a Debezium-style change event.
+//! * `--budget-ms MS`: stop enumeration for an epoch once it has taken `MS` milliseconds, and
+//!   report an `overflow` object with the partial changes in matches.
+//! * `--metadata PATH`: write the plan fingerprint, dataset, configuration, and summary statistics
+//!   of the run to `PATH` as JSON once all workers finish; see `metadata`.
+//! * `--metrics ADDR`: serve counters for scraping at `ADDR`; needs the `prometheus` feature.
+//!   Requests for `/introspect` at the same address are answered with the frontier of each stage
+//!   of each worker's dataflow and the sizes of its buffers, as JSON, naming the stage that holds
+//!   back a stalled worker.
+//! * `--read-stats`: include in the metrics the search steps, runs, and uncommitted updates that
+//!   reads of the indices visited, to guide the tuning of merging and compaction.
+
+extern crate timely;
+extern crate alg3_dynamic;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use timely::communication::Configuration;
+
+use alg3_dynamic::Edge;
+use alg3_dynamic::changefeed;
+use alg3_dynamic::config::{Config, Generator};
+use alg3_dynamic::io::{self, EdgeReader, IngestStats};
+use alg3_dynamic::metadata::{RunMetadata, Value};
+use alg3_dynamic::introspect::Introspection;
+use alg3_dynamic::metrics::Metrics;
+use alg3_dynamic::rescale;
+use alg3_dynamic::runtime::{Engine, BatchStats};
+use alg3_dynamic::wings_plan::plan::{self, Plan, PlanError};
+use alg3_dynamic::wings_plan::optimizer::DegreeStatistics;
+use alg3_dynamic::wings_plan::parser::parse_query_with;
+use alg3_dynamic::wings_plan::schema::Schema;
+
+static TRIANGLE_PLAN: &'static str = include_str!("../../triangle_plan.txt");
+
+static USAGE: &'static str = "usage: wings (run|triangles|bench|dot|rescale) [--graph <path>] [--plan <path> | --query <rule> [--schema <path>]] [--gen rmat [--scale S] [--edges N] [--seed S]] [--base N] [--bulk N] [--batch N] [--chunks N] [--batches N] [--by-time] [--bloom BITS] [--budget-ms MS] [--min-degree K] [--intersect forward|reverse|adaptive] [--semantics attributed|set|unordered] [--undirected] [--count embeddings|subgraphs] [--placement W,W,..] [--index-copies N] [--packed-prefixes] [--changefeed] [--read-stats] [--metadata PATH] [--checkpoint DIR --to DIR --to-workers M] [--metrics ADDR] [--inspect] [timely args]";
+
+fn main() {
+
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| fail(USAGE));
+
+    let (config, others) = Config::from_args(args).unwrap_or_else(|error| fail(&error));
+
+    match command.as_str() {
+        "run" => {
+            if config.plan.is_none() && config.query.is_none() { fail("run requires --plan or --query"); }
+            if config.graph.is_none() && config.generator.is_none() { fail("run requires --graph or --gen"); }
+        },
+        "triangles" => {
+            if config.plan.is_some() || config.query.is_some() { fail("triangles does not accept --plan or --query"); }
+            if config.graph.is_none() && config.generator.is_none() { fail("triangles requires --graph or --gen"); }
+        },
+        "bench" => {
+            if config.generator.is_none() { fail("bench requires --gen"); }
+        },
+        "dot" => {
+            let plan = read_plan(&config);
+            if config.graph.is_some() || config.generator.is_some() {
+                let statistics = DegreeStatistics::from_edges(&Source::open(&config).edges(config.base));
+                print!("{}", plan.to_dot_with_costs(&statistics));
+            } else {
+                print!("{}", plan.to_dot());
+            }
+            return;
+        },
+        "rescale" => {
+            let from = config.checkpoint.as_ref().unwrap_or_else(|| fail("rescale requires --checkpoint"));
+            let to = config.rescale_to.as_ref().unwrap_or_else(|| fail("rescale requires --to"));
+            let workers = config.rescale_workers.unwrap_or_else(|| fail("rescale requires --to-workers"));
+            match rescale::rescale(from, to, workers, &config.placement) {
+                Ok(previous) => println!("{{\"rescaled\":{{\"from_workers\":{},\"to_workers\":{}}}}}", previous, workers),
+                Err(error) => fail(&format!("couldn't rescale {}: {}", from, error)),
+            }
+            return;
+        },
+        _ => fail(USAGE),
+    }
+
+    let timely_config = Configuration::from_args(others.into_iter()).unwrap_or_else(|error| fail(&error));
+
+    let metrics = Metrics::new();
+    let introspection = Introspection::new();
+    metrics.set_introspection(introspection.clone());
+    if let Some(ref address) = config.metrics { serve(&metrics, address); }
+
+    let metadata = Arc::new(Mutex::new(RunMetadata::new()));
+    metadata.lock().unwrap().record_config(&config);
+    metadata.lock().unwrap().set("run.started_ms", changefeed::now_ms() as i64);
+    let sidecar = config.metadata.clone();
+    let recorded = metadata.clone();
+
+    let guards = timely::execute(timely_config, move |root| {
+
+        let mut plan = read_plan(&config);
+        plan.set_min_degree(config.min_degree);
+        plan.set_intersect_direction(config.intersect);
+        plan.set_semantics(config.semantics);
+        plan.set_orientation(config.orientation);
+        plan.set_counting(config.counting);
+        plan.set_placement(config.placement.clone());
+        plan.set_index_copies(config.index_copies);
+        plan.set_prefix_encoding(config.prefix_encoding);
+        plan.set_deterministic(config.deterministic);
+        if root.index() == 0 {
+            metrics.set_agm_exponents(plan.queries().into_iter().filter_map(|query| plan.agm_exponent(query).map(|x| (query, x))).collect());
+            recorded.lock().unwrap().record_plan(&plan);
+            introspection.register_plan(&plan);
+        }
+        let mut source = Source::open(&config);
+        let mut engine = if config.changefeed { Engine::recording(root, &plan) } else { Engine::new(root, &plan) };
+        if let Some(bits) = config.bloom { engine.enable_filter(bits); }
+        engine.set_budget(config.budget_ms.map(Duration::from_millis));
+        engine.set_digests(config.digests);
+        engine.set_read_stats(config.read_stats);
+        engine.set_introspection(Some(introspection.clone()));
+
+        let index = engine.index();
+        let peers = engine.peers();
+
+        // each worker reads the same edges, and introduces its share of them.
+        match config.bulk {
+            None => {
+                let base = share(source.edges(config.base), index, peers);
+                source.attach_position(&mut engine);
+                let stats = engine.load_base(base);
+                report("base", index, &stats);
+                recorded.lock().unwrap().record_batch(&stats);
+                metrics.record(index, &stats, engine.index_len());
+                if config.changefeed { feed(&stats); }
+            },
+            Some(part) => {
+                let mut loaded = 0;
+                while loaded < config.base {
+                    let edges = source.edges(::std::cmp::min(part, config.base - loaded));
+                    if edges.is_empty() { break; }
+                    loaded += edges.len();
+                    source.attach_position(&mut engine);
+                    let stats = engine.bulk_load(share(edges, index, peers));
+                    if config.inspect { report("bulk", index, &stats); }
+                    metrics.record(index, &stats, engine.index_len());
+                    recorded.lock().unwrap().record_batch(&stats);
+                }
+                let stats = engine.seed(Vec::new());
+                report("base", index, &stats);
+                recorded.lock().unwrap().record_batch(&stats);
+                metrics.record(index, &stats, engine.index_len());
+                if config.changefeed { feed(&stats); }
+            },
+        }
+
+        let mut totals = HashMap::new();
+        let mut batches = 0;
+        let mut elapsed = Duration::new(0, 0);
+
+        while config.batches.map(|limit| batches < limit).unwrap_or(true) {
+            let batch = source.updates(config.batch, config.by_time);
+            if batch.is_empty() { break; }
+
+            source.attach_position(&mut engine);
+            let batch = share(batch, index, peers);
+            let stats = if config.chunks > 1 { engine.step_pipelined(batch, config.chunks) } else { engine.step(batch) };
+            if config.inspect { report("update", index, &stats); }
+            if stats.discarded > 0 { overflow(index, &stats); }
+            metrics.record(index, &stats, engine.index_len());
+            recorded.lock().unwrap().record_batch(&stats);
+            if config.changefeed { feed(&stats); }
+
+            for &(query, count) in stats.matches.iter() {
+                *totals.entry(query).or_insert(0) += count;
+            }
+            elapsed += stats.index_time + stats.match_time;
+            batches += 1;
+        }
+
+        let positions = engine.frontier_positions();
+        engine.finish();
+
+        if let Some(stats) = source.stats() {
+            let max_node = stats.max_node.map(|node| node.to_string()).unwrap_or("null".to_owned());
+            println!("{{\"worker\":{},\"phase\":\"input\",\"lines\":{},\"changes\":{},\"skipped\":{},\"max_node\":{}}}",
+                     index, stats.lines, stats.changes, stats.skipped, max_node);
+            if let Some(ref error) = stats.first_skipped {
+                eprintln!("worker {} skipped {} malformed lines, the first at {}", index, stats.skipped, error);
+            }
+            recorded.lock().unwrap().record_ingest(stats);
+        }
+
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort();
+        let positions = positions.iter().map(|&(ref source, offset)| format!("{:?}:{}", source, offset)).collect::<Vec<_>>();
+        {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.set("run.workers", peers);
+            recorded.set("run.batches", batches);
+            let slowest = match recorded.get("run.elapsed_ms") { Some(&Value::Float(ms)) => ms, _ => 0.0 };
+            recorded.set("run.elapsed_ms", slowest.max(millis(elapsed)));
+        }
+
+        println!("{{\"worker\":{},\"phase\":\"summary\",\"batches\":{},\"elapsed_ms\":{},\"matches\":{},\"positions\":{{{}}}}}",
+                 index, batches, millis(elapsed), json_counts(&totals), positions.join(","));
+
+    }).unwrap();
+
+    // the workers of this process have finished once their guards are joined.
+    guards.join();
+    if let Some(path) = sidecar {
+        let mut metadata = metadata.lock().unwrap();
+        metadata.set("run.finished_ms", changefeed::now_ms() as i64);
+        metadata.write_sidecar(&path).unwrap_or_else(|error| fail(&format!("couldn't write metadata to {}: {}", path, error)));
+    }
+}
+
+/// Reads the plan file, or plans the query, or reads the triangle plan if there is neither.
+fn read_plan(config: &Config) -> Plan {
+    match (&config.plan, &config.query) {
+        (&Some(ref filename), _) if filename.ends_with(".json") => read_json_plan(filename),
+        (&Some(ref filename), _) => {
+            let (plan, warnings) = Plan::from_file_with_warnings(filename).unwrap_or_else(|error| match error {
+                PlanError::Io(reason) => fail(&reason),
+                error => fail(&format!("{}: {}", filename, error)),
+            });
+            for warning in warnings { eprintln!("WARNING: {}: {}", filename, warning); }
+            plan
+        },
+        (&None, &Some(ref query)) => {
+            let schema = match config.schema {
+                Some(ref filename) => {
+                    let text = ::std::fs::read_to_string(filename).unwrap_or_else(|error| fail(&format!("couldn't read {}: {}", filename, error)));
+                    Schema::parse(&text).unwrap_or_else(|error| fail(&format!("{}: {}", filename, error)))
+                },
+                None => Schema::default(),
+            };
+            parse_query_with(query, &schema).unwrap_or_else(|error| fail(&format!("couldn't plan query: {}", error))).plan
+        },
+        (&None, &None) => plan::read_plan_from(TRIANGLE_PLAN.as_bytes()),
+    }
+}
+
+#[cfg(feature = "json")]
+fn read_json_plan(filename: &str) -> Plan {
+    let text = ::std::fs::read_to_string(filename).unwrap_or_else(|error| fail(&format!("couldn't read {}: {}", filename, error)));
+    Plan::from_json(&text).unwrap_or_else(|error| fail(&format!("{}: {}", filename, error)))
+}
+
+#[cfg(not(feature = "json"))]
+fn read_json_plan(filename: &str) -> Plan {
+    fail(&format!("{} is a JSON plan, which wings reads when built with --features json", filename))
+}
+
+/// A sequence of edges, read from a file or generated.
+enum Source {
+    File(EdgeReader),
+    Generated(::std::vec::IntoIter<Edge>),
+}
+
+impl Source {
+    fn open(config: &Config) -> Source {
+        match config.generator {
+            Some(Generator::Rmat { scale, edges, seed }) => Source::Generated(io::rmat(scale, edges, seed).into_iter()),
+            None => {
+                let graph = config.graph.as_ref().unwrap();
+                let mut reader = EdgeReader::open(graph).unwrap_or_else(|error| fail(&format!("couldn't open {}: {}", graph, error)));
+                reader.set_strictness(config.malformed);
+                Source::File(reader)
+            },
+        }
+    }
+
+    fn edges(&mut self, limit: usize) -> Vec<Edge> {
+        match *self {
+            Source::File(ref mut reader) => reader.read_edges(limit).unwrap_or_else(|error| fail(&format!("bad input: {}", error))),
+            Source::Generated(ref mut edges) => edges.take(limit).collect(),
+        }
+    }
+
+    // records how far the source has been read in the engine's current epoch.
+    fn attach_position<A: ::timely::communication::Allocate>(&self, engine: &mut Engine<A>) {
+        match *self {
+            Source::File(ref reader) => {
+                if let Some((path, lines)) = reader.position() { engine.attach_position(&path, lines); }
+            },
+            Source::Generated(_) => { },
+        }
+    }
+
+    // statistics of the input read from a file, if any.
+    fn stats(&self) -> Option<&IngestStats> {
+        match *self {
+            Source::File(ref reader) => Some(reader.stats()),
+            Source::Generated(_) => None,
+        }
+    }
+
+    fn updates(&mut self, limit: usize, by_time: bool) -> Vec<(Edge, i32)> {
+        match *self {
+            Source::File(ref mut reader) if by_time => reader.read_epoch(limit).unwrap_or_else(|error| fail(&format!("bad input: {}", error))).1,
+            Source::File(ref mut reader) => reader.read_updates(limit).unwrap_or_else(|error| fail(&format!("bad input: {}", error))),
+            Source::Generated(ref mut edges) => edges.take(limit).map(|edge| (edge, 1)).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+fn serve(metrics: &Metrics, address: &str) {
+    metrics.serve(address).unwrap_or_else(|error| fail(&format!("couldn't serve metrics at {}: {}", address, error)));
+}
+
+#[cfg(not(feature = "prometheus"))]
+fn serve(_metrics: &Metrics, _address: &str) {
+    fail("--metrics requires building with the prometheus feature");
+}
+
+fn share<D>(data: Vec<D>, index: usize, peers: usize) -> Vec<D> {
+    data.into_iter().enumerate().filter(|&(i, _)| i % peers == index).map(|(_, datum)| datum).collect()
+}
+
+fn report(phase: &str, worker: usize, stats: &BatchStats) {
+    // digests are only computed with `--digests`, and are then summed over workers to compare runs.
+    let digests = if stats.digests.is_empty() { String::new() } else {
+        let fields = stats.digests.iter().map(|&(query, digest)| format!("\"{}\":\"{:016x}\"", query, digest)).collect::<Vec<_>>();
+        format!(",\"digests\":{{{}}}", fields.join(","))
+    };
+    println!("{{\"worker\":{},\"phase\":\"{}\",\"epoch\":{},\"updates\":{},\"index_ms\":{},\"match_ms\":{},\"matches\":{}{}}}",
+             worker, phase, stats.epoch, stats.updates, millis(stats.index_time), millis(stats.match_time), json_counts(&stats.matches), digests);
+}
+
+fn feed(stats: &BatchStats) {
+    let stdout = ::std::io::stdout();
+    changefeed::write_batch(&mut stdout.lock(), stats, changefeed::now_ms()).unwrap_or_else(|error| fail(&format!("couldn't write change events: {}", error)));
+}
+
+// reports an epoch whose matching exceeded its budget, and whose changes in matches are partial.
+fn overflow(worker: usize, stats: &BatchStats) {
+    println!("{{\"worker\":{},\"phase\":\"overflow\",\"epoch\":{},\"discarded\":{},\"partial\":true,\"matches\":{}}}",
+             worker, stats.epoch, stats.discarded, json_counts(&stats.matches));
+}
+
+fn json_counts(counts: &[(usize, i64)]) -> String {
+    let fields = counts.iter().map(|&(query, count)| format!("\"{}\":{}", query, count)).collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + duration.subsec_nanos() as f64 / 1e6
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    ::std::process::exit(1);
+}
@@ -0,0 +1,51 @@
+//! Change events for matches, in the style of Debezium envelopes.
+//!
+//! Each change to a match is encoded as one JSON object per line, with the fields of a Debezium
+//! change event: `op` is `c` for a created match and `d` for a deleted one, `before` and `after`
+//! hold the match before and after the change (`null` where it does not exist), `source` names the
+//! query node and epoch that produced it, and `ts_ms` is the time the event was encoded. A match is
+//! encoded as `{"query": q, "vertices": [...]}`, whose vertices are in the order of the plan's
+//! bound attributes.
+//!
+//! A change with a diff of `n` is encoded as `|n|` identical events, as CDC consumers expect each
+//! event to describe a single row. Events are written from the `embeddings` of batch statistics,
+//! which only an engine built with `Engine::recording` reports.
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use runtime::BatchStats;
+use ::Node;
+
+/// The name reported as the connector of each event's source.
+pub const CONNECTOR: &'static str = "wings";
+
+/// Encodes a change of `diff` to the match `embedding` of `query` at `epoch` as change events.
+pub fn encode<T: Display>(query: usize, epoch: &T, embedding: &[Node], diff: i64, ts_ms: u64) -> Vec<String> {
+    let row = format!("{{\"query\":{},\"vertices\":[{}]}}", query, embedding.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","));
+    let (op, before, after) = if diff > 0 { ("c", "null", row.as_str()) } else { ("d", row.as_str(), "null") };
+    let event = format!("{{\"before\":{},\"after\":{},\"source\":{{\"connector\":\"{}\",\"query\":{},\"epoch\":\"{}\"}},\"op\":\"{}\",\"ts_ms\":{}}}",
+                        before, after, CONNECTOR, query, epoch, op, ts_ms);
+    (0 .. diff.abs()).map(|_| event.clone()).collect()
+}
+
+/// Writes the change events of a batch to `writer`, one per line, returning the number written.
+///
+/// The batch statistics must come from a recording engine; otherwise they hold no embeddings and
+/// nothing is written.
+pub fn write_batch<W: Write, T: Display>(writer: &mut W, stats: &BatchStats<T>, ts_ms: u64) -> io::Result<usize> {
+    let mut written = 0;
+    for &(query, ref embedding, diff) in stats.embeddings.iter() {
+        for event in encode(query, &stats.epoch, embedding, diff, ts_ms) {
+            writeln!(writer, "{}", event)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// The current time in milliseconds since the Unix epoch, for use as `ts_ms`.
+pub fn now_ms() -> u64 {
+    let since = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH).expect("clock before 1970");
+    since.as_secs() * 1000 + since.subsec_nanos() as u64 / 1_000_000
+}
@@ -0,0 +1,264 @@
+//! Configuration of a run of the engine.
+//!
+//! A `Config` describes where the graph and plan come from and how updates are batched. It is
+//! parsed from `--name value` command line arguments; arguments it does not recognize are handed
+//! back so that they can be passed on to timely (e.g. `-w 4`).
+
+use io::Strictness;
+use wings_plan::{Counting, IntersectDirection, MatchSemantics, Orientation, Placement, PrefixEncoding};
+
+/// A synthetic source of edges.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Generator {
+    /// An R-MAT graph on `2^scale` nodes with `edges` edges, generated from `seed`.
+    Rmat { scale: u32, edges: usize, seed: u64 },
+}
+
+/// Settings for a run of the engine.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// A file, or a directory of files, of `src dst` lines.
+    pub graph: Option<String>,
+    /// A plan file.
+    pub plan: Option<String>,
+    /// A query to plan, written as a rule; see `wings_plan::parser`.
+    pub query: Option<String>,
+    /// A schema file the query is checked against; see `wings_plan::schema`.
+    pub schema: Option<String>,
+    /// A synthetic graph, used in place of `graph`.
+    pub generator: Option<Generator>,
+    /// The number of edges loaded as the base graph, before any updates.
+    pub base: usize,
+    /// Load the base graph in parts of this many edges, skipping its initial results.
+    pub bulk: Option<usize>,
+    /// The number of updates introduced in each batch.
+    pub batch: usize,
+    /// The number of epochs each batch is pipelined over; see `Engine::step_pipelined`.
+    pub chunks: usize,
+    /// An optional limit on the number of batches.
+    pub batches: Option<usize>,
+    /// Batch updates by their change log times, rather than by count.
+    pub by_time: bool,
+    /// How lines of `graph` that cannot be parsed are treated.
+    pub malformed: Strictness,
+    /// Report progress in more detail.
+    pub inspect: bool,
+    /// A limit on the time spent matching each epoch, in milliseconds.
+    pub budget_ms: Option<u64>,
+    /// Bits per key of Bloom filters over index keys, if enabled.
+    pub bloom: Option<usize>,
+    /// The minimum degree of vertices whose edges are considered, if restricted.
+    pub min_degree: Option<usize>,
+    /// The indices used to check edges between bound vertices.
+    pub intersect: IntersectDirection,
+    /// How changes to matches are reported.
+    pub semantics: MatchSemantics,
+    /// Whether the graph and patterns are directed.
+    pub orientation: Orientation,
+    /// What the matches of query nodes count.
+    pub counting: Counting,
+    /// The workers that maintain the indices and extend prefixes.
+    pub placement: Placement,
+    /// The number of copies of the indices, each routing keys differently; see `Plan::set_index_copies`.
+    pub index_copies: usize,
+    /// How prefixes are represented as they are exchanged; see `Plan::set_prefix_encoding`.
+    pub prefix_encoding: PrefixEncoding,
+    /// Process prefixes and report changes in a reproducible order; see `Plan::set_deterministic`.
+    pub deterministic: bool,
+    /// Report a digest of each epoch's changes to matches; see `Engine::set_digests`.
+    pub digests: bool,
+    /// Count the work reads of the indices do; see `Engine::set_read_stats`.
+    pub read_stats: bool,
+    /// Write each change to a match as a change event; see `changefeed`.
+    pub changefeed: bool,
+    /// An address to serve metrics at, with the `prometheus` feature.
+    pub metrics: Option<String>,
+    /// A file to write run metadata to; see `metadata`.
+    pub metadata: Option<String>,
+    /// A directory of index snapshots of every worker; see `rescale`.
+    pub checkpoint: Option<String>,
+    /// A directory to write a re-partitioned checkpoint to.
+    pub rescale_to: Option<String>,
+    /// The number of workers to re-partition a checkpoint for.
+    pub rescale_workers: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            graph: None,
+            plan: None,
+            query: None,
+            schema: None,
+            generator: None,
+            base: 0,
+            bulk: None,
+            batch: 1000,
+            chunks: 1,
+            batches: None,
+            by_time: false,
+            malformed: Strictness::Strict,
+            inspect: false,
+            budget_ms: None,
+            bloom: None,
+            min_degree: None,
+            intersect: IntersectDirection::Forward,
+            semantics: MatchSemantics::Attributed,
+            orientation: Orientation::Directed,
+            counting: Counting::Embeddings,
+            placement: Placement::All,
+            index_copies: 1,
+            prefix_encoding: PrefixEncoding::Plain,
+            deterministic: false,
+            digests: false,
+            read_stats: false,
+            changefeed: false,
+            metrics: None,
+            metadata: None,
+            checkpoint: None,
+            rescale_to: None,
+            rescale_workers: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses a configuration from command line arguments.
+    ///
+    /// Returns the configuration and the arguments that were not recognized, in order.
+    pub fn from_args<I: Iterator<Item=String>>(args: I) -> Result<(Config, Vec<String>), String> {
+
+        let mut config = Config::default();
+        let mut others = Vec::new();
+
+        let mut scale = 16;
+        let mut edges = 1 << 20;
+        let mut seed = 0;
+        let mut generator = None;
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--graph"   => { config.graph = Some(value(&arg, args.next())?); },
+                "--plan"    => { config.plan = Some(value(&arg, args.next())?); },
+                "--query"   => { config.query = Some(value(&arg, args.next())?); },
+                "--schema"  => { config.schema = Some(value(&arg, args.next())?); },
+                "--base"    => { config.base = parse(&arg, args.next())?; },
+                "--bulk"    => { config.bulk = Some(parse(&arg, args.next())?); },
+                "--batch"   => { config.batch = parse(&arg, args.next())?; },
+                "--chunks"  => { config.chunks = parse(&arg, args.next())?; },
+                "--batches" => { config.batches = Some(parse(&arg, args.next())?); },
+                "--gen"     => { generator = Some(value(&arg, args.next())?); },
+                "--scale"   => { scale = parse(&arg, args.next())?; },
+                "--edges"   => { edges = parse(&arg, args.next())?; },
+                "--seed"    => { seed = parse(&arg, args.next())?; },
+                "--min-degree" => { config.min_degree = Some(parse(&arg, args.next())?); },
+                "--intersect" => {
+                    config.intersect = match value(&arg, args.next())?.as_str() {
+                        "forward"  => IntersectDirection::Forward,
+                        "reverse"  => IntersectDirection::Reverse,
+                        "adaptive" => IntersectDirection::Adaptive,
+                        other => return Err(format!("unknown intersect direction: {}", other)),
+                    };
+                },
+                "--budget-ms" => { config.budget_ms = Some(parse(&arg, args.next())?); },
+                "--semantics" => {
+                    config.semantics = match value(&arg, args.next())?.as_str() {
+                        "attributed" => MatchSemantics::Attributed,
+                        "set"        => MatchSemantics::SetDifference { unordered: false },
+                        "unordered"  => MatchSemantics::SetDifference { unordered: true },
+                        other => return Err(format!("unknown semantics: {}", other)),
+                    };
+                },
+                "--undirected" => { config.orientation = Orientation::Undirected; },
+                "--count" => {
+                    config.counting = match value(&arg, args.next())?.as_str() {
+                        "embeddings" => Counting::Embeddings,
+                        "subgraphs"  => Counting::Subgraphs,
+                        other => return Err(format!("unknown counting: {}", other)),
+                    };
+                },
+                "--placement" => {
+                    let workers = value(&arg, args.next())?.split(',')
+                        .map(|worker| worker.trim().parse().map_err(|_| format!("invalid worker in --placement: {}", worker)))
+                        .collect::<Result<Vec<usize>, String>>()?;
+                    config.placement = Placement::Workers(workers);
+                },
+                "--index-copies" => { config.index_copies = parse(&arg, args.next())?; },
+                "--packed-prefixes" => { config.prefix_encoding = PrefixEncoding::Packed; },
+                "--bloom"   => { config.bloom = Some(parse(&arg, args.next())?); },
+                "--by-time" => { config.by_time = true; },
+                "--malformed" => {
+                    config.malformed = match value(&arg, args.next())?.as_str() {
+                        "strict" => Strictness::Strict,
+                        "skip"   => Strictness::Skip,
+                        other => Strictness::SkipAtMost(other.parse().map_err(|_| format!("unknown malformed line policy: {}", other))?),
+                    };
+                },
+                "--metrics" => { config.metrics = Some(value(&arg, args.next())?); },
+                "--metadata" => { config.metadata = Some(value(&arg, args.next())?); },
+                "--checkpoint" => { config.checkpoint = Some(value(&arg, args.next())?); },
+                "--to" => { config.rescale_to = Some(value(&arg, args.next())?); },
+                "--to-workers" => { config.rescale_workers = Some(parse(&arg, args.next())?); },
+                "--changefeed" => { config.changefeed = true; },
+                "--deterministic" => { config.deterministic = true; },
+                "--digests" => { config.digests = true; },
+                "--read-stats" => { config.read_stats = true; },
+                "--inspect" | "inspect" => { config.inspect = true; },
+                _ => { others.push(arg); },
+            }
+        }
+
+        if let Some(name) = generator {
+            config.generator = match name.as_str() {
+                "rmat" => Some(Generator::Rmat { scale, edges, seed }),
+                _ => return Err(format!("unknown generator: {}", name)),
+            };
+        }
+
+        if let Some(Generator::Rmat { scale, .. }) = config.generator {
+            if scale > 32 {
+                return Err(format!("--scale {} exceeds the 32 bits of a node id", scale));
+            }
+        }
+
+        if config.batch == 0 {
+            return Err("--batch must be positive".to_owned());
+        }
+
+        if config.bulk == Some(0) {
+            return Err("--bulk must be positive".to_owned());
+        }
+
+        if config.index_copies == 0 {
+            return Err("--index-copies must be positive".to_owned());
+        }
+
+        if config.rescale_workers == Some(0) {
+            return Err("--to-workers must be positive".to_owned());
+        }
+
+        if config.chunks == 0 {
+            return Err("--chunks must be positive".to_owned());
+        }
+
+        if config.plan.is_some() && config.query.is_some() {
+            return Err("--plan and --query are exclusive".to_owned());
+        }
+
+        if config.schema.is_some() && config.query.is_none() {
+            return Err("--schema requires --query".to_owned());
+        }
+
+        Ok((config, others))
+    }
+}
+
+fn value(name: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("missing value for {}", name))
+}
+
+fn parse<T: ::std::str::FromStr>(name: &str, text: Option<String>) -> Result<T, String> {
+    let text = value(name, text)?;
+    text.parse().map_err(|_| format!("malformed value for {}: {}", name, text))
+}
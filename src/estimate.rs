@@ -0,0 +1,130 @@
+//! Estimates of counts from sampled updates, with their standard errors.
+//!
+//! Counting a sample of the changes to matches, e.g. one taken by `Sample::sample_tracked` to shed
+//! load, undercounts by the sampling rate, and scaling the count back up leaves it uncertain by an
+//! amount that depends on the rate and the size of the sample. `estimate_counts` reports, for each
+//! key and epoch, the Horvitz-Thompson estimate of the change in the key's count, in which each
+//! retained update weighs its diff divided by the probability with which it was retained, along
+//! with the estimate's standard error, and the same for the key's total over all epochs so far.
+//!
+//! Updates are taken to be sampled independently, as Bernoulli sampling by the hash of their data
+//! is for distinct data. The retraction of a match is sampled with its insertion, so the variance
+//! of a total that accumulated both overstates the uncertainty in it; the errors reported for
+//! totals are conservative. Updates retained with probability one contribute no uncertainty, so
+//! unsampled streams report exact counts with zero error.
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use abomonation::Abomonation;
+use timely::ExchangeData;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+
+/// An estimated quantity with its standard error.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// The estimated value.
+    pub value: f64,
+    /// The standard error of the estimate.
+    pub stderr: f64,
+}
+
+impl Estimate {
+    /// An exactly known `value`.
+    pub fn exact(value: f64) -> Self {
+        Estimate { value, stderr: 0.0 }
+    }
+
+    /// The interval of `z` standard errors either side of the value, e.g. 1.96 for 95% confidence.
+    pub fn interval(&self, z: f64) -> (f64, f64) {
+        (self.value - z * self.stderr, self.value + z * self.stderr)
+    }
+
+    /// The estimate of the sum of this quantity and an independently estimated `other`.
+    pub fn add(&self, other: &Estimate) -> Estimate {
+        Estimate {
+            value: self.value + other.value,
+            stderr: (self.stderr * self.stderr + other.stderr * other.stderr).sqrt(),
+        }
+    }
+}
+
+/// The estimated change in a key's count at an epoch, and its estimated count once it completed.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EpochEstimate {
+    /// The estimated change in the count at the epoch.
+    pub change: Estimate,
+    /// The estimated count over all epochs up to and including this one.
+    pub total: Estimate,
+}
+
+impl Abomonation for EpochEstimate { }
+
+// the sums of a Horvitz-Thompson estimate: the scaled diffs, and the variances of the terms.
+#[derive(Copy, Clone, Debug, Default)]
+struct Sums {
+    value: f64,
+    variance: f64,
+}
+
+impl Sums {
+    fn add(&mut self, diff: i64, probability: f64) {
+        let diff = diff as f64;
+        self.value += diff / probability;
+        self.variance += diff * diff * (1.0 - probability) / (probability * probability);
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate { value: self.value, stderr: self.variance.max(0.0).sqrt() }
+    }
+}
+
+/// Estimates the change in each key's count at each epoch, and its total, from sampled updates.
+///
+/// Each update `(key, diff, probability)` is a change to the count of `key` retained with
+/// `probability`, which must be in `(0, 1]`. Each key is estimated at one worker, which reports
+/// its estimates once an epoch is complete, for the keys with retained updates at the epoch.
+pub fn estimate_counts<G: Scope, K: ExchangeData+Hash+Eq+Ord>(updates: &Stream<G, (K, i64, f64)>) -> Stream<G, (K, EpochEstimate)>
+    where G::Timestamp: Hash {
+
+    let mut totals: HashMap<K, Sums> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let exchange = Exchange::new(|x: &(K, i64, f64)| {
+        let mut hasher = DefaultHasher::new();
+        x.0.hash(&mut hasher);
+        hasher.finish()
+    });
+    updates.unary_notify(exchange, "EstimateCounts", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let changes = stash.entry(time.time().clone()).or_insert(HashMap::new());
+            for (key, diff, probability) in buffer.drain(..) {
+                assert!(probability > 0.0 && probability <= 1.0, "updates are retained with probability in (0, 1]");
+                changes.entry(key).or_insert(Sums::default()).add(diff, probability);
+            }
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(HashMap::new()).into_iter().collect::<Vec<_>>();
+            changes.sort_by(|x, y| x.0.cmp(&y.0));
+            let mut session = output.session(&time);
+            for (key, change) in changes {
+                let total = totals.entry(key.clone()).or_insert(Sums::default());
+                total.value += change.value;
+                total.variance += change.variance;
+                session.give((key, EpochEstimate { change: change.estimate(), total: total.estimate() }));
+            }
+        }
+    })
+}
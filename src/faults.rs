@@ -0,0 +1,119 @@
+//! Recovering from poisoned locks and reporting non-fatal operator errors.
+//!
+//! State shared between workers and the driver sits behind locks, which a panic while one is held
+//! poisons; every later attempt to take the lock then panics too, so that one bad batch brings
+//! down every batch after it. The functions here take locks regardless, keeping the guarded data
+//! as the panicking thread left it. Updates to counters and registries are single assignments or
+//! insertions, so the data is whole, if it may lack the panicking thread's last change.
+//!
+//! Operators that meet data they can not process, such as a match with a vertex that has no label,
+//! report an `OperatorError` to an `ErrorEvents` and carry on with the rest of their input. The
+//! driver holds a clone of the same `ErrorEvents`, and drains the errors reported since it last
+//! looked, e.g. after each batch, to log them or to stop the computation itself.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Takes `mutex`, recovering it if it is poisoned.
+pub fn lock<'a, T>(mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Takes `lock` for reading, recovering it if it is poisoned.
+pub fn read<'a, T>(lock: &'a RwLock<T>) -> RwLockReadGuard<'a, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Takes `lock` for writing, recovering it if it is poisoned.
+pub fn write<'a, T>(lock: &'a RwLock<T>) -> RwLockWriteGuard<'a, T> {
+    lock.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An error an operator met and continued past.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorError {
+    /// The name of the operator that reported the error.
+    pub operator: String,
+    /// What went wrong, and what the operator did about it.
+    pub message: String,
+}
+
+impl ::std::fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}: {}", self.operator, self.message)
+    }
+}
+
+/// The errors operators have reported, shared by the operators and the driver.
+///
+/// Clones share their errors. At most `capacity` errors are held; once more are reported the
+/// oldest are dropped, and counted as `dropped`.
+#[derive(Debug, Clone)]
+pub struct ErrorEvents {
+    inner: Arc<Mutex<(VecDeque<OperatorError>, usize)>>,
+    capacity: usize,
+}
+
+impl Default for ErrorEvents {
+    fn default() -> Self { ErrorEvents::with_capacity(1024) }
+}
+
+impl ErrorEvents {
+    /// Allocates an empty log holding up to 1024 errors.
+    pub fn new() -> Self { ErrorEvents::default() }
+
+    /// Allocates an empty log holding up to `capacity` errors.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "error logs need room for at least one error");
+        ErrorEvents { inner: Arc::new(Mutex::new((VecDeque::new(), 0))), capacity }
+    }
+
+    /// Records that `operator` met an error described by `message`.
+    pub fn report<S: Into<String>>(&self, operator: &str, message: S) {
+        let mut inner = lock(&self.inner);
+        if inner.0.len() == self.capacity {
+            inner.0.pop_front();
+            inner.1 += 1;
+        }
+        inner.0.push_back(OperatorError { operator: operator.to_owned(), message: message.into() });
+    }
+
+    /// Removes and returns the errors reported since the last call, oldest first.
+    pub fn drain(&self) -> Vec<OperatorError> {
+        lock(&self.inner).0.drain(..).collect()
+    }
+
+    /// The number of errors dropped because the log was full.
+    pub fn dropped(&self) -> usize {
+        lock(&self.inner).1
+    }
+
+    /// Whether no errors are waiting to be drained.
+    pub fn is_empty(&self) -> bool {
+        lock(&self.inner).0.is_empty()
+    }
+
+    /// Takes `mutex` for `operator`, recovering it and reporting an error if it is poisoned.
+    pub fn lock<'a, T>(&self, operator: &str, mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+        mutex.lock().unwrap_or_else(|poisoned| {
+            self.report(operator, "recovered a lock poisoned by a panic elsewhere");
+            poisoned.into_inner()
+        })
+    }
+
+    /// Takes `lock` for reading for `operator`, recovering it and reporting an error if it is poisoned.
+    pub fn read<'a, T>(&self, operator: &str, lock: &'a RwLock<T>) -> RwLockReadGuard<'a, T> {
+        lock.read().unwrap_or_else(|poisoned| {
+            self.report(operator, "recovered a lock poisoned by a panic elsewhere");
+            poisoned.into_inner()
+        })
+    }
+
+    /// Takes `lock` for writing for `operator`, recovering it and reporting an error if it is poisoned.
+    pub fn write<'a, T>(&self, operator: &str, lock: &'a RwLock<T>) -> RwLockWriteGuard<'a, T> {
+        lock.write().unwrap_or_else(|poisoned| {
+            self.report(operator, "recovered a lock poisoned by a panic elsewhere");
+            poisoned.into_inner()
+        })
+    }
+}
@@ -0,0 +1,181 @@
+//! What a run's dataflows are doing, for debugging stalls.
+//!
+//! When an epoch takes far longer than its neighbors, the question is which part of the dataflow
+//! holds back its frontier: index maintenance at some worker, matching for one query node, or the
+//! stage gathering totals. An `Introspection` registry collects, from each worker's engine (see
+//! `Engine::set_introspection`), a `WorkerReport` of the frontier of each stage of its dataflow,
+//! with the address of the operator producing each query node's matches as timely's logging
+//! names operators, and the sizes of the buffers in which updates wait: the updates of a batch
+//! not yet committed or staged for a later time, the updates the indices hold uncommitted, and
+//! the epochs whose changes are retained or whose totals are still being gathered. Engines report
+//! as each epoch starts and completes, and every second while they wait on one, so that a registry
+//! read from another thread describes a worker that is stuck.
+//!
+//! The registry also describes the plan the workers run: its fingerprint, its query nodes with
+//! their patterns, and its edges with their operations. `render` describes all of it as JSON, and
+//! `Metrics::set_introspection` has the metrics exporter answer requests for `/introspect` with it.
+//!
+//! A stage is behind if it has not yet completed times before the input's current epoch. The stage
+//! holding a worker back is the first of its stages that is behind, in the order the dataflow
+//! passes updates through them: the forward and reverse indices, the query nodes, and then the
+//! stage collecting all matches, which also gathers totals.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use wings_plan::Plan;
+use wings_plan::document::{OperationKind, PlanDocument};
+use faults;
+
+/// The frontier of one stage of a worker's dataflow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageReport {
+    /// The stage, e.g. `forward index` or `query 3`.
+    pub name: String,
+    /// The address of the operator producing the stage's output, if it is one operator.
+    pub operator: Option<Vec<usize>>,
+    /// The epochs of the stage's frontier, as their debug representation.
+    pub frontier: Vec<String>,
+    /// Whether the stage has not yet completed epochs before the input's current epoch.
+    pub behind: bool,
+}
+
+/// The state of one worker's dataflow, as its engine last reported it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerReport {
+    /// The index of the worker.
+    pub worker: usize,
+    /// The input's current epoch, as its debug representation.
+    pub epoch: String,
+    /// The stages of the dataflow, in the order updates pass through them.
+    pub stages: Vec<StageReport>,
+    /// The number of entries waiting in each of the engine's buffers, by name.
+    pub buffers: Vec<(String, usize)>,
+}
+
+impl WorkerReport {
+    /// The first stage that is behind, which holds back the worker's frontier.
+    pub fn holding_back(&self) -> Option<&StageReport> {
+        self.stages.iter().find(|stage| stage.behind)
+    }
+}
+
+/// A query node of the plan the workers run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryReport {
+    /// The index of the query node.
+    pub node: usize,
+    /// The edges of the node's pattern; see `Plan::pattern_edges`.
+    pub pattern: Vec<(usize, usize)>,
+}
+
+/// The plan the workers run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlanReport {
+    /// The plan's fingerprint; see `Plan::fingerprint`.
+    pub fingerprint: u64,
+    /// The plan's query nodes.
+    pub queries: Vec<QueryReport>,
+    /// The plan's nodes and edges.
+    pub document: PlanDocument,
+}
+
+/// A shared registry of the reports of the workers of a process.
+#[derive(Clone, Default)]
+pub struct Introspection {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    plan: Option<PlanReport>,
+    workers: BTreeMap<usize, WorkerReport>,
+}
+
+impl Introspection {
+    /// Allocates a registry without a plan or reports.
+    pub fn new() -> Self {
+        Introspection::default()
+    }
+
+    /// Describes `plan` as the plan the workers run.
+    pub fn register_plan(&self, plan: &Plan) {
+        let queries = plan.queries().into_iter().map(|node| {
+            QueryReport { node, pattern: plan.pattern_edges(node).unwrap_or_else(Vec::new) }
+        }).collect();
+        faults::lock(&self.inner).plan = Some(PlanReport { fingerprint: plan.fingerprint(), queries, document: plan.to_document() });
+    }
+
+    /// Records `report`, replacing the last report of its worker.
+    pub fn record(&self, report: WorkerReport) {
+        faults::lock(&self.inner).workers.insert(report.worker, report);
+    }
+
+    /// The registered plan, if any.
+    pub fn plan(&self) -> Option<PlanReport> {
+        faults::lock(&self.inner).plan.clone()
+    }
+
+    /// The last report of each worker, in order of the workers.
+    pub fn workers(&self) -> Vec<WorkerReport> {
+        faults::lock(&self.inner).workers.values().cloned().collect()
+    }
+
+    /// The plan and the workers' reports as JSON.
+    pub fn render(&self) -> String {
+        let registry = faults::lock(&self.inner);
+        let plan = match registry.plan {
+            Some(ref plan) => render_plan(plan),
+            None => "null".to_owned(),
+        };
+        let workers = registry.workers.values().map(render_worker).collect::<Vec<_>>();
+        format!("{{\"plan\":{},\"workers\":[{}]}}\n", plan, workers.join(","))
+    }
+}
+
+fn render_plan(plan: &PlanReport) -> String {
+    let queries = plan.queries.iter().map(|query| {
+        let pattern = query.pattern.iter().map(|&(src, dst)| format!("[{},{}]", src, dst)).collect::<Vec<_>>();
+        format!("{{\"node\":{},\"pattern\":[{}]}}", query.node, pattern.join(","))
+    }).collect::<Vec<_>>();
+    let edges = plan.document.edges.iter().map(|edge| {
+        let operations = edge.operations.iter().map(|operation| {
+            let kind = match operation.kind { OperationKind::Extension => "extension", OperationKind::Intersection => "intersection" };
+            format!("{{\"src\":{},\"dst\":{},\"kind\":\"{}\"}}", operation.src, operation.dst, kind)
+        }).collect::<Vec<_>>();
+        format!("{{\"from\":{},\"to\":{},\"propose_first\":{},\"operations\":[{}]}}", edge.from, edge.to, edge.propose_first, operations.join(","))
+    }).collect::<Vec<_>>();
+    format!("{{\"fingerprint\":\"{:016x}\",\"queries\":[{}],\"edges\":[{}]}}", plan.fingerprint, queries.join(","), edges.join(","))
+}
+
+fn render_worker(report: &WorkerReport) -> String {
+    let stages = report.stages.iter().map(|stage| {
+        let operator = match stage.operator {
+            Some(ref address) => format!("[{}]", address.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")),
+            None => "null".to_owned(),
+        };
+        let frontier = stage.frontier.iter().map(|time| format!("\"{}\"", escape(time))).collect::<Vec<_>>();
+        format!("{{\"name\":\"{}\",\"operator\":{},\"frontier\":[{}],\"behind\":{}}}", escape(&stage.name), operator, frontier.join(","), stage.behind)
+    }).collect::<Vec<_>>();
+    let buffers = report.buffers.iter().map(|&(ref name, size)| format!("\"{}\":{}", escape(name), size)).collect::<Vec<_>>();
+    let holding = match report.holding_back() {
+        Some(stage) => format!("\"{}\"", escape(&stage.name)),
+        None => "null".to_owned(),
+    };
+    format!("{{\"worker\":{},\"epoch\":\"{}\",\"holding_back\":{},\"stages\":[{}],\"buffers\":{{{}}}}}",
+            report.worker, escape(&report.epoch), holding, stages.join(","), buffers.join(","))
+}
+
+// escapes `text` for a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,607 @@
+//! Reading and generating graph edges.
+//!
+//! Edge files contain one `src dst` pair per line; empty lines and lines starting with `#` are
+//! skipped. Change logs instead contain one `+ src dst` or `- src dst` line per update, optionally
+//! followed by a time, so that deletion workloads can be replayed. An `EdgeReader` may read a single
+//! file or every file of a directory, in sorted order, as one sequence of edges or updates.
+//! Malformed lines, including node ids too large for a `Node`, are reported with their file and
+//! line number, or skipped and counted if the reader's `Strictness` allows it.
+//!
+//! Updates may also be held as `Columns`, parallel arrays of sources, destinations, and diffs, as
+//! columnar sources supply them, which `Engine::step_columns` sends to the workers as columns.
+//!
+//! Index snapshots are stored as binary files of little-endian `u32` pairs, following a magic
+//! number and the number of pairs.
+//!
+//! Vertex label files contain one `node label` pair per line, skipping lines as edge files do.
+//!
+//! With the `csr` feature, graphs in graph_map's compressed sparse row layout, a `prefix.offsets`
+//! file of `u64` row offsets and a `prefix.targets` file of `u32` targets, are memory-mapped as
+//! `CsrGraph`s, from which each worker takes the index pairs it owns without parsing or exchanging
+//! them; see `Engine::from_csr`.
+
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::vec::IntoIter;
+
+use ::{Node, Edge};
+
+/// A change to an edge: the edge, its signed diff, and the change log time if one was given.
+pub type Change = (Edge, i32, Option<u64>);
+
+/// Parses a line of an edge file or of a change log.
+///
+/// A line is either `src dst`, an insertion of the edge, or `+ src dst [time]` or `- src dst [time]`,
+/// an insertion or deletion of the edge, optionally at a change log time. Further fields of
+/// unsigned lines are ignored, as edge files often carry additional columns. Returns the reason a
+/// malformed line could not be parsed, including node ids that do not fit a `Node`.
+pub fn parse_change(line: &str) -> Result<Change, String> {
+    let mut elts = line.split_whitespace().peekable();
+    let diff = match elts.peek() {
+        Some(&"+") => { elts.next(); 1 },
+        Some(&"-") => { elts.next(); -1 },
+        _ => 0,
+    };
+    let src = parse_node(elts.next(), "src")?;
+    let dst = parse_node(elts.next(), "dst")?;
+    if diff == 0 {
+        Ok(((src, dst), 1, None))
+    }
+    else {
+        let time = match elts.next() {
+            Some(text) => Some(text.parse().map_err(|_| format!("malformed time {:?}", text))?),
+            None => None,
+        };
+        Ok(((src, dst), diff, time))
+    }
+}
+
+fn parse_node(text: Option<&str>, name: &str) -> Result<Node, String> {
+    let text = text.ok_or_else(|| format!("missing {}", name))?;
+    let id: u64 = text.parse().map_err(|_| format!("malformed {} {:?}", name, text))?;
+    if id > Node::max_value() as u64 {
+        return Err(format!("{} {} exceeds the largest node id, {}", name, id, Node::max_value()));
+    }
+    Ok(id as Node)
+}
+
+/// How a reader treats lines it cannot parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail on the first malformed line.
+    Strict,
+    /// Skip malformed lines, counting them.
+    Skip,
+    /// Skip up to this many malformed lines, and fail on the next.
+    SkipAtMost(u64),
+}
+
+impl Default for Strictness {
+    fn default() -> Self { Strictness::Strict }
+}
+
+/// A line of input a reader could not use, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputError {
+    /// The file the line was read from.
+    pub path: String,
+    /// The number of the line in its file, from one, or zero if the file could not be opened.
+    pub line: u64,
+    /// The line, without its line break.
+    pub text: String,
+    /// Why the line could not be used.
+    pub reason: String,
+}
+
+impl ::std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}:{}: {}: {:?}", self.path, self.line, self.reason, self.text)
+    }
+}
+
+/// Statistics of the input a reader has consumed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Lines read, including empty lines and comments.
+    pub lines: u64,
+    /// Changes read, each an edge or a signed update.
+    pub changes: u64,
+    /// Malformed lines skipped.
+    pub skipped: u64,
+    /// The largest node id read, if any change was read.
+    pub max_node: Option<Node>,
+    /// The first malformed line skipped, to report along with the number skipped.
+    pub first_skipped: Option<InputError>,
+}
+
+/// Reads edges from a file, or from a directory of files.
+///
+/// The files may be plain edge files or change logs, as described in `parse_change`. Malformed
+/// lines are errors, unless the reader's strictness allows skipping them (see `set_strictness`);
+/// `stats` reports how much input was read and skipped either way.
+pub struct EdgeReader {
+    reader: Option<BufReader<File>>,
+    paths: IntoIter<PathBuf>,
+    pending: Option<Change>,
+    path: Option<PathBuf>,
+    lines: u64,
+    strictness: Strictness,
+    stats: IngestStats,
+}
+
+impl EdgeReader {
+    /// Opens `path`, which may be either a file or a directory of files.
+    pub fn open(path: &str) -> ::std::io::Result<EdgeReader> {
+        let path = PathBuf::from(path);
+        let mut paths = if path.is_dir() {
+            fs::read_dir(&path)?
+                .map(|res| res.map(|e| e.path()))
+                .collect::<Result<Vec<_>, ::std::io::Error>>()?
+        }
+        else {
+            vec![path]
+        };
+
+        paths.sort();
+
+        // open the first file eagerly, to report errors early.
+        let mut paths = paths.into_iter();
+        let path = paths.next();
+        let reader = match path {
+            Some(ref path) => Some(BufReader::new(File::open(path)?)),
+            None => None,
+        };
+
+        Ok(EdgeReader { reader, paths, pending: None, path, lines: 0, strictness: Strictness::Strict, stats: IngestStats::default() })
+    }
+
+    /// Sets how malformed lines are treated. The default is `Strict`.
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    /// Statistics of the input read so far.
+    pub fn stats(&self) -> &IngestStats {
+        &self.stats
+    }
+
+    /// The file being read and the number of its lines consumed so far.
+    ///
+    /// Lines are counted whether or not they contain an edge, so the position can be used to
+    /// resume reading the file. Returns `None` once all files have been read.
+    pub fn position(&self) -> Option<(String, u64)> {
+        let pending = if self.pending.is_some() { 1 } else { 0 };
+        self.path.as_ref().map(|path| (path.display().to_string(), self.lines - pending))
+    }
+
+    /// Reads the next line with content, moving on to the next file as needed.
+    ///
+    /// A line that can not be read, as one that is not valid UTF-8, is counted, so that the error
+    /// names it; a file that can not be opened is reported at line zero.
+    fn next_line(&mut self, line: &mut String) -> ::std::io::Result<bool> {
+        loop {
+            let done = match self.reader {
+                Some(ref mut reader) => {
+                    line.clear();
+                    match reader.read_line(line) {
+                        Ok(read) => read == 0,
+                        Err(error) => {
+                            self.lines += 1;
+                            self.stats.lines += 1;
+                            return Err(error);
+                        },
+                    }
+                },
+                None => return Ok(false),
+            };
+
+            if done {
+                self.path = self.paths.next();
+                self.reader = None;
+                self.lines = 0;
+                if let Some(ref path) = self.path {
+                    self.reader = Some(BufReader::new(File::open(path)?));
+                }
+            }
+            else {
+                self.lines += 1;
+                self.stats.lines += 1;
+                if !line.starts_with('#') && line.trim().len() > 0 {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    // an error for the line just read.
+    fn error(&self, line: &str, reason: String) -> InputError {
+        InputError {
+            path: self.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+            line: self.lines,
+            text: line.trim().to_owned(),
+            reason,
+        }
+    }
+
+    /// Reads the next change, if the input is not yet exhausted, skipping malformed lines as allowed.
+    fn next_change(&mut self) -> Result<Option<Change>, InputError> {
+        if let Some(change) = self.pending.take() {
+            return Ok(Some(change));
+        }
+        let mut line = String::new();
+        loop {
+            let parsed = match self.next_line(&mut line) {
+                Ok(true) => parse_change(&line),
+                Ok(false) => return Ok(None),
+                // the rest of the line is consumed, so that it may be skipped as malformed.
+                Err(ref error) if error.kind() == ::std::io::ErrorKind::InvalidData => {
+                    line.clear();
+                    Err(format!("unreadable line: {}", error))
+                },
+                Err(error) => {
+                    let reason = if self.lines == 0 { format!("couldn't open: {}", error) } else { format!("couldn't read: {}", error) };
+                    return Err(self.error("", reason));
+                },
+            };
+            match parsed {
+                Ok(change) => {
+                    let ((src, dst), _, _) = change;
+                    self.stats.changes += 1;
+                    self.stats.max_node = ::std::cmp::max(self.stats.max_node, Some(::std::cmp::max(src, dst)));
+                    return Ok(Some(change));
+                },
+                Err(reason) => {
+                    let error = self.error(&line, reason);
+                    let allowed = match self.strictness {
+                        Strictness::Strict => false,
+                        Strictness::Skip => true,
+                        Strictness::SkipAtMost(limit) => self.stats.skipped < limit,
+                    };
+                    if !allowed { return Err(error); }
+                    self.stats.skipped += 1;
+                    if self.stats.first_skipped.is_none() { self.stats.first_skipped = Some(error); }
+                },
+            }
+        }
+    }
+
+    /// Reads up to `limit` edges.
+    ///
+    /// Fewer than `limit` edges are returned only once the input is exhausted. A deletion is an
+    /// error whatever the strictness, as a base graph can only contain edges.
+    pub fn read_edges(&mut self, limit: usize) -> Result<Vec<Edge>, InputError> {
+        let mut edges = Vec::new();
+        while edges.len() < limit {
+            match self.next_change()? {
+                Some((edge, 1, _)) => edges.push(edge),
+                Some((edge, _, _)) => {
+                    let text = format!("- {} {}", edge.0, edge.1);
+                    return Err(self.error(&text, "deletion among base edges".to_owned()));
+                },
+                None => break,
+            }
+        }
+        Ok(edges)
+    }
+
+    /// Reads up to `limit` signed edge updates.
+    pub fn read_updates(&mut self, limit: usize) -> Result<Vec<(Edge, i32)>, InputError> {
+        let mut updates = Vec::new();
+        while updates.len() < limit {
+            match self.next_change()? {
+                Some((edge, diff, _)) => updates.push((edge, diff)),
+                None => break,
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Reads up to `limit` signed edge updates, appending them to `columns`.
+    pub fn read_columns(&mut self, limit: usize, columns: &mut Columns) -> Result<(), InputError> {
+        let mut count = 0;
+        while count < limit {
+            match self.next_change()? {
+                Some((edge, diff, _)) => columns.push(edge, diff),
+                None => break,
+            }
+            count += 1;
+        }
+        Ok(())
+    }
+
+    /// Reads up to `limit` signed edge updates sharing the change log time of the first of them.
+    ///
+    /// Returns that time, which is `None` for updates without a time, and the updates. Replaying
+    /// a change log one time at a time reproduces its epochs, provided no time has more than
+    /// `limit` updates.
+    pub fn read_epoch(&mut self, limit: usize) -> Result<(Option<u64>, Vec<(Edge, i32)>), InputError> {
+        let mut updates = Vec::new();
+        let mut epoch = None;
+        while updates.len() < limit {
+            match self.next_change()? {
+                Some((edge, diff, time)) => {
+                    if updates.is_empty() { epoch = time; }
+                    if time != epoch {
+                        self.pending = Some((edge, diff, time));
+                        break;
+                    }
+                    updates.push((edge, diff));
+                },
+                None => break,
+            }
+        }
+        Ok((epoch, updates))
+    }
+}
+
+/// A batch of edge updates, stored as parallel columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Columns {
+    pub src: Vec<Node>,
+    pub dst: Vec<Node>,
+    pub diff: Vec<i32>,
+}
+
+impl Columns {
+    /// Allocates empty columns.
+    pub fn new() -> Self { Columns::default() }
+
+    /// Allocates empty columns with space for `capacity` updates.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Columns {
+            src: Vec::with_capacity(capacity),
+            dst: Vec::with_capacity(capacity),
+            diff: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Converts a list of updates to columns.
+    pub fn from_updates(updates: &[(Edge, i32)]) -> Self {
+        let mut columns = Columns::with_capacity(updates.len());
+        for &(edge, diff) in updates.iter() {
+            columns.push(edge, diff);
+        }
+        columns
+    }
+
+    /// Converts columns to a list of updates.
+    pub fn to_updates(&self) -> Vec<(Edge, i32)> {
+        zip_updates(&self.src[..], &self.dst[..], &self.diff[..])
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, (src, dst): Edge, diff: i32) {
+        self.src.push(src);
+        self.dst.push(dst);
+        self.diff.push(diff);
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize { self.diff.len() }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool { self.diff.is_empty() }
+
+    /// Removes all updates, retaining the allocations.
+    pub fn clear(&mut self) {
+        self.src.clear();
+        self.dst.clear();
+        self.diff.clear();
+    }
+}
+
+/// Zips columns of sources, destinations, and diffs into a list of updates.
+///
+/// This method panics if the columns have different lengths.
+pub fn zip_updates(src: &[Node], dst: &[Node], diff: &[i32]) -> Vec<(Edge, i32)> {
+    assert!(src.len() == dst.len() && dst.len() == diff.len(), "columns of different lengths");
+    src.iter().zip(dst.iter()).zip(diff.iter()).map(|((&s, &d), &w)| ((s, d), w)).collect()
+}
+
+static SNAPSHOT_MAGIC: &'static [u8; 8] = b"wings\x00s1";
+
+/// Writes a snapshot of an index, as produced by `Index::snapshot`, to `path`.
+pub fn write_snapshot(path: &str, pairs: &[Edge]) -> ::std::io::Result<()> {
+    let mut writer = ::std::io::BufWriter::new(File::create(path)?);
+    writer.write_all(&SNAPSHOT_MAGIC[..])?;
+    writer.write_all(&(pairs.len() as u64).to_le_bytes())?;
+    for &(key, val) in pairs.iter() {
+        writer.write_all(&key.to_le_bytes())?;
+        writer.write_all(&val.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Reads a snapshot of an index written by `write_snapshot` from `path`.
+///
+/// Returns an `InvalidData` error if the file is not a snapshot, or holds fewer pairs than its
+/// header counts.
+pub fn read_snapshot(path: &str) -> ::std::io::Result<Vec<Edge>> {
+    use std::io::{Error, ErrorKind};
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != SNAPSHOT_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is not an index snapshot", path)));
+    }
+    let mut count = [0u8; 8];
+    reader.read_exact(&mut count)?;
+    let count = u64::from_le_bytes(count);
+
+    // a corrupt count would otherwise reserve memory the file could never fill.
+    let length = reader.get_ref().metadata()?.len();
+    if count > length.saturating_sub(16) / 8 {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} holds {} bytes, too few for the {} pairs its header counts", path, length, count)));
+    }
+
+    let mut pairs = Vec::with_capacity(count as usize);
+    let mut pair = [0u8; 8];
+    for _ in 0 .. count {
+        reader.read_exact(&mut pair)?;
+        let key = u32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]);
+        let val = u32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]);
+        pairs.push((key, val));
+    }
+    Ok(pairs)
+}
+
+/// Reads the label of each vertex from the vertex label file at `path`.
+///
+/// A vertex listed more than once takes its last label. Malformed lines are errors.
+pub fn read_vertex_labels(path: &str) -> ::std::io::Result<HashMap<Node, u32>> {
+    use std::io::{Error, ErrorKind};
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut labels = HashMap::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let mut fields = line.split_whitespace().map(|field| field.parse::<u32>());
+        match (fields.next(), fields.next()) {
+            (Some(Ok(node)), Some(Ok(label))) => { labels.insert(node, label); },
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("{}:{}: malformed vertex label: {:?}", path, number + 1, line))),
+        }
+    }
+    Ok(labels)
+}
+
+/// A graph in graph_map's compressed sparse row layout, memory-mapped from its two files.
+///
+/// Rows are read straight from the mapped files, so that a large static base graph loads at the
+/// speed memory can be scanned. Nodes may be renamed in order of degree as they are read (see
+/// `relabel_by_degree`), in which case updates to the graph must be renamed the same way.
+#[cfg(feature = "csr")]
+pub struct CsrGraph {
+    graph: ::graph_map::GraphMMap,
+    names: Option<Vec<Node>>,
+}
+
+#[cfg(feature = "csr")]
+impl CsrGraph {
+    /// Maps the graph stored in `prefix.offsets` and `prefix.targets`.
+    pub fn open(prefix: &str) -> ::std::io::Result<Self> {
+        // graph_map panics on missing files, so check for them first.
+        for suffix in ["offsets", "targets"].iter() {
+            fs::metadata(format!("{}.{}", prefix, suffix))?;
+        }
+        Ok(CsrGraph { graph: ::graph_map::GraphMMap::new(prefix), names: None })
+    }
+
+    /// The number of rows of the graph.
+    pub fn nodes(&self) -> usize { self.graph.nodes() }
+
+    /// The targets of the edges from `node`, as stored, without renaming.
+    pub fn row(&self, node: usize) -> &[Node] { self.graph.edges(node) }
+
+    /// Renames each node by its rank in order of degree, counting edges in both directions and
+    /// breaking ties by id, so that the nodes of highest degree take the largest names.
+    ///
+    /// This is the renaming the degree-ordering preprocessor of `static_graph` applies to a graph
+    /// before writing it, applied instead as the graph is read. Graphs it already wrote are read as
+    /// they are.
+    pub fn relabel_by_degree(&mut self) {
+        let mut degrees = Vec::new();
+        for node in 0 .. self.graph.nodes() {
+            for &target in self.graph.edges(node) {
+                let largest = ::std::cmp::max(node, target as usize);
+                while degrees.len() <= largest { let next = degrees.len() as Node; degrees.push((0u64, next)); }
+                degrees[node].0 += 1;
+                degrees[target as usize].0 += 1;
+            }
+        }
+        degrees.sort();
+        let mut names = vec![0; degrees.len()];
+        for (rank, &(_, node)) in degrees.iter().enumerate() {
+            names[node as usize] = rank as Node;
+        }
+        self.names = Some(names);
+    }
+
+    /// The name of `node` in the graph as read: its rank if renamed by degree, and `node` otherwise.
+    ///
+    /// Nodes with no edges in the graph keep their ids, which no renamed node takes.
+    #[inline(always)]
+    pub fn name(&self, node: Node) -> Node {
+        match self.names {
+            Some(ref names) => names.get(node as usize).cloned().unwrap_or(node),
+            None => node,
+        }
+    }
+
+    /// Renames the nodes of `updates` as the graph's nodes are named.
+    pub fn rename_updates(&self, updates: &mut [(Edge, i32)]) {
+        if self.names.is_some() {
+            for update in updates.iter_mut() { update.0 = (self.name((update.0).0), self.name((update.0).1)); }
+        }
+    }
+
+    /// The pairs of the forward and reverse indices worker `worker` of `peers` owns, as `router`
+    /// routes keys: the edges whose sources it owns, and the reversed edges whose destinations it
+    /// owns.
+    ///
+    /// Only the rows of owned sources are copied for the forward index, while every worker scans
+    /// all targets for the destinations it owns. With `symmetric`, each edge other than a self-loop
+    /// is also read in the other direction, as undirected plans index it.
+    pub fn owned_pairs<H: Fn(Node)->u64>(&self, router: &H, worker: usize, peers: usize, symmetric: bool) -> (Vec<Edge>, Vec<Edge>) {
+        let owned = |node: Node| (router(node) % peers as u64) as usize == worker;
+        let mut forward = Vec::new();
+        let mut reverse = Vec::new();
+        for node in 0 .. self.graph.nodes() {
+            let src = self.name(node as Node);
+            let owns_src = owned(src);
+            for &dst in self.graph.edges(node) {
+                let dst = self.name(dst);
+                let owns_dst = owned(dst);
+                if owns_src { forward.push((src, dst)); }
+                if owns_dst { reverse.push((dst, src)); }
+                if symmetric && src != dst {
+                    if owns_dst { forward.push((dst, src)); }
+                    if owns_src { reverse.push((src, dst)); }
+                }
+            }
+        }
+        (forward, reverse)
+    }
+}
+
+/// Generates `edges` edges of an R-MAT graph on `2^scale` nodes.
+///
+/// Each edge is placed by recursively choosing one of the four quadrants of the adjacency matrix,
+/// with the usual probabilities (0.57, 0.19, 0.19, 0.05). The same `seed` produces the same edges.
+///
+/// This method panics if `scale` exceeds 32, as node ids would not fit a `Node`.
+pub fn rmat(scale: u32, edges: usize, seed: u64) -> Vec<Edge> {
+
+    assert!(scale <= 32, "R-MAT scale {} exceeds the 32 bits of a node id", scale);
+
+    // xorshift64*, which is plenty for graph generation.
+    let mut state = seed.wrapping_mul(0x9E3779B97F4A7C15) | 1;
+    let mut next = move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545F4914F6CDD1D)
+    };
+
+    let mut result = Vec::with_capacity(edges);
+    for _ in 0 .. edges {
+        let mut src = 0;
+        let mut dst = 0;
+        for _ in 0 .. scale {
+            let draw = (next() >> 11) as f64 / (1u64 << 53) as f64;
+            let (s, d) = if draw < 0.57 { (0, 0) }
+                    else if draw < 0.76 { (0, 1) }
+                    else if draw < 0.95 { (1, 0) }
+                    else                { (1, 1) };
+            src = (src << 1) | s;
+            dst = (dst << 1) | d;
+        }
+        result.push((src, dst));
+    }
+    result
+}
@@ -23,14 +23,41 @@
 //! indicate a relational query like above, and the crate with synthesize a timely dataflow computation which
 //! reports all changes to the occurrences of satisfying assignments to the values. The amount of work performed
 //! is no more than the worst-case optimal bound.
+//!
+//! The supported interface is re-exported by `prelude`, and follows semantic versioning. Modules
+//! hidden from the documentation hold the engine's operators and index layout, which may change
+//! between any two releases.
 
 extern crate timely;
+extern crate abomonation;
+#[cfg(feature = "json")] extern crate serde;
+#[cfg(feature = "json")] #[macro_use] extern crate serde_derive;
+#[cfg(feature = "json")] extern crate serde_json;
+#[cfg(feature = "csr")] extern crate graph_map;
 
-pub mod timely_rule;
-pub mod wings_rule;
+#[doc(hidden)] pub mod timely_rule;
+#[doc(hidden)] pub mod wings_rule;
 
 pub mod wings_plan;
 
+pub mod anchor;
+#[cfg(feature = "sinks")] pub mod changefeed;
+#[cfg(feature = "io")] pub mod config;
+pub mod estimate;
+pub mod faults;
+pub mod introspect;
+#[cfg(feature = "io")] pub mod io;
+#[cfg(feature = "sinks")] pub mod metadata;
+#[cfg(feature = "io")] pub mod rescale;
+pub mod metrics;
+pub mod runtime;
+pub mod sample;
+pub mod search;
+pub mod subscribe;
+#[doc(hidden)] pub mod wire;
+
+pub mod prelude;
+
 pub trait Indexable<Val>{
     fn index(&self, index: usize) -> Val;
     fn get_src(&self) -> Val;
@@ -40,8 +67,38 @@ pub trait Indexable<Val>{
     fn push(&mut self, node: Val);
 }
 
+/// A vertex of the graph.
+///
+/// This is the crate's one definition of vertex identifiers; modules that name them re-export it.
 pub type Node = u32;
+/// A directed edge of the graph, from its source to its destination.
 pub type Edge = (Node, Node);
+/// A directed edge with a label, as `(src, dst, label)`; see `GraphStreamIndex::from_labeled`.
+pub type LabeledEdge = (Node, Node, u32);
+
+/// The weight of a change to a match: the number of times the match is added, or removed if negative.
+///
+/// Updates to the graph carry `i32` diffs, and each change to a match starts out with the diff of
+/// the update that caused it. Plans convert diffs to their weight type as they read updates, so
+/// that the changes they report, and the sums sinks accumulate from them, can be wider than the
+/// diffs: bag semantics over large dense graphs overflow `i32`. The engine weighs changes as `i64`.
+pub trait Weight: ::timely::ExchangeData+Copy+Ord+::std::hash::Hash+::std::fmt::Debug+Default+From<i32>
+    +::std::ops::Add<Output=Self>+::std::ops::Neg<Output=Self> {
+    /// The weight as an `i64`, in which sinks accumulate weights of any type.
+    fn to_i64(self) -> i64;
+    /// The weight an `i64` holds, which must be in range, as it is for the results of `to_i64`.
+    fn from_i64(weight: i64) -> Self;
+}
+
+impl Weight for i32 {
+    #[inline(always)] fn to_i64(self) -> i64 { self as i64 }
+    #[inline(always)] fn from_i64(weight: i64) -> Self { weight as i32 }
+}
+
+impl Weight for i64 {
+    #[inline(always)] fn to_i64(self) -> i64 { self }
+    #[inline(always)] fn from_i64(weight: i64) -> Self { weight }
+}
 
 impl Indexable<Node> for Vec<Node>{
     #[inline(always)] fn index(&self, index: usize) -> Node { self[index] }
@@ -0,0 +1,299 @@
+//! Run metadata, recorded as a JSON sidecar next to a run's output.
+//!
+//! A `RunMetadata` is a small key-value store in which a driver records what a run did: a
+//! fingerprint of its plan, a description of its dataset, its configuration, and summary statistics
+//! of its batches. Keys are dotted names (`plan.fingerprint`, `config.batch`, `matches.3`), and
+//! values are text, integers, floats, or flags. The store is written as one flat JSON object with
+//! its keys in order, so that sidecars of different runs can be compared with `diff`, or read back
+//! with `RunMetadata::read_sidecar` and compared with `differences` and `same_setup`.
+//!
+//! The `wings` binary writes a sidecar with `--metadata PATH`, summarizing the workers of its
+//! process; runs spanning several processes write one sidecar per process.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use config::{Config, Generator};
+use io::IngestStats;
+use runtime::BatchStats;
+use wings_plan::plan::Plan;
+
+/// The value of a metadata key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Flag(bool),
+}
+
+impl fmt::Display for Value {
+    // values are written as JSON.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Text(ref text) => write!(f, "\"{}\"", escape(text)),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) if value.is_finite() => write!(f, "{:?}", value),
+            Value::Float(_) => write!(f, "null"),
+            Value::Flag(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<String> for Value { fn from(text: String) -> Value { Value::Text(text) } }
+impl<'a> From<&'a str> for Value { fn from(text: &'a str) -> Value { Value::Text(text.to_owned()) } }
+impl From<i64> for Value { fn from(value: i64) -> Value { Value::Integer(value) } }
+impl From<usize> for Value { fn from(value: usize) -> Value { Value::Integer(value as i64) } }
+impl From<f64> for Value { fn from(value: f64) -> Value { Value::Float(value) } }
+impl From<bool> for Value { fn from(value: bool) -> Value { Value::Flag(value) } }
+
+/// The keys that describe how a run was set up, rather than what it produced.
+const SETUP_PREFIXES: &'static [&'static str] = &["plan.", "dataset.", "config."];
+
+/// Metadata describing a run, in order of key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunMetadata {
+    entries: BTreeMap<String, Value>,
+}
+
+impl RunMetadata {
+    /// Allocates metadata with no keys.
+    pub fn new() -> Self { RunMetadata::default() }
+
+    /// Sets `key` to `value`, replacing any previous value.
+    pub fn set<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.entries.insert(key.to_owned(), value.into());
+    }
+
+    /// The value of `key`, if it is set.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    /// Adds `value` to the integer at `key`, which is zero if unset.
+    ///
+    /// Drivers use this to accumulate the statistics of several workers. A key holding something
+    /// other than an integer is replaced.
+    pub fn add(&mut self, key: &str, value: i64) {
+        let entry = self.entries.entry(key.to_owned()).or_insert(Value::Integer(0));
+        *entry = match *entry {
+            Value::Integer(current) => Value::Integer(current + value),
+            _ => Value::Integer(value),
+        };
+    }
+
+    /// The keys and values, in order of key.
+    pub fn entries(&self) -> Vec<(&str, &Value)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value)).collect()
+    }
+
+    /// Records the fingerprint and shape of `plan` under `plan.`.
+    pub fn record_plan(&mut self, plan: &Plan) {
+        self.set("plan.fingerprint", format!("{:016x}", plan.fingerprint()));
+        self.set("plan.queries", plan.queries().len());
+    }
+
+    /// Records the dataset and settings of `config` under `dataset.` and `config.`.
+    pub fn record_config(&mut self, config: &Config) {
+        match config.generator {
+            Some(Generator::Rmat { scale, edges, seed }) => {
+                self.set("dataset.generator", "rmat");
+                self.set("dataset.scale", scale as i64);
+                self.set("dataset.edges", edges);
+                self.set("dataset.seed", seed as i64);
+            },
+            None => {
+                if let Some(ref graph) = config.graph { self.set("dataset.graph", graph.as_str()); }
+            },
+        }
+        if let Some(ref plan) = config.plan { self.set("config.plan", plan.as_str()); }
+        if let Some(ref query) = config.query { self.set("config.query", query.as_str()); }
+        if let Some(ref schema) = config.schema { self.set("config.schema", schema.as_str()); }
+        self.set("config.base", config.base);
+        if let Some(bulk) = config.bulk { self.set("config.bulk", bulk); }
+        self.set("config.batch", config.batch);
+        self.set("config.chunks", config.chunks);
+        if let Some(batches) = config.batches { self.set("config.batches", batches); }
+        self.set("config.by_time", config.by_time);
+        if let Some(budget) = config.budget_ms { self.set("config.budget_ms", budget as i64); }
+        if let Some(bits) = config.bloom { self.set("config.bloom", bits); }
+        if let Some(degree) = config.min_degree { self.set("config.min_degree", degree); }
+        self.set("config.intersect", format!("{:?}", config.intersect));
+        self.set("config.semantics", format!("{:?}", config.semantics));
+        self.set("config.orientation", format!("{:?}", config.orientation));
+        self.set("config.counting", format!("{:?}", config.counting));
+        self.set("config.placement", format!("{:?}", config.placement));
+        self.set("config.index_copies", config.index_copies);
+        self.set("config.prefix_encoding", format!("{:?}", config.prefix_encoding));
+        self.set("config.malformed", format!("{:?}", config.malformed));
+    }
+
+    /// Adds the updates and changes in matches of a batch to the statistics under `run.` and `matches.`.
+    pub fn record_batch<T>(&mut self, stats: &BatchStats<T>) {
+        self.add("run.updates", stats.updates as i64);
+        for &(query, count) in stats.matches.iter() {
+            self.add(&format!("matches.{}", query), count);
+        }
+    }
+
+    /// Records how much input was read and skipped under `input.`.
+    ///
+    /// Every worker reads the same input, so the statistics replace those of other workers.
+    pub fn record_ingest(&mut self, stats: &IngestStats) {
+        self.set("input.lines", stats.lines as i64);
+        self.set("input.changes", stats.changes as i64);
+        self.set("input.skipped", stats.skipped as i64);
+        if let Some(node) = stats.max_node { self.set("input.max_node", node as i64); }
+    }
+
+    /// The keys whose values differ between `self` and `other`, including keys only one of them sets.
+    pub fn differences(&self, other: &RunMetadata) -> Vec<String> {
+        let mut keys = self.entries.keys().chain(other.entries.keys())
+            .filter(|key| self.entries.get(*key) != other.entries.get(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// True if `self` and `other` used the same plan, dataset, and configuration.
+    ///
+    /// Results of runs are only comparable if they were set up the same way, which this checks
+    /// before comparing anything else.
+    pub fn same_setup(&self, other: &RunMetadata) -> bool {
+        self.differences(other).iter().all(|key| !SETUP_PREFIXES.iter().any(|prefix| key.starts_with(prefix)))
+    }
+
+    /// The metadata as a JSON object, with one key per line.
+    pub fn to_json(&self) -> String {
+        let fields = self.entries.iter().map(|(key, value)| format!("  \"{}\": {}", escape(key), value)).collect::<Vec<_>>();
+        format!("{{\n{}\n}}\n", fields.join(",\n"))
+    }
+
+    /// Parses metadata from a flat JSON object of text, number, and flag values.
+    pub fn from_json(text: &str) -> Result<RunMetadata, String> {
+        let mut parser = Parser { chars: text.chars().collect(), position: 0 };
+        let mut metadata = RunMetadata::new();
+        parser.expect('{')?;
+        if !parser.eat('}') {
+            loop {
+                let key = parser.string()?;
+                parser.expect(':')?;
+                let value = parser.value()?;
+                metadata.entries.insert(key, value);
+                if parser.eat('}') { break; }
+                parser.expect(',')?;
+            }
+        }
+        if parser.skip_whitespace() < parser.chars.len() {
+            return Err(format!("trailing characters at {}", parser.position));
+        }
+        Ok(metadata)
+    }
+
+    /// Writes the metadata as JSON to `path`.
+    pub fn write_sidecar(&self, path: &str) -> io::Result<()> {
+        File::create(path)?.write_all(self.to_json().as_bytes())
+    }
+
+    /// Reads metadata written by `write_sidecar`.
+    pub fn read_sidecar(path: &str) -> io::Result<RunMetadata> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        RunMetadata::from_json(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, error)))
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// just enough of a JSON parser to read back sidecars.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) -> usize {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() { self.position += 1; }
+        self.position
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).cloned()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) { self.position += 1; true } else { false }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) { Ok(()) } else { Err(format!("expected '{}' at {}", c, self.position)) }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            let c = *self.chars.get(self.position).ok_or("unterminated string")?;
+            self.position += 1;
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = *self.chars.get(self.position).ok_or("unterminated string")?;
+                    self.position += 1;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        't' => text.push('\t'),
+                        'u' => {
+                            let digits = self.chars.get(self.position .. self.position + 4).ok_or("truncated escape")?.iter().collect::<String>();
+                            self.position += 4;
+                            let code = u32::from_str_radix(&digits, 16).map_err(|_| format!("malformed escape: {}", digits))?;
+                            text.push(::std::char::from_u32(code).ok_or_else(|| format!("malformed escape: {}", digits))?);
+                        },
+                        other => text.push(other),
+                    }
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::Text),
+            Some(_) => {
+                let start = self.position;
+                while self.position < self.chars.len() && !",}".contains(self.chars[self.position]) && !self.chars[self.position].is_whitespace() {
+                    self.position += 1;
+                }
+                let token = self.chars[start .. self.position].iter().collect::<String>();
+                match token.as_str() {
+                    "true" => Ok(Value::Flag(true)),
+                    "false" => Ok(Value::Flag(false)),
+                    "null" => Ok(Value::Float(::std::f64::NAN)),
+                    _ => token.parse().map(Value::Integer)
+                              .or_else(|_| token.parse().map(Value::Float))
+                              .map_err(|_| format!("malformed value: {}", token)),
+                }
+            },
+            None => Err("expected a value".to_owned()),
+        }
+    }
+}
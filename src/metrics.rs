@@ -0,0 +1,177 @@
+//! Counters describing a run, in Prometheus text format.
+//!
+//! A `Metrics` registry accumulates the statistics of each batch the workers of a process complete:
+//! the matches created and deleted for each query node, the number of batches and their latency,
+//! the number of entries held by each worker's indices, and, if engines count them, the work reads
+//! of the indices did (see `Engine::set_read_stats`). Given the AGM exponents of the query
+//! nodes (see `Plan::agm_exponent`), it also reports each query's worst-case bound for the current
+//! number of edges, against which the matches created can be compared. The registry is shared by cloning it
+//! into each worker, and `render` describes its contents in the Prometheus text exposition format.
+//!
+//! With the `prometheus` feature, `serve` answers HTTP requests on an address with the rendered
+//! metrics, so that a Prometheus server can scrape them without further glue. Given an
+//! introspection registry (see `set_introspection`), it answers requests for `/introspect` with
+//! the registry's description of the dataflows instead.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use runtime::BatchStats;
+use wings_plan::agm::agm_bound;
+use wings_rule::ReadStats;
+use faults;
+use introspect::Introspection;
+
+/// A shared registry of counters for the workers of a process.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    created: HashMap<usize, i64>,
+    deleted: HashMap<usize, i64>,
+    batches: u64,
+    updates: u64,
+    index_seconds: f64,
+    match_seconds: f64,
+    index_entries: HashMap<usize, (usize, usize)>,
+    edges: HashMap<usize, usize>,
+    exponents: Vec<(usize, f64)>,
+    reads: (ReadStats, ReadStats),
+    introspection: Option<Introspection>,
+}
+
+impl Metrics {
+    /// Allocates a registry with all counters at zero.
+    pub fn new() -> Self {
+        Metrics { inner: Arc::new(Mutex::new(Registry::default())) }
+    }
+
+    /// Sets the AGM exponents of the query nodes, whose bounds are then reported.
+    pub fn set_agm_exponents(&self, exponents: Vec<(usize, f64)>) {
+        faults::lock(&self.inner).exponents = exponents;
+    }
+
+    /// Serves `introspection` alongside the metrics, at `/introspect`; see `introspect`.
+    pub fn set_introspection(&self, introspection: Introspection) {
+        faults::lock(&self.inner).introspection = Some(introspection);
+    }
+
+    /// Records a batch completed by `worker`, whose indices then held `index_len` entries.
+    pub fn record<T>(&self, worker: usize, stats: &BatchStats<T>, index_len: (usize, usize)) {
+        let mut registry = faults::lock(&self.inner);
+        for &(query, count) in stats.created.iter() {
+            *registry.created.entry(query).or_insert(0) += count;
+        }
+        for &(query, count) in stats.deleted.iter() {
+            *registry.deleted.entry(query).or_insert(0) += count;
+        }
+        registry.batches += 1;
+        registry.updates += stats.updates as u64;
+        registry.index_seconds += seconds(stats.index_time);
+        registry.match_seconds += seconds(stats.match_time);
+        registry.index_entries.insert(worker, index_len);
+        registry.edges.insert(worker, stats.edges);
+        registry.reads.0.add(&stats.reads.0);
+        registry.reads.1.add(&stats.reads.1);
+    }
+
+    /// The metrics in Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = faults::lock(&self.inner);
+        let mut text = String::new();
+
+        counter(&mut text, "wings_matches_created_total", "Matches created, by query node.");
+        for (query, count) in sorted(&registry.created) {
+            text.push_str(&format!("wings_matches_created_total{{query=\"{}\"}} {}\n", query, count));
+        }
+        counter(&mut text, "wings_matches_deleted_total", "Matches deleted, by query node.");
+        for (query, count) in sorted(&registry.deleted) {
+            text.push_str(&format!("wings_matches_deleted_total{{query=\"{}\"}} {}\n", query, count));
+        }
+
+        counter(&mut text, "wings_batches_total", "Batches completed, summed over workers.");
+        text.push_str(&format!("wings_batches_total {}\n", registry.batches));
+        counter(&mut text, "wings_updates_total", "Edge updates introduced, summed over workers.");
+        text.push_str(&format!("wings_updates_total {}\n", registry.updates));
+        counter(&mut text, "wings_batch_seconds_total", "Time spent completing batches, by phase and summed over workers.");
+        text.push_str(&format!("wings_batch_seconds_total{{phase=\"index\"}} {}\n", registry.index_seconds));
+        text.push_str(&format!("wings_batch_seconds_total{{phase=\"match\"}} {}\n", registry.match_seconds));
+
+        text.push_str("# HELP wings_index_entries Entries held by the graph indices, by worker and index.\n");
+        text.push_str("# TYPE wings_index_entries gauge\n");
+        for (worker, (forward, reverse)) in sorted(&registry.index_entries) {
+            text.push_str(&format!("wings_index_entries{{worker=\"{}\",index=\"forward\"}} {}\n", worker, forward));
+            text.push_str(&format!("wings_index_entries{{worker=\"{}\",index=\"reverse\"}} {}\n", worker, reverse));
+        }
+
+        counter(&mut text, "wings_index_reads_total", "Work done reading the graph indices, by index and kind, if counted.");
+        for &(index, ref reads) in [("forward", registry.reads.0), ("reverse", registry.reads.1)].iter() {
+            for &(kind, count) in [("operations", reads.operations), ("keys", reads.keys), ("search_steps", reads.search_steps),
+                                   ("runs_scanned", reads.runs_scanned), ("diffs_scanned", reads.diffs_scanned)].iter() {
+                text.push_str(&format!("wings_index_reads_total{{index=\"{}\",kind=\"{}\"}} {}\n", index, kind, count));
+            }
+        }
+
+        let edges = registry.edges.values().sum::<usize>();
+        text.push_str("# HELP wings_graph_edges Edges in the graph, summed over workers.\n");
+        text.push_str("# TYPE wings_graph_edges gauge\n");
+        text.push_str(&format!("wings_graph_edges {}\n", edges));
+        if !registry.exponents.is_empty() {
+            text.push_str("# HELP wings_agm_bound Worst-case number of matches for the current edges, by query node.\n");
+            text.push_str("# TYPE wings_agm_bound gauge\n");
+            for &(query, exponent) in registry.exponents.iter() {
+                text.push_str(&format!("wings_agm_bound{{query=\"{}\"}} {}\n", query, agm_bound(edges, exponent)));
+            }
+        }
+
+        text
+    }
+
+    /// Serves the rendered metrics over HTTP at `address`, from a background thread.
+    ///
+    /// Requests for `/introspect` are answered with the introspection registry's JSON, if one is
+    /// set, and every other request with the current metrics, whatever its path. Clients are
+    /// answered one at a time, and given a second to send their request and to read the answer.
+    #[cfg(feature = "prometheus")]
+    pub fn serve(&self, address: &str) -> ::std::io::Result<::std::thread::JoinHandle<()>> {
+        use std::io::{Read, Write};
+        let listener = ::std::net::TcpListener::bind(address)?;
+        let metrics = self.clone();
+        Ok(::std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(mut stream) = stream {
+                    // one thread answers every client, so none may hold it for long.
+                    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+                    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+                    // only the path of the request matters.
+                    let mut request = [0u8; 1024];
+                    let _ = stream.read(&mut request);
+                    let introspection = faults::lock(&metrics.inner).introspection.clone();
+                    let (body, content) = match introspection {
+                        Some(ref introspection) if request.starts_with(b"GET /introspect") => (introspection.render(), "application/json"),
+                        _ => (metrics.render(), "text/plain; version=0.0.4"),
+                    };
+                    let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", content, body.len(), body);
+                }
+            }
+        }))
+    }
+}
+
+fn counter(text: &mut String, name: &str, help: &str) {
+    text.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n", name, help, name));
+}
+
+fn sorted<V: Copy>(map: &HashMap<usize, V>) -> Vec<(usize, V)> {
+    let mut entries = map.iter().map(|(&key, &val)| (key, val)).collect::<Vec<_>>();
+    entries.sort_by_key(|x| x.0);
+    entries
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
@@ -0,0 +1,47 @@
+//! The supported public surface of the crate.
+//!
+//! Programs embedding the engine should import what they need from here:
+//!
+//!     use alg3_dynamic::prelude::*;
+//!
+//! Everything re-exported here follows semantic versioning: it changes incompatibly only with the
+//! crate's major version (or, before 1.0, its minor version). The modules it is drawn from hold
+//! more, and the modules hidden from the documentation (`wings_rule`, `timely_rule`, and `wire`)
+//! hold the operators and index layout the engine is built from; these remain reachable for
+//! experiments, but may change in any release.
+
+pub use ::{Node, Edge, LabeledEdge, Weight};
+
+// running plans, and the statistics of each epoch.
+pub use runtime::{Engine, Epoch, BatchStats, Bundle, Changes, Cursor, CursorExpired, TimedUpdate, TimedInputError};
+pub use runtime::{run_deterministic, run_distributed, compare_graphs, apply_patch, graph_difference, change_digest};
+
+// plans, and the settings that describe how they match patterns.
+pub use wings_plan::{Plan, GraphStreamIndex, IntersectDirection, JoinHint, MatchSemantics, Orientation, Counting, Placement, GlobalCounts, PrefixEncoding, ExtensionLimit, LimitOrder};
+pub use wings_plan::plan::{read_plan, read_plan_from, PlanError, PlanBuilder, NodeId, Vertex};
+pub use wings_plan::parser::{parse_query, parse_query_with, plan_motif, ParsedQuery};
+pub use wings_plan::schema::{Schema, AttributeType};
+pub use wings_plan::optimizer::{Optimizer, OptimizedPlan, DegreeStatistics};
+pub use wings_plan::document::{PlanDocument, NodeDocument, EdgeDocument, OperationDocument, OperationKind};
+pub use wings_plan::sorted::SortedOutput;
+pub use wings_plan::properties::{GraphSummary, summarize};
+pub use wings_plan::attributes::{AttributeIndex, enrich_matches};
+pub use wings_plan::limit::limit_matches;
+pub use wings_plan::bindings::{BindingIndex, bind_prefixes};
+pub use wings_plan::sources::{ExtensionSource, ListSource, SourceExtender, SourceReport, check_source};
+pub use wings_plan::filters::{AttributeFilter, Comparison, VertexAttributes};
+pub use wings_plan::aggregation::{Aggregation, Aggregator, GroupKey};
+pub use wings_plan::standing::StandingMatches;
+
+// delivering matches to their consumers; subscription filters are `subscribe::Filter`, left out
+// so as not to collide with timely's `Filter` operator when both are imported with `*`.
+pub use subscribe::{SubscriptionRegistry, SubscriptionUpdate, RouteSubscriptions};
+pub use anchor::{AnchorRegistry, AnchorUpdate, RouteAnchors};
+pub use wings_plan::existence::{anchor_pairs, pair_existence};
+pub use metrics::Metrics;
+pub use introspect::{Introspection, WorkerReport, StageReport};
+pub use faults::{ErrorEvents, OperatorError};
+#[cfg(feature = "sinks")] pub use metadata::RunMetadata;
+
+// tuning the maintained indices.
+pub use wings_rule::{Maintenance, MergePolicy, DefaultMergePolicy, MergeStats, ReadStats, IntersectKernel, KernelStats, Breaker, Materialization, IndexRegistry};
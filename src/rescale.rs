@@ -0,0 +1,111 @@
+//! Offline re-partitioning of index checkpoints between numbers of workers.
+//!
+//! A checkpoint is a directory holding the index snapshots of every worker of a run, as written by
+//! `write_checkpoint`: `worker-I.forward` and `worker-I.reverse` for each worker `I`, in the format
+//! of `io::write_snapshot`. Each worker's snapshots hold only the keys it owns, so they can only
+//! resume a run with the same workers and placement (see `Engine::from_snapshot`).
+//!
+//! `repartition` rehashes the pairs of the snapshots of `N` workers into those of `M` workers, as
+//! the indices of a run with `M` workers and a given placement would hold them, and `rescale`
+//! does the same for checkpoint directories. This lets a deployment change its number of workers
+//! between runs without replaying its input. Only the forward and reverse pairs are moved; both
+//! are keyed by their first vertex, and the snapshots hold no times, so nothing else changes.
+
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use io::{read_snapshot, write_snapshot};
+use wings_plan::Placement;
+use ::Edge;
+
+/// The forward and reverse index snapshots of one worker, as `Engine::snapshot` returns them.
+pub type WorkerSnapshot = (Vec<Edge>, Vec<Edge>);
+
+/// Moves the pairs of `snapshots`, one for each worker of a run, to the `workers` workers of a run
+/// with `placement`, returning one snapshot for each of them.
+///
+/// Each pair goes to the worker owning its key, the first vertex, and each snapshot is sorted.
+/// Returns an error if `placement` names workers beyond `workers`.
+pub fn repartition(snapshots: Vec<WorkerSnapshot>, workers: usize, placement: &Placement) -> Result<Vec<WorkerSnapshot>, String> {
+    if workers == 0 { return Err("repartitioning needs at least one worker".to_owned()); }
+    placement.validate(workers)?;
+    let router = placement.router();
+    let owner = |key| (router(key) % workers as u64) as usize;
+
+    let mut result = vec![(Vec::new(), Vec::new()); workers];
+    for (forward, reverse) in snapshots {
+        for pair in forward { result[owner(pair.0)].0.push(pair); }
+        for pair in reverse { result[owner(pair.0)].1.push(pair); }
+    }
+    for &mut (ref mut forward, ref mut reverse) in result.iter_mut() {
+        forward.sort();
+        reverse.sort();
+    }
+    Ok(result)
+}
+
+/// Writes the snapshots of worker `worker` to the checkpoint directory `dir`, creating it if needed.
+pub fn write_checkpoint<P: AsRef<Path>>(dir: P, worker: usize, snapshot: &WorkerSnapshot) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    write_snapshot(&path(dir, worker, "forward"), &snapshot.0)?;
+    write_snapshot(&path(dir, worker, "reverse"), &snapshot.1)
+}
+
+/// Reads the snapshots of worker `worker` from the checkpoint directory `dir`.
+pub fn read_checkpoint<P: AsRef<Path>>(dir: P, worker: usize) -> io::Result<WorkerSnapshot> {
+    let dir = dir.as_ref();
+    Ok((read_snapshot(&path(dir, worker, "forward"))?, read_snapshot(&path(dir, worker, "reverse"))?))
+}
+
+/// The number of workers whose snapshots the checkpoint directory `dir` holds.
+///
+/// Returns an error if the directory holds no snapshots, or is missing some worker's.
+pub fn checkpoint_workers<P: AsRef<Path>>(dir: P) -> io::Result<usize> {
+    let dir = dir.as_ref();
+    let mut workers = 0;
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.starts_with("worker-") && name.ends_with(".forward") {
+            if let Ok(worker) = name["worker-".len() .. name.len() - ".forward".len()].parse::<usize>() {
+                workers = ::std::cmp::max(workers, worker + 1);
+            }
+        }
+    }
+    if workers == 0 {
+        return Err(Error::new(ErrorKind::NotFound, format!("{} holds no index snapshots", dir.display())));
+    }
+    for worker in 0 .. workers {
+        for &direction in ["forward", "reverse"].iter() {
+            if !Path::new(&path(dir, worker, direction)).exists() {
+                return Err(Error::new(ErrorKind::NotFound, format!("{} lacks the {} snapshot of worker {}", dir.display(), direction, worker)));
+            }
+        }
+    }
+    Ok(workers)
+}
+
+/// Re-partitions the checkpoint in `from` into a checkpoint in `to` for `workers` workers with `placement`.
+///
+/// Returns the number of workers the checkpoint in `from` was taken with. The checkpoint in `to`
+/// is not overwritten if it exists, as snapshots of its workers beyond `workers` would remain.
+pub fn rescale<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q, workers: usize, placement: &Placement) -> io::Result<usize> {
+    if checkpoint_workers(&to).is_ok() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{} already holds a checkpoint", to.as_ref().display())));
+    }
+    let previous = checkpoint_workers(&from)?;
+    let mut snapshots = Vec::with_capacity(previous);
+    for worker in 0 .. previous {
+        snapshots.push(read_checkpoint(&from, worker)?);
+    }
+    let snapshots = repartition(snapshots, workers, placement).map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    for (worker, snapshot) in snapshots.iter().enumerate() {
+        write_checkpoint(&to, worker, snapshot)?;
+    }
+    Ok(previous)
+}
+
+fn path(dir: &Path, worker: usize, direction: &str) -> String {
+    dir.join(format!("worker-{}.{}", worker, direction)).to_string_lossy().into_owned()
+}
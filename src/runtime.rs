@@ -0,0 +1,1647 @@
+//! A per-worker driver for plan execution.
+//!
+//! An `Engine` owns the inputs, probes, and index handles of a dataflow executing a `Plan`, and
+//! takes care of the epoch choreography every example otherwise repeats: loading the base graph,
+//! introducing each batch of updates in its own epoch, waiting for index maintenance and then for
+//! matching to complete, and merging the indices once an epoch is done.
+//!
+//! The engine is generic over its epoch type. Epochs advance either to their successor, or to an
+//! epoch chosen by the driver (e.g. derived from a wall clock); the index code only ever compares
+//! timestamps, and so makes no assumptions about how they advance.
+//!
+//! Drivers may attach source positions (e.g. file lines or log offsets) to the current epoch. Once
+//! the epoch is complete the positions are reported by `frontier_positions`, letting downstream
+//! systems learn exactly which input is reflected in the results they have received.
+//!
+//! An engine belongs to the worker thread it was built on, as do the indices it drives. Application
+//! threads should be handed data the engine reports, such as batch statistics or snapshots,
+//! rather than access to the indices themselves.
+//!
+//! Base graphs too large to load in one epoch may be bulk loaded over many epochs, in which only
+//! index maintenance runs, followed by a seed epoch that either reports initial results for a
+//! final set of edges or skips them, after which the engine processes updates incrementally.
+//!
+//! Indices may be rebuilt from scratch between epochs, reclaiming the memory held by deleted
+//! edges, either in place or on background threads whose results are swapped in between epochs.
+//! With compaction enabled the engine rebuilds them itself whenever they grow too large for the
+//! edges they hold, so that their memory reaches a steady state under continuous churn.
+//!
+//! `compare_graphs` and `apply_patch` wrap this choreography to report how the counts of matches
+//! differ between two graphs, enumerating only the matches that differ.
+//!
+//! Rather than receiving each epoch's changes to matches with its statistics, drivers of recording
+//! engines may have the engine retain them, and later pull them in pages through a `Cursor`. The
+//! engine holds the changes of an epoch until a cursor for it is dropped, or until more epochs
+//! than its retention limit complete, after which cursors for the epoch report it as expired.
+//!
+//! A plan's placement restricts its indices and the extension of prefixes to a subset of the
+//! workers; every worker still reads input and drives epochs, and workers outside the placement
+//! simply hold empty indices. Plans may also read several copies of the indices, each routing keys
+//! to workers with its own salt, so that a heavy key is processed at different workers by
+//! different stages of the plan.
+//!
+//! A batch may also be assembled over several calls, e.g. while parsing a file, and is only handed
+//! to the dataflow once it is committed, so that a driver that finds a problem halfway through can
+//! abort the batch and leave the graph as it was. Batches may also be given as adjacency bundles,
+//! each updating the edges from one source to a list of destinations.
+//!
+//! Updates may also carry their own times, as `(edge, time, diff)` triples from systems that
+//! already produce multi-temporal change batches. The engine stages them until the driver
+//! advances the input frontier past their times, and then introduces each at its own time and
+//! completes them together. Updates may correct any time the frontier has not yet passed.
+//!
+//! Each worker reports the changes to matches it produced itself. Plans may also have engines
+//! gather cluster-wide totals of matches, at the first worker or at every worker, through a final
+//! stage that exchanges each worker's changes in counts; epochs then complete only once the stage
+//! has absorbed them, so that the totals reported for each epoch are exact.
+//!
+//! To compare runs without storing their outputs, e.g. with different numbers of workers, an
+//! engine can report an order-independent digest of each epoch's changes for each query node.
+//!
+//! Plans may also have engines hold the standing matches of each query node, consolidated from
+//! their changes, so that a consumer that restarts resyncs from them between epochs rather than
+//! replaying every change since the base graph was loaded.
+//!
+//! Vertices may be contracted into others, e.g. as entity resolution discovers duplicates. The
+//! engine then rewrites the contracted vertex in all later updates, and moves its indexed edges to
+//! the vertex it was contracted into, so that match counts reflect the contracted graph.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+use timely::dataflow::{InputHandle, ProbeHandle, Scope, Stream};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::*;
+use timely::order::PartialOrder;
+use timely::progress::Timestamp;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::{RootTimestamp, PathSummary};
+use abomonation::Abomonation;
+
+use wings_plan::{GlobalCounts, GraphStreamIndex, Index, Orientation, Placement, Plan};
+use wings_plan::graph_stream::GraphStreamIndexHandle;
+use wings_plan::conventions::symmetrize;
+use wings_plan::optimizer::DegreeStatistics;
+use wings_plan::degree_filter::degree_filter;
+use wings_plan::standing::{StandingMatches, consolidate_standing};
+use introspect::{Introspection, StageReport, WorkerReport};
+use wings_rule::{Breaker, ForeignKeys, Maintenance, Materialization, Materializer, ReadStats};
+use wire::check_peers;
+use ::{Node, Edge};
+
+/// The timestamp type of dataflows built by the engine.
+pub type Time<T=u32> = Product<RootTimestamp, T>;
+
+/// A timestamp the engine can use for its epochs.
+pub trait Epoch: Timestamp+Copy {
+    /// The epoch following `self`.
+    ///
+    /// This method panics rather than wrapping around when the epoch type is exhausted.
+    fn succ(&self) -> Self;
+}
+
+impl Epoch for u32 {
+    fn succ(&self) -> u32 { self.checked_add(1).expect("u32 epochs exhausted") }
+}
+
+impl Epoch for u64 {
+    fn succ(&self) -> u64 { self.checked_add(1).expect("u64 epochs exhausted") }
+}
+
+/// An epoch made of a date and a sequence number within that date.
+///
+/// The `date` is typically derived from a clock (e.g. seconds since the Unix epoch), and `seq`
+/// distinguishes several epochs within the same date. Epochs are ordered lexicographically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dated {
+    pub date: u64,
+    pub seq: u32,
+}
+
+impl Dated {
+    /// The first epoch of `date`.
+    pub fn at(date: u64) -> Self { Dated { date, seq: 0 } }
+
+    /// The first epoch of the current date, in seconds since the Unix epoch.
+    pub fn now() -> Self {
+        let since = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH).expect("clock before 1970");
+        Dated::at(since.as_secs())
+    }
+}
+
+impl ::std::fmt::Display for Dated {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}.{}", self.date, self.seq)
+    }
+}
+
+impl Abomonation for Dated { }
+
+impl PartialOrder for Dated {
+    fn less_equal(&self, other: &Self) -> bool { self <= other }
+}
+
+impl Timestamp for Dated {
+    type Summary = DatedSummary;
+}
+
+impl Epoch for Dated {
+    fn succ(&self) -> Dated { Dated { date: self.date, seq: self.seq.checked_add(1).expect("sequence numbers exhausted") } }
+}
+
+/// The only path summary for `Dated`, which leaves times unchanged.
+///
+/// The engine's dataflows have no cycles, and so never need to advance a `Dated` along a path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatedSummary;
+
+impl PartialOrder for DatedSummary {
+    fn less_equal(&self, _other: &Self) -> bool { true }
+}
+
+impl PathSummary<Dated> for DatedSummary {
+    fn results_in(&self, src: &Dated) -> Option<Dated> { Some(*src) }
+    fn followed_by(&self, _other: &Self) -> Option<Self> { Some(DatedSummary) }
+}
+
+/// Executes `plan` deterministically on a single worker.
+///
+/// The base graph `base` is loaded first, after which each of `batches` is introduced in its
+/// own epoch. The statistics of each epoch, starting with the base graph, record every change to
+/// a match in sorted order, so the results of a run do not depend on scheduling and may be
+/// compared directly, e.g. in tests of plan semantics. The plan is constructed by `plan`, as
+/// plans cannot be sent to a worker thread.
+pub fn run_deterministic<F>(plan: F, base: Vec<Edge>, batches: Vec<Vec<(Edge, i32)>>) -> Vec<BatchStats>
+    where F: Fn()->Plan+Send+Sync+'static
+{
+    run_distributed(1, plan, base, batches)
+}
+
+/// Executes `plan` as `run_deterministic` does, but on `workers` worker threads.
+///
+/// Each worker introduces every `workers`th edge of the base graph and of each batch, and the
+/// statistics of the workers are combined for each epoch: counts of matches and updates are
+/// summed, times are the largest of any worker, and changes to matches are consolidated; queries
+/// whose changes in matches cancel are omitted from `matches`. The results should not depend on
+/// the number of workers, which tests can check by comparing the `matches` and `embeddings` of
+/// runs with different numbers of workers.
+pub fn run_distributed<F>(workers: usize, plan: F, base: Vec<Edge>, batches: Vec<Vec<(Edge, i32)>>) -> Vec<BatchStats>
+    where F: Fn()->Plan+Send+Sync+'static
+{
+    run_workers(workers, plan, base, batches, true)
+}
+
+/// The changes in the number of matches of each query node between two graphs.
+///
+/// The graph `before` is loaded as the base graph, and the difference between the graphs as a
+/// single batch of updates, so that only the matches that differ are enumerated. Graphs are
+/// multisets of edges. Returns the change for each query node whose count changes, in order.
+pub fn compare_graphs<F>(workers: usize, plan: F, before: Vec<Edge>, after: Vec<Edge>) -> Vec<(usize, i64)>
+    where F: Fn()->Plan+Send+Sync+'static
+{
+    let patch = graph_difference(&before, &after);
+    apply_patch(workers, plan, before, patch)
+}
+
+/// The changes in the number of matches of each query node when `patch` is applied to `base`.
+///
+/// Returns the change for each query node whose count changes, in order.
+pub fn apply_patch<F>(workers: usize, plan: F, base: Vec<Edge>, patch: Vec<(Edge, i32)>) -> Vec<(usize, i64)>
+    where F: Fn()->Plan+Send+Sync+'static
+{
+    let mut results = run_workers(workers, plan, base, vec![patch], false);
+    results.pop().expect("no patch epoch").matches
+}
+
+/// The updates that turn the multiset of edges `before` into `after`.
+pub fn graph_difference(before: &[Edge], after: &[Edge]) -> Vec<(Edge, i32)> {
+    let mut updates = before.iter().map(|&edge| (edge, -1)).chain(after.iter().map(|&edge| (edge, 1))).collect::<Vec<_>>();
+    updates.sort();
+    let mut difference: Vec<(Edge, i32)> = Vec::new();
+    for (edge, diff) in updates {
+        let merge = match difference.last_mut() {
+            Some(&mut (e, ref mut d)) if e == edge => { *d += diff; true },
+            _ => false,
+        };
+        if !merge { difference.push((edge, diff)); }
+    }
+    difference.retain(|x| x.1 != 0);
+    difference
+}
+
+// runs `plan` on `workers` threads, recording the changes to matches if `record` is set.
+fn run_workers<F>(workers: usize, plan: F, base: Vec<Edge>, batches: Vec<Vec<(Edge, i32)>>, record: bool) -> Vec<BatchStats>
+    where F: Fn()->Plan+Send+Sync+'static
+{
+    let configuration = if workers == 1 {
+        ::timely::communication::Configuration::Thread
+    } else {
+        ::timely::communication::Configuration::Process(workers)
+    };
+
+    let guards = ::timely::execute(configuration, move |worker| {
+        let index = worker.index();
+        let peers = worker.peers();
+        let plan = plan();
+        let mut engine = if record { Engine::recording(worker, &plan) } else { Engine::new(worker, &plan) };
+        let part = base.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect();
+        let mut results = vec![engine.load_base(part)];
+        for batch in batches.iter() {
+            let part = batch.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect();
+            results.push(engine.step(part));
+        }
+        engine.finish();
+        results
+    }).expect("failed to start workers");
+
+    let mut combined: Option<Vec<BatchStats>> = None;
+    for result in guards.join() {
+        let result = result.expect("worker failed");
+        combined = Some(match combined {
+            None => result,
+            Some(combined) => combined.into_iter().zip(result.into_iter()).map(|(x, y)| x.combine(y)).collect(),
+        });
+    }
+    // queries whose changes cancel are reported by some numbers of workers but not others.
+    let mut combined = combined.expect("no worker");
+    for stats in combined.iter_mut() { stats.matches.retain(|x| x.1 != 0); }
+    combined
+}
+
+/// The probes a driver must wait on to complete an epoch.
+///
+/// Matching operators read the graph indices at each time only once the indices have absorbed
+/// all updates at that time, so a driver must first wait for both index probes and only then for
+/// the probe of the match streams. Merging the indices before both have happened yields wrong
+/// results. `complete` performs these steps in order.
+#[derive(Clone)]
+pub struct EpochProbes<T: Timestamp> {
+    /// Times completely absorbed into the forward index.
+    pub forward: ProbeHandle<T>,
+    /// Times completely absorbed into the reverse index.
+    pub reverse: ProbeHandle<T>,
+    /// Times for which all matches have been produced.
+    pub matches: ProbeHandle<T>,
+}
+
+impl<T: Timestamp> EpochProbes<T> {
+    /// Steps `worker` until all work at times less than `frontier` is complete.
+    ///
+    /// Returns the time spent until both indices were complete, and the time spent after that
+    /// until matching was complete.
+    pub fn complete<A: Allocate>(&self, worker: &mut Worker<A>, frontier: &T) -> (Duration, Duration) {
+        self.complete_observed(worker, frontier, &mut || { })
+    }
+
+    /// As `complete`, but calling `observe` between steps of `worker`.
+    pub fn complete_observed<A: Allocate>(&self, worker: &mut Worker<A>, frontier: &T, observe: &mut FnMut()) -> (Duration, Duration) {
+
+        let start = Instant::now();
+
+        let forward = &self.forward;
+        let reverse = &self.reverse;
+        let matches = &self.matches;
+
+        worker.step_while(|| { observe(); forward.less_than(frontier) || reverse.less_than(frontier) });
+        let index_time = start.elapsed();
+
+        debug_assert!(!forward.less_than(frontier) && !reverse.less_than(frontier));
+        worker.step_while(|| { observe(); matches.less_than(frontier) });
+        let match_time = start.elapsed() - index_time;
+
+        (index_time, match_time)
+    }
+}
+
+/// Statistics about one epoch of work.
+#[derive(Debug, Clone)]
+pub struct BatchStats<T=u32> {
+    /// The epoch the batch was introduced at.
+    pub epoch: T,
+    /// The number of updates this worker introduced.
+    pub updates: usize,
+    /// Time spent until both indices absorbed the batch.
+    pub index_time: Duration,
+    /// Time spent after index maintenance until matching completed.
+    pub match_time: Duration,
+    /// Accumulated changes in matches observed at this worker, for each query node.
+    pub matches: Vec<(usize, i64)>,
+    /// Matches created at this worker, for each query node with any.
+    pub created: Vec<(usize, i64)>,
+    /// Matches deleted at this worker, for each query node with any.
+    pub deleted: Vec<(usize, i64)>,
+    /// The number of edges held by this worker's indices once the epoch completed.
+    pub edges: usize,
+    /// An estimate of the bytes allocated by this worker's indices once the epoch completed.
+    pub index_bytes: usize,
+    /// The number of prefixes discarded because matching exceeded the engine's time budget.
+    ///
+    /// If this is non-zero, the changes in matches reported for the epoch are partial.
+    pub discarded: usize,
+    /// The numbers of times propose operators materialized the candidates of the epoch eagerly,
+    /// and in chunks; see `Engine::set_materialization`.
+    pub materialized: (usize, usize),
+    /// Accumulated changes to each match observed at this worker, if the engine records them.
+    ///
+    /// Changes are sorted by query node and then by match, and those that cancel are omitted.
+    pub embeddings: Vec<(usize, Vec<Node>, i64)>,
+    /// Digests of the changes to matches observed at this worker, if the engine computes them.
+    ///
+    /// Digests are sorted by query node; see `change_digest`.
+    pub digests: Vec<(usize, u64)>,
+    /// The work reads of this worker's forward and reverse indices did, if the engine counts it.
+    ///
+    /// The counters are zero otherwise; see `Engine::set_read_stats`.
+    pub reads: (ReadStats, ReadStats),
+    /// The cluster-wide number of matches of each query node once the epoch completed, if this
+    /// worker gathers them; see `Plan::set_global_counts`.
+    ///
+    /// Totals are sorted by query node, and query nodes without matches are omitted.
+    pub totals: Vec<(usize, i64)>,
+    /// An estimate of the bytes taken by the standing matches this worker holds once the epoch
+    /// completed, if the plan has engines hold them; see `Plan::set_standing_matches`.
+    pub standing_bytes: usize,
+}
+
+impl<T> BatchStats<T> {
+    /// The bytes allocated by the indices for each edge they hold, if they hold any.
+    ///
+    /// Under continuous churn with compaction enabled this should settle to a constant, the
+    /// steady-state overhead of the indices per live edge.
+    pub fn bytes_per_edge(&self) -> Option<f64> {
+        if self.edges > 0 { Some(self.index_bytes as f64 / self.edges as f64) } else { None }
+    }
+
+    /// Combines the statistics of the same epoch at two workers.
+    pub fn combine(self, other: BatchStats<T>) -> BatchStats<T> {
+        let mut embeddings = self.embeddings;
+        embeddings.extend(other.embeddings);
+        embeddings.sort();
+        let mut consolidated: Vec<(usize, Vec<Node>, i64)> = Vec::with_capacity(embeddings.len());
+        for (query, embedding, diff) in embeddings {
+            let merge = match consolidated.last_mut() {
+                Some(&mut (ref q, ref e, ref mut d)) if *q == query && *e == embedding => { *d += diff; true },
+                _ => false,
+            };
+            if !merge { consolidated.push((query, embedding, diff)); }
+        }
+        consolidated.retain(|x| x.2 != 0);
+
+        BatchStats {
+            epoch: self.epoch,
+            updates: self.updates + other.updates,
+            index_time: ::std::cmp::max(self.index_time, other.index_time),
+            match_time: ::std::cmp::max(self.match_time, other.match_time),
+            matches: sum_counts(self.matches, other.matches),
+            created: sum_counts(self.created, other.created),
+            deleted: sum_counts(self.deleted, other.deleted),
+            edges: self.edges + other.edges,
+            index_bytes: self.index_bytes + other.index_bytes,
+            discarded: self.discarded + other.discarded,
+            materialized: (self.materialized.0 + other.materialized.0, self.materialized.1 + other.materialized.1),
+            embeddings: consolidated,
+            digests: sum_digests(self.digests, other.digests),
+            reads: {
+                let (mut forward, mut reverse) = self.reads;
+                forward.add(&other.reads.0);
+                reverse.add(&other.reads.1);
+                (forward, reverse)
+            },
+            // workers that gather totals all report the same ones.
+            totals: if self.totals.is_empty() { other.totals } else { self.totals },
+            standing_bytes: self.standing_bytes + other.standing_bytes,
+        }
+    }
+}
+
+// sums each worker's changes in the counts of each query node at each time, gathers the sums where
+// `global` says, and adds them to `gathered` once their time is complete there.
+fn gather_totals<G: Scope>(changes: &Stream<G, (usize, i64)>, global: GlobalCounts, gathered: Rc<RefCell<BTreeMap<G::Timestamp, HashMap<usize, i64>>>>) -> Stream<G, ()>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+    let sums = changes.unary_notify(Pipeline, "SumCounts", vec![], move |input, output, notificator| {
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let sums = stash.entry(time.time().clone()).or_insert_with(HashMap::new);
+            for (query, diff) in buffer.drain(..) { *sums.entry(query).or_insert(0i64) += diff; }
+            notificator.notify_at(time.retain());
+        });
+        notificator.for_each(|time, _, _| {
+            if let Some(sums) = stash.remove(time.time()) {
+                let mut session = output.session(&time);
+                for (query, sum) in sums {
+                    if sum != 0 { session.give((query, sum)); }
+                }
+            }
+        });
+    });
+
+    let routed = match global {
+        GlobalCounts::Replicated => sums.broadcast(),
+        _ => sums.exchange(|_| 0),
+    };
+
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+    routed.unary_notify(Pipeline, "GatherCounts", vec![], move |input, _output, notificator| {
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let sums = stash.entry(time.time().clone()).or_insert_with(HashMap::new);
+            for (query, sum) in buffer.drain(..) { *sums.entry(query).or_insert(0i64) += sum; }
+            notificator.notify_at(time.retain());
+        });
+        notificator.for_each(|time, _, _| {
+            if let Some(sums) = stash.remove(time.time()) {
+                gathered.borrow_mut().insert(time.time().clone(), sums);
+            }
+        });
+    })
+}
+
+// sums two lists of per-query counts, sorted by query, omitting those that cancel.
+fn sum_counts(mut counts: Vec<(usize, i64)>, other: Vec<(usize, i64)>) -> Vec<(usize, i64)> {
+    counts.extend(other);
+    counts.sort();
+    let mut summed: Vec<(usize, i64)> = Vec::with_capacity(counts.len());
+    for (query, count) in counts {
+        let merge = match summed.last_mut() {
+            Some(&mut (q, ref mut c)) if q == query => { *c += count; true },
+            _ => false,
+        };
+        if !merge { summed.push((query, count)); }
+    }
+    summed.retain(|x| x.1 != 0);
+    summed
+}
+
+// sums two lists of per-query digests, sorted by query, omitting those that cancel.
+fn sum_digests(mut digests: Vec<(usize, u64)>, other: Vec<(usize, u64)>) -> Vec<(usize, u64)> {
+    digests.extend(other);
+    digests.sort();
+    let mut summed: Vec<(usize, u64)> = Vec::with_capacity(digests.len());
+    for (query, digest) in digests {
+        let merge = match summed.last_mut() {
+            Some(&mut (q, ref mut d)) if q == query => { *d = d.wrapping_add(digest); true },
+            _ => false,
+        };
+        if !merge { summed.push((query, digest)); }
+    }
+    summed.retain(|x| x.1 != 0);
+    summed
+}
+
+/// The digest of a change of `diff` to the match `embedding`.
+///
+/// The digest of a set of changes is the wrapping sum of the digests of its changes, so it does
+/// not depend on the order of the changes, on how they are split among workers, or on whether
+/// changes to the same match are consolidated, and changes that cancel contribute nothing. Two
+/// runs whose epochs report the same digests very likely produced the same changes, which lets
+/// them be compared, e.g. across worker counts or versions, without storing their outputs. The
+/// hash is fixed, so digests are comparable across processes and platforms.
+pub fn change_digest(embedding: &[Node], diff: i64) -> u64 {
+    let mut hash = embedding.iter().fold(0xcbf29ce484222325u64, |hash, &node| {
+        (0 .. 4).fold(hash, |hash, byte| (hash ^ ((node >> (8 * byte)) & 0xff) as u64).wrapping_mul(0x100000001b3))
+    });
+    // a final mix, so that similar embeddings have unrelated digests.
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash.wrapping_mul(diff as u64)
+}
+
+/// The changes to matches of one epoch, as `(query, match, diff)` triples.
+pub type Changes = Vec<(usize, Vec<Node>, i64)>;
+
+/// An error reported by a cursor whose epoch the engine no longer retains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorExpired;
+
+impl ::std::fmt::Display for CursorExpired {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "the epoch's changes are no longer retained")
+    }
+}
+
+/// A cursor over the changes to matches of a completed epoch, pulled in pages.
+pub struct Cursor<T> {
+    epoch: T,
+    changes: Rc<RefCell<Option<Changes>>>,
+    position: usize,
+}
+
+impl<T: Copy> Cursor<T> {
+    /// The epoch whose changes the cursor reads.
+    pub fn epoch(&self) -> T { self.epoch }
+
+    /// The next `size` changes, in order of query node and then match.
+    ///
+    /// Returns an empty page once all changes have been read, and an error if the engine has
+    /// since stopped retaining the epoch.
+    pub fn next_page(&mut self, size: usize) -> Result<Changes, CursorExpired> {
+        match *self.changes.borrow() {
+            Some(ref changes) => {
+                let start = ::std::cmp::min(self.position, changes.len());
+                let end = ::std::cmp::min(start + size, changes.len());
+                self.position = end;
+                Ok(changes[start .. end].to_vec())
+            },
+            None => Err(CursorExpired),
+        }
+    }
+
+    /// The number of changes not yet read, or an error if the epoch is no longer retained.
+    pub fn remaining(&self) -> Result<usize, CursorExpired> {
+        match *self.changes.borrow() {
+            Some(ref changes) => Ok(changes.len().saturating_sub(self.position)),
+            None => Err(CursorExpired),
+        }
+    }
+}
+
+/// Updates to the edges leaving a vertex, as `(src, dsts, diff)`: each edge `(src, dst)` changes by `diff`.
+pub type Bundle = (Node, Vec<Node>, i32);
+
+/// Expands adjacency bundles into edge updates, in sorted order.
+pub fn expand_bundles(bundles: &[Bundle]) -> Vec<(Edge, i32)> {
+    let mut updates = Vec::with_capacity(bundles.iter().map(|bundle| bundle.1.len()).sum());
+    for &(src, ref dsts, diff) in bundles.iter() {
+        updates.extend(dsts.iter().map(|&dst| ((src, dst), diff)));
+    }
+    updates.sort();
+    updates
+}
+
+// updates addressed to a worker, as columns of sources, destinations, and diffs.
+type ColumnBatch = (u64, Vec<Node>, Vec<Node>, Vec<i32>);
+
+// moves each batch of columns to the worker it is addressed to, whole, and unpacks it into updates there.
+fn unpack_columns<G: Scope>(batches: &Stream<G, ColumnBatch>) -> Stream<G, (Edge, i32)> {
+    let mut buffer = Vec::new();
+    let exchange = ::timely::dataflow::channels::pact::Exchange::new(|x: &ColumnBatch| x.0);
+    batches.unary(exchange, "UnpackColumns", move |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let mut session = output.session(&time);
+            for (_, src, dst, diff) in buffer.drain(..) {
+                session.give_iterator(src.into_iter().zip(dst.into_iter()).zip(diff.into_iter()));
+            }
+        });
+    })
+}
+
+/// An update with its own time, as `(edge, time, diff)`.
+pub type TimedUpdate<T> = (Edge, T, i32);
+
+/// An error introducing timed updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimedInputError<T> {
+    /// An update was for `time`, which the input frontier `frontier` has already passed.
+    Late { time: T, frontier: T },
+    /// The updates to a base edge accumulated to a negative count.
+    NegativeBase(Edge),
+}
+
+impl<T: ::std::fmt::Debug> ::std::fmt::Display for TimedInputError<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            TimedInputError::Late { ref time, ref frontier } => write!(f, "update at {:?} is behind the input frontier {:?}", time, frontier),
+            TimedInputError::NegativeBase(edge) => write!(f, "base edge {:?} has a negative count", edge),
+        }
+    }
+}
+
+// how the indices of an engine start out.
+enum Start {
+    // empty, for the base graph to be loaded into.
+    Empty,
+    // restored from snapshots of this worker's forward and reverse indices.
+    Snapshot(Vec<Edge>, Vec<Edge>),
+    // empty, for pairs of forward snapshots to be loaded into as the base graph, as they are.
+    Partitioned,
+}
+
+// the retained changes of an epoch, and whether a cursor has been handed out for them.
+struct Retained<T> {
+    epoch: T,
+    changes: Rc<RefCell<Option<Changes>>>,
+    opened: bool,
+}
+
+// the parts of an engine an introspection report reads, which stay readable while it steps.
+struct Observer<T: Epoch> {
+    worker: usize,
+    time: Time<T>,
+    probes: EpochProbes<Time<T>>,
+    stages: Vec<(usize, Vec<usize>, ProbeHandle<Time<T>>)>,
+    handles: GraphStreamIndexHandle<Time<T>>,
+    gathered: Rc<RefCell<BTreeMap<Time<T>, HashMap<usize, i64>>>>,
+    buffers: Vec<(&'static str, usize)>,
+}
+
+impl<T: Epoch> Observer<T> {
+    fn report(&self) -> WorkerReport {
+        let stage = |name: String, operator: Option<Vec<usize>>, probe: &ProbeHandle<Time<T>>| StageReport {
+            name,
+            operator,
+            frontier: probe.with_frontier(|frontier| frontier.iter().map(|time| format!("{:?}", time.inner)).collect()),
+            behind: probe.less_than(&self.time),
+        };
+        let mut stages = vec![
+            stage("forward index".to_owned(), None, &self.probes.forward),
+            stage("reverse index".to_owned(), None, &self.probes.reverse),
+        ];
+        for &(query, ref address, ref probe) in self.stages.iter() {
+            stages.push(stage(format!("query {}", query), Some(address.clone()), probe));
+        }
+        stages.push(stage("all matches".to_owned(), None, &self.probes.matches));
+
+        let (forward, reverse) = self.handles.uncommitted();
+        let mut buffers = self.buffers.iter().map(|&(name, size)| (name.to_owned(), size)).collect::<Vec<_>>();
+        buffers.push(("forward_uncommitted".to_owned(), forward));
+        buffers.push(("reverse_uncommitted".to_owned(), reverse));
+        buffers.push(("gathering_epochs".to_owned(), self.gathered.borrow().len()));
+
+        WorkerReport { worker: self.worker, epoch: format!("{:?}", self.time.inner), stages, buffers }
+    }
+}
+
+/// Drives a dataflow executing a plan on one worker.
+pub struct Engine<'w, A: Allocate+'w, T: Epoch=u32> {
+    worker: &'w mut Worker<A>,
+    base: Option<InputHandle<T, Edge>>,
+    updates: InputHandle<T, (Edge, i32)>,
+    columns: InputHandle<T, ColumnBatch>,
+    bindings: InputHandle<T, (Vec<Node>, i32)>,
+    probes: EpochProbes<Time<T>>,
+    // the probe of each query node's matches, with the address of the operator producing them.
+    stages: Vec<(usize, Vec<usize>, ProbeHandle<Time<T>>)>,
+    handles: GraphStreamIndexHandle<Time<T>>,
+    counts: Rc<RefCell<HashMap<usize, (i64, i64)>>>,
+    digests: Rc<RefCell<Option<HashMap<usize, u64>>>>,
+    recorded: Option<Rc<RefCell<Vec<(usize, Vec<Node>, i64)>>>>,
+    gathered: Rc<RefCell<BTreeMap<Time<T>, HashMap<usize, i64>>>>,
+    standing: Option<StandingMatches>,
+    totals: HashMap<usize, i64>,
+    aliases: HashMap<Node, Node>,
+    positions: Vec<(String, u64)>,
+    frontier: HashMap<String, u64>,
+    breaker: Breaker,
+    materializer: Materializer,
+    budget: Option<Duration>,
+    placement: Placement,
+    orientation: Orientation,
+    min_degree: Option<usize>,
+    retention: Option<usize>,
+    retained: VecDeque<Retained<T>>,
+    compaction: Option<f64>,
+    staged: BTreeMap<T, Vec<(Edge, i32)>>,
+    pending: Vec<(Edge, i32)>,
+    introspection: Option<Introspection>,
+}
+
+impl<'w, A: Allocate, T: Epoch> Engine<'w, A, T> {
+
+    /// Builds a dataflow executing `plan` on `worker`.
+    pub fn new(worker: &'w mut Worker<A>, plan: &Plan) -> Self {
+        Engine::build(worker, plan, Start::Empty, false, None)
+    }
+
+    /// Builds a dataflow executing `plan` on `worker`, which records each change to a match.
+    ///
+    /// The changes are reported in the `embeddings` field of each epoch's statistics. Recording
+    /// every match is expensive, and intended for testing and debugging.
+    pub fn recording(worker: &'w mut Worker<A>, plan: &Plan) -> Self {
+        Engine::build(worker, plan, Start::Empty, true, None)
+    }
+
+    /// Builds a dataflow executing `plan` on `worker`, resuming from snapshots of its indices.
+    ///
+    /// The snapshots are those `snapshot` returned at the worker with the same index in an earlier
+    /// run with the same number of workers and the same placement. The base graph is already
+    /// loaded, and the engine is ready for updates. Panics if the snapshots hold keys this worker
+    /// does not own, as snapshots of another worker, or of a run with other workers, would.
+    pub fn from_snapshot(worker: &'w mut Worker<A>, plan: &Plan, forward: Vec<Edge>, reverse: Vec<Edge>) -> Self {
+        let mut engine = Engine::build(worker, plan, Start::Snapshot(forward, reverse), false, None);
+        engine.base.take().map(|base| base.close());
+        engine
+    }
+
+    /// Builds a dataflow executing `plan` on `worker`, resuming from snapshots taken by any workers.
+    ///
+    /// Each worker supplies any share of the forward snapshots of an earlier run, e.g. one taken
+    /// with a different number of workers or placement, or all of them read from one file by the
+    /// first worker. The pairs are routed to the workers that now own them and loaded as the base
+    /// graph, in the first epoch; the reverse indices are rebuilt from the same pairs, so their
+    /// snapshots are not needed. The engine is then ready for updates.
+    ///
+    /// The pairs are indexed as they are: those of undirected plans already hold both directions of
+    /// each edge, and are not symmetrized again. Plans with a degree filter cannot resume, as the
+    /// snapshots lack the edges the filter held back.
+    pub fn from_partitioned_snapshot(worker: &'w mut Worker<A>, plan: &Plan, forward: Vec<Edge>) -> Self {
+        let mut engine = Engine::build(worker, plan, Start::Partitioned, false, None);
+        engine.load_base(forward);
+        engine
+    }
+
+    /// Builds a dataflow executing `plan` on `worker`, whose base graph is the memory-mapped `graph`.
+    ///
+    /// Each worker reads the pairs its indices own straight from the mapped rows and initializes
+    /// its indices with them, without sending edges through the dataflow; every worker should be
+    /// given the same graph. Undirected plans read each edge in both directions. As with
+    /// `from_snapshot`, the base graph produces no matches, and the engine is ready for updates,
+    /// which must name nodes as `graph` does (see `CsrGraph::rename_updates`).
+    #[cfg(feature = "csr")]
+    pub fn from_csr(worker: &'w mut Worker<A>, plan: &Plan, graph: &::io::CsrGraph) -> Self {
+        let symmetric = plan.orientation() == Orientation::Undirected;
+        let (forward, reverse) = graph.owned_pairs(&plan.placement().router(), worker.index(), worker.peers(), symmetric);
+        Engine::from_snapshot(worker, plan, forward, reverse)
+    }
+
+    /// Builds a dataflow executing `plan` on `worker`, whose indices read the labels of vertices
+    /// from `labels`, for plans whose operations require labels; see `Plan::set_operation_label`.
+    ///
+    /// Every worker should be given the same labels. If `record`, the engine records each change to
+    /// a match, as one built with `recording` does.
+    pub fn with_vertex_labels(worker: &'w mut Worker<A>, plan: &Plan, labels: Arc<HashMap<Node, u32>>, record: bool) -> Self {
+        Engine::build(worker, plan, Start::Empty, record, Some(labels))
+    }
+
+    fn build(worker: &'w mut Worker<A>, plan: &Plan, start: Start, record: bool, labels: Option<Arc<HashMap<Node, u32>>>) -> Self {
+
+        // workers built differently would misinterpret each other's data.
+        check_peers(worker).unwrap_or_else(|error| panic!("{}", error));
+        plan.placement().validate(worker.peers()).unwrap_or_else(|error| panic!("{}", error));
+        let copies = plan.index_copies();
+        let (snapshot, partitioned) = match start {
+            Start::Empty => (None, false),
+            Start::Snapshot(forward, reverse) => (Some((forward, reverse)), false),
+            Start::Partitioned => (None, true),
+        };
+        assert!(copies == 1 || snapshot.is_none(), "copies of the indices cannot resume from snapshots");
+
+        let counts = Rc::new(RefCell::new(HashMap::new()));
+        let counts2 = counts.clone();
+
+        let digests = Rc::new(RefCell::new(None::<HashMap<usize, u64>>));
+        let digests2 = digests.clone();
+
+        let recorded = if record { Some(Rc::new(RefCell::new(Vec::new()))) } else { None };
+        let recorded2 = recorded.clone();
+
+        let global = plan.global_counts();
+        let gathered = Rc::new(RefCell::new(BTreeMap::new()));
+        let gathered2 = gathered.clone();
+
+        let standing = if plan.standing_matches() { Some(StandingMatches::new()) } else { None };
+        let standing2 = standing.clone();
+
+        let (base, updates, columns, bindings, forward, reverse, probe, handles, stages) = worker.dataflow::<T,_,_>(move |builder| {
+
+            let (base, base_stream) = builder.new_input::<Edge>();
+            let (updates, update_stream) = builder.new_input::<(Edge, i32)>();
+            let (columns, column_stream) = builder.new_input::<ColumnBatch>();
+            let (bindings, binding_stream) = builder.new_input::<(Vec<Node>, i32)>();
+            let update_stream = update_stream.concat(&unpack_columns(&column_stream));
+
+            let (base_stream, update_stream) = match plan.min_degree() {
+                Some(k) => {
+                    assert!(snapshot.is_none() && !partitioned, "degree filters cannot resume from snapshots");
+                    degree_filter(&base_stream, &update_stream, k)
+                },
+                None => (base_stream, update_stream),
+            };
+
+            // degrees are those of the undirected graph, so edges are read both ways only after filtering.
+            let (base_stream, update_stream) = match plan.orientation() {
+                Orientation::Directed => (base_stream, update_stream),
+                Orientation::Undirected => {
+                    // snapshots of the indices already hold both directions of each edge.
+                    let (symmetric, update_stream) = symmetrize(&base_stream, &update_stream);
+                    (if partitioned { base_stream } else { symmetric }, update_stream)
+                },
+            };
+
+            // the first copy uses salt zero, which routes as `router` does; all copies share a router type.
+            let (mut graph_index, mut handles) = match snapshot {
+                None => GraphStreamIndex::from(base_stream.clone(), update_stream.clone(), plan.placement().salted_router(0), plan.placement().salted_router(0)),
+                Some((forward, reverse)) => {
+                    let (worker, peers) = (builder.index(), builder.peers());
+                    let router = plan.placement().salted_router(0);
+                    let restore = |pairs: Vec<Edge>| Index::from_snapshot_owned(pairs, &router, worker, peers, ForeignKeys::Reject)
+                        .unwrap_or_else(|error| panic!("snapshot not taken by this worker: {}; resume with the same workers and placement, or with from_partitioned_snapshot", error));
+                    let forward = restore(forward);
+                    let reverse = restore(reverse);
+                    GraphStreamIndex::from_indices(forward, reverse, update_stream.clone(), plan.placement().salted_router(0), plan.placement().salted_router(0))
+                },
+            };
+
+            if let Some(ref labels) = labels { graph_index.set_vertex_labels(labels.clone()); }
+
+            // further copies of the indices, each routing keys with its own salt.
+            let mut salted = Vec::new();
+            for salt in 1 .. copies {
+                let (mut copy, copy_handles) = GraphStreamIndex::from(base_stream.clone(), update_stream.clone(),
+                                                                      plan.placement().salted_router(salt), plan.placement().salted_router(salt));
+                if let Some(ref labels) = labels { copy.set_vertex_labels(labels.clone()); }
+                handles.add_copy(copy_handles);
+                salted.push(copy);
+            }
+            let mut graphs = vec![&graph_index];
+            graphs.extend(salted.iter());
+
+            let mut probe = ProbeHandle::new();
+            let mut stages = Vec::new();
+            let mut changes: Option<Stream<_, (usize, i64)>> = None;
+            let mut keyed: Option<Stream<_, (usize, Vec<Node>, i64)>> = None;
+            let tracked = if plan.parameter_slots() > 0 { plan.track_motif_bound::<i64, _, _, _>(&graphs, &binding_stream) }
+                else { plan.track_motif_over::<i64, _, _, _>(&graphs) };
+            for (query, matches) in tracked {
+                // the operator producing the matches, addressed as timely's logging addresses it.
+                let mut address = matches.scope().addr();
+                address.push(matches.name().index);
+                let mut stage = ProbeHandle::new();
+                let counts = counts2.clone();
+                let digests = digests2.clone();
+                let recorded = recorded2.clone();
+                let matches = matches
+                    .inspect_batch(move |_, xs| {
+                        let mut counts = counts.borrow_mut();
+                        let entry = counts.entry(query).or_insert((0, 0));
+                        for x in xs.iter() {
+                            if x.1 > 0 { entry.0 += x.1; } else { entry.1 -= x.1; }
+                        }
+                        if let Some(ref mut digests) = *digests.borrow_mut() {
+                            let digest = digests.entry(query).or_insert(0u64);
+                            for x in xs.iter() { *digest = digest.wrapping_add(change_digest(&x.0, x.1)); }
+                        }
+                        if let Some(ref recorded) = recorded {
+                            recorded.borrow_mut().extend(xs.iter().map(|x| (query, x.0.clone(), x.1)));
+                        }
+                    })
+                    .probe_with(&mut probe)
+                    .probe_with(&mut stage);
+                stages.push((query, address, stage));
+                if global != GlobalCounts::Off {
+                    let counted = matches.map(move |x| (query, x.1));
+                    changes = Some(match changes.take() {
+                        Some(changes) => changes.concat(&counted),
+                        None => counted,
+                    });
+                }
+                if standing2.is_some() {
+                    let changed = matches.map(move |x| (query, x.0, x.1));
+                    keyed = Some(match keyed.take() {
+                        Some(keyed) => keyed.concat(&changed),
+                        None => changed,
+                    });
+                }
+            }
+
+            // epochs complete only once their totals are gathered, so the totals reported are exact.
+            if let Some(changes) = changes {
+                gather_totals(&changes, global, gathered2).probe_with(&mut probe);
+            }
+
+            // likewise, epochs complete only once the standing matches reflect them.
+            if let (Some(keyed), Some(standing)) = (keyed, standing2) {
+                consolidate_standing(&keyed, standing).probe_with(&mut probe);
+            }
+
+            (base, updates, columns, bindings, graph_index.forward.handle, graph_index.reverse.handle, probe, handles, stages)
+        });
+
+        let materializer = Materializer::new();
+        handles.set_materializer(materializer.clone());
+
+        Engine {
+            worker,
+            base: Some(base),
+            updates,
+            columns,
+            bindings,
+            probes: EpochProbes { forward, reverse, matches: probe },
+            stages,
+            handles,
+            counts,
+            digests,
+            recorded,
+            gathered,
+            standing,
+            totals: HashMap::new(),
+            aliases: HashMap::new(),
+            positions: Vec::new(),
+            frontier: HashMap::new(),
+            breaker: Breaker::new(),
+            materializer,
+            budget: None,
+            placement: plan.placement().clone(),
+            orientation: plan.orientation(),
+            min_degree: plan.min_degree(),
+            retention: None,
+            retained: VecDeque::new(),
+            compaction: None,
+            staged: BTreeMap::new(),
+            pending: Vec::new(),
+            introspection: None,
+        }
+    }
+
+    /// The index of this worker.
+    pub fn index(&self) -> usize { self.worker.index() }
+
+    /// The number of workers.
+    pub fn peers(&self) -> usize { self.worker.peers() }
+
+    /// Snapshots of this worker's forward and reverse indices, to resume with `from_snapshot`.
+    ///
+    /// The snapshots reflect all completed epochs.
+    pub fn snapshot(&self) -> (Vec<Edge>, Vec<Edge>) {
+        self.handles.snapshot()
+    }
+
+    /// Degree statistics of this worker's indices, for choosing plans; see `wings_plan::optimizer`.
+    ///
+    /// The statistics reflect all completed epochs, and cover the vertices this worker owns.
+    pub fn degree_statistics(&self) -> DegreeStatistics {
+        let (forward, reverse) = self.handles.snapshot();
+        DegreeStatistics::from_snapshot(&forward, &reverse)
+    }
+
+    /// The number of entries held in this worker's forward and reverse indices.
+    pub fn index_len(&self) -> (usize, usize) {
+        self.handles.len()
+    }
+
+    /// Limits the time spent matching each epoch to `budget`, or lifts the limit.
+    ///
+    /// Once an epoch's budget is exhausted, operators discard the prefixes they hold rather than
+    /// extending them, and the epoch's statistics report how many were discarded. The indices
+    /// absorb every update regardless, so later epochs are unaffected, but the changes in matches
+    /// reported for an epoch that exhausted its budget are partial.
+    pub fn set_budget(&mut self, budget: Option<Duration>) {
+        if self.budget.is_none() && budget.is_some() {
+            self.handles.set_breaker(self.breaker.clone());
+        }
+        self.budget = budget;
+    }
+
+    /// Sets how operators proposing extensions materialize the candidates of each epoch.
+    ///
+    /// The default, `Materialization::Adaptive`, proposes the candidates of an epoch at once
+    /// unless the degrees of the keys its prefixes probe bound them above a threshold, and in
+    /// chunks otherwise, so that massive epochs do not hold all their candidates at once. Epochs
+    /// with a time budget (see `set_budget`) are always chunked, so that they stop promptly once
+    /// it is exhausted. Each epoch's statistics report the choices made.
+    pub fn set_materialization(&mut self, policy: Materialization) {
+        self.materializer.set_policy(policy);
+    }
+
+    /// How operators proposing extensions materialize the candidates of each epoch.
+    pub fn materialization(&self) -> Materialization {
+        self.materializer.policy()
+    }
+
+    /// Rebuilds this worker's indices from scratch, between epochs.
+    ///
+    /// After heavy churn the indices hold many committed updates beside their compact forms, which
+    /// slows reads and holds memory for deleted edges. Rebuilding compacts them afresh, but stalls
+    /// the worker for time proportional to the size of its indices.
+    pub fn rebuild(&mut self) {
+        self.handles.rebuild();
+    }
+
+    /// Starts rebuilding this worker's indices on background threads.
+    ///
+    /// Epochs proceed with the current indices meanwhile, and the rebuilt indices are swapped in
+    /// at the end of the first epoch to complete after they are ready.
+    pub fn rebuild_in_background(&mut self) {
+        self.handles.rebuild_in_background();
+    }
+
+    /// Rebuilds the indices once their entries exceed `factor` times the edges they hold, or stops.
+    ///
+    /// Deleted edges and keys without edges occupy the indices until they are rebuilt, so under
+    /// continuous churn indices that are never rebuilt grow without bound. With compaction, the
+    /// engine checks the indices after each epoch and rebuilds them in place when they exceed the
+    /// limit, so that their size stays proportional to the number of edges. A `factor` of two is
+    /// a reasonable start; smaller factors rebuild more often.
+    pub fn set_compaction(&mut self, factor: Option<f64>) {
+        self.compaction = factor;
+    }
+
+    /// Enables Bloom filters over the keys of the graph indices, with `bits_per_key` bits per key.
+    ///
+    /// The filters let intersections skip keys without any edges cheaply. Enable them before
+    /// loading the base graph, so that they are sized for it.
+    pub fn enable_filter(&mut self, bits_per_key: usize) {
+        self.handles.enable_filter(bits_per_key);
+    }
+
+    /// The epoch the next batch will be introduced at.
+    pub fn epoch(&self) -> T { self.updates.time().inner }
+
+    /// Binds the plan's parameters to `values`, one for each parameter slot, from the current
+    /// epoch on; see `wings_plan::bindings`.
+    ///
+    /// Bindings supplied at any worker apply at every worker. A binding supplied more than once,
+    /// here or at other workers, stays in effect until each has been unbound. Batches introduced
+    /// while a binding is in effect report the changes they make to its matches.
+    pub fn bind(&mut self, values: Vec<Node>) {
+        self.bindings.send((values, 1));
+    }
+
+    /// Unbinds the plan's parameters from `values`, as bound by `bind`, from the current epoch on.
+    pub fn unbind(&mut self, values: Vec<Node>) {
+        self.bindings.send((values, -1));
+    }
+
+    /// Records that input from `source` up to `offset` is introduced in the current epoch.
+    ///
+    /// The position is reported by `frontier_positions` once the epoch is complete.
+    pub fn attach_position(&mut self, source: &str, offset: u64) {
+        self.positions.push((source.to_owned(), offset));
+    }
+
+    /// The positions of each source fully reflected in completed epochs, sorted by source.
+    ///
+    /// Positions are those attached at this worker; each worker reports the sources it reads.
+    pub fn frontier_positions(&self) -> Vec<(String, u64)> {
+        let mut positions = self.frontier.iter().map(|(source, &offset)| (source.clone(), offset)).collect::<Vec<_>>();
+        positions.sort();
+        positions
+    }
+
+    /// Computes a digest of each epoch's changes to the matches of each query node, or stops.
+    ///
+    /// Digests are reported in each epoch's statistics; see `change_digest`. Combining the
+    /// statistics of all workers gives digests that depend only on the changes of the epoch.
+    pub fn set_digests(&mut self, enabled: bool) {
+        let mut digests = self.digests.borrow_mut();
+        if !enabled { *digests = None; }
+        else if digests.is_none() { *digests = Some(HashMap::new()); }
+    }
+
+    /// Counts the work reads of the indices do in each epoch, or stops; see `ReadStats`.
+    ///
+    /// The counters are reported in each epoch's statistics, to guide tuning of merge policies
+    /// and compaction. Counting costs a little on every read, and is off by default.
+    pub fn set_read_stats(&mut self, enabled: bool) {
+        self.handles.set_read_stats(enabled);
+    }
+
+    /// Reports the state of the dataflow to `introspection`, or stops.
+    ///
+    /// The engine reports now, as each epoch starts and completes, and every second while it waits
+    /// for an epoch to complete; see `introspect`.
+    pub fn set_introspection(&mut self, introspection: Option<Introspection>) {
+        if let Some(ref introspection) = introspection { introspection.record(self.introspect()); }
+        self.introspection = introspection;
+    }
+
+    /// The frontiers of the stages of the dataflow, and the entries waiting in its buffers.
+    pub fn introspect(&self) -> WorkerReport {
+        self.observer().report()
+    }
+
+    // what `introspect` reports, detached from the engine so that it can be read between steps.
+    fn observer(&self) -> Observer<T> {
+        let staged = self.staged.values().map(|updates| updates.len()).sum();
+        let standing = self.standing.as_ref().map(|standing| standing.len()).unwrap_or(0);
+        Observer {
+            worker: self.worker.index(),
+            time: self.updates.time().clone(),
+            probes: self.probes.clone(),
+            stages: self.stages.clone(),
+            handles: self.handles.clone(),
+            gathered: self.gathered.clone(),
+            buffers: vec![("pending", self.pending.len()), ("staged", staged), ("retained_epochs", self.retained.len()), ("standing_matches", standing)],
+        }
+    }
+
+    /// The standing matches of each query node this worker holds, if the plan has engines hold
+    /// them; see `Plan::set_standing_matches`.
+    ///
+    /// Between epochs the matches reflect every completed epoch, and the union of those every
+    /// worker holds is the standing result, each match at one worker. A consumer resyncs by
+    /// reading them, e.g. with `StandingMatches::dump`, after an epoch completes, and then applies
+    /// the changes of the epochs that follow. Matches of a base graph loaded without reporting
+    /// them are not held, as no change to them is reported either.
+    pub fn standing_matches(&self) -> Option<&StandingMatches> {
+        self.standing.as_ref()
+    }
+
+    /// Retains the changes to matches of up to `limit` completed epochs for cursors, or stops.
+    ///
+    /// While changes are retained they are not reported in each epoch's statistics, and are read
+    /// through `cursor` instead. The changes of an epoch are released once a cursor for it is
+    /// dropped, or once `limit` later epochs are retained. This method panics if the engine was
+    /// not built with `recording`, as other engines do not collect the changes.
+    pub fn set_retention(&mut self, limit: Option<usize>) {
+        assert!(limit.is_none() || self.recorded.is_some(), "retention requires an engine built with `recording`");
+        self.retention = limit;
+        if limit.is_none() {
+            for retained in self.retained.drain(..) { *retained.changes.borrow_mut() = None; }
+        }
+    }
+
+    /// A cursor over the changes to matches of the completed `epoch`, if they are retained.
+    ///
+    /// The changes are released once every cursor for the epoch has been dropped.
+    pub fn cursor(&mut self, epoch: T) -> Option<Cursor<T>> {
+        self.retained.iter_mut().find(|retained| retained.epoch == epoch).map(|retained| {
+            retained.opened = true;
+            Cursor { epoch, changes: retained.changes.clone(), position: 0 }
+        })
+    }
+
+    /// The completed epochs whose changes are retained, oldest first.
+    pub fn retained_epochs(&self) -> Vec<T> {
+        self.retained.iter().map(|retained| retained.epoch).collect()
+    }
+
+    // retains the changes of `epoch`, releasing those of epochs read or beyond the limit.
+    fn retain(&mut self, epoch: T, changes: Changes, limit: usize) {
+        self.retained.retain(|retained| !(retained.opened && Rc::strong_count(&retained.changes) == 1));
+        self.retained.push_back(Retained { epoch, changes: Rc::new(RefCell::new(Some(changes))), opened: false });
+        while self.retained.len() > limit {
+            let expired = self.retained.pop_front().unwrap();
+            *expired.changes.borrow_mut() = None;
+        }
+    }
+
+    /// Loads the base graph, indexing `edges` without producing matches for them.
+    ///
+    /// Each worker should supply a disjoint part of the base graph; the index routes edges to
+    /// their owners. The base graph can only be loaded once, before any updates.
+    pub fn load_base(&mut self, mut edges: Vec<Edge>) -> BatchStats<T> {
+        let count = edges.len();
+        if !self.aliases.is_empty() {
+            for edge in edges.iter_mut() { *edge = (self.resolve(edge.0), self.resolve(edge.1)); }
+        }
+        match self.base {
+            Some(ref mut base) => base.send_batch(&mut edges),
+            None => panic!("base graph loaded after updates"),
+        }
+        let next = self.epoch().succ();
+        let stats = self.complete(count, next);
+        self.base.take().map(|base| base.close());
+        stats
+    }
+
+    /// Indexes `edges` as part of the base graph, in an epoch of their own, without producing matches.
+    ///
+    /// A base graph too large to load at once may be loaded by calling this method repeatedly, each
+    /// call waiting only for index maintenance. Loading ends with `seed`, or with the first update;
+    /// the base graph can not be extended after that. Parts loaded after the first are absorbed as
+    /// insertions, and are compacted as the indices are merged. Nothing reads the indices while
+    /// they load, so parts are committed to them directly, grouped by key; see `Maintenance`.
+    pub fn bulk_load(&mut self, mut edges: Vec<Edge>) -> BatchStats<T> {
+        let count = edges.len();
+        if !self.aliases.is_empty() {
+            for edge in edges.iter_mut() { *edge = (self.resolve(edge.0), self.resolve(edge.1)); }
+        }
+        match self.base {
+            Some(ref mut base) => base.send_batch(&mut edges),
+            None => panic!("base graph loaded after updates"),
+        }
+        let next = self.epoch().succ();
+        self.handles.set_maintenance(Maintenance::Direct);
+        let stats = self.complete(count, next);
+        self.handles.set_maintenance(Maintenance::Buffered);
+        stats
+    }
+
+    /// Loads the base graph from timed updates, indexing the edges they accumulate to.
+    ///
+    /// The base graph reflects every update regardless of its time, as does `load_base` for the
+    /// edges it is given. Returns an error, and loads nothing, if the updates to some edge
+    /// accumulate to a negative count.
+    pub fn load_base_timed(&mut self, mut updates: Vec<TimedUpdate<T>>) -> Result<BatchStats<T>, TimedInputError<T>> {
+        updates.sort_by(|x, y| x.0.cmp(&y.0));
+        let mut edges = Vec::new();
+        let mut index = 0;
+        while index < updates.len() {
+            let edge = updates[index].0;
+            let mut count = 0;
+            while index < updates.len() && updates[index].0 == edge {
+                count += updates[index].2 as i64;
+                index += 1;
+            }
+            if count < 0 { return Err(TimedInputError::NegativeBase(edge)); }
+            for _ in 0 .. count { edges.push(edge); }
+        }
+        Ok(self.load_base(edges))
+    }
+
+    /// Ends loading the base graph, and introduces `edges` as insertions in a single seed epoch.
+    ///
+    /// The seed epoch reports the matches that involve at least one of `edges`, which must not
+    /// have been loaded as part of the base graph. Seeding with no edges skips initial results,
+    /// and the engine moves on to incremental updates; seeding with the last part of the base
+    /// graph reports the matches that part completes, without enumerating those of earlier parts.
+    pub fn seed(&mut self, edges: Vec<Edge>) -> BatchStats<T> {
+        assert!(self.base.is_some(), "seeded after the base graph was closed");
+        self.step(edges.into_iter().map(|edge| (edge, 1)).collect())
+    }
+
+    /// Introduces `updates` at the current epoch, and completes all work for it.
+    ///
+    /// The engine then moves on to the successor of the current epoch.
+    pub fn step(&mut self, updates: Vec<(Edge, i32)>) -> BatchStats<T> {
+        let next = self.epoch().succ();
+        self.step_to(updates, next)
+    }
+
+    /// Introduces `updates` at the current epoch, completes all work for it, and moves on to `next`.
+    ///
+    /// This allows epochs to skip ahead, for example to follow a clock. The epoch `next` must be
+    /// strictly greater than the current epoch.
+    pub fn step_to(&mut self, mut updates: Vec<(Edge, i32)>, next: T) -> BatchStats<T> {
+        assert!(self.epoch() < next, "epochs must increase");
+        // the base graph must be indexed before (or with) the first updates.
+        self.base.take().map(|base| base.close());
+        let count = updates.len();
+        if !self.aliases.is_empty() {
+            for update in updates.iter_mut() { update.0 = (self.resolve((update.0).0), self.resolve((update.0).1)); }
+        }
+        self.updates.send_batch(&mut updates);
+        self.complete(count, next)
+    }
+
+    /// Adds `updates` to the pending batch, without introducing them to the dataflow.
+    ///
+    /// The pending batch is introduced by `commit_batch`, or discarded by `abort_batch`; until then
+    /// the graph and its matches are unaffected.
+    pub fn push_updates(&mut self, updates: &[(Edge, i32)]) {
+        self.pending.extend_from_slice(updates);
+    }
+
+    /// The number of updates in the pending batch.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Discards the pending batch, returning the number of updates discarded.
+    ///
+    /// No epoch is consumed. With several workers, each must abort the batch, just as each must
+    /// step every epoch; drivers should agree on whether to commit before doing either.
+    pub fn abort_batch(&mut self) -> usize {
+        let aborted = self.pending.len();
+        self.pending.clear();
+        aborted
+    }
+
+    /// Introduces the pending batch at the current epoch, and completes all work for it.
+    pub fn commit_batch(&mut self) -> BatchStats<T> {
+        let updates = ::std::mem::replace(&mut self.pending, Vec::new());
+        self.step(updates)
+    }
+
+    /// Commits the pending batch if `validate` accepts it, and otherwise aborts it.
+    ///
+    /// The batch is either introduced in its entirety or not at all; if it is rejected, the error
+    /// is returned and no epoch is consumed.
+    pub fn commit_batch_with<E, F: FnOnce(&[(Edge, i32)])->Result<(), E>>(&mut self, validate: F) -> Result<BatchStats<T>, E> {
+        match validate(&self.pending[..]) {
+            Ok(()) => Ok(self.commit_batch()),
+            Err(error) => {
+                self.abort_batch();
+                Err(error)
+            },
+        }
+    }
+
+    /// Introduces `updates` over `chunks` consecutive epochs, overlapping the matching of each chunk
+    /// with the index maintenance of the next, and completes all work for them.
+    ///
+    /// Stepping a batch waits for both indices to absorb all of its updates before any matching
+    /// can complete. Here the batch is split into `chunks` parts, each introduced in its own epoch
+    /// once the indices have absorbed the previous part, so that matching the previous part
+    /// proceeds while the indices absorb the next. The accumulated changes in matches are those of
+    /// stepping the batch at once; only the number of epochs consumed differs, and the statistics
+    /// report the first of them. All workers must use the same number of chunks.
+    pub fn step_pipelined(&mut self, mut updates: Vec<(Edge, i32)>, chunks: usize) -> BatchStats<T> {
+        assert!(chunks > 0, "batches must have at least one chunk");
+        self.base.take().map(|base| base.close());
+        let count = updates.len();
+        if !self.aliases.is_empty() {
+            for update in updates.iter_mut() { update.0 = (self.resolve((update.0).0), self.resolve((update.0).1)); }
+        }
+
+        let first = self.epoch();
+        let start = Instant::now();
+        if let Some(budget) = self.budget { self.breaker.arm(budget); }
+        let size = ::std::cmp::max((count + chunks - 1) / chunks, 1);
+        for _ in 1 .. chunks {
+            let rest = if updates.len() > size { updates.split_off(size) } else { Vec::new() };
+            self.updates.send_batch(&mut updates);
+            updates = rest;
+            let next = self.epoch().succ();
+            self.updates.advance_to(next);
+            self.columns.advance_to(next);
+            // parameterized plans hold the prefixes of a chunk until the bindings pass it, too.
+            self.bindings.advance_to(next);
+
+            // wait only for index maintenance; matching continues while the next chunk is indexed.
+            let forward = &self.probes.forward;
+            let reverse = &self.probes.reverse;
+            let frontier = self.updates.time();
+            self.worker.step_while(|| forward.less_than(frontier) || reverse.less_than(frontier));
+        }
+        self.updates.send_batch(&mut updates);
+        let next = self.epoch().succ();
+        let mut stats = self.complete(count, next);
+        stats.epoch = first;
+        stats.index_time = start.elapsed() - stats.match_time;
+        stats
+    }
+
+    /// Stages `updates` to be introduced at their own times once the input frontier passes them.
+    ///
+    /// The input frontier is the current epoch: updates at earlier times can no longer be
+    /// introduced, and if any are present an error is returned and nothing is staged. Updates may
+    /// be staged at any later time, including times before those of updates already staged.
+    pub fn stage(&mut self, updates: Vec<TimedUpdate<T>>) -> Result<(), TimedInputError<T>> {
+        let frontier = self.epoch();
+        if let Some(late) = updates.iter().find(|update| update.1 < frontier) {
+            return Err(TimedInputError::Late { time: late.1, frontier });
+        }
+        for (edge, time, diff) in updates {
+            self.staged.entry(time).or_insert_with(Vec::new).push((edge, diff));
+        }
+        Ok(())
+    }
+
+    /// The number of staged updates not yet introduced.
+    pub fn staged(&self) -> usize {
+        self.staged.values().map(|updates| updates.len()).sum()
+    }
+
+    /// Introduces the staged updates at times before `frontier`, each at its own time, completes
+    /// all work for them, and moves on to `frontier`.
+    ///
+    /// The updates of different times are processed together, so the statistics report the
+    /// accumulated changes in matches, and the first time introduced as their epoch. As with
+    /// `step`, every worker must advance to the same frontiers, whichever updates they staged.
+    /// The frontier must be strictly greater than the current epoch.
+    pub fn advance_frontier(&mut self, frontier: T) -> BatchStats<T> {
+        assert!(self.epoch() < frontier, "frontiers must increase");
+        self.base.take().map(|base| base.close());
+
+        let later = self.staged.split_off(&frontier);
+        let ready = ::std::mem::replace(&mut self.staged, later);
+
+        let first = self.updates.time().clone();
+        let mut count = 0;
+        for (time, mut updates) in ready {
+            assert!(self.epoch() <= time, "updates staged at {:?} were overtaken by stepping to {:?}", time, self.epoch());
+            if self.epoch() < time {
+                self.updates.advance_to(time);
+                self.columns.advance_to(time);
+                self.bindings.advance_to(time);
+            }
+            count += updates.len();
+            if !self.aliases.is_empty() {
+                for update in updates.iter_mut() { update.0 = (self.resolve((update.0).0), self.resolve((update.0).1)); }
+            }
+            self.updates.send_batch(&mut updates);
+        }
+        self.complete_from(first, count, frontier)
+    }
+
+    /// The vertex `node` has been contracted into, or `node` itself if it has not been contracted.
+    pub fn resolve(&self, node: Node) -> Node {
+        let mut node = node;
+        while let Some(&alias) = self.aliases.get(&node) { node = alias; }
+        node
+    }
+
+    /// Contracts each `old` vertex into the corresponding `new` vertex, in order, and completes
+    /// the resulting epoch.
+    ///
+    /// All later updates to edges of `old` are rewritten to apply to `new`. In this epoch, each
+    /// edge of `old` already in the graph is retracted and reintroduced as an edge of `new`, which
+    /// updates the matches of both vertices. Every worker must be supplied the same merges, and
+    /// the indices must partition vertices by their identifiers, as the engine's do.
+    ///
+    /// This method panics if the plan filters vertices by degree (see `Plan::set_min_degree`):
+    /// the filter holds back the edges of vertices of low degree, which are not in the indices to
+    /// be moved, and would admit them under their old vertices once their degrees rise.
+    pub fn contract(&mut self, merges: &[(Node, Node)]) -> BatchStats<T> {
+
+        assert!(self.min_degree.is_none(), "vertices cannot be contracted under a degree filter");
+
+        let peers = self.peers() as u64;
+        let index = self.index() as u64;
+        let route = self.placement.router();
+
+        let mut olds = Vec::new();
+        for &(old, new) in merges.iter() {
+            let old = self.resolve(old);
+            let new = self.resolve(new);
+            if old != new {
+                self.aliases.insert(old, new);
+                olds.push(old);
+            }
+        }
+        olds.sort();
+
+        // the worker owning each contracted vertex moves its edges; edges between contracted
+        // vertices are found in both directions, and moved only by the owner of their source.
+        // undirected indices hold each edge both ways, and updates are symmetrized again as they
+        // are read, so only one direction of each edge is moved, from its lesser contracted end.
+        let undirected = self.orientation == Orientation::Undirected;
+        let mut retractions = Vec::new();
+        for &old in olds.iter().filter(|&&old| route(old) % peers == index) {
+            let (outs, ins) = self.handles.adjacency(old);
+            if undirected {
+                retractions.extend(outs.into_iter().filter(|x| old <= x.0 || olds.binary_search(&x.0).is_err()).map(|(dst, count)| ((old, dst), count)));
+            } else {
+                retractions.extend(outs.into_iter().map(|(dst, count)| ((old, dst), count)));
+                retractions.extend(ins.into_iter().filter(|x| olds.binary_search(&x.0).is_err()).map(|(src, count)| ((src, old), count)));
+            }
+        }
+
+        let next = self.epoch().succ();
+        self.base.take().map(|base| base.close());
+        for &((src, dst), count) in retractions.iter() {
+            let edge = (self.resolve(src), self.resolve(dst));
+            self.updates.send(((src, dst), -count));
+            self.updates.send((edge, count));
+        }
+        self.complete(2 * retractions.len(), next)
+    }
+
+    /// Closes the inputs and runs the dataflow to completion.
+    pub fn finish(mut self) {
+        self.base.take().map(|base| base.close());
+        self.updates.close();
+        self.columns.close();
+        self.bindings.close();
+        while self.worker.step() { }
+    }
+
+    /// Introduces the columnar updates `(src[i], dst[i], diff[i])` at the current epoch, and
+    /// completes all work for it.
+    ///
+    /// The updates are split into columns for each worker, holding the updates whose sources its
+    /// forward index holds, and each worker's columns are sent to it as one record, rather than
+    /// as one record for each update, and only unpacked into updates once they arrive. This method
+    /// panics if the columns have different lengths.
+    pub fn step_columns(&mut self, src: &[Node], dst: &[Node], diff: &[i32]) -> BatchStats<T> {
+        assert!(src.len() == dst.len() && dst.len() == diff.len(), "columns of different lengths");
+        let next = self.epoch().succ();
+        self.base.take().map(|base| base.close());
+        let (peers, router) = (self.worker.peers() as u64, self.placement.router());
+        let mut batches = (0 .. peers).map(|worker| (worker, Vec::new(), Vec::new(), Vec::new())).collect::<Vec<ColumnBatch>>();
+        for ((&s, &d), &w) in src.iter().zip(dst.iter()).zip(diff.iter()) {
+            let (s, d) = (self.resolve(s), self.resolve(d));
+            let batch = &mut batches[(router(s) % peers) as usize];
+            batch.1.push(s);
+            batch.2.push(d);
+            batch.3.push(w);
+        }
+        for batch in batches.into_iter().filter(|batch| !batch.3.is_empty()) {
+            self.columns.send(batch);
+        }
+        self.complete(src.len(), next)
+    }
+
+    /// Introduces the adjacency bundles `bundles` at the current epoch, and completes all work for it.
+    ///
+    /// Bundles are the usual output of upstream aggregation by source. Their edges are handed to
+    /// the dataflow in sorted order, which the forward index receives as a few sorted runs and
+    /// absorbs with less sorting than edges in arbitrary order.
+    pub fn step_bundles(&mut self, bundles: &[Bundle]) -> BatchStats<T> {
+        self.step(expand_bundles(bundles))
+    }
+
+    /// Adds the edges of `bundles` to the pending batch; see `push_updates`.
+    pub fn push_bundles(&mut self, bundles: &[Bundle]) {
+        self.pending.extend(expand_bundles(bundles));
+    }
+
+    // advances the inputs to `next` and waits for the work of the current epoch to complete.
+    fn complete(&mut self, updates: usize, next: T) -> BatchStats<T> {
+        let time = self.updates.time().clone();
+        self.complete_from(time, updates, next)
+    }
+
+    // advances the inputs to `next` and waits for the work of all times from `time` to complete.
+    fn complete_from(&mut self, time: Time<T>, updates: usize, next: T) -> BatchStats<T> {
+
+        if let Some(ref mut base) = self.base { base.advance_to(next); }
+        self.updates.advance_to(next);
+        self.columns.advance_to(next);
+        self.bindings.advance_to(next);
+
+        if let Some(budget) = self.budget {
+            if !self.breaker.is_armed() { self.breaker.arm(budget); }
+        }
+        let (index_time, match_time) = match self.introspection.clone() {
+            Some(introspection) => {
+                let observer = self.observer();
+                introspection.record(observer.report());
+                let mut reported = Instant::now();
+                self.probes.complete_observed(self.worker, self.updates.time(), &mut || {
+                    if reported.elapsed() >= Duration::from_secs(1) {
+                        introspection.record(observer.report());
+                        reported = Instant::now();
+                    }
+                })
+            },
+            None => self.probes.complete(self.worker, self.updates.time()),
+        };
+        // the probes of each query's stage are updated by their own operators, which may trail the
+        // probe of all matches by a step; reports after the epoch should find no stage behind.
+        let (stages, frontier) = (&self.stages, self.updates.time());
+        self.worker.step_while(|| stages.iter().any(|stage| stage.2.less_than(frontier)));
+        self.breaker.disarm();
+        let discarded = self.breaker.take_discarded();
+
+        // all queries before the frontier are complete, and the index can forget their distinctness.
+        self.handles.merge_before(self.updates.time());
+        self.handles.finish_rebuild(false);
+        if let Some(factor) = self.compaction { self.handles.compact(factor); }
+
+        // the input up to the attached positions is now reflected in the results.
+        for (source, offset) in self.positions.drain(..) {
+            self.frontier.insert(source, offset);
+        }
+
+        let mut counts = self.counts.borrow_mut().drain().collect::<Vec<_>>();
+        counts.sort();
+        let matches = counts.iter().map(|&(query, (created, deleted))| (query, created - deleted)).collect();
+        let created = counts.iter().filter(|x| (x.1).0 > 0).map(|&(query, (created, _))| (query, created)).collect();
+        let deleted = counts.iter().filter(|x| (x.1).1 > 0).map(|&(query, (_, deleted))| (query, deleted)).collect();
+
+        // the gathered changes of every complete epoch, in order.
+        let complete = {
+            let mut gathered = self.gathered.borrow_mut();
+            let later = match gathered.keys().find(|time| !time.less_than(self.updates.time())).cloned() {
+                Some(later) => gathered.split_off(&later),
+                None => BTreeMap::new(),
+            };
+            ::std::mem::replace(&mut *gathered, later)
+        };
+        for (_, changes) in complete {
+            for (query, diff) in changes {
+                *self.totals.entry(query).or_insert(0) += diff;
+            }
+        }
+        self.totals.retain(|_, total| *total != 0);
+        let mut totals = self.totals.iter().map(|(&query, &total)| (query, total)).collect::<Vec<_>>();
+        totals.sort();
+
+        let mut digests = match *self.digests.borrow_mut() {
+            Some(ref mut digests) => digests.drain().filter(|x| x.1 != 0).collect(),
+            None => Vec::new(),
+        };
+        digests.sort();
+
+        let mut embeddings = Vec::new();
+        if let Some(ref recorded) = self.recorded {
+            let mut recorded = recorded.borrow_mut();
+            recorded.sort();
+            for (query, embedding, diff) in recorded.drain(..) {
+                let merge = match embeddings.last_mut() {
+                    Some(&mut (ref q, ref e, ref mut d)) if *q == query && *e == embedding => { *d += diff; true },
+                    _ => false,
+                };
+                if !merge { embeddings.push((query, embedding, diff)); }
+            }
+            embeddings.retain(|x| x.2 != 0);
+        }
+        if let Some(limit) = self.retention {
+            let changes = ::std::mem::replace(&mut embeddings, Vec::new());
+            self.retain(time.inner, changes, limit);
+        }
+        if let Some(ref introspection) = self.introspection { introspection.record(self.introspect()); }
+
+        BatchStats {
+            epoch: time.inner,
+            updates,
+            index_time,
+            match_time,
+            matches,
+            created,
+            deleted,
+            edges: self.handles.edges(),
+            index_bytes: self.handles.heap_size(),
+            discarded,
+            materialized: self.materializer.take_choices(),
+            embeddings,
+            digests,
+            reads: self.handles.take_read_stats(),
+            totals,
+            standing_bytes: self.standing.as_ref().map(|standing| standing.heap_size()).unwrap_or(0),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Sampling of output streams.
+//!
+//! Plans with many matches can produce far more output than is useful to inspect. The operators
+//! here reduce a stream of `(data, diff)` updates to a sample, without exchanging data between
+//! workers: each worker samples the updates it produces.
+//!
+//! Bernoulli sampling selects updates by a seeded hash of their data, so the same embedding is
+//! either always or never selected. Its insertions and retractions are therefore sampled together,
+//! and the sample can be accumulated like the full stream. Reservoir sampling retains at most a
+//! fixed number of updates per epoch and worker, chosen using a generator seeded from the seed
+//! and the worker index; it is deterministic as long as each worker receives its updates in the
+//! same order.
+//!
+//! Samples whose counts are to be scaled back up carry the probability with which each update was
+//! retained. `sample_tracked` selects updates as Bernoulli sampling does, at the probability a
+//! shared `SamplingRate` holds when the update is sampled, so that a driver can shed load by
+//! lowering the rate as epochs grow expensive, and raise it again once they are not. The
+//! estimators in `estimate` scale each retained update by its probability.
+
+use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Filter, Map, Operator};
+use timely::dataflow::channels::pact::Pipeline;
+
+/// Extension methods for sampling streams of updates.
+pub trait Sample<G: Scope, D: Data, W: Data> {
+    /// Retains each update with probability `probability`, as determined by `seed` and its data.
+    fn sample_bernoulli(&self, probability: f64, seed: u64) -> Stream<G, (D, W)>;
+    /// Retains a uniform sample of at most `size` updates of each epoch, at each worker.
+    ///
+    /// The sample for an epoch is produced once the epoch is complete.
+    fn sample_reservoir(&self, size: usize, seed: u64) -> Stream<G, (D, W)>;
+    /// Retains each update with the probability `rate` holds when it is sampled, as determined by
+    /// `seed` and its data, along with that probability.
+    ///
+    /// An update is selected if it would be by `sample_bernoulli` at the same probability, so that
+    /// insertions and retractions are sampled together as long as the rate does not change between
+    /// them.
+    fn sample_tracked(&self, rate: &SamplingRate, seed: u64) -> Stream<G, (D, W, f64)>;
+}
+
+/// A shared probability with which `sample_tracked` retains updates.
+#[derive(Clone, Debug)]
+pub struct SamplingRate {
+    probability: Rc<Cell<f64>>,
+}
+
+impl SamplingRate {
+    /// A rate retaining updates with `probability`, which must be in `[0, 1]`.
+    pub fn new(probability: f64) -> Self {
+        let rate = SamplingRate { probability: Rc::new(Cell::new(1.0)) };
+        rate.set(probability);
+        rate
+    }
+
+    /// Retains updates sampled from now on with `probability`, which must be in `[0, 1]`.
+    pub fn set(&self, probability: f64) {
+        assert!(probability >= 0.0 && probability <= 1.0, "probability must be in [0, 1]");
+        self.probability.set(probability);
+    }
+
+    /// The probability with which updates sampled from now on are retained.
+    pub fn get(&self) -> f64 { self.probability.get() }
+}
+
+impl Default for SamplingRate {
+    fn default() -> Self { SamplingRate::new(1.0) }
+}
+
+impl<G: Scope, D: Data+Hash, W: Data> Sample<G, D, W> for Stream<G, (D, W)> where G::Timestamp: Hash {
+
+    fn sample_bernoulli(&self, probability: f64, seed: u64) -> Stream<G, (D, W)> {
+        assert!(probability >= 0.0 && probability <= 1.0, "probability must be in [0, 1]");
+        self.filter(move |&(ref data, _)| selected(data, probability, seed))
+    }
+
+    fn sample_reservoir(&self, size: usize, seed: u64) -> Stream<G, (D, W)> {
+
+        let mut rng = XorShift::new(seed ^ (self.scope().index() as u64).wrapping_mul(0x9E3779B97F4A7C15));
+        let mut reservoirs = HashMap::new();
+        let mut buffer = Vec::new();
+
+        self.unary_notify(Pipeline, "SampleReservoir", vec![], move |input, output, notificator| {
+
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let &mut (ref mut seen, ref mut reservoir) = reservoirs
+                    .entry(time.time().clone())
+                    .or_insert((0u64, Vec::with_capacity(size)));
+                for update in buffer.drain(..) {
+                    *seen += 1;
+                    if reservoir.len() < size {
+                        reservoir.push(update);
+                    }
+                    else {
+                        let slot = (rng.next() % *seen) as usize;
+                        if slot < size { reservoir[slot] = update; }
+                    }
+                }
+                notificator.notify_at(time.retain());
+            });
+
+            notificator.for_each(|time, _, _| {
+                if let Some((_, reservoir)) = reservoirs.remove(time.time()) {
+                    output.session(&time).give_iterator(reservoir.into_iter());
+                }
+            });
+        })
+    }
+
+    fn sample_tracked(&self, rate: &SamplingRate, seed: u64) -> Stream<G, (D, W, f64)> {
+        let rate = rate.clone();
+        self.flat_map(move |(data, diff)| {
+            let probability = rate.get();
+            if selected(&data, probability, seed) { Some((data, diff, probability)) } else { None }
+        })
+    }
+}
+
+// whether Bernoulli sampling with `probability` and `seed` selects `data`.
+fn selected<D: Hash>(data: &D, probability: f64, seed: u64) -> bool {
+    let threshold = (probability * u64::max_value() as f64) as u64;
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    data.hash(&mut hasher);
+    probability > 0.0 && hasher.finish() <= threshold
+}
+
+// xorshift64*, which is plenty for sampling.
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        XorShift { state: seed.wrapping_mul(0x9E3779B97F4A7C15) | 1 }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+}
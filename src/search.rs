@@ -0,0 +1,107 @@
+//! Searches of sorted slices, from their start or from a cursor.
+//!
+//! The indices and intersection kernels walk sorted slices with cursors: each key or proposal is
+//! looked for at or after the position the last one was found, and is usually close to it.
+//! Exponential search, or galloping, finds a position `d` places past the cursor with about
+//! `2 log d` comparisons, however long the slice, where binary search would take `log n`. The
+//! functions here take the cursor as a position in the whole slice and return positions in the
+//! whole slice, so that callers do not add offsets of subslices to their cursors by hand.
+//!
+//! Each search is for the end of a prefix of the slice satisfying a predicate, as
+//! `partition_point` describes it: the predicate must hold for some prefix of the slice and for
+//! none of its elements after that, as `|x| x < value` does for a sorted slice. Searches from a
+//! cursor require this of the slice from the cursor on. A predicate that holds again after
+//! failing leaves the position returned unspecified, but always within the slice.
+//!
+//! `advance` and `advance_from` gallop, for cursors that move a little at a time, and
+//! `partition_point` and `partition_point_from` bisect, for searches with no idea where their
+//! answer lies. `gallop` and `equal_range` specialize galloping to the elements less than, and
+//! equal to, a value.
+
+use std::ops::Range;
+
+/// The number of elements at the start of `slice` satisfying `predicate`.
+///
+/// The predicate must stay false once it becomes false, which allows exponential search to count
+/// the elements in time logarithmic in their number rather than in the length of the slice.
+#[inline]
+pub fn advance<T, F: Fn(&T)->bool>(slice: &[T], predicate: F) -> usize {
+
+    // start with no advance
+    let mut index = 0;
+    if index < slice.len() && predicate(&slice[index]) {
+
+        // advance in exponentially growing steps, while the element `step` past `index` satisfies
+        // the predicate; steps are compared to what remains of the slice, so that they never overflow.
+        let mut step = 1;
+        while step < slice.len() - index && predicate(&slice[index + step]) {
+            index += step;
+            step <<= 1;
+        }
+
+        // advance in exponentially shrinking steps, to the last element satisfying the predicate.
+        step >>= 1;
+        while step > 0 {
+            if step < slice.len() - index && predicate(&slice[index + step]) {
+                index += step;
+            }
+            step >>= 1;
+        }
+
+        index += 1;
+    }
+
+    index
+}
+
+/// The position of the first element at or after `start` not satisfying `predicate`, or the
+/// length of `slice` if there is none, found by galloping from `start`.
+///
+/// Panics if `start` is past the end of `slice`.
+#[inline]
+pub fn advance_from<T, F: Fn(&T)->bool>(slice: &[T], start: usize, predicate: F) -> usize {
+    assert!(start <= slice.len(), "search from position {} of a slice of length {}", start, slice.len());
+    start + advance(&slice[start ..], predicate)
+}
+
+/// The number of elements at the start of `slice` satisfying `predicate`, found by bisection.
+///
+/// As with `advance`, the predicate must stay false once it becomes false.
+#[inline]
+pub fn partition_point<T, F: Fn(&T)->bool>(slice: &[T], predicate: F) -> usize {
+    let (mut low, mut high) = (0, slice.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if predicate(&slice[middle]) { low = middle + 1; } else { high = middle; }
+    }
+    low
+}
+
+/// The position of the first element at or after `start` not satisfying `predicate`, or the
+/// length of `slice` if there is none, found by bisection.
+///
+/// Panics if `start` is past the end of `slice`.
+#[inline]
+pub fn partition_point_from<T, F: Fn(&T)->bool>(slice: &[T], start: usize, predicate: F) -> usize {
+    assert!(start <= slice.len(), "search from position {} of a slice of length {}", start, slice.len());
+    start + partition_point(&slice[start ..], predicate)
+}
+
+/// The position of the first element at or after `start` not less than `value`, in a slice sorted
+/// from `start` on.
+///
+/// Panics if `start` is past the end of `slice`.
+#[inline]
+pub fn gallop<T: Ord>(slice: &[T], start: usize, value: &T) -> usize {
+    advance_from(slice, start, |x| x < value)
+}
+
+/// The positions of the elements equal to `value` at or after `start`, in a slice sorted from
+/// `start` on; empty, and starting where `value` would be, if there are none.
+///
+/// Panics if `start` is past the end of `slice`.
+#[inline]
+pub fn equal_range<T: Ord>(slice: &[T], start: usize, value: &T) -> Range<usize> {
+    let lower = gallop(slice, start, value);
+    lower .. advance_from(slice, lower, |x| x == value)
+}
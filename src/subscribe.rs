@@ -0,0 +1,265 @@
+//! Subscriptions to changes in matches, restricted by filter expressions.
+//!
+//! A `SubscriptionRegistry` holds a channel and a `Filter` for each subscriber. Routing the match
+//! streams of a plan through the registry evaluates each subscriber's filter against each change,
+//! at the worker producing it, and sends only the changes that pass to the subscriber's channel.
+//! Subscribers that each want a narrow slice of the results therefore cost little, as changes are
+//! neither copied nor serialized for subscribers that would discard them.
+//!
+//! Filters are written as expressions over the query node and the vertices of a match, e.g.
+//!
+//!     query = 3 and (v0 = 17 or contains 42) and not v2 < 100
+//!
+//! where `vN` is the vertex bound to the `N`th attribute, compared with `=`, `!=`, `<`, `<=`, `>`,
+//! or `>=`; `contains N` holds if any vertex of the match is `N`; and `true` holds always. Clauses
+//! combine with `not`, `and`, and `or`, in decreasing order of precedence, and parentheses.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Pipeline;
+
+use ::{Node, Weight};
+use faults;
+
+/// A change to a match delivered to a subscriber: the time, the query node, the match, and its diff.
+pub type SubscriptionUpdate<T> = (T, usize, Vec<Node>, i64);
+
+/// A comparison between a vertex of a match and a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison { Eq, Ne, Lt, Le, Gt, Ge }
+
+/// A predicate over changes to matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Holds for every change.
+    True,
+    /// Holds for changes to matches of the query node.
+    Query(usize),
+    /// Holds if the vertex at the position compares as indicated with the constant.
+    Vertex(usize, Comparison, Node),
+    /// Holds if any vertex of the match is the constant.
+    Contains(Node),
+    /// Holds if the filter does not.
+    Not(Box<Filter>),
+    /// Holds if both filters do.
+    And(Box<Filter>, Box<Filter>),
+    /// Holds if either filter does.
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    /// Parses a filter expression.
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let tokens = tokenize(text)?;
+        let mut cursor = 0;
+        let filter = parse_or(&tokens, &mut cursor)?;
+        if cursor < tokens.len() {
+            return Err(format!("unexpected `{}` in filter", tokens[cursor]));
+        }
+        Ok(filter)
+    }
+
+    /// True if the filter holds for a change to `embedding`, a match of `query`.
+    ///
+    /// Comparisons with positions past the end of the match do not hold.
+    pub fn matches(&self, query: usize, embedding: &[Node]) -> bool {
+        match *self {
+            Filter::True => true,
+            Filter::Query(q) => q == query,
+            Filter::Vertex(position, comparison, value) => {
+                embedding.get(position).map(|&vertex| match comparison {
+                    Comparison::Eq => vertex == value,
+                    Comparison::Ne => vertex != value,
+                    Comparison::Lt => vertex < value,
+                    Comparison::Le => vertex <= value,
+                    Comparison::Gt => vertex > value,
+                    Comparison::Ge => vertex >= value,
+                }).unwrap_or(false)
+            },
+            Filter::Contains(value) => embedding.contains(&value),
+            Filter::Not(ref filter) => !filter.matches(query, embedding),
+            Filter::And(ref a, ref b) => a.matches(query, embedding) && b.matches(query, embedding),
+            Filter::Or(ref a, ref b) => a.matches(query, embedding) || b.matches(query, embedding),
+        }
+    }
+}
+
+// splits a filter expression into words, numbers, operators, and parentheses.
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() { chars.next(); }
+        else if c == '(' || c == ')' { tokens.push(c.to_string()); chars.next(); }
+        else if c == '=' || c == '<' || c == '>' || c == '!' {
+            chars.next();
+            let mut token = c.to_string();
+            if chars.peek() == Some(&'=') { token.push('='); chars.next(); }
+            if token == "!" { return Err("expected `!=` in filter".to_owned()); }
+            tokens.push(token);
+        }
+        else if c.is_alphanumeric() || c == '_' {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') { break; }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+        else { return Err(format!("unexpected character `{}` in filter", c)); }
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[String], cursor: &mut usize) -> Result<Filter, String> {
+    let mut filter = parse_and(tokens, cursor)?;
+    while tokens.get(*cursor).map(|t| t == "or").unwrap_or(false) {
+        *cursor += 1;
+        filter = Filter::Or(Box::new(filter), Box::new(parse_and(tokens, cursor)?));
+    }
+    Ok(filter)
+}
+
+fn parse_and(tokens: &[String], cursor: &mut usize) -> Result<Filter, String> {
+    let mut filter = parse_not(tokens, cursor)?;
+    while tokens.get(*cursor).map(|t| t == "and").unwrap_or(false) {
+        *cursor += 1;
+        filter = Filter::And(Box::new(filter), Box::new(parse_not(tokens, cursor)?));
+    }
+    Ok(filter)
+}
+
+fn parse_not(tokens: &[String], cursor: &mut usize) -> Result<Filter, String> {
+    let token = tokens.get(*cursor).ok_or_else(|| "unexpected end of filter".to_owned())?;
+    *cursor += 1;
+    match token.as_str() {
+        "not" => Ok(Filter::Not(Box::new(parse_not(tokens, cursor)?))),
+        "(" => {
+            let filter = parse_or(tokens, cursor)?;
+            if tokens.get(*cursor).map(|t| t != ")").unwrap_or(true) {
+                return Err("expected `)` in filter".to_owned());
+            }
+            *cursor += 1;
+            Ok(filter)
+        },
+        "true" => Ok(Filter::True),
+        "contains" => Ok(Filter::Contains(number(tokens, cursor)?)),
+        "query" => {
+            if tokens.get(*cursor).map(|t| t != "=").unwrap_or(true) {
+                return Err("expected `=` after `query` in filter".to_owned());
+            }
+            *cursor += 1;
+            Ok(Filter::Query(number(tokens, cursor)?))
+        },
+        word if word.starts_with('v') && word[1..].parse::<usize>().is_ok() => {
+            let position = word[1..].parse().unwrap();
+            let comparison = match tokens.get(*cursor).map(|t| t.as_str()) {
+                Some("=") => Comparison::Eq,
+                Some("!=") => Comparison::Ne,
+                Some("<") => Comparison::Lt,
+                Some("<=") => Comparison::Le,
+                Some(">") => Comparison::Gt,
+                Some(">=") => Comparison::Ge,
+                _ => return Err(format!("expected a comparison after `{}` in filter", word)),
+            };
+            *cursor += 1;
+            Ok(Filter::Vertex(position, comparison, number(tokens, cursor)?))
+        },
+        other => Err(format!("unexpected `{}` in filter", other)),
+    }
+}
+
+fn number<N: ::std::str::FromStr>(tokens: &[String], cursor: &mut usize) -> Result<N, String> {
+    let token = tokens.get(*cursor).ok_or_else(|| "expected a number at end of filter".to_owned())?;
+    *cursor += 1;
+    token.parse().map_err(|_| format!("expected a number in filter, found `{}`", token))
+}
+
+/// A shared map from subscription identifiers to their filters and channels.
+pub struct SubscriptionRegistry<T> {
+    subscriptions: Arc<Mutex<(usize, HashMap<usize, (Filter, Sender<SubscriptionUpdate<T>>)>)>>,
+}
+
+impl<T> Clone for SubscriptionRegistry<T> {
+    fn clone(&self) -> Self { SubscriptionRegistry { subscriptions: self.subscriptions.clone() } }
+}
+
+impl<T: Send> SubscriptionRegistry<T> {
+    /// Allocates a registry with no subscriptions.
+    pub fn new() -> Self {
+        SubscriptionRegistry { subscriptions: Arc::new(Mutex::new((0, HashMap::new()))) }
+    }
+
+    /// Subscribes to the changes that pass `filter`, returning an identifier and the receiver.
+    pub fn subscribe(&self, filter: Filter) -> (usize, Receiver<SubscriptionUpdate<T>>) {
+        let (sender, receiver) = channel();
+        let mut subscriptions = faults::lock(&self.subscriptions);
+        let id = subscriptions.0;
+        subscriptions.0 += 1;
+        subscriptions.1.insert(id, (filter, sender));
+        (id, receiver)
+    }
+
+    /// Unsubscribes `id`, returning true if it was subscribed.
+    pub fn unsubscribe(&self, id: usize) -> bool {
+        faults::lock(&self.subscriptions).1.remove(&id).is_some()
+    }
+
+    /// The number of subscriptions.
+    pub fn len(&self) -> usize {
+        faults::lock(&self.subscriptions).1.len()
+    }
+
+    /// True if there are no subscriptions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Extension method delivering matches to subscribers.
+pub trait RouteSubscriptions<G: Scope, W: Weight> {
+    /// Sends each change to a match of `query` to each subscriber whose filter it passes.
+    ///
+    /// Returns the delivered `(subscription, match, diff)` triples, which may be probed to learn
+    /// when all changes at a time have been delivered. Subscribers receive diffs as `i64`, whatever
+    /// the weight of the stream.
+    fn route_subscriptions(&self, query: usize, registry: &SubscriptionRegistry<G::Timestamp>) -> Stream<G, (usize, Vec<Node>, W)>
+        where G::Timestamp: Send;
+}
+
+impl<G: Scope, W: Weight> RouteSubscriptions<G, W> for Stream<G, (Vec<Node>, W)> {
+    fn route_subscriptions(&self, query: usize, registry: &SubscriptionRegistry<G::Timestamp>) -> Stream<G, (usize, Vec<Node>, W)>
+        where G::Timestamp: Send {
+
+        let registry = registry.clone();
+        let mut buffer = Vec::new();
+        let mut closed = Vec::new();
+
+        // evaluate filters at the worker producing each change, so that only passing changes move.
+        self.unary(Pipeline, "RouteSubscriptions", move |_,_| move |input, output| {
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let mut subscriptions = faults::lock(&registry.subscriptions);
+                if subscriptions.1.is_empty() { buffer.clear(); return; }
+                let mut session = output.session(&time);
+                for (embedding, diff) in buffer.drain(..) {
+                    for (&id, &(ref filter, ref sender)) in subscriptions.1.iter() {
+                        if filter.matches(query, &embedding) {
+                            if sender.send((time.time().clone(), query, embedding.clone(), diff.to_i64())).is_ok() {
+                                session.give((id, embedding.clone(), diff));
+                            }
+                            else { closed.push(id); }
+                        }
+                    }
+                }
+                // subscribers whose receivers were dropped are unsubscribed.
+                for id in closed.drain(..) { subscriptions.1.remove(&id); }
+            });
+        })
+    }
+}
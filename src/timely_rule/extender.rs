@@ -338,17 +338,17 @@ mod merge_sorter {
         #[inline(always)]
         pub fn peek(&self) -> &T {
             debug_assert!(self.head < self.tail);
-            unsafe { self.list.get_unchecked(self.head) }
+            unsafe { &*self.list.as_ptr().offset(self.head as isize) }
         }
         #[inline(always)]
         pub fn _peek_tail(&self) -> &T {
             debug_assert!(self.head < self.tail);
-            unsafe { self.list.get_unchecked(self.tail-1) }
+            unsafe { &*self.list.as_ptr().offset((self.tail as isize) - 1) }
         }
         #[inline(always)]
         pub fn _slice(&self) -> &[T] {
             debug_assert!(self.head < self.tail);
-            unsafe { from_raw_parts(self.list.get_unchecked(self.head), self.tail - self.head) }
+            unsafe { from_raw_parts(self.list.as_ptr().offset(self.head as isize), self.tail - self.head) }
         }
         #[inline(always)]
         pub fn from(mut list: Vec<T>) -> Self {
@@ -376,7 +376,7 @@ mod merge_sorter {
     unsafe fn push_unchecked<T>(vec: &mut Vec<T>, element: T) {
         debug_assert!(vec.len() < vec.capacity());
         let len = vec.len();
-        ::std::ptr::write(vec.get_unchecked_mut(len), element);
+        ::std::ptr::write(vec.as_mut_ptr().offset(len as isize), element);
         vec.set_len(len + 1);
     }
 
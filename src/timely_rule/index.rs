@@ -1,4 +1,4 @@
-use super::advance;
+use search::{advance_from, gallop};
 
 use std::hash::Hash;
 use std::collections::HashMap;
@@ -28,7 +28,7 @@ pub struct Index<Key: Ord+Hash, Val: Ord, T> {
 
 mod compact {
 
-    use super::advance;
+    use search::advance_from;
 
     pub struct CompactIndex<K, V> {
         keys: Vec<(K, usize)>,
@@ -70,7 +70,7 @@ mod compact {
 
             if *key_cursor < self.keys.len() {
 
-                *key_cursor += advance(&self.keys[*key_cursor..], |x| &x.0 < key);
+                *key_cursor = advance_from(&self.keys, *key_cursor, |x| &x.0 < key);
 
                 if self.keys.get(*key_cursor).map(|x| &x.0) == Some(key) {
                     let lower = if *key_cursor == 0 { 0 } else { self.keys[*key_cursor-1].1 };
@@ -90,7 +90,7 @@ mod compact {
 
 mod edge_list_neu {
 
-    use super::advance;
+    use search::advance_from;
 
     /// A LSM-style list of updates.
     ///
@@ -272,9 +272,7 @@ mod edge_list_neu {
             while s_cursor < source.len() && u_cursor < updates.len() {
                 match source[s_cursor].cmp(&updates[u_cursor].0) {
                     Ordering::Less => {
-                        let step = 1 + advance(&source[(s_cursor+1)..], |x| x < &updates[u_cursor].0);
-                        debug_assert!(step > 0);
-                        s_cursor += step;
+                        s_cursor = advance_from(source, s_cursor + 1, |x| x < &updates[u_cursor].0);
                     },
                     Ordering::Equal => {
                         counts[s_cursor] += updates[u_cursor].1;
@@ -282,9 +280,7 @@ mod edge_list_neu {
                         u_cursor += 1;
                     },
                     Ordering::Greater => {
-                        let step = 1 + advance(&updates[(u_cursor+1)..], |x| x.0 < source[s_cursor]);
-                        debug_assert!(step > 0);
-                        u_cursor += step;
+                        u_cursor = advance_from(updates, u_cursor + 1, |x| x.0 < source[s_cursor]);
                     },
                 }
             }
@@ -294,7 +290,7 @@ mod edge_list_neu {
 
 mod unsorted {
 
-    use super::advance;
+    use search::advance_from;
 
     pub struct Unsorted<K, V, T> {
         pub updates: Vec<(K, V, T, i32)>,
@@ -306,16 +302,27 @@ mod unsorted {
         pub fn new() -> Self { Unsorted { updates: Vec::new(), min_time: None } }
 
         pub fn values_from<'a>(&'a self, key: &K, key_cursor: &mut usize) -> &'a [(K, V, T, i32)] {
-            *key_cursor += advance(&self.updates[*key_cursor ..], |x| &x.0 < key);
-            let step = advance(&self.updates[*key_cursor ..], |x| &x.0 <= key);
-            let result = &self.updates[*key_cursor..][..step];
-            *key_cursor += step;
-            result
+            let lower = advance_from(&self.updates, *key_cursor, |x| &x.0 < key);
+            *key_cursor = advance_from(&self.updates, lower, |x| &x.0 <= key);
+            &self.updates[lower .. *key_cursor]
         }
 
+        // adds updates at `time`, summing the diffs of updates to the same key and value at the
+        // same time, and dropping those that cancel.
         pub fn extend<I: Iterator<Item=((K, V), i32)>>(&mut self, time: T, iterator: I) {
             self.updates.extend(iterator.map(|((k,v),d)| (k, v, time.clone(), d)));
-            self.updates.sort_by(|x,y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+            self.updates.sort_by(|x,y| (&x.0, &x.1, &x.2).cmp(&(&y.0, &y.1, &y.2)));
+            let mut updates = ::std::mem::replace(&mut self.updates, Vec::new()).into_iter();
+            if let Some(mut last) = updates.next() {
+                for update in updates {
+                    if (&update.0, &update.1, &update.2) == (&last.0, &last.1, &last.2) { last.3 += update.3; }
+                    else {
+                        if last.3 != 0 { self.updates.push(last); }
+                        last = update;
+                    }
+                }
+                if last.3 != 0 { self.updates.push(last); }
+            }
 
             if self.min_time == None || self.min_time.as_ref().unwrap() > &time {
                 self.min_time = Some(time);
@@ -520,14 +527,14 @@ impl<Key: Ord+Hash+Clone, Val: Ord+Clone, T: Ord+Clone> Index<Key, Val, T> {
                 for (proposal, count) in proposals.iter().zip(temp.iter_mut()) {
 
                     // move c_cursor to where `proposal` would start ..
-                    c_cursor += advance(&compact_slice[c_cursor..], |x| x < proposal);
+                    c_cursor = gallop(compact_slice, c_cursor, proposal);
                     while compact_slice.get(c_cursor) == Some(proposal) {
                         *count += 1;
                         c_cursor += 1;
                     }
 
                     // move d_cursor to where `proposal` would start ..
-                    d_cursor += advance(&diffs_slice[d_cursor..], |x| &x.1 < proposal);
+                    d_cursor = advance_from(diffs_slice, d_cursor, |x| &x.1 < proposal);
                     while diffs_slice.get(d_cursor).map(|x| &x.1) == Some(proposal) {
                         if valid(&diffs_slice[d_cursor].2) {
                             *count += diffs_slice[d_cursor].3;
@@ -576,7 +583,7 @@ impl<Key: Ord+Hash+Clone, Val: Ord+Clone, T: Ord+Clone> Index<Key, Val, T> {
             // consider the amount of effort we are about to invest:
             let mut effort = 16;
 
-            let temp_index = index + advance(&data[index..],|x|func1(&x.0)<= key1);
+            let temp_index = advance_from(&data[..], index, |x| func1(&x.0) <= key1);
             effort += temp_index - index;
 
             // (i) position `self.compact` cursor so that we can re-use it.
@@ -609,14 +616,14 @@ impl<Key: Ord+Hash+Clone, Val: Ord+Clone, T: Ord+Clone> Index<Key, Val, T> {
             for (proposal, count) in proposals.iter().zip(temp.iter_mut()) {
 
                 // move c_cursor to where `proposal` would start ..
-                c_cursor += advance(&compact_slice[c_cursor..], |x| x < proposal);
+                c_cursor = gallop(compact_slice, c_cursor, proposal);
                 while compact_slice.get(c_cursor) == Some(proposal) {
                     *count += 1;
                     c_cursor += 1;
                 }
 
                 // move d_cursor to where `proposal` would start ..
-                d_cursor += advance(&diffs_slice[d_cursor..], |x| &x.1 < proposal);
+                d_cursor = advance_from(diffs_slice, d_cursor, |x| &x.1 < proposal);
                 while diffs_slice.get(d_cursor).map(|x| &x.1) == Some(proposal) {
                     if valid(&diffs_slice[d_cursor].2) {
                         *count += diffs_slice[d_cursor].3;
@@ -0,0 +1,122 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+use timely::progress::Timestamp;
+use timely::dataflow::operators::probe::Handle as ProbeHandle;
+
+use super::{Index, IndexStream};
+use super::StreamPrefixIntersector;
+
+/// An `IndexStream` wrapper that filters prefixes by the presence of a (key, value) pair.
+///
+/// The `logic1` and `logic2` functions extract the key and the value from each prefix, and the
+/// time validator indicates, as for `IndexExtender`, whether updates at one time should be
+/// included in answers for another.
+pub struct IndexIntersector<K, V, T, P, L, L1, H, F>
+    where
+        K: Ord+Hash+Clone,
+        V: Ord+Clone,
+        T: Timestamp,
+        L: Fn(&P)->K,
+        L1: Fn(&P)->V,
+        H: Fn(K)->u64,
+        F: Fn(&T, &T)->bool,
+{
+    handle: ProbeHandle<T>,
+    index: Rc<RefCell<Index<K, V, T>>>,
+    hash: Rc<H>,
+    logic1: Rc<L>,
+    logic2: Rc<L1>,
+    valid: Rc<F>,
+    phantom: PhantomData<P>,
+}
+
+pub trait IntersectOnly<K: Ord+Hash+Clone, V: Ord+Clone, H: Fn(K)->u64, T: Timestamp+Ord>
+{
+    /// Intersects an `IndexStream` using the supplied functions.
+    ///
+    /// The `logic1` and `logic2` functions map prefixes to index keys and values, respectively.
+    /// The `func` function compares timestamps, acting as either `lt` or `le` depending on the need.
+    fn intersect_using<P, L, L1, F>(&self, logic1: L, logic2: L1, func: F) -> Rc<IndexIntersector<K, V, T, P, L, L1, H, F>>
+        where
+            L: Fn(&P)->K+'static,
+            L1: Fn(&P)->V+'static,
+            F: Fn(&T, &T)->bool+'static;
+}
+
+impl<K: Ord+Hash+Clone, V: Ord+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IntersectOnly<K, V, H, T> for IndexStream<K, V, H, T> {
+    fn intersect_using<P, L, L1, F>(&self, logic1: L, logic2: L1, func: F) -> Rc<IndexIntersector<K, V, T, P, L, L1, H, F>>
+        where
+            L: Fn(&P)->K+'static,
+            L1: Fn(&P)->V+'static,
+            F: Fn(&T, &T)->bool+'static,
+    {
+        Rc::new(IndexIntersector {
+            handle: self.handle.clone(),
+            index: self.index.clone(),
+            hash: self.hash.clone(),
+            logic1: Rc::new(logic1),
+            logic2: Rc::new(logic2),
+            valid: Rc::new(func),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<K, V, G, P, L, L1, H, F> StreamPrefixIntersector<G> for Rc<IndexIntersector<K, V, G::Timestamp, P, L, L1, H, F>>
+    where
+        K: Ord+Hash+Clone+ExchangeData,
+        V: Ord+Clone+ExchangeData,
+        G: Scope,
+        G::Timestamp: Timestamp+Ord+Clone,
+        P: ExchangeData+Debug,
+        L: Fn(&P)->K+'static,
+        L1: Fn(&P)->V+'static,
+        H: Fn(K)->u64+'static,
+        F: Fn(&G::Timestamp, &G::Timestamp)->bool+'static,
+{
+    type Prefix = P;
+
+    fn intersect_only(&self, stream: Stream<G, (Self::Prefix, i32)>) -> Stream<G, (Self::Prefix, i32)>{
+        let hash = self.hash.clone();
+        let logic1 = self.logic1.clone();
+        let logic1_2 = self.logic1.clone();
+        let logic2 = self.logic2.clone();
+        let valid = self.valid.clone();
+        let index = self.index.clone();
+        let handle = self.handle.clone();
+
+        let mut buffer = Vec::new();
+        let mut blocked = HashMap::new();
+        let exch = Exchange::new(move |&(ref x,_)| (*hash)((*logic1_2)(x)));
+
+        stream.unary(exch, "Intersect_only", move |_,_| move |input, output| {
+
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                blocked.entry(time.retain())
+                    .or_insert(Vec::new())
+                    .extend(buffer.drain(..))
+            });
+
+            for (time, data) in blocked.iter_mut() {
+
+                // ok to process if no further updates less or equal to `time`.
+                if !handle.less_equal(time.time()) {
+                    (*index).borrow_mut().intersect_only(data, &*logic1, &*logic2, &|t| (*valid)(t, time.time()));
+                    output.session(&time).give_iterator(data.drain(..));
+                }
+            }
+
+            blocked.retain(|_, data| data.len() > 0);
+        })
+    }
+}
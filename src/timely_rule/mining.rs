@@ -0,0 +1,196 @@
+//! Incremental mining of frequent motifs.
+//!
+//! Rather than tracking one described motif, mining tracks every connected motif on a few vertices
+//! and maintains which of them are frequent: those whose support, the number of distinct subgraphs
+//! matching the motif, is at least a threshold. As the graph changes, the mining dataflow reports
+//! each motif whose support crosses the threshold, in either direction.
+//!
+//! Motifs are the connected oriented graphs on the requested number of vertices (no edge appears
+//! in both directions), one for each class of isomorphic graphs, as `connected_motifs` enumerates
+//! them. `track_motif` reports each subgraph matching a motif once for each automorphism of the
+//! motif, so supports are the numbers of matches divided by the numbers of automorphisms. With
+//! vertex labels, each motif is further split by the labels of its vertices, up to the motif's
+//! automorphisms, and each labeled motif has its own support.
+//!
+//! Every motif on three or four vertices is tracked by its own dataflow, so mining is only
+//! practical for these small sizes.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::*;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::motif::{GraphStreamIndex, Node};
+
+/// A motif, as the edges between its vertices `0 .. n`.
+pub type Motif = Vec<(usize, usize)>;
+
+/// A change in whether a motif is frequent, as `(motif, labels, support, frequent)`.
+///
+/// `motif` indexes the motifs mining returns, `labels` are the labels of its vertices (all zero
+/// without labels), and `support` is the support of the labeled motif once the change happened.
+pub type SupportChange = (usize, Vec<u32>, i64, bool);
+
+/// The connected oriented graphs on `vertices` vertices, one for each class of isomorphic graphs.
+///
+/// Each motif is the least of its class, comparing sorted edge lists, so the enumeration does not
+/// change between runs.
+pub fn connected_motifs(vertices: usize) -> Vec<Motif> {
+
+    let pairs = (0 .. vertices).flat_map(|src| (src + 1 .. vertices).map(move |dst| (src, dst))).collect::<Vec<_>>();
+    let permutations = permutations(vertices);
+
+    // each pair of vertices is joined by no edge, an edge one way, or an edge the other way.
+    let mut motifs = Vec::new();
+    for choice in 0 .. 3usize.pow(pairs.len() as u32) {
+        let mut edges = Vec::new();
+        let mut rest = choice;
+        for &(src, dst) in pairs.iter() {
+            match rest % 3 {
+                1 => edges.push((src, dst)),
+                2 => edges.push((dst, src)),
+                _ => { },
+            }
+            rest /= 3;
+        }
+        if connected(vertices, &edges) {
+            motifs.push(permutations.iter().map(|permutation| relabel(&edges, permutation)).min().unwrap());
+        }
+    }
+    motifs.sort();
+    motifs.dedup();
+    motifs
+}
+
+/// The permutations of the vertices of `motif` mapping its edges onto its edges.
+pub fn automorphisms(vertices: usize, motif: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let sorted = relabel(motif, &(0 .. vertices).collect::<Vec<_>>());
+    permutations(vertices).into_iter().filter(|permutation| relabel(motif, permutation) == sorted).collect()
+}
+
+// the sorted edges of `edges` with each vertex `v` renamed to `permutation[v]`.
+fn relabel(edges: &[(usize, usize)], permutation: &[usize]) -> Motif {
+    let mut result = edges.iter().map(|&(src, dst)| (permutation[src], permutation[dst])).collect::<Vec<_>>();
+    result.sort();
+    result
+}
+
+fn permutations(vertices: usize) -> Vec<Vec<usize>> {
+    if vertices == 0 { return vec![Vec::new()]; }
+    let mut result = Vec::new();
+    for shorter in permutations(vertices - 1) {
+        for position in 0 .. vertices {
+            let mut permutation = shorter.clone();
+            permutation.insert(position, vertices - 1);
+            result.push(permutation);
+        }
+    }
+    result
+}
+
+fn connected(vertices: usize, edges: &[(usize, usize)]) -> bool {
+    let mut reached = vec![false; vertices];
+    let mut frontier = vec![0];
+    reached[0] = true;
+    while let Some(vertex) = frontier.pop() {
+        for &(src, dst) in edges.iter() {
+            let other = if src == vertex { dst } else if dst == vertex { src } else { continue };
+            if !reached[other] {
+                reached[other] = true;
+                frontier.push(other);
+            }
+        }
+    }
+    reached.iter().all(|&x| x)
+}
+
+impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStreamIndex<G, H1, H2> where G::Timestamp: Ord+::std::hash::Hash {
+
+    /// Tracks the connected motifs on `vertices` vertices, reporting those whose support crosses `threshold`.
+    ///
+    /// Returns the motifs, indexed by the changes reported, and the stream of changes.
+    pub fn mine_frequent<'a>(&self, vertices: usize, threshold: i64) -> (Vec<Motif>, Stream<G, SupportChange>) where G: 'a {
+        self.mine_frequent_labeled(vertices, threshold, |_| 0)
+    }
+
+    /// Tracks the connected motifs on `vertices` vertices split by the labels `label` assigns to
+    /// their vertices, reporting the labeled motifs whose support crosses `threshold`.
+    ///
+    /// Returns the motifs, indexed by the changes reported, and the stream of changes.
+    pub fn mine_frequent_labeled<'a, L>(&self, vertices: usize, threshold: i64, label: L) -> (Vec<Motif>, Stream<G, SupportChange>)
+        where G: 'a, L: Fn(Node)->u32+'static {
+
+        assert!(vertices >= 2, "motifs need at least two vertices");
+        assert!(threshold > 0, "support thresholds must be positive");
+        let label = Rc::new(label);
+        let motifs = connected_motifs(vertices);
+
+        let mut symmetries = Vec::new();
+        let mut keyed: Option<Stream<G, _>> = None;
+        for (index, motif) in motifs.iter().enumerate() {
+            let automorphisms = automorphisms(vertices, motif);
+            symmetries.push(automorphisms.len() as i64);
+
+            // the labels of a match, least among the orders the motif's automorphisms give them.
+            let label = label.clone();
+            let changes = self.track_motif(motif).map(move |(binding, diff)| {
+                let labels = binding.iter().map(|&node| (*label)(node)).collect::<Vec<_>>();
+                let least = automorphisms.iter().map(|permutation| permutation.iter().map(|&v| labels[v]).collect::<Vec<_>>()).min().unwrap();
+                ((index, least), diff)
+            });
+            keyed = Some(match keyed {
+                None => changes,
+                Some(stream) => stream.concat(&changes),
+            });
+        }
+        let keyed = keyed.expect("no motifs");
+
+        // matches of each labeled motif, and their changes stashed by time.
+        let mut matches: HashMap<(usize, Vec<u32>), i64> = HashMap::new();
+        let mut stash = HashMap::new();
+        let mut buffer = Vec::new();
+
+        let exchange = Exchange::new(|x: &((usize, Vec<u32>), i32)| (x.0).1.iter().fold((x.0).0 as u64, |h, &l| h.wrapping_mul(0x9E3779B97F4A7C15) ^ l as u64));
+        let changes = keyed.unary_notify(exchange, "FrequentMotifs", vec![], move |input, output, notificator| {
+
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+                notificator.notify_at(time.retain());
+            });
+
+            let mut ready = Vec::new();
+            notificator.for_each(|time, _, _| ready.push(time));
+            ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+            for time in ready {
+                let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+                changes.sort();
+                let mut session = output.session(&time);
+                let mut index = 0;
+                while index < changes.len() {
+                    let start = index;
+                    let mut diff = 0i64;
+                    while index < changes.len() && changes[index].0 == changes[start].0 {
+                        diff += changes[index].1 as i64;
+                        index += 1;
+                    }
+                    if diff != 0 {
+                        let key = changes[start].0.clone();
+                        let symmetry = symmetries[key.0];
+                        let before = *matches.get(&key).unwrap_or(&0);
+                        let after = before + diff;
+                        let (was, is) = (before / symmetry >= threshold, after / symmetry >= threshold);
+                        if was != is { session.give((key.0, key.1.clone(), after / symmetry, is)); }
+                        if after == 0 { matches.remove(&key); }
+                        else { matches.insert(key, after); }
+                    }
+                }
+            }
+        });
+
+        (motifs, changes)
+    }
+}
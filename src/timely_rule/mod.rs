@@ -4,10 +4,14 @@ use timely::Data;
 
 mod index;
 mod extender;
+mod intersector;
 pub mod motif;
+pub mod mining;
 
 pub use self::index::Index;
 pub use self::extender::IndexStream;
+pub use self::intersector::IntersectOnly;
+pub use search::advance;
 
 //use ::Indexable;
 
@@ -34,6 +38,35 @@ pub trait GenericJoin<G:Scope, P:Data, W: Data> {
                            -> Stream<G, (P, Vec<E>, W)>;
 }
 
+/// Functionality used to filter prefixes by the presence of a relation's tuple.
+///
+/// Unlike `StreamPrefixExtender`, an intersector does not propose new attributes; it retains those
+/// prefixes whose bound attributes are present in the relation, as needed to close cycles.
+pub trait StreamPrefixIntersector<G:Scope>{
+    /// The type of data to filter.
+    type Prefix: Data;
+    /// Retains prefixes whose tuple is present in this relation.
+    fn intersect_only(&self, Stream<G, (Self::Prefix, i32)>) -> Stream<G,(Self::Prefix, i32)>;
+}
+
+/// Extension method for filtering prefixes by several relations.
+pub trait Intersection<G:Scope, P:Data>{
+    /// Retains prefixes present in each of the supplied intersectors.
+    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P>+'a>>)
+                          -> Stream<G, (P, i32)>;
+}
+
+impl<G: Scope, P:Data> Intersection<G, P> for Stream<G, (P, i32)> {
+    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P> + 'a>>) -> Stream<G, (P, i32)>
+    {
+        let mut results = self.clone();
+        for intersector in intersectors{
+            results = intersector.intersect_only(results);
+        }
+        results
+    }
+}
+
 // A layer of GenericJoin, in which a collection of prefixes are extended by one attribute
 impl<G: Scope, P:Data, W: Data> GenericJoin<G, P, W> for Stream<G, (P, W)> {
     fn extend<'a, E>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>) -> Stream<G, (P, Vec<E>, W)>
@@ -64,38 +97,3 @@ impl<G: Scope, P:Data, W: Data> GenericJoin<G, P, W> for Stream<G, (P, W)> {
         }
     }
 }
-
-/// Reports the number of elements satisfing the predicate.
-///
-/// This methods *relies strongly* on the assumption that the predicate
-/// stays false once it becomes false, a joint property of the predicate
-/// and the slice. This allows `advance` to use exponential search to
-/// count the number of elements in time logarithmic in the result.
-// #[inline(never)]
-pub fn advance<T, F: Fn(&T)->bool>(slice: &[T], function: F) -> usize {
-
-    // start with no advance
-    let mut index = 0;
-    if index < slice.len() && function(&slice[index]) {
-
-        // advance in exponentially growing steps.
-        let mut step = 1;
-        while index + step < slice.len() && function(&slice[index + step]) {
-            index += step;
-            step = step << 1;
-        }
-
-        // advance in exponentially shrinking steps.
-        step = step >> 1;
-        while step > 0 {
-            if index + step < slice.len() && function(&slice[index + step]) {
-                index += step;
-            }
-            step = step >> 1;
-        }
-
-        index += 1;
-    }
-
-    index
-}
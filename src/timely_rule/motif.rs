@@ -17,10 +17,10 @@ use timely::dataflow::operators::*;
 
 use super::index::Index;
 use super::{IndexStream, StreamPrefixExtender, GenericJoin};
+use super::{IntersectOnly, StreamPrefixIntersector, Intersection};
 use ::Indexable;
 
-pub type Node = u32;
-pub type Edge = (Node, Node);
+pub use ::{Node, Edge};
 
 /// Handles to the forward and reverse graph indices.
 pub struct GraphStreamIndexHandle<T> {
@@ -105,11 +105,24 @@ impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStream
 
     /// Constructs a dataflow subgraph to track a described motif.
     pub fn track_motif<'a>(&self, description: &[(usize, usize)]) -> Stream<G, (Vec<Node>, i32)> where G: 'a {
+        self.track_motif_attributed(description).0
+    }
+    /// Constructs a dataflow subgraph to track a described motif, attributing changes to motif edges.
+    ///
+    /// Returns the stream of `track_motif`, and for each motif edge the stream of the changes caused
+    /// by updates bound to that motif edge. The first stream is the concatenation of the others,
+    /// and each change is attributed to exactly one motif edge, so the streams explain which
+    /// relation's updates produced each change, and can feed processing keyed on the triggering
+    /// relation.
+    pub fn track_motif_attributed<'a>(&self, description: &[(usize, usize)]) -> (Stream<G, (Vec<Node>, i32)>, Vec<Stream<G, (Vec<Node>, i32)>>) where G: 'a {
         let mut result = self.updates.filter(|_| false).map(|_| (Vec::new(), 0));
+        let mut attributed = Vec::new();
         for relation in 0 .. description.len() {
-            result = result.concat(&self.relation_update(relation, &description));
+            let changes = self.relation_update(relation, &description);
+            result = result.concat(&changes);
+            attributed.push(changes);
         }
-        result
+        (result, attributed)
     }
     /// Constructs a dataflow subgraph to track a described motif.
     pub fn build_motif<'a>(&self, description: &[(usize, usize)]) -> Stream<G, (Vec<Node>, i32)> where G: 'a {
@@ -189,6 +202,24 @@ impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStream
         }
         stream.extend(extenders)
     }
+
+    /// Filters an indexable prefix by the presence of edges between bound attributes.
+    ///
+    /// The plan is described by several (src, dst, is_prior) cues, each requiring the edge from
+    /// attribute `src` to attribute `dst`, where `is_prior` indicates whether updates at the same
+    /// time should be included.
+    pub fn intersect_attributes<'a, P>(&self, stream: &Stream<G, (P, i32)>, plan: &[(usize, usize, bool)]) -> Stream<G, (P, i32)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        let mut intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P>+'a>> = vec![];
+        for &(src, dst, prior) in plan {
+            intersectors.push(match prior {
+                true    => Box::new(self.forward.intersect_using(move |x: &P| x.index(src), move |x: &P| x.index(dst), <_ as PartialOrd>::le)),
+                false   => Box::new(self.forward.intersect_using(move |x: &P| x.index(src), move |x: &P| x.index(dst), <_ as PartialOrd>::lt)),
+            })
+        }
+        stream.intersect_only(intersectors)
+    }
 }
 
 // orders the numbers 0 .. so that each has at least one relation binding it to a prior attribute,
@@ -0,0 +1,163 @@
+//! Predicates over aggregates of the payloads of a match's edges.
+//!
+//! Some patterns are only interesting when their edges jointly satisfy a constraint, e.g. triangles
+//! whose three edges weigh more than a threshold in total. Such a constraint can not be checked as
+//! each edge is bound, and is instead evaluated for complete matches at the query node.
+//!
+//! Edge payloads are supplied as a stream of `(edge, payload, diff)` updates, which each worker
+//! stores in full. A payload must be introduced at the same time as the insertion of its edge, and
+//! retracted at the same time as the edge's deletion, so that the insertion and retraction of a
+//! match see the same payloads and receive the same verdict. At each time, payload insertions are
+//! applied before matches are evaluated and payload retractions after, so that both new matches and
+//! those retracted by deleted edges find their payloads. A match with an edge that has no payload
+//! does not satisfy the predicate.
+//!
+//! Payloads may also weigh matches rather than filter them. `weigh_matches` multiplies each change
+//! to a match by the product of a function of its edges and their payloads, e.g. a decay factor
+//! computed from the times the edges were observed. Decaying towards a fixed reference time, as
+//! in `exp(rate * (observed - reference))`, gives each match a weight that does not change once it
+//! is created, so that sums of weights, such as decayed motif counts, are maintained incrementally
+//! as matches appear and disappear; dividing a sum by `exp(rate * (now - reference))` yields the
+//! decay relative to the current time.
+
+use std::collections::HashMap;
+
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Broadcast, Operator};
+use timely::dataflow::channels::pact::Pipeline;
+
+use subscribe::Comparison;
+use ::Weight;
+use super::{Node, Edge};
+
+/// A function aggregating the payloads of a match's edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate { Sum, Min, Max }
+
+/// A comparison of an aggregate of edge payloads with a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggregatePredicate {
+    pub aggregate: Aggregate,
+    pub comparison: Comparison,
+    pub value: i64,
+}
+
+impl AggregatePredicate {
+    /// True if the aggregate of `payloads` compares with the constant as required.
+    pub fn holds(&self, payloads: &[i64]) -> bool {
+        let aggregate = match self.aggregate {
+            Aggregate::Sum => payloads.iter().sum(),
+            Aggregate::Min => payloads.iter().cloned().min().unwrap_or(i64::max_value()),
+            Aggregate::Max => payloads.iter().cloned().max().unwrap_or(i64::min_value()),
+        };
+        match self.comparison {
+            Comparison::Eq => aggregate == self.value,
+            Comparison::Ne => aggregate != self.value,
+            Comparison::Lt => aggregate < self.value,
+            Comparison::Le => aggregate <= self.value,
+            Comparison::Gt => aggregate > self.value,
+            Comparison::Ge => aggregate >= self.value,
+        }
+    }
+}
+
+/// Retains the changes to matches whose edges' payloads satisfy `predicate`.
+///
+/// The edges of a match are the pairs of positions `edges`, typically those `Plan::pattern_edges`
+/// reports for the query node producing `matches`.
+pub fn filter_aggregate<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, payloads: &Stream<G, (Edge, i64, i32)>,
+                                             edges: Vec<(usize, usize)>, predicate: AggregatePredicate) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut values = Vec::new();
+    with_payloads(matches, payloads, "FilterAggregate", move |stored, embedding, diff| {
+        values.clear();
+        let complete = edges.iter().all(|&(src, dst)| {
+            match stored.get(&(embedding[src], embedding[dst])) {
+                Some(&(payload, _)) => { values.push(payload); true },
+                None => false,
+            }
+        });
+        if complete && predicate.holds(&values) { Some((embedding, diff)) } else { None }
+    })
+}
+
+/// Weighs the changes to matches by the product of `weight` over their edges and payloads.
+///
+/// Each change `(embedding, diff)` becomes `(embedding, diff * w)`, where `w` is the product of
+/// `weight(edge, payload)` for the edges of the match at the pairs of positions `edges`. Changes
+/// to matches with an edge that has no payload are dropped.
+pub fn weigh_matches<G: Scope, W: Weight, F>(matches: &Stream<G, (Vec<Node>, W)>, payloads: &Stream<G, (Edge, i64, i32)>,
+                                             edges: Vec<(usize, usize)>, weight: F) -> Stream<G, (Vec<Node>, f64)>
+    where G::Timestamp: ::std::hash::Hash, F: Fn(Edge, i64)->f64+'static {
+
+    with_payloads(matches, payloads, "WeighMatches", move |stored, embedding, diff| {
+        let mut product = diff.to_i64() as f64;
+        for &(src, dst) in edges.iter() {
+            let edge = (embedding[src], embedding[dst]);
+            match stored.get(&edge) {
+                Some(&(payload, _)) => { product *= weight(edge, payload); },
+                None => { return None; },
+            }
+        }
+        Some((embedding, product))
+    })
+}
+
+// evaluates `logic` for each change to a match, against the payloads present at its time.
+fn with_payloads<G: Scope, W: Weight, D: Data, L>(matches: &Stream<G, (Vec<Node>, W)>, payloads: &Stream<G, (Edge, i64, i32)>,
+                                                 name: &str, mut logic: L) -> Stream<G, D>
+    where G::Timestamp: ::std::hash::Hash, L: FnMut(&HashMap<Edge, (i64, i32)>, Vec<Node>, W)->Option<D>+'static {
+
+    let mut stored: HashMap<Edge, (i64, i32)> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer1 = Vec::new();
+    let mut buffer2 = Vec::new();
+
+    matches.binary_notify(&payloads.broadcast(), Pipeline, Pipeline, name, vec![], move |input1, input2, output, notificator| {
+
+        input1.for_each(|time, data| {
+            data.swap(&mut buffer1);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), Vec::new())).0.extend(buffer1.drain(..));
+            notificator.notify_at(time.retain());
+        });
+        input2.for_each(|time, data| {
+            data.swap(&mut buffer2);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), Vec::new())).1.extend(buffer2.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            if let Some((changes, mut updates)) = stash.remove(time.time()) {
+
+                // insertions first, so that new matches find their payloads.
+                updates.sort_by(|x, y| y.2.cmp(&x.2));
+                let split = updates.iter().position(|x| x.2 < 0).unwrap_or(updates.len());
+                for &(edge, payload, diff) in updates[.. split].iter() {
+                    let entry = stored.entry(edge).or_insert((payload, 0));
+                    entry.0 = payload;
+                    entry.1 += diff;
+                }
+
+                let mut session = output.session(&time);
+                for (embedding, diff) in changes {
+                    if let Some(result) = logic(&stored, embedding, diff) { session.give(result); }
+                }
+
+                // retractions last, so that retracted matches still find theirs.
+                for &(edge, _, diff) in updates[split ..].iter() {
+                    let remove = match stored.get_mut(&edge) {
+                        Some(entry) => { entry.1 += diff; entry.1 <= 0 },
+                        None => false,
+                    };
+                    if remove { stored.remove(&edge); }
+                }
+            }
+        }
+    })
+}
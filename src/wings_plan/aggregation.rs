@@ -0,0 +1,200 @@
+//! Aggregates of the matches of query nodes, grouped by a key and maintained across batches.
+//!
+//! Many uses of a pattern need a summary of its matches rather than the matches themselves: the
+//! number of triangles through each vertex, or the number of matches of each combination of
+//! vertex labels. A query node may declare an `Aggregation` (see `Plan::set_aggregation`), which
+//! groups its matches by a key, each term of which is the vertex at a position of the match or its
+//! label, and aggregates each group as the number of its matches or the sum of an attribute of one
+//! of their vertices, counting each match with its multiplicity. (For predicates over the payloads
+//! of a match's edges, see `aggregate`.)
+//!
+//! The matches of a pattern are spread over the query nodes of its plan, which start from the
+//! different edges an update may bind, and which list the vertices of their matches in different
+//! orders. Aggregations name a group: `Plan::track_aggregates` combines the query nodes declaring
+//! the same group into one aggregate, each node reading its key and value from the positions of its
+//! own matches.
+//!
+//! `maintain_groups` holds the aggregate of every group with matches at the worker owning its key,
+//! and reports the new aggregate of each group a time changes once the time is complete, as
+//! `(key, matches, value)`. A group whose matches and value both return to zero is reported so,
+//! and forgotten; sums of attributes, as sums of floating point numbers, need not return to zero
+//! exactly as the matches contributing to them are removed. Matches a key or value can not be read
+//! for, because a vertex lacks a label or the attribute summed, are left out of their group.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::Node;
+use super::filters::VertexAttributes;
+
+/// A term of the key grouping matches.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum GroupKey {
+    /// The vertex at a position of the match.
+    Vertex(usize),
+    /// The label of the vertex at a position of the match.
+    Label(usize),
+}
+
+impl GroupKey {
+    /// The position of the vertex the term reads.
+    pub fn position(&self) -> usize {
+        match *self {
+            GroupKey::Vertex(position) => position,
+            GroupKey::Label(position) => position,
+        }
+    }
+}
+
+/// How the matches of a group are aggregated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum Aggregator {
+    /// The number of matches, with multiplicity.
+    Count,
+    /// The sum over matches, with multiplicity, of an attribute of the vertex at a position.
+    Sum {
+        /// The position of the vertex.
+        vertex: usize,
+        /// The attribute summed.
+        attribute: u32,
+    },
+}
+
+/// The aggregate a query node contributes its matches to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Aggregation {
+    /// The group, which every query node contributing to the aggregate names.
+    pub group: usize,
+    /// The terms of the key, in order.
+    pub key: Vec<GroupKey>,
+    /// How each group's matches are aggregated.
+    pub aggregator: Aggregator,
+}
+
+impl Aggregation {
+    /// Counts the matches of each key; see `Plan::set_aggregation`.
+    pub fn count(group: usize, key: Vec<GroupKey>) -> Self {
+        Aggregation { group, key, aggregator: Aggregator::Count }
+    }
+
+    /// Sums attribute `attribute` of the vertex at `vertex` over the matches of each key.
+    pub fn sum(group: usize, key: Vec<GroupKey>, vertex: usize, attribute: u32) -> Self {
+        Aggregation { group, key, aggregator: Aggregator::Sum { vertex, attribute } }
+    }
+
+    /// The positions of the matches the aggregation reads, for its key and its value.
+    pub fn positions(&self) -> Vec<usize> {
+        let mut positions = self.key.iter().map(|term| term.position()).collect::<Vec<_>>();
+        if let Aggregator::Sum { vertex, .. } = self.aggregator { positions.push(vertex); }
+        positions
+    }
+
+    /// Whether the aggregation reads the labels of vertices.
+    pub fn reads_labels(&self) -> bool {
+        self.key.iter().any(|term| match *term { GroupKey::Label(_) => true, GroupKey::Vertex(_) => false })
+    }
+
+    /// Whether the aggregation reads the attributes of vertices.
+    pub fn reads_attributes(&self) -> bool {
+        self.aggregator != Aggregator::Count
+    }
+
+    /// Whether matches aggregated by `other`, read from the positions of another query node, can be
+    /// aggregated with those aggregated by `self`: their keys have terms of the same kinds, and they
+    /// aggregate alike.
+    pub fn combines_with(&self, other: &Aggregation) -> bool {
+        let kinds = |aggregation: &Aggregation| aggregation.key.iter().map(|term| match *term { GroupKey::Vertex(_) => 0, GroupKey::Label(_) => 1 }).collect::<Vec<_>>();
+        let attribute = |aggregation: &Aggregation| match aggregation.aggregator { Aggregator::Count => None, Aggregator::Sum { attribute, .. } => Some(attribute) };
+        self.group == other.group && kinds(self) == kinds(other) && attribute(self) == attribute(other)
+    }
+
+    /// The key of `embedding`, and the change to the matches and value of its group that a change
+    /// of `weight` to it makes, or `None` if a label or attribute it needs is missing.
+    pub fn contribution(&self, embedding: &[Node], weight: i64, labels: Option<&HashMap<Node, u32>>, attributes: Option<&VertexAttributes>) -> Option<(Vec<Node>, i64, f64)> {
+        let mut key = Vec::with_capacity(self.key.len());
+        for term in self.key.iter() {
+            key.push(match *term {
+                GroupKey::Vertex(position) => embedding[position],
+                GroupKey::Label(position) => *labels?.get(&embedding[position])?,
+            });
+        }
+        let value = match self.aggregator {
+            Aggregator::Count => weight as f64,
+            Aggregator::Sum { vertex, attribute } => weight as f64 * attributes?.get(attribute, embedding[vertex])?,
+        };
+        Some((key, weight, value))
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.aggregator {
+            Aggregator::Count => write!(f, "group {}: count", self.group)?,
+            Aggregator::Sum { vertex, attribute } => write!(f, "group {}: sum x{}.{}", self.group, vertex, attribute)?,
+        }
+        let key = self.key.iter().map(|term| match *term {
+            GroupKey::Vertex(position) => format!("x{}", position),
+            GroupKey::Label(position) => format!("label(x{})", position),
+        }).collect::<Vec<_>>();
+        if !key.is_empty() { write!(f, " by {}", key.join(","))?; }
+        Ok(())
+    }
+}
+
+/// Maintains the aggregates of groups from changes `(key, matches, value)` to them, reporting the
+/// aggregates of the groups each time changes once it is complete.
+pub fn maintain_groups<G: Scope>(changes: &Stream<G, (Vec<Node>, i64, f64)>) -> Stream<G, (Vec<Node>, i64, f64)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut groups: HashMap<Vec<Node>, (i64, f64)> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let exchange = Exchange::new(|x: &(Vec<Node>, i64, f64)| x.0.iter().fold(0u64, |h, &v| h.wrapping_mul(0x9E3779B97F4A7C15) ^ v as u64));
+    changes.unary_notify(exchange, "MaintainGroups", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+            changes.sort_by(|x, y| x.0.cmp(&y.0));
+            let mut session = output.session(&time);
+            let mut index = 0;
+            while index < changes.len() {
+                let start = index;
+                let (mut matches, mut value) = (0i64, 0.0);
+                while index < changes.len() && changes[index].0 == changes[start].0 {
+                    matches += changes[index].1;
+                    value += changes[index].2;
+                    index += 1;
+                }
+                if matches != 0 || value != 0.0 {
+                    let key = changes[start].0.clone();
+                    let after = {
+                        let group = groups.entry(key.clone()).or_insert((0, 0.0));
+                        group.0 += matches;
+                        group.1 += value;
+                        *group
+                    };
+                    if after == (0, 0.0) { groups.remove(&key); }
+                    session.give((key, after.0, after.1));
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,62 @@
+//! Worst-case bounds on the number of matches of a pattern.
+//!
+//! The AGM bound limits the number of matches of a pattern in a graph with `m` edges to `m^ρ*`,
+//! where `ρ*` is the fractional edge cover number of the pattern: the least total weight of its
+//! edges such that the edges at each vertex weigh at least one. Worst-case optimal joins, which
+//! the plans implement, do work proportional to this bound, so comparing the work done for a query
+//! with its bound as the graph changes validates a plan, and exposes plans that do much more.
+//!
+//! For graphs the fractional edge cover number is `|V| - ν*`, where `ν*` is the fractional
+//! matching number, which is half the size of a maximum matching in the bipartite double cover
+//! of the pattern. Patterns are small, so the matching is found by augmenting paths.
+
+/// The fractional edge cover number of a pattern on `vertices` vertices with edges `edges`.
+///
+/// The direction of edges does not matter, and self-loops are ignored. Returns `None` if some
+/// vertex has no edge, as such patterns have no finite cover.
+pub fn fractional_edge_cover(vertices: usize, edges: &[(usize, usize)]) -> Option<f64> {
+
+    // adjacency of the bipartite double cover: each edge `(u, v)` joins `u` on the left with `v`
+    // on the right, and `v` on the left with `u` on the right.
+    let mut adjacent = vec![Vec::new(); vertices];
+    for &(src, dst) in edges.iter().filter(|x| x.0 != x.1) {
+        adjacent[src].push(dst);
+        adjacent[dst].push(src);
+    }
+    if adjacent.iter().any(|neighbors| neighbors.is_empty()) {
+        return None;
+    }
+
+    let mut matched: Vec<Option<usize>> = vec![None; vertices];
+    let mut size = 0;
+    for left in 0 .. vertices {
+        let mut visited = vec![false; vertices];
+        if augment(left, &adjacent, &mut matched, &mut visited) { size += 1; }
+    }
+
+    Some(vertices as f64 - size as f64 / 2.0)
+}
+
+// searches for an augmenting path from `left`, flipping it into the matching if one is found.
+fn augment(left: usize, adjacent: &[Vec<usize>], matched: &mut Vec<Option<usize>>, visited: &mut Vec<bool>) -> bool {
+    for &right in adjacent[left].iter() {
+        if !visited[right] {
+            visited[right] = true;
+            let free = match matched[right] {
+                None => true,
+                Some(other) => augment(other, adjacent, matched, visited),
+            };
+            if free {
+                matched[right] = Some(left);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// The AGM bound on the matches of a pattern with fractional edge cover number `exponent`, in a
+/// graph with `edges` edges.
+pub fn agm_bound(edges: usize, exponent: f64) -> f64 {
+    (edges as f64).powf(exponent)
+}
@@ -0,0 +1,140 @@
+//! Enriching matches with the attributes of their vertices, as of the time of each change.
+//!
+//! Consumers of matches often want more than vertex ids: a user's country, an account's risk
+//! score. Attributes change over time, and a change to a match should be reported with the
+//! attributes its vertices had at the change's time rather than the latest ones, so that a match
+//! found late, or retracted long after it was created, joins against the attributes of its time.
+//!
+//! `AttributeIndex` holds the versions of each vertex's attribute, as the graph indices hold the
+//! versions of each vertex's edges, and answers lookups as of a time. Versions no longer needed
+//! are collapsed by `merge_to`. `enrich_matches` maintains an index from a stream of attribute
+//! assignments, which each worker stores in full, and pairs each change to a match with the
+//! attributes of its vertices as of the change's time. Assignments at a time apply to the changes
+//! to matches at that time.
+
+use std::collections::HashMap;
+
+use timely::ExchangeData;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Broadcast, Operator};
+use timely::dataflow::channels::pact::Pipeline;
+
+use super::Node;
+use ::Weight;
+
+/// The versions of an attribute of each vertex, for lookups as of a time.
+///
+/// Each version assigns the attribute a value, or clears it, from its time on. Times must be
+/// totally ordered, as epochs are.
+#[derive(Debug, Clone)]
+pub struct AttributeIndex<V, T> {
+    versions: HashMap<Node, Vec<(T, Option<V>)>>,
+}
+
+impl<V, T> Default for AttributeIndex<V, T> {
+    fn default() -> Self { AttributeIndex { versions: HashMap::new() } }
+}
+
+impl<V: Ord+Clone, T: Ord+Clone> AttributeIndex<V, T> {
+    /// Allocates an index with no attributes.
+    pub fn new() -> Self { AttributeIndex::default() }
+
+    /// Assigns `value` to the attribute of `node` from `time` on, or clears it if `value` is `None`.
+    ///
+    /// Assignments to a vertex at the same time should agree; if they do not, the greatest value
+    /// is kept, so that the outcome does not depend on the order of the assignments.
+    pub fn set(&mut self, node: Node, time: T, value: Option<V>) {
+        let versions = self.versions.entry(node).or_insert(Vec::new());
+        match versions.binary_search_by(|x| x.0.cmp(&time)) {
+            Ok(position) => { if versions[position].1 < value { versions[position].1 = value; } },
+            Err(position) => versions.insert(position, (time, value)),
+        }
+    }
+
+    /// The attribute of `node` as of `time`: the value of its last version at or before `time`.
+    pub fn value_at(&self, node: Node, time: &T) -> Option<&V> {
+        let versions = self.versions.get(&node)?;
+        let position = following(versions, time);
+        if position == 0 { None } else { versions[position - 1].1.as_ref() }
+    }
+
+    /// The attribute of `node` as of its latest version.
+    pub fn latest(&self, node: Node) -> Option<&V> {
+        self.versions.get(&node).and_then(|versions| versions.last()).and_then(|version| version.1.as_ref())
+    }
+
+    /// Collapses the versions of each vertex at or before `time` into one, at `time`.
+    ///
+    /// Lookups at `time` or later are unaffected; those at earlier times see the attributes as of
+    /// `time`. Vertices whose attributes are cleared as of `time` and later are removed.
+    pub fn merge_to(&mut self, time: &T) {
+        self.versions.retain(|_, versions| {
+            let position = following(versions, time);
+            if position > 0 {
+                let value = versions[position - 1].1.clone();
+                versions.drain(.. position);
+                versions.insert(0, (time.clone(), value));
+            }
+            versions.iter().any(|version| version.1.is_some())
+        });
+    }
+
+    /// The number of vertices with versions.
+    pub fn nodes(&self) -> usize { self.versions.len() }
+
+    /// The number of versions held, over all vertices.
+    pub fn versions(&self) -> usize { self.versions.values().map(|versions| versions.len()).sum() }
+}
+
+// the position of the first of `versions` after `time`.
+fn following<V, T: Ord>(versions: &[(T, Option<V>)], time: &T) -> usize {
+    match versions.binary_search_by(|x| x.0.cmp(time)) {
+        Ok(position) => position + 1,
+        Err(position) => position,
+    }
+}
+
+/// Pairs each change to a match with the attributes of its vertices as of the change's time.
+///
+/// Attributes are assigned by `attributes`, as `(node, value)` with a value of `None` clearing
+/// the attribute. The output lists the attribute of the vertex at each position of the match,
+/// `None` for vertices without one.
+pub fn enrich_matches<G: Scope, W: Weight, V: ExchangeData+Ord>(matches: &Stream<G, (Vec<Node>, W)>, attributes: &Stream<G, (Node, Option<V>)>) -> Stream<G, (Vec<Node>, Vec<Option<V>>, W)>
+    where G::Timestamp: Ord+::std::hash::Hash {
+
+    let mut index = AttributeIndex::new();
+    let mut stash = HashMap::new();
+    let mut buffer1 = Vec::new();
+    let mut buffer2 = Vec::new();
+
+    matches.binary_notify(&attributes.broadcast(), Pipeline, Pipeline, "EnrichMatches", vec![], move |input1, input2, output, notificator| {
+
+        input1.for_each(|time, data| {
+            data.swap(&mut buffer1);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer1.drain(..));
+            notificator.notify_at(time.retain());
+        });
+        // assignments are versioned by their time, and apply to matches at that time and later.
+        input2.for_each(|time, data| {
+            data.swap(&mut buffer2);
+            for (node, value) in buffer2.drain(..) { index.set(node, time.time().clone(), value); }
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready.iter() {
+            if let Some(changes) = stash.remove(time.time()) {
+                let mut session = output.session(time);
+                for (embedding, diff) in changes {
+                    let values = embedding.iter().map(|&node| index.value_at(node, time.time()).cloned()).collect();
+                    session.give((embedding, values, diff));
+                }
+            }
+        }
+
+        // later changes are at later times, which see the same attributes after merging.
+        if let Some(time) = ready.last() { index.merge_to(time.time()); }
+    })
+}
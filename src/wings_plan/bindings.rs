@@ -0,0 +1,135 @@
+//! Bindings of the parameters of a plan, supplied as the plan runs.
+//!
+//! A plan prepared once for many anchors, e.g. the triangles through a vertex `$v`, marks the
+//! vertices it is anchored at as parameters (see `Plan::set_parameter`) rather than pinning them
+//! to constants. Its dataflow is built once, and reads the values of its parameters from a
+//! stream of bindings, each listing a value for every parameter slot, in order. A plan node with
+//! parameters holds only prefixes whose vertices at its parameters' positions take the values of
+//! some binding in effect, so that one dataflow answers for any number of anchors, and for anchors
+//! that change from batch to batch.
+//!
+//! Bindings form a collection that changes over time, as `(binding, diff)` changes, and a binding
+//! is in effect at the times at which its accumulated diff is positive. As with the attributes of
+//! `attributes`, each worker holds every binding, and the bindings in effect at a time apply to
+//! the prefixes at that time: the changes to matches reported are the changes the updates of a
+//! batch make to the matches of the bindings in effect for it. Supplying a binding does not report
+//! the matches already present for it.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Pipeline;
+
+use super::Node;
+use ::{Indexable, Weight};
+
+/// The bindings of a plan's parameters that changed over time, for lookups as of a time.
+///
+/// Times must be totally ordered, as epochs are.
+#[derive(Debug, Clone)]
+pub struct BindingIndex<T> {
+    versions: HashMap<Vec<Node>, Vec<(T, i32)>>,
+}
+
+impl<T> Default for BindingIndex<T> {
+    fn default() -> Self { BindingIndex { versions: HashMap::new() } }
+}
+
+impl<T: Ord+Clone> BindingIndex<T> {
+    /// Allocates an index with no bindings.
+    pub fn new() -> Self { BindingIndex::default() }
+
+    /// Changes the multiplicity of `binding` by `diff` from `time` on.
+    pub fn update(&mut self, binding: Vec<Node>, time: T, diff: i32) {
+        let versions = self.versions.entry(binding).or_insert(Vec::new());
+        match versions.binary_search_by(|x| x.0.cmp(&time)) {
+            Ok(position) => versions[position].1 += diff,
+            Err(position) => versions.insert(position, (time, diff)),
+        }
+    }
+
+    /// Whether `binding` is in effect at `time`: whether its accumulated diff is positive.
+    pub fn contains_at(&self, binding: &[Node], time: &T) -> bool {
+        match self.versions.get(binding) {
+            Some(versions) => versions.iter().take_while(|x| x.0 <= *time).map(|x| x.1).sum::<i32>() > 0,
+            None => false,
+        }
+    }
+
+    /// Collapses the versions of each binding at or before `time` into one, at `time`.
+    ///
+    /// Lookups at `time` or later are unaffected. Bindings not in effect at `time` and not
+    /// changed later are removed.
+    pub fn merge_to(&mut self, time: &T) {
+        self.versions.retain(|_, versions| {
+            let position = versions.iter().take_while(|x| x.0 <= *time).count();
+            if position > 0 {
+                let diff = versions.drain(.. position).map(|x| x.1).sum::<i32>();
+                if diff != 0 { versions.insert(0, (time.clone(), diff)); }
+            }
+            !versions.is_empty()
+        });
+    }
+
+    /// The number of bindings with versions.
+    pub fn bindings(&self) -> usize { self.versions.len() }
+}
+
+/// Keeps the prefixes whose vertices at `positions` take the values of a binding in effect at
+/// their time.
+///
+/// `positions` lists pairs `(position, slot)`, each asking that the vertex at `position` of a
+/// prefix equal the value at `slot` of the binding. `bindings` must carry the same changes to
+/// every worker, as `broadcast` makes it; each worker then keeps its own prefixes where they are.
+/// Panics if a binding has no value at a slot of `positions`.
+pub fn bind_prefixes<G: Scope, W: Weight, P: ExchangeData+Indexable<Node>>(prefixes: &Stream<G, (P, W)>, bindings: &Stream<G, (Vec<Node>, i32)>, positions: Vec<(usize, usize)>) -> Stream<G, (P, W)>
+    where G::Timestamp: Ord+Hash {
+
+    // bindings are indexed by the values of the slots `positions` reads, in order.
+    let mut index = BindingIndex::new();
+    let mut stash = HashMap::new();
+    let mut buffer1 = Vec::new();
+    let mut buffer2 = Vec::new();
+    let slots = positions.iter().map(|x| x.1).collect::<Vec<_>>();
+
+    prefixes.binary_notify(bindings, Pipeline, Pipeline, "BindPrefixes", vec![], move |input1, input2, output, notificator| {
+
+        input1.for_each(|time, data| {
+            data.swap(&mut buffer1);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer1.drain(..));
+            notificator.notify_at(time.retain());
+        });
+        input2.for_each(|time, data| {
+            data.swap(&mut buffer2);
+            for (binding, diff) in buffer2.drain(..) {
+                let values = slots.iter().map(|&slot| match binding.get(slot) {
+                    Some(&value) => value,
+                    None => panic!("EXCEPTION: binding {:?} has no value for parameter slot {}", binding, slot),
+                }).collect();
+                index.update(values, time.time().clone(), diff);
+            }
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready.iter() {
+            if let Some(prefixes) = stash.remove(time.time()) {
+                let mut session = output.session(time);
+                let mut values = Vec::with_capacity(positions.len());
+                for (prefix, diff) in prefixes {
+                    values.clear();
+                    values.extend(positions.iter().map(|&(position, _)| prefix.index(position)));
+                    if index.contains_at(&values, time.time()) { session.give((prefix, diff)); }
+                }
+            }
+        }
+
+        // later prefixes are at later times, which see the same bindings after merging.
+        if let Some(time) = ready.last() { index.merge_to(time.time()); }
+    })
+}
@@ -0,0 +1,84 @@
+//! Conventions for directed and undirected graphs and for counting matches.
+//!
+//! Plans match directed patterns against directed graphs, and report every embedding of a pattern:
+//! a subgraph that matches a pattern with automorphisms is reported once for each of them. Counts
+//! in the literature are usually of subgraphs instead, and often of undirected patterns in
+//! undirected graphs, where e.g. each triangle counts once. Rather than leave the arithmetic to
+//! users, a plan can be told which conventions its counts follow.
+//!
+//! With `Orientation::Undirected`, each edge of the graph is read in both directions: `symmetrize`
+//! applies each update to an edge to its reverse as well, so that a pattern edge matches a graph
+//! edge whichever way either is listed. Each undirected edge should be listed once, in either
+//! direction. Self-loops are not doubled.
+//!
+//! With `Counting::Subgraphs`, `canonical_matches` reports only one embedding of each subgraph:
+//! the least, among the embeddings the automorphisms of the pattern relate it to. Automorphisms
+//! preserve the pattern's edges with their directions, or as unordered pairs if the plan is
+//! undirected, so that the triangle plan over an undirected graph reports each triangle once.
+//! Changes to the other embeddings are dropped rather than accumulated, which is exact because
+//! every embedding of a subgraph changes whenever the subgraph does.
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Filter, Map};
+
+use super::{Node, Edge, Orientation};
+use ::Weight;
+
+/// Reads each edge of the initial graph and its updates in both directions.
+pub fn symmetrize<G: Scope>(initially: &Stream<G, Edge>, updates: &Stream<G, (Edge, i32)>) -> (Stream<G, Edge>, Stream<G, (Edge, i32)>) {
+    let initially = initially.flat_map(|(src, dst)| {
+        if src == dst { vec![(src, dst)] } else { vec![(src, dst), (dst, src)] }
+    });
+    let updates = updates.flat_map(|((src, dst), diff)| {
+        if src == dst { vec![((src, dst), diff)] } else { vec![((src, dst), diff), ((dst, src), diff)] }
+    });
+    (initially, updates)
+}
+
+/// The permutations of `0 .. vertices` mapping the pattern's `edges` onto its edges.
+///
+/// Edges are compared with their directions if `orientation` is `Directed`, and as unordered
+/// pairs otherwise. The identity is always among the permutations.
+pub fn pattern_automorphisms(vertices: usize, edges: &[(usize, usize)], orientation: Orientation) -> Vec<Vec<usize>> {
+    let normalize = |permutation: &[usize]| {
+        let mut result = edges.iter().map(|&(src, dst)| {
+            let (src, dst) = (permutation[src], permutation[dst]);
+            if orientation == Orientation::Undirected && dst < src { (dst, src) } else { (src, dst) }
+        }).collect::<Vec<_>>();
+        result.sort();
+        result
+    };
+    let identity = (0 .. vertices).collect::<Vec<_>>();
+    let pattern = normalize(&identity);
+    permutations(vertices).into_iter().filter(|permutation| normalize(permutation) == pattern).collect()
+}
+
+fn permutations(vertices: usize) -> Vec<Vec<usize>> {
+    if vertices == 0 { return vec![Vec::new()]; }
+    let mut result = Vec::new();
+    for shorter in permutations(vertices - 1) {
+        for position in 0 .. vertices {
+            let mut permutation = shorter.clone();
+            permutation.insert(position, vertices - 1);
+            result.push(permutation);
+        }
+    }
+    result
+}
+
+/// Retains the changes to the least embedding of each subgraph, among those `automorphisms` relate.
+///
+/// An embedding is retained if it is no greater than its image under each automorphism, which
+/// places at each position the vertex the embedding binds to the position the automorphism maps
+/// it to.
+pub fn canonical_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, automorphisms: Vec<Vec<usize>>) -> Stream<G, (Vec<Node>, W)> {
+    let automorphisms = automorphisms.into_iter().filter(|permutation| permutation.iter().enumerate().any(|(x, &y)| x != y)).collect::<Vec<_>>();
+    if automorphisms.is_empty() { return matches.clone(); }
+    matches.filter(move |&(ref embedding, _)| {
+        automorphisms.iter().all(|permutation| {
+            let mut image = vec![0; embedding.len()];
+            for (position, &target) in permutation.iter().enumerate() { image[target] = embedding[position]; }
+            embedding[..] <= image[..]
+        })
+    })
+}
@@ -18,11 +18,11 @@ use std::path::Path;
 use super::Indexable;
 
 use super::graph_stream::GraphStreamIndex;
+use faults::ErrorEvents;
 use wings_plan::ExtendEdges;
 use wings_rule::advance;
 
-pub type Node = u32;
-pub type Edge = (Node, Node);
+pub use ::{Node, Edge};
 type Label = u32;
 
 #[derive(Debug, Default, Clone)]
@@ -61,10 +61,24 @@ pub struct EdgeLabeledPlan{
     edges: Vec<PlanEdge>,
     nodes: Vec<Rc<PlanNode>>,
     root_node_id: usize,
-    node_graph_map:  Vec<Graph>
+    node_graph_map:  Vec<Graph>,
+    errors: ErrorEvents,
 }
 
 impl EdgeLabeledPlan{
+    /// Sets where the plan's operators report the errors they continue past; see `faults`.
+    ///
+    /// Matches whose labels can not be found are reported and left uncounted, and poisoned
+    /// counters are recovered and reported, rather than failing every later batch.
+    pub fn set_error_events(&mut self, errors: ErrorEvents) {
+        self.errors = errors;
+    }
+
+    /// Where the plan's operators report the errors they continue past.
+    pub fn error_events(&self) -> &ErrorEvents {
+        &self.errors
+    }
+
     pub fn track_motif<H1, H2, G: Scope>(&self, graph: &GraphStreamIndex<G, H1, H2>, probe: &mut ProbeHandle<G::Timestamp>, counter: Arc<Mutex<u64>>, labeled_counters: Arc<RwLock<HashMap<(usize,Vec<u32>),Mutex<u64>>>>, vertex_id_label_map: Arc<HashMap<u32, u32>>, edge_label: Arc<RwLock<Vec<Vec<(u32, u32, u32)>>>>, graph_map: Arc<Vec<Graph>>)
         where H1: Fn(Node)->u64 + 'static,
               H2: Fn(Node)->u64 + 'static
@@ -91,6 +105,8 @@ impl EdgeLabeledPlan{
             let labeled_counters = labeled_counters.clone();
             let vertex_id_label_map1 = vertex_id_label_map.clone();
             let vertex_id_label_map2 = vertex_id_label_map.clone();
+            let errors1 = self.errors.clone();
+            let errors2 = self.errors.clone();
 
             let edge_label1 = edge_label.clone();
             let edge_label2 = edge_label.clone();
@@ -131,24 +147,29 @@ impl EdgeLabeledPlan{
                 output.exchange(|x| (x.0).index(0) as u64)
                     .inspect_batch(move |_,xs| {
                         let mut batch_query_count = HashMap::new();
+                        let edge_label = errors1.read("LabeledCount", &*edge_label2);
                         for x in xs.iter(){
-                            let labeled_query = label_matching(&x.0, vertex_id_label_map2.clone(), edge_label2.clone(),graph_map2.clone(),child.idx);
-                            let counter =  batch_query_count.entry((child.idx, labeled_query)).or_insert(0 as u64);
-                            *counter += 1;
+                            match label_matching(&x.0, &vertex_id_label_map2, &edge_label[..], &graph_map2, child.idx) {
+                                Ok(labeled_query) => {
+                                    let counter =  batch_query_count.entry((child.idx, labeled_query)).or_insert(0 as u64);
+                                    *counter += 1;
+                                },
+                                Err(error) => errors1.report("LabeledCount", format!("left {:?} uncounted: {}", x.0, error)),
+                            }
                         }
 
                         for (query, count) in batch_query_count.into_iter() {
-                            let counters = labeled_counters.read().expect("Mutex poisoned");
+                            let counters = errors1.read("LabeledCount", &*labeled_counters);
 
                             if let Some(counter) = counters.get(&query) {
-                                let mut counter = counter.lock().expect("Mutex poisoned");
+                                let mut counter = errors1.lock("LabeledCount", counter);
                                 *counter += count;
                                 println!("Labeled {:?}: {}", query, *counter);
                                 continue;
                             }
 
                             drop(counters);
-                            let mut counters = labeled_counters.write().expect("RwLock poisoned");
+                            let mut counters = errors1.write("LabeledCount", &*labeled_counters);
                             println!("Labeled {:?}: {}", query, count);
                             counters.entry(query).or_insert_with(||Mutex::new(count));
 
@@ -158,9 +179,7 @@ impl EdgeLabeledPlan{
                     .count()
                     .inspect_batch(move |t,x| println!("{:?}: {:?}", t, x))
                     .inspect_batch(move |_,x| {
-                        if let Ok(mut bound) = counter1.lock() {
-                            *bound += x[0] as u64;
-                        }
+                        *errors2.lock("Count", &*counter1) += x[0] as u64;
                         //Find count for each labeled query
 
                     });
@@ -336,27 +355,36 @@ pub fn get_id_graph_map_from_plan(plan: &EdgeLabeledPlan) -> Vec<Graph> {
     plan.node_graph_map.clone()
 }
 
-fn label_matching<P: Indexable<Node>>(matching: &P, vertex_id_label_map: Arc<HashMap<Node, Label>>,  edge_label: Arc<RwLock<Vec<Vec<(u32, u32, u32)>>>>, graph_map: Arc<Vec<Graph>>, node_id: usize) -> Vec<Label> {
+// the labels of the vertices of `matching`, then of its edges, or an error naming one without a label.
+fn label_matching<P: Indexable<Node>>(matching: &P, vertex_id_label_map: &HashMap<Node, Label>, edge_label: &[Vec<(u32, u32, u32)>], graph_map: &[Graph], node_id: usize) -> Result<Vec<Label>, String> {
     let mut labels = Vec::new();
     let graph = &graph_map[node_id];
 
     //add vertex label
     for i in 0..matching.length() {
-        labels.push(vertex_id_label_map[&matching.index(i)])
+        match vertex_id_label_map.get(&matching.index(i)) {
+            Some(&label) => labels.push(label),
+            None => return Err(format!("vertex {} has no label", matching.index(i))),
+        }
     }
 
     for src in 0..graph.adj_list.len() {
         for dst_idx in 0..graph.adj_list[src].len() {
             //find which vector
             let dst = graph.adj_list[src][dst_idx];
-            let vec_idx = advance(&edge_label.read().unwrap()[..], |x| &x[0].0 < &matching.index(src) || (&x[0].0 == &matching.index(src) && &x[0].1 <= &matching.index(dst as usize))) - 1;
-            let start_idx = advance(&edge_label.read().unwrap()[vec_idx][..], |x| &x.0 < &matching.index(src));
-            let end_idx = advance(&edge_label.read().unwrap()[vec_idx][start_idx..], |x| &x.0 == &matching.index(src)) + start_idx;
-            let idx = advance(&edge_label.read().unwrap()[vec_idx][start_idx..end_idx], |x| &x.1 < &matching.index(dst as usize));
-            labels.push(edge_label.read().unwrap()[vec_idx][idx + start_idx].2);
+            let (src_node, dst_node) = (matching.index(src), matching.index(dst as usize));
+            let missing = || format!("edge ({}, {}) has no label", src_node, dst_node);
+            let vec_idx = advance(edge_label, |x| &x[0].0 < &src_node || (&x[0].0 == &src_node && &x[0].1 <= &dst_node));
+            if vec_idx == 0 { return Err(missing()); }
+            let vec_idx = vec_idx - 1;
+            let idx = advance(&edge_label[vec_idx], |x| (&x.0, &x.1) < (&src_node, &dst_node));
+            match edge_label[vec_idx].get(idx) {
+                Some(&(s, d, label)) if s == src_node && d == dst_node => labels.push(label),
+                _ => return Err(missing()),
+            }
         }
     }
     //add edge label according to (src, dst) order
-    labels
+    Ok(labels)
 }
 
@@ -18,10 +18,10 @@ use std::path::Path;
 use super::Indexable;
 
 use super::graph_stream::GraphStreamIndex;
+use faults::ErrorEvents;
 use wings_plan::ExtendEdges;
 
-pub type Node = u32;
-pub type Edge = (Node, Node);
+pub use ::{Node, Edge};
 type Label = u32;
 
 #[derive(Debug, Default, Clone)]
@@ -60,10 +60,24 @@ pub struct VertexLabeledPlan{
     edges: Vec<PlanEdge>,
     nodes: Vec<Rc<PlanNode>>,
     root_node_id: usize,
-    node_graph_map:  Vec<Graph>
+    node_graph_map:  Vec<Graph>,
+    errors: ErrorEvents,
 }
 
 impl VertexLabeledPlan{
+    /// Sets where the plan's operators report the errors they continue past; see `faults`.
+    ///
+    /// Matches whose labels can not be found are reported and left uncounted, and poisoned
+    /// counters are recovered and reported, rather than failing every later batch.
+    pub fn set_error_events(&mut self, errors: ErrorEvents) {
+        self.errors = errors;
+    }
+
+    /// Where the plan's operators report the errors they continue past.
+    pub fn error_events(&self) -> &ErrorEvents {
+        &self.errors
+    }
+
     pub fn track_motif<H1, H2, G: Scope>(&self, graph: &GraphStreamIndex<G, H1, H2>, probe: &mut ProbeHandle<G::Timestamp>, counter: Arc<Mutex<u64>>, labeled_counters: Arc<RwLock<HashMap<(usize,Vec<u32>),Mutex<u64>>>>, vertex_id_label_map: Arc<HashMap<u32, u32>>)
         where H1: Fn(Node)->u64 + 'static,
               H2: Fn(Node)->u64 + 'static
@@ -90,6 +104,8 @@ impl VertexLabeledPlan{
             let labeled_counters = labeled_counters.clone();
             let vertex_id_label_map1 = vertex_id_label_map.clone();
             let vertex_id_label_map2 = vertex_id_label_map.clone();
+            let errors1 = self.errors.clone();
+            let errors2 = self.errors.clone();
 
             let plan_edge = &self.edges[index];
             let intersect_attributes = plan_edge.get_intersect_attributes();
@@ -123,23 +139,27 @@ impl VertexLabeledPlan{
                     .inspect_batch(move |_,xs| {
                         let mut batch_query_count = HashMap::new();
                         for x in xs.iter(){
-                            let labeled_query = label_matching(&x.0, vertex_id_label_map2.clone());
-                            let counter =  batch_query_count.entry((child.idx, labeled_query)).or_insert(0 as u64);
-                            *counter += 1;
+                            match label_matching(&x.0, &vertex_id_label_map2) {
+                                Ok(labeled_query) => {
+                                    let counter =  batch_query_count.entry((child.idx, labeled_query)).or_insert(0 as u64);
+                                    *counter += 1;
+                                },
+                                Err(error) => errors1.report("LabeledCount", format!("left {:?} uncounted: {}", x.0, error)),
+                            }
                         }
 
                         for (query, count) in batch_query_count.into_iter() {
-                            let counters = labeled_counters.read().expect("Mutex poisoned");
+                            let counters = errors1.read("LabeledCount", &*labeled_counters);
 
                             if let Some(counter) = counters.get(&query) {
-                                let mut counter = counter.lock().expect("Mutex poisoned");
+                                let mut counter = errors1.lock("LabeledCount", counter);
                                 *counter += count;
                                 println!("Labeled {:?}: {}", query, *counter);
                                 continue;
                             }
 
                             drop(counters);
-                            let mut counters = labeled_counters.write().expect("RwLock poisoned");
+                            let mut counters = errors1.write("LabeledCount", &*labeled_counters);
                             println!("Labeled {:?}: {}", query, count);
                             counters.entry(query).or_insert_with(||Mutex::new(count));
 
@@ -149,9 +169,7 @@ impl VertexLabeledPlan{
                     .count()
                     .inspect_batch(move |t,x| println!("{:?}: {:?}", t, x))
                     .inspect_batch(move |_,x| {
-                        if let Ok(mut bound) = counter1.lock() {
-                            *bound += x[0] as u64;
-                        }
+                        *errors2.lock("Count", &*counter1) += x[0] as u64;
                         //Find count for each labeled query
 
                     });
@@ -245,6 +263,7 @@ impl PlanEdge{
 }
 
 
+/// Reads a vertex-labeled plan from the file `filename`.
 pub fn read_plan(filename:&str) -> VertexLabeledPlan{
     let path = Path::new(filename);
     let display = path.display();
@@ -258,7 +277,11 @@ pub fn read_plan(filename:&str) -> VertexLabeledPlan{
         Ok(file) => file,
     };
 
-    let mut reader = BufReader::new(file);
+    read_plan_from(BufReader::new(file))
+}
+
+/// Reads a vertex-labeled plan in the plan file format from `reader`.
+pub fn read_plan_from<R: BufRead>(mut reader: R) -> VertexLabeledPlan{
     let mut plan:VertexLabeledPlan = Default::default();
 
     let mut line = String::new();
@@ -325,12 +348,16 @@ pub fn read_plan(filename:&str) -> VertexLabeledPlan{
     plan
 }
 
-fn label_matching<P: Indexable<Node>>(matching: &P, vertex_id_label_map: Arc<HashMap<Node, Label>>) -> Vec<Label> {
+// the labels of the vertices of `matching`, or an error naming a vertex without a label.
+fn label_matching<P: Indexable<Node>>(matching: &P, vertex_id_label_map: &HashMap<Node, Label>) -> Result<Vec<Label>, String> {
     let mut labels = Vec::new();
 
     for i in 0..matching.length() {
-        labels.push(vertex_id_label_map[&matching.index(i)])
+        match vertex_id_label_map.get(&matching.index(i)) {
+            Some(&label) => labels.push(label),
+            None => return Err(format!("vertex {} has no label", matching.index(i))),
+        }
     }
 
-    labels
+    Ok(labels)
 }
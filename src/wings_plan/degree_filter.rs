@@ -0,0 +1,164 @@
+//! A filter retaining edges between vertices of at least a minimum degree.
+//!
+//! The degree of a vertex counts its in- and out-edges in the unfiltered graph, with multiplicity.
+//! An edge passes the filter while both of its endpoints have degree at least `k`; as degrees cross
+//! the threshold, the edges of the vertex are retracted or reintroduced, so that the output always
+//! describes the filtered graph. This is one round of the peeling that computes a k-core: edges
+//! removed by the filter do not in turn lower the degrees of other vertices.
+//!
+//! The filter works in two stages. The first exchanges each edge to both of its endpoints, whose
+//! owners track the vertex's degree and report the edge's multiplicity while the vertex is above
+//! the threshold. The second exchanges these reports by edge, and passes the edge while both of
+//! its endpoints report it. Each stage processes times in order, once they are complete.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Concat, Map, Filter, Operator};
+use timely::dataflow::channels::pact::Exchange;
+
+use super::{Node, Edge};
+
+/// Filters the initial edges and updates of a graph to edges between vertices of degree at least `k`.
+///
+/// Returns the filtered initial edges and the filtered updates. Initial edges must be introduced
+/// at times before any updates, as is the case when they are loaded in a separate epoch.
+pub fn degree_filter<G: Scope>(initially: &Stream<G, Edge>, updates: &Stream<G, (Edge, i32)>, k: usize) -> (Stream<G, Edge>, Stream<G, (Edge, i32)>)
+    where G::Timestamp: Hash {
+
+    // (edge, diff, initial), for both initial edges and updates.
+    let changes = initially.map(|edge| (edge, 1, true)).concat(&updates.map(|(edge, diff)| (edge, diff, false)));
+
+    // each edge is reported to both endpoints, as (vertex, is_dst, edge, diff, initial).
+    let halves = changes.flat_map(|(edge, diff, initial)| {
+        vec![(edge.0, false, edge, diff, initial), (edge.1, true, edge, diff, initial)].into_iter()
+    });
+
+    let mut stash = HashMap::new();
+    let mut vertices: HashMap<Node, (i64, HashMap<(bool, Edge), i32>)> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let reports = halves.unary_notify(Exchange::new(|x: &(Node, bool, Edge, i32, bool)| x.0 as u64), "DegreeVertices", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+            changes.sort();
+            let mut session = output.session(&time);
+
+            let mut index = 0;
+            while index < changes.len() {
+                let vertex = changes[index].0;
+                let empty = {
+                    let state = vertices.entry(vertex).or_insert((0, HashMap::new()));
+                    let active_old = state.0 >= k as i64;
+
+                    // apply this vertex's changes, noting each affected edge and its old multiplicity.
+                    let mut affected = Vec::new();
+                    let mut initial = true;
+                    while index < changes.len() && changes[index].0 == vertex {
+                        let (_, is_dst, edge, diff, init) = changes[index];
+                        let count = state.1.entry((is_dst, edge)).or_insert(0);
+                        if affected.last().map(|x: &((bool, Edge), i32)| x.0 != (is_dst, edge)).unwrap_or(true) {
+                            affected.push(((is_dst, edge), *count));
+                        }
+                        *count += diff;
+                        state.0 += diff as i64;
+                        initial = initial && init;
+                        index += 1;
+                    }
+
+                    let active_new = state.0 >= k as i64;
+
+                    if active_old != active_new {
+                        // every edge of the vertex changes; report the difference for each.
+                        let old = affected.into_iter().collect::<HashMap<_,_>>();
+                        for (&key, &count) in state.1.iter() {
+                            let before = if active_old { *old.get(&key).unwrap_or(&count) } else { 0 };
+                            let after = if active_new { count } else { 0 };
+                            if before != after { session.give((key.1, key.0, after - before, initial)); }
+                        }
+                    }
+                    else if active_new {
+                        for &((is_dst, edge), count) in affected.iter() {
+                            let after = state.1[&(is_dst, edge)];
+                            if after != count { session.give((edge, is_dst, after - count, initial)); }
+                        }
+                    }
+
+                    state.1.retain(|_, count| *count != 0);
+                    state.1.is_empty()
+                };
+                if empty { vertices.remove(&vertex); }
+            }
+        }
+    });
+
+    let mut stash = HashMap::new();
+    let mut edges: HashMap<Edge, (i32, i32, i32)> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    // pass each edge while both endpoints report it, as (edge, diff, initial).
+    let filtered = reports.unary_notify(Exchange::new(|x: &(Edge, bool, i32, bool)| ((x.0).0 as u64) << 32 | (x.0).1 as u64), "DegreeEdges", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut reports = stash.remove(time.time()).unwrap_or(Vec::new());
+            reports.sort();
+            let mut session = output.session(&time);
+
+            let mut index = 0;
+            while index < reports.len() {
+                let edge = reports[index].0;
+                let empty = {
+                    let mut initial = true;
+                    let state = edges.entry(edge).or_insert((0, 0, 0));
+                    while index < reports.len() && reports[index].0 == edge {
+                        let (_, is_dst, diff, init) = reports[index];
+                        if is_dst { state.1 += diff; } else { state.0 += diff; }
+                        initial = initial && init;
+                        index += 1;
+                    }
+                    let passed = if state.0 > 0 && state.1 > 0 { ::std::cmp::min(state.0, state.1) } else { 0 };
+                    if passed != state.2 {
+                        session.give((edge, passed - state.2, initial));
+                        state.2 = passed;
+                    }
+                    *state == (0, 0, 0)
+                };
+                if empty { edges.remove(&edge); }
+            }
+        }
+    });
+
+    let initially = filtered
+        .filter(|x| x.2)
+        .flat_map(|(edge, diff, _)| {
+            assert!(diff > 0, "retraction of an initial edge");
+            (0 .. diff).map(move |_| edge)
+        });
+    let updates = filtered
+        .filter(|x| !x.2)
+        .map(|(edge, diff, _)| (edge, diff));
+
+    (initially, updates)
+}
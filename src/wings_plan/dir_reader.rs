@@ -7,6 +7,8 @@ use std::collections::HashMap;
 
 use std::{fs, io};
 
+use ::io::parse_change;
+
 pub struct DirReader {
     reader: BufReader<File>,
     paths: IntoIter<PathBuf>,
@@ -54,8 +56,7 @@ impl DirReader {
             }
             if !discard && !line.starts_with('#') && line.len() > 0 {
                 let elts: Vec<&str> = line[..].split_whitespace().collect();
-                let src: u32 = elts[0].parse().ok().expect("malformed src");
-                let dst: u32 = elts[1].parse().ok().expect("malformed dst");
+                let ((src, dst), _, _) = parse_change(&line).unwrap_or_else(|reason| panic!("{}: {:?}", reason, line.trim()));
                 edges.push((src, dst));
                 edge_index += 1;
             }
@@ -65,6 +66,33 @@ impl DirReader {
         edges
     }
 
+    /// Reads up to `num_updates` signed updates from change log lines (`+ src dst` or `- src dst`).
+    pub fn read_changes(&mut self, num_updates: usize) -> Vec<((u32, u32), i32)> {
+        let mut updates = Vec::new();
+
+        while updates.len() < num_updates {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).unwrap() == 0 {
+                let path: PathBuf;
+                if let Some(p) = self.paths.next() {
+                    path = p;
+                }else {
+                    return updates;
+                }
+
+                let file = File::open(path).unwrap();
+                self.reader = BufReader::new(file);
+                continue;
+            }
+            if !line.starts_with('#') && line.trim().len() > 0 {
+                let (edge, diff, _time) = parse_change(&line).unwrap_or_else(|reason| panic!("{}: {:?}", reason, line.trim()));
+                updates.push((edge, diff));
+            }
+        }
+
+        updates
+    }
+
     pub fn read_vertex_labels(&mut self) -> HashMap<u32, u32> {
         let mut vertex_label_map = HashMap::new();
 
@@ -83,8 +111,8 @@ impl DirReader {
             }
             if line.len() > 0 {
                 let elts: Vec<&str> = line[..].split_whitespace().collect();
-                let node: u32 = elts[0].parse().ok().expect("malformed node");
-                let label: u32 = elts[1].parse().ok().expect("malformed label");
+                let node: u32 = elts.get(0).and_then(|x| x.parse().ok()).unwrap_or_else(|| panic!("malformed node: {:?}", line.trim()));
+                let label: u32 = elts.get(1).and_then(|x| x.parse().ok()).unwrap_or_else(|| panic!("malformed label: {:?}", line.trim()));
                 vertex_label_map.insert(node, label);
             }
         }
@@ -0,0 +1,79 @@
+//! Set-difference semantics for changes to matches.
+//!
+//! Plans attribute each new match to exactly one of the updates that created it, and report the
+//! changes to matches with multiplicities: an embedding appears once for each way its edges are
+//! present, and the automorphisms of a pattern produce several embeddings of one set of vertices.
+//! Some users instead want the matches present in each epoch's graph as a set, and the changes
+//! between consecutive epochs as a set difference: `+1` for each match that appears, and `-1` for
+//! each that disappears.
+//!
+//! `distinct_matches` computes these differences from the attributed changes, by accumulating the
+//! multiplicity of each match and reporting when it becomes or stops being positive. It stores
+//! every present match, so it is slower and uses more memory than the attributed changes, but it
+//! does not depend on how changes are attributed, which makes it useful for validation.
+
+use std::collections::HashMap;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::channels::pact::Exchange;
+
+use super::Node;
+use ::Weight;
+
+/// Reports the changes to the set of matches present at each time.
+///
+/// With `unordered` set, matches are identified by their sets of vertices, so that the embeddings
+/// of one set of vertices under a pattern's automorphisms count once; otherwise each embedding is
+/// a distinct match.
+pub fn distinct_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, unordered: bool) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut present: HashMap<Vec<Node>, i64> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let keyed = if unordered {
+        matches.map_in_place(|x| x.0.sort())
+    } else {
+        matches.clone()
+    };
+
+    let exchange = Exchange::new(|x: &(Vec<Node>, W)| x.0.iter().fold(0u64, |h, &v| h.wrapping_mul(0x9E3779B97F4A7C15) ^ v as u64));
+    keyed.unary_notify(exchange, "DistinctMatches", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+            changes.sort();
+            let mut session = output.session(&time);
+            let mut index = 0;
+            while index < changes.len() {
+                let mut diff = 0i64;
+                let start = index;
+                while index < changes.len() && changes[index].0 == changes[start].0 {
+                    diff += changes[index].1.to_i64();
+                    index += 1;
+                }
+                if diff != 0 {
+                    let embedding = changes[start].0.clone();
+                    let before = *present.get(&embedding).unwrap_or(&0);
+                    let after = before + diff;
+                    if before <= 0 && after > 0 { session.give((embedding.clone(), W::from(1))); }
+                    if before > 0 && after <= 0 { session.give((embedding.clone(), W::from(-1))); }
+                    if after == 0 { present.remove(&embedding); }
+                    else { present.insert(embedding, after); }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,225 @@
+//! Plans as documents of plain data, which serde reads and writes as JSON.
+//!
+//! The plan file format numbers everything by position and encodes each operation by the keys
+//! of the indices it probes, which other programs can only write by knowing how `read_plan`
+//! decodes them. A `PlanDocument` describes a plan as `PlanBuilder` does instead: its nodes, in
+//! order of their indices and starting with the root, whether each is a query node and the
+//! vertices it pins to constants or parameters, and its edges, each with the pattern edges it
+//! reads as extensions or intersections, their hints, directions, limits, and labels, whether it
+//! always proposes from its first extension, and the label of the updates it reads if it leaves
+//! the root. `Plan::to_document` describes a plan, and `PlanDocument::build` checks a document
+//! and builds its plan, as `PlanBuilder::build` does.
+//!
+//! Built with `--features json`, documents are read and written as JSON by `Plan::to_json` and
+//! `Plan::from_json`. The triangle plan, whose first edge binds `x2` from the update `x0->x1`:
+//!
+//! ```text
+//! {
+//!   "nodes": [{ "query": false }, { "query": true }],
+//!   "edges": [{
+//!     "from": 0, "to": 1,
+//!     "operations": [
+//!       { "src": 0, "dst": 2, "kind": "extension" },
+//!       { "src": 1, "dst": 2, "kind": "extension", "hint": "intersect" }
+//!     ]
+//!   }]
+//! }
+//! ```
+//!
+//! An operation may carry a `hint`, `extend` or `intersect`, and a `direction`, `forward`,
+//! `reverse`, or `adaptive`, an extension a `limit`, as `{ "k": 3, "order": "weight" }`, and a
+//! `label` its vertex must have, an `edge_label` its pattern edge must have, and the `source` it
+//! reads its pattern edge from, and set `anti` if its pattern edge must be absent; an edge may set
+//! `propose_first` or `hash_join` and an `update_label`, and list `filters`, as
+//! `[{ "vertex": 2, "attribute": 3, "comparison": "greater", "value": 30.0 }]`; and a node may list
+//! `constants`, as `[[2, 42]]` to pin `x2` to vertex 42, and `parameters`, as `[[2, 0]]` to pin
+//! `x2` to the first parameter slot, set `negated` to negate its matches, and give a `projection`,
+//! as `[0]` to report only `x0`, or an `aggregation`, as
+//! `{ "group": 0, "key": [{ "vertex": 0 }], "aggregator": "count" }` to count its matches by `x0`.
+//! Settings made on a plan once it is built, such as its semantics or placement, describe how it
+//! runs rather than what it matches, and are not part of its document.
+
+use wings_plan::{ExtensionLimit, IntersectDirection, JoinHint};
+use wings_plan::filters::AttributeFilter;
+use wings_plan::aggregation::Aggregation;
+use wings_plan::plan::{NodeId, Plan, Vertex};
+use ::Node;
+
+/// The nodes and edges of a plan.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct PlanDocument {
+    /// The nodes of the plan, by index; the first is the root.
+    pub nodes: Vec<NodeDocument>,
+    /// The edges of the plan.
+    pub edges: Vec<EdgeDocument>,
+}
+
+/// A node of a plan.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct NodeDocument {
+    /// Whether the node's matches are reported.
+    pub query: bool,
+    /// The vertices the node pins, as positions and their constants; see `Plan::set_constant`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub constants: Vec<(usize, Node)>,
+    /// The vertices the node pins to parameters, as positions and their slots; see `Plan::set_parameter`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub parameters: Vec<(usize, usize)>,
+    /// Whether the node's matches are reported with their weights negated; see `Plan::set_negated`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "::std::ops::Not::not"))]
+    pub negated: bool,
+    /// The positions of its matches the node reports, if it projects them; see `Plan::set_projection`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub projection: Option<Vec<usize>>,
+    /// The aggregate the node contributes its matches to, if any; see `Plan::set_aggregation`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub aggregation: Option<Aggregation>,
+}
+
+/// An edge of a plan, from one node to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct EdgeDocument {
+    /// The index of the node whose prefixes the edge extends.
+    pub from: usize,
+    /// The index of the node whose prefixes the edge produces.
+    pub to: usize,
+    /// Whether the edge always proposes from its first extension; see `PlanBuilder::propose_first`.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub propose_first: bool,
+    /// Whether the edge proposes from its first extension with a hash join; see
+    /// `PlanBuilder::hash_join`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "::std::ops::Not::not"))]
+    pub hash_join: bool,
+    /// The label updates must have for the edge to read them, if it leaves the root; see
+    /// `Plan::set_update_label`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub update_label: Option<u32>,
+    /// The filters of the vertices of the prefixes the edge produces; see `Plan::set_vertex_filters`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub filters: Vec<AttributeFilter>,
+    /// The pattern edges the edge reads, in order.
+    pub operations: Vec<OperationDocument>,
+}
+
+/// A pattern edge a plan edge reads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct OperationDocument {
+    /// The source of the pattern edge, by its position in prefixes.
+    pub src: usize,
+    /// The destination of the pattern edge, by its position in prefixes.
+    pub dst: usize,
+    /// Whether the pattern edge binds the vertex its plan edge binds, or is between bound ones.
+    pub kind: OperationKind,
+    /// How the operation is handled, if not as its kind says; see `Plan::set_join_hint`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub hint: Option<JoinHint>,
+    /// The indices that check the operation; see `Plan::set_operation_direction`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub direction: Option<IntersectDirection>,
+    /// The neighbors the operation admits; see `Plan::set_extension_limit`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub limit: Option<ExtensionLimit>,
+    /// Whether the pattern edge must be absent rather than present; see `PlanBuilder::anti`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "::std::ops::Not::not"))]
+    pub anti: bool,
+    /// The label the vertex the operation binds must have; see `Plan::set_operation_label`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub label: Option<u32>,
+    /// The label the pattern edge must have; see `Plan::set_edge_label`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub edge_label: Option<u32>,
+    /// The source the pattern edge is read from; see `Plan::set_operation_source`.
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub source: Option<u32>,
+}
+
+/// Whether an operation binds a vertex, as `PlanBuilder::extension` and `intersection` distinguish.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum OperationKind {
+    /// The pattern edge joins a vertex bound earlier to the vertex its plan edge binds.
+    Extension,
+    /// The pattern edge joins two vertices bound earlier.
+    Intersection,
+}
+
+impl PlanDocument {
+    /// Builds the plan the document describes, or returns an error describing the first problem
+    /// with it, as `PlanBuilder::build` does.
+    pub fn build(&self) -> Result<Plan, String> {
+        match self.nodes.first() {
+            None => return Err("the plan has no root node".to_owned()),
+            Some(root) if root.query => return Err("the root node is a query node".to_owned()),
+            Some(_) => { },
+        }
+        let mut builder = Plan::builder();
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            builder = builder.add_node(NodeId(index), node.query);
+        }
+        for (index, node) in self.nodes.iter().enumerate() {
+            for &(position, value) in node.constants.iter() {
+                builder = builder.constant(NodeId(index), Vertex(position), value);
+            }
+            for &(position, slot) in node.parameters.iter() {
+                builder = builder.parameter(NodeId(index), Vertex(position), slot);
+            }
+            if node.negated { builder = builder.negated(NodeId(index)); }
+            if let Some(ref positions) = node.projection {
+                builder = builder.project(NodeId(index), &positions.iter().map(|&position| Vertex(position)).collect::<Vec<_>>());
+            }
+            if let Some(ref aggregation) = node.aggregation { builder = builder.aggregate(NodeId(index), aggregation.clone()); }
+        }
+        for edge in self.edges.iter() {
+            builder = builder.add_edge(NodeId(edge.from), NodeId(edge.to));
+            for operation in edge.operations.iter() {
+                let (src, dst) = (Vertex(operation.src), Vertex(operation.dst));
+                builder = match operation.kind {
+                    OperationKind::Extension => builder.extension(src, dst),
+                    OperationKind::Intersection => builder.intersection(src, dst),
+                };
+                if let Some(hint) = operation.hint { builder = builder.hint(hint); }
+                if let Some(direction) = operation.direction { builder = builder.direction(direction); }
+                if let Some(limit) = operation.limit { builder = builder.limit(limit); }
+                if let Some(label) = operation.label { builder = builder.label(label); }
+                if let Some(label) = operation.edge_label { builder = builder.edge_label(label); }
+                if let Some(source) = operation.source { builder = builder.source(source); }
+                if operation.anti { builder = builder.anti(); }
+            }
+            if edge.propose_first { builder = builder.propose_first(); }
+            if edge.hash_join { builder = builder.hash_join(); }
+            if let Some(label) = edge.update_label { builder = builder.update_label(label); }
+            for &filter in edge.filters.iter() { builder = builder.filter(filter); }
+        }
+        builder.build()
+    }
+
+    /// The document as pretty-printed JSON.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).expect("plan documents are always representable as JSON")
+    }
+
+    /// Reads a document from JSON, or returns an error describing why it is not one.
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> Result<PlanDocument, String> {
+        ::serde_json::from_str(text).map_err(|error| format!("malformed plan document: {}", error))
+    }
+}
+
+#[cfg(feature = "json")]
+impl Plan {
+    /// The plan's document as JSON; see `PlanDocument`.
+    pub fn to_json(&self) -> String {
+        self.to_document().to_json()
+    }
+
+    /// Reads a plan from its document as JSON, or returns an error describing the first problem
+    /// with the document or the plan it describes.
+    pub fn from_json(text: &str) -> Result<Plan, String> {
+        PlanDocument::from_json(text)?.build()
+    }
+}
@@ -0,0 +1,70 @@
+//! Whether any match exists between pairs of anchored vertices.
+//!
+//! Some consumers do not need the matches through a pair of vertices, only whether there are any:
+//! whether some triangle closes over the edge `(u, v)`, or whether two accounts are joined by any
+//! path of the pattern. `anchor_pairs` reads the vertices at two positions of each match as its
+//! anchor pair, and `pair_existence` counts the support of each pair, the multiplicity of the
+//! matches through it, reporting `+1` when a pair gains its first support and `-1` when it loses
+//! its last, so that the changes at each time are those to the set of supported pairs.
+//!
+//! Query nodes list the vertices of their matches in different orders, so the anchors of each
+//! query node are read at its own positions (see `ParsedQuery::positions`), and the pairs of all
+//! query nodes are concatenated before their existence is maintained. Supports start at zero, so
+//! the existence of pairs reflects the changes to matches the plan reported; the matches of a base
+//! graph loaded without reporting them are not counted.
+
+use std::collections::HashMap;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::channels::pact::Exchange;
+
+use super::{Node, Edge};
+use ::Weight;
+
+/// The anchor pair of each change to a match: its vertices at `positions`, with its diff.
+pub fn anchor_pairs<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, positions: (usize, usize)) -> Stream<G, (Edge, W)> {
+    matches.map(move |(embedding, diff)| ((embedding[positions.0], embedding[positions.1]), diff))
+}
+
+/// Reports the changes to the set of anchor pairs supported by some match at each time.
+///
+/// Each pair is counted at one worker, which stores the support of every supported pair.
+pub fn pair_existence<G: Scope, W: Weight>(pairs: &Stream<G, (Edge, W)>) -> Stream<G, (Edge, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut support: HashMap<Edge, i64> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let exchange = Exchange::new(|x: &(Edge, W)| ((x.0).0 as u64).wrapping_mul(0x9E3779B97F4A7C15) ^ (x.0).1 as u64);
+    pairs.unary_notify(exchange, "PairExistence", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let changes = stash.entry(time.time().clone()).or_insert(HashMap::new());
+            for (pair, diff) in buffer.drain(..) {
+                *changes.entry(pair).or_insert(0i64) += diff.to_i64();
+            }
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(HashMap::new()).into_iter().filter(|x| x.1 != 0).collect::<Vec<_>>();
+            changes.sort();
+            let mut session = output.session(&time);
+            for (pair, diff) in changes {
+                let before = *support.get(&pair).unwrap_or(&0);
+                let after = before + diff;
+                if before <= 0 && after > 0 { session.give((pair, W::from(1))); }
+                if before > 0 && after <= 0 { session.give((pair, W::from(-1))); }
+                if after == 0 { support.remove(&pair); }
+                else { support.insert(pair, after); }
+            }
+        }
+    })
+}
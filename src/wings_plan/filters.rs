@@ -0,0 +1,149 @@
+//! Predicates on numeric attributes of pattern vertices, such as `x1.age > 30`.
+//!
+//! Property graphs describe vertices by more than a label: a person's age, an account's balance.
+//! A plan edge may carry filters (see `Plan::set_vertex_filters`), each comparing an attribute of
+//! a vertex its prefixes bind with a constant. Filters of the vertex the edge binds remove the
+//! candidates its extensions propose before they become prefixes, and filters of vertices bound
+//! earlier remove the prefixes the edge reads, so that a selective filter prunes prefixes before
+//! they are extended further rather than matches after.
+//!
+//! Attributes are numbered, as labels are, and their values are read from the `VertexAttributes`
+//! given to `GraphStreamIndex::set_vertex_attributes`, which every worker holds whole. Values do
+//! not change as the graph does. A vertex without a value for an attribute satisfies no comparison
+//! of it, not even `!=`.
+//!
+//! In plan files, a filter is written `xV.A` followed by a comparison and a number, as
+//! `x2.3>=30` compares attribute 3 of `x2` with 30; see `AttributeFilter::parse`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use ::Node;
+
+/// The values of the numeric attributes of vertices, by attribute and vertex.
+#[derive(Clone, Debug, Default)]
+pub struct VertexAttributes {
+    values: HashMap<(u32, Node), f64>,
+}
+
+impl VertexAttributes {
+    /// Allocates a map with no values.
+    pub fn new() -> Self { VertexAttributes::default() }
+
+    /// Sets attribute `attribute` of `node` to `value`.
+    pub fn set(&mut self, attribute: u32, node: Node, value: f64) {
+        self.values.insert((attribute, node), value);
+    }
+
+    /// The value of attribute `attribute` of `node`, if it has one.
+    pub fn get(&self, attribute: u32, node: Node) -> Option<f64> {
+        self.values.get(&(attribute, node)).cloned()
+    }
+
+    /// The number of values held, over all attributes.
+    pub fn len(&self) -> usize { self.values.len() }
+}
+
+/// How an `AttributeFilter` compares a vertex's value with its constant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum Comparison {
+    /// The value is less than the constant.
+    Less,
+    /// The value is at most the constant.
+    LessEqual,
+    /// The value equals the constant.
+    Equal,
+    /// The value differs from the constant.
+    NotEqual,
+    /// The value is at least the constant.
+    GreaterEqual,
+    /// The value is greater than the constant.
+    Greater,
+}
+
+impl Comparison {
+    /// The comparison as written in plan files: `<`, `<=`, `=`, `!=`, `>=`, or `>`.
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+
+    /// Whether `value` compares so with `constant`.
+    pub fn holds(&self, value: f64, constant: f64) -> bool {
+        match *self {
+            Comparison::Less => value < constant,
+            Comparison::LessEqual => value <= constant,
+            Comparison::Equal => value == constant,
+            Comparison::NotEqual => value != constant,
+            Comparison::GreaterEqual => value >= constant,
+            Comparison::Greater => value > constant,
+        }
+    }
+}
+
+/// A comparison of an attribute of the vertex at a position of prefixes with a constant.
+///
+/// Constants are numbers other than NaN, which compares with nothing, so that two filters are
+/// equal exactly when they remove the same vertices.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct AttributeFilter {
+    /// The position of the vertex in prefixes.
+    pub vertex: usize,
+    /// The attribute compared.
+    pub attribute: u32,
+    /// How the vertex's value compares with the constant.
+    pub comparison: Comparison,
+    /// The constant.
+    pub value: f64,
+}
+
+impl AttributeFilter {
+    /// Whether `node`, at the filter's position, has a value of the attribute comparing as required.
+    pub fn accepts(&self, attributes: &VertexAttributes, node: Node) -> bool {
+        attributes.get(self.attribute, node).map(|value| self.comparison.holds(value, self.value)).unwrap_or(false)
+    }
+
+    /// Reads a filter as plan files write it, e.g. `x2.3>=30`, or returns an error describing why not.
+    pub fn parse(text: &str) -> Result<AttributeFilter, String> {
+        let malformed = || format!("malformed filter {:?}, expected xV.A followed by <, <=, =, !=, >=, or >, and a number", text);
+        if !text.starts_with('x') { return Err(malformed()); }
+        let dot = text.find('.').ok_or_else(&malformed)?;
+        let vertex = text[1 .. dot].parse().map_err(|_| malformed())?;
+        let rest = &text[dot + 1 ..];
+        let split = rest.find(|c: char| c == '<' || c == '>' || c == '=' || c == '!').ok_or_else(&malformed)?;
+        let attribute = rest[.. split].parse().map_err(|_| malformed())?;
+        let rest = &rest[split ..];
+        // two-character comparisons first, so that `<=` is not read as `<` and a number starting with `=`.
+        let comparison = [Comparison::LessEqual, Comparison::NotEqual, Comparison::GreaterEqual, Comparison::Less, Comparison::Equal, Comparison::Greater]
+            .iter().cloned().find(|comparison| rest.starts_with(comparison.symbol())).ok_or_else(&malformed)?;
+        let value: f64 = rest[comparison.symbol().len() ..].parse().map_err(|_| malformed())?;
+        if value.is_nan() { return Err(malformed()); }
+        Ok(AttributeFilter { vertex, attribute, comparison, value })
+    }
+}
+
+impl fmt::Display for AttributeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x{}.{}{}{}", self.vertex, self.attribute, self.comparison.symbol(), self.value)
+    }
+}
+
+// constants are never NaN, so equality is reflexive.
+impl Eq for AttributeFilter { }
+
+impl Hash for AttributeFilter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.vertex, self.attribute, self.comparison).hash(state);
+        // adding zero turns -0.0, which equals 0.0, into 0.0, so that equal constants hash alike.
+        (self.value + 0.0).to_bits().hash(state);
+    }
+}
@@ -1,25 +1,216 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 use timely::dataflow::*;
 use timely::dataflow::operators::*;
 
 use super::{Index,IndexStream};
+use super::filters::VertexAttributes;
+use wings_rule::{Breaker, IndexRegistry, IntersectKernel, KernelStats, Maintenance, Materializer, MergePolicy, MergeStats, ReadStats, read_index, write_index};
 
-pub type Node = u32;
-pub type Edge = (Node, Node);
+pub use ::{Node, Edge, LabeledEdge};
 
 /// Handles to the forward and reverse graph indices.
+///
+/// A handle may also cover copies of the indices, added with `add_copy`. Reads consult the first
+/// copy, which holds the same graph as the others, while maintenance applies to every copy, and
+/// to the indices of the edges with each label, if the graph is labeled.
 pub struct GraphStreamIndexHandle<T> {
     forward: Rc<RefCell<Index<Node,T>>>,
     reverse: Rc<RefCell<Index<Node,T>>>,
+    copies: Vec<(Rc<RefCell<Index<Node,T>>>, Rc<RefCell<Index<Node,T>>>)>,
+    labeled: Vec<GraphStreamIndexHandle<T>>,
+}
+
+impl<T> Clone for GraphStreamIndexHandle<T> {
+    fn clone(&self) -> Self {
+        GraphStreamIndexHandle { forward: self.forward.clone(), reverse: self.reverse.clone(), copies: self.copies.clone(), labeled: self.labeled.clone() }
+    }
 }
 
 impl<T: Ord+Clone+::std::fmt::Debug> GraphStreamIndexHandle<T> {
+    /// Adds the indices of `other`, a copy of the same graph, to those the handle maintains.
+    pub fn add_copy(&mut self, other: GraphStreamIndexHandle<T>) {
+        self.copies.push((other.forward, other.reverse));
+        self.copies.extend(other.copies);
+        self.labeled.extend(other.labeled);
+    }
+
+    /// The number of copies of the indices the handle covers.
+    pub fn copies(&self) -> usize {
+        1 + self.copies.len()
+    }
+
+    // every index the handle covers, forward and reverse, starting with the first copy.
+    fn indices(&self) -> Vec<&Rc<RefCell<Index<Node,T>>>> {
+        let mut indices = vec![&self.forward, &self.reverse];
+        for &(ref forward, ref reverse) in self.copies.iter() {
+            indices.push(forward);
+            indices.push(reverse);
+        }
+        for labeled in self.labeled.iter() {
+            indices.extend(labeled.indices());
+        }
+        indices
+    }
+
     /// Merges both handles up to the specified time, compacting their representations.
     pub fn merge_to(&self, time: &T) {
-        self.forward.borrow_mut().merge_to(time);
-        self.reverse.borrow_mut().merge_to(time);
+        for index in self.indices() { write_index(index).merge_to(time); }
+    }
+
+    /// Merges both handles at times strictly before `frontier`.
+    pub fn merge_before(&self, frontier: &T) {
+        for index in self.indices() { write_index(index).merge_before(frontier); }
+    }
+
+    /// Registers every index the handle covers with `registry`, as `name.forward[i]` and
+    /// `name.reverse[i]` for the `i`th pair of indices: the copies, then the labeled indices.
+    pub fn register(&self, registry: &IndexRegistry<Node, T>, name: &str) where T: 'static {
+        for (position, index) in self.indices().into_iter().enumerate() {
+            let direction = if position % 2 == 0 { "forward" } else { "reverse" };
+            registry.register_index(&format!("{}.{}[{}]", name, direction, position / 2), index.clone());
+        }
+    }
+
+    /// The out-neighbors and in-neighbors of `node` with their multiplicities, as held at this worker.
+    ///
+    /// Only the worker owning `node` under the indices' hash functions holds its neighbors.
+    pub fn adjacency(&self, node: Node) -> (Vec<(Node, i32)>, Vec<(Node, i32)>) {
+        (read_index(&self.forward).values(&node), read_index(&self.reverse).values(&node))
+    }
+
+    /// Snapshots of the forward and reverse indices, as `(src, dst)` and `(dst, src)` pairs.
+    ///
+    /// The indices must have been merged to the last complete time.
+    pub fn snapshot(&self) -> (Vec<Edge>, Vec<Edge>) {
+        (read_index(&self.forward).snapshot(), read_index(&self.reverse).snapshot())
+    }
+
+    /// The number of entries held in the forward and reverse indices.
+    pub fn len(&self) -> (usize, usize) {
+        (read_index(&self.forward).len(), read_index(&self.reverse).len())
+    }
+
+    /// The number of updates held uncommitted by the forward and reverse indices, summed over copies.
+    pub fn uncommitted(&self) -> (usize, usize) {
+        let indices = self.indices();
+        let forward = indices.iter().step_by(2).map(|index| read_index(index).uncommitted()).sum();
+        let reverse = indices.iter().skip(1).step_by(2).map(|index| read_index(index).uncommitted()).sum();
+        (forward, reverse)
+    }
+
+    /// Rebuilds the compact forms of both indices from their committed contents.
+    pub fn rebuild(&self) {
+        for index in self.indices() { write_index(index).rebuild(); }
+    }
+
+    /// Starts rebuilding the compact forms of both indices on background threads.
+    pub fn rebuild_in_background(&self) {
+        for index in self.indices() { write_index(index).rebuild_in_background(); }
+    }
+
+    /// Swaps in the indices rebuilt in the background, waiting for them if `block` is set.
+    ///
+    /// Returns true if neither index is still being rebuilt.
+    pub fn finish_rebuild(&self, block: bool) -> bool {
+        let mut finished = true;
+        for index in self.indices() {
+            let mut index = write_index(index);
+            if index.is_rebuilding() { index.finish_rebuild(block); }
+            finished = finished && !index.is_rebuilding();
+        }
+        finished
+    }
+
+    /// An estimate of the bytes allocated by both indices.
+    pub fn heap_size(&self) -> usize {
+        self.indices().into_iter().map(|index| read_index(index).heap_size()).sum()
+    }
+
+    /// Rebuilds both indices if their entries exceed `factor` times the edges they hold.
+    ///
+    /// Indices being rebuilt in the background are left alone. Returns true if they were rebuilt.
+    pub fn compact(&self, factor: f64) -> bool {
+        let (forward, reverse) = self.len();
+        let edges = self.edges();
+        let rebuilding = self.indices().into_iter().any(|index| read_index(index).is_rebuilding());
+        if !rebuilding && (forward + reverse) as f64 > factor * (2 * edges) as f64 {
+            self.rebuild();
+            true
+        }
+        else { false }
+    }
+
+    /// The number of edges held by the forward index, once merged to the last complete time.
+    pub fn edges(&self) -> usize {
+        read_index(&self.forward).committed_len()
+    }
+
+    /// Sets the policy deciding when committed updates are merged in both indices.
+    pub fn set_merge_policy<P: MergePolicy+Clone+'static>(&self, policy: P) {
+        for index in self.indices() { write_index(index).set_merge_policy(policy.clone()); }
+    }
+
+    /// Sets how the indices absorb updates; see `Maintenance`.
+    pub fn set_maintenance(&self, maintenance: Maintenance) {
+        for index in self.indices() { write_index(index).set_maintenance(maintenance); }
+    }
+
+    /// Counters of the merges performed in the forward and reverse indices.
+    pub fn merge_stats(&self) -> (MergeStats, MergeStats) {
+        (read_index(&self.forward).merge_stats(), read_index(&self.reverse).merge_stats())
+    }
+
+    /// Sets the kernel both indices use to check proposals against their compacted values.
+    pub fn set_intersect_kernel(&self, kernel: IntersectKernel) {
+        for index in self.indices() { write_index(index).set_intersect_kernel(kernel); }
+    }
+
+    /// Counters of the kernels used by intersections in the forward and reverse indices.
+    pub fn kernel_stats(&self) -> (KernelStats, KernelStats) {
+        (read_index(&self.forward).kernel_stats(), read_index(&self.reverse).kernel_stats())
+    }
+
+    /// Starts or stops counting the work reads of the indices do; see `ReadStats`.
+    pub fn set_read_stats(&self, enabled: bool) {
+        for index in self.indices() { write_index(index).set_read_stats(enabled); }
+    }
+
+    /// The work reads of the forward and reverse indices have done since last taken, resetting the counters.
+    ///
+    /// Reads of every copy are counted with their index. The counters are zero if not enabled.
+    pub fn take_read_stats(&self) -> (ReadStats, ReadStats) {
+        let mut stats = (ReadStats::default(), ReadStats::default());
+        for (position, index) in self.indices().into_iter().enumerate() {
+            if let Some(reads) = write_index(index).take_read_stats() {
+                if position % 2 == 0 { stats.0.add(&reads); } else { stats.1.add(&reads); }
+            }
+        }
+        stats
+    }
+
+    /// Sets whether operators reading either index process their inputs in a reproducible order;
+    /// see `Index::set_deterministic`.
+    pub fn set_deterministic(&self, enabled: bool) {
+        for index in self.indices() { write_index(index).set_deterministic(enabled); }
+    }
+
+    /// Sets the breaker consulted by operators reading either index.
+    pub fn set_breaker(&self, breaker: Breaker) {
+        for index in self.indices() { write_index(index).set_breaker(breaker.clone()); }
+    }
+
+    /// Sets the materializer consulted by operators proposing from either index.
+    pub fn set_materializer(&self, materializer: Materializer) {
+        for index in self.indices() { write_index(index).set_materializer(materializer.clone()); }
+    }
+
+    /// Enables Bloom filters over the keys of both indices, with `bits_per_key` bits for each key.
+    pub fn enable_filter(&self, bits_per_key: usize) {
+        for index in self.indices() { write_index(index).enable_filter(bits_per_key); }
     }
 }
 
@@ -29,6 +220,24 @@ pub struct GraphStreamIndex<G: Scope, H1: Fn(Node)->u64, H2: Fn(Node)->u64>
     pub forward: IndexStream<Node, H1, G::Timestamp>,
     pub reverse: IndexStream<Node, H2, G::Timestamp>,
     pub updates: Stream<G, (Vec<Node>, i32)>,
+    /// The edge updates the indices receive, including self-loops, which `updates` omits.
+    pub changes: Stream<G, (Edge, i32)>,
+    /// Net changes `(vertex, out, in)` to the out- and in-degrees of vertices at each time.
+    ///
+    /// Changes to out-degrees come from the worker owning the vertex in the forward index, and to
+    /// in-degrees from its owner in the reverse index, once each has absorbed the time's updates.
+    /// The initial edges, or the edges of restored indices, count as changes from zero. `degrees`
+    /// combines them into the degrees themselves.
+    pub degree_changes: Stream<G, (Node, i64, i64)>,
+    /// The label of each labeled vertex, which plan operations requiring a label read; see
+    /// `set_vertex_labels`.
+    pub vertex_labels: Option<Arc<HashMap<Node, u32>>>,
+    /// The numeric attributes of vertices, which plan edges filtering their vertices read; see
+    /// `set_vertex_attributes`.
+    pub vertex_attributes: Option<Arc<VertexAttributes>>,
+    /// Indices and updates of the edges with each label, for plan operations requiring an edge
+    /// label; see `from_labeled`.
+    pub labeled: Vec<(u32, GraphStreamIndex<G, H1, H2>)>,
 }
 
 impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStreamIndex<G, H1, H2> where G::Timestamp: Ord+::std::hash::Hash {
@@ -38,15 +247,111 @@ impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStream
         let forward = IndexStream::from(hash1, &initially, &updates, true);
         let reverse = IndexStream::from(hash2, &initially.map(|(src, dst)| (dst, src)),
                                         &updates.map(|((src, dst), wgt)| ((dst, src), wgt)), false);
+        GraphStreamIndex::assemble(forward, reverse, updates)
+    }
+
+    /// Constructs a new graph stream index from existing indices and an update stream.
+    ///
+    /// This resumes from the indices of an earlier run, e.g. restored from `snapshot` with
+    /// `Index::from_snapshot`, without replaying the initial edges.
+    pub fn from_indices(forward: Index<Node, G::Timestamp>, reverse: Index<Node, G::Timestamp>,
+                        updates: Stream<G, (Edge, i32)>, hash1: H1, hash2: H2) -> (Self, GraphStreamIndexHandle<G::Timestamp>) {
+        let forward = IndexStream::from_index(hash1, forward, &updates, true);
+        let reverse = IndexStream::from_index(hash2, reverse, &updates.map(|((src, dst), wgt)| ((dst, src), wgt)), false);
+        GraphStreamIndex::assemble(forward, reverse, updates)
+    }
+
+    /// Constructs a new graph stream index from initial labeled edges and an update stream.
+    ///
+    /// The indices hold every edge, whatever its label, and alongside them indices of the edges
+    /// with each of `labels`, which operations requiring an edge label propose from and intersect
+    /// with (see `Plan::set_edge_label`), as `with_edge_label` finds them. An edge with several
+    /// labels is updated once for each. Edges with labels not among `labels` are only held by the
+    /// indices of every edge.
+    pub fn from_labeled(initially: Stream<G, LabeledEdge>, updates: Stream<G, (LabeledEdge, i32)>,
+                        labels: &[u32], hash1: H1, hash2: H2) -> (Self, GraphStreamIndexHandle<G::Timestamp>)
+        where H1: Clone, H2: Clone {
+        let (mut index, mut handles) = GraphStreamIndex::from(initially.map(|(src, dst, _)| (src, dst)),
+                                                              updates.map(|((src, dst, _), diff)| ((src, dst), diff)),
+                                                              hash1.clone(), hash2.clone());
+        for &label in labels.iter() {
+            if index.labeled.iter().any(|x| x.0 == label) { continue; }
+            let (labeled, labeled_handles) = GraphStreamIndex::from(initially.filter(move |x| x.2 == label).map(|(src, dst, _)| (src, dst)),
+                                                                    updates.filter(move |x| (x.0).2 == label).map(|((src, dst, _), diff)| ((src, dst), diff)),
+                                                                    hash1.clone(), hash2.clone());
+            index.labeled.push((label, labeled));
+            handles.labeled.push(labeled_handles);
+        }
+        (index, handles)
+    }
+
+    /// The indices of the edges labeled `label`, or of every edge if `label` is none.
+    ///
+    /// Panics if the graph holds no indices for `label`; see `from_labeled`.
+    pub fn with_edge_label(&self, label: Option<u32>) -> &Self {
+        match label {
+            None => self,
+            Some(label) => match self.labeled.iter().find(|x| x.0 == label) {
+                Some(&(_, ref labeled)) => labeled,
+                None => panic!("EXCEPTION: operations read edges labeled {}, but the graph indices hold no edges by that label", label),
+            },
+        }
+    }
+
+    /// Sets the labels of the graph's vertices, for plans whose operations require labels of the
+    /// vertices they bind; see `Plan::set_operation_label`.
+    ///
+    /// Labels do not change as the graph does, and every worker reads all of them, as
+    /// `io::read_vertex_labels` reads them; vertices without a label have none of the labels
+    /// operations require.
+    pub fn set_vertex_labels(&mut self, labels: Arc<HashMap<Node, u32>>) {
+        for &mut (_, ref mut labeled) in self.labeled.iter_mut() {
+            labeled.set_vertex_labels(labels.clone());
+        }
+        self.vertex_labels = Some(labels);
+    }
+
+    /// Sets the numeric attributes of the graph's vertices, for plans whose edges filter the
+    /// vertices they bind; see `Plan::set_vertex_filters`.
+    ///
+    /// As labels, attributes do not change as the graph does, and every worker reads all of them.
+    pub fn set_vertex_attributes(&mut self, attributes: Arc<VertexAttributes>) {
+        for &mut (_, ref mut labeled) in self.labeled.iter_mut() {
+            labeled.set_vertex_attributes(attributes.clone());
+        }
+        self.vertex_attributes = Some(attributes);
+    }
+
+    /// Sets whether operators reading the indices, and the indices of the edges with each label,
+    /// process their inputs in a reproducible order; see `Index::set_deterministic`.
+    pub fn set_deterministic(&self, enabled: bool) {
+        write_index(&self.forward.index).set_deterministic(enabled);
+        write_index(&self.reverse.index).set_deterministic(enabled);
+        for &(_, ref labeled) in self.labeled.iter() { labeled.set_deterministic(enabled); }
+    }
+
+    fn assemble((forward, out_counts): (IndexStream<Node, H1, G::Timestamp>, Stream<G, (Node, i64)>),
+                (reverse, in_counts): (IndexStream<Node, H2, G::Timestamp>, Stream<G, (Node, i64)>),
+                updates: Stream<G, (Edge, i32)>) -> (Self, GraphStreamIndexHandle<G::Timestamp>) {
+        let changes = updates.clone();
         let updates = updates.filter(|((src, dst),_)| src != dst).map(|((src, dst),wgt)|(vec![src, dst], wgt));
+        let degree_changes = out_counts.map(|(node, diff)| (node, diff, 0))
+                                       .concat(&in_counts.map(|(node, diff)| (node, 0, diff)));
         let index = GraphStreamIndex {
             forward: forward,
             reverse: reverse,
             updates: updates,
+            changes: changes,
+            degree_changes: degree_changes,
+            vertex_labels: None,
+            vertex_attributes: None,
+            labeled: Vec::new(),
         };
         let handles = GraphStreamIndexHandle {
             forward: index.forward.index.clone(),
             reverse: index.reverse.index.clone(),
+            copies: Vec::new(),
+            labeled: Vec::new(),
         };
         (index, handles)
     }
@@ -0,0 +1,92 @@
+//! Frequencies of edge labels, for ordering the edges of labeled patterns by selectivity.
+//!
+//! Matching a pattern's rarely labeled edges first keeps the number of prefixes small, in the same
+//! way that worst-case optimal joins extend prefixes by the relation proposing the fewest values.
+//! `LabelStatistics` is the interface a planner consults for label frequencies, and
+//! `LabelFrequencies` maintains them incrementally from labeled edge updates.
+//!
+//! The indices do not yet hold labels, so `EdgeLabeledPlan` matches unlabeled patterns and only
+//! reports the labels of each match afterwards; the statistics are meant for plans that restrict
+//! edges by label, and in the meantime let drivers report how skewed their labels are.
+
+use std::collections::HashMap;
+
+use super::Edge;
+
+pub type Label = u32;
+
+/// Counts of edges by label, as consumed by planners.
+pub trait LabelStatistics {
+    /// The number of edges with `label`.
+    fn frequency(&self, label: Label) -> u64;
+    /// The number of labeled edges.
+    fn total(&self) -> u64;
+
+    /// The fraction of labeled edges with `label`, or one if there are no labeled edges.
+    fn selectivity(&self, label: Label) -> f64 {
+        let total = self.total();
+        if total == 0 { 1.0 } else { self.frequency(label) as f64 / total as f64 }
+    }
+}
+
+/// Frequencies of labels, maintained from labeled edge updates.
+#[derive(Debug, Clone, Default)]
+pub struct LabelFrequencies {
+    counts: HashMap<Label, i64>,
+    total: i64,
+}
+
+impl LabelFrequencies {
+    /// Allocates frequencies with no labeled edges.
+    pub fn new() -> Self { LabelFrequencies::default() }
+
+    /// Records `diff` edges with `label`.
+    pub fn update(&mut self, label: Label, diff: i64) {
+        let remove = {
+            let count = self.counts.entry(label).or_insert(0);
+            *count += diff;
+            *count == 0
+        };
+        if remove { self.counts.remove(&label); }
+        self.total += diff;
+    }
+
+    /// Records a batch of labeled edge updates.
+    pub fn update_edges(&mut self, updates: &[(Edge, Label, i32)]) {
+        for &(_, label, diff) in updates.iter() {
+            self.update(label, diff as i64);
+        }
+    }
+
+    /// Adds the frequencies recorded by `other`, e.g. at another worker.
+    pub fn merge(&mut self, other: &LabelFrequencies) {
+        for (&label, &count) in other.counts.iter() {
+            self.update(label, count);
+        }
+    }
+
+    /// The labels with edges and their frequencies, in order of label.
+    pub fn frequencies(&self) -> Vec<(Label, u64)> {
+        let mut frequencies = self.counts.iter().map(|(&label, &count)| (label, ::std::cmp::max(count, 0) as u64)).collect::<Vec<_>>();
+        frequencies.sort();
+        frequencies
+    }
+}
+
+impl LabelStatistics for LabelFrequencies {
+    fn frequency(&self, label: Label) -> u64 {
+        self.counts.get(&label).map(|&count| ::std::cmp::max(count, 0) as u64).unwrap_or(0)
+    }
+    fn total(&self) -> u64 {
+        ::std::cmp::max(self.total, 0) as u64
+    }
+}
+
+/// Orders the positions of `labels`, the labels of a pattern's edges, from rarest to most frequent.
+///
+/// Edges with equally frequent labels keep their relative order, so that plans are reproducible.
+pub fn order_by_selectivity<S: LabelStatistics>(statistics: &S, labels: &[Label]) -> Vec<usize> {
+    let mut order = (0 .. labels.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&position| statistics.frequency(labels[position]));
+    order
+}
@@ -0,0 +1,229 @@
+//! Limits on the extensions an operation admits for each prefix.
+//!
+//! Recommendation-style queries explore a bounded neighborhood: of the items a user rated, only
+//! the first few, or those rated most often, lead on to further matches. An `ExtensionLimit` on an
+//! operation of a plan (see `Plan::set_extension_limit`) admits, for each prefix, only extensions
+//! among the first `k` neighbors of the vertex the operation reads, in the index it reads, by id
+//! or by the number of copies of their edge. The neighbors admitted form a relation derived from
+//! the graph, the pairs `(u, v)` with `v` among the first `k` neighbors of `u`, and the matches of
+//! a query node below a limited operation are the matches of the unlimited plan whose vertices at
+//! the operation's positions are a pair of the relation.
+//!
+//! The relation changes when the graph does, and not only at the edges updated: an edge inserted
+//! among the first `k` neighbors of its vertex displaces the neighbor ranked `k`th, and an edge
+//! deleted from them admits the neighbor ranked next. Matches follow the relation as they follow
+//! the graph. At each time, the matches of pairs that leave the relation are retracted and those
+//! of pairs that enter it are reported, as though the pattern edge between them had been deleted
+//! or inserted, so that the changes accumulated up to any time are the matches of the graph at
+//! that time whose pairs are among the first neighbors then.
+//!
+//! `GraphStreamIndex::top_neighbors` maintains the relation from the indices: once an index has
+//! absorbed a time, the worker owning each vertex whose edges changed reads the vertex's neighbors
+//! as of the time, and reports the pairs entering and leaving its first `k`. `limit_matches` keeps
+//! the changes to matches whose pairs are in the relation. It stores every match it has seen by
+//! its pair, to report them when the pair enters the relation, so limits restrict the matches
+//! reported rather than the work of finding them, and cost memory in the matches below them.
+//!
+//! As in `properties`, the indices are read as of a time, so they must not be merged past a time
+//! before the operators have read it, as is the case when the plan's outputs are probed before
+//! merging.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::*;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::graph_stream::GraphStreamIndex;
+use super::{ExtensionLimit, IndexStream, LimitOrder, Node, Edge};
+use wings_rule::read_index;
+use ::Weight;
+
+impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStreamIndex<G, H1, H2> where G::Timestamp: Ord+Hash {
+
+    /// Changes to the pairs `(u, v)` with `v` among the first `limit.k` out-neighbors of `u`, if
+    /// `is_forward`, or in-neighbors otherwise.
+    ///
+    /// The changes at the initial time describe the initial contents of the index, including any
+    /// restored from a snapshot.
+    pub fn top_neighbors(&self, is_forward: bool, limit: ExtensionLimit) -> Stream<G, (Edge, i32)> {
+        if is_forward {
+            top_changes(&self.forward, &self.changes.map(|((src, _), diff)| (src, diff)), limit)
+        }
+        else {
+            top_changes(&self.reverse, &self.changes.map(|((_, dst), diff)| (dst, diff)), limit)
+        }
+    }
+}
+
+// changes to the pairs of each key of `index` and its first values under `limit`, as `keys` updates them.
+fn top_changes<G: Scope, H: Fn(Node)->u64+'static>(index: &IndexStream<Node, H, G::Timestamp>, keys: &Stream<G, (Node, i32)>, limit: ExtensionLimit) -> Stream<G, (Edge, i32)>
+    where G::Timestamp: Ord+Hash {
+
+    let hash = index.hash.clone();
+    let handle = index.handle.clone();
+    let index = index.index.clone();
+
+    // the first values of each key with any, in order.
+    let mut tops: HashMap<Node, Vec<Node>> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    // the initial time, until the index has its initial contents, and then times not yet read.
+    let mut initial = None;
+    let mut initialized = false;
+    let mut ready = Vec::new();
+
+    let exchange = Exchange::new(move |x: &(Node, i32)| (*hash)(x.0));
+    keys.unary_notify(exchange, "TopNeighbors", vec![Default::default()], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(HashSet::new()).extend(buffer.drain(..).map(|x| x.0));
+            notificator.notify_at(time.retain());
+        });
+
+        notificator.for_each(|time, _, _| {
+            if !initialized && initial.is_none() && time.time() == &Default::default() { initial = Some(time); }
+            else { ready.push(time); }
+        });
+
+        // the first values of every key of the initial contents, once the index holds them.
+        if let Some(time) = initial.take() {
+            if handle.less_equal(time.time()) { initial = Some(time); }
+            else {
+                let contents = read_index(&index);
+                let mut session = output.session(&time);
+                for (key, _) in contents.degrees_at(time.time()) {
+                    let top = first(contents.values_at(&key, time.time()), limit);
+                    for &value in top.iter() { session.give(((key, value), 1)); }
+                    if !top.is_empty() { tops.insert(key, top); }
+                }
+                // keys changed at the initial time are already read.
+                stash.remove(time.time());
+                initialized = true;
+            }
+        }
+
+        if initialized {
+            // times are read in order, each once the index has absorbed it.
+            ready.sort_by(|x, y| x.time().cmp(y.time()));
+            let absorbed = ready.iter().position(|time| handle.less_equal(time.time())).unwrap_or(ready.len());
+            for time in ready.drain(.. absorbed) {
+                let mut keys = stash.remove(time.time()).unwrap_or(HashSet::new()).into_iter().collect::<Vec<_>>();
+                keys.sort();
+                let contents = read_index(&index);
+                let mut session = output.session(&time);
+                for key in keys {
+                    let top = first(contents.values_at(&key, time.time()), limit);
+                    let before = tops.remove(&key).unwrap_or(Vec::new());
+                    for &value in before.iter().filter(|value| !top.contains(value)) { session.give(((key, value), -1)); }
+                    for &value in top.iter().filter(|value| !before.contains(value)) { session.give(((key, value), 1)); }
+                    if !top.is_empty() { tops.insert(key, top); }
+                }
+            }
+        }
+    })
+}
+
+// the first `limit.k` of `values`, sorted and consolidated, that are present.
+fn first(mut values: Vec<(Node, i32)>, limit: ExtensionLimit) -> Vec<Node> {
+    values.retain(|x| x.1 > 0);
+    if limit.order == LimitOrder::Weight {
+        values.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+    }
+    values.truncate(limit.k);
+    values.into_iter().map(|x| x.0).collect()
+}
+
+/// Keeps the changes to matches whose vertices at `positions` are a pair of `top`, as pairs enter
+/// and leave it.
+///
+/// `top` describes a collection of pairs, each present at most once, e.g. the first neighbors of
+/// vertices reported by `GraphStreamIndex::top_neighbors`. Each pair is handled by one worker,
+/// which stores the matches of the pair, and reports the changes of each time once it completes.
+pub fn limit_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, top: &Stream<G, (Edge, i32)>, positions: (usize, usize)) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: Hash {
+
+    let (src, dst) = positions;
+
+    // the pairs present in `top`, and the accumulated matches of each pair.
+    let mut members: HashSet<Edge> = HashSet::new();
+    let mut present: HashMap<Edge, HashMap<Vec<Node>, W>> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer1 = Vec::new();
+    let mut buffer2 = Vec::new();
+
+    let exchange1 = Exchange::new(move |x: &(Vec<Node>, W)| pair_hash((x.0[src], x.0[dst])));
+    let exchange2 = Exchange::new(|x: &(Edge, i32)| pair_hash(x.0));
+    matches.binary_notify(top, exchange1, exchange2, "LimitMatches", vec![], move |input1, input2, output, notificator| {
+
+        input1.for_each(|time, data| {
+            data.swap(&mut buffer1);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), Vec::new())).0.extend(buffer1.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        input2.for_each(|time, data| {
+            data.swap(&mut buffer2);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), Vec::new())).1.extend(buffer2.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let (changes, membership) = stash.remove(time.time()).unwrap_or((Vec::new(), Vec::new()));
+
+            // the net change in the membership of each pair, and the changes to its matches.
+            let mut pairs: HashMap<Edge, (i32, Vec<(Vec<Node>, W)>)> = HashMap::new();
+            for (pair, diff) in membership {
+                pairs.entry(pair).or_insert((0, Vec::new())).0 += diff;
+            }
+            for (embedding, weight) in changes {
+                pairs.entry((embedding[src], embedding[dst])).or_insert((0, Vec::new())).1.push((embedding, weight));
+            }
+            let mut pairs = pairs.into_iter().collect::<Vec<_>>();
+            pairs.sort_by(|x, y| x.0.cmp(&y.0));
+
+            let mut output_changes = Vec::new();
+            for (pair, (diff, changes)) in pairs {
+                let before = members.contains(&pair);
+                let after = if diff > 0 { true } else if diff < 0 { false } else { before };
+
+                // a pair leaving retracts its matches, and one entering reports them with this time's changes.
+                if before && !after {
+                    if let Some(matches) = present.get(&pair) {
+                        output_changes.extend(matches.iter().map(|(embedding, &weight)| (embedding.clone(), -weight)));
+                    }
+                }
+                let empty = {
+                    let matches = present.entry(pair).or_insert_with(HashMap::new);
+                    for (embedding, weight) in changes {
+                        if before && after { output_changes.push((embedding.clone(), weight)); }
+                        let total = *matches.get(&embedding).unwrap_or(&W::default()) + weight;
+                        if total == W::default() { matches.remove(&embedding); }
+                        else { matches.insert(embedding, total); }
+                    }
+                    if !before && after {
+                        output_changes.extend(matches.iter().map(|(embedding, &weight)| (embedding.clone(), weight)));
+                    }
+                    matches.is_empty()
+                };
+                if empty { present.remove(&pair); }
+                if after { members.insert(pair); } else { members.remove(&pair); }
+            }
+
+            output_changes.sort();
+            output.session(&time).give_iterator(output_changes.into_iter());
+        }
+    })
+}
+
+// routes a pair, and the matches of the pair, to one worker.
+fn pair_hash(pair: Edge) -> u64 {
+    (pair.0 as u64).wrapping_mul(0x9E3779B97F4A7C15) ^ pair.1 as u64
+}
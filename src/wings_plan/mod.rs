@@ -1,7 +1,32 @@
-pub mod count_vertex_labeled_query_plan;
-pub mod count_edge_labeled_query_plan;
+pub mod plan;
+pub mod parser;
+pub mod schema;
+pub mod optimizer;
+pub mod document;
+#[cfg(feature = "labels")] pub mod count_vertex_labeled_query_plan;
+#[cfg(feature = "labels")] pub mod count_edge_labeled_query_plan;
 pub mod graph_stream;
-pub mod dir_reader;
+#[cfg(feature = "io")] pub mod dir_reader;
+pub mod degree_filter;
+pub mod properties;
+pub mod aggregate;
+pub mod attributes;
+pub mod distinct;
+pub mod existence;
+pub mod conventions;
+pub mod sorted;
+pub mod projection;
+pub mod aggregation;
+pub mod standing;
+#[cfg(feature = "labels")] pub mod label_stats;
+pub mod agm;
+pub mod packed;
+pub mod limit;
+pub mod bindings;
+pub mod sources;
+pub mod filters;
+
+use std::sync::Arc;
 
 use timely::dataflow::*;
 
@@ -12,33 +37,286 @@ pub use super::Indexable;
 
 pub use self::graph_stream::GraphStreamIndex;
 
-pub use self::count_vertex_labeled_query_plan::{VertexLabeledPlan};
-pub use self::count_edge_labeled_query_plan::{EdgeLabeledPlan};
-pub use self::dir_reader::DirReader;
-pub use super::wings_rule::{Index, IndexStream, advance, StreamPrefixExtender, StreamPrefixIntersector, Intersection, GenericJoin, IntersectOnly};
+pub use self::plan::Plan;
+pub use self::sources::{ExtensionSource, ListSource, SourceExtender};
+pub use self::filters::{AttributeFilter, Comparison, VertexAttributes};
+#[cfg(feature = "labels")] pub use self::count_vertex_labeled_query_plan::{VertexLabeledPlan};
+#[cfg(feature = "labels")] pub use self::count_edge_labeled_query_plan::{EdgeLabeledPlan};
+#[cfg(feature = "io")] pub use self::dir_reader::DirReader;
+pub use super::wings_rule::{Index, IndexStream, advance, StreamPrefixExtender, StreamPrefixIntersector, Intersection, GenericJoin, ProposeFirst, IntersectOnly, Extensions, expand_counted, intersect_adaptive};
+use super::wings_rule::ExtenderCombinators;
+
+pub use ::{Node, Edge};
+
+/// The index used to check that an edge between two prefix vertices exists.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum IntersectDirection {
+    /// Probe the forward index with the edge's source.
+    Forward,
+    /// Probe the reverse index with the edge's destination.
+    Reverse,
+    /// Probe whichever index holds fewer values for each prefix; see `intersect_adaptive`.
+    Adaptive,
+}
+
+impl Default for IntersectDirection {
+    fn default() -> Self { IntersectDirection::Forward }
+}
+
+/// How the changes to a query node's matches are reported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MatchSemantics {
+    /// Each change to a match is attributed to one of the updates that caused it.
+    Attributed,
+    /// The changes are the set difference of the matches of consecutive epochs; see `distinct`.
+    SetDifference {
+        /// Identify matches by their sets of vertices, rather than as embeddings.
+        unordered: bool,
+    },
+}
+
+impl Default for MatchSemantics {
+    fn default() -> Self { MatchSemantics::Attributed }
+}
+
+/// Whether the edges of a plan's graph and patterns have directions; see `conventions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Edges go from their source to their destination.
+    Directed,
+    /// Edges join their endpoints both ways, and are read in both directions.
+    Undirected,
+}
+
+impl Default for Orientation {
+    fn default() -> Self { Orientation::Directed }
+}
+
+/// What the matches of a query node count; see `conventions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counting {
+    /// Every embedding of the pattern, so that a subgraph counts once per automorphism.
+    Embeddings,
+    /// Each subgraph matching the pattern, once.
+    Subgraphs,
+}
+
+impl Default for Counting {
+    fn default() -> Self { Counting::Embeddings }
+}
+
+/// Where an engine gathers cluster-wide totals of each query node's matches.
+///
+/// Each worker otherwise only observes the changes to matches it produced itself, and a process
+/// of a cluster only the changes of its own workers. Gathering totals adds a final stage to the
+/// dataflow that exchanges each worker's changes in counts of each epoch, and epochs complete only
+/// once the stage has absorbed them, so that the totals reported for an epoch are exact.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GlobalCounts {
+    /// Gather no totals.
+    Off,
+    /// Gather totals at the first worker, which reports them.
+    First,
+    /// Gather totals at every worker, each of which reports them.
+    Replicated,
+}
+
+impl Default for GlobalCounts {
+    fn default() -> Self { GlobalCounts::Off }
+}
 
-pub type Node = u32;
-pub type Edge = (Node, Node);
+/// How a plan's prefixes are represented as they are exchanged between workers.
+///
+/// Workers decode only the representation they expect, so every worker must execute a plan with
+/// the same encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PrefixEncoding {
+    /// Vectors of vertices.
+    Plain,
+    /// Vertices packed into as few bits as the largest of each prefix needs; see `packed`.
+    Packed,
+}
 
+impl Default for PrefixEncoding {
+    fn default() -> Self { PrefixEncoding::Plain }
+}
+
+/// How a plan edge handles one of its operations, overriding the plan's own classification.
+///
+/// An operation whose pattern edge reaches the vertex the plan edge binds is an extension: it
+/// proposes and intersects candidates for the vertex along with the edge's other extensions.
+/// Operations between vertices bound earlier are intersections, checked before extending.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum JoinHint {
+    /// Extend prefixes using the operation, which must reach the vertex its plan edge binds.
+    Extend,
+    /// Check the operation's pattern edge with an intersection, once its vertices are bound.
+    ///
+    /// An extension hinted this way takes no part in proposing candidates, and is instead checked
+    /// for each candidate the other extensions propose, which pays when its relation is much
+    /// larger than theirs.
+    Intersect,
+}
+
+/// A limit on the extensions an operation admits for each prefix; see `limit`.
+///
+/// An operation limited to `k` reaches only the first `k` neighbors of the vertex it reads, in the
+/// index it reads, in the limit's order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct ExtensionLimit {
+    /// The number of neighbors admitted, at least one.
+    pub k: usize,
+    /// The order in which neighbors are admitted.
+    pub order: LimitOrder,
+}
+
+/// The order in which an `ExtensionLimit` admits the neighbors of a vertex.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum LimitOrder {
+    /// Neighbors with smaller ids first.
+    Id,
+    /// Neighbors joined by more copies of their edge first, and then those with smaller ids.
+    Weight,
+}
 
-pub trait ExtendEdges<G: Scope, P: Data>{
+/// The workers that maintain the graph indices and extend prefixes.
+///
+/// Extending prefixes is the heavy part of a plan, and must run where the indices it reads are
+/// held. Restricting both to a subset of workers, e.g. those on larger machines, leaves the other
+/// workers only reading input and routing updates. Index keys are spread evenly over the subset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Every worker holds a share of the indices.
+    All,
+    /// Only the listed workers hold shares of the indices.
+    Workers(Vec<usize>),
+}
+
+impl Default for Placement {
+    fn default() -> Self { Placement::All }
+}
+
+impl Placement {
+    /// Checks that the placement names at least one worker, and only workers among `peers`.
+    pub fn validate(&self, peers: usize) -> Result<(), String> {
+        match *self {
+            Placement::All => Ok(()),
+            Placement::Workers(ref workers) => {
+                if workers.is_empty() {
+                    return Err("placement names no workers".to_owned());
+                }
+                match workers.iter().find(|&&worker| worker >= peers) {
+                    Some(worker) => Err(format!("placement names worker {}, but there are {} workers", worker, peers)),
+                    None => Ok(()),
+                }
+            },
+        }
+    }
+
+    /// A hash function for index keys that routes each key to a worker of the placement.
+    ///
+    /// Timely routes data with hash `h` to worker `h % peers`, so the function returns the
+    /// index of the chosen worker itself.
+    pub fn router(&self) -> impl Fn(Node)->u64 {
+        self.salted_router(0)
+    }
+
+    /// As `router`, but rotating the workers of the placement by `salt`.
+    ///
+    /// Each key is routed to the worker `salt` places after the one `router` chooses, so that
+    /// copies of an index built with different salts hold each key at different workers.
+    pub fn salted_router(&self, salt: usize) -> impl Fn(Node)->u64 {
+        let workers = match *self {
+            Placement::All => None,
+            Placement::Workers(ref workers) => Some(workers.clone()),
+        };
+        move |key| match workers {
+            None => key as u64 + salt as u64,
+            Some(ref workers) => workers[(key as usize + salt) % workers.len()] as u64,
+        }
+    }
+}
+
+pub trait ExtendEdges<G: Scope, P: Data, W: Data>{
     fn extend_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)])
-                                                                                       -> Stream<G, (P, Vec<Node>, i32)>
+                                                                                       -> Stream<G, (P, Vec<Node>, W)>
         where G: 'a,
               P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
 
     fn intersect_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize)])
-                                                                                          -> Stream<G, (P, i32)>
+                                                                                          -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `intersect_attributes`, but checking each edge using the indices chosen by `direction`.
+    fn intersect_attributes_using<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize)], direction: IntersectDirection)
+                                                                                                -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `intersect_attributes_using`, but checking each edge using the indices chosen for it.
+    fn intersect_attributes_each<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize, IntersectDirection)])
+                                                                                               -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `intersect_attributes_each`, but keeping the prefixes whose edges are all absent.
+    ///
+    /// Edges to be checked adaptively are checked through the forward index.
+    fn anti_intersect_attributes_each<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize, IntersectDirection)])
+                                                                                                    -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `extend_attributes`, but always proposing from the first attribute's relation.
+    fn extend_attributes_first<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)])
+                                                                                     -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `extend_attributes_first`, but proposing from the first attribute's relation with a hash
+    /// join, which neither sorts the prefixes nor merges them with the index; see
+    /// `IndexExtender::hash_join`.
+    fn extend_attributes_hashed<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)])
+                                                                                      -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `extend_attributes`, or `extend_attributes_first` if `propose_first` is set, but proposing
+    /// only the vertices labeled `label`, which the indices filter as they read them.
+    ///
+    /// Panics if `graph` has no vertex labels; see `GraphStreamIndex::set_vertex_labels`.
+    fn extend_attributes_labeled<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)], label: u32, propose_first: bool)
+                                                                                               -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `extend_attributes_labeled`, but reading each attribute from its own indices, those of
+    /// `graphs` at the same position, as the indices of the edges with the label its operation
+    /// requires are, and proposing vertices of any label if `label` is not set.
+    fn extend_attributes_over<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], attributes: &[(usize, bool)], label: Option<u32>, propose_first: bool)
+                                                                                            -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
+
+    /// As `extend_attributes_over`, but reading each attribute with a source at the same position
+    /// in `sources` from that source, in place of its indices, which must be forward.
+    fn extend_attributes_sourced<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], attributes: &[(usize, bool)], sources: &[Option<Arc<ExtensionSource>>], label: Option<u32>, propose_first: bool)
+                                                                                               -> Stream<G, (P, Vec<Node>, W)>
         where G: 'a,
               P: ::std::fmt::Debug+ExchangeData+Indexable<Node>;
 }
 
-impl<G: Scope, P: ::std::fmt::Debug+ExchangeData+Indexable<Node>> ExtendEdges<G, P> for Stream<G, (P, i32)>{
+impl<G: Scope, P: ::std::fmt::Debug+ExchangeData+Indexable<Node>, W: ExchangeData> ExtendEdges<G, P, W> for Stream<G, (P, W)>{
 
-    fn extend_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)]) -> Stream<G, (P, Vec<Node>, i32)>
+    fn extend_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)]) -> Stream<G, (P, Vec<Node>, W)>
         where G: 'a,
               P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
-        let mut extenders: Vec<Box<StreamPrefixExtender<G, i32, Prefix=P, Extension=Node>+'a>> = vec![];
+        let mut extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=Node>+'a>> = vec![];
         for &(attribute, is_forward) in attributes {
             extenders.push(match is_forward {
                 true    => Box::new(graph.forward.extend_using(move |x: &P| x.index(attribute))),
@@ -48,13 +326,140 @@ impl<G: Scope, P: ::std::fmt::Debug+ExchangeData+Indexable<Node>> ExtendEdges<G,
         self.extend(extenders)
     }
 
-    fn intersect_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize)]) -> Stream<G, (P, i32)>
+    fn extend_attributes_first<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)]) -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        let mut extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=Node>+'a>> = vec![];
+        for &(attribute, is_forward) in attributes {
+            extenders.push(match is_forward {
+                true    => Box::new(graph.forward.extend_using(move |x: &P| x.index(attribute))),
+                false   => Box::new(graph.reverse.extend_using(move |x: &P| x.index(attribute))),
+            });
+        }
+        self.propose_first(extenders)
+    }
+
+    fn extend_attributes_hashed<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)]) -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        let (attribute, is_forward) = attributes[0];
+        let mut extensions = match is_forward {
+            true    => graph.forward.extend_using(move |x: &P| x.index(attribute)).hash_join(self.clone()),
+            false   => graph.reverse.extend_using(move |x: &P| x.index(attribute)).hash_join(self.clone()),
+        };
+        for &(attribute, is_forward) in attributes.iter().skip(1) {
+            extensions = match is_forward {
+                true    => graph.forward.extend_using(move |x: &P| x.index(attribute)).intersect(extensions),
+                false   => graph.reverse.extend_using(move |x: &P| x.index(attribute)).intersect(extensions),
+            };
+        }
+        extensions
+    }
+
+    fn extend_attributes_labeled<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)], label: u32, propose_first: bool) -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        let labels = match graph.vertex_labels {
+            Some(ref labels) => labels.clone(),
+            None => panic!("EXCEPTION: extensions require vertex label {}, but the graph indices have no vertex labels", label),
+        };
+        let mut extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=Node>+'a>> = vec![];
+        for &(attribute, is_forward) in attributes {
+            let labels = labels.clone();
+            let accept = move |vertex: &Node| labels.get(vertex) == Some(&label);
+            extenders.push(match is_forward {
+                true    => Box::new(graph.forward.extend_where(move |x: &P| x.index(attribute), accept)),
+                false   => Box::new(graph.reverse.extend_where(move |x: &P| x.index(attribute), accept)),
+            });
+        }
+        if propose_first { self.propose_first(extenders) } else { self.extend(extenders) }
+    }
+
+    fn extend_attributes_over<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], attributes: &[(usize, bool)], label: Option<u32>, propose_first: bool) -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        let sources: Vec<Option<Arc<ExtensionSource>>> = vec![None; attributes.len()];
+        self.extend_attributes_sourced(graphs, attributes, &sources, label, propose_first)
+    }
+
+    fn extend_attributes_sourced<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], attributes: &[(usize, bool)], sources: &[Option<Arc<ExtensionSource>>], label: Option<u32>, propose_first: bool) -> Stream<G, (P, Vec<Node>, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node> {
+        assert_eq!(graphs.len(), attributes.len(), "each attribute needs indices to read");
+        assert_eq!(sources.len(), attributes.len(), "each attribute needs a source, or none");
+        let mut extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=Node>+'a>> = vec![];
+        for ((graph, &(attribute, is_forward)), source) in graphs.iter().zip(attributes.iter()).zip(sources.iter()) {
+            let accept = label.map(|label| {
+                let labels = match graph.vertex_labels {
+                    Some(ref labels) => labels.clone(),
+                    None => panic!("EXCEPTION: extensions require vertex label {}, but the graph indices have no vertex labels", label),
+                };
+                move |vertex: &Node| labels.get(vertex) == Some(&label)
+            });
+            extenders.push(match (source, accept, is_forward) {
+                (&Some(ref source), None, _)            => Box::new(SourceExtender::new(source.clone(), move |x: &P| x.index(attribute))),
+                (&Some(ref source), Some(accept), _)    => Box::new(ExtenderCombinators::<G, W>::filtered(SourceExtender::new(source.clone(), move |x: &P| x.index(attribute)), accept)),
+                (&None, None, true)                     => Box::new(graph.forward.extend_using(move |x: &P| x.index(attribute))),
+                (&None, None, false)                    => Box::new(graph.reverse.extend_using(move |x: &P| x.index(attribute))),
+                (&None, Some(accept), true)             => Box::new(graph.forward.extend_where(move |x: &P| x.index(attribute), accept)),
+                (&None, Some(accept), false)            => Box::new(graph.reverse.extend_where(move |x: &P| x.index(attribute), accept)),
+            });
+        }
+        if propose_first { self.propose_first(extenders) } else { self.extend(extenders) }
+    }
+
+    fn intersect_attributes<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize)]) -> Stream<G, (P, W)>
         where G: 'a,
               P: ::std::fmt::Debug+ExchangeData+Indexable<Node>{
-        let mut intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P>+'a>> = vec![];
+        let mut intersectors: Vec<Box<StreamPrefixIntersector<G, W, Prefix=P>+'a>> = vec![];
         for &(src, dst) in attributes{
             intersectors.push(Box::new(graph.forward.intersect_using(move |x: &P| x.index(src), move |x: &P| x.index(dst))));
         }
         self.intersect_only(intersectors)
     }
+
+    fn intersect_attributes_using<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize)], direction: IntersectDirection) -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>{
+        match direction {
+            IntersectDirection::Forward => self.intersect_attributes(graph, attributes),
+            IntersectDirection::Reverse => {
+                let mut intersectors: Vec<Box<StreamPrefixIntersector<G, W, Prefix=P>+'a>> = vec![];
+                for &(src, dst) in attributes{
+                    intersectors.push(Box::new(graph.reverse.intersect_using(move |x: &P| x.index(dst), move |x: &P| x.index(src))));
+                }
+                self.intersect_only(intersectors)
+            },
+            IntersectDirection::Adaptive => {
+                let mut results = self.clone();
+                for &(src, dst) in attributes{
+                    results = intersect_adaptive(&results, &graph.forward, &graph.reverse, move |x: &P| x.index(src), move |x: &P| x.index(dst));
+                }
+                results
+            },
+        }
+    }
+
+    fn intersect_attributes_each<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize, IntersectDirection)]) -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>{
+        let mut results = self.clone();
+        for &(src, dst, direction) in attributes{
+            results = results.intersect_attributes_using(graph, &[(src, dst)], direction);
+        }
+        results
+    }
+
+    fn anti_intersect_attributes_each<'a, H1: Fn(Node)->u64 + 'static, H2: Fn(Node)->u64 + 'static>(&self, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, usize, IntersectDirection)]) -> Stream<G, (P, W)>
+        where G: 'a,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>{
+        let mut intersectors: Vec<Box<StreamPrefixIntersector<G, W, Prefix=P>+'a>> = vec![];
+        for &(src, dst, direction) in attributes{
+            intersectors.push(match direction {
+                IntersectDirection::Reverse => Box::new(graph.reverse.anti_intersect_using(move |x: &P| x.index(dst), move |x: &P| x.index(src))),
+                _ => Box::new(graph.forward.anti_intersect_using(move |x: &P| x.index(src), move |x: &P| x.index(dst))),
+            });
+        }
+        self.intersect_only(intersectors)
+    }
 }
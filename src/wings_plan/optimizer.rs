@@ -0,0 +1,260 @@
+//! Plans for patterns, chosen by their estimated cost on a graph's degree statistics.
+//!
+//! A plan for a pattern has a chain of nodes for each of its edges, binding the remaining vertices
+//! one at a time from an update to the edge. The order in which a chain binds vertices decides how
+//! many prefixes it extends: on a graph with a few vertices of very high out-degree, following an
+//! edge out of a vertex proposes far more candidates than following an edge into it. The parser
+//! binds vertices greedily by their links to those bound, which ignores the graph; the optimizer
+//! instead estimates the work of every order from `DegreeStatistics` and keeps the cheapest.
+//!
+//! The estimate follows each chain from a single update. Binding a vertex proposes, for each
+//! prefix, the neighbors of the bound vertex whose index holds fewest, and intersects them with the
+//! other links to the vertex, each of which keeps a candidate with probability its degree over
+//! the number of vertices. Degrees are those of the endpoints of a random edge, the sum of squared
+//! degrees over the number of edges, as prefixes reach vertices through edges and so favor those of
+//! high degree. A link whose degree exceeds the least of its step's by more than the optimizer's
+//! ratio is hinted to only intersect (see `JoinHint::Intersect`), so that counting skips it.
+//!
+//! Statistics describe the indices of one worker, which hold the vertices the worker owns;
+//! statistics merged from every worker describe the whole graph.
+
+use std::collections::HashMap;
+
+use wings_plan::parser::{check_pattern, plan_chains, Chain};
+use wings_plan::Plan;
+use ::{Node, Edge};
+
+/// Counts of vertices and edges and the squared degrees of vertices, in either direction.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DegreeStatistics {
+    /// The number of edges.
+    pub edges: u64,
+    /// The number of vertices with out-going edges.
+    pub sources: u64,
+    /// The number of vertices with in-coming edges.
+    pub destinations: u64,
+    /// The sum over vertices of their squared out-degrees.
+    pub out_squares: u64,
+    /// The sum over vertices of their squared in-degrees.
+    pub in_squares: u64,
+}
+
+impl DegreeStatistics {
+    /// The statistics of the graph with edges `edges`, repeated according to their multiplicity.
+    pub fn from_edges(edges: &[Edge]) -> Self {
+        let mut out_degrees = HashMap::new();
+        let mut in_degrees = HashMap::new();
+        for &(src, dst) in edges.iter() {
+            *out_degrees.entry(src).or_insert(0) += 1;
+            *in_degrees.entry(dst).or_insert(0) += 1;
+        }
+        DegreeStatistics::from_degrees(out_degrees.values().cloned(), in_degrees.values().cloned())
+    }
+
+    /// The statistics of snapshots of a forward and a reverse index, as `Engine::snapshot` returns.
+    pub fn from_snapshot(forward: &[Edge], reverse: &[Edge]) -> Self {
+        DegreeStatistics::from_degrees(key_degrees(forward), key_degrees(reverse))
+    }
+
+    /// The statistics of vertices with out-degrees `out_degrees` and in-degrees `in_degrees`.
+    ///
+    /// The edges are counted from the out-degrees. Vertices without edges may be omitted.
+    pub fn from_degrees<I: IntoIterator<Item=u64>, J: IntoIterator<Item=u64>>(out_degrees: I, in_degrees: J) -> Self {
+        let mut statistics = DegreeStatistics::default();
+        for degree in out_degrees.into_iter().filter(|&degree| degree > 0) {
+            statistics.edges += degree;
+            statistics.sources += 1;
+            statistics.out_squares += degree * degree;
+        }
+        for degree in in_degrees.into_iter().filter(|&degree| degree > 0) {
+            statistics.destinations += 1;
+            statistics.in_squares += degree * degree;
+        }
+        statistics
+    }
+
+    /// Adds the statistics of `other`, which covers different vertices of the same graph.
+    pub fn merge(&mut self, other: &DegreeStatistics) {
+        self.edges += other.edges;
+        self.sources += other.sources;
+        self.destinations += other.destinations;
+        self.out_squares += other.out_squares;
+        self.in_squares += other.in_squares;
+    }
+
+    /// The number of vertices with edges, as the larger of the numbers of sources and destinations.
+    pub fn vertices(&self) -> u64 {
+        ::std::cmp::max(self.sources, self.destinations)
+    }
+
+    /// The expected out-degree of the source of a random edge, or zero if there are no edges.
+    pub fn out_degree(&self) -> f64 {
+        if self.edges == 0 { 0.0 } else { self.out_squares as f64 / self.edges as f64 }
+    }
+
+    /// The expected in-degree of the destination of a random edge, or zero if there are no edges.
+    pub fn in_degree(&self) -> f64 {
+        if self.edges == 0 { 0.0 } else { self.in_squares as f64 / self.edges as f64 }
+    }
+}
+
+// the number of values of each key of a sorted snapshot of an index.
+fn key_degrees(snapshot: &[Edge]) -> Vec<u64> {
+    let mut degrees: Vec<(Node, u64)> = Vec::new();
+    for &(key, _) in snapshot.iter() {
+        match degrees.last_mut() {
+            Some(last) if last.0 == key => { last.1 += 1; continue; },
+            _ => { },
+        }
+        degrees.push((key, 1));
+    }
+    degrees.into_iter().map(|x| x.1).collect()
+}
+
+/// A plan chosen by the optimizer, with its estimated cost.
+#[derive(Debug)]
+pub struct OptimizedPlan {
+    /// The plan.
+    pub plan: Plan,
+    /// For each query node of the plan, the position in its matches of each vertex of the pattern.
+    pub positions: Vec<(usize, Vec<usize>)>,
+    /// The chain of each pattern edge, in order of the edges.
+    pub chains: Vec<Chain>,
+    /// The estimated work of the plan for one update to each pattern edge.
+    pub cost: f64,
+}
+
+/// Chooses plans for patterns from degree statistics.
+#[derive(Clone, Debug)]
+pub struct Optimizer {
+    statistics: DegreeStatistics,
+    ratio: f64,
+}
+
+impl Optimizer {
+    /// An optimizer estimating costs from `statistics`, hinting links to intersect by ratio 16.
+    pub fn new(statistics: DegreeStatistics) -> Self {
+        Optimizer { statistics, ratio: 16.0 }
+    }
+
+    /// Hints a link to intersect if its degree exceeds `ratio` times the least of its step.
+    ///
+    /// An infinite ratio hints none. Panics if `ratio` is less than one.
+    pub fn set_intersect_ratio(&mut self, ratio: f64) {
+        assert!(ratio >= 1.0, "intersect ratios are at least one");
+        self.ratio = ratio;
+    }
+
+    /// The statistics the optimizer estimates costs from.
+    pub fn statistics(&self) -> &DegreeStatistics { &self.statistics }
+
+    /// Plans the pattern on `vertices` vertices with edges `edges`, choosing for each edge the
+    /// binding order of least estimated cost.
+    ///
+    /// Returns an error if the pattern is one plans can not evaluate; see `check_pattern`.
+    pub fn optimize(&self, vertices: usize, edges: &[(usize, usize)]) -> Result<OptimizedPlan, String> {
+        check_pattern(vertices, edges)?;
+        let mut chains = Vec::new();
+        let mut cost = 0.0;
+        for &(src, dst) in edges.iter() {
+            let mut order = vec![src, dst];
+            let mut best = None;
+            self.search(vertices, edges, &mut order, self.start(edges, src, dst), &mut best);
+            let (chain_cost, order) = best.ok_or_else(|| "the edges of the pattern are not connected".to_owned())?;
+            let checked = self.checked(edges, &order);
+            chains.push(Chain { order, checked });
+            cost += chain_cost;
+        }
+        let (plan, positions) = plan_chains(vertices, edges, &chains)?;
+        Ok(OptimizedPlan { plan, positions, chains, cost })
+    }
+
+    /// The estimated work of binding the vertices of a pattern with edges `edges` in `order`,
+    /// for one update to the edge between its first two vertices.
+    ///
+    /// Panics if a vertex of `order` after the first two has no edge to a vertex before it.
+    pub fn cost(&self, edges: &[(usize, usize)], order: &[usize]) -> f64 {
+        let (mut prefixes, mut cost) = (1.0, self.start(edges, order[0], order[1]));
+        for bound in 2 .. order.len() {
+            let (step, survivors) = self.step(edges, &order[.. bound], order[bound])
+                .unwrap_or_else(|| panic!("vertex {} is bound before any vertex it is joined to", order[bound]));
+            cost += prefixes * step;
+            prefixes *= survivors;
+        }
+        cost
+    }
+
+    // the work of checking the edge opposite the update to `(src, dst)`, if the pattern has one.
+    fn start(&self, edges: &[(usize, usize)], src: usize, dst: usize) -> f64 {
+        if edges.contains(&(dst, src)) { 1.0 } else { 0.0 }
+    }
+
+    // extends `order` in every way cheaper than `best`, which it updates, starting from `cost`.
+    fn search(&self, vertices: usize, edges: &[(usize, usize)], order: &mut Vec<usize>, cost: f64, best: &mut Option<(f64, Vec<usize>)>) {
+        if best.as_ref().map(|x| cost >= x.0).unwrap_or(false) { return; }
+        if order.len() == vertices {
+            *best = Some((cost, order.clone()));
+            return;
+        }
+        // vertices with more links to those bound first, so that ties keep the parser's order.
+        let mut candidates = (0 .. vertices)
+            .filter(|vertex| !order.contains(vertex))
+            .map(|vertex| (self.links(edges, order, vertex).len(), vertex))
+            .filter(|x| x.0 > 0)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&(links, vertex)| (::std::cmp::Reverse(links), vertex));
+        let prefixes = self.prefixes(edges, order);
+        for (_, vertex) in candidates {
+            let (step, _) = self.step(edges, order, vertex).expect("candidates have links");
+            order.push(vertex);
+            self.search(vertices, edges, order, cost + prefixes * step, best);
+            order.pop();
+        }
+    }
+
+    // the estimated prefixes binding the vertices of `order`, from one update.
+    fn prefixes(&self, edges: &[(usize, usize)], order: &[usize]) -> f64 {
+        (2 .. order.len()).fold(1.0, |prefixes, bound| {
+            prefixes * self.step(edges, &order[.. bound], order[bound]).map(|x| x.1).unwrap_or(0.0)
+        })
+    }
+
+    // the work for each prefix binding `vertex` after `bound`, and the prefixes each yields, or
+    // `None` if no edge joins the vertex to those bound.
+    fn step(&self, edges: &[(usize, usize)], bound: &[usize], vertex: usize) -> Option<(f64, f64)> {
+        let degrees = self.links(edges, bound, vertex).into_iter().map(|x| x.1).collect::<Vec<_>>();
+        if degrees.is_empty() { return None; }
+        let least = degrees.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        let vertices = ::std::cmp::max(self.statistics.vertices(), 1) as f64;
+        // counting consults every link, and each proposal is intersected with the other links.
+        let work = degrees.len() as f64 + least * degrees.len() as f64;
+        let mut survivors = least;
+        let mut skipped = false;
+        for &degree in degrees.iter() {
+            if degree == least && !skipped { skipped = true; }
+            else { survivors *= (degree / vertices).min(1.0); }
+        }
+        Some((work, survivors))
+    }
+
+    // the pattern edges joining `vertex` to those of `bound`, with the degrees of the bound
+    // vertices in the directions that reach `vertex`.
+    fn links(&self, edges: &[(usize, usize)], bound: &[usize], vertex: usize) -> Vec<((usize, usize), f64)> {
+        edges.iter().filter_map(|&(src, dst)| {
+            if dst == vertex && bound.contains(&src) { Some(((src, dst), self.statistics.out_degree())) }
+            else if src == vertex && bound.contains(&dst) { Some(((src, dst), self.statistics.in_degree())) }
+            else { None }
+        }).collect()
+    }
+
+    // the links of each step of `order` whose degree exceeds the least of the step by the ratio.
+    fn checked(&self, edges: &[(usize, usize)], order: &[usize]) -> Vec<(usize, usize)> {
+        let mut checked = Vec::new();
+        for bound in 2 .. order.len() {
+            let links = self.links(edges, &order[.. bound], order[bound]);
+            let least = links.iter().map(|x| x.1).fold(::std::f64::INFINITY, f64::min);
+            checked.extend(links.into_iter().filter(|x| x.1 > least * self.ratio).map(|x| x.0));
+        }
+        checked
+    }
+}
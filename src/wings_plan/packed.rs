@@ -0,0 +1,125 @@
+//! Prefixes packed into as few bits per vertex as their largest vertex needs.
+//!
+//! Prefixes are exchanged between workers at every stage of a plan, and each vertex of a prefix
+//! takes four bytes as a `Vec<Node>`. Graphs whose vertex ids are much smaller than `Node::MAX`,
+//! as those of a graph with a million vertices numbered densely are, need far fewer bits. A
+//! `PackedPrefix` holds the vertices of a prefix in words of 64 bits, each vertex taking the bits
+//! the largest of them needs, and widens when a vertex needing more is pushed. Plans exchange
+//! packed prefixes under `PrefixEncoding::Packed` (see `Plan::set_prefix_encoding`), packing the
+//! updated edges as they read them and unpacking the matches of query nodes as they report them,
+//! so that only the exchanged data change.
+//!
+//! Packing pays for long prefixes of small vertices: a prefix of five vertices below `2^20` takes
+//! two words rather than twenty bytes. Short prefixes of large vertices may take a few more bytes
+//! than they would otherwise, and every access to a vertex shifts and masks its bits.
+
+use std::fmt;
+
+use abomonation::Abomonation;
+
+use ::{Indexable, Node};
+
+/// The vertices of a prefix, packed into equally wide fields of 64-bit words.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct PackedPrefix {
+    // the bits each vertex takes, between one and 32.
+    width: u8,
+    // the number of vertices.
+    length: u8,
+    words: Vec<u64>,
+}
+
+impl PackedPrefix {
+    /// Packs `nodes` into as few bits each as the largest of them needs.
+    ///
+    /// Panics if there are more than 255 nodes.
+    pub fn pack(nodes: &[Node]) -> Self {
+        let width = nodes.iter().map(|&node| width(node)).max().unwrap_or(1);
+        let mut packed = PackedPrefix { width, length: 0, words: Vec::new() };
+        for &node in nodes.iter() { packed.append(node); }
+        packed
+    }
+
+    /// The vertices of the prefix, in order.
+    pub fn unpack(&self) -> Vec<Node> {
+        (0 .. self.len()).map(|index| self.get(index)).collect()
+    }
+
+    /// The vertex at `index`.
+    ///
+    /// Panics if `index` is not less than the prefix's length.
+    pub fn get(&self, index: usize) -> Node {
+        assert!(index < self.len(), "index {} of a prefix of {} vertices", index, self.len());
+        let width = self.width as usize;
+        let (word, offset) = (index * width / 64, index * width % 64);
+        let mut value = self.words[word] >> offset;
+        if offset + width > 64 { value |= self.words[word + 1] << (64 - offset); }
+        (value & ((1u64 << width) - 1)) as Node
+    }
+
+    /// Appends `node`, repacking the prefix more widely if `node` needs more bits than it has.
+    pub fn push(&mut self, node: Node) {
+        if width(node) > self.width {
+            let nodes = self.unpack();
+            *self = PackedPrefix { width: width(node), length: 0, words: Vec::new() };
+            for node in nodes { self.append(node); }
+        }
+        self.append(node);
+    }
+
+    /// The number of vertices in the prefix.
+    pub fn len(&self) -> usize { self.length as usize }
+
+    /// True if the prefix has no vertices.
+    pub fn is_empty(&self) -> bool { self.length == 0 }
+
+    /// The bits each vertex of the prefix takes.
+    pub fn width(&self) -> usize { self.width as usize }
+
+    /// The bytes the packed vertices take.
+    pub fn packed_bytes(&self) -> usize { 8 * self.words.len() }
+
+    // appends `node`, which must fit the prefix's width.
+    fn append(&mut self, node: Node) {
+        assert!(self.length < u8::max_value(), "packed prefixes hold at most 255 vertices");
+        let width = self.width as usize;
+        let bit = self.len() * width;
+        let (word, offset) = (bit / 64, bit % 64);
+        while self.words.len() * 64 < bit + width { self.words.push(0); }
+        self.words[word] |= (node as u64) << offset;
+        if offset + width > 64 { self.words[word + 1] |= (node as u64) >> (64 - offset); }
+        self.length += 1;
+    }
+}
+
+// the bits `node` needs, at least one.
+fn width(node: Node) -> u8 {
+    ::std::cmp::max(32 - node.leading_zeros(), 1) as u8
+}
+
+impl Indexable<Node> for PackedPrefix {
+    #[inline(always)] fn index(&self, index: usize) -> Node { self.get(index) }
+    #[inline(always)] fn get_src(&self) -> Node { self.get(0) }
+    #[inline(always)] fn get_dst(&self) -> Node { self.get(1) }
+    #[inline(always)] fn find(&self, element: &Node) -> bool { (0 .. self.len()).any(|index| self.get(index) == *element) }
+    #[inline(always)] fn length(&self) -> usize { self.len() }
+    #[inline(always)] fn push(&mut self, node: Node) { PackedPrefix::push(self, node) }
+}
+
+impl fmt::Debug for PackedPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.unpack()).finish()
+    }
+}
+
+impl Abomonation for PackedPrefix {
+    unsafe fn entomb<W: ::std::io::Write>(&self, write: &mut W) -> ::std::io::Result<()> {
+        self.words.entomb(write)
+    }
+    unsafe fn exhume<'a, 'b>(&'a mut self, bytes: &'b mut [u8]) -> Option<&'b mut [u8]> {
+        self.words.exhume(bytes)
+    }
+    fn extent(&self) -> usize {
+        self.words.extent()
+    }
+}
@@ -0,0 +1,486 @@
+//! Plans from conjunctive queries written as Datalog rules.
+//!
+//! A query such as
+//!
+//! ```text
+//! cycle3(x0, x1, x2) :- edge(x0, x1), edge(x1, x2), edge(x2, x0).
+//! ```
+//!
+//! names a pattern by its head, and lists the pattern's edges as the atoms of its body. The head
+//! lists every variable of the body once, in the order in which matches are to be read; variables
+//! are identifiers. The trailing period is optional. Atoms name a relation declared by a `Schema`,
+//! `edge` unless one is given, and all atoms of a body name the same relation, which is the graph
+//! the plan reads.
+//!
+//! Each atom of the body yields a query node of the plan, whose prefixes start from an updated
+//! edge bound to the atom, as the hand-written plans do. The remaining variables are bound one at
+//! a time, each time choosing the unbound variable with the most atoms to those already bound, so
+//! that every extension intersects as many relations as it can. Query nodes bind the variables in
+//! different orders; `ParsedQuery::project` reads a match of any of them in the order of the head.
+//!
+//! An atom may name a vertex id in place of a variable, as `edge(42, b)` does: the pattern then has
+//! a vertex pinned to the id, which the head does not list, and whose query nodes extend only
+//! prefixes holding it (see `Plan::set_constant`). `tri(b, c) :- edge(42, b), edge(b, c), edge(c, 42)`
+//! finds the triangles through vertex 42. An atom may also name a parameter, as `edge($v, b)`
+//! does, pinning a vertex to a value supplied as the plan runs rather than when it is planned (see
+//! `Plan::set_parameter`): `tri(b, c) :- edge($v, b), edge(b, c), edge(c, $v)` is planned once,
+//! and finds the triangles through the vertices each binding of `$v` names. Parameters take slots
+//! in order of their first appearance, which `ParsedQuery::parameters` lists.
+//!
+//! An atom preceded by `!` names an edge that must be absent, so that rules can ask for induced
+//! patterns: `wedge(a, b, c) :- edge(a, b), edge(a, c), !edge(b, c), !edge(c, b)` finds the
+//! wedges whose ends are not adjacent either way. Such atoms only name terms other atoms name, and
+//! each yields a query node too, whose chain starts from an update to the absent edge and whose
+//! matches are negated (see `Plan::set_negated`), so that inserting the edge retracts the matches
+//! it destroys. Plans for such rules assume the graph holds each edge at most once.
+//!
+//! Patterns given as edge lists, as `timely_rule::motif` takes them, are planned the same way by
+//! `plan_motif`, as though by a rule whose head lists `x0`, `x1`, and so on and whose body lists
+//! the edges in order: `[(0,1), (1,2), (2,0)]` is planned as `motif(x0, x1, x2) :- edge(x0, x1),
+//! edge(x1, x2), edge(x2, x0)`.
+
+use std::collections::HashMap;
+
+use wings_plan::JoinHint;
+use wings_plan::plan::{NodeId, Plan, Vertex};
+use wings_plan::schema::Schema;
+use ::Node;
+
+/// A query parsed from a rule, and the plan that evaluates it.
+#[derive(Debug)]
+pub struct ParsedQuery {
+    /// The name of the rule's head.
+    pub name: String,
+    /// The variables of the head, in order.
+    pub variables: Vec<String>,
+    /// The relation the atoms of the body name.
+    pub relation: String,
+    /// The plan evaluating the query.
+    pub plan: Plan,
+    /// For each query node of the plan, the position in its matches of each variable of the head.
+    pub positions: Vec<(usize, Vec<usize>)>,
+    /// The parameters of the body, without their `$`, in order of their slots.
+    pub parameters: Vec<String>,
+}
+
+impl ParsedQuery {
+    /// Reads a match of query node `query` in the order of the head's variables.
+    ///
+    /// Panics if `query` is not a query node of the plan.
+    pub fn project(&self, query: usize, embedding: &[Node]) -> Vec<Node> {
+        let positions = match self.positions.iter().find(|x| x.0 == query) {
+            Some(&(_, ref positions)) => positions,
+            None => panic!("{} is not a query node of the plan", query),
+        };
+        positions.iter().map(|&position| embedding[position]).collect()
+    }
+}
+
+/// Parses a rule, e.g. `cycle3(x0,x1,x2) :- edge(x0,x1), edge(x1,x2), edge(x2,x0)`, into a plan.
+///
+/// Returns an error describing the first problem found, with the column it was found at if it
+/// is one of syntax. Besides malformed rules, the plans can not evaluate bodies with fewer than
+/// two atoms that must hold, atoms joining a variable to itself or repeating another, bodies that
+/// are not connected by the atoms that must hold, atoms that must not hold naming terms no other
+/// atom names or contradicting one that must, or heads that omit or repeat variables of the body.
+pub fn parse_query(text: &str) -> Result<ParsedQuery, String> {
+    parse_query_with(text, &Schema::default())
+}
+
+/// As `parse_query`, but checking the relations the atoms name against `schema`.
+pub fn parse_query_with(text: &str, schema: &Schema) -> Result<ParsedQuery, String> {
+
+    let mut tokens = Tokens::new(text);
+    let name = tokens.identifier()?;
+    let variables = tokens.arguments()?;
+    tokens.expect(":-")?;
+    let mut atoms = Vec::new();
+    let mut absent = Vec::new();
+    let mut named: Option<String> = None;
+    loop {
+        let negated = tokens.accept("!");
+        let column = tokens.column();
+        let relation = tokens.identifier()?;
+        schema.relation(&relation).map_err(|error| format!("column {}: {}", column, error))?;
+        match named {
+            Some(ref first) if *first != relation => {
+                return Err(format!("column {}: atoms name relations {} and {}, but plans read one relation", column, first, relation));
+            },
+            _ => { named = Some(relation); },
+        }
+        let arguments = tokens.terms()?;
+        if arguments.len() != 2 {
+            return Err(format!("column {}: atoms take two variables, vertex ids, or parameters, found {}", column, arguments.len()));
+        }
+        if negated { absent.push((arguments[0].clone(), arguments[1].clone())); }
+        else { atoms.push((arguments[0].clone(), arguments[1].clone())); }
+        if !tokens.accept(",") { break; }
+    }
+    tokens.accept(".");
+    tokens.end()?;
+
+    // the head names each variable once, and so numbers them.
+    let mut numbers = HashMap::new();
+    for variable in variables.iter() {
+        let number = numbers.len();
+        if numbers.insert(variable.clone(), number).is_some() {
+            return Err(format!("the head repeats variable {}", variable));
+        }
+    }
+    if let Some(variable) = variables.iter().find(|&variable| !atoms.iter().any(|atom| atom.0 == *variable || atom.1 == *variable)) {
+        return Err(format!("variable {} of the head appears in no atom that must hold", variable));
+    }
+    for &(ref src, ref dst) in absent.iter() {
+        if let Some(term) = [src, dst].iter().find(|&&term| !atoms.iter().any(|atom| atom.0 == *term || atom.1 == *term)) {
+            return Err(format!("atom !edge({}, {}) names {}, which no atom that must hold names", src, dst, term));
+        }
+    }
+    // vertex ids and parameters in the body are numbered after the variables, in order of their appearance.
+    let mut pins = Vec::new();
+    let mut parameters = Vec::new();
+    for &(ref src, ref dst) in atoms.iter() {
+        for term in [src, dst].iter() {
+            let pin = if term.starts_with('$') {
+                Pin::Parameter(parameters.len())
+            }
+            else if term.starts_with(|c: char| c.is_numeric()) {
+                Pin::Constant(term.parse::<Node>().map_err(|_| format!("{} is not a vertex id", term))?)
+            }
+            else { continue; };
+            if !numbers.contains_key(*term) {
+                numbers.insert((*term).clone(), variables.len() + pins.len());
+                if let Pin::Parameter(_) = pin { parameters.push(term[1 ..].to_owned()); }
+                pins.push(pin);
+            }
+        }
+    }
+    let number = |variable: &String| numbers.get(variable).cloned()
+        .ok_or_else(|| format!("variable {} appears in the body but not the head", variable));
+    let mut edges = Vec::new();
+    for &(ref src, ref dst) in atoms.iter() {
+        let edge = (number(src)?, number(dst)?);
+        if edge.0 == edge.1 {
+            return Err(format!("atom edge({}, {}) joins a variable to itself", src, dst));
+        }
+        if edges.contains(&edge) {
+            return Err(format!("atom edge({}, {}) is repeated", src, dst));
+        }
+        edges.push(edge);
+    }
+    let mut anti = Vec::new();
+    for &(ref src, ref dst) in absent.iter() {
+        let edge = (number(src)?, number(dst)?);
+        if edge.0 == edge.1 {
+            return Err(format!("atom !edge({}, {}) joins a variable to itself", src, dst));
+        }
+        if edges.contains(&edge) {
+            return Err(format!("atom !edge({}, {}) contradicts edge({}, {})", src, dst, src, dst));
+        }
+        if anti.contains(&edge) {
+            return Err(format!("atom !edge({}, {}) is repeated", src, dst));
+        }
+        anti.push(edge);
+    }
+
+    if edges.len() < 2 {
+        return Err("the body needs at least two atoms".to_owned());
+    }
+    let (mut plan, mut positions) = plan_pattern(variables.len() + pins.len(), &edges, &anti)?;
+    pin_vertices(&mut plan, &positions, variables.len(), &pins);
+    for query in positions.iter_mut() { query.1.truncate(variables.len()); }
+    let relation = named.expect("bodies have an atom");
+    Ok(ParsedQuery { name, variables, relation, plan, positions, parameters })
+}
+
+/// Plans the pattern with edges `edges`, e.g. `[(0,1), (1,2), (2,0)]`, whose vertices are numbered
+/// from zero, as `parse_query` plans a rule.
+///
+/// The query is named `motif`, its variables are `x0`, `x1`, and so on, and its atoms name `edge`.
+/// Returns an error if the pattern has fewer than two edges, edges joining a vertex to itself or
+/// repeating another, a vertex numbered below another but in no edge, or is not connected.
+pub fn plan_motif(edges: &[(usize, usize)]) -> Result<ParsedQuery, String> {
+    let vertices = edges.iter().map(|&(src, dst)| ::std::cmp::max(src, dst) + 1).max().unwrap_or(0);
+    if let Some(vertex) = (0 .. vertices).find(|&vertex| !edges.iter().any(|&(src, dst)| src == vertex || dst == vertex)) {
+        return Err(format!("vertex x{} appears in no edge", vertex));
+    }
+    check_pattern(vertices, edges)?;
+    let (plan, positions) = plan_pattern(vertices, edges, &[])?;
+    let variables = (0 .. vertices).map(|vertex| format!("x{}", vertex)).collect();
+    Ok(ParsedQuery { name: "motif".to_owned(), variables, relation: "edge".to_owned(), plan, positions, parameters: Vec::new() })
+}
+
+// a plan evaluating the pattern with `vertices` vertices, `edges` edges, and edges `anti` that
+// must be absent, and for each of its query nodes the position of each vertex in its matches.
+fn plan_pattern(vertices: usize, edges: &[(usize, usize)], anti: &[(usize, usize)]) -> Result<(Plan, Vec<(usize, Vec<usize>)>), String> {
+    let mut chains = Vec::new();
+    for &(src, dst) in edges.iter().chain(anti.iter()) {
+        let order = binding_order(vertices, edges, src, dst)
+            .ok_or_else(|| "the atoms of the body are not connected".to_owned())?;
+        chains.push(Chain { order, checked: Vec::new() });
+    }
+    plan_induced(vertices, edges, anti, &chains)
+}
+
+// what a vertex named in the body rather than the head is pinned to.
+enum Pin {
+    Constant(Node),
+    Parameter(usize),
+}
+
+// pins the vertices numbered from `first` on as `pins` says, at the node of each chain of `plan`
+// that binds them; `positions` gives the position of each vertex in the matches of each chain's
+// query node, as `plan_chains` returns them.
+fn pin_vertices(plan: &mut Plan, positions: &[(usize, Vec<usize>)], first: usize, pins: &[Pin]) {
+    for &(query, ref positions) in positions.iter() {
+        // chains number their nodes consecutively, binding a vertex at each after the first.
+        let steps = ::std::cmp::max(positions.len(), 3) - 2;
+        for (offset, pin) in pins.iter().enumerate() {
+            let position = positions[first + offset];
+            let node = query + 1 + ::std::cmp::max(position, 2) - 2 - steps;
+            let pinned = match *pin {
+                Pin::Constant(value) => plan.set_constant(node, position, Some(value)),
+                Pin::Parameter(slot) => plan.set_parameter(node, position, Some(slot)),
+            };
+            pinned.expect("chains bind each vertex at the node pinning it");
+        }
+    }
+}
+
+/// The order in which the chain of plan nodes for a pattern edge binds the pattern's vertices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chain {
+    /// The vertices in the order they are bound, starting with the source and destination of the
+    /// updated pattern edge.
+    pub order: Vec<usize>,
+    /// Pattern edges the chain checks by intersection rather than using to propose candidates for
+    /// the vertex they bind; see `JoinHint::Intersect`.
+    pub checked: Vec<(usize, usize)>,
+}
+
+/// Checks that plans can evaluate the pattern on `vertices` vertices with edges `edges`.
+///
+/// Patterns have at least two edges, which join distinct vertices, name each pair at most once,
+/// and together reach every vertex. The errors name vertices by their numbers.
+pub fn check_pattern(vertices: usize, edges: &[(usize, usize)]) -> Result<(), String> {
+    if edges.len() < 2 {
+        return Err("the pattern needs at least two edges".to_owned());
+    }
+    for (index, &(src, dst)) in edges.iter().enumerate() {
+        if src >= vertices || dst >= vertices {
+            return Err(format!("edge x{}->x{} reads a vertex beyond the pattern, which has {} vertices", src, dst, vertices));
+        }
+        if src == dst {
+            return Err(format!("edge x{}->x{} joins a vertex to itself", src, dst));
+        }
+        if edges[.. index].contains(&(src, dst)) {
+            return Err(format!("edge x{}->x{} is repeated", src, dst));
+        }
+    }
+    match binding_order(vertices, edges, edges[0].0, edges[0].1) {
+        Some(_) => Ok(()),
+        None => Err("the edges of the pattern are not connected".to_owned()),
+    }
+}
+
+/// A plan evaluating the pattern on `vertices` vertices with edges `edges`, with one chain of
+/// nodes for each edge binding the vertices in the order `chains` gives, one chain for each edge.
+///
+/// Returns the plan and, for each of its query nodes, the position in its matches of each vertex.
+pub fn plan_chains(vertices: usize, edges: &[(usize, usize)], chains: &[Chain]) -> Result<(Plan, Vec<(usize, Vec<usize>)>), String> {
+    plan_induced(vertices, edges, &[], chains)
+}
+
+/// As `plan_chains`, for a pattern whose edges `anti` must be absent.
+///
+/// Each chain checks that the edges of `anti` between the vertices it binds are absent, as it
+/// binds them. `chains` lists a chain for each edge of `edges`, and then one for each edge of
+/// `anti`, whose query node is negated; see `Plan::set_negated`.
+pub fn plan_induced(vertices: usize, edges: &[(usize, usize)], anti: &[(usize, usize)], chains: &[Chain]) -> Result<(Plan, Vec<(usize, Vec<usize>)>), String> {
+
+    check_pattern(vertices, edges)?;
+    if chains.len() != edges.len() + anti.len() {
+        return Err(format!("{} chains given for {} edges", chains.len(), edges.len() + anti.len()));
+    }
+    if let Some(&(src, dst)) = anti.iter().find(|&&(src, dst)| src == dst || src >= vertices || dst >= vertices || edges.contains(&(src, dst))) {
+        return Err(format!("edge x{}->x{} can not be absent from the pattern", src, dst));
+    }
+
+    // each edge's chain of nodes binds the remaining vertices in turn.
+    let mut builder = Plan::builder();
+    let mut nodes = 1;
+    let mut positions = Vec::new();
+    for (index, (&(src, dst), chain)) in edges.iter().chain(anti.iter()).zip(chains.iter()).enumerate() {
+        let negated = index >= edges.len();
+
+        let order = &chain.order;
+        if order.len() != vertices || order[.. 2] != [src, dst] {
+            return Err(format!("the chain of edge x{}->x{} does not start with it and bind each vertex", src, dst));
+        }
+        let mut position = vec![None; vertices];
+        for (index, &vertex) in order.iter().enumerate() {
+            if vertex >= vertices || position[vertex].is_some() {
+                return Err(format!("the chain of edge x{}->x{} does not bind each vertex once", src, dst));
+            }
+            position[vertex] = Some(index);
+        }
+        let position = position.into_iter().map(|x| x.expect("every vertex bound")).collect::<Vec<_>>();
+
+        // a pattern of two vertices only checks the edge opposite the updated one.
+        let steps = ::std::cmp::max(vertices, 3) - 2;
+        let mut source = NodeId::ROOT;
+        for step in 0 .. steps {
+            let node = NodeId(nodes);
+            nodes += 1;
+            builder = builder.add_node(node, step + 1 == steps).add_edge(source, node);
+            if negated && step + 1 == steps {
+                builder = builder.negated(node);
+            }
+            if step == 0 && edges.contains(&(dst, src)) {
+                builder = builder.intersection(Vertex(1), Vertex(0));
+            }
+            if step == 0 && anti.contains(&(dst, src)) {
+                builder = builder.intersection(Vertex(1), Vertex(0)).anti();
+            }
+            if let Some(&vertex) = order.get(step + 2) {
+                let bound = step + 2;
+                let mut linked = false;
+                for &(from, to) in edges.iter() {
+                    let extension = if to == vertex && position[from] < bound { Some((Vertex(position[from]), Vertex(bound))) }
+                                    else if from == vertex && position[to] < bound { Some((Vertex(bound), Vertex(position[to]))) }
+                                    else { None };
+                    if let Some((from_vertex, to_vertex)) = extension {
+                        builder = builder.extension(from_vertex, to_vertex);
+                        if chain.checked.contains(&(from, to)) { builder = builder.hint(JoinHint::Intersect); }
+                        linked = true;
+                    }
+                }
+                if !linked {
+                    return Err(format!("the chain of edge x{}->x{} binds x{} before any vertex it is joined to", src, dst, vertex));
+                }
+                for &(from, to) in anti.iter() {
+                    if to == vertex && position[from] < bound {
+                        builder = builder.extension(Vertex(position[from]), Vertex(bound)).anti();
+                    }
+                    else if from == vertex && position[to] < bound {
+                        builder = builder.extension(Vertex(bound), Vertex(position[to])).anti();
+                    }
+                }
+            }
+            source = node;
+        }
+        positions.push(position);
+    }
+    let plan = builder.build()?;
+    let positions = plan.queries().into_iter().zip(positions.into_iter()).collect();
+    Ok((plan, positions))
+}
+
+// the order in which to bind the vertices, starting from the updated edge `(src, dst)` and then
+// choosing the vertex with the most edges to those bound, or `None` if some vertex is unreachable.
+fn binding_order(vertices: usize, edges: &[(usize, usize)], src: usize, dst: usize) -> Option<Vec<usize>> {
+    let mut order = vec![src, dst];
+    while order.len() < vertices {
+        let links = |vertex: usize| edges.iter().filter(|&&(from, to)| {
+            (from == vertex && order.contains(&to)) || (to == vertex && order.contains(&from))
+        }).count();
+        let next = (0 .. vertices)
+            .filter(|vertex| !order.contains(vertex))
+            .map(|vertex| (links(vertex), vertex))
+            .filter(|x| x.0 > 0)
+            .max_by_key(|&(links, vertex)| (links, ::std::cmp::Reverse(vertex)))?;
+        order.push(next.1);
+    }
+    Some(order)
+}
+
+// a cursor over the text of a rule, skipping whitespace between tokens.
+struct Tokens<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokens { text, offset: 0 }
+    }
+
+    fn rest(&mut self) -> &'a str {
+        let rest = &self.text[self.offset ..];
+        let trimmed = rest.trim_start();
+        self.offset += rest.len() - trimmed.len();
+        trimmed
+    }
+
+    // the column of the next token, counting from one.
+    fn column(&mut self) -> usize {
+        self.rest();
+        self.text[.. self.offset].chars().count() + 1
+    }
+
+    fn accept(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) { self.offset += token.len(); true } else { false }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.accept(token) { Ok(()) } else { Err(self.unexpected(token)) }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        let rest = self.rest();
+        let length = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if length == 0 || rest.starts_with(|c: char| c.is_numeric()) {
+            return Err(self.unexpected("an identifier"));
+        }
+        self.offset += length;
+        Ok(rest[.. length].to_owned())
+    }
+
+    // an identifier, a vertex id, or a parameter: an identifier following `$`.
+    fn term(&mut self) -> Result<String, String> {
+        if self.accept("$") {
+            return Ok(format!("${}", self.identifier()?));
+        }
+        let rest = self.rest();
+        let length = rest.find(|c: char| !c.is_numeric()).unwrap_or(rest.len());
+        if length == 0 { return self.identifier(); }
+        if rest[length ..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return Err(self.unexpected("an identifier or a vertex id"));
+        }
+        self.offset += length;
+        Ok(rest[.. length].to_owned())
+    }
+
+    // a parenthesized list of terms, separated by commas.
+    fn terms(&mut self) -> Result<Vec<String>, String> {
+        self.expect("(")?;
+        let mut terms = vec![self.term()?];
+        while self.accept(",") {
+            terms.push(self.term()?);
+        }
+        self.expect(")")?;
+        Ok(terms)
+    }
+
+    // a parenthesized list of identifiers, separated by commas.
+    fn arguments(&mut self) -> Result<Vec<String>, String> {
+        self.expect("(")?;
+        let mut arguments = vec![self.identifier()?];
+        while self.accept(",") {
+            arguments.push(self.identifier()?);
+        }
+        self.expect(")")?;
+        Ok(arguments)
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        if self.rest().is_empty() { Ok(()) } else { Err(self.unexpected("the end of the rule")) }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> String {
+        let column = self.column();
+        match self.rest().chars().next() {
+            Some(found) => format!("column {}: expected {}, found {:?}", column, expected, found),
+            None => format!("column {}: expected {}, found the end of the rule", column, expected),
+        }
+    }
+}
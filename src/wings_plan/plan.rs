@@ -0,0 +1,2470 @@
+//! Unlabeled query plans.
+//!
+//! A `Plan` is a DAG of plan nodes, each describing a partially bound pattern, connected by plan
+//! edges describing the extensions and intersections that take a prefix from one node to the next.
+//! Query nodes produce complete embeddings, which `track_motif` returns as streams rather than
+//! folding them into shared counters, leaving the choice of sink to the caller. A query node may
+//! have its changes sorted before they are returned; see `set_sorted_output`, and projected onto
+//! some of its vertices, with the changes to each projected tuple summed; see `set_projection`.
+//! Query nodes may also contribute their matches to aggregates grouped by a key, such as the number
+//! of triangles through each vertex, which `track_aggregates` maintains; see `set_aggregation`.
+//! Plans over undirected graphs, or counting subgraphs rather than embeddings, are described by
+//! `set_orientation` and `set_counting`.
+//!
+//! Plan files take optional fields after the ones every line has:
+//!
+//! * a plan edge line may carry a fourth field, `1` to always propose from the relation of its
+//!   first extension, skipping the count step of generic join; see `PlanBuilder::propose_first`.
+//! * a plan edge leaving the root may end with `edge_label=L`, after its fourth field, to read only
+//!   the updates to edges labeled `L`; see `set_update_label`.
+//! * a plan edge may end with `hash_join`, after its fourth field, to propose from its first
+//!   extension with a hash join; see `PlanBuilder::hash_join`.
+//! * any plan edge may end with filters such as `filter=x2.3>=30`, comparing attribute 3 of `x2`
+//!   with 30; see `set_vertex_filters`.
+//! * an operation line may carry `extend` or `intersect`, overriding how the operation is
+//!   classified; see `set_join_hint`.
+//! * `forward`, `reverse`, or `adaptive` on an operation line choose the indices that check it;
+//!   see `set_operation_direction`.
+//! * `first=K` or `heaviest=K` admit only the first `K` neighbors of the vertex the operation
+//!   reads, by id or by weight; see `set_extension_limit`.
+//! * `label=L` requires the vertex the operation binds to have label `L`; see
+//!   `set_operation_label`.
+//! * `edge_label=L` on an operation line requires its pattern edge to have label `L`; see
+//!   `set_edge_label`.
+//! * `source=S` reads the operation's pattern edge from the source registered as `S` rather than
+//!   the graph; see `set_operation_source`.
+//!
+//! Operations written from the vertex their edge binds, as some generators write every pattern
+//! edge from its source, are reversed as the plan is read, with a warning that
+//! `from_reader_with_warnings` returns; see `orient_operations`.
+//!
+//! Each plan edge begins by exchanging the prefixes of its parent node to the workers holding the
+//! keys it probes first. When several edges leaving a node probe the same index by the same
+//! attribute, the node's prefixes are exchanged once and fanned out locally to each of them, whose
+//! own exchanges then keep every prefix on its worker. Bushy plans, whose nodes have many children,
+//! serialize and send each prefix once per distinct first key rather than once per child.
+//!
+//! Edges leaving a node that apply the same operations to the same prefixes produce the same
+//! prefixes, and are executed as one stage, whose output the children of all of them extend (see
+//! `shared_stages`). Plans for several queries combined with `combine`, e.g. triangles and
+//! 4-cliques, thus match the sub-patterns their plans share, such as the wedge both first extend
+//! an updated edge to, once for all of them.
+//!
+//! A plan node may pin vertices of its prefixes to constants (see `set_constant`), so that a query
+//! anchored at a vertex, e.g. the triangles containing vertex 42, extends only the prefixes that
+//! hold it. The edge into the node filters its input by the pins of vertices bound before it, so
+//! that other prefixes are never extended, and its output by the pin of the vertex it binds.
+//! Vertices may instead be pinned to parameters (see `set_parameter`), whose values are supplied
+//! as the plan runs by a stream of bindings (see `track_motif_bound` and `bindings`), so that the
+//! dataflow of a plan prepared once answers for anchors chosen batch by batch.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::BufReader;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use timely::dataflow::*;
+use timely::dataflow::operators::*;
+use timely::ExchangeData;
+
+use super::graph_stream::GraphStreamIndex;
+use wings_plan::{Counting, ExtendEdges, ExtensionLimit, GlobalCounts, IntersectDirection, JoinHint, LimitOrder, MatchSemantics, Orientation, Placement, PrefixEncoding};
+use wings_plan::conventions::{canonical_matches, pattern_automorphisms};
+use wings_plan::distinct::distinct_matches;
+use wings_plan::sorted::{order_matches, sort_matches, SortedOutput};
+use wings_plan::projection::project_matches;
+use wings_plan::aggregation::{Aggregation, maintain_groups};
+use wings_plan::agm::fractional_edge_cover;
+use wings_plan::optimizer::DegreeStatistics;
+use wings_plan::packed::PackedPrefix;
+use wings_plan::limit::limit_matches;
+use wings_plan::bindings::bind_prefixes;
+use wings_plan::sources::ExtensionSource;
+use wings_plan::filters::{AttributeFilter, VertexAttributes};
+use wings_plan::document::{PlanDocument, NodeDocument, EdgeDocument, OperationDocument, OperationKind};
+
+pub use ::{Node, Edge};
+use ::{Indexable, Weight};
+
+#[derive(Debug, Default)]
+pub struct PlanNode{
+    // edges[edge_start_idx, edge_start_idx + num_edges] are the out edges of this node
+    edge_start_idx: usize,
+    num_edges: usize,
+    subgraph_num_vertices: usize,
+    is_query: bool,
+    idx: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PlanOperation{
+    src_key: usize,
+    dst_key: usize,
+    is_forward: bool,
+    hint: Option<JoinHint>,
+    // the indices checking the operation if it is an intersection, overriding the plan's.
+    direction: Option<IntersectDirection>,
+    // the neighbors the operation admits, if it binds a vertex and is limited.
+    limit: Option<ExtensionLimit>,
+    // whether the pattern edge must be absent, rather than present.
+    anti: bool,
+    // the label the vertex the operation binds must have, if it binds one and requires a label.
+    label: Option<u32>,
+    // the label the pattern edge must have, read from the indices of the edges with it.
+    edge_label: Option<u32>,
+    // the registered source the pattern edge is read from, in place of the graph indices.
+    source: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct PlanEdge{
+    src: Rc<PlanNode>,
+    dst: Rc<PlanNode>,
+    operations: Vec<PlanOperation>,
+    extensions: Vec<PlanOperation>,
+    intersections: Vec<PlanOperation>,
+    // extensions hinted to be checked as intersections once the new vertex is bound.
+    checks: Vec<PlanOperation>,
+    // operations reaching the new vertex whose pattern edges must be absent, removing proposals.
+    exclusions: Vec<PlanOperation>,
+    propose_first: bool,
+    // whether the edge proposes from its first extension with a hash join, rather than a sort-merge join.
+    hash_join: bool,
+    // the label the updated edge must have, if the edge leaves the root and requires one.
+    update_label: Option<u32>,
+    // the attribute filters of the vertices the edge's prefixes bind.
+    filters: Vec<AttributeFilter>,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan{
+    edges: Vec<PlanEdge>,
+    nodes: Vec<Rc<PlanNode>>,
+    root_node_id: usize,
+    min_degree: Option<usize>,
+    direction: IntersectDirection,
+    semantics: MatchSemantics,
+    orientation: Orientation,
+    counting: Counting,
+    placement: Placement,
+    copies: usize,
+    global: GlobalCounts,
+    standing: bool,
+    deterministic: bool,
+    encoding: PrefixEncoding,
+    sorted: BTreeMap<usize, SortedOutput>,
+    // the vertices pinned to constants, by node and position.
+    constants: BTreeMap<(usize, usize), Node>,
+    // the vertices pinned to parameter slots, by node and position.
+    parameters: BTreeMap<(usize, usize), usize>,
+    // the query nodes whose matches are reported with their weights negated.
+    negated: BTreeSet<usize>,
+    // the positions the query nodes with projections report of their matches, in order.
+    projections: BTreeMap<usize, Vec<usize>>,
+    // the aggregates the query nodes contribute their matches to.
+    aggregations: BTreeMap<usize, Aggregation>,
+    // the sources operations read in place of the graph indices, by id.
+    sources: Sources,
+}
+
+// the sources registered with a plan, which describes them by id only.
+#[derive(Clone, Default)]
+struct Sources(BTreeMap<u32, Arc<ExtensionSource>>);
+
+impl ::std::fmt::Debug for Sources {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+impl Plan{
+    /// Constructs the dataflow for the plan, returning the match stream of each query node.
+    ///
+    /// Each stream carries changes to embeddings of the query node's pattern, paired with the
+    /// index of the query node that produced them. Changes are weighed as `W`, to which the diffs
+    /// of the graph's updates are converted as the plan reads them.
+    pub fn track_motif<W: Weight, H1, H2, G: Scope>(&self, graph: &GraphStreamIndex<G, H1, H2>) -> Vec<(usize, Stream<G, (Vec<Node>, W)>)>
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static
+    {
+        self.track_motif_over(&[graph])
+    }
+
+    /// As `track_motif`, but reading copies of the graph indices.
+    ///
+    /// Plan edge `i` reads the copy `graphs[i % graphs.len()]`, so that when the copies route keys
+    /// to workers differently (see `set_index_copies`) the stages of the plan process a heavy key
+    /// at different workers. The copies must hold the same graph.
+    ///
+    /// Panics if the plan has parameters, whose bindings `track_motif_bound` supplies.
+    pub fn track_motif_over<W: Weight, H1, H2, G: Scope>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>]) -> Vec<(usize, Stream<G, (Vec<Node>, W)>)>
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static
+    {
+        if self.parameter_slots() > 0 {
+            panic!("EXCEPTION: the plan has {} parameters, and needs a stream of their bindings", self.parameter_slots());
+        }
+        self.track(graphs, None)
+    }
+
+    /// As `track_motif_over`, reading the values of the plan's parameters from `bindings`.
+    ///
+    /// `bindings` carries changes to the bindings in effect, each listing a value for every
+    /// parameter slot of the plan, and may be supplied at any worker; see `bindings`. Panics if a
+    /// binding has fewer values than the plan has parameter slots.
+    pub fn track_motif_bound<W: Weight, H1, H2, G: Scope>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], bindings: &Stream<G, (Vec<Node>, i32)>) -> Vec<(usize, Stream<G, (Vec<Node>, W)>)>
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static
+    {
+        self.track(graphs, Some(&bindings.broadcast()))
+    }
+
+    /// Maintains the aggregates the plan's query nodes contribute to, from their matches as
+    /// `track_motif` returns them; see `set_aggregation`.
+    ///
+    /// Returns, for each group in order, the aggregates of the keys each time changes, as `(key,
+    /// matches, value)` once the time is complete; see `aggregation`. Query nodes missing from
+    /// `matches` contribute nothing. Labels and attributes of vertices are read from `graph`, and
+    /// panics if an aggregation reads labels or attributes the graph does not have.
+    pub fn track_aggregates<W: Weight, H1, H2, G: Scope>(&self, graph: &GraphStreamIndex<G, H1, H2>, matches: &[(usize, Stream<G, (Vec<Node>, W)>)]) -> Vec<(usize, Stream<G, (Vec<Node>, i64, f64)>)>
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static
+    {
+        let mut groups: BTreeMap<usize, Vec<Stream<G, (Vec<Node>, i64, f64)>>> = BTreeMap::new();
+        for &(query, ref stream) in matches.iter() {
+            if let Some(aggregation) = self.aggregations.get(&query) {
+                let labels = if !aggregation.reads_labels() { None } else {
+                    match graph.vertex_labels {
+                        Some(ref labels) => Some(labels.clone()),
+                        None => panic!("EXCEPTION: query node {} aggregates by the labels of vertices, but the graph indices have no vertex labels", query),
+                    }
+                };
+                let attributes = if !aggregation.reads_attributes() { None } else {
+                    match graph.vertex_attributes {
+                        Some(ref attributes) => Some(attributes.clone()),
+                        None => panic!("EXCEPTION: query node {} aggregates the attributes of vertices, but the graph indices have no vertex attributes", query),
+                    }
+                };
+                let group = aggregation.group;
+                let aggregation = aggregation.clone();
+                let changes = stream.flat_map(move |(embedding, weight)| {
+                    aggregation.contribution(&embedding, weight.to_i64(), labels.as_ref().map(|labels| &**labels), attributes.as_ref().map(|attributes| &**attributes))
+                });
+                groups.entry(group).or_insert(Vec::new()).push(changes);
+            }
+        }
+        groups.into_iter().map(|(group, streams)| {
+            let changes = streams[0].scope().concatenate(streams);
+            (group, maintain_groups(&changes))
+        }).collect()
+    }
+
+    // the match streams of the query nodes, reading parameters from `bindings`, which every worker holds.
+    fn track<W: Weight, H1, H2, G: Scope>(&self, graphs: &[&GraphStreamIndex<G, H1, H2>], bindings: Option<&Stream<G, (Vec<Node>, i32)>>) -> Vec<(usize, Stream<G, (Vec<Node>, W)>)>
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static
+    {
+        assert!(!graphs.is_empty(), "plans need at least one copy of the graph indices");
+        let violations = self.validate();
+        if !violations.is_empty() { panic!("EXCEPTION: inconsistent plan: {}", violations.join("; ")); }
+        for (index, edge) in self.edges.iter().enumerate() {
+            if let Some(source) = edge.operations.iter().filter_map(|operation| operation.source).find(|source| !self.sources.0.contains_key(source)) {
+                panic!("EXCEPTION: plan edge {} reads source {}, which is not registered; see `set_source`", index, source);
+            }
+        }
+        if self.deterministic {
+            for graph in graphs.iter() { graph.set_deterministic(true); }
+        }
+        let mut results = Vec::new();
+        let root = self.pins(self.root_node_id);
+        let updates = root.restrict(&graphs[0].updates.map(|(prefix, diff)| (prefix, W::from(diff))), bindings);
+        // edges leaving the root that require a label of the updated edge read only the updates with it.
+        let mut labeled = HashMap::new();
+        for label in self.edges.iter().filter_map(|edge| edge.update_label) {
+            labeled.entry(label).or_insert_with(|| root.restrict(&graphs[0].with_edge_label(Some(label)).updates.map(|(prefix, diff)| (prefix, W::from(diff))), bindings));
+        }
+        match self.encoding {
+            PrefixEncoding::Plain => self.execute_nodes(&[self.root_node_id], &updates, &labeled, graphs, bindings, &mut results),
+            PrefixEncoding::Packed => {
+                let mut packed = Vec::new();
+                let labeled = labeled.into_iter().map(|(label, updates)| (label, updates.map(|(prefix, diff)| (PackedPrefix::pack(&prefix), diff)))).collect();
+                self.execute_nodes(&[self.root_node_id], &updates.map(|(prefix, diff)| (PackedPrefix::pack(&prefix), diff)), &labeled, graphs, bindings, &mut packed);
+                results.extend(packed.into_iter().map(|(query, matches)| (query, matches.map(|(prefix, diff)| (prefix.unpack(), diff)))));
+            },
+        }
+        // negated query nodes retract the matches an edge that must be absent destroys; see `set_negated`.
+        let results = results.into_iter().map(|(query, matches)| {
+            if self.negated.contains(&query) { (query, matches.map(|(prefix, weight)| (prefix, -weight))) } else { (query, matches) }
+        }).collect::<Vec<_>>();
+        // matches below limited operations are kept while their pairs are among the first neighbors; see `limit`.
+        let mut tops = HashMap::new();
+        let results = results.into_iter().map(|(query, matches)| {
+            let mut limits = Vec::new();
+            self.collect_limits(self.root_node_id, query, &mut limits);
+            let matches = limits.into_iter().fold(matches, |matches, (positions, is_forward, limit)| {
+                let top = tops.entry((is_forward, limit)).or_insert_with(|| graphs[0].top_neighbors(is_forward, limit)).clone();
+                limit_matches(&matches, &top, positions)
+            });
+            (query, matches)
+        }).collect::<Vec<_>>();
+        let results = match self.counting {
+            Counting::Embeddings => results,
+            Counting::Subgraphs => results.into_iter().map(|(query, matches)| {
+                let vertices = self.nodes[query].subgraph_num_vertices;
+                let edges = self.pattern_edges(query).expect("query node unreachable from the root");
+                let anti = self.anti_edges(query).expect("query node unreachable from the root");
+                let mut automorphisms = pattern_automorphisms(vertices, &edges, self.orientation);
+                if !anti.is_empty() {
+                    // automorphisms of the pattern also map the edges that must be absent to each other.
+                    let preserving = pattern_automorphisms(vertices, &anti, self.orientation);
+                    automorphisms.retain(|permutation| preserving.contains(permutation));
+                }
+                (query, canonical_matches(&matches, automorphisms))
+            }).collect(),
+        };
+        // projected query nodes report the net change of each tuple of the positions they keep; see `set_projection`.
+        let results = results.into_iter().map(|(query, matches)| match self.projections.get(&query) {
+            Some(positions) => (query, project_matches(&matches, positions)),
+            None => (query, matches),
+        }).collect::<Vec<_>>();
+        let results = match self.semantics {
+            MatchSemantics::Attributed => results,
+            MatchSemantics::SetDifference { unordered } => {
+                results.into_iter().map(|(query, matches)| (query, distinct_matches(&matches, unordered))).collect()
+            },
+        };
+        results.into_iter().map(|(query, matches)| match self.sorted.get(&query) {
+            Some(config) => (query, sort_matches(&matches, config)),
+            None if self.deterministic => (query, order_matches(&matches)),
+            None => (query, matches),
+        }).collect()
+    }
+
+    // extends the prefixes of `nodes`, which all bind the same vertices and read `stream`, along
+    // their edges, executing each stage once for the edges sharing it; see `stages`. Edges leaving
+    // the root that require a label of the updated edge read `labeled` instead.
+    fn execute_nodes<W: Weight, H1, H2, G: Scope, P>(&self, nodes: &[usize], stream: &Stream<G, (P, W)>, labeled: &HashMap<u32, Stream<G, (P, W)>>, graphs: &[&GraphStreamIndex<G, H1, H2>], bindings: Option<&Stream<G, (Vec<Node>, i32)>>, results: &mut Vec<(usize, Stream<G, (P, W)>)>)
+        where H1: Fn(Node)->u64 + 'static,
+              H2: Fn(Node)->u64 + 'static,
+              P: ::std::fmt::Debug+ExchangeData+Indexable<Node>,
+    {
+        let stages = self.stages(nodes, graphs.len());
+
+        // stages whose first exchange routes prefixes alike, and that pin none of them, read prefixes
+        // exchanged once for all of them.
+        let route = |index: usize| if !self.pinned(index).0.is_empty() || self.edges[index].update_label.is_some() { None }
+            else { self.edges[index].first_route(self.direction).map(|route| (index % graphs.len(), route)) };
+        let mut sharing = HashMap::new();
+        for stage in stages.iter() {
+            if let Some(key) = route(stage[0]) { *sharing.entry(key).or_insert(0) += 1; }
+        }
+        let mut exchanged = HashMap::new();
+
+        for stage in stages {
+            let index = stage[0];
+            let graph = graphs[index % graphs.len()];
+
+            let (before, after) = self.pinned(index);
+            let plan_edge = &self.edges[index];
+            let input = match plan_edge.update_label {
+                Some(label) => &labeled[&label],
+                None => stream,
+            };
+            let stream = &match route(index) {
+                Some(key) if sharing[&key] > 1 => {
+                    exchanged.entry(key).or_insert_with(|| exchange_prefixes(input, graph, key.1)).clone()
+                },
+                _ => before.restrict(input, bindings),
+            };
+
+            // operations requiring an edge label read the indices of the edges with it.
+            let labels = plan_edge.edge_labels();
+            let extend_attributes = plan_edge.get_extend_attributes();
+
+            // filters of vertices bound before the edge remove the prefixes it reads, and those of
+            // the vertex it binds the candidates its extensions propose.
+            let new = plan_edge.src.subgraph_num_vertices;
+            let (earlier, binding): (Vec<_>, Vec<_>) = plan_edge.filters.iter().cloned().partition(|filter| filter.vertex < new);
+            let mut stream = stream.clone();
+            if !earlier.is_empty() {
+                let attributes = vertex_attributes(graph, index);
+                stream = stream.filter(move |x| earlier.iter().all(|filter| filter.accepts(&attributes, x.0.index(filter.vertex))));
+            }
+
+            // prefixes must have the edges between their vertices that are present, and lack those that are absent.
+            for (src, dst, source) in plan_edge.get_source_attributes(false) {
+                let source = self.sources.0[&source].clone();
+                stream = stream.filter(move |x| source.contains(x.0.index(src), x.0.index(dst)));
+            }
+            for &label in labels.iter() {
+                let intersect_attributes = plan_edge.get_intersect_attributes(self.direction, false, label);
+                if !intersect_attributes.is_empty() { stream = stream.intersect_attributes_each(graph.with_edge_label(label), &intersect_attributes); }
+            }
+            for &label in labels.iter() {
+                let anti_attributes = plan_edge.get_intersect_attributes(self.direction, true, label);
+                if !anti_attributes.is_empty() { stream = stream.anti_intersect_attributes_each(graph.with_edge_label(label), &anti_attributes); }
+            }
+
+            let output = if plan_edge.extensions.is_empty(){
+                stream
+            }
+            else {
+                let proposals = if plan_edge.extensions.iter().any(|operation| operation.source.is_some()) {
+                    let indices = plan_edge.extensions.iter().map(|operation| graph.with_edge_label(operation.edge_label)).collect::<Vec<_>>();
+                    let sources = plan_edge.extensions.iter().map(|operation| operation.source.map(|source| self.sources.0[&source].clone())).collect::<Vec<_>>();
+                    stream.extend_attributes_sourced(&indices, &extend_attributes, &sources, plan_edge.get_label(), plan_edge.propose_first)
+                }
+                else if plan_edge.extensions.iter().any(|operation| operation.edge_label.is_some()) {
+                    let indices = plan_edge.extensions.iter().map(|operation| graph.with_edge_label(operation.edge_label)).collect::<Vec<_>>();
+                    stream.extend_attributes_over(&indices, &extend_attributes, plan_edge.get_label(), plan_edge.propose_first)
+                }
+                else {
+                    match plan_edge.get_label() {
+                        Some(label) => stream.extend_attributes_labeled(graph, &extend_attributes, label, plan_edge.propose_first),
+                        None if plan_edge.hash_join => stream.extend_attributes_hashed(graph, &extend_attributes),
+                        None if plan_edge.propose_first => stream.extend_attributes_first(graph, &extend_attributes),
+                        None => stream.extend_attributes(graph, &extend_attributes),
+                    }
+                };
+                let proposals = labels.iter().fold(proposals, |proposals, &label| {
+                    exclude_proposals(&proposals, graph.with_edge_label(label), &plan_edge.get_exclude_attributes(label))
+                });
+                let proposals = if binding.is_empty() { proposals } else {
+                    let attributes = vertex_attributes(graph, index);
+                    proposals.map(move |(p, mut es, w)| {
+                        es.retain(|&e| binding.iter().all(|filter| filter.accepts(&attributes, e)));
+                        (p, es, w)
+                    })
+                };
+                proposals
+                    .flat_map(|(p, es, w)|
+                        es.into_iter().map(move |e|  {
+                            let mut clone = p.clone();
+                            clone.push(e);
+                            (clone, w)
+                        }))
+            };
+
+            let mut output = output;
+            for (src, dst, source) in plan_edge.get_source_attributes(true) {
+                let source = self.sources.0[&source].clone();
+                output = output.filter(move |x| source.contains(x.0.index(src), x.0.index(dst)));
+            }
+            for &label in labels.iter() {
+                let check_attributes = plan_edge.get_check_attributes(self.direction, false, label);
+                if !check_attributes.is_empty() { output = output.intersect_attributes_each(graph.with_edge_label(label), &check_attributes); }
+            }
+            for &label in labels.iter() {
+                let anti_check_attributes = plan_edge.get_check_attributes(self.direction, true, label);
+                if !anti_check_attributes.is_empty() { output = output.anti_intersect_attributes_each(graph.with_edge_label(label), &anti_check_attributes); }
+            }
+            let output = after.restrict(&output, bindings);
+
+            let children = stage.iter().map(|&index| self.edges[index].dst.idx).collect::<Vec<_>>();
+            for &child in children.iter().filter(|&&child| self.nodes[child].is_query) {
+                results.push((child, output.clone()));
+            }
+            self.execute_nodes(&children, &output, labeled, graphs, bindings, results);
+        }
+    }
+
+    // the edges of `nodes`, grouped into stages of edges that read the same copy of the indices
+    // with the same operations and lead to nodes pinning the same vertices, in order of their first
+    // edges. The edges of a stage produce the same prefixes from the same input, so they are
+    // executed once; limits are applied to the matches of query nodes, and do not distinguish stages.
+    fn stages(&self, nodes: &[usize], copies: usize) -> Vec<Vec<usize>> {
+        let mut stages: Vec<Vec<usize>> = Vec::new();
+        let mut positions = HashMap::new();
+        for &node in nodes.iter() {
+            let node = &self.nodes[node];
+            for index in node.edge_start_idx .. node.edge_start_idx + node.num_edges {
+                let edge = &self.edges[index];
+                let operations = edge.operations.iter().map(|op| (op.src_key, op.dst_key, op.is_forward, op.hint, op.direction, op.anti, op.label, op.edge_label, op.source)).collect::<Vec<_>>();
+                let pins = self.pins(edge.dst.idx);
+                let position = *positions.entry((index % copies, edge.propose_first, edge.hash_join, edge.update_label, operations, edge.filters.clone(), pins)).or_insert(stages.len());
+                if position == stages.len() { stages.push(Vec::new()); }
+                stages[position].push(index);
+            }
+        }
+        stages
+    }
+
+    // the pins of the node plan edge `index` leads to, split into those of vertices bound before
+    // the edge, checked as prefixes enter it, and that of the vertex it binds, checked as they leave.
+    fn pinned(&self, index: usize) -> (Pins, Pins) {
+        let edge = &self.edges[index];
+        let bound = edge.src.subgraph_num_vertices;
+        let pins = self.pins(edge.dst.idx);
+        let (constants_before, constants_after): (Vec<_>, Vec<_>) = pins.constants.into_iter().partition(|&(position, _)| position < bound);
+        let (parameters_before, parameters_after): (Vec<_>, Vec<_>) = pins.parameters.into_iter().partition(|&(position, _)| position < bound);
+        (Pins { constants: constants_before, parameters: parameters_before }, Pins { constants: constants_after, parameters: parameters_after })
+    }
+
+    // the constants and parameters plan node `node` pins.
+    fn pins(&self, node: usize) -> Pins {
+        Pins { constants: self.constants(node), parameters: self.parameters(node) }
+    }
+
+    /// The plan edges executed together, as groups of two or more edges.
+    ///
+    /// Plan edges leaving nodes that bind the same vertices from the same prefixes, and reading
+    /// the same copy of the indices with the same operations, produce the same prefixes. The
+    /// dataflow executes them as one stage, whose prefixes the children of all of them extend in
+    /// turn, so that sub-patterns shared by several queries, as those of plans combined with
+    /// `combine`, are matched once. Each group lists its edges in order.
+    pub fn shared_stages(&self) -> Vec<Vec<usize>> {
+        let mut shared = Vec::new();
+        let mut pending = vec![vec![self.root_node_id]];
+        while let Some(nodes) = pending.pop() {
+            for stage in self.stages(&nodes, self.index_copies()) {
+                pending.push(stage.iter().map(|&index| self.edges[index].dst.idx).collect());
+                if stage.len() > 1 { shared.push(stage); }
+            }
+        }
+        shared.sort();
+        shared
+    }
+
+    /// Restricts the plan to edges between vertices of degree at least `k`, or lifts the restriction.
+    ///
+    /// The restriction is applied to the graph before it is indexed; see `degree_filter`.
+    pub fn set_min_degree(&mut self, k: Option<usize>) {
+        self.min_degree = k;
+    }
+
+    /// The minimum degree of vertices whose edges the plan considers, if restricted.
+    pub fn min_degree(&self) -> Option<usize> {
+        self.min_degree
+    }
+
+    /// Sets the indices used to check edges between bound vertices. The default is `Forward`.
+    pub fn set_intersect_direction(&mut self, direction: IntersectDirection) {
+        self.direction = direction;
+    }
+
+    /// The indices used to check edges between bound vertices.
+    pub fn intersect_direction(&self) -> IntersectDirection {
+        self.direction
+    }
+
+    /// Sets how changes to matches are reported. The default is `Attributed`.
+    pub fn set_semantics(&mut self, semantics: MatchSemantics) {
+        self.semantics = semantics;
+    }
+
+    /// How changes to matches are reported.
+    pub fn semantics(&self) -> MatchSemantics {
+        self.semantics
+    }
+
+    /// Sets whether the graph and patterns are directed. The default is `Directed`.
+    ///
+    /// The plan only describes how its graph is to be read: drivers building indices for an
+    /// `Undirected` plan read each edge in both directions (see `conventions::symmetrize`), as
+    /// `Engine` does.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Whether the graph and patterns are directed.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Sets what the matches of query nodes count. The default is `Embeddings`.
+    ///
+    /// Counting `Subgraphs` reports one embedding of each matching subgraph, identifying the
+    /// embeddings related by automorphisms of the pattern, read as the plan's orientation says.
+    pub fn set_counting(&mut self, counting: Counting) {
+        self.counting = counting;
+    }
+
+    /// What the matches of query nodes count.
+    pub fn counting(&self) -> Counting {
+        self.counting
+    }
+
+    /// Sets the workers that maintain the indices and extend prefixes. The default is `All`.
+    ///
+    /// Input is read and routed on every worker regardless; see `Placement`.
+    pub fn set_placement(&mut self, placement: Placement) {
+        self.placement = placement;
+    }
+
+    /// The workers that maintain the indices and extend prefixes.
+    pub fn placement(&self) -> &Placement {
+        &self.placement
+    }
+
+    /// Sets the number of copies of the graph indices the plan reads. The default is one.
+    ///
+    /// A heavy key is otherwise processed by the same worker at every stage of the plan. Each copy
+    /// routes keys to workers with its own salt (see `Placement::salted_router`), and consecutive
+    /// plan edges read different copies, so that the work on a heavy key is spread over several
+    /// workers. Each copy holds the whole graph, so memory grows with the number of copies.
+    pub fn set_index_copies(&mut self, copies: usize) {
+        assert!(copies > 0, "plans need at least one copy of the graph indices");
+        self.copies = copies;
+    }
+
+    /// The number of copies of the graph indices the plan reads.
+    pub fn index_copies(&self) -> usize {
+        ::std::cmp::max(self.copies, 1)
+    }
+
+    /// Sets where engines executing the plan gather cluster-wide totals of matches. The default is `Off`.
+    ///
+    /// Every worker must execute the plan with the same setting; see `GlobalCounts`.
+    pub fn set_global_counts(&mut self, global: GlobalCounts) {
+        self.global = global;
+    }
+
+    /// Where engines executing the plan gather cluster-wide totals of matches.
+    pub fn global_counts(&self) -> GlobalCounts {
+        self.global
+    }
+
+    /// Sets whether engines executing the plan hold the standing matches of each query node, from
+    /// which consumers resync between epochs; see `Engine::standing_matches`. The default is not to.
+    ///
+    /// Every worker must execute the plan with the same setting, as each holds the matches it owns.
+    pub fn set_standing_matches(&mut self, enabled: bool) {
+        self.standing = enabled;
+    }
+
+    /// Whether engines executing the plan hold the standing matches of each query node.
+    pub fn standing_matches(&self) -> bool {
+        self.standing
+    }
+
+    /// Sets whether the plan executes deterministically, for debugging. The default is not to.
+    ///
+    /// Each stage reading the graph indices then waits for all prefixes of a time to arrive, and
+    /// processes them in sorted order (see `Index::set_deterministic`), and each worker emits the
+    /// changes to each query node's matches of a time in sorted order once the time is complete.
+    /// Two runs with the same input and workers then report the same changes to each query node in
+    /// the same order at each worker, so that their traces can be compared line by line. Prefixes are still routed by
+    /// key, as always. Each stage waits for the previous one to complete, which costs latency, and
+    /// enumeration interrupted by a time budget remains nondeterministic.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    /// Whether the plan executes deterministically.
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Sets how the plan's prefixes are represented as they are exchanged. The default is `Plain`.
+    ///
+    /// Every worker must execute the plan with the same setting; see `PrefixEncoding`.
+    pub fn set_prefix_encoding(&mut self, encoding: PrefixEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// How the plan's prefixes are represented as they are exchanged.
+    pub fn prefix_encoding(&self) -> PrefixEncoding {
+        self.encoding
+    }
+
+    /// Sorts the changes to the matches of query node `query` before output, or stops sorting them.
+    ///
+    /// Each time's changes are then emitted at the first worker, in order of embedding; see `sorted`.
+    pub fn set_sorted_output(&mut self, query: usize, config: Option<SortedOutput>) {
+        match config {
+            Some(config) => { self.sorted.insert(query, config); },
+            None => { self.sorted.remove(&query); },
+        }
+    }
+
+    /// How the changes to the matches of query node `query` are sorted, if they are.
+    pub fn sorted_output(&self, query: usize) -> Option<&SortedOutput> {
+        self.sorted.get(&query)
+    }
+
+    /// Pins the vertex at `position` of the prefixes of plan node `node` to `value`, or lifts the pin.
+    ///
+    /// The node and the nodes below it then only hold prefixes with `value` at `position`. The
+    /// prefixes are filtered as early as the plan allows: as they enter the edge into the node, if
+    /// the position is bound before it, and otherwise as the edge produces them, so that a pinned
+    /// vertex restricts the work of extending them rather than the matches reported. Returns an
+    /// error if the plan has no node `node`, or if the node binds no vertex at `position`.
+    pub fn set_constant(&mut self, node: usize, position: usize, value: Option<Node>) -> Result<(), String> {
+        let vertices = self.nodes.get(node).ok_or_else(|| format!("the plan has no node {}", node))?.subgraph_num_vertices;
+        if position >= vertices {
+            return Err(format!("node {} binds {} vertices, and none at position {}", node, vertices, position));
+        }
+        match value {
+            Some(value) => { self.constants.insert((node, position), value); },
+            None => { self.constants.remove(&(node, position)); },
+        }
+        Ok(())
+    }
+
+    /// The vertices plan node `node` pins, as positions and their constants, in order of position.
+    ///
+    /// Pins of the nodes above `node` hold for its prefixes as well, but are not repeated.
+    pub fn constants(&self, node: usize) -> Vec<(usize, Node)> {
+        self.constants.range((node, 0) .. (node + 1, 0)).map(|(&(_, position), &value)| (position, value)).collect()
+    }
+
+    /// Pins the vertex at `position` of the prefixes of plan node `node` to parameter slot `slot`,
+    /// or lifts the pin.
+    ///
+    /// As with `set_constant`, but the node and the nodes below it hold the prefixes whose vertex
+    /// at `position` takes the value at `slot` of some binding in effect, as `track_motif_bound`
+    /// reads them. Slots are numbered from zero, and the same slot may pin vertices of several
+    /// nodes, as the query nodes of a pattern anchored at one vertex do. Returns an error if the
+    /// plan has no node `node`, or if the node binds no vertex at `position`.
+    pub fn set_parameter(&mut self, node: usize, position: usize, slot: Option<usize>) -> Result<(), String> {
+        let vertices = self.nodes.get(node).ok_or_else(|| format!("the plan has no node {}", node))?.subgraph_num_vertices;
+        if position >= vertices {
+            return Err(format!("node {} binds {} vertices, and none at position {}", node, vertices, position));
+        }
+        match slot {
+            Some(slot) => { self.parameters.insert((node, position), slot); },
+            None => { self.parameters.remove(&(node, position)); },
+        }
+        Ok(())
+    }
+
+    /// The vertices plan node `node` pins to parameters, as positions and their slots, in order of
+    /// position.
+    pub fn parameters(&self, node: usize) -> Vec<(usize, usize)> {
+        self.parameters.range((node, 0) .. (node + 1, 0)).map(|(&(_, position), &slot)| (position, slot)).collect()
+    }
+
+    /// The number of values each binding of the plan's parameters lists: one more than the
+    /// greatest slot pinning a vertex, or zero if none does.
+    pub fn parameter_slots(&self) -> usize {
+        self.parameters.values().map(|&slot| slot + 1).max().unwrap_or(0)
+    }
+
+    /// Sets whether query node `query` reports its matches with their weights negated.
+    ///
+    /// A pattern with edges that must be absent (see `PlanBuilder::anti`) loses matches when one
+    /// of those edges is inserted, and gains them when it is deleted. A query node whose chain
+    /// starts from an update to such an edge, read as though it were present, finds the matches
+    /// the update affects; negated, it reports their retraction on insertion and their return on
+    /// deletion. Its pattern, as `pattern_edges` and `anti_edges` describe it, counts the root's
+    /// edge among those that must be absent. This assumes the graph holds each edge at most once,
+    /// as an edge inserted twice is only absent once both copies are deleted. Returns an error if
+    /// `query` is not a query node of the plan.
+    pub fn set_negated(&mut self, query: usize, negated: bool) -> Result<(), String> {
+        match self.nodes.get(query) {
+            Some(node) if node.is_query => { },
+            _ => return Err(format!("node {} is not a query node of the plan", query)),
+        }
+        if negated { self.negated.insert(query); } else { self.negated.remove(&query); }
+        Ok(())
+    }
+
+    /// Whether query node `query` reports its matches with their weights negated.
+    pub fn negated(&self, query: usize) -> bool {
+        self.negated.contains(&query)
+    }
+
+    /// Has query node `query` report only the vertices of its matches at `positions`, in the order
+    /// listed, or report whole matches again.
+    ///
+    /// Each time's changes to the matches projecting to a tuple are then summed, and the tuple is
+    /// reported once with the sum, unless it is zero; see `projection`. Projection follows the
+    /// counting of subgraphs, which reads whole matches, and precedes set semantics, which then
+    /// reports the changes to the set of projected tuples. Returns an error if `query` is not a
+    /// query node of the plan, or if a position is listed twice or binds no vertex of its matches.
+    pub fn set_projection(&mut self, query: usize, positions: Option<Vec<usize>>) -> Result<(), String> {
+        let vertices = match self.nodes.get(query) {
+            Some(node) if node.is_query => node.subgraph_num_vertices,
+            _ => return Err(format!("node {} is not a query node of the plan", query)),
+        };
+        match positions {
+            Some(positions) => {
+                if self.aggregations.contains_key(&query) {
+                    return Err(format!("node {} aggregates its matches, which it must report whole", query));
+                }
+                for (index, &position) in positions.iter().enumerate() {
+                    if position >= vertices {
+                        return Err(format!("node {} binds {} vertices, and none at position {}", query, vertices, position));
+                    }
+                    if positions[.. index].contains(&position) {
+                        return Err(format!("node {} projects position {} twice", query, position));
+                    }
+                }
+                self.projections.insert(query, positions);
+            },
+            None => { self.projections.remove(&query); },
+        }
+        Ok(())
+    }
+
+    /// The positions query node `query` reports of its matches, if it projects them.
+    pub fn projection(&self, query: usize) -> Option<&[usize]> {
+        self.projections.get(&query).map(|positions| &positions[..])
+    }
+
+    /// Has query node `query` contribute its matches to `aggregation`, or stop contributing.
+    ///
+    /// The node's matches are grouped by the aggregation's key, read from the positions of its own
+    /// matches, and aggregated with those of the other query nodes naming the same group, which
+    /// `track_aggregates` maintains; see `aggregation`. The node still reports its matches. Returns
+    /// an error if `query` is not a query node of the plan, if it projects its matches, if the
+    /// aggregation reads a position binding no vertex of its matches, or if another query node
+    /// aggregates the same group with terms of other kinds or another aggregator.
+    pub fn set_aggregation(&mut self, query: usize, aggregation: Option<Aggregation>) -> Result<(), String> {
+        let vertices = match self.nodes.get(query) {
+            Some(node) if node.is_query => node.subgraph_num_vertices,
+            _ => return Err(format!("node {} is not a query node of the plan", query)),
+        };
+        match aggregation {
+            Some(aggregation) => {
+                if self.projections.contains_key(&query) {
+                    return Err(format!("node {} projects its matches, and can not aggregate them", query));
+                }
+                if let Some(position) = aggregation.positions().into_iter().find(|&position| position >= vertices) {
+                    return Err(format!("node {} binds {} vertices, and none at position {}", query, vertices, position));
+                }
+                let conflict = self.aggregations.iter().find(|&(&other, existing)| other != query && existing.group == aggregation.group && !existing.combines_with(&aggregation));
+                if let Some((&other, existing)) = conflict {
+                    return Err(format!("node {} aggregates group {} as {}, which {} can not be aggregated with", other, existing.group, existing, aggregation));
+                }
+                self.aggregations.insert(query, aggregation);
+            },
+            None => { self.aggregations.remove(&query); },
+        }
+        Ok(())
+    }
+
+    /// The aggregate query node `query` contributes its matches to, if any.
+    pub fn aggregation(&self, query: usize) -> Option<&Aggregation> {
+        self.aggregations.get(&query)
+    }
+
+    /// Overrides how operation `operation` of plan edge `edge` is classified, or restores the default.
+    ///
+    /// Plan edges extend prefixes using the operations that reach the vertex they bind, and check
+    /// the others with intersections first. An extension hinted `Intersect` is instead checked once
+    /// the vertex has been bound by the edge's other extensions, so that experts can keep a large
+    /// relation out of the proposals for tricky patterns. Returns an error, leaving the plan as it
+    /// was, if the edge or operation does not exist, if an operation between vertices bound earlier
+    /// is hinted `Extend`, or if no operation would be left to extend the edge's prefixes.
+    pub fn set_join_hint(&mut self, edge: usize, operation: usize, hint: Option<JoinHint>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.hint, hint),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].hint = previous; Err(error) },
+        }
+    }
+
+    /// Reverses the operations written from the vertex their plan edge binds, returning a warning
+    /// for each.
+    ///
+    /// An operation `s d f` reads prefixes at the bound vertex `xs` and reaches `xd`, through the
+    /// forward index if `f` is 1, for the pattern edge `xs->xd`, and otherwise through the reverse
+    /// index, for `xd->xs`. Generators that write each pattern edge forward from its source write
+    /// an edge leaving the new vertex as `s d 1` with `xs` not yet bound, which must be read as
+    /// `d s 0` instead: the same pattern edge, extended from `xd` through the reverse index.
+    /// Returns an error, leaving the plan as it was, if an operation is wrong either way round,
+    /// reading a vertex no earlier edge binds or joining a vertex to itself.
+    pub fn orient_operations(&mut self) -> Result<Vec<String>, String> {
+        let mut reversals = Vec::new();
+        for (index, edge) in self.edges.iter().enumerate() {
+            for position in edge.misoriented().map_err(|error| format!("plan edge {}: {}", index, error))? {
+                reversals.push((index, position));
+            }
+        }
+        let mut warnings = Vec::new();
+        for (index, position) in reversals {
+            let edge = &mut self.edges[index];
+            let reversed = edge.operations[position].reversed();
+            warnings.push(format!("plan edge {} operation {}: {} reads x{}, which is not yet bound; reversed to {} ({})",
+                                  index, position, edge.operations[position].describe(), reversed.dst_key, reversed.describe(), reversed.encode()));
+            edge.operations[position] = reversed;
+        }
+        if !warnings.is_empty() {
+            for edge in self.edges.iter_mut() { edge.initialize(); }
+        }
+        Ok(warnings)
+    }
+
+    /// Checks that every operation reads vertices bound before its plan edge, returning a
+    /// diagnostic naming the first that does not.
+    ///
+    /// Unlike `orient_operations`, operations written from the vertex their edge binds are
+    /// rejected rather than reversed, with the line that should replace them.
+    pub fn check_operations(&self) -> Result<(), String> {
+        for (index, edge) in self.edges.iter().enumerate() {
+            let misoriented = edge.misoriented().map_err(|error| format!("plan edge {}: {}", index, error))?;
+            if let Some(&position) = misoriented.first() {
+                let operation = &edge.operations[position];
+                return Err(format!("plan edge {} operation {}: {} reads x{}, which is not yet bound; write it as `{}`",
+                                   index, position, operation.describe(), operation.src_key, operation.reversed().encode()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the structure of the plan before a dataflow is built from it, returning a description
+    /// of each violation found, or none if the plan can be executed.
+    ///
+    /// The root node must exist and bind the two vertices of an updated edge. The edges of each
+    /// node, `edge_start_idx` onwards, must exist and leave that node, and every edge must be among
+    /// the edges of exactly one node. Every node but the root must be reached from it, by exactly
+    /// one edge and without cycles. Each edge must have operations, each reading a vertex its
+    /// prefixes bind and reaching one of them or the vertex the edge binds, and hints leaving it
+    /// something to extend with (see `set_join_hint`). Each node must bind one more vertex than its
+    /// parent if its edge extends prefixes, and as many otherwise, so that query nodes bind the
+    /// vertices of their patterns.
+    pub fn validate(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        if self.root_node_id >= self.nodes.len() {
+            violations.push(format!("the root is node {}, but the plan has {} nodes", self.root_node_id, self.nodes.len()));
+            return violations;
+        }
+
+        // the nodes among whose edges each edge is.
+        let mut owners = vec![Vec::new(); self.edges.len()];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.idx != idx {
+                violations.push(format!("node {} is numbered {}", idx, node.idx));
+            }
+            let end = node.edge_start_idx + node.num_edges;
+            if end > self.edges.len() {
+                violations.push(format!("node {} has edges {} to {}, but the plan has {} edges", idx, node.edge_start_idx, end, self.edges.len()));
+                continue;
+            }
+            for index in node.edge_start_idx .. end {
+                owners[index].push(idx);
+                if self.edges[index].src.idx != idx {
+                    violations.push(format!("edge {} is among the edges of node {}, but leaves node {}", index, idx, self.edges[index].src.idx));
+                }
+            }
+        }
+        for (index, owners) in owners.iter().enumerate() {
+            match owners.len() {
+                0 => violations.push(format!("edge {} is among the edges of no node, and is never executed", index)),
+                1 => { },
+                _ => violations.push(format!("edge {} is among the edges of nodes {:?}, and is executed for each", index, owners)),
+            }
+        }
+
+        let mut parents = vec![0; self.nodes.len()];
+        for edge in self.edges.iter().filter(|edge| edge.dst.idx < self.nodes.len()) { parents[edge.dst.idx] += 1; }
+        for (idx, &parents) in parents.iter().enumerate() {
+            if idx == self.root_node_id && parents > 0 { violations.push(format!("node {} is the root, but is reached by {} edges", idx, parents)); }
+            if idx != self.root_node_id && parents > 1 { violations.push(format!("node {} is reached by {} edges, and its matches are produced for each", idx, parents)); }
+        }
+
+        // nodes reached from the root, following the edges of each node as `execute_nodes` does.
+        let mut reached = vec![false; self.nodes.len()];
+        let mut path = vec![false; self.nodes.len()];
+        self.visit(self.root_node_id, &mut reached, &mut path, &mut violations);
+        for (idx, reached) in reached.iter().enumerate() {
+            if !reached { violations.push(format!("node {} is unreachable from the root", idx)); }
+        }
+
+        let root = &self.nodes[self.root_node_id];
+        if root.subgraph_num_vertices != 2 {
+            violations.push(format!("the root binds {} vertices, but its prefixes are updated edges, which bind 2", root.subgraph_num_vertices));
+        }
+        for (index, edge) in self.edges.iter().enumerate() {
+            if edge.operations.is_empty() {
+                violations.push(format!("plan edge {} has no operations", index));
+                continue;
+            }
+            match edge.misoriented() {
+                Ok(positions) => for position in positions {
+                    let operation = &edge.operations[position];
+                    violations.push(format!("plan edge {} operation {}: {} reads x{}, which is not yet bound; see `orient_operations`",
+                                            index, position, operation.describe(), operation.src_key));
+                },
+                Err(error) => {
+                    violations.push(format!("plan edge {}: {}", index, error));
+                    continue;
+                },
+            }
+            if let Err(error) = edge.check_hints() {
+                violations.push(format!("plan edge {}: {}", index, error));
+            }
+            if let Err(error) = edge.check_filters() {
+                violations.push(format!("plan edge {}: {}", index, error));
+            }
+            match edge.update_label {
+                Some(label) if edge.src.idx != self.root_node_id => {
+                    violations.push(format!("plan edge {} requires label {} of the updated edge, but leaves node {} rather than the root", index, label, edge.src.idx));
+                },
+                _ => { },
+            }
+            let new = edge.src.subgraph_num_vertices;
+            let binds = edge.operations.iter().any(|operation| operation.dst_key == new);
+            let expected = if binds { new + 1 } else { new };
+            if edge.dst.subgraph_num_vertices != expected {
+                violations.push(format!("plan edge {} leads from node {}, binding {} vertices, to node {}, which should bind {} but binds {}",
+                                        index, edge.src.idx, new, edge.dst.idx, expected, edge.dst.subgraph_num_vertices));
+            }
+        }
+        violations
+    }
+
+    // marks the nodes reachable from `node` as reached, reporting edges that lead to a node on
+    // `path`, the nodes leading to `node`, or to no node of the plan.
+    fn visit(&self, node: usize, reached: &mut Vec<bool>, path: &mut Vec<bool>, violations: &mut Vec<String>) {
+        reached[node] = true;
+        path[node] = true;
+        let (start, end) = (self.nodes[node].edge_start_idx, self.nodes[node].edge_start_idx + self.nodes[node].num_edges);
+        if end <= self.edges.len() {
+            for index in start .. end {
+                let child = self.edges[index].dst.idx;
+                if child >= self.nodes.len() {
+                    violations.push(format!("plan edge {} leads to node {}, but the plan has {} nodes", index, child, self.nodes.len()));
+                }
+                else if path[child] {
+                    violations.push(format!("plan edge {} leads back to node {}, forming a cycle", index, child));
+                }
+                else if !reached[child] {
+                    self.visit(child, reached, path, violations);
+                }
+            }
+        }
+        path[node] = false;
+    }
+
+    /// Sets the indices used to check operation `operation` of plan edge `edge`, overriding the
+    /// plan's intersect direction, or restores the plan's.
+    ///
+    /// Closing edges are cheaper to check through the index that holds fewer values for the vertex
+    /// probed: the forward index at the edge's source, or the reverse index at its destination.
+    /// Returns an error, leaving the plan as it was, if the edge or operation does not exist or the
+    /// operation extends prefixes rather than checking them, as extensions read the index their
+    /// direction names.
+    pub fn set_operation_direction(&mut self, edge: usize, operation: usize, direction: Option<IntersectDirection>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.direction, direction),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].direction = previous; Err(error) },
+        }
+    }
+
+    /// The indices set to check operation `operation` of plan edge `edge`, if they override the plan's.
+    pub fn operation_direction(&self, edge: usize, operation: usize) -> Option<IntersectDirection> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.direction)
+    }
+
+    /// How operation `operation` of plan edge `edge` is hinted to be handled, if it is.
+    pub fn join_hint(&self, edge: usize, operation: usize) -> Option<JoinHint> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.hint)
+    }
+
+    /// Limits the neighbors operation `operation` of plan edge `edge` admits, or lifts its limit.
+    ///
+    /// Matches of the query nodes below the edge are then reported only while the vertex the
+    /// operation binds is among the first `limit.k` neighbors of the vertex it reads, in the index
+    /// it reads; see `limit`. Returns an error, leaving the plan as it was, if the edge or
+    /// operation does not exist, if the operation joins vertices bound earlier, or if `limit.k` is
+    /// zero.
+    pub fn set_extension_limit(&mut self, edge: usize, operation: usize, limit: Option<ExtensionLimit>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.limit, limit),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].limit = previous; Err(error) },
+        }
+    }
+
+    /// The neighbors operation `operation` of plan edge `edge` admits, if it is limited.
+    pub fn extension_limit(&self, edge: usize, operation: usize) -> Option<ExtensionLimit> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.limit)
+    }
+
+    /// Requires the vertex operation `operation` of plan edge `edge` binds to have label `label`,
+    /// or lifts the requirement.
+    ///
+    /// The indices then propose only the vertices with the label, as the graph indices read them
+    /// (see `GraphStreamIndex::set_vertex_labels`), so that a selective label shrinks the prefixes
+    /// the edge produces rather than the matches reported; the label applies to the vertex, and
+    /// so to every extension of the edge. Returns an error, leaving the plan as it was, if the
+    /// edge or operation does not exist, if the operation binds no vertex, or if the edge's
+    /// operations would require different labels.
+    pub fn set_operation_label(&mut self, edge: usize, operation: usize, label: Option<u32>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.label, label),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].label = previous; Err(error) },
+        }
+    }
+
+    /// The label operation `operation` of plan edge `edge` requires of the vertex it binds, if any.
+    pub fn operation_label(&self, edge: usize, operation: usize) -> Option<u32> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.label)
+    }
+
+    /// Requires the pattern edge of operation `operation` of plan edge `edge` to have label `label`,
+    /// or lifts the requirement.
+    ///
+    /// The operation then proposes from and checks against the indices of the edges with the label,
+    /// which the graph indices hold when built with `GraphStreamIndex::from_labeled`, so that only
+    /// matching edges are ever read. Returns an error, leaving the plan as it was, if the edge or
+    /// operation does not exist, or if the operation is limited, as limits rank the neighbors
+    /// reached by edges of every label.
+    pub fn set_edge_label(&mut self, edge: usize, operation: usize, label: Option<u32>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.edge_label, label),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].edge_label = previous; Err(error) },
+        }
+    }
+
+    /// The label operation `operation` of plan edge `edge` requires of its pattern edge, if any.
+    pub fn edge_label(&self, edge: usize, operation: usize) -> Option<u32> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.edge_label)
+    }
+
+    /// Registers `source` as source `id`, replacing any source registered as `id` before; see
+    /// `wings_plan::sources`.
+    ///
+    /// Every worker reads the whole of the source, which should not change while the dataflow of
+    /// the plan reads it.
+    pub fn set_source(&mut self, id: u32, source: Arc<ExtensionSource>) {
+        self.sources.0.insert(id, source);
+    }
+
+    /// The source registered as `id`, if any.
+    pub fn source(&self, id: u32) -> Option<&Arc<ExtensionSource>> {
+        self.sources.0.get(&id)
+    }
+
+    /// Reads the pattern edge of operation `operation` of plan edge `edge` from the source
+    /// registered as `source`, in place of the graph indices, or from the indices again.
+    ///
+    /// The source relates the vertex the operation reads to the vertices it proposes, or, for an
+    /// operation between vertices bound earlier, the source of its pattern edge to the destination.
+    /// The source need not be registered until the plan's dataflow is built. Returns an error,
+    /// leaving the plan as it was, if the edge or operation does not exist, if the operation
+    /// binds a vertex it would read the source from, or if it requires its edge to be absent,
+    /// limits its neighbors, requires an edge label, or names the indices that check it, none of
+    /// which a source provides.
+    pub fn set_operation_source(&mut self, edge: usize, operation: usize, source: Option<u32>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = match plan_edge.operations.get_mut(operation) {
+            Some(op) => ::std::mem::replace(&mut op.source, source),
+            None => return Err(format!("plan edge {} has no operation {}", edge, operation)),
+        };
+        match plan_edge.check_hints() {
+            Ok(()) => { plan_edge.initialize(); Ok(()) },
+            Err(error) => { plan_edge.operations[operation].source = previous; Err(error) },
+        }
+    }
+
+    /// The source operation `operation` of plan edge `edge` reads its pattern edge from, if any.
+    pub fn operation_source(&self, edge: usize, operation: usize) -> Option<u32> {
+        self.edges.get(edge).and_then(|edge| edge.operations.get(operation)).and_then(|operation| operation.source)
+    }
+
+    /// Requires the updated edge to have label `label` for plan edge `edge` to read it, or lifts
+    /// the requirement.
+    ///
+    /// Plan edges leaving the root each take the updated edge for one of the pattern's edges, and
+    /// should read only the updates to edges with the label that pattern edge requires. They read
+    /// the updates of the indices of the edges with the label; see `GraphStreamIndex::from_labeled`.
+    /// Returns an error if the edge does not exist or does not leave the root.
+    pub fn set_update_label(&mut self, edge: usize, label: Option<u32>) -> Result<(), String> {
+        let root = self.root_node_id;
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        if plan_edge.src.idx != root {
+            return Err(format!("plan edge {} leaves node {} rather than the root, and reads no updates", edge, plan_edge.src.idx));
+        }
+        plan_edge.update_label = label;
+        Ok(())
+    }
+
+    /// The label plan edge `edge` requires of the updated edge, if any.
+    pub fn update_label(&self, edge: usize) -> Option<u32> {
+        self.edges.get(edge).and_then(|edge| edge.update_label)
+    }
+
+    /// Filters the vertices of the prefixes plan edge `edge` produces by `filters`, replacing its
+    /// filters; see `wings_plan::filters`.
+    ///
+    /// Filters of the vertex the edge binds remove candidates as its extensions propose them, and
+    /// those of vertices bound earlier remove the prefixes the edge reads. The values compared are
+    /// read from the graph indices; see `GraphStreamIndex::set_vertex_attributes`. Returns an
+    /// error, leaving the plan as it was, if the edge does not exist, or if a filter reads a
+    /// vertex the edge's prefixes do not bind, or compares with NaN.
+    pub fn set_vertex_filters(&mut self, edge: usize, filters: Vec<AttributeFilter>) -> Result<(), String> {
+        let plan_edge = self.edges.get_mut(edge).ok_or_else(|| format!("the plan has no edge {}", edge))?;
+        let previous = ::std::mem::replace(&mut plan_edge.filters, filters);
+        match plan_edge.check_filters() {
+            Ok(()) => Ok(()),
+            Err(error) => { plan_edge.filters = previous; Err(error) },
+        }
+    }
+
+    /// The filters of the vertices of the prefixes plan edge `edge` produces.
+    pub fn vertex_filters(&self, edge: usize) -> &[AttributeFilter] {
+        self.edges.get(edge).map(|edge| &edge.filters[..]).unwrap_or(&[])
+    }
+
+    /// The indices of the query nodes of the plan.
+    pub fn queries(&self) -> Vec<usize> {
+        self.nodes.iter().filter(|node| node.is_query).map(|node| node.idx).collect()
+    }
+
+    /// The edges of the pattern of plan node `query`, as pairs of positions in its matches.
+    ///
+    /// The pair `(i, j)` indicates an edge from the vertex at position `i` to that at position `j`.
+    /// The root's edge is `(0, 1)`, unless the node is negated, and each operation on the way to
+    /// the node contributes another, unless its edge must be absent. Returns `None` if `query` can
+    /// not be reached from the root.
+    pub fn pattern_edges(&self, query: usize) -> Option<Vec<(usize, usize)>> {
+        let mut path = if self.negated(query) { vec![] } else { vec![(0, 1)] };
+        if self.collect_edges(self.root_node_id, query, false, &mut path) { Some(path) } else { None }
+    }
+
+    /// The edges that must be absent from the matches of plan node `query`, as `pattern_edges`
+    /// describes edges: those of anti-operations on the way to the node, and the root's edge if
+    /// the node is negated; see `set_negated`. Returns `None` if `query` can not be reached from
+    /// the root.
+    pub fn anti_edges(&self, query: usize) -> Option<Vec<(usize, usize)>> {
+        let mut path = if self.negated(query) { vec![(0, 1)] } else { vec![] };
+        if self.collect_edges(self.root_node_id, query, true, &mut path) { Some(path) } else { None }
+    }
+
+    /// The fractional edge cover number of the pattern of plan node `query`; see `agm`.
+    ///
+    /// The number of matches of the pattern in a graph with `m` edges is at most `m` to this power.
+    /// Returns `None` if `query` can not be reached from the root.
+    pub fn agm_exponent(&self, query: usize) -> Option<f64> {
+        let edges = self.pattern_edges(query)?;
+        fractional_edge_cover(self.nodes[query].subgraph_num_vertices, &edges)
+    }
+
+    /// A fingerprint of the plan's structure and settings, to check that runs used the same plan.
+    ///
+    /// The fingerprint is an FNV-1a hash of the plan's debug representation, so it is stable
+    /// across runs and platforms, though not across versions that change how plans are represented.
+    pub fn fingerprint(&self) -> u64 {
+        format!("{:?}", self).bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    // extends `path` with the edges leading from node `from` to node `to`, those that must be
+    // absent if `anti` is set and those that must be present otherwise, if there is a way.
+    fn collect_edges(&self, from: usize, to: usize, anti: bool, path: &mut Vec<(usize, usize)>) -> bool {
+        if from == to { return true; }
+        let node = &self.nodes[from];
+        for edge in self.edges[node.edge_start_idx .. node.edge_start_idx + node.num_edges].iter() {
+            let length = path.len();
+            for operation in edge.operations.iter().filter(|operation| operation.anti == anti) {
+                path.push(if operation.is_forward { (operation.src_key, operation.dst_key) } else { (operation.dst_key, operation.src_key) });
+            }
+            if self.collect_edges(edge.dst.idx, to, anti, path) { return true; }
+            path.truncate(length);
+        }
+        false
+    }
+
+    // extends `limits` with the positions, index, and limit of each limited operation on the way
+    // from node `from` to node `to`, if there is a way.
+    fn collect_limits(&self, from: usize, to: usize, limits: &mut Vec<((usize, usize), bool, ExtensionLimit)>) -> bool {
+        if from == to { return true; }
+        let node = &self.nodes[from];
+        for edge in self.edges[node.edge_start_idx .. node.edge_start_idx + node.num_edges].iter() {
+            let length = limits.len();
+            for operation in edge.operations.iter() {
+                if let Some(limit) = operation.limit {
+                    limits.push(((operation.src_key, operation.dst_key), operation.is_forward, limit));
+                }
+            }
+            if self.collect_limits(edge.dst.idx, to, limits) { return true; }
+            limits.truncate(length);
+        }
+        false
+    }
+
+    /// Describes the plan as a Graphviz digraph.
+    ///
+    /// Nodes are labeled with their index, the number of bound vertices, and the vertices they pin,
+    /// to constants or as `$slot` to parameters, whether they are negated, the positions they
+    /// project, and the aggregates they contribute to, and query nodes are drawn doubled. Edges are labeled with their operations, `ext` for
+    /// extensions and `int` for intersections, each as `src->dst` or `src<-dst` for the forward and
+    /// reverse index, `chk` for extensions hinted to be checked as intersections, with their
+    /// limits and labels if they have any, and `exc` for the edges that must be absent from the vertex the
+    /// edge binds; operations whose edges must be absent are marked `!`, and those requiring an edge
+    /// label show it as `src-[L]->dst`. Edges reading only updates with a label begin with
+    /// `updates [L]`, and edges always proposing from their first extension are dashed.
+    pub fn to_dot(&self) -> String {
+        self.describe_dot(None)
+    }
+
+    /// Describes the plan as a Graphviz digraph as `to_dot` does, with the costs the plan is
+    /// estimated to have on a graph with degree statistics `statistics`.
+    ///
+    /// Nodes are also labeled with the number of prefixes they are expected to bind for each
+    /// update, and edges with the work expected to produce them, counted as the optimizer counts
+    /// it: a unit for each index consulted and for each proposal checked against another index.
+    /// Extensions propose as many neighbors as the expected degree in their direction, or their
+    /// limit if smaller, and each other operation keeps a prefix with the probability that a
+    /// random pair of vertices is, or for those marked `!` is not, joined by an edge. Filters are
+    /// assumed to keep every prefix. The estimates treat edges as independent, so they tell which
+    /// edges multiply a plan's prefixes rather than how many prefixes there will be.
+    pub fn to_dot_with_costs(&self, statistics: &DegreeStatistics) -> String {
+        self.describe_dot(Some(self.estimate_costs(statistics)))
+    }
+
+    // the digraph `to_dot` describes, with the prefixes of each node and the work of each edge
+    // of `costs` if there are any.
+    fn describe_dot(&self, costs: Option<(Vec<f64>, Vec<f64>)>) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph plan {\n");
+        for node in self.nodes.iter() {
+            let shape = if node.is_query { "doublecircle" } else if node.idx == self.root_node_id { "box" } else { "circle" };
+            let mut pins = self.constants(node.idx).into_iter().map(|(position, value)| format!("\\nx{}={}", position, value)).collect::<String>();
+            pins.extend(self.parameters(node.idx).into_iter().map(|(position, slot)| format!("\\nx{}=${}", position, slot)));
+            if self.negated(node.idx) { pins.push_str("\\nnegated"); }
+            if let Some(positions) = self.projection(node.idx) {
+                pins.push_str(&format!("\\nproject {}", positions.iter().map(|position| format!("x{}", position)).collect::<Vec<_>>().join(",")));
+            }
+            if let Some(aggregation) = self.aggregation(node.idx) { pins.push_str(&format!("\\n{}", aggregation)); }
+            if let Some((ref prefixes, _)) = costs { pins.push_str(&format!("\\n~{:.2} prefixes", prefixes[node.idx])); }
+            dot.push_str(&format!("    n{} [shape={}, label=\"{}\\n{} vertices{}\"];\n", node.idx, shape, node.idx, node.subgraph_num_vertices, pins));
+        }
+        for (position, edge) in self.edges.iter().enumerate() {
+            let mut labels = Vec::new();
+            for operation in edge.extensions.iter() {
+                labels.push(format!("ext {}{}{}", operation.describe(), operation.describe_limit(), operation.describe_label()));
+            }
+            for operation in edge.intersections.iter() {
+                labels.push(format!("int {}{}", operation.describe(), operation.describe_direction()));
+            }
+            for operation in edge.checks.iter() {
+                labels.push(format!("chk {}{}{}{}", operation.describe(), operation.describe_direction(), operation.describe_limit(), operation.describe_label()));
+            }
+            for operation in edge.exclusions.iter() {
+                labels.push(format!("exc {}", operation.describe()));
+            }
+            for filter in edge.filters.iter() {
+                labels.push(format!("where {}", filter));
+            }
+            if let Some(label) = edge.update_label { labels.insert(0, format!("updates [{}]", label)); }
+            if let Some((_, ref work)) = costs { labels.push(format!("~{:.2} work", work[position])); }
+            if edge.hash_join { labels.push("hash join".to_owned()); }
+            let style = if edge.propose_first { ", style=dashed" } else { "" };
+            dot.push_str(&format!("    n{} -> n{} [label=\"{}\"{}];\n", edge.src.idx, edge.dst.idx, labels.join("\\n"), style));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the expected prefixes of each node and work of each edge for each update, on a graph with
+    // degree statistics `statistics`; see `to_dot_with_costs`.
+    fn estimate_costs(&self, statistics: &DegreeStatistics) -> (Vec<f64>, Vec<f64>) {
+        let mut prefixes = vec![0.0; self.nodes.len()];
+        let mut work = vec![0.0; self.edges.len()];
+        self.estimate_from(self.root_node_id, 1.0, statistics, &mut prefixes, &mut work);
+        (prefixes, work)
+    }
+
+    // adds the costs of `bound` prefixes at node `from`, and of those they extend to below it.
+    fn estimate_from(&self, from: usize, bound: f64, statistics: &DegreeStatistics, prefixes: &mut [f64], work: &mut [f64]) {
+        prefixes[from] += bound;
+        let vertices = ::std::cmp::max(statistics.vertices(), 1) as f64;
+        let degree = |operation: &PlanOperation| {
+            let degree = if operation.is_forward { statistics.out_degree() } else { statistics.in_degree() };
+            operation.limit.map_or(degree, |limit| degree.min(limit.k as f64))
+        };
+        let keeps = |operation: &PlanOperation| {
+            let present = (degree(operation) / vertices).min(1.0);
+            if operation.anti { 1.0 - present } else { present }
+        };
+        let node = &self.nodes[from];
+        for position in node.edge_start_idx .. node.edge_start_idx + node.num_edges {
+            let edge = &self.edges[position];
+            // counting consults every extension, and each proposal is checked against the others.
+            let (mut cost, mut survivors) = (0.0, 1.0);
+            if !edge.extensions.is_empty() {
+                let least = (0 .. edge.extensions.len()).min_by(|&x, &y| degree(&edge.extensions[x]).partial_cmp(&degree(&edge.extensions[y])).unwrap()).unwrap();
+                let proposed = degree(&edge.extensions[least]);
+                cost = edge.extensions.len() as f64 * (1.0 + proposed);
+                survivors = proposed;
+                for (index, operation) in edge.extensions.iter().enumerate() {
+                    if index != least { survivors *= keeps(operation); }
+                }
+            }
+            for operation in edge.intersections.iter().chain(edge.checks.iter()).chain(edge.exclusions.iter()) {
+                cost += survivors;
+                survivors *= keeps(operation);
+            }
+            work[position] += bound * cost;
+            self.estimate_from(edge.dst.idx, bound * survivors, statistics, prefixes, work);
+        }
+    }
+
+    /// Combines `plans` into one plan, whose root leads to the nodes of each of them, so that their
+    /// queries run in one dataflow and the stages they share are executed once; see
+    /// `shared_stages`.
+    ///
+    /// Returns the plan and, for each of `plans`, the index in the combined plan of each of its
+    /// nodes, the roots becoming the combined plan's root. The combined plan takes its settings
+    /// from the first of `plans`, and the sorted output of each query node from the plan it came
+    /// from. Returns an error if there are no plans, if they differ in what they match (their
+    /// orientation, counting, semantics, or minimum degree), if one of them is inconsistent, or if
+    /// one pins vertices of its root.
+    pub fn combine(plans: &[&Plan]) -> Result<(Plan, Vec<Vec<usize>>), String> {
+        let first = plans.first().ok_or_else(|| "no plans to combine".to_owned())?;
+        let mut document = PlanDocument { nodes: vec![NodeDocument::default()], edges: Vec::new() };
+        let mut indices = Vec::new();
+        for (position, plan) in plans.iter().enumerate() {
+            if (plan.orientation, plan.counting, plan.semantics, plan.min_degree) != (first.orientation, first.counting, first.semantics, first.min_degree) {
+                return Err(format!("plan {} matches patterns differently from plan 0, and can not share its dataflow", position));
+            }
+            let violations = plan.validate();
+            if !violations.is_empty() {
+                return Err(format!("plan {} is inconsistent: {}", position, violations.join("; ")));
+            }
+            if !plan.pins(plan.root_node_id).is_empty() {
+                return Err(format!("plan {} pins vertices of its root, which the combined plans share", position));
+            }
+            let plan_document = plan.to_document();
+            let mut index = Vec::new();
+            for node in plan.nodes.iter() {
+                if node.idx == plan.root_node_id { index.push(0); }
+                else {
+                    index.push(document.nodes.len());
+                    document.nodes.push(plan_document.nodes[node.idx].clone());
+                }
+            }
+            for edge in plan_document.edges {
+                document.edges.push(EdgeDocument { from: index[edge.from], to: index[edge.to], ..edge });
+            }
+            indices.push(index);
+        }
+
+        let mut combined = document.build()?;
+        combined.min_degree = first.min_degree;
+        combined.direction = first.direction;
+        combined.semantics = first.semantics;
+        combined.orientation = first.orientation;
+        combined.counting = first.counting;
+        combined.placement = first.placement.clone();
+        combined.copies = first.copies;
+        combined.global = first.global;
+        combined.standing = first.standing;
+        combined.deterministic = first.deterministic;
+        combined.encoding = first.encoding;
+        for (position, plan) in plans.iter().enumerate() {
+            for (&id, source) in plan.sources.0.iter() {
+                match combined.sources.0.get(&id) {
+                    Some(registered) if !Arc::ptr_eq(registered, source) => {
+                        return Err(format!("plan {} registers another source as source {}", position, id));
+                    },
+                    _ => { },
+                }
+                combined.sources.0.insert(id, source.clone());
+            }
+        }
+        for (plan, index) in plans.iter().zip(indices.iter()) {
+            for (&query, config) in plan.sorted.iter() {
+                combined.sorted.insert(index[query], config.clone());
+            }
+        }
+        Ok((combined, indices))
+    }
+
+    /// Describes the plan's nodes and edges as plain data; see `PlanDocument`.
+    ///
+    /// Building the document yields a plan matching the same patterns, in the same way.
+    pub fn to_document(&self) -> PlanDocument {
+        let nodes = self.nodes.iter().map(|node| NodeDocument { query: node.is_query, constants: self.constants(node.idx), parameters: self.parameters(node.idx), negated: self.negated(node.idx), projection: self.projection(node.idx).map(|positions| positions.to_vec()), aggregation: self.aggregation(node.idx).cloned() }).collect();
+        let edges = self.edges.iter().map(|edge| {
+            let new = edge.src.subgraph_num_vertices;
+            let operations = edge.operations.iter().map(|operation| {
+                let (src, dst) = if operation.is_forward { (operation.src_key, operation.dst_key) } else { (operation.dst_key, operation.src_key) };
+                let kind = if operation.dst_key == new { OperationKind::Extension } else { OperationKind::Intersection };
+                OperationDocument { src, dst, kind, hint: operation.hint, direction: operation.direction, limit: operation.limit, anti: operation.anti, label: operation.label, edge_label: operation.edge_label, source: operation.source }
+            }).collect();
+            EdgeDocument { from: edge.src.idx, to: edge.dst.idx, propose_first: edge.propose_first, hash_join: edge.hash_join, update_label: edge.update_label, filters: edge.filters.clone(), operations }
+        }).collect();
+        PlanDocument { nodes, edges }
+    }
+
+    fn initialize(&mut self){
+        for edge in &mut self.edges {
+            edge.initialize();
+        }
+    }
+}
+
+impl PlanOperation{
+    fn describe(&self) -> String {
+        let anti = if self.anti { "!" } else { "" };
+        match (self.is_forward, self.edge_label, self.source) {
+            (true, _, Some(source)) => format!("{}x{}-{{{}}}->x{}", anti, self.src_key, source, self.dst_key),
+            (false, _, Some(source)) => format!("{}x{}<-{{{}}}-x{}", anti, self.src_key, source, self.dst_key),
+            (true, None, None) => format!("{}x{}->x{}", anti, self.src_key, self.dst_key),
+            (false, None, None) => format!("{}x{}<-x{}", anti, self.src_key, self.dst_key),
+            (true, Some(label), None) => format!("{}x{}-[{}]->x{}", anti, self.src_key, label, self.dst_key),
+            (false, Some(label), None) => format!("{}x{}<-[{}]-x{}", anti, self.src_key, label, self.dst_key),
+        }
+    }
+
+    // the indices checking the operation, if set, as a suffix to its description.
+    fn describe_direction(&self) -> &'static str {
+        match self.direction {
+            Some(IntersectDirection::Forward) => " (fwd)",
+            Some(IntersectDirection::Reverse) => " (rev)",
+            Some(IntersectDirection::Adaptive) => " (adaptive)",
+            None => "",
+        }
+    }
+
+    // the neighbors the operation admits, if limited, as a suffix to its description.
+    fn describe_limit(&self) -> String {
+        match self.limit {
+            Some(ExtensionLimit { k, order: LimitOrder::Id }) => format!(" (first {})", k),
+            Some(ExtensionLimit { k, order: LimitOrder::Weight }) => format!(" (heaviest {})", k),
+            None => String::new(),
+        }
+    }
+
+    // the label the operation requires, if any, as a suffix to its description.
+    fn describe_label(&self) -> String {
+        match self.label {
+            Some(label) => format!(" (label {})", label),
+            None => String::new(),
+        }
+    }
+
+    // the operation as a line of a plan file.
+    fn encode(&self) -> String {
+        let mut line = format!("{} {} {}", self.src_key, self.dst_key, if self.is_forward { 1 } else { 0 });
+        match self.hint {
+            Some(JoinHint::Extend) => line.push_str(" extend"),
+            Some(JoinHint::Intersect) => line.push_str(" intersect"),
+            None => { },
+        }
+        match self.direction {
+            Some(IntersectDirection::Forward) => line.push_str(" forward"),
+            Some(IntersectDirection::Reverse) => line.push_str(" reverse"),
+            Some(IntersectDirection::Adaptive) => line.push_str(" adaptive"),
+            None => { },
+        }
+        match self.limit {
+            Some(ExtensionLimit { k, order: LimitOrder::Id }) => line.push_str(&format!(" first={}", k)),
+            Some(ExtensionLimit { k, order: LimitOrder::Weight }) => line.push_str(&format!(" heaviest={}", k)),
+            None => { },
+        }
+        if let Some(label) = self.label { line.push_str(&format!(" label={}", label)); }
+        if let Some(label) = self.edge_label { line.push_str(&format!(" edge_label={}", label)); }
+        if let Some(source) = self.source { line.push_str(&format!(" source={}", source)); }
+        if self.anti { line.push_str(" not"); }
+        line
+    }
+
+    // the same pattern edge, read from the other vertex through the other index.
+    fn reversed(&self) -> PlanOperation {
+        PlanOperation { src_key: self.dst_key, dst_key: self.src_key, is_forward: !self.is_forward, hint: self.hint, direction: self.direction, limit: self.limit, anti: self.anti, label: self.label, edge_label: self.edge_label, source: self.source }
+    }
+}
+
+impl PlanEdge{
+    fn initialize(&mut self){
+        self.extensions.clear();
+        self.intersections.clear();
+        self.checks.clear();
+        self.exclusions.clear();
+        for operation in &self.operations{
+            if operation.dst_key != self.src.subgraph_num_vertices{
+                self.intersections.push(operation.clone());
+            } else if operation.hint == Some(JoinHint::Intersect) {
+                self.checks.push(operation.clone());
+            } else if operation.anti {
+                self.exclusions.push(operation.clone());
+            } else {
+                self.extensions.push(operation.clone());
+            }
+        }
+    }
+
+    // checks that the hints of the operations leave a valid classification.
+    fn check_hints(&self) -> Result<(), String> {
+        let new = self.src.subgraph_num_vertices;
+        if let Some(operation) = self.operations.iter().find(|op| op.hint == Some(JoinHint::Extend) && op.dst_key != new) {
+            return Err(format!("operation {} joins vertices bound earlier, and can not extend", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.anti && op.hint == Some(JoinHint::Extend)) {
+            return Err(format!("operation {} reads an edge that must be absent, and can not extend", operation.describe()));
+        }
+        let extending = self.operations.iter().filter(|op| op.dst_key == new && !op.anti).count();
+        let checked = self.operations.iter().filter(|op| op.dst_key == new && !op.anti && op.hint == Some(JoinHint::Intersect)).count();
+        if extending > 0 && checked == extending {
+            return Err(format!("every operation binding x{} is hinted to intersect, leaving none to extend", new));
+        }
+        if extending == 0 && self.operations.iter().any(|op| op.dst_key == new) {
+            return Err(format!("every operation reaching x{} reads an edge that must be absent, leaving none to extend", new));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.direction.is_some() && op.dst_key == new && op.hint != Some(JoinHint::Intersect)) {
+            return Err(format!("operation {} extends prefixes through the index it names, and is checked through no other", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.limit.is_some() && op.dst_key != new) {
+            return Err(format!("operation {} joins vertices bound earlier, and can not limit the vertex its edge binds", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.limit.is_some() && op.anti) {
+            return Err(format!("operation {} reads an edge that must be absent, and can not limit the vertex its edge binds", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.limit.map(|limit| limit.k == 0).unwrap_or(false)) {
+            return Err(format!("operation {} is limited to no neighbors", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.limit.is_some() && op.edge_label.is_some()) {
+            return Err(format!("operation {} limits neighbors among edges of every label, and can not require an edge label", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.source.is_some() && (op.anti || op.limit.is_some() || op.edge_label.is_some() || op.direction.is_some())) {
+            return Err(format!("operation {} reads a source, which holds neither absent, limited, nor labeled edges, nor indices to choose", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.source.is_some() && op.dst_key == new && !op.is_forward && op.hint != Some(JoinHint::Intersect)) {
+            return Err(format!("operation {} reads a source from the vertex it binds, which a source can not propose from", operation.describe()));
+        }
+        if let Some(operation) = self.operations.iter().find(|op| op.label.is_some() && (op.dst_key != new || op.anti)) {
+            return Err(format!("operation {} binds no vertex, and can not require a label of one", operation.describe()));
+        }
+        let mut labels = self.operations.iter().filter_map(|op| op.label).collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        if labels.len() > 1 {
+            return Err(format!("operations binding x{} require different labels {:?}, which no vertex has", new, labels));
+        }
+        if self.hash_join {
+            if let Some(operation) = self.operations.iter().find(|op| op.dst_key == new && (op.label.is_some() || op.edge_label.is_some() || op.source.is_some())) {
+                return Err(format!("operation {} reads labels or a source, which a hash join does not read", operation.describe()));
+            }
+        }
+        Ok(())
+    }
+
+    // checks that the filters compare attributes of vertices the edge's prefixes bind with numbers.
+    fn check_filters(&self) -> Result<(), String> {
+        let bound = self.dst.subgraph_num_vertices;
+        if let Some(filter) = self.filters.iter().find(|filter| filter.vertex >= bound) {
+            return Err(format!("filter {} reads x{}, but the edge's prefixes bind {} vertices", filter, filter.vertex, bound));
+        }
+        if let Some(filter) = self.filters.iter().find(|filter| filter.value.is_nan()) {
+            return Err(format!("filter {} compares with NaN, which no value compares with", filter));
+        }
+        Ok(())
+    }
+
+    // the positions of the operations reading the vertex the edge binds, which must be reversed,
+    // or an error if an operation is wrong either way round.
+    fn misoriented(&self) -> Result<Vec<usize>, String> {
+        let new = self.src.subgraph_num_vertices;
+        let mut positions = Vec::new();
+        for (position, operation) in self.operations.iter().enumerate() {
+            if operation.src_key == operation.dst_key {
+                return Err(format!("operation {} ({}) joins x{} to itself", position, operation.describe(), operation.src_key));
+            }
+            if let Some(&key) = [operation.src_key, operation.dst_key].iter().find(|&&key| key > new) {
+                return Err(format!("operation {} ({}) reads x{}, but the edge binds x{} after the {} vertices of its prefixes",
+                                   position, operation.describe(), key, new, new));
+            }
+            if operation.src_key == new { positions.push(position); }
+        }
+        Ok(positions)
+    }
+
+    // the prefix attribute and index (forward or not) by which the edge first exchanges prefixes, if known.
+    fn first_route(&self, direction: IntersectDirection) -> Option<(usize, bool)> {
+        let labels = self.edge_labels();
+        let mut intersections = Vec::new();
+        for &anti in [false, true].iter() {
+            for &label in labels.iter() {
+                intersections.extend(self.get_intersect_attributes(direction, anti, label));
+            }
+        }
+        match intersections.first() {
+            Some(&(src, dst, direction)) => match direction {
+                IntersectDirection::Forward => Some((src, true)),
+                IntersectDirection::Reverse => Some((dst, false)),
+                IntersectDirection::Adaptive => None,
+            },
+            None => self.extensions.iter().find(|operation| operation.source.is_none()).map(|operation| (operation.src_key, operation.is_forward)),
+        }
+    }
+
+    fn get_extend_attributes(&self) -> Vec<(usize, bool)>{
+        let mut constraints = vec![];
+
+        for &ref operation in &self.extensions{
+            constraints.push((operation.src_key, operation.is_forward));
+        }
+
+        constraints
+    }
+
+    // the edge labels the operations require, in order of the first operation requiring each, with
+    // none for the operations requiring no label.
+    fn edge_labels(&self) -> Vec<Option<u32>> {
+        let mut labels = Vec::new();
+        for operation in self.operations.iter() {
+            if !labels.contains(&operation.edge_label) { labels.push(operation.edge_label); }
+        }
+        labels
+    }
+
+    // the edges labeled `label` to check before extending, those that must be absent if `anti` is
+    // set and present otherwise, with the indices to check each through; `direction` unless overridden.
+    fn get_intersect_attributes(&self, direction: IntersectDirection, anti: bool, label: Option<u32>) -> Vec<(usize, usize, IntersectDirection)>{
+        let mut constraints = vec![];
+
+        for &ref operation in self.intersections.iter().filter(|operation| operation.anti == anti && operation.edge_label == label && operation.source.is_none()){
+            let direction = operation.direction.unwrap_or(direction);
+            if operation.is_forward{
+                constraints.push((operation.src_key, operation.dst_key, direction));
+            }else{
+                constraints.push((operation.dst_key, operation.src_key, direction));
+            }
+        }
+
+        constraints
+    }
+
+    fn get_check_attributes(&self, direction: IntersectDirection, anti: bool, label: Option<u32>) -> Vec<(usize, usize, IntersectDirection)>{
+        self.checks.iter().filter(|operation| operation.anti == anti && operation.edge_label == label && operation.source.is_none()).map(|operation| {
+            let direction = operation.direction.unwrap_or(direction);
+            if operation.is_forward { (operation.src_key, operation.dst_key, direction) } else { (operation.dst_key, operation.src_key, direction) }
+        }).collect()
+    }
+
+    // the pattern edges read from sources, as the vertex each source is read from, the vertex it
+    // must relate it to, and the source: those checked after extending if `checks` is set, and
+    // those checked before otherwise.
+    fn get_source_attributes(&self, checks: bool) -> Vec<(usize, usize, u32)> {
+        let operations = if checks { &self.checks } else { &self.intersections };
+        operations.iter().filter_map(|operation| operation.source.map(|source| {
+            if operation.is_forward { (operation.src_key, operation.dst_key, source) } else { (operation.dst_key, operation.src_key, source) }
+        })).collect()
+    }
+
+    // the label the vertex the edge binds must have, if its operations require one.
+    fn get_label(&self) -> Option<u32> {
+        self.operations.iter().filter_map(|operation| operation.label).next()
+    }
+
+    // the edges labeled `label` that must be absent from the vertex the edge binds, as extensions read them.
+    fn get_exclude_attributes(&self, label: Option<u32>) -> Vec<(usize, bool)>{
+        self.exclusions.iter().filter(|operation| operation.edge_label == label).map(|operation| (operation.src_key, operation.is_forward)).collect()
+    }
+}
+
+// vertices of prefixes pinned to constants, and to parameter slots, by position.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct Pins {
+    constants: Vec<(usize, Node)>,
+    parameters: Vec<(usize, usize)>,
+}
+
+impl Pins {
+    fn is_empty(&self) -> bool {
+        self.constants.is_empty() && self.parameters.is_empty()
+    }
+
+    // the prefixes of `stream` with each constant at its position, and with values of a binding
+    // in effect at the parameters' positions.
+    fn restrict<G: Scope, W: Weight, P: ExchangeData+Indexable<Node>>(&self, stream: &Stream<G, (P, W)>, bindings: Option<&Stream<G, (Vec<Node>, i32)>>) -> Stream<G, (P, W)> {
+        let stream = if self.constants.is_empty() { stream.clone() }
+            else {
+                let constants = self.constants.clone();
+                stream.filter(move |x| constants.iter().all(|&(position, value)| x.0.index(position) == value))
+            };
+        match bindings {
+            Some(bindings) if !self.parameters.is_empty() => bind_prefixes(&stream, bindings, self.parameters.clone()),
+            _ => stream,
+        }
+    }
+}
+
+// exchanges prefixes by `attribute` as the forward or reverse index of `graph` would.
+fn exchange_prefixes<G: Scope, W: Weight, H1, H2, P>(stream: &Stream<G, (P, W)>, graph: &GraphStreamIndex<G, H1, H2>, (attribute, is_forward): (usize, bool)) -> Stream<G, (P, W)>
+    where H1: Fn(Node)->u64 + 'static,
+          H2: Fn(Node)->u64 + 'static,
+          P: ExchangeData+Indexable<Node>,
+{
+    if is_forward {
+        let hash = graph.forward.hash.clone();
+        stream.exchange(move |x| (*hash)(x.0.index(attribute)))
+    } else {
+        let hash = graph.reverse.hash.clone();
+        stream.exchange(move |x| (*hash)(x.0.index(attribute)))
+    }
+}
+
+// the vertex attributes plan edge `index` filters its vertices by.
+fn vertex_attributes<G: Scope, H1, H2>(graph: &GraphStreamIndex<G, H1, H2>, index: usize) -> Arc<VertexAttributes>
+    where H1: Fn(Node)->u64 + 'static,
+          H2: Fn(Node)->u64 + 'static,
+{
+    match graph.vertex_attributes {
+        Some(ref attributes) => attributes.clone(),
+        None => panic!("EXCEPTION: plan edge {} filters its vertices by their attributes, but the graph indices have no vertex attributes", index),
+    }
+}
+
+// the proposals of `proposals` to which no edge leads from the vertex at each of `attributes`,
+// through the forward index or the reverse one.
+fn exclude_proposals<G: Scope, W: Weight, H1, H2, P>(proposals: &Stream<G, (P, Vec<Node>, W)>, graph: &GraphStreamIndex<G, H1, H2>, attributes: &[(usize, bool)]) -> Stream<G, (P, Vec<Node>, W)>
+    where H1: Fn(Node)->u64 + 'static,
+          H2: Fn(Node)->u64 + 'static,
+          P: ::std::fmt::Debug+ExchangeData+Indexable<Node>,
+{
+    let mut proposals = proposals.clone();
+    for &(attribute, is_forward) in attributes {
+        proposals = match is_forward {
+            true    => graph.forward.extend_using(move |x: &P| x.index(attribute)).exclude(proposals),
+            false   => graph.reverse.extend_using(move |x: &P| x.index(attribute)).exclude(proposals),
+        };
+    }
+    proposals
+}
+
+/// A vertex of a pattern, by its position in the prefixes that bind it.
+///
+/// Vertices and plan nodes are both numbered, and have distinct types so that one can not be
+/// passed for the other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Vertex(pub usize);
+
+/// A node of a plan being built, by its index in the plan.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+impl NodeId {
+    /// The root node, whose prefixes are the updated edges, binding `Vertex(0)` and `Vertex(1)`.
+    pub const ROOT: NodeId = NodeId(0);
+}
+
+/// Builds a plan in code rather than reading it from a plan file.
+///
+/// A builder starts with the root node, `NodeId::ROOT`. Further nodes are declared in order of
+/// their indices, and each is reached by one plan edge from a node declared before it. The pattern
+/// edges an edge reads follow it: `extension` for a pattern edge joining a vertex bound earlier to
+/// the vertex the edge binds, which is the next vertex of its source node's prefixes, and
+/// `intersection` for a pattern edge between vertices bound earlier. An edge without extensions
+/// binds no vertex, and only checks its intersections. `hint`, `direction`, `limit`, `label`,
+/// `edge_label`, `source`, and `anti` then apply to the operation added last, and `propose_first`,
+/// `hash_join`, `update_label`, and `filter` to the edge added last.
+///
+///     use alg3_dynamic::prelude::{Plan, NodeId, Vertex};
+///
+///     // triangles x0->x1, x0->x2, x1->x2, from updates to their first edge.
+///     let plan = Plan::builder()
+///         .add_node(NodeId(1), true)
+///         .add_edge(NodeId::ROOT, NodeId(1))
+///         .extension(Vertex(0), Vertex(2))
+///         .extension(Vertex(1), Vertex(2))
+///         .build()
+///         .expect("malformed plan");
+///     assert_eq!(plan.queries(), vec![1]);
+///
+/// Problems are reported by `build`, which checks the plan as `read_plan` would.
+#[derive(Debug)]
+pub struct PlanBuilder {
+    // whether each node is a query node, by index.
+    nodes: Vec<bool>,
+    // the edges, as added; `build` checks them.
+    edges: Vec<EdgeDocument>,
+    // the vertices pinned to constants, as nodes, positions, and constants.
+    constants: Vec<(NodeId, Vertex, Node)>,
+    // the vertices pinned to parameters, as nodes, positions, and slots.
+    parameters: Vec<(NodeId, Vertex, usize)>,
+    // the query nodes whose matches are negated.
+    negated: Vec<NodeId>,
+    // the query nodes projecting their matches, with the positions they report.
+    projections: Vec<(NodeId, Vec<Vertex>)>,
+    // the query nodes contributing to aggregates, with the aggregates.
+    aggregations: Vec<(NodeId, Aggregation)>,
+    error: Option<String>,
+}
+
+impl Plan {
+    /// A builder for a plan with only its root node; see `PlanBuilder`.
+    pub fn builder() -> PlanBuilder {
+        PlanBuilder { nodes: vec![false], edges: Vec::new(), constants: Vec::new(), parameters: Vec::new(), negated: Vec::new(), projections: Vec::new(), aggregations: Vec::new(), error: None }
+    }
+}
+
+impl PlanBuilder {
+    /// Declares `node`, whose matches are reported if `query` is set.
+    ///
+    /// Nodes are declared in order of their indices, starting at one.
+    pub fn add_node(mut self, node: NodeId, query: bool) -> Self {
+        if node.0 != self.nodes.len() {
+            self.fail(format!("nodes are declared in order, and node {} is not next; expected node {}", node.0, self.nodes.len()));
+        }
+        self.nodes.push(query);
+        self
+    }
+
+    /// Pins the vertex at `position` of the prefixes of `node` to `value`; see `Plan::set_constant`.
+    pub fn constant(mut self, node: NodeId, position: Vertex, value: Node) -> Self {
+        self.constants.push((node, position, value));
+        self
+    }
+
+    /// Pins the vertex at `position` of the prefixes of `node` to parameter slot `slot`; see
+    /// `Plan::set_parameter`.
+    pub fn parameter(mut self, node: NodeId, position: Vertex, slot: usize) -> Self {
+        self.parameters.push((node, position, slot));
+        self
+    }
+
+    /// Negates the matches of query node `node`; see `Plan::set_negated`.
+    pub fn negated(mut self, node: NodeId) -> Self {
+        self.negated.push(node);
+        self
+    }
+
+    /// Reports only the vertices at `positions` of the matches of query node `node`; see
+    /// `Plan::set_projection`.
+    pub fn project(mut self, node: NodeId, positions: &[Vertex]) -> Self {
+        self.projections.push((node, positions.to_vec()));
+        self
+    }
+
+    /// Contributes the matches of query node `node` to `aggregation`; see `Plan::set_aggregation`.
+    pub fn aggregate(mut self, node: NodeId, aggregation: Aggregation) -> Self {
+        self.aggregations.push((node, aggregation));
+        self
+    }
+
+    /// Adds a plan edge from `from` to `to`, which must both be declared.
+    pub fn add_edge(mut self, from: NodeId, to: NodeId) -> Self {
+        if from.0 >= self.nodes.len() || to.0 >= self.nodes.len() {
+            self.fail(format!("edge from node {} to node {} names a node not yet declared", from.0, to.0));
+        }
+        self.edges.push(EdgeDocument { from: from.0, to: to.0, propose_first: false, hash_join: false, update_label: None, filters: Vec::new(), operations: Vec::new() });
+        self
+    }
+
+    /// Adds to the last edge the pattern edge `src->dst`, one of whose vertices the edge binds.
+    pub fn extension(self, src: Vertex, dst: Vertex) -> Self {
+        self.operation(src, dst, OperationKind::Extension)
+    }
+
+    /// Adds to the last edge the pattern edge `src->dst`, whose vertices are bound before it.
+    pub fn intersection(self, src: Vertex, dst: Vertex) -> Self {
+        self.operation(src, dst, OperationKind::Intersection)
+    }
+
+    /// Hints how the operation added last is handled; see `Plan::set_join_hint`.
+    pub fn hint(mut self, hint: JoinHint) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.hint = Some(hint); },
+            None => self.fail("hint given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Sets the indices that check the operation added last; see `Plan::set_operation_direction`.
+    pub fn direction(mut self, direction: IntersectDirection) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.direction = Some(direction); },
+            None => self.fail("direction given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Limits the neighbors the operation added last admits; see `Plan::set_extension_limit`.
+    pub fn limit(mut self, limit: ExtensionLimit) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.limit = Some(limit); },
+            None => self.fail("limit given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Requires the vertex the operation added last binds to have `label`; see
+    /// `Plan::set_operation_label`.
+    pub fn label(mut self, label: u32) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.label = Some(label); },
+            None => self.fail("label given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Requires the pattern edge of the operation added last to have `label`; see
+    /// `Plan::set_edge_label`.
+    pub fn edge_label(mut self, label: u32) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.edge_label = Some(label); },
+            None => self.fail("edge label given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Reads the pattern edge of the operation added last from the source registered as `source`;
+    /// see `Plan::set_operation_source`.
+    pub fn source(mut self, source: u32) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.source = Some(source); },
+            None => self.fail("source given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Requires the pattern edge of the operation added last to be absent rather than present.
+    ///
+    /// An intersection then keeps the prefixes lacking the edge, and an extension removes the
+    /// vertices it reaches from those the edge's other extensions propose, so that queries can
+    /// ask for induced patterns, such as paths whose ends are not adjacent. Such operations never
+    /// propose vertices themselves. Matches also change when an absent edge is updated, which the
+    /// plan follows only with a negated chain starting from that edge; see `Plan::set_negated`.
+    pub fn anti(mut self) -> Self {
+        match self.edges.last_mut().and_then(|edge| edge.operations.last_mut()) {
+            Some(operation) => { operation.anti = true; },
+            None => self.fail("anti given before any operation".to_owned()),
+        }
+        self
+    }
+
+    /// Has the edge added last always propose from its first extension, and restrict the proposals
+    /// by the others, rather than count which extension proposes the fewest for each prefix. This
+    /// saves the counting for edges whose first extension is known to be the most selective.
+    pub fn propose_first(mut self) -> Self {
+        match self.edges.last_mut() {
+            Some(edge) => { edge.propose_first = true; },
+            None => self.fail("proposing first requested before any edge".to_owned()),
+        }
+        self
+    }
+
+    /// Has the edge added last propose from its first extension, as `propose_first` does, but with a
+    /// hash join: its prefixes are grouped by the vertex they read rather than sorted, and the
+    /// neighbors of each vertex are looked up once for the whole group. This suits edges whose
+    /// prefixes read few distinct vertices. The first extension may not require a vertex label, an
+    /// edge label, or a source.
+    pub fn hash_join(mut self) -> Self {
+        match self.edges.last_mut() {
+            Some(edge) => { edge.propose_first = true; edge.hash_join = true; },
+            None => self.fail("hash join requested before any edge".to_owned()),
+        }
+        self
+    }
+
+    /// Reads only the updates to edges labeled `label` into the edge added last, which must leave
+    /// the root; see `Plan::set_update_label`.
+    pub fn update_label(mut self, label: u32) -> Self {
+        match self.edges.last_mut() {
+            Some(edge) => { edge.update_label = Some(label); },
+            None => self.fail("update label given before any edge".to_owned()),
+        }
+        self
+    }
+
+    /// Filters the vertices of the prefixes the edge added last produces by `filter`; see
+    /// `Plan::set_vertex_filters`.
+    pub fn filter(mut self, filter: AttributeFilter) -> Self {
+        match self.edges.last_mut() {
+            Some(edge) => { edge.filters.push(filter); },
+            None => self.fail("filter given before any edge".to_owned()),
+        }
+        self
+    }
+
+    /// Builds the plan, or returns an error describing the first problem with it.
+    pub fn build(self) -> Result<Plan, String> {
+        if let Some(error) = self.error { return Err(error); }
+        if !self.nodes.iter().any(|&query| query) {
+            return Err("the plan has no query nodes".to_owned());
+        }
+
+        // the edge into each node, and then the vertices each binds, from the root down.
+        let mut parent = vec![None; self.nodes.len()];
+        for (index, edge) in self.edges.iter().enumerate() {
+            if edge.to == 0 { return Err(format!("edge {} leads into the root", index)); }
+            if parent[edge.to].is_some() { return Err(format!("node {} is reached by more than one edge", edge.to)); }
+            if edge.operations.is_empty() { return Err(format!("edge {} has no operations", index)); }
+            match edge.update_label {
+                Some(label) if edge.from != 0 => return Err(format!("edge {} requires label {} of the updated edge, but leaves node {} rather than the root", index, label, edge.from)),
+                _ => { },
+            }
+            parent[edge.to] = Some(index);
+        }
+        let mut vertices = vec![None; self.nodes.len()];
+        vertices[0] = Some(2);
+        let mut frontier = vec![0];
+        while let Some(node) = frontier.pop() {
+            for edge in self.edges.iter().filter(|edge| edge.from == node) {
+                let bound = vertices[node].expect("node visited before its vertices are known");
+                let extends = edge.operations.iter().any(|operation| operation.kind == OperationKind::Extension);
+                vertices[edge.to] = Some(if extends { bound + 1 } else { bound });
+                frontier.push(edge.to);
+            }
+        }
+        if let Some(node) = vertices.iter().position(|vertices| vertices.is_none()) {
+            return Err(format!("node {} is unreachable from the root", node));
+        }
+        let vertices = vertices.into_iter().map(|vertices| vertices.expect("unreachable node")).collect::<Vec<_>>();
+
+        // edges leaving the same node are adjacent, in the order they were added.
+        let mut order = (0 .. self.edges.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| self.edges[index].from);
+        let mut nodes = Vec::new();
+        for (idx, &is_query) in self.nodes.iter().enumerate() {
+            let edge_start_idx = self.edges.iter().filter(|edge| edge.from < idx).count();
+            let num_edges = self.edges.iter().filter(|edge| edge.from == idx).count();
+            nodes.push(Rc::new(PlanNode { edge_start_idx, num_edges, subgraph_num_vertices: vertices[idx], is_query, idx }));
+        }
+
+        let mut plan: Plan = Default::default();
+        for &index in order.iter() {
+            let edge = &self.edges[index];
+            let new = vertices[edge.from];
+            let mut encoded = Vec::new();
+            for operation in edge.operations.iter() {
+                let (src, dst) = (operation.src, operation.dst);
+                let describe = || format!("edge {}: pattern edge x{}->x{}", index, src, dst);
+                if src == dst { return Err(format!("{} joins a vertex to itself", describe())); }
+                let (src_key, dst_key, is_forward) = if operation.kind == OperationKind::Intersection {
+                    if src >= new || dst >= new { return Err(format!("{} is an intersection, but x{} is not bound before the edge", describe(), ::std::cmp::max(src, dst))); }
+                    (src, dst, true)
+                } else if dst == new && src < new {
+                    (src, dst, true)
+                } else if src == new && dst < new {
+                    (dst, src, false)
+                } else {
+                    return Err(format!("{} is an extension, but does not join a bound vertex to x{}, the vertex the edge binds", describe(), new));
+                };
+                encoded.push(PlanOperation { src_key, dst_key, is_forward, hint: operation.hint, direction: operation.direction, limit: operation.limit, anti: operation.anti, label: operation.label, edge_label: operation.edge_label, source: operation.source });
+            }
+            let plan_edge = PlanEdge {
+                src: nodes[edge.from].clone(),
+                dst: nodes[edge.to].clone(),
+                operations: encoded,
+                extensions: Vec::new(),
+                intersections: Vec::new(),
+                checks: Vec::new(),
+                exclusions: Vec::new(),
+                propose_first: edge.propose_first,
+                hash_join: edge.hash_join,
+                update_label: edge.update_label,
+                filters: edge.filters.clone(),
+            };
+            plan_edge.check_hints().map_err(|error| format!("edge {}: {}", index, error))?;
+            plan_edge.check_filters().map_err(|error| format!("edge {}: {}", index, error))?;
+            plan.edges.push(plan_edge);
+        }
+        plan.nodes = nodes;
+        plan.root_node_id = 0;
+        plan.initialize();
+        for &(node, position, value) in self.constants.iter() {
+            plan.set_constant(node.0, position.0, Some(value))?;
+        }
+        for &(node, position, slot) in self.parameters.iter() {
+            plan.set_parameter(node.0, position.0, Some(slot))?;
+        }
+        for &node in self.negated.iter() {
+            plan.set_negated(node.0, true)?;
+        }
+        for &(node, ref positions) in self.projections.iter() {
+            plan.set_projection(node.0, Some(positions.iter().map(|position| position.0).collect()))?;
+        }
+        for &(node, ref aggregation) in self.aggregations.iter() {
+            plan.set_aggregation(node.0, Some(aggregation.clone()))?;
+        }
+        Ok(plan)
+    }
+
+    // adds the pattern edge `src->dst` to the last edge, as an operation of kind `kind`.
+    fn operation(mut self, src: Vertex, dst: Vertex, kind: OperationKind) -> Self {
+        match self.edges.last_mut() {
+            Some(edge) => edge.operations.push(OperationDocument { src: src.0, dst: dst.0, kind, hint: None, direction: None, limit: None, anti: false, label: None, edge_label: None, source: None }),
+            None => self.fail(format!("pattern edge x{}->x{} given before any edge", src.0, dst.0)),
+        }
+        self
+    }
+
+    // records `error`, unless an earlier one was recorded.
+    fn fail(&mut self, error: String) {
+        if self.error.is_none() { self.error = Some(error); }
+    }
+}
+
+/// A problem with a plan file, as `Plan::from_reader` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlanError {
+    /// The file could not be read, for the reason given.
+    Io(String),
+    /// A line, numbered from one, lacks a field or holds a field other than the one expected.
+    Syntax {
+        /// The number of the line.
+        line: usize,
+        /// What the line should hold, e.g. `the number of edges`.
+        expected: String,
+        /// The field the line holds instead, or `None` if the line or the file ended.
+        found: Option<String>,
+    },
+    /// A line describes something no plan can run, such as an operation reading a vertex its
+    /// prefixes do not bind.
+    Invalid {
+        /// The number of the line.
+        line: usize,
+        /// What is wrong.
+        reason: String,
+    },
+    /// The lines describe a plan whose parts do not fit together, e.g. a node unreachable from the
+    /// root, with each violation `Plan::validate` reports.
+    Inconsistent(Vec<String>),
+}
+
+impl PlanError {
+    /// The number of the line the problem is on, unless the file could not be read or the problem
+    /// is with the plan as a whole.
+    pub fn line(&self) -> Option<usize> {
+        match *self {
+            PlanError::Io(_) | PlanError::Inconsistent(_) => None,
+            PlanError::Syntax { line, .. } | PlanError::Invalid { line, .. } => Some(line),
+        }
+    }
+}
+
+impl ::std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            PlanError::Io(ref reason) => write!(f, "{}", reason),
+            PlanError::Syntax { line, ref expected, found: Some(ref found) } => write!(f, "line {}: expected {}, found {:?}", line, expected, found),
+            PlanError::Syntax { line, ref expected, found: None } => write!(f, "line {}: expected {}, found nothing", line, expected),
+            PlanError::Invalid { line, ref reason } => write!(f, "line {}: {}", line, reason),
+            PlanError::Inconsistent(ref violations) => write!(f, "inconsistent plan: {}", violations.join("; ")),
+        }
+    }
+}
+
+impl Plan {
+    /// Reads a plan from the file `filename`, in the plan file format; see `from_reader`.
+    pub fn from_file(filename: &str) -> Result<Plan, PlanError> {
+        Plan::from_file_with_warnings(filename).map(|(plan, _)| plan)
+    }
+
+    /// As `from_file`, but also returning the warnings of `from_reader_with_warnings`.
+    pub fn from_file_with_warnings(filename: &str) -> Result<(Plan, Vec<String>), PlanError> {
+        let file = File::open(Path::new(filename)).map_err(|error| PlanError::Io(format!("couldn't open {}: {}", filename, error)))?;
+        Plan::from_reader_with_warnings(BufReader::new(file))
+    }
+
+    /// Reads a plan in the plan file format from `reader`, or returns the first problem with it.
+    ///
+    /// Besides fields that are missing or malformed, the problems reported are plan nodes and
+    /// edges referring to nodes the plan does not have, operations reading vertices their edge's
+    /// prefixes do not bind or joining a vertex to itself, and hints that leave an edge nothing to
+    /// extend with (see `set_join_hint`). Operations written from the vertex their edge binds are
+    /// reversed; see `orient_operations`, and `from_reader_with_warnings` for the warnings about
+    /// them. The plan read is then checked as a whole by `validate`.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Plan, PlanError> {
+        Plan::from_reader_with_warnings(reader).map(|(plan, _)| plan)
+    }
+
+    /// As `from_reader`, but also returning a warning for each operation reversed, which programs
+    /// reading plans from users may want to report.
+    pub fn from_reader_with_warnings<R: BufRead>(reader: R) -> Result<(Plan, Vec<String>), PlanError> {
+        let mut lines = PlanLines { reader, number: 0 };
+        let mut plan: Plan = Default::default();
+
+        // the first line is a header the plan does not use.
+        lines.next("a header line")?;
+
+        let (root_line, fields) = lines.next("the index of the root node")?;
+        plan.root_node_id = field(root_line, &fields, 0, "the index of the root node")?;
+        let (line, fields) = lines.next("the number of nodes")?;
+        let nodes: usize = field(line, &fields, 0, "the number of nodes")?;
+        if plan.root_node_id >= nodes {
+            return Err(PlanError::Invalid { line: root_line, reason: format!("the root is node {}, but the plan has {} nodes", plan.root_node_id, nodes) });
+        }
+
+        for idx in 0 .. nodes {
+            let what = format!("node {} as its first edge, number of edges, number of vertices, and 0 or 1 for a query node", idx);
+            let (line, fields) = lines.next(&what)?;
+            let edge_start_idx = field(line, &fields, 0, &format!("the first edge of node {}", idx))?;
+            let num_edges = field(line, &fields, 1, &format!("the number of edges of node {}", idx))?;
+            let subgraph_num_vertices = field(line, &fields, 2, &format!("the number of vertices of node {}", idx))?;
+            let is_query = flag(line, &fields, 3, &format!("0 or 1 for whether node {} is a query node", idx))?;
+            plan.nodes.push(Rc::new(PlanNode{ edge_start_idx, num_edges, subgraph_num_vertices, is_query, idx}));
+        }
+
+        let (line, fields) = lines.next("the number of edges")?;
+        let edges: usize = field(line, &fields, 0, "the number of edges")?;
+
+        let mut edge_lines = Vec::new();
+        for index in 0 .. edges {
+            let what = format!("edge {} as its source node, destination node, and number of operations", index);
+            let (line, fields) = lines.next(&what)?;
+            let src: usize = field(line, &fields, 0, &format!("the source node of edge {}", index))?;
+            let dst: usize = field(line, &fields, 1, &format!("the destination node of edge {}", index))?;
+            let num_operations: usize = field(line, &fields, 2, &format!("the number of operations of edge {}", index))?;
+            let propose_first = fields.len() > 3 && flag(line, &fields, 3, &format!("0 or 1 for whether edge {} proposes from its first extension", index))?;
+            let mut hash_join = false;
+            let mut update_label = None;
+            let mut filters = Vec::new();
+            for field in fields.iter().skip(4) {
+                let malformed = || PlanError::Syntax { line, expected: "hash_join, edge_label=L, or filter=xV.A<C".to_owned(), found: Some(field.clone()) };
+                if field == "hash_join" {
+                    hash_join = true;
+                }
+                else if field.starts_with("edge_label=") {
+                    update_label = Some(field["edge_label=".len() ..].parse().map_err(|_| malformed())?);
+                }
+                else if field.starts_with("filter=") {
+                    filters.push(AttributeFilter::parse(&field["filter=".len() ..]).map_err(|_| malformed())?);
+                }
+                else { return Err(malformed()); }
+            }
+            if let Some(&node) = [src, dst].iter().find(|&&node| node >= nodes) {
+                return Err(PlanError::Invalid { line, reason: format!("edge {} refers to node {}, but the plan has {} nodes", index, node, nodes) });
+            }
+            let new = plan.nodes[src].subgraph_num_vertices;
+
+            let mut operations = vec![];
+            for position in 0 .. num_operations {
+                let what = format!("operation {} of edge {} as its source vertex, destination vertex, and 0 or 1 for the forward index", position, index);
+                let (line, fields) = lines.next(&what)?;
+                let src_key = field(line, &fields, 0, &format!("the source vertex of operation {} of edge {}", position, index))?;
+                let dst_key = field(line, &fields, 1, &format!("the destination vertex of operation {} of edge {}", position, index))?;
+                let is_forward = flag(line, &fields, 2, &format!("0 or 1 for whether operation {} of edge {} reads the forward index", position, index))?;
+                let mut hint = None;
+                let mut direction = None;
+                let mut limit = None;
+                let mut anti = false;
+                let mut label = None;
+                let mut edge_label = None;
+                let mut source = None;
+                for field in fields[3 ..].iter() {
+                    let malformed = || PlanError::Syntax { line, expected: "extend, intersect, forward, reverse, adaptive, first=K, heaviest=K, label=L, edge_label=L, source=S, or not".to_owned(), found: Some(field.clone()) };
+                    match field.as_str() {
+                        "not" => anti = true,
+                        "extend" => hint = Some(JoinHint::Extend),
+                        "intersect" => hint = Some(JoinHint::Intersect),
+                        "forward" => direction = Some(IntersectDirection::Forward),
+                        "reverse" => direction = Some(IntersectDirection::Reverse),
+                        "adaptive" => direction = Some(IntersectDirection::Adaptive),
+                        other if other.starts_with("label=") => {
+                            label = Some(other["label=".len() ..].parse().map_err(|_| malformed())?);
+                        },
+                        other if other.starts_with("edge_label=") => {
+                            edge_label = Some(other["edge_label=".len() ..].parse().map_err(|_| malformed())?);
+                        },
+                        other if other.starts_with("source=") => {
+                            source = Some(other["source=".len() ..].parse().map_err(|_| malformed())?);
+                        },
+                        other => {
+                            let (order, k) = if other.starts_with("first=") { (LimitOrder::Id, &other["first=".len() ..]) }
+                                             else if other.starts_with("heaviest=") { (LimitOrder::Weight, &other["heaviest=".len() ..]) }
+                                             else { return Err(malformed()); };
+                            limit = Some(ExtensionLimit { k: k.parse().map_err(|_| malformed())?, order });
+                        },
+                    }
+                }
+                let operation = PlanOperation{src_key, dst_key, is_forward, hint, direction, limit, anti, label, edge_label, source};
+                if src_key == dst_key {
+                    return Err(PlanError::Invalid { line, reason: format!("operation {} joins x{} to itself", operation.describe(), src_key) });
+                }
+                if let Some(&key) = [src_key, dst_key].iter().find(|&&key| key > new) {
+                    return Err(PlanError::Invalid { line, reason: format!("operation {} reads x{}, but edge {} leaves node {}, whose prefixes bind {} vertices, and binds x{}",
+                                                                          operation.describe(), key, index, src, new, new) });
+                }
+                operations.push(operation);
+            }
+
+            plan.edges.push(PlanEdge{
+                src: plan.nodes[src].clone(),
+                dst: plan.nodes[dst].clone(),
+                extensions: Vec::new(),
+                intersections: Vec::new(),
+                checks: Vec::new(),
+                exclusions: Vec::new(),
+                operations,
+                propose_first: propose_first || hash_join,
+                hash_join,
+                update_label,
+                filters,
+            });
+            edge_lines.push(line);
+        }
+
+        // hints are checked once operations are oriented, as reversing one changes which vertex it binds.
+        let warnings = plan.orient_operations().expect("operations are checked as they are read");
+        for (index, edge) in plan.edges.iter().enumerate() {
+            edge.check_hints().map_err(|reason| PlanError::Invalid { line: edge_lines[index], reason: format!("edge {}: {}", index, reason) })?;
+            edge.check_filters().map_err(|reason| PlanError::Invalid { line: edge_lines[index], reason: format!("edge {}: {}", index, reason) })?;
+        }
+
+        plan.initialize();
+
+        let violations = plan.validate();
+        if !violations.is_empty() { return Err(PlanError::Inconsistent(violations)); }
+        Ok((plan, warnings))
+    }
+}
+
+// the lines of a plan file, counting them as they are read.
+struct PlanLines<R> {
+    reader: R,
+    number: usize,
+}
+
+impl<R: BufRead> PlanLines<R> {
+    // the number and fields of the next line, or an error expecting `what` if the file has ended.
+    fn next(&mut self, what: &str) -> Result<(usize, Vec<String>), PlanError> {
+        let mut line = String::new();
+        let read = self.reader.read_line(&mut line).map_err(|error| PlanError::Io(format!("couldn't read line {}: {}", self.number + 1, error)))?;
+        self.number += 1;
+        if read == 0 { return Err(PlanError::Syntax { line: self.number, expected: what.to_owned(), found: None }); }
+        Ok((self.number, line.split_whitespace().map(|field| field.to_owned()).collect()))
+    }
+}
+
+// field `index` of line `line`, parsed, or an error expecting `what`.
+fn field<T: ::std::str::FromStr>(line: usize, fields: &[String], index: usize, what: &str) -> Result<T, PlanError> {
+    let error = || PlanError::Syntax { line, expected: what.to_owned(), found: fields.get(index).cloned() };
+    fields.get(index).ok_or_else(&error)?.parse().map_err(|_| error())
+}
+
+// field `index` of line `line`, which must be 0 or 1, or an error expecting `what`.
+fn flag(line: usize, fields: &[String], index: usize, what: &str) -> Result<bool, PlanError> {
+    match fields.get(index).map(|field| field.as_str()) {
+        Some("0") => Ok(false),
+        Some("1") => Ok(true),
+        found => Err(PlanError::Syntax { line, expected: what.to_owned(), found: found.map(|field| field.to_owned()) }),
+    }
+}
+
+/// Reads a plan from the file `filename`.
+///
+/// Panics if the file can not be read or does not hold a plan; `Plan::from_file` reports why instead.
+pub fn read_plan(filename:&str) -> Plan{
+    Plan::from_file(filename).unwrap_or_else(|error| panic!("EXCEPTION: {}", error))
+}
+
+/// Reads a plan in the plan file format from `reader`.
+///
+/// Panics if the reader does not hold a plan; `Plan::from_reader` reports why instead.
+pub fn read_plan_from<R: BufRead>(reader: R) -> Plan{
+    Plan::from_reader(reader).unwrap_or_else(|error| panic!("EXCEPTION: {}", error))
+}
@@ -0,0 +1,66 @@
+//! Projections of the changes to a query node's matches onto some of their vertices.
+//!
+//! Consumers often need only some vertices of each match: the first vertex of every triangle it
+//! is part of, or the ends of paths without their interior vertices. A query node may declare the
+//! positions of its matches to report (see `Plan::set_projection`), and then reports, for each
+//! time, the net change in the multiplicity of each projected tuple: the sum of the changes to the
+//! matches projecting to it. Tuples whose changes cancel are not reported, so that consumers see
+//! one change per tuple and time however many matches project to it.
+//!
+//! `project_matches` exchanges the projected changes by tuple, and sums them at the worker owning
+//! each tuple once their time is complete. It holds the changes of the times not yet complete, but
+//! no state across times, so that the multiplicity of a tuple at a time is the sum of the changes
+//! reported for it up to then.
+
+use std::collections::HashMap;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::channels::pact::Exchange;
+
+use super::Node;
+use ::Weight;
+
+/// Reports the net change at each time of each tuple of the vertices of `matches` at `positions`,
+/// in the order `positions` lists them.
+pub fn project_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, positions: &[usize]) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let positions = positions.to_vec();
+    let projected = matches.map(move |(embedding, weight)| (positions.iter().map(|&position| embedding[position]).collect::<Vec<_>>(), weight));
+
+    let exchange = Exchange::new(|x: &(Vec<Node>, W)| x.0.iter().fold(0u64, |h, &v| h.wrapping_mul(0x9E3779B97F4A7C15) ^ v as u64));
+    projected.unary_notify(exchange, "ProjectMatches", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+            changes.sort();
+            let mut session = output.session(&time);
+            let mut index = 0;
+            while index < changes.len() {
+                let start = index;
+                let mut diff = W::default();
+                while index < changes.len() && changes[index].0 == changes[start].0 {
+                    diff = diff + changes[index].1;
+                    index += 1;
+                }
+                if diff != W::default() {
+                    session.give((changes[start].0.clone(), diff));
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,290 @@
+//! Summary properties of the evolving graph, computed from the maintained indices.
+//!
+//! Monitoring a graph stream usually wants a few numbers per epoch rather than every match: how
+//! degrees are distributed, and how clustered the graph is. These operators derive them from the
+//! indices a plan already maintains, rather than from a second copy of the graph.
+//!
+//! `degree_histogram` reports changes in the number of vertices with each out-degree (or
+//! in-degree). Each worker reads the degrees of the keys it owns from the index once its initial
+//! contents are in place, and then follows the edge updates the index receives. `summarize`
+//! gathers the histogram and the matches of a triangle query at the first worker, and reports a
+//! `GraphSummary` for each time at which either changed. `degrees` maintains the out- and
+//! in-degrees of each vertex as a collection, from the `degree_changes` the indices report as they
+//! absorb updates.
+//!
+//! Clustering is measured as the fraction of wedges, pairs of edges leaving the same vertex, that
+//! are closed by an edge between their other endpoints. The triangle plan matches `x0->x1, x0->x2,
+//! x1->x2`, which closes exactly one wedge of out-edges (at `x0`) and one wedge of in-edges (at
+//! `x2`), so its matches over the wedges of either histogram are the fraction of closed wedges,
+//! counting a wedge twice if it is closed in both directions.
+//!
+//! The indices describe a time only until later updates are committed to them, so the indices must
+//! not be merged past a time before the operators have read it, as is the case when their outputs
+//! are probed along with the plan's before merging.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use abomonation::Abomonation;
+use timely::dataflow::*;
+use timely::dataflow::operators::*;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::graph_stream::GraphStreamIndex;
+use ::Weight;
+use super::{IndexStream, Node};
+use wings_rule::read_index;
+
+/// A change in the number of vertices with a degree, as `(degree, diff)`.
+pub type DegreeChange = (usize, i64);
+
+/// Summary properties of a graph at one time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphSummary {
+    /// The number of vertices with each degree, for degrees with vertices, in order of degree.
+    pub histogram: Vec<(usize, u64)>,
+    /// The number of triangle matches.
+    pub triangles: i64,
+}
+
+impl Abomonation for GraphSummary {
+    unsafe fn entomb<W: ::std::io::Write>(&self, write: &mut W) -> ::std::io::Result<()> {
+        self.histogram.entomb(write)
+    }
+    unsafe fn exhume<'a, 'b>(&'a mut self, bytes: &'b mut [u8]) -> Option<&'b mut [u8]> {
+        self.histogram.exhume(bytes)
+    }
+    fn extent(&self) -> usize {
+        self.histogram.extent()
+    }
+}
+
+impl GraphSummary {
+    /// The number of vertices with edges.
+    pub fn vertices(&self) -> u64 {
+        self.histogram.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// The number of edges, counted with multiplicity.
+    pub fn edges(&self) -> u64 {
+        self.histogram.iter().map(|&(degree, count)| degree as u64 * count).sum()
+    }
+
+    /// The number of pairs of edges sharing the vertex the histogram counts them at.
+    pub fn wedges(&self) -> u64 {
+        self.histogram.iter().map(|&(degree, count)| (degree as u64 * (degree as u64).saturating_sub(1) / 2) * count).sum()
+    }
+
+    /// The mean degree of vertices with edges, if there are any.
+    pub fn mean_degree(&self) -> Option<f64> {
+        let vertices = self.vertices();
+        if vertices == 0 { None } else { Some(self.edges() as f64 / vertices as f64) }
+    }
+
+    /// The triangle matches per wedge, if there are wedges.
+    pub fn clustering(&self) -> Option<f64> {
+        let wedges = self.wedges();
+        if wedges == 0 { None } else { Some(self.triangles as f64 / wedges as f64) }
+    }
+}
+
+impl<G: Scope, H1: Fn(Node)->u64+'static, H2: Fn(Node)->u64+'static> GraphStreamIndex<G, H1, H2> where G::Timestamp: Ord+Hash {
+
+    /// Changes in the histogram of out-degrees, if `is_forward`, or of in-degrees otherwise.
+    ///
+    /// Vertices without edges are not counted. The changes at the initial time describe the
+    /// initial contents of the index, including any restored from a snapshot.
+    pub fn degree_histogram(&self, is_forward: bool) -> Stream<G, DegreeChange> {
+        if is_forward {
+            degree_changes(&self.forward, &self.changes.map(|((src, _), diff)| (src, diff)))
+        }
+        else {
+            degree_changes(&self.reverse, &self.changes.map(|((_, dst), diff)| (dst, diff)))
+        }
+    }
+
+    /// The out- and in-degrees of vertices, as a collection of `(vertex, out, in)` that changes over time.
+    ///
+    /// Each vertex's degrees are maintained by the worker owning it in the forward index, which at
+    /// each time its degrees change retracts their earlier value and inserts the new one, once the
+    /// time is complete. Vertices without edges are absent. Negative degrees, which only updates
+    /// retracting absent edges produce, are reported as zero.
+    pub fn degrees(&self) -> Stream<G, ((Node, u64, u64), i32)> {
+        let hash = self.forward.hash.clone();
+        let mut degrees: HashMap<Node, (i64, i64)> = HashMap::new();
+        let mut stash = HashMap::new();
+        let mut buffer = Vec::new();
+
+        let exchange = Exchange::new(move |x: &(Node, i64, i64)| (*hash)(x.0));
+        self.degree_changes.unary_notify(exchange, "Degrees", vec![], move |input, output, notificator| {
+
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+                notificator.notify_at(time.retain());
+            });
+
+            let mut ready = Vec::new();
+            notificator.for_each(|time, _, _| ready.push(time));
+            ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+            for time in ready {
+                let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+                changes.sort();
+                let mut session = output.session(&time);
+                let mut position = 0;
+                while position < changes.len() {
+                    let node = changes[position].0;
+                    let (mut out_diff, mut in_diff) = (0, 0);
+                    while position < changes.len() && changes[position].0 == node {
+                        out_diff += changes[position].1;
+                        in_diff += changes[position].2;
+                        position += 1;
+                    }
+                    if out_diff == 0 && in_diff == 0 { continue; }
+
+                    let before = *degrees.get(&node).unwrap_or(&(0, 0));
+                    let after = (before.0 + out_diff, before.1 + in_diff);
+                    if after == (0, 0) { degrees.remove(&node); }
+                    else { degrees.insert(node, after); }
+
+                    let (before, after) = (clamped(before), clamped(after));
+                    if before != after {
+                        if before != (0, 0) { session.give(((node, before.0, before.1), -1)); }
+                        if after != (0, 0) { session.give(((node, after.0, after.1), 1)); }
+                    }
+                }
+            }
+        })
+    }
+}
+
+// degrees as reported, with negative degrees as zero.
+fn clamped(degrees: (i64, i64)) -> (u64, u64) {
+    (::std::cmp::max(degrees.0, 0) as u64, ::std::cmp::max(degrees.1, 0) as u64)
+}
+
+// changes in the histogram of the degrees of the keys of `index`, as `keys` updates them.
+fn degree_changes<G: Scope, H: Fn(Node)->u64+'static>(index: &IndexStream<Node, H, G::Timestamp>, keys: &Stream<G, (Node, i32)>) -> Stream<G, DegreeChange>
+    where G::Timestamp: Ord+Hash {
+
+    let hash = index.hash.clone();
+    let handle = index.handle.clone();
+    let index = index.index.clone();
+
+    let mut degrees: HashMap<Node, i64> = HashMap::new();
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    // the initial time, until the index has its initial contents, and then times ready to process.
+    let mut initial = None;
+    let mut initialized = false;
+    let mut ready = Vec::new();
+
+    let exchange = Exchange::new(move |x: &(Node, i32)| (*hash)(x.0));
+    keys.unary_notify(exchange, "DegreeHistogram", vec![Default::default()], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        notificator.for_each(|time, _, _| {
+            if !initialized && initial.is_none() && time.time() == &Default::default() { initial = Some(time); }
+            else { ready.push(time); }
+        });
+
+        // the degrees of the initial contents, once the index holds them.
+        if let Some(time) = initial.take() {
+            if handle.less_equal(time.time()) { initial = Some(time); }
+            else {
+                let mut session = output.session(&time);
+                for (key, degree) in read_index(&index).degrees_at(time.time()) {
+                    if degree > 0 { session.give((degree as usize, 1)); }
+                    degrees.insert(key, degree);
+                }
+                // updates at the initial time are already reflected in the index.
+                stash.remove(time.time());
+                initialized = true;
+            }
+        }
+
+        if initialized {
+            ready.sort_by(|x, y| x.time().cmp(y.time()));
+            for time in ready.drain(..) {
+                let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+                changes.sort();
+                let mut session = output.session(&time);
+                let mut index = 0;
+                while index < changes.len() {
+                    let key = changes[index].0;
+                    let mut diff = 0;
+                    while index < changes.len() && changes[index].0 == key {
+                        diff += changes[index].1 as i64;
+                        index += 1;
+                    }
+                    if diff != 0 {
+                        let before = *degrees.get(&key).unwrap_or(&0);
+                        let after = before + diff;
+                        if before > 0 { session.give((before as usize, -1)); }
+                        if after > 0 { session.give((after as usize, 1)); }
+                        if after == 0 { degrees.remove(&key); }
+                        else { degrees.insert(key, after); }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Gathers changes in a degree histogram and the matches of a triangle query at the first worker,
+/// and reports the summary of the graph at each time either changed.
+///
+/// Summaries are reported in order of time; times at which nothing changed are not reported.
+pub fn summarize<G: Scope, W: Weight>(histogram: &Stream<G, DegreeChange>, triangles: &Stream<G, (Vec<Node>, W)>) -> Stream<G, GraphSummary>
+    where G::Timestamp: Ord+Hash {
+
+    let mut counts: HashMap<usize, i64> = HashMap::new();
+    let mut total = 0i64;
+    let mut stash = HashMap::new();
+    let mut buffer1 = Vec::new();
+    let mut buffer2 = Vec::new();
+
+    let triangles = triangles.map(|(_, diff)| diff.to_i64());
+    histogram.binary_notify(&triangles, Exchange::new(|_: &DegreeChange| 0), Exchange::new(|_: &i64| 0), "GraphSummary", vec![], move |input1, input2, output, notificator| {
+
+        input1.for_each(|time, data| {
+            data.swap(&mut buffer1);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), 0)).0.extend(buffer1.drain(..));
+            notificator.notify_at(time.retain());
+        });
+        input2.for_each(|time, data| {
+            data.swap(&mut buffer2);
+            stash.entry(time.time().clone()).or_insert((Vec::new(), 0)).1 += buffer2.drain(..).sum::<i64>();
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            if let Some((changes, triangles)) = stash.remove(time.time()) {
+                for (degree, diff) in changes {
+                    let remove = {
+                        let count = counts.entry(degree).or_insert(0);
+                        *count += diff;
+                        *count == 0
+                    };
+                    if remove { counts.remove(&degree); }
+                }
+                total += triangles;
+
+                let mut histogram = counts.iter().filter(|x| *x.1 > 0).map(|(&degree, &count)| (degree, count as u64)).collect::<Vec<_>>();
+                histogram.sort();
+                output.session(&time).give(GraphSummary { histogram, triangles: total });
+            }
+        }
+    })
+}
@@ -0,0 +1,215 @@
+//! Names and types for the relations, labels, and attributes queries refer to.
+//!
+//! Plans and the graph they read identify everything by position: vertex and edge labels are
+//! integers, and attributes are whatever streams a program pairs with matches. A `Schema` declares
+//! names for them, so that queries written against the schema are checked as they are built, and
+//! refer to `person` or `age` rather than to label 3 or the second attribute stream.
+//!
+//! A schema declares named edge relations, vertex and edge labels with the integers the graph uses
+//! for them, and attributes with their types. Names are identifiers, and each kind has its own
+//! namespace. The default schema declares the one relation `edge`, as which plans read the graph.
+//!
+//! Schemas may also be read from text, one declaration per line:
+//!
+//! ```text
+//! relation follows
+//! vertex_label person 1
+//! edge_label knows 3
+//! attribute age int
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::collections::BTreeMap;
+
+/// The type of the values of an attribute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    /// Signed integers.
+    Int,
+    /// Floating point numbers.
+    Float,
+    /// Strings.
+    Text,
+    /// Booleans.
+    Bool,
+}
+
+impl AttributeType {
+    /// The type named `name`: `int`, `float`, `text`, or `bool`.
+    pub fn from_name(name: &str) -> Option<AttributeType> {
+        match name {
+            "int" => Some(AttributeType::Int),
+            "float" => Some(AttributeType::Float),
+            "text" => Some(AttributeType::Text),
+            "bool" => Some(AttributeType::Bool),
+            _ => None,
+        }
+    }
+
+    /// The name of the type, as `from_name` reads it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AttributeType::Int => "int",
+            AttributeType::Float => "float",
+            AttributeType::Text => "text",
+            AttributeType::Bool => "bool",
+        }
+    }
+
+    /// Whether values of the type are numbers, and so can be compared with numeric constants.
+    pub fn is_numeric(&self) -> bool {
+        *self == AttributeType::Int || *self == AttributeType::Float
+    }
+}
+
+/// The declared relations, labels, and attributes of a graph.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    relations: Vec<String>,
+    vertex_labels: BTreeMap<String, u32>,
+    edge_labels: BTreeMap<String, u32>,
+    attributes: BTreeMap<String, AttributeType>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        let mut schema = Schema::empty();
+        schema.relations.push("edge".to_owned());
+        schema
+    }
+}
+
+impl Schema {
+    /// A schema declaring the one relation `edge`.
+    pub fn new() -> Self { Schema::default() }
+
+    /// A schema declaring nothing.
+    pub fn empty() -> Self {
+        Schema { relations: Vec::new(), vertex_labels: BTreeMap::new(), edge_labels: BTreeMap::new(), attributes: BTreeMap::new() }
+    }
+
+    /// Reads a schema from its text, as described in the module documentation.
+    ///
+    /// The schema declares only what the text does, not the relation `edge`.
+    pub fn parse(text: &str) -> Result<Schema, String> {
+        let mut schema = Schema::empty();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            schema.declare(&fields).map_err(|error| format!("line {}: {}", number + 1, error))?;
+        }
+        Ok(schema)
+    }
+
+    // declares what the fields of a line of a schema's text do.
+    fn declare(&mut self, fields: &[&str]) -> Result<(), String> {
+        match (fields[0], fields.len()) {
+            ("relation", 2) => self.add_relation(fields[1]).map(|_| ()),
+            ("vertex_label", 3) | ("edge_label", 3) => {
+                let label = fields[2].parse::<u32>().map_err(|_| format!("label {:?} is not an integer", fields[2]))?;
+                if fields[0] == "vertex_label" { self.add_vertex_label(fields[1], label) } else { self.add_edge_label(fields[1], label) }
+            },
+            ("attribute", 3) => {
+                let kind = AttributeType::from_name(fields[2])
+                    .ok_or_else(|| format!("unknown attribute type {:?}, expected int, float, text, or bool", fields[2]))?;
+                self.add_attribute(fields[1], kind)
+            },
+            _ => Err(format!("malformed declaration {:?}", fields.join(" "))),
+        }
+    }
+
+    /// Declares the relation `name`, returning its position among the relations.
+    pub fn add_relation(&mut self, name: &str) -> Result<usize, String> {
+        check_name(name)?;
+        if self.relations.iter().any(|relation| relation == name) {
+            return Err(format!("relation {} is already declared", name));
+        }
+        self.relations.push(name.to_owned());
+        Ok(self.relations.len() - 1)
+    }
+
+    /// Declares the vertex label `name`, which the graph writes as `label`.
+    pub fn add_vertex_label(&mut self, name: &str, label: u32) -> Result<(), String> {
+        add_label(&mut self.vertex_labels, "vertex", name, label)
+    }
+
+    /// Declares the edge label `name`, which the graph writes as `label`.
+    pub fn add_edge_label(&mut self, name: &str, label: u32) -> Result<(), String> {
+        add_label(&mut self.edge_labels, "edge", name, label)
+    }
+
+    /// Declares the attribute `name`, whose values have type `kind`.
+    pub fn add_attribute(&mut self, name: &str, kind: AttributeType) -> Result<(), String> {
+        check_name(name)?;
+        if self.attributes.contains_key(name) {
+            return Err(format!("attribute {} is already declared", name));
+        }
+        self.attributes.insert(name.to_owned(), kind);
+        Ok(())
+    }
+
+    /// The declared relations, in order of declaration.
+    pub fn relations(&self) -> &[String] { &self.relations }
+
+    /// The position of relation `name`, or an error listing the declared relations.
+    pub fn relation(&self, name: &str) -> Result<usize, String> {
+        self.relations.iter().position(|relation| relation == name)
+            .ok_or_else(|| format!("unknown relation {:?}, {}", name, expected(self.relations.iter())))
+    }
+
+    /// The integer the graph writes vertex label `name` as, or an error listing the declared labels.
+    pub fn vertex_label(&self, name: &str) -> Result<u32, String> {
+        self.vertex_labels.get(name).cloned()
+            .ok_or_else(|| format!("unknown vertex label {:?}, {}", name, expected(self.vertex_labels.keys())))
+    }
+
+    /// The integer the graph writes edge label `name` as, or an error listing the declared labels.
+    pub fn edge_label(&self, name: &str) -> Result<u32, String> {
+        self.edge_labels.get(name).cloned()
+            .ok_or_else(|| format!("unknown edge label {:?}, {}", name, expected(self.edge_labels.keys())))
+    }
+
+    /// The name of the vertex label the graph writes as `label`, if one is declared.
+    pub fn vertex_label_name(&self, label: u32) -> Option<&str> {
+        self.vertex_labels.iter().find(|x| *x.1 == label).map(|x| x.0.as_str())
+    }
+
+    /// The name of the edge label the graph writes as `label`, if one is declared.
+    pub fn edge_label_name(&self, label: u32) -> Option<&str> {
+        self.edge_labels.iter().find(|x| *x.1 == label).map(|x| x.0.as_str())
+    }
+
+    /// The type of attribute `name`, or an error listing the declared attributes.
+    pub fn attribute(&self, name: &str) -> Result<AttributeType, String> {
+        self.attributes.get(name).cloned()
+            .ok_or_else(|| format!("unknown attribute {:?}, {}", name, expected(self.attributes.keys())))
+    }
+}
+
+// declares label `name` as `label` among `labels`, unless either is already declared.
+fn add_label(labels: &mut BTreeMap<String, u32>, kind: &str, name: &str, label: u32) -> Result<(), String> {
+    check_name(name)?;
+    if labels.contains_key(name) {
+        return Err(format!("{} label {} is already declared", kind, name));
+    }
+    if let Some(other) = labels.iter().find(|x| *x.1 == label) {
+        return Err(format!("{} label {} is already declared as {}", kind, label, other.0));
+    }
+    labels.insert(name.to_owned(), label);
+    Ok(())
+}
+
+// checks that `name` is an identifier, as queries write names.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && name.chars().next().map(|c| !c.is_numeric()).unwrap_or(false);
+    if valid { Ok(()) } else { Err(format!("{:?} is not a valid name", name)) }
+}
+
+// the names an unknown name was expected to be one of, for error messages.
+fn expected<'a, I: Iterator<Item=&'a String>>(names: I) -> String {
+    let names = names.map(|name| name.as_str()).collect::<Vec<_>>();
+    if names.is_empty() { "and none are declared".to_owned() } else { format!("expected one of {}", names.join(", ")) }
+}
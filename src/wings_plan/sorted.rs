@@ -0,0 +1,197 @@
+//! Sorted output of the changes to a query node's matches.
+//!
+//! Plans report the changes to matches in whatever order the workers produce them. Consumers that
+//! merge the output with other sorted data instead need each epoch's changes in order, by first
+//! vertex, then second, and so on. `sort_matches` gathers the changes of each time at the first
+//! worker and emits them there in sorted order, once the time is complete; changes to the same
+//! embedding are ordered by diff, and are not consolidated.
+//!
+//! An epoch's changes can be too many to hold in memory. With `spill_after` set, the changes of a
+//! time are sorted into runs of that many changes, each written to a file in `spill_dir`, and the
+//! runs are merged as they are read back to produce the output. Run files are removed once merged.
+//!
+//! Plans executing deterministically (see `Plan::set_deterministic`) instead have each worker
+//! emit the changes it produced in sorted order, with `order_matches`, so that their order does
+//! not depend on which stage of the plan completed first, without gathering them at one worker.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+
+use super::Node;
+use ::Weight;
+
+/// How the changes to a query node's matches are sorted before output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortedOutput {
+    /// The number of changes of a time held in memory before they are spilled as a sorted run.
+    pub spill_after: Option<usize>,
+    /// The directory spilled runs are written to.
+    pub spill_dir: PathBuf,
+}
+
+impl SortedOutput {
+    /// Sorts in memory, however many changes a time has.
+    pub fn in_memory() -> Self {
+        SortedOutput { spill_after: None, spill_dir: ::std::env::temp_dir() }
+    }
+
+    /// Sorts in runs of at most `changes` changes, spilled to files in `dir`.
+    pub fn spilling<P: Into<PathBuf>>(changes: usize, dir: P) -> Self {
+        assert!(changes > 0, "spilled runs need at least one change");
+        SortedOutput { spill_after: Some(changes), spill_dir: dir.into() }
+    }
+}
+
+// distinguishes the run files of the operators of one process.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Emits the changes of each time at the first worker, in sorted order, as `config` describes.
+pub fn sort_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>, config: &SortedOutput) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let config = config.clone();
+    let mut pending: HashMap<G::Timestamp, (Vec<(Vec<Node>, W)>, Vec<PathBuf>)> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    matches.unary_notify(Exchange::new(|_: &(Vec<Node>, W)| 0), "SortMatches", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            let entry = pending.entry(time.time().clone()).or_insert((Vec::new(), Vec::new()));
+            for change in buffer.drain(..) {
+                entry.0.push(change);
+                if config.spill_after.map(|limit| entry.0.len() >= limit).unwrap_or(false) {
+                    entry.0.sort();
+                    entry.1.push(spill(&config.spill_dir, &entry.0));
+                    entry.0.clear();
+                }
+            }
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            if let Some((mut memory, runs)) = pending.remove(time.time()) {
+                memory.sort();
+                let mut session = output.session(&time);
+                if runs.is_empty() {
+                    session.give_iterator(memory.into_iter());
+                }
+                else {
+                    merge_runs(memory, &runs, |change| session.give(change));
+                    for path in runs.iter() { let _ = fs::remove_file(path); }
+                }
+            }
+        }
+    })
+}
+
+/// Emits the changes of each time at the worker that produced them, in sorted order, once the
+/// time is complete.
+pub fn order_matches<G: Scope, W: Weight>(matches: &Stream<G, (Vec<Node>, W)>) -> Stream<G, (Vec<Node>, W)>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut pending: HashMap<G::Timestamp, Vec<(Vec<Node>, W)>> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    matches.unary_notify(Pipeline, "OrderMatches", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            pending.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            if let Some(mut changes) = pending.remove(time.time()) {
+                changes.sort();
+                output.session(&time).give_iterator(changes.into_iter());
+            }
+        }
+    })
+}
+
+// writes sorted `changes` to a new run file in `dir`, returning its path.
+fn spill<W: Weight>(dir: &PathBuf, changes: &[(Vec<Node>, W)]) -> PathBuf {
+    let path = dir.join(format!("sorted-{}-{}.run", ::std::process::id(), RUNS.fetch_add(1, Ordering::SeqCst)));
+    write_run(&path, changes).unwrap_or_else(|error| panic!("couldn't spill sorted matches to {}: {}", path.display(), error));
+    path
+}
+
+fn write_run<W: Weight>(path: &PathBuf, changes: &[(Vec<Node>, W)]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for &(ref embedding, diff) in changes.iter() {
+        writer.write_all(&(embedding.len() as u32).to_le_bytes())?;
+        for node in embedding.iter() { writer.write_all(&node.to_le_bytes())?; }
+        writer.write_all(&diff.to_i64().to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+fn fail(path: &PathBuf, error: io::Error) -> ! {
+    panic!("couldn't read spilled matches from {}: {}", path.display(), error)
+}
+
+// reads the next change of a run, or `None` at its end.
+fn read_change<R: Read, W: Weight>(reader: &mut R) -> io::Result<Option<(Vec<Node>, W)>> {
+    let mut word = [0u8; 4];
+    match reader.read_exact(&mut word) {
+        Ok(()) => { },
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let length = u32::from_le_bytes(word) as usize;
+    let mut embedding = Vec::with_capacity(length);
+    for _ in 0 .. length {
+        reader.read_exact(&mut word)?;
+        embedding.push(u32::from_le_bytes(word));
+    }
+    let mut diff = [0u8; 8];
+    reader.read_exact(&mut diff)?;
+    Ok(Some((embedding, W::from_i64(i64::from_le_bytes(diff)))))
+}
+
+// merges the sorted changes in `memory` with the sorted runs at `runs`, in order.
+fn merge_runs<W: Weight, F: FnMut((Vec<Node>, W))>(memory: Vec<(Vec<Node>, W)>, runs: &[PathBuf], mut emit: F) {
+
+    let mut readers = Vec::new();
+    for path in runs.iter() {
+        readers.push(BufReader::new(File::open(path).unwrap_or_else(|error| fail(path, error))));
+    }
+    let mut memory = memory.into_iter();
+
+    // the next change of each source, with the source: runs by index, and memory last.
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(change) = read_change(reader).unwrap_or_else(|error| fail(&runs[index], error)) {
+            heap.push(Reverse((change, index)));
+        }
+    }
+    if let Some(change) = memory.next() { heap.push(Reverse((change, runs.len()))); }
+
+    while let Some(Reverse((change, index))) = heap.pop() {
+        emit(change);
+        let next = if index < readers.len() {
+            read_change(&mut readers[index]).unwrap_or_else(|error| fail(&runs[index], error))
+        }
+        else {
+            memory.next()
+        };
+        if let Some(change) = next { heap.push(Reverse((change, index))); }
+    }
+}
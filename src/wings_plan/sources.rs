@@ -0,0 +1,226 @@
+//! Relations other than the graph, from which plans extend prefixes.
+//!
+//! A plan operation reads its pattern edge from the graph indices, unless it names a source (see
+//! `Plan::set_operation_source`), in which case the vertices it proposes, or the pairs it checks,
+//! come from an `ExtensionSource` registered with the plan (see `Plan::set_source`): a
+//! precomputed list of similar vertices, a table held by an external store, or any other relation
+//! from vertices to sorted lists of vertices. Sources take part in generic join as the indices
+//! do: each counts the values it would propose for a prefix, the one counting fewest proposes
+//! them, and every other keeps only the proposals it holds. `SourceExtender` adapts a source to
+//! `StreamPrefixExtender`, the trait the indices implement, so that sources can also be used
+//! directly with `GenericJoin::extend`, alongside extenders reading the indices.
+//!
+//! Generic join is worst-case optimal only if every extender keeps to the contract documented on
+//! `StreamPrefixExtender`: counts are exact, or exceed the values proposed by at most a constant
+//! factor; proposing takes time proportional to the values proposed; and checking a value takes
+//! time that does not grow with the number of values, or grows logarithmically. `check_source`
+//! validates what can be observed of a source against the contract, for the tests of custom sources.
+//!
+//! Every worker holds the whole of each source, as every worker holds the bindings of parameters,
+//! so that prefixes are extended from sources where they are, without being exchanged. Sources
+//! are read as prefixes reach them, and should not change while a dataflow reads them: the matches
+//! reported for an update reflect the source as it was when the update was processed, and are not
+//! revised when the source changes.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Filter, Map};
+
+use super::StreamPrefixExtender;
+use ::{Node, Edge};
+
+/// A relation from vertices to sorted lists of vertices, which plan operations may read in place
+/// of the graph.
+///
+/// Sources are shared by every worker, and must be safe to read from all of them at once.
+pub trait ExtensionSource: Send+Sync {
+    /// The number of values `key` relates to, or a bound on it within a constant factor.
+    fn count(&self, key: Node) -> usize;
+
+    /// Appends the values `key` relates to to `values`, in increasing order and without repeats.
+    fn propose(&self, key: Node, values: &mut Vec<Node>);
+
+    /// Whether `key` relates to `value`.
+    ///
+    /// The default proposes every value of `key` and searches them, which takes time proportional
+    /// to their number; sources that can look up a single value should do so instead.
+    fn contains(&self, key: Node, value: Node) -> bool {
+        let mut values = Vec::new();
+        self.propose(key, &mut values);
+        values.binary_search(&value).is_ok()
+    }
+}
+
+/// A source holding its relation in memory, as a sorted list of values for each key.
+#[derive(Clone, Debug, Default)]
+pub struct ListSource {
+    lists: HashMap<Node, Vec<Node>>,
+}
+
+impl ListSource {
+    /// A source relating the first vertex of each of `pairs` to the second; repeated pairs count once.
+    pub fn from_pairs<I: IntoIterator<Item=Edge>>(pairs: I) -> Self {
+        let mut lists = HashMap::new();
+        for (key, value) in pairs {
+            lists.entry(key).or_insert(Vec::new()).push(value);
+        }
+        for list in lists.values_mut() {
+            list.sort();
+            list.dedup();
+        }
+        ListSource { lists }
+    }
+
+    /// The number of pairs the source holds.
+    pub fn len(&self) -> usize {
+        self.lists.values().map(|list| list.len()).sum()
+    }
+}
+
+impl ExtensionSource for ListSource {
+    fn count(&self, key: Node) -> usize {
+        self.lists.get(&key).map(|list| list.len()).unwrap_or(0)
+    }
+
+    fn propose(&self, key: Node, values: &mut Vec<Node>) {
+        if let Some(list) = self.lists.get(&key) { values.extend_from_slice(list); }
+    }
+
+    fn contains(&self, key: Node, value: Node) -> bool {
+        self.lists.get(&key).map(|list| list.binary_search(&value).is_ok()).unwrap_or(false)
+    }
+}
+
+/// An extender proposing the values `source` relates the vertex `logic` reads from each prefix to.
+///
+/// Every worker reads its own prefixes from the source, so no operator of the extender exchanges
+/// data.
+pub struct SourceExtender<P, L> {
+    source: Arc<ExtensionSource>,
+    logic: Rc<L>,
+    phantom: PhantomData<P>,
+}
+
+impl<P, L: Fn(&P)->Node> SourceExtender<P, L> {
+    /// An extender reading `source` with the vertex `logic` reads from each prefix.
+    pub fn new(source: Arc<ExtensionSource>, logic: L) -> Self {
+        SourceExtender { source, logic: Rc::new(logic), phantom: PhantomData }
+    }
+}
+
+impl<G, W, P, L> StreamPrefixExtender<G, W> for SourceExtender<P, L>
+    where G: Scope, W: Data, P: Data, L: Fn(&P)->Node+'static {
+
+    type Prefix = P;
+    type Extension = Node;
+
+    fn count(&self, prefixes: Stream<G, (P, u64, u64, W)>, ident: u64) -> Stream<G, (P, u64, u64, W)> {
+        let (source, logic) = (self.source.clone(), self.logic.clone());
+        prefixes
+            .map(move |(p, count, index, w)| {
+                let mine = source.count((*logic)(&p)) as u64;
+                if mine < count { (p, mine, ident, w) } else { (p, count, index, w) }
+            })
+            .filter(|x| x.1 > 0)
+    }
+
+    fn propose(&self, stream: Stream<G, (P, W)>) -> Stream<G, (P, Vec<Node>, W)> {
+        let (source, logic) = (self.source.clone(), self.logic.clone());
+        stream
+            .map(move |(p, w)| {
+                let mut values = Vec::new();
+                source.propose((*logic)(&p), &mut values);
+                (p, values, w)
+            })
+            .filter(|x| !x.1.is_empty())
+    }
+
+    fn intersect(&self, stream: Stream<G, (P, Vec<Node>, W)>) -> Stream<G, (P, Vec<Node>, W)> {
+        let (source, logic) = (self.source.clone(), self.logic.clone());
+        stream.map(move |(p, mut es, w)| {
+            let key = (*logic)(&p);
+            es.retain(|&e| source.contains(key, e));
+            (p, es, w)
+        })
+    }
+
+    fn propose_counted(&self, stream: Stream<G, (P, W)>) -> Stream<G, (P, Vec<(Node, i32)>, W)> {
+        self.propose(stream).map(|(p, es, w)| (p, es.into_iter().map(|e| (e, 1)).collect(), w))
+    }
+
+    fn intersect_counted(&self, stream: Stream<G, (P, Vec<(Node, i32)>, W)>) -> Stream<G, (P, Vec<(Node, i32)>, W)> {
+        let (source, logic) = (self.source.clone(), self.logic.clone());
+        stream.map(move |(p, mut es, w)| {
+            let key = (*logic)(&p);
+            es.retain(|e| source.contains(key, e.0));
+            (p, es, w)
+        })
+    }
+}
+
+/// What `check_source` observed of a source.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceReport {
+    /// The keys checked.
+    pub keys: usize,
+    /// The values proposed for them.
+    pub proposed: usize,
+    /// The largest ratio of a key's count to the number of values proposed for it, taking keys
+    /// without values to have one; exact counts have ratio one.
+    pub overcount: f64,
+}
+
+/// Checks that `source` keeps the contract of generic join for each of `keys`, returning what it
+/// observed, or a description of the first violation.
+///
+/// For each key, the values proposed must be in increasing order without repeats, and the same
+/// each time they are proposed. The count must be at least their number, and at most
+/// `max_overcount` times it. `contains` must hold for each value proposed, and fail for the
+/// vertices just before and after each of them, and for the least and greatest vertices, unless
+/// they are proposed. How long each method takes can not be observed, and is not checked.
+pub fn check_source<S: ExtensionSource+?Sized>(source: &S, keys: &[Node], max_overcount: f64) -> Result<SourceReport, String> {
+    let mut report = SourceReport { keys: keys.len(), proposed: 0, overcount: 1.0 };
+    let mut values = Vec::new();
+    let mut again = Vec::new();
+    for &key in keys.iter() {
+        values.clear();
+        source.propose(key, &mut values);
+        if let Some(position) = (1 .. values.len()).find(|&position| values[position - 1] >= values[position]) {
+            return Err(format!("key {} proposes {} after {}, out of increasing order", key, values[position], values[position - 1]));
+        }
+        again.clear();
+        source.propose(key, &mut again);
+        if values != again {
+            return Err(format!("key {} proposes {:?}, and then {:?}", key, values, again));
+        }
+
+        let count = source.count(key);
+        if count < values.len() {
+            return Err(format!("key {} counts {} values, but proposes {}", key, count, values.len()));
+        }
+        let overcount = count as f64 / ::std::cmp::max(values.len(), 1) as f64;
+        if overcount > max_overcount {
+            return Err(format!("key {} counts {} values, but proposes {}, more than {} times fewer", key, count, values.len(), max_overcount));
+        }
+        if overcount > report.overcount { report.overcount = overcount; }
+
+        if let Some(&value) = values.iter().find(|&&value| !source.contains(key, value)) {
+            return Err(format!("key {} proposes {}, but does not contain it", key, value));
+        }
+        let mut absent = vec![0, Node::max_value()];
+        for &value in values.iter() {
+            if value > 0 { absent.push(value - 1); }
+            if value < Node::max_value() { absent.push(value + 1); }
+        }
+        if let Some(&value) = absent.iter().find(|&&value| values.binary_search(&value).is_err() && source.contains(key, value)) {
+            return Err(format!("key {} contains {}, but does not propose it", key, value));
+        }
+        report.proposed += values.len();
+    }
+    Ok(report)
+}
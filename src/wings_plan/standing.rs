@@ -0,0 +1,154 @@
+//! The standing matches of query nodes, consolidated from the changes to them.
+//!
+//! Consumers of a plan's output usually apply each epoch's changes to matches to their own copy of
+//! the result. A consumer that restarts, or that joins late, would otherwise have to replay every
+//! change since the base graph was loaded to rebuild it. Plans may instead have engines hold the
+//! standing matches of each query node (see `Plan::set_standing_matches`), from which a consumer
+//! resyncs at an epoch boundary and then applies the changes of later epochs.
+//!
+//! `consolidate_standing` exchanges the changes by query node and match, and applies those of each
+//! time to the multiplicities held at the worker owning the match once the time is complete, so
+//! that between epochs the union of the workers' `StandingMatches` is the standing result of every
+//! completed epoch. Matches whose multiplicity returns to zero are forgotten. The holder accounts
+//! for the memory its matches take, which grows with the standing result rather than with the
+//! number of changes.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::Node;
+
+// the multiplicities of the standing matches, and an estimate of the bytes they take.
+#[derive(Default)]
+struct Standing {
+    matches: HashMap<(usize, Vec<Node>), i64>,
+    bytes: usize,
+}
+
+// the bytes a standing match of `vertices` vertices takes: its entry, and its vertices.
+fn entry_bytes(vertices: usize) -> usize {
+    mem::size_of::<((usize, Vec<Node>), i64)>() + vertices * mem::size_of::<Node>()
+}
+
+/// The standing matches of query nodes held at a worker.
+///
+/// Clones refer to the same matches. Like the indices, the matches belong to the worker holding
+/// them, and are only complete between epochs.
+#[derive(Clone, Default)]
+pub struct StandingMatches {
+    standing: Rc<RefCell<Standing>>,
+}
+
+impl StandingMatches {
+    /// Allocates a holder of no matches.
+    pub fn new() -> Self { StandingMatches::default() }
+
+    /// The number of standing matches held, over all query nodes.
+    pub fn len(&self) -> usize { self.standing.borrow().matches.len() }
+
+    /// True if no match is held.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// An estimate of the bytes the matches held take.
+    pub fn heap_size(&self) -> usize {
+        let standing = self.standing.borrow();
+        let (capacity, len) = (standing.matches.capacity(), standing.matches.len());
+        // hash tables keep some slots free, and a control byte beside each slot.
+        standing.bytes + (capacity - len) * mem::size_of::<((usize, Vec<Node>), i64)>() + capacity
+    }
+
+    /// The standing matches of `query` with their multiplicities, sorted by match.
+    pub fn matches(&self, query: usize) -> Vec<(Vec<Node>, i64)> {
+        let mut matches = self.standing.borrow().matches.iter()
+            .filter(|&(&(q, _), _)| q == query)
+            .map(|(&(_, ref embedding), &count)| (embedding.clone(), count))
+            .collect::<Vec<_>>();
+        matches.sort();
+        matches
+    }
+
+    /// Every standing match with its multiplicity, as `(query, match, count)` triples sorted by
+    /// query node and then match, in the form of the changes that would create them from nothing.
+    pub fn dump(&self) -> Vec<(usize, Vec<Node>, i64)> {
+        let mut matches = self.standing.borrow().matches.iter()
+            .map(|(&(query, ref embedding), &count)| (query, embedding.clone(), count))
+            .collect::<Vec<_>>();
+        matches.sort();
+        matches
+    }
+
+    /// Calls `logic` with each standing match and its multiplicity, as `(query, match, count)`, in
+    /// no particular order and without copying them.
+    ///
+    /// `logic` must not step the worker, which would change the matches as they are read.
+    pub fn for_each<F: FnMut(usize, &[Node], i64)>(&self, mut logic: F) {
+        for (&(query, ref embedding), &count) in self.standing.borrow().matches.iter() {
+            logic(query, embedding, count);
+        }
+    }
+
+    // applies the change of `diff` to the multiplicity of `embedding` as a match of `query`.
+    fn update(&self, query: usize, embedding: Vec<Node>, diff: i64) {
+        let mut standing = self.standing.borrow_mut();
+        let bytes = entry_bytes(embedding.len());
+        let key = (query, embedding);
+        let (before, after) = {
+            let count = standing.matches.entry(key.clone()).or_insert(0);
+            *count += diff;
+            (*count - diff, *count)
+        };
+        if before == 0 { standing.bytes += bytes; }
+        if after == 0 {
+            standing.matches.remove(&key);
+            standing.bytes -= bytes;
+        }
+    }
+}
+
+/// Applies the changes to matches `changes`, as `(query, match, diff)`, to `standing` at the worker
+/// owning each match once their time is complete.
+///
+/// The returned stream carries no data; a probe of it tells when `standing` reflects a time.
+pub fn consolidate_standing<G: Scope>(changes: &Stream<G, (usize, Vec<Node>, i64)>, standing: StandingMatches) -> Stream<G, ()>
+    where G::Timestamp: ::std::hash::Hash {
+
+    let mut stash = HashMap::new();
+    let mut buffer = Vec::new();
+
+    let exchange = Exchange::new(|x: &(usize, Vec<Node>, i64)| x.1.iter().fold(x.0 as u64, |h, &v| h.wrapping_mul(0x9E3779B97F4A7C15) ^ v as u64));
+    changes.unary_notify(exchange, "ConsolidateStanding", vec![], move |input, _output, notificator| {
+
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(buffer.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        let mut ready = Vec::new();
+        notificator.for_each(|time, _, _| ready.push(time));
+        ready.sort_by(|x, y| x.time().cmp(y.time()));
+
+        for time in ready {
+            let mut changes = stash.remove(time.time()).unwrap_or(Vec::new());
+            changes.sort();
+            let mut index = 0;
+            while index < changes.len() {
+                let start = index;
+                let mut diff = 0;
+                while index < changes.len() && changes[index].0 == changes[start].0 && changes[index].1 == changes[start].1 {
+                    diff += changes[index].2;
+                    index += 1;
+                }
+                if diff != 0 {
+                    standing.update(changes[start].0, changes[start].1.clone(), diff);
+                }
+            }
+        }
+    })
+}
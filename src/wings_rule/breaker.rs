@@ -0,0 +1,58 @@
+//! Cooperative cancellation of enumeration that exceeds a time budget.
+//!
+//! A single update can create a combinatorial number of matches, for example when it connects a
+//! vertex of very high degree. A `Breaker` lets a driver bound the time spent enumerating: once it
+//! is armed and its deadline passes, the operators reading the indices discard the prefixes they
+//! hold rather than extending them, recording how many they discarded. Index maintenance is never
+//! interrupted, so the indices remain correct and later epochs proceed as usual; only the changes
+//! in matches reported for the interrupted epoch are partial.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// A shared deadline for enumeration, with a count of the prefixes discarded after it.
+#[derive(Clone, Default)]
+pub struct Breaker {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    discarded: usize,
+}
+
+impl Breaker {
+    /// Allocates a breaker that is not armed.
+    pub fn new() -> Self { Breaker::default() }
+
+    /// Arms the breaker to trip once `budget` has elapsed from now.
+    pub fn arm(&self, budget: Duration) {
+        self.state.borrow_mut().deadline = Some(Instant::now() + budget);
+    }
+
+    /// True if the breaker is armed.
+    pub fn is_armed(&self) -> bool {
+        self.state.borrow().deadline.is_some()
+    }
+
+    /// Disarms the breaker, so that it no longer trips.
+    pub fn disarm(&self) {
+        self.state.borrow_mut().deadline = None;
+    }
+
+    /// True if the breaker has tripped, in which case the `count` prefixes in hand are counted as
+    /// discarded and must not be processed further.
+    pub fn discard(&self, count: usize) -> bool {
+        let mut state = self.state.borrow_mut();
+        let tripped = state.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false);
+        if tripped { state.discarded += count; }
+        tripped
+    }
+
+    /// The number of prefixes discarded since the last call, resetting the count.
+    pub fn take_discarded(&self) -> usize {
+        ::std::mem::replace(&mut self.state.borrow_mut().discarded, 0)
+    }
+}
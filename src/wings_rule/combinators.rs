@@ -0,0 +1,149 @@
+//! Derived prefix extenders, restricting or renaming the proposals of another extender.
+//!
+//! A `filtered` extender proposes only the extensions of its inner extender that satisfy a
+//! predicate, and a `mapped` extender proposes the images of its inner extender's extensions
+//! under a bijection. Both read the inner extender's index, so that custom dataflows can build
+//! specialized relations (e.g. only neighbors with even identifiers) without maintaining another
+//! copy of an index.
+//!
+//! The inner extender's counts remain valid upper bounds for a filtered extender, and are exact
+//! for a mapped one, so both take part in `GenericJoin::extend` as any other extender does.
+
+use std::rc::Rc;
+
+use timely::Data;
+use timely::dataflow::*;
+use timely::dataflow::operators::*;
+
+use super::StreamPrefixExtender;
+
+/// An extender proposing only the extensions of `inner` that satisfy a predicate.
+pub struct Filtered<E, F> {
+    inner: E,
+    predicate: Rc<F>,
+}
+
+/// An extender proposing the images of the extensions of `inner` under a bijection.
+pub struct Mapped<E, F, B> {
+    inner: E,
+    forward: Rc<F>,
+    backward: Rc<B>,
+}
+
+/// Extension methods deriving extenders from an extender.
+pub trait ExtenderCombinators<G: Scope, W: Data> : StreamPrefixExtender<G, W>+Sized {
+    /// Restricts the proposals of this extender to the extensions satisfying `predicate`.
+    fn filtered<F>(self, predicate: F) -> Filtered<Self, F>
+        where F: Fn(&Self::Extension)->bool+'static {
+        Filtered { inner: self, predicate: Rc::new(predicate) }
+    }
+
+    /// Maps the proposals of this extender through `forward`, whose inverse is `backward`.
+    ///
+    /// Intersection maps proposals back through `backward` to consult the inner extender, so the
+    /// two functions must be inverses on the extensions the inner extender proposes.
+    fn mapped<X, F, B>(self, forward: F, backward: B) -> Mapped<Self, F, B>
+        where X: Data+Ord, F: Fn(Self::Extension)->X+'static, B: Fn(X)->Self::Extension+'static {
+        Mapped { inner: self, forward: Rc::new(forward), backward: Rc::new(backward) }
+    }
+}
+
+impl<G: Scope, W: Data, E: StreamPrefixExtender<G, W>> ExtenderCombinators<G, W> for E { }
+
+impl<G, W, E, F> StreamPrefixExtender<G, W> for Filtered<E, F>
+    where G: Scope, W: Data, E: StreamPrefixExtender<G, W>, F: Fn(&E::Extension)->bool+'static {
+
+    type Prefix = E::Prefix;
+    type Extension = E::Extension;
+
+    fn count(&self, prefixes: Stream<G, (Self::Prefix, u64, u64, W)>, ident: u64) -> Stream<G, (Self::Prefix, u64, u64, W)> {
+        self.inner.count(prefixes, ident)
+    }
+
+    fn propose(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        let predicate = self.predicate.clone();
+        self.inner.propose(stream)
+            .map(move |(p, mut es, w)| { es.retain(|e| (*predicate)(e)); (p, es, w) })
+            .filter(|x| !x.1.is_empty())
+    }
+
+    fn intersect(&self, stream: Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        let predicate = self.predicate.clone();
+        self.inner.intersect(stream)
+            .map(move |(p, mut es, w)| { es.retain(|e| (*predicate)(e)); (p, es, w) })
+    }
+
+    fn propose_counted(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        let predicate = self.predicate.clone();
+        self.inner.propose_counted(stream)
+            .map(move |(p, mut es, w)| { es.retain(|e| (*predicate)(&e.0)); (p, es, w) })
+            .filter(|x| !x.1.is_empty())
+    }
+
+    fn intersect_counted(&self, stream: Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        let predicate = self.predicate.clone();
+        self.inner.intersect_counted(stream)
+            .map(move |(p, mut es, w)| { es.retain(|e| (*predicate)(&e.0)); (p, es, w) })
+    }
+}
+
+// proposals are kept sorted, as intersection relies on it; a bijection need not preserve order.
+impl<G, W, E, X, F, B> StreamPrefixExtender<G, W> for Mapped<E, F, B>
+    where G: Scope, W: Data, E: StreamPrefixExtender<G, W>, E::Extension: Ord, X: Data+Ord,
+          F: Fn(E::Extension)->X+'static, B: Fn(X)->E::Extension+'static {
+
+    type Prefix = E::Prefix;
+    type Extension = X;
+
+    fn count(&self, prefixes: Stream<G, (Self::Prefix, u64, u64, W)>, ident: u64) -> Stream<G, (Self::Prefix, u64, u64, W)> {
+        self.inner.count(prefixes, ident)
+    }
+
+    fn propose(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        let forward = self.forward.clone();
+        self.inner.propose(stream).map(move |(p, es, w)| {
+            let mut xs = es.into_iter().map(|e| (*forward)(e)).collect::<Vec<_>>();
+            xs.sort();
+            (p, xs, w)
+        })
+    }
+
+    fn intersect(&self, stream: Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        let forward = self.forward.clone();
+        let backward = self.backward.clone();
+        let stream = stream.map(move |(p, xs, w)| {
+            let mut es = xs.into_iter().map(|x| (*backward)(x)).collect::<Vec<_>>();
+            es.sort();
+            (p, es, w)
+        });
+        self.inner.intersect(stream).map(move |(p, es, w)| {
+            let mut xs = es.into_iter().map(|e| (*forward)(e)).collect::<Vec<_>>();
+            xs.sort();
+            (p, xs, w)
+        })
+    }
+
+    fn propose_counted(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        let forward = self.forward.clone();
+        self.inner.propose_counted(stream).map(move |(p, es, w)| {
+            let mut xs = es.into_iter().map(|(e, c)| ((*forward)(e), c)).collect::<Vec<_>>();
+            xs.sort_by(|x, y| x.0.cmp(&y.0));
+            (p, xs, w)
+        })
+    }
+
+    fn intersect_counted(&self, stream: Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        let forward = self.forward.clone();
+        let backward = self.backward.clone();
+        let stream = stream.map(move |(p, xs, w)| {
+            let mut es = xs.into_iter().map(|(x, c)| ((*backward)(x), c)).collect::<Vec<_>>();
+            es.sort_by(|x, y| x.0.cmp(&y.0));
+            (p, es, w)
+        });
+        self.inner.intersect_counted(stream).map(move |(p, es, w)| {
+            let mut xs = es.into_iter().map(|(e, c)| ((*forward)(e), c)).collect::<Vec<_>>();
+            xs.sort_by(|x, y| x.0.cmp(&y.0));
+            (p, xs, w)
+        })
+    }
+}
@@ -5,14 +5,15 @@ use std::marker::PhantomData;
 use std::fmt::Debug;
 use std::hash::Hash;
 
-use timely::ExchangeData;
+use timely::{Data, ExchangeData};
 use timely::dataflow::{Stream, Scope};
 use timely::dataflow::operators::{Probe, Operator};
 use timely::dataflow::channels::pact::Exchange;
 use timely::progress::Timestamp;
 use timely::dataflow::operators::probe::Handle as ProbeHandle;
 
-use super::{Index, StreamPrefixExtender};
+use super::{Index, Extensions, StreamPrefixExtender, read_index, write_index};
+use super::order::{ready_entries, sort_prefixes};
 use ::Indexable;
 
 /// An index materialized from streamed updates.
@@ -23,6 +24,10 @@ use ::Indexable;
 /// about whether outstanding times might still exist less than any query time.
 /// There is also a function `hash` from the key type `K` to `u64` values to indicate how
 /// the data are partitioned, so that users can align their query streams.
+///
+/// An `IndexStream` belongs to the worker whose dataflow built it, and is neither `Send` nor `Sync`;
+/// other threads should be given copies of the data they need, e.g. from `Index::snapshot`. The
+/// index may be read between steps of the worker with `read_index`, which explains misuse.
 pub struct IndexStream<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp> {
     /// Times completely absorded into the index.
     pub handle: ProbeHandle<T>,
@@ -52,6 +57,30 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
             hash: self.hash.clone(),
             is_forward: self.is_forward,
             logic: Rc::new(logic),
+            accept: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// As `extend_using`, but proposing and intersecting with only the values satisfying `accept`.
+    ///
+    /// The extender describes the relation of the index restricted to the values `accept` admits,
+    /// e.g. the neighbors with a required label, which the index filters as it reads them rather
+    /// than after proposing them. Counts are those of the whole relation, which remain upper
+    /// bounds. `exclude` reads the whole relation.
+    pub fn extend_where<P, L, A>(&self, logic: L, accept: A) -> Rc<IndexExtender<K, T, P, L, H>>
+        where
+            L: Fn(&P)->K+'static,
+            P: Indexable<K>,
+            A: Fn(&K)->bool+'static,
+    {
+        Rc::new(IndexExtender {
+            handle: self.handle.clone(),
+            index: self.index.clone(),
+            hash: self.hash.clone(),
+            is_forward: self.is_forward,
+            logic: Rc::new(logic),
+            accept: Some(Rc::new(accept)),
             phantom: PhantomData,
         })
     }
@@ -62,7 +91,42 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
     /// The index can be static with no changes, or wholy dynamic with no starting data,
     /// or a mix of both. If neither stream has any data, you are probably using the wrong
     /// abstraction (though it will still work correctly).
-    pub fn from<G>(hash: H, initially: &Stream<G, (K, K)>, updates: &Stream<G, ((K, K), i32)>, is_forward: bool) -> Self
+    ///
+    /// Also returns the changes in the number of values of each key, net at each time, which the
+    /// worker owning the key reports once it has absorbed the time's updates.
+    pub fn from<G>(hash: H, initially: &Stream<G, (K, K)>, updates: &Stream<G, ((K, K), i32)>, is_forward: bool) -> (Self, Stream<G, (K, i64)>)
+        where
+            G: Scope<Timestamp=T>,
+            K: ExchangeData,
+            T: Hash,
+            H: 'static
+    {
+        IndexStream::build(hash, Index::new(), initially, updates, is_forward, true)
+    }
+
+    /// Constructs an `IndexStream` from an existing index and an update stream.
+    ///
+    /// The index is typically restored with `Index::from_snapshot`, from a snapshot taken by a
+    /// worker of an earlier run, which allows a computation to resume without replaying its
+    /// initial data. Each worker must supply the index of the worker with the same index in the
+    /// earlier run, which must have had the same number of workers and the same `hash`.
+    ///
+    /// Also returns the changes in the number of values of each key, as `from` does. The numbers
+    /// of values the restored index holds are reported at the first time it receives updates.
+    pub fn from_index<G>(hash: H, index: Index<K, T>, updates: &Stream<G, ((K, K), i32)>, is_forward: bool) -> (Self, Stream<G, (K, i64)>)
+        where
+            G: Scope<Timestamp=T>,
+            K: ExchangeData,
+            T: Hash,
+            H: 'static
+    {
+        use timely::dataflow::operators::ToStream;
+        let initially = Vec::new().to_stream(&mut updates.scope());
+        IndexStream::build(hash, index, &initially, updates, is_forward, false)
+    }
+
+    // constructs the index operator, loading initial data into `index` only if `initialize` is set.
+    fn build<G>(hash: H, index: Index<K, T>, initially: &Stream<G, (K, K)>, updates: &Stream<G, ((K, K), i32)>, is_forward: bool, initialize: bool) -> (Self, Stream<G, (K, i64)>)
         where
             G: Scope<Timestamp=T>,
             K: ExchangeData,
@@ -73,7 +137,7 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
 
         let worker_index = initially.scope().index();
 
-        let index_1 = Rc::new(RefCell::new(Index::new()));  // held by operator
+        let index_1 = Rc::new(RefCell::new(index));         // held by operator
         let index_2 = index_1.clone();                      // returned in `IndexStream`.
 
         let hash_1 = Rc::new(hash);     // used by exchange pact 1.
@@ -81,7 +145,9 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
         let hash_3 = hash_1.clone();    // returned in `IndexStream`.
 
         let mut map = HashMap::new();
-        let mut sorter = Some(MergeSorter::new(|x: &(K,K)| x.clone()));
+        let mut sorter = if initialize { Some(MergeSorter::new(|x: &(K,K)| x.clone())) } else { None };
+        // a restored index reports the numbers of values it holds along with its first changes.
+        let mut restored = !initialize;
 
         let exch1 = Exchange::new(move |x: &((K,K),i32)| (*hash_1)((x.0).0.clone()));
         let exch2 = Exchange::new(move |x: &(K,K)| (*hash_2)(x.0.clone()));
@@ -89,14 +155,12 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
         let mut buffer1 = Vec::new();
         let mut buffer2 = Vec::new();
 
-        let handle = updates.binary_notify(initially, exch1, exch2, "Index", vec![],
-                                           //::<_,(),_,_,_>
-                                           move |input1, input2,_output,notificator| {
+        let counts = updates.binary_notify(initially, exch1, exch2, "Index", vec![],
+                                           move |input1, input2, output, notificator| {
 
 
                                                // extract, enqueue updates.
                                                input1.for_each(|time, data| {
-                                                   if false { _output.session(&time).give(()); }
                                                    data.swap(&mut buffer1);
                                                    map.entry(time.time().clone())
                                                        .or_insert(Vec::new())
@@ -111,36 +175,68 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IndexStream<K, H, T> {
                                                        sorter.push(&mut buffer2);
                                                        notificator.notify_at(time.retain());
                                                    }
+                                                   else if initialize {
+                                                       // initial data after the index is built, e.g. a base loaded over
+                                                       // several epochs, is absorbed as insertions at its time.
+                                                       map.entry(time.time().clone())
+                                                           .or_insert(Vec::new())
+                                                           .extend(buffer2.drain(..).map(|x| (x, 1)));
+                                                       notificator.notify_at(time.retain());
+                                                   }
                                                });
 
                                                notificator.for_each(|time,_,_| {
+                                                   let mut changes = Vec::new();
                                                    // initialize if this is the first time
                                                    if let Some(mut sorter) = sorter.take() {
                                                        let mut sorted = Vec::new();
                                                        sorter.finish_into(&mut sorted);
                                                        let sum: usize = sorted.iter().map(|x| x.len()).sum();
                                                        println!("worker {}: index built with {} elements", worker_index, sum);
-                                                       index_1.borrow_mut().initialize(&mut sorted);
+                                                       changes.extend(sorted.iter().flat_map(|chunk| chunk.iter()).map(|x| (x.0.clone(), 1)));
+                                                       write_index(&index_1).initialize(&mut sorted);
+                                                   }
+                                                   if restored {
+                                                       restored = false;
+                                                       changes.extend(read_index(&index_1).snapshot().into_iter().map(|x| (x.0, 1)));
                                                    }
                                                    // push updates if updates exist
                                                    if let Some(mut list) = map.remove(time.time()) {
-                                                       index_1.borrow_mut().update(time.time().clone(), &mut list);
+                                                       changes.extend(list.iter().map(|x| ((x.0).0.clone(), x.1 as i64)));
+                                                       write_index(&index_1).update(time.time().clone(), &mut list);
                                                    }
+                                                   output.session(&time).give_iterator(net_counts(changes).into_iter());
                                                });
                                            }
-        ).probe();
+        );
 
-        IndexStream {
-            handle: handle,
+        let index = IndexStream {
+            handle: counts.probe(),
             index: index_2,
             hash: hash_3,
             is_forward: is_forward,
-        }
+        };
+        (index, counts)
     }
 
 }
 
 
+// the net change of each key of `changes`, omitting keys whose changes cancel.
+fn net_counts<K: Ord>(mut changes: Vec<(K, i64)>) -> Vec<(K, i64)> {
+    changes.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut net: Vec<(K, i64)> = Vec::new();
+    for (key, diff) in changes {
+        match net.last_mut() {
+            Some(last) if last.0 == key => { last.1 += diff; continue; },
+            _ => { },
+        }
+        net.push((key, diff));
+    }
+    net.retain(|x| x.1 != 0);
+    net
+}
+
 /// An `IndexStream` wrapper adding key selectors and time validators.
 ///
 /// The `IndexExtender` wraps an index so that different types `P` can gain access to the
@@ -160,6 +256,8 @@ pub struct IndexExtender<K, T, P, L, H>
     hash: Rc<H>,
     logic: Rc<L>,
     is_forward: bool,
+    // the values the extender admits, if not all of them.
+    accept: Option<Rc<Fn(&K)->bool>>,
     phantom: PhantomData<P>,
 }
 
@@ -193,7 +291,7 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
 
         let exch = Exchange::new(move |&(ref x,_,_,_)| (*hash)((*logic1)(x)));
 
-        prefixes.unary(exch, "Count", move |_,_| move |input, output| {
+        prefixes.unary_frontier(exch, "Count", move |_,_| move |input, output| {
 
             // The logic in this operator should only be applied to data inputs at `time` once we are
             // certain that the second input has also advanced to `time`. The shared index `clone` is
@@ -210,14 +308,15 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
                     .extend(buffer1.drain(..))
             });
 
-            // scan each stashed element and see if it is time to process it.
-            for (time, data) in blocked.iter_mut() {
-                // ok to process if no further updates less or equal to `time`.
-                if !handle.less_equal(time.time()) {
-                    // pop the data out of the list; we'll clean up the entry later.
-                    (*index).borrow_mut().count(data, &*logic2, &time.time(), ident);
-                    output.session(time).give_iterator(data.drain(..).filter(|x| x.1 > 0));
-                }
+            // process each stashed element once no further updates are less or equal to its time.
+            let deterministic = read_index(&index).is_deterministic();
+            for (time, data) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+                // discard the data if enumeration has run out of time.
+                if read_index(&index).breaker().discard(data.len()) { data.clear(); continue; }
+                if deterministic { sort_prefixes(data, |x| &x.0); }
+                // pop the data out of the list; we'll clean up the entry later.
+                write_index(&index).count(data, &*logic2, &time.time(), ident);
+                output.session(time).give_iterator(data.drain(..).filter(|x| x.1 > 0));
             }
 
             // discard any data we processed up above.
@@ -226,12 +325,40 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
     }
 
     fn propose(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        self.propose_into(stream)
+    }
+
+    fn intersect(&self, stream: Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+        self.intersect_into(stream, false)
+    }
+
+    fn propose_counted(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        self.propose_into(stream)
+    }
+
+    fn intersect_counted(&self, stream: Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)> {
+        self.intersect_into(stream, false)
+    }
+}
+
+impl<K, T, P, L, H> IndexExtender<K, T, P, L, H>
+    where
+        K: Ord+Hash+Clone+ExchangeData,
+        T: Timestamp+Ord+Clone,
+        P: ExchangeData+Debug+Indexable<K>,
+        L: Fn(&P)->K+'static,
+        H: Fn(K)->u64+'static,
+{
+    // proposes extensions, collected into lists of type `X`.
+    fn propose_into<G, W, X>(&self, stream: Stream<G, (P, W)>) -> Stream<G, (P, X, W)>
+        where G: Scope<Timestamp=T>, W: ExchangeData, X: Extensions<K>+Default+Data {
 
         let hash = self.hash.clone();
         let logic1 = self.logic.clone();
         let logic2 = self.logic.clone();
         let handle = self.handle.clone();
         let is_forward = self.is_forward;
+        let accept = self.accept.clone();
 
         let exch = Exchange::new(move |&(ref x,_)| (*hash)((*logic1)(x)));
 
@@ -239,53 +366,157 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
 
         let mut buffer1 = Vec::new();
 
-        let mut blocked = HashMap::new();//vec![];
+        // lists of prefixes at each time, with the choice of how to materialize their candidates once made.
+        let mut blocked: HashMap<_, (Option<Option<usize>>, Vec<Vec<(P, W)>>)> = HashMap::new();
 
-        stream.unary(exch, "Propose", move |_,_| move |input, output| {
+        stream.unary_frontier(exch, "Propose", move |_,_| move |input, output| {
 
             input.for_each(|time, data| {
                 data.swap(&mut buffer1);
                 blocked
                     .entry(time.retain())
-                    .or_insert(Vec::new())
+                    .or_insert((None, Vec::new()))
                     // .extend(data.drain(..).map(|(p,s)| (p,vec![],s)))
-                    .push(::std::mem::replace(&mut buffer1, Vec::new()))
+                    .1.push(::std::mem::replace(&mut buffer1, Vec::new()))
             });
 
 
-            // scan each stashed element and see if it is time to process it.
-            for (time, data) in blocked.iter_mut() {
+            // process each stashed element once no further updates are less or equal to its time.
+            let deterministic = read_index(&index).is_deterministic();
+            for (time, &mut (ref mut choice, ref mut data)) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
 
-                // ok to process if no further updates less or equal to `time`.
-                if !handle.less_equal(time.time()) {
+                // every list of the time has arrived, and is proposed from as one sorted list.
+                if deterministic && choice.is_none() {
+                    let mut list = data.drain(..).flat_map(|list| list.into_iter()).collect::<Vec<_>>();
+                    sort_prefixes(&mut list, |x| &x.0);
+                    data.push(list);
+                }
 
-                    let mut effort = 4096;
-                    while data.len() > 0 && effort > 0 {
-                        let mut list = data.pop().unwrap();
-                        effort = if list.len() > effort { 0 } else { effort - list.len() };
+                // choose how to materialize the candidates of `time` once, when its prefixes are first ready.
+                if choice.is_none() {
+                    let index = read_index(&index);
+                    let lists = &*data;
+                    *choice = Some(index.materializer().choose(index.breaker().is_armed(), || {
+                        lists.iter().flat_map(|list| list.iter()).map(|x| index.degree(&(*logic2)(&x.0))).sum()
+                    }));
+                }
 
-                        let mut data = list.drain(..).map(|(p,s)| (p,vec![],s)).collect::<Vec<_>>();
-                        if is_forward{
-                            (*index).borrow_mut().forward_propose(&mut data, &*logic2, &time.time());
-                        }
-                        else{
-                            (*index).borrow_mut().reverse_propose(&mut data, &*logic2, &time.time());
-                        }
-                        let mut session = output.session(&time);
-                        for x in data.drain(..) {
-                            if x.1.len() > 0 {
-                                session.give(x);
+                // the lists to propose from now: all of them, or those holding about a chunk of candidates.
+                let mut ready = Vec::new();
+                match choice.unwrap() {
+                    None => ready.extend(data.drain(..)),
+                    Some(chunk) => {
+                        let index = read_index(&index);
+                        let mut effort = chunk;
+                        while effort > 0 {
+                            let mut list = match data.pop() { Some(list) => list, None => break };
+                            let mut split = list.len();
+                            while split > 0 && effort > 0 {
+                                split -= 1;
+                                let degree = index.degree(&(*logic2)(&list[split].0));
+                                effort = effort.saturating_sub(::std::cmp::max(degree, 1));
                             }
+                            let tail = list.split_off(split);
+                            if list.len() > 0 { data.push(list); }
+                            ready.push(tail);
+                        }
+                    },
+                }
+
+                for mut list in ready {
+                    if read_index(&index).breaker().discard(list.len()) { continue; }
+
+                    let mut data = list.drain(..).map(|(p,s)| (p,X::default(),s)).collect::<Vec<_>>();
+                    match (is_forward, &accept) {
+                        (true, &None) => write_index(&index).forward_propose(&mut data, &*logic2, &time.time()),
+                        (false, &None) => write_index(&index).reverse_propose(&mut data, &*logic2, &time.time()),
+                        (true, &Some(ref accept)) => write_index(&index).forward_propose_where(&mut data, &*logic2, &time.time(), &|x: &K| (**accept)(x)),
+                        (false, &Some(ref accept)) => write_index(&index).reverse_propose_where(&mut data, &*logic2, &time.time(), &|x: &K| (**accept)(x)),
+                    }
+                    let mut session = output.session(&time);
+                    for x in data.drain(..) {
+                        if !x.1.is_empty() {
+                            session.give(x);
                         }
                     }
                 }
             }
 
-            blocked.retain(|_, data| data.len() > 0);
+            blocked.retain(|_, entry| entry.1.len() > 0);
         })
     }
 
-    fn intersect(&self, stream: Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)> {
+    /// Restricts proposed extensions to those this relation would not propose.
+    ///
+    /// The counterpart of `intersect` for pattern edges that must be absent; see
+    /// `Index::anti_intersect`. Lists left empty are passed on, as `intersect` passes them.
+    pub fn exclude<G, W>(&self, stream: Stream<G, (P, Vec<K>, W)>) -> Stream<G, (P, Vec<K>, W)>
+        where G: Scope<Timestamp=T>, W: ExchangeData {
+        self.intersect_into(stream, true)
+    }
+
+    /// Proposes extensions from this relation with a hash join, rather than a sort-merge join.
+    ///
+    /// The prefixes of each time are built into a hash table keyed by the index key they read, and
+    /// once the index has absorbed the time, the relation is probed once for each distinct key,
+    /// and its values joined with every prefix under the key; see `Index::probe_where`. Prefixes
+    /// are never sorted, which suits binary joins in which few prefixes read a large relation, or
+    /// many prefixes read few keys. Values are validated against the time as `propose` validates
+    /// them, so the two propose the same extensions to each prefix.
+    pub fn hash_join<G, W>(&self, stream: Stream<G, (P, W)>) -> Stream<G, (P, Vec<K>, W)>
+        where G: Scope<Timestamp=T>, W: ExchangeData {
+
+        let hash = self.hash.clone();
+        let logic1 = self.logic.clone();
+        let logic2 = self.logic.clone();
+        let is_forward = self.is_forward;
+        let index = self.index.clone();
+        let handle = self.handle.clone();
+        let accept = self.accept.clone();
+
+        let mut buffer = Vec::new();
+        // the prefixes of each time, grouped by the key they read.
+        let mut blocked: HashMap<_, HashMap<K, Vec<(P, Vec<K>, W)>>> = HashMap::new();
+        let exch = Exchange::new(move |&(ref x,_)| (*hash)((*logic1)(x)));
+
+        stream.unary_frontier(exch, "HashJoin", move |_,_| move |input, output| {
+
+            // build: add each prefix to the table of its time, under its key.
+            input.for_each(|time, data| {
+                data.swap(&mut buffer);
+                let table = blocked.entry(time.retain()).or_insert_with(HashMap::new);
+                for (prefix, weight) in buffer.drain(..) {
+                    table.entry((*logic2)(&prefix)).or_insert_with(Vec::new).push((prefix, Vec::new(), weight));
+                }
+            });
+
+            // probe: once no further updates are less or equal to a time, join each key's prefixes.
+            let deterministic = read_index(&index).is_deterministic();
+            for (time, table) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+                let mut groups = table.drain().collect::<Vec<_>>();
+                if deterministic {
+                    groups.sort_by(|x, y| x.0.cmp(&y.0));
+                    for group in groups.iter_mut() { sort_prefixes(&mut group.1, |x| &x.0); }
+                }
+                let mut relation = write_index(&index);
+                let mut session = output.session(&time);
+                for (key, mut group) in groups {
+                    if relation.breaker().discard(group.len()) { continue; }
+                    match accept {
+                        None => relation.probe_where(&key, &mut group, is_forward, &time.time(), &|_| true),
+                        Some(ref accept) => relation.probe_where(&key, &mut group, is_forward, &time.time(), &|x: &K| (**accept)(x)),
+                    }
+                    session.give_iterator(group.drain(..).filter(|x| !x.1.is_empty()));
+                }
+            }
+
+            blocked.retain(|_, table| !table.is_empty());
+        })
+    }
+
+    // restricts lists of proposed extensions of type `X`, to those absent from the index if `anti` is set.
+    fn intersect_into<G, W, X>(&self, stream: Stream<G, (P, X, W)>, anti: bool) -> Stream<G, (P, X, W)>
+        where G: Scope<Timestamp=T>, W: ExchangeData, X: Extensions<K>+ExchangeData {
 
         let hash = self.hash.clone();
         let logic1 = self.logic.clone();
@@ -293,12 +524,13 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
         let is_forward = self.is_forward;
         let index = self.index.clone();
         let handle = self.handle.clone();
+        let accept = if anti { None } else { self.accept.clone() };
 
         let mut buffer = Vec::new();
         let mut blocked = HashMap::new();
         let exch = Exchange::new(move |&(ref x,_,_)| (*hash)((*logic1)(x)));
 
-        stream.unary(exch, "Intersect", move |_,_| move |input, output| {
+        stream.unary_frontier(exch, "Intersect", move |_,_| move |input, output| {
 
             input.for_each(|time, data| {
                 data.swap(&mut buffer);
@@ -307,13 +539,20 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
                     .extend(buffer.drain(..))
             });
 
-            for (time, data) in blocked.iter_mut() {
-
-                // ok to process if no further updates less or equal to `time`.
-                if !handle.less_equal(time.time()) {
-                    (*index).borrow_mut().intersect(data, &*logic2, is_forward, &time.time());
-                    output.session(&time).give_iterator(data.drain(..));
+            // process each stashed element once no further updates are less or equal to its time.
+            let deterministic = read_index(&index).is_deterministic();
+            for (time, data) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+                if read_index(&index).breaker().discard(data.len()) { data.clear(); continue; }
+                if deterministic { sort_prefixes(data, |x| &x.0); }
+                if anti { write_index(&index).anti_intersect(data, &*logic2, is_forward, &time.time()); }
+                else { write_index(&index).intersect(data, &*logic2, is_forward, &time.time()); }
+                if let Some(ref accept) = accept {
+                    for entry in data.iter_mut() {
+                        let counts = entry.1.with_values(|values| values.iter().map(|x| if (**accept)(x) { 1 } else { 0 }).collect::<Vec<_>>());
+                        entry.1.retain_positive(&counts);
+                    }
                 }
+                output.session(&time).give_iterator(data.drain(..));
             }
 
             blocked.retain(|_, data| data.len() > 0);
@@ -321,7 +560,6 @@ impl<K, G, P, L, H, W> StreamPrefixExtender<G, W> for Rc<IndexExtender<K, G::Tim
     }
 }
 
-
 mod merge_sorter {
 
     use std::slice::{from_raw_parts};
@@ -344,17 +582,17 @@ mod merge_sorter {
         #[inline(always)]
         pub fn peek(&self) -> &T {
             debug_assert!(self.head < self.tail);
-            unsafe { self.list.get_unchecked(self.head) }
+            unsafe { &*self.list.as_ptr().offset(self.head as isize) }
         }
         #[inline(always)]
         pub fn _peek_tail(&self) -> &T {
             debug_assert!(self.head < self.tail);
-            unsafe { self.list.get_unchecked(self.tail-1) }
+            unsafe { &*self.list.as_ptr().offset((self.tail as isize) - 1) }
         }
         #[inline(always)]
         pub fn _slice(&self) -> &[T] {
             debug_assert!(self.head < self.tail);
-            unsafe { from_raw_parts(self.list.get_unchecked(self.head), self.tail - self.head) }
+            unsafe { from_raw_parts(self.list.as_ptr().offset(self.head as isize), self.tail - self.head) }
         }
         #[inline(always)]
         pub fn from(mut list: Vec<T>) -> Self {
@@ -382,7 +620,7 @@ mod merge_sorter {
     unsafe fn push_unchecked<T>(vec: &mut Vec<T>, element: T) {
         debug_assert!(vec.len() < vec.capacity());
         let len = vec.len();
-        ::std::ptr::write(vec.get_unchecked_mut(len), element);
+        ::std::ptr::write(vec.as_mut_ptr().offset(len as isize), element);
         vec.set_len(len + 1);
     }
 
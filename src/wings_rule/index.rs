@@ -1,13 +1,40 @@
-use super::advance;
+use search::advance_from;
 
+use std::fmt;
 use std::hash::Hash;
+use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
 
 use self::edge_list_neu::EdgeList;
+pub use self::edge_list_neu::{MergePolicy, DefaultMergePolicy, MergeStats};
 use self::compact::CompactIndex;
 use self::unsorted::Unsorted;
+use self::bloom::Bloom;
+use super::{Breaker, Materializer};
+use super::kernel::{self, IntersectKernel, KernelStats, Table};
 use ::Indexable;
 
+/// Borrows an index shared by the operators of a dataflow, in order to read it.
+///
+/// Indices are owned by the worker whose dataflow maintains them: the operators of the dataflow
+/// share each index through an `Rc<RefCell<Index>>`, which can not be sent to other threads, and
+/// they borrow it only while the worker steps. Drivers may read an index between steps, but must
+/// not hold a borrow across a step. This function panics with an explanation if the index is being
+/// modified, rather than with `RefCell`'s generic error.
+pub fn read_index<K: Ord+Hash, T>(index: &RefCell<Index<K, T>>) -> Ref<Index<K, T>> {
+    index.try_borrow().unwrap_or_else(|_| panic!("index read while it is being modified; indices may only be read between steps of their worker"))
+}
+
+/// Borrows an index shared by the operators of a dataflow, in order to modify it.
+///
+/// As `read_index`, this panics with an explanation if the index is borrowed elsewhere, which
+/// happens if a driver holds a borrow of an index (e.g. from `IndexStream::index`) across a step.
+pub fn write_index<K: Ord+Hash, T>(index: &RefCell<Index<K, T>>) -> RefMut<Index<K, T>> {
+    index.try_borrow_mut().unwrap_or_else(|_| panic!("index modified while it is borrowed elsewhere; borrows of an index must not be held across steps of its worker"))
+}
+
 /// A multiversion multimap from `Key` to `Val`.
 ///
 /// An `Index` represents a multiversion `(Key, Val)` relation keyed on the first field.
@@ -23,11 +50,130 @@ pub struct Index<Key: Ord+Hash, T> {
     /// A sorted list of un-committed updates.
     // diffs: Vec<(Key, u32, T, i32)>,
     diffs: Unsorted<Key, Key, T>,
+    /// Optionally, a Bloom filter over keys with any updates, and its bits per key.
+    filter: Option<(Bloom, usize)>,
+    /// A deadline after which operators reading the index discard their prefixes.
+    breaker: Breaker,
+    /// Decides how operators proposing from the index materialize their candidates.
+    materializer: Materializer,
+    /// Decides when the runs of `edges` are merged.
+    policy: Box<MergePolicy>,
+    /// Counters of the merges performed in `edges`.
+    merge_stats: MergeStats,
+    /// Optionally, a compact index being rebuilt in the background, and the updates committed since.
+    rebuilding: Option<(Receiver<CompactIndex<Key,Key>>, Vec<((Key, Key), i32)>)>,
+    /// Counts proposals against compacted values in `intersect` and `intersect_only`.
+    kernel: IntersectKernel,
+    /// Counters of the kernels used.
+    kernel_stats: KernelStats,
+    /// How `update` absorbs updates.
+    maintenance: Maintenance,
+    /// Optionally, counters of the work reads have done since they were last taken.
+    read_stats: Option<ReadStats>,
+    /// Whether operators reading the index process their inputs in a reproducible order.
+    deterministic: bool,
+}
+
+/// Counters of the work reads of an index have done, to guide tuning of its representation.
+///
+/// Each key a read visits is searched for among the compacted keys, has the sorted runs of its
+/// committed updates scanned, and has its uncommitted updates scanned. Many search steps suggest
+/// rebuilding the compacted index less often matters little, while many runs or uncommitted
+/// updates per key suggest merging more eagerly; see `MergePolicy` and `Index::merge_to`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadStats {
+    /// Counting, proposing, and intersecting operations.
+    pub operations: usize,
+    /// Keys visited by the operations.
+    pub keys: usize,
+    /// Compacted keys compared while searching for visited keys.
+    pub search_steps: usize,
+    /// Sorted runs of committed updates scanned.
+    pub runs_scanned: usize,
+    /// Uncommitted updates scanned.
+    pub diffs_scanned: usize,
+}
+
+impl ReadStats {
+    /// Adds the counters of `other`.
+    pub fn add(&mut self, other: &ReadStats) {
+        self.operations += other.operations;
+        self.keys += other.keys;
+        self.search_steps += other.search_steps;
+        self.runs_scanned += other.runs_scanned;
+        self.diffs_scanned += other.diffs_scanned;
+    }
+
+    // counts a visit to a key whose reading scanned `runs` runs and `diffs` uncommitted updates.
+    fn visit(&mut self, runs: usize, diffs: usize) {
+        self.keys += 1;
+        self.runs_scanned += runs;
+        self.diffs_scanned += diffs;
+    }
+}
+
+// the compacted values of `key`, counting the steps of the search in `reads` if it is set.
+fn compact_values<'a, K: Ord>(compact: &'a CompactIndex<K, K>, key: &K, cursor: &mut usize, reads: &mut Option<ReadStats>) -> &'a [K] {
+    match *reads {
+        Some(ref mut reads) => compact.values_counted(key, cursor, &mut reads.search_steps),
+        None => compact.values_from(key, cursor),
+    }
+}
+
+/// How an index absorbs the updates introduced by `update`.
+///
+/// Buffered updates are held with their times until they are merged, so that reads can tell
+/// updates at a time from those before it. Updating many values of few keys then sorts every
+/// update twice: once among the buffered updates, and again as `merge_to` moves them to their keys.
+/// Direct maintenance instead groups each batch by key and appends it to the committed updates of
+/// each key at once, as if it were merged immediately.
+///
+/// Direct maintenance is only correct while nothing reads the index at the times of the updates it
+/// absorbs, for example while loading a graph before any matching. Batches arriving while earlier
+/// updates are still buffered are buffered as well, so that updates are committed in order of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Maintenance {
+    /// Updates are buffered with their times until merged.
+    Buffered,
+    /// Updates are committed as they arrive, grouped by key.
+    Direct,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self { Maintenance::Buffered }
+}
+
+/// What `Index::initialize_owned` does with pairs whose keys another worker owns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignKeys {
+    /// Drops them, as when every worker is given the whole initial collection.
+    Drop,
+    /// Rejects the initial collection, loading none of it.
+    Reject,
+}
+
+/// A key given to a worker that does not own it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey<K> {
+    /// The key.
+    pub key: K,
+    /// The worker that owns the key.
+    pub owner: usize,
+    /// The worker the key was given to.
+    pub worker: usize,
+}
+
+impl<K: fmt::Debug> fmt::Display for ForeignKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key {:?} belongs to worker {}, not worker {}", self.key, self.owner, self.worker)
+    }
 }
 
 mod compact {
 
-    use super::advance;
+    use std::cell::Cell;
+
+    use search::advance_from;
 
     pub struct CompactIndex<K,V> {
         keys: Vec<(K, usize)>,
@@ -63,13 +209,36 @@ mod compact {
             }
         }
 
+        /// The number of values, over all keys.
+        pub fn len(&self) -> usize { self.vals.len() }
+
+        /// The bytes allocated for keys and values.
+        pub fn heap_size(&self) -> usize {
+            self.keys.capacity() * ::std::mem::size_of::<(K, usize)>() + self.vals.capacity() * ::std::mem::size_of::<V>()
+        }
+
+        /// The key-value pairs, in order.
+        pub fn pairs<'a>(&'a self) -> impl Iterator<Item=(&'a K, &'a V)>+'a {
+            let mut lower = 0;
+            self.keys.iter().flat_map(move |&(ref key, upper)| {
+                let vals = &self.vals[lower .. upper];
+                lower = upper;
+                vals.iter().map(move |val| (key, val))
+            })
+        }
+
+        /// The distinct keys, in order.
+        pub fn keys<'a>(&'a self) -> impl Iterator<Item=&'a K>+'a {
+            self.keys.iter().map(|x| &x.0)
+        }
+
         /// Reveal the slice for `key` starting from (and updating) `key_cursor`.
         #[inline(always)]
         pub fn values_from<'a>(&'a self, key: &K, key_cursor: &mut usize) -> &'a [V] {
 
             if *key_cursor < self.keys.len() {
 
-                *key_cursor += advance(&self.keys[*key_cursor..], |x| &x.0 < key);
+                *key_cursor = advance_from(&self.keys, *key_cursor, |x| &x.0 < key);
 
                 if self.keys.get(*key_cursor).map(|x| &x.0) == Some(key) {
                     let lower = if *key_cursor == 0 { 0 } else { self.keys[*key_cursor-1].1 };
@@ -84,12 +253,62 @@ mod compact {
             }
             else { &[] }
         }
+
+        /// As `values_from`, adding the number of keys compared while searching to `steps`.
+        pub fn values_counted<'a>(&'a self, key: &K, key_cursor: &mut usize, steps: &mut usize) -> &'a [V] {
+            if *key_cursor < self.keys.len() {
+                let compared = Cell::new(0);
+                *key_cursor = advance_from(&self.keys, *key_cursor, |x| { compared.set(compared.get() + 1); &x.0 < key });
+                *steps += compared.get();
+            }
+            self.values_from(key, key_cursor)
+        }
     }
 }
 
 mod edge_list_neu {
 
-    use super::advance;
+    use search::advance_from;
+
+    /// Decides when the sorted runs of an `EdgeList` are merged.
+    ///
+    /// Merging runs costs writes, as the merged updates are sorted and consolidated again, while
+    /// leaving them separate costs reads, as each read visits every run. The default policy keeps
+    /// run lengths roughly geometric, so that both are logarithmic in the number of updates.
+    pub trait MergePolicy {
+        /// True if a newly pushed run of `pushed` updates should be merged with the previous run
+        /// of `previous` updates, rather than kept as a run of its own.
+        fn merge_pushed(&self, previous: usize, pushed: usize) -> bool { pushed >= previous / 2 }
+        /// True if, while merging, a run of `newer` updates should also absorb the run of `older`
+        /// updates before it.
+        fn merge_runs(&self, older: usize, newer: usize) -> bool { older < 2 * newer }
+        /// True if, while merging, the first run of `first` updates should also be absorbed into
+        /// the `rest`.
+        fn merge_first(&self, first: usize, rest: usize) -> bool { first < (first + rest) / 2 }
+        /// True if reads about to expend `effort` justify consolidating all `len` updates.
+        fn consolidate(&self, effort: usize, len: usize) -> bool { effort > len }
+    }
+
+    /// The default merge policy.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct DefaultMergePolicy;
+
+    impl MergePolicy for DefaultMergePolicy { }
+
+    /// Counters describing the merges an index's edge lists have performed.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct MergeStats {
+        /// Runs of updates pushed.
+        pub pushed: usize,
+        /// Runs kept separate when pushed.
+        pub kept: usize,
+        /// Merges performed when runs were pushed.
+        pub merges: usize,
+        /// Consolidations of all runs, for reads.
+        pub consolidations: usize,
+        /// Updates sorted by merges and consolidations, a measure of write amplification.
+        pub merged_updates: usize,
+    }
 
     /// A LSM-style list of updates.
     ///
@@ -110,7 +329,7 @@ mod edge_list_neu {
         bounds: Vec<usize>,
         values: Vec<(V, i32)>,
         effort: u32,
-        count: i32,     // accumulated diffs; could be negative
+        count: i64,     // accumulated diffs; could be negative
     }
 
     impl<V: Ord> EdgeList<V> {
@@ -127,7 +346,12 @@ mod edge_list_neu {
         }
 
         #[inline(always)]
-        pub fn count(&self) -> i32 { self.count }
+        pub fn count(&self) -> i64 { self.count }
+
+        /// The bytes allocated for runs and their bounds.
+        pub fn heap_size(&self) -> usize {
+            self.values.capacity() * ::std::mem::size_of::<(V, i32)>() + self.bounds.capacity() * ::std::mem::size_of::<usize>()
+        }
 
         // The next methods are, annoyingly, in support of pushing updates into the LSM.
         // Because insertion is a bit interactive, with tests on timestamps and setting
@@ -142,7 +366,7 @@ mod edge_list_neu {
 
         #[inline(always)]
         pub fn push(&mut self, update: (V, i32)) {
-            self.count += update.1;
+            self.count += update.1 as i64;
             self.values.push(update);
         }
 
@@ -150,18 +374,21 @@ mod edge_list_neu {
         ///
         /// This method is called after a series of `push` calls, and is a moment
         /// for reflection on whether the most recent sorted run of updates is
-        /// large enough that we should merge it with prior runs.
+        /// large enough that we should merge it with prior runs, as `policy` decides.
         #[inline(always)]
-        pub fn seal_from(&mut self, position: usize) {
+        pub fn seal_from(&mut self, position: usize, policy: &MergePolicy, stats: &mut MergeStats) {
 
             // only if values have been pushed.
             if self.values.len() > position {
 
+                stats.pushed += 1;
+
                 // we will push `position` only if there are already values, and
-                // the new run is shorter than half the second most recent run.
+                // the policy prefers to keep the new run separate.
                 let prev_run = position - self.bounds.last().map(|&x| x).unwrap_or(0);
-                if self.values.len() - position < prev_run / 2 {
+                if prev_run > 0 && !policy.merge_pushed(prev_run, self.values.len() - position) {
                     self.bounds.push(position);
+                    stats.kept += 1;
                 }
                 else {
 
@@ -170,25 +397,52 @@ mod edge_list_neu {
                     // from `self.bounds` as long as they separate regions that
                     // should be merged.
 
-                    // while the last region is greater than half the second-to-last
-                    // region (a sorted run), remove the boundary between them.
-                    while self.bounds.len() >= 2 && (self.bounds[self.bounds.len()-2] - self.bounds[self.bounds.len()-1] < 2 * (self.values.len() - self.bounds[self.bounds.len()-1])) {
+                    // while the policy would merge the last region with the run before it,
+                    // remove the boundary between them.
+                    while self.bounds.len() >= 2 {
+                        let older = self.bounds[self.bounds.len()-1] - self.bounds[self.bounds.len()-2];
+                        let newer = self.values.len() - self.bounds[self.bounds.len()-1];
+                        if !policy.merge_runs(older, newer) { break; }
                         self.bounds.pop();
                     }
 
                     // if the final boundary should be removed, do that too.
-                    if self.bounds.len() == 1 && self.bounds[0] < self.values.len() / 2 {
+                    if self.bounds.len() == 1 && policy.merge_first(self.bounds[0], self.values.len() - self.bounds[0]) {
                         self.bounds = Vec::new();
                     }
 
+                    stats.merges += 1;
+                    stats.merged_updates += self.values.len() - self.bounds.last().map(|&x| x).unwrap_or(0);
                     self.consolidate_tail();
                 }
             }
         }
 
+        /// The lengths of the sorted runs, oldest first.
+        pub fn run_lengths(&self) -> Vec<usize> {
+            let mut lengths = Vec::with_capacity(self.bounds.len() + 1);
+            let mut lower = 0;
+            for bound in self.bounds.iter().cloned().chain(::std::iter::once(self.values.len())) {
+                lengths.push(bound - lower);
+                lower = bound;
+            }
+            lengths
+        }
+
+        /// The number of sorted runs, each of which reads scan separately.
+        pub fn runs(&self) -> usize {
+            if self.values.is_empty() { 0 } else { self.bounds.len() + 1 }
+        }
+
+        /// All pushed updates, in sorted runs that are not necessarily consolidated.
         #[inline(always)]
-        pub fn proposals(&mut self) -> &[(V, i32)] {
+        pub fn updates(&self) -> &[(V, i32)] { &self.values[..] }
+
+        #[inline(always)]
+        pub fn proposals(&mut self, stats: &mut MergeStats) -> &[(V, i32)] {
             if self.bounds.len() > 0 {
+                stats.consolidations += 1;
+                stats.merged_updates += self.values.len();
                 self.bounds = Vec::new();
                 self.consolidate_tail();
             }
@@ -225,10 +479,12 @@ mod edge_list_neu {
         /// that is about to be done. If a great deal of work will be done, it may make sense to
         /// consolidate the edge list to simplify that work.
         #[inline(always)]
-        pub fn expend(&mut self, effort: u32) {
+        pub fn expend(&mut self, effort: u32, policy: &MergePolicy, stats: &mut MergeStats) {
             if self.bounds.len() > 0 {
                 self.effort += effort;
-                if (self.effort as usize) > self.values.len() {
+                if policy.consolidate(self.effort as usize, self.values.len()) {
+                    stats.consolidations += 1;
+                    stats.merged_updates += self.values.len();
                     self.bounds = Vec::new();
                     self.consolidate_tail();
                 }
@@ -271,9 +527,7 @@ mod edge_list_neu {
             while s_cursor < source.len() && u_cursor < updates.len() {
                 match source[s_cursor].cmp(&updates[u_cursor].0) {
                     Ordering::Less => {
-                        let step = 1 + advance(&source[(s_cursor+1)..], |x| x < &updates[u_cursor].0);
-                        debug_assert!(step > 0);
-                        s_cursor += step;
+                        s_cursor = advance_from(source, s_cursor + 1, |x| x < &updates[u_cursor].0);
                     },
                     Ordering::Equal => {
                         counts[s_cursor] += updates[u_cursor].1;
@@ -281,9 +535,7 @@ mod edge_list_neu {
                         //u_cursor += 1;
                     },
                     Ordering::Greater => {
-                        let step = 1 + advance(&updates[(u_cursor+1)..], |x| x.0 < source[s_cursor]);
-                        debug_assert!(step > 0);
-                        u_cursor += step;
+                        u_cursor = advance_from(updates, u_cursor + 1, |x| x.0 < source[s_cursor]);
                     },
                 }
             }
@@ -291,10 +543,67 @@ mod edge_list_neu {
     }
 }
 
+mod bloom {
+
+    use std::hash::{Hash, Hasher};
+    use std::collections::hash_map::DefaultHasher;
+
+    /// A Bloom filter over keys.
+    ///
+    /// Keys are hashed once, and the two halves of the hash are combined to produce each of
+    /// the probed bit positions. A filter never reports an inserted key as absent.
+    pub struct Bloom {
+        bits: Vec<u64>,
+        hashes: u32,
+    }
+
+    impl Bloom {
+
+        /// Allocates a filter for `keys` keys at `bits_per_key` bits each.
+        pub fn new(keys: usize, bits_per_key: usize) -> Self {
+            let words = ::std::cmp::max(1, (keys * bits_per_key + 63) / 64);
+            // ln(2) * bits per key hashes minimizes false positives.
+            let hashes = ::std::cmp::max(1, (bits_per_key as f64 * 0.69) as u32);
+            Bloom { bits: vec![0; words], hashes }
+        }
+
+        #[inline(always)]
+        fn hash<K: Hash>(key: &K) -> (u64, u64) {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            let hash = hasher.finish();
+            (hash & 0xFFFFFFFF, (hash >> 32) | 1)
+        }
+
+        pub fn insert<K: Hash>(&mut self, key: &K) {
+            let (h1, h2) = Bloom::hash(key);
+            let bits = (self.bits.len() * 64) as u64;
+            for i in 0 .. self.hashes as u64 {
+                let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bits;
+                self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+
+        /// True if `key` may have been inserted; false if it certainly was not.
+        #[inline(always)]
+        pub fn contains<K: Hash>(&self, key: &K) -> bool {
+            let (h1, h2) = Bloom::hash(key);
+            let bits = (self.bits.len() * 64) as u64;
+            (0 .. self.hashes as u64).all(|i| {
+                let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bits;
+                self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+            })
+        }
+    }
+}
+
 mod unsorted {
 
-    use super::advance;
+    use std::cmp::Ordering;
+
+    use search::advance_from;
 
+    // updates sorted by key, value, and time, with at most one non-zero update of each.
     pub struct Unsorted<K, V, T> {
         pub updates: Vec<(K, V, T, i32)>,
         pub min_time: Option<T>,
@@ -305,22 +614,135 @@ mod unsorted {
         pub fn new() -> Self { Unsorted { updates: Vec::new(), min_time: None } }
 
         pub fn values_from<'a>(&'a self, key: &K, key_cursor: &mut usize) -> &'a [(K, V, T, i32)] {
-            *key_cursor += advance(&self.updates[*key_cursor ..], |x| &x.0 < key);
-            let step = advance(&self.updates[*key_cursor ..], |x| &x.0 <= key);
-            let result = &self.updates[*key_cursor..][..step];
-            *key_cursor += step;
-            result
+            let lower = advance_from(&self.updates, *key_cursor, |x| &x.0 < key);
+            *key_cursor = advance_from(&self.updates, lower, |x| &x.0 <= key);
+            &self.updates[lower .. *key_cursor]
         }
 
+        // adds updates at `time`, summing the diffs of updates to the same key and value at the
+        // same time, among themselves and with those held, and dropping those that cancel.
         pub fn extend<I: Iterator<Item=((K, V), i32)>>(&mut self, time: T, iterator: I) {
-            self.updates.extend(iterator.map(|((k,v),d)| (k, v, time.clone(), d)));
-            self.updates.sort_by(|x,y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+            // sort only the new updates, and merge them with the sorted updates already held;
+            // updates are often introduced in many small batches, or already sorted by key.
+            let mut added = iterator.map(|((k,v),d)| (k, v, time.clone(), d)).collect::<Vec<_>>();
+            added.sort_by(|x,y| (&x.0, &x.1).cmp(&(&y.0, &y.1)));
+            consolidate(&mut added);
+            if self.updates.is_empty() {
+                self.updates = added;
+            }
+            else if !added.is_empty() {
+                let held = ::std::mem::replace(&mut self.updates, Vec::new());
+                self.updates.reserve(held.len() + added.len());
+                let mut held = held.into_iter().peekable();
+                let mut added = added.into_iter().peekable();
+                loop {
+                    let order = match (held.peek(), added.peek()) {
+                        (Some(x), Some(y)) => (&x.0, &x.1, &x.2).cmp(&(&y.0, &y.1, &y.2)),
+                        (Some(_), None) => Ordering::Less,
+                        (None, Some(_)) => Ordering::Greater,
+                        (None, None) => break,
+                    };
+                    match order {
+                        Ordering::Less => self.updates.push(held.next().unwrap()),
+                        Ordering::Greater => self.updates.push(added.next().unwrap()),
+                        Ordering::Equal => {
+                            let mut update = held.next().unwrap();
+                            update.3 += added.next().unwrap().3;
+                            if update.3 != 0 { self.updates.push(update); }
+                        },
+                    }
+                }
+            }
 
             if self.min_time == None || self.min_time.as_ref().unwrap() > &time {
                 self.min_time = Some(time);
             }
         }
     }
+
+    // sums the diffs of adjacent updates to the same key and value at the same time, and drops
+    // those that cancel.
+    fn consolidate<K: Eq, V: Eq, T: Eq>(updates: &mut Vec<(K, V, T, i32)>) {
+        let mut length = 0;
+        for index in 0 .. updates.len() {
+            if length > 0 && (&updates[length - 1].0, &updates[length - 1].1, &updates[length - 1].2) == (&updates[index].0, &updates[index].1, &updates[index].2) {
+                updates[length - 1].3 += updates[index].3;
+            }
+            else {
+                if length > 0 && updates[length - 1].3 == 0 { length -= 1; }
+                updates.swap(length, index);
+                length += 1;
+            }
+        }
+        if length > 0 && updates[length - 1].3 == 0 { length -= 1; }
+        updates.truncate(length);
+    }
+}
+
+/// A list of extensions proposed for a prefix.
+///
+/// Extensions are proposed in sorted order, each with its multiplicity. A `Vec<V>` holds each
+/// extension once per unit of multiplicity, whereas a `Vec<(V, i32)>` holds each extension once,
+/// with its multiplicity.
+pub trait Extensions<V> {
+    /// Appends `value`, proposed with multiplicity `count`.
+    fn propose(&mut self, value: &V, count: i32);
+    /// Applies `logic` to the proposed values, in order and with repetitions.
+    fn with_values<R, F: FnOnce(&[V])->R>(&self, logic: F) -> R;
+    /// Retains the extensions whose counts, in the order of `with_values`, are strictly positive.
+    fn retain_positive(&mut self, counts: &[i32]);
+    /// True if no extensions are proposed.
+    fn is_empty(&self) -> bool;
+    /// Reserves room for `distinct` more extensions, proposed with multiplicities summing to `total`.
+    fn reserve_proposals(&mut self, _distinct: usize, _total: usize) { }
+}
+
+impl<V: Clone> Extensions<V> for Vec<V> {
+    #[inline(always)]
+    fn propose(&mut self, value: &V, count: i32) {
+        for _ in 0 .. count { self.push(value.clone()); }
+    }
+    #[inline(always)]
+    fn reserve_proposals(&mut self, _distinct: usize, total: usize) { self.reserve(total); }
+    #[inline(always)]
+    fn with_values<R, F: FnOnce(&[V])->R>(&self, logic: F) -> R { logic(&self[..]) }
+    fn retain_positive(&mut self, counts: &[i32]) {
+        let mut cursor = 0;
+        for i in 0 .. counts.len() {
+            if counts[i] > 0 {
+                self.swap(cursor, i);
+                cursor += 1;
+            }
+        }
+        self.truncate(cursor);
+    }
+    #[inline(always)]
+    fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+impl<V: Clone> Extensions<V> for Vec<(V, i32)> {
+    #[inline(always)]
+    fn propose(&mut self, value: &V, count: i32) {
+        if count > 0 { self.push((value.clone(), count)); }
+    }
+    #[inline(always)]
+    fn reserve_proposals(&mut self, distinct: usize, _total: usize) { self.reserve(distinct); }
+    fn with_values<R, F: FnOnce(&[V])->R>(&self, logic: F) -> R {
+        let values = self.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+        logic(&values[..])
+    }
+    fn retain_positive(&mut self, counts: &[i32]) {
+        let mut cursor = 0;
+        for i in 0 .. counts.len() {
+            if counts[i] > 0 {
+                self.swap(cursor, i);
+                cursor += 1;
+            }
+        }
+        self.truncate(cursor);
+    }
+    #[inline(always)]
+    fn is_empty(&self) -> bool { self.len() == 0 }
 }
 
 impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
@@ -331,6 +753,140 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             compact: CompactIndex::new(),
             edges: HashMap::new(),
             diffs: Unsorted::new(),
+            filter: None,
+            breaker: Breaker::new(),
+            materializer: Materializer::new(),
+            policy: Box::new(DefaultMergePolicy),
+            merge_stats: MergeStats::default(),
+            rebuilding: None,
+            kernel: IntersectKernel::Adaptive,
+            kernel_stats: KernelStats::default(),
+            maintenance: Maintenance::Buffered,
+            read_stats: None,
+            deterministic: false,
+        }
+    }
+
+    /// Sets the breaker consulted by operators reading the index; see `Breaker`.
+    pub fn set_breaker(&mut self, breaker: Breaker) {
+        self.breaker = breaker;
+    }
+
+    /// The breaker consulted by operators reading the index.
+    pub fn breaker(&self) -> &Breaker {
+        &self.breaker
+    }
+
+    /// Sets the materializer consulted by operators proposing from the index; see `Materializer`.
+    pub fn set_materializer(&mut self, materializer: Materializer) {
+        self.materializer = materializer;
+    }
+
+    /// The materializer consulted by operators proposing from the index.
+    pub fn materializer(&self) -> &Materializer {
+        &self.materializer
+    }
+
+    /// Sets the policy deciding when committed updates to each key are merged; see `MergePolicy`.
+    pub fn set_merge_policy<P: MergePolicy+'static>(&mut self, policy: P) {
+        self.policy = Box::new(policy);
+    }
+
+    /// Counters of the merges performed since the index was created.
+    pub fn merge_stats(&self) -> MergeStats {
+        self.merge_stats
+    }
+
+    /// Sets the kernel counting proposals against compacted values when intersecting; see `IntersectKernel`.
+    pub fn set_intersect_kernel(&mut self, kernel: IntersectKernel) {
+        self.kernel = kernel;
+    }
+
+    /// Counters of the kernels used by intersections since the index was created.
+    pub fn kernel_stats(&self) -> KernelStats {
+        self.kernel_stats
+    }
+
+    /// Sets how `update` absorbs updates; see `Maintenance`.
+    pub fn set_maintenance(&mut self, maintenance: Maintenance) {
+        self.maintenance = maintenance;
+    }
+
+    /// How `update` absorbs updates.
+    pub fn maintenance(&self) -> Maintenance {
+        self.maintenance
+    }
+
+    /// Starts or stops counting the work reads do; see `ReadStats`.
+    pub fn set_read_stats(&mut self, enabled: bool) {
+        if !enabled { self.read_stats = None; }
+        else if self.read_stats.is_none() { self.read_stats = Some(ReadStats::default()); }
+    }
+
+    /// The work reads have done since the counters were last taken, resetting them, if counted.
+    pub fn take_read_stats(&mut self) -> Option<ReadStats> {
+        self.read_stats.as_mut().map(|reads| ::std::mem::replace(reads, ReadStats::default()))
+    }
+
+    /// Sets whether operators reading the index process their inputs in a reproducible order.
+    ///
+    /// Operators otherwise extend prefixes as they arrive, in an order that depends on how the
+    /// workers sending them were scheduled. Processing deterministically, an operator holds the
+    /// prefixes of a time until all of them have arrived, sorts them by their vertices, and
+    /// processes its times in order, so that two runs over the same input produce their output in
+    /// the same order. This delays every stage until the previous one completes, and is intended
+    /// for debugging.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    /// Whether operators reading the index process their inputs in a reproducible order.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// The distribution of the lengths of the sorted runs of committed updates.
+    ///
+    /// Returns pairs `(bucket, count)` in order of bucket, counting the runs whose length has
+    /// `bucket` significant bits: bucket zero holds empty runs, bucket one runs of one update,
+    /// bucket two runs of two or three updates, and so on.
+    pub fn run_lengths(&self) -> Vec<(usize, usize)> {
+        let mut buckets = Vec::new();
+        for entry in self.edges.values() {
+            for length in entry.run_lengths() {
+                let bucket = (0usize.leading_zeros() - length.leading_zeros()) as usize;
+                if buckets.len() <= bucket { buckets.resize(bucket + 1, 0); }
+                buckets[bucket] += 1;
+            }
+        }
+        buckets.into_iter().enumerate().filter(|x| x.1 > 0).collect()
+    }
+
+    /// Enables a Bloom filter over keys, with `bits_per_key` bits for each key.
+    ///
+    /// The filter lets `intersect_only` skip prefixes whose key has never had any updates without
+    /// searching the index. It is rebuilt from the current keys now and whenever the index is
+    /// initialized or rebuilt, and keys of later updates are added as they arrive; keys whose
+    /// updates cancel remain in the filter until it is rebuilt.
+    pub fn enable_filter(&mut self, bits_per_key: usize) {
+        self.filter = Some((Bloom::new(0, bits_per_key), bits_per_key));
+        self.rebuild_filter();
+    }
+
+    /// Disables the Bloom filter over keys.
+    pub fn disable_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Rebuilds the Bloom filter, if enabled, from the keys currently in the index.
+    pub fn rebuild_filter(&mut self) {
+        if let Some((_, bits_per_key)) = self.filter.take() {
+            let keys = self.compact.keys().count() + self.edges.len() + self.diffs.updates.len();
+            let mut bloom = Bloom::new(keys, bits_per_key);
+            for key in self.compact.keys() { bloom.insert(key); }
+            for key in self.edges.keys() { bloom.insert(key); }
+            for update in self.diffs.updates.iter() { bloom.insert(&update.0); }
+            self.filter = Some((bloom, bits_per_key));
         }
     }
 
@@ -354,6 +910,9 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
 
         let possible_diffs = self.diffs.min_time.as_ref().map(|t| t <= &start_time).unwrap_or(false);
 
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
+
         let mut index = 0;
         while index < data.len() {
 
@@ -361,15 +920,19 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             let key = func(&data[index].0);
 
             // (ia) update `count` by the number of values in `self.compact`.
-            count += self.compact.values_from(&key, &mut c_cursor).len() as u64;
+            count += compact_values(&self.compact, &key, &mut c_cursor, &mut reads).len() as u64;
 
-            // (ib) update `count` by values in `self.edges`.
-            count += self.edges.get(&key).map(|entry| entry.count() as u64).unwrap_or(0);
+            // (ib) update `count` by values in `self.edges`, whose net count is negative where they
+            // retract compacted values; the sum with those of `self.compact` is not.
+            count = count.wrapping_add(self.edges.get(&key).map(|entry| entry.count() as u64).unwrap_or(0));
 
             // (ic) update `count` by values in `self.diffs`. (an over-estimate)
+            let mut diffs = 0;
             if possible_diffs {
-                count += self.diffs.values_from(&key, &mut d_cursor).len() as u64;
+                diffs = self.diffs.values_from(&key, &mut d_cursor).len();
+                count += diffs as u64;
             }
+            if let Some(ref mut reads) = reads { reads.visit(0, diffs); }
 
             // (ii) we may have multiple records with the same key, do them all.
             while index < data.len() && func(&data[index].0) == key {
@@ -383,12 +946,29 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                 index += 1;
             }
         }
+
+        self.read_stats = reads;
+    }
+
+    pub fn forward_propose<P, K, X, W>(&mut self, data: &mut Vec<(P, X, W)>, func: &K, start_time: &T)
+        where K: Fn(&P) -> Key,
+              P: Indexable<Key>,
+              X: Extensions<Key>,
+    {
+        self.forward_propose_where(data, func, start_time, &|_| true);
     }
 
+    /// As `forward_propose`, but proposing only the values satisfying `accept`.
+    ///
+    /// Values are filtered as they are read from the index, before they are consolidated and
+    /// proposed to each prefix, so that a selective predicate, such as a required vertex label,
+    /// shrinks the proposals rather than the extensions made from them.
     #[inline(never)]
-    pub fn forward_propose<P, K, W>(&mut self, data: &mut Vec<(P, Vec<Key>, W)>, func: &K, start_time: &T)
+    pub fn forward_propose_where<P, K, X, W, A>(&mut self, data: &mut Vec<(P, X, W)>, func: &K, start_time: &T, accept: &A)
         where K: Fn(&P) -> Key,
               P: Indexable<Key>,
+              X: Extensions<Key>,
+              A: Fn(&Key) -> bool,
     {
         // sorting allows us to re-use computation for the same key, and simplifies the searching
         // of self.compact and self.diffs.
@@ -400,8 +980,12 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
         let mut diffs_cursor = 0;
         // let mut diffs = &self.diffs[..];
 
-        // temporary array to stage proposals
+        // temporary array to stage proposals, and the proposals each record receives.
         let mut proposals = Vec::<(Key, i32)>::new();
+        let mut staged = Vec::new();
+
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
 
         // current position in `data`.
         let mut index = 0;
@@ -411,17 +995,22 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             proposals.clear();
 
             // (ia): incorporate updates from `self.compact`.
-            let values = self.compact.values_from(&key, &mut offset_cursor);
-            proposals.extend(values.iter().map(|v| (v.clone(), 1)));
+            let values = compact_values(&self.compact, &key, &mut offset_cursor, &mut reads);
+            proposals.extend(values.iter().filter(|v| accept(v)).map(|v| (v.clone(), 1)));
 
             // (ib): incorporate updates from `self.edges`.
-            self.edges.get_mut(&key).map(|entry| proposals.extend_from_slice(entry.proposals()));
+            let mut runs = 0;
+            if let Some(entry) = self.edges.get_mut(&key) {
+                runs = entry.runs();
+                proposals.extend(entry.proposals(&mut self.merge_stats).iter().filter(|x| accept(&x.0)).cloned());
+            }
 
             // (ic): incorporate updates from `self.diffs`.
             let values = self.diffs.values_from(&key, &mut diffs_cursor);
+            if let Some(ref mut reads) = reads { reads.visit(runs, values.len()); }
 
             for &(ref _key, ref val, ref time, wgt) in values.iter() {
-                if time < start_time{
+                if time < start_time && accept(val) {
                     proposals.push((val.clone(), wgt));
                 }
             }
@@ -458,16 +1047,9 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                     consolidate_proposals(&mut proposals);
 
                     //for all src with src < key, in self.diffs only edges with less timestamp can be seen, propose them all
-                    while index < data.len() && func(&data[index].0) == key && data[index].0.get_src() < key {
-                        for &(ref val, cnt) in &proposals {
-                            for _ in 0..cnt {
-                                if !data[index].0.find(val){
-                                    data[index].1.push(val.clone());
-                                }
-                            }
-                        }
-                        index += 1;
-                    }
+                    let end = advance_from(&data[..], index, |x| func(&x.0) == key && x.0.get_src() < key);
+                    propose_each(&mut data[index .. end], &proposals, &mut staged);
+                    index = end;
                 }
                 else if src == key {
                     //re-use computation for the same key, src
@@ -477,7 +1059,7 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                         //for src with src == key, except edges with less timestamp, edges (key -> extension) with the same timestamp
                         //and extension < dst can be seen
                         while dst_cursor < values.len() && values[dst_cursor].1 < dst {
-                            if values[dst_cursor].2 == *start_time {
+                            if values[dst_cursor].2 == *start_time && accept(&values[dst_cursor].1) {
                                 proposals.push((values[dst_cursor].1.clone(), values[dst_cursor].3));
                             }
                             dst_cursor += 1;
@@ -486,22 +1068,15 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                         consolidate_proposals(&mut proposals);
 
                         //propose for those with the same key, src and dst
-                        while index < data.len() && func(&data[index].0) == key && data[index].0.get_src() == src && data[index].0.get_dst() == dst {
-                            for &(ref val, cnt) in &proposals {
-                                for _ in 0..cnt {
-                                    if !data[index].0.find(val){
-                                        data[index].1.push(val.clone());
-                                    }
-                                }
-                            }
-                            index += 1;
-                        }
+                        let end = advance_from(&data[..], index, |x| func(&x.0) == key && x.0.get_src() == src && x.0.get_dst() == dst);
+                        propose_each(&mut data[index .. end], &proposals, &mut staged);
+                        index = end;
                     }
                 }
                 else if src > key {
                     //all edges with less equal timestamp can be seen,re-use computation from (src == key)
                     while dst_cursor < values.len() {
-                        if values[dst_cursor].2 == *start_time{
+                        if values[dst_cursor].2 == *start_time && accept(&values[dst_cursor].1) {
                             proposals.push((values[dst_cursor].1.clone(), values[dst_cursor].3));
                         }
                         dst_cursor += 1;
@@ -511,24 +1086,31 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                     consolidate_proposals(&mut proposals);
 
                     //propose for all with the same key and src > key
-                    while index < data.len() && func(&data[index].0) == key{
-                        for &(ref val, cnt) in &proposals {
-                            for _ in 0..cnt {
-                                if !data[index].0.find(val){
-                                    data[index].1.push(val.clone());
-                                }
-                            }
-                        }
-                        index += 1;
-                    }
+                    let end = advance_from(&data[..], index, |x| func(&x.0) == key);
+                    propose_each(&mut data[index .. end], &proposals, &mut staged);
+                    index = end;
                 }
             }
         }
+
+        self.read_stats = reads;
     }
 
-    pub fn reverse_propose<P, K, W>(&mut self, data: &mut Vec<(P, Vec<Key>, W)>, func: &K, start_time: &T)
+    pub fn reverse_propose<P, K, X, W>(&mut self, data: &mut Vec<(P, X, W)>, func: &K, start_time: &T)
         where K: Fn(&P) -> Key,
               P: Indexable<Key>,
+              X: Extensions<Key>,
+    {
+        self.reverse_propose_where(data, func, start_time, &|_| true);
+    }
+
+    /// As `reverse_propose`, but proposing only the values satisfying `accept`; see
+    /// `forward_propose_where`.
+    pub fn reverse_propose_where<P, K, X, W, A>(&mut self, data: &mut Vec<(P, X, W)>, func: &K, start_time: &T, accept: &A)
+        where K: Fn(&P) -> Key,
+              P: Indexable<Key>,
+              X: Extensions<Key>,
+              A: Fn(&Key) -> bool,
     {
         data.sort_unstable_by(|x,y| (func(&x.0), x.0.get_src(), x.0.get_dst()).cmp(&(func(&y.0), y.0.get_src(), y.0.get_dst())));
         //data.sort_unstable_by(|x,y| (func(&x.0)).cmp(&(func(&y.0))));
@@ -537,8 +1119,12 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
         let mut diffs_cursor = 0;
         // let mut diffs = &self.diffs[..];
 
-        // temporary array to stage proposals
+        // temporary array to stage proposals, and the proposals each record receives.
         let mut proposals = Vec::<(Key, i32)>::new();
+        let mut staged = Vec::new();
+
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
 
         // current position in `data`.
         let mut index = 0;
@@ -548,17 +1134,22 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             proposals.clear();
 
             // (ia): incorporate updates from `self.compact`.
-            let values = self.compact.values_from(&key, &mut offset_cursor);
-            proposals.extend(values.iter().map(|v| (v.clone(), 1)));
+            let values = compact_values(&self.compact, &key, &mut offset_cursor, &mut reads);
+            proposals.extend(values.iter().filter(|v| accept(v)).map(|v| (v.clone(), 1)));
 
             // (ib): incorporate updates from `self.edges`.
-            self.edges.get_mut(&key).map(|entry| proposals.extend_from_slice(entry.proposals()));
+            let mut runs = 0;
+            if let Some(entry) = self.edges.get_mut(&key) {
+                runs = entry.runs();
+                proposals.extend(entry.proposals(&mut self.merge_stats).iter().filter(|x| accept(&x.0)).cloned());
+            }
 
             // (ic): incorporate updates from `self.diffs`.
             let values = self.diffs.values_from(&key, &mut diffs_cursor);
+            if let Some(ref mut reads) = reads { reads.visit(runs, values.len()); }
 
             for &(ref _key, ref val, ref time, wgt) in values.iter() {
-                if time < &start_time{
+                if time < &start_time && accept(val) {
                     proposals.push((val.clone(), wgt));
                 }
             }
@@ -594,7 +1185,7 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                 if dst <= key {// propose extension < src
 
                     while src_cursor < values.len() && values[src_cursor].1 < src {
-                        if values[src_cursor].2 == *start_time{
+                        if values[src_cursor].2 == *start_time && accept(&values[src_cursor].1) {
                             proposals.push((values[src_cursor].1.clone(), values[src_cursor].3));
                         }
                         src_cursor += 1;
@@ -604,21 +1195,14 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                     consolidate_proposals(&mut proposals);
 
                     // propose for all with the same key, src and dst (dst <= key)
-                    while index < data.len() && func(&data[index].0) == key && data[index].0.get_src() == src && data[index].0.get_dst() <= key {
-                        for &(ref val, cnt) in &proposals {
-                            for _ in 0..cnt {
-                                if !data[index].0.find(val){
-                                    data[index].1.push(val.clone());
-                                }
-                            }
-                        }
-                        index += 1;
-                    }
+                    let end = advance_from(&data[..], index, |x| func(&x.0) == key && x.0.get_src() == src && x.0.get_dst() <= key);
+                    propose_each(&mut data[index .. end], &proposals, &mut staged);
+                    index = end;
                 }
                 else if dst > key { // propose extension <= src
 
                     while src_cursor < values.len()&& values[src_cursor].1 <= src {
-                        if values[src_cursor].2 == *start_time{
+                        if values[src_cursor].2 == *start_time && accept(&values[src_cursor].1) {
                             proposals.push((values[src_cursor].1.clone(), values[src_cursor].3));
                         }
                         src_cursor += 1;
@@ -628,25 +1212,92 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                     consolidate_proposals(&mut proposals);
 
                     // propose for all with the same key ,src and dst (dst > key)
-                    while index < data.len() && func(&data[index].0) == key && data[index].0.get_src() == src {
-                        for &(ref val, cnt) in &proposals {
-                            for _ in 0..cnt {
-                                if !data[index].0.find(val){
-                                    data[index].1.push(val.clone());
-                                }
-                            }
-                        }
-                        index += 1;
-                    }
+                    let end = advance_from(&data[..], index, |x| func(&x.0) == key && x.0.get_src() == src);
+                    propose_each(&mut data[index .. end], &proposals, &mut staged);
+                    index = end;
                 }
             }
         }
+
+        self.read_stats = reads;
+    }
+
+    /// Proposes the values of `key` satisfying `accept` to each record of `group`, whose prefixes
+    /// all read `key`.
+    ///
+    /// This is the probe of a hash join: the values of `key` are looked up once, rather than
+    /// reached by a cursor moving over keys in sorted order, so `group` need not be sorted. They
+    /// are validated against `start_time` for each prefix as `forward_propose_where` validates
+    /// them, or `reverse_propose_where` if `is_forward` is not set, so each record receives the
+    /// same proposals as it would from those.
+    pub fn probe_where<P, X, W, A>(&mut self, key: &Key, group: &mut [(P, X, W)], is_forward: bool, start_time: &T, accept: &A)
+        where P: Indexable<Key>,
+              X: Extensions<Key>,
+              A: Fn(&Key) -> bool,
+    {
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
+
+        // committed values, and uncommitted updates before `start_time`, which every prefix sees.
+        let mut cursor = 0;
+        let mut proposals = compact_values(&self.compact, key, &mut cursor, &mut reads).iter().filter(|v| accept(v)).map(|v| (v.clone(), 1)).collect::<Vec<_>>();
+        let mut runs = 0;
+        if let Some(entry) = self.edges.get_mut(key) {
+            runs = entry.runs();
+            proposals.extend(entry.proposals(&mut self.merge_stats).iter().filter(|x| accept(&x.0)).cloned());
+        }
+        let mut cursor = 0;
+        let values = self.diffs.values_from(key, &mut cursor);
+        if let Some(ref mut reads) = reads { reads.visit(runs, values.len()); }
+        proposals.extend(values.iter().filter(|x| &x.2 < start_time && accept(&x.1)).map(|x| (x.1.clone(), x.3)));
+
+        // updates at `start_time` are seen only by the prefixes of the updates ordered after them.
+        let current = values.iter().filter(|x| &x.2 == start_time && accept(&x.1)).collect::<Vec<_>>();
+        let mut staged = Vec::new();
+        if current.is_empty() {
+            consolidate_proposals(&mut proposals);
+            propose_each(group, &proposals, &mut staged);
+        } else {
+            for record in 0 .. group.len() {
+                let (src, dst) = (group[record].0.get_src(), group[record].0.get_dst());
+                let mut visible = proposals.clone();
+                visible.extend(current.iter().filter(|x| {
+                    if is_forward { src > *key || (src == *key && x.1 < dst) } else { x.1 < src || (x.1 == src && dst > *key) }
+                }).map(|x| (x.1.clone(), x.3)));
+                consolidate_proposals(&mut visible);
+                propose_each(&mut group[record .. record + 1], &visible, &mut staged);
+            }
+        }
+
+        self.read_stats = reads;
     }
 
+    pub fn intersect<P, F, X, W>(&mut self, data: &mut Vec<(P, X, W)>, func: &F, is_forward: bool, start_time: &T)
+        where F: Fn(&P)->Key,
+              P: Indexable<Key>,
+              X: Extensions<Key>,
+    {
+        self.intersect_proposals(data, func, is_forward, start_time, false);
+    }
+
+    /// As `intersect`, but removing the proposals the index holds rather than keeping them.
+    ///
+    /// A proposal is kept if its count, as `intersect` reads it, is not strictly positive: the
+    /// edge from the key to the proposal is absent as of the updates the prefix may see.
+    pub fn anti_intersect<P, F, X, W>(&mut self, data: &mut Vec<(P, X, W)>, func: &F, is_forward: bool, start_time: &T)
+        where F: Fn(&P)->Key,
+              P: Indexable<Key>,
+              X: Extensions<Key>,
+    {
+        self.intersect_proposals(data, func, is_forward, start_time, true);
+    }
+
+    // keeps the proposals whose counts are strictly positive, or are not if `anti` is set.
     #[inline(never)]
-    pub fn intersect<P, F, W>(&mut self, data: &mut Vec<(P, Vec<Key>, W)>, func: &F, is_forward: bool, start_time: &T)
+    fn intersect_proposals<P, F, X, W>(&mut self, data: &mut Vec<(P, X, W)>, func: &F, is_forward: bool, start_time: &T, anti: bool)
         where F: Fn(&P)->Key,
               P: Indexable<Key>,
+              X: Extensions<Key>,
     {
         // sorting data by key allows us to re-use some work / compact representations.
         data.sort_unstable_by(|x,y| func(&x.0).cmp(&(func(&y.0))));
@@ -654,6 +1305,12 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
         // counts for each value to validate
         let mut temp = Vec::new();
 
+        // the kernel is read and its counters updated within closures, which must not borrow `self`.
+        let kernel = self.kernel;
+        let mut kernel_stats = self.kernel_stats;
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
+
         // fingers into compacted data and uncommited updates.
         let mut offset_cursor = 0;
         let mut diffs_cursor = 0;
@@ -668,20 +1325,28 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             let mut effort = 16;
             let mut temp_index = index;
             while temp_index < data.len() && func(&data[temp_index].0) == key {
-                effort += data[temp_index].1.len();
+                effort += data[temp_index].1.with_values(|values| values.len());
                 temp_index += 1;
             }
 
             // (i) position `self.compact` cursor so that we can re-use it.
-            let compact_slice = self.compact.values_from(&key, &mut offset_cursor);
+            let compact_slice = compact_values(&self.compact, &key, &mut offset_cursor, &mut reads);
 
             // (ii) prepare non-compact updates. if our effort level is large, consolidate.
             let mut entry = self.edges.get_mut(&key);
-            entry.as_mut().map(|x| x.expend(effort as u32));
+            if let Some(ref mut entry) = entry { entry.expend(effort as u32, &*self.policy, &mut self.merge_stats); }
 
             // (iii) position `self.diffs` cursor so that we can re-use it.
             let diffs_slice = self.diffs.values_from(&key, &mut diffs_cursor);
 
+            // each record with the key scans every run and uncommitted update of the key.
+            if let Some(ref mut reads) = reads {
+                let records = temp_index - index;
+                reads.visit(records * entry.as_ref().map(|x| x.runs()).unwrap_or(0), records * diffs_slice.len());
+            }
+
+            // a table of `compact_slice`, built if a kernel probing it is chosen for this key.
+            let mut table: Option<Table<Key>> = None;
 
             // we may have multiple records with the same key, do them all.
             while index < data.len() && func(&data[index].0) == key {
@@ -695,56 +1360,62 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
                 // Our plan is to take the list of proposals (record.1) and populate
                 // a corresponding vector of `i32` counts for each proposal, from each
                 // of our sources of changes.
+                proposals.with_values(|proposals| {
+
+                    // set `temp` to be a vector of initially zero counts.
+                    temp.clear();
+                    temp.resize(proposals.len(), 0);
+
+                    // (ia) update `temp` counts based on `self.edges[key]`, if it exists.
+                    entry.as_mut().map(|x| x.intersect(proposals, &mut temp));
+
+                    // (ib) update `temp` counts based on `self.compact`, with a kernel suited to the lengths.
+                    let chosen = kernel.choose(proposals.len(), compact_slice.len(), effort - 16);
+                    kernel_stats.record(chosen);
+                    if chosen == IntersectKernel::Hash {
+                        if table.is_none() {
+                            table = Some(kernel::tabulate(compact_slice));
+                            kernel_stats.tables += 1;
+                        }
+                        kernel::probe(table.as_ref().unwrap(), proposals, &mut temp[..]);
+                    }
+                    else {
+                        kernel::count_occurrences(chosen, proposals, compact_slice, &mut temp[..]);
+                    }
 
-                // set `temp` to be a vector of initially zero counts.
-                temp.clear();
-                temp.resize(proposals.len(), 0);
-
-                // (ia) update `temp` counts based on `self.edges[key]`, if it exists.
-                entry.as_mut().map(|x| x.intersect(proposals, &mut temp));
-
-                // (ib, ic) update `temp` counts based on `self.compact` and `self.diffs`.
-                let mut c_cursor = 0;
-                let mut d_cursor = 0;
-
-                // walk proposals linearly (could gallop, if we felt strongly enough).
-                for (proposal, count) in proposals.iter().zip(temp.iter_mut()) {
+                    // (ic) update `temp` counts based on `self.diffs`.
+                    let mut d_cursor = 0;
 
-                    // move c_cursor to where `proposal` would start ..
-                    c_cursor += advance(&compact_slice[c_cursor..], |x| x < proposal);
-                    while compact_slice.get(c_cursor) == Some(proposal) {
-                        *count += 1;
-                        c_cursor += 1;
-                    }
+                    for (proposal, count) in proposals.iter().zip(temp.iter_mut()) {
 
-                    // move d_cursor to where `proposal` would start ..
-                    d_cursor += advance(&diffs_slice[d_cursor..], |x| &x.1 < proposal);
+                        // move d_cursor to where `proposal` would start ..
+                        d_cursor = advance_from(diffs_slice, d_cursor, |x| &x.1 < proposal);
 
-                    while diffs_slice.get(d_cursor).map(|x| &x.1) == Some(proposal) {
-                        if (start_time > &diffs_slice[d_cursor].2)
-                        ||((start_time == &diffs_slice[d_cursor].2)&&
-                            ((is_forward && ((key < src)||(key == src && proposal < &dst)))
-                                ||(!is_forward && ((proposal < &src)||(proposal == &src && key < dst))))) {
-                            *count += diffs_slice[d_cursor].3;
+                        while diffs_slice.get(d_cursor).map(|x| &x.1) == Some(proposal) {
+                            if (start_time > &diffs_slice[d_cursor].2)
+                            ||((start_time == &diffs_slice[d_cursor].2)&&
+                                ((is_forward && ((key < src)||(key == src && proposal < &dst)))
+                                    ||(!is_forward && ((proposal < &src)||(proposal == &src && key < dst))))) {
+                                *count += diffs_slice[d_cursor].3;
+                            }
+                            d_cursor += 1;
                         }
-                        d_cursor += 1;
-                    }
 
-                }
-
-                // (ii) remove elements whose count is not strictly positive.
-                let mut cursor = 0;
-                for i in 0 .. temp.len() {
-                    if temp[i] > 0 {
-                        proposals.swap(cursor, i);
-                        cursor += 1;
                     }
+                });
+
+                // (ii) remove elements whose count is not strictly positive, or, anti-intersecting, those whose count is.
+                if anti {
+                    for count in temp.iter_mut() { *count = if *count > 0 { 0 } else { 1 }; }
                 }
-                proposals.truncate(cursor);
+                proposals.retain_positive(&temp[..]);
 
                 index += 1;
             }
         }
+
+        self.kernel_stats = kernel_stats;
+        self.read_stats = reads;
     }
 
     pub fn intersect_only<P,K1,K2,W>(&mut self, data: &mut Vec<(P, W)>, func1: &K1, func2: &K2, is_forward: bool, start_time: &T)
@@ -752,6 +1423,24 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
               K2: Fn(&P)->Key,
               P: Indexable<Key>,
     {
+        self.intersect_prefixes(data, func1, func2, is_forward, start_time, false);
+    }
+
+    /// As `intersect_only`, but keeping the prefixes whose edge from `func1` to `func2` is absent.
+    pub fn anti_intersect_only<P,K1,K2,W>(&mut self, data: &mut Vec<(P, W)>, func1: &K1, func2: &K2, is_forward: bool, start_time: &T)
+        where K1: Fn(&P)->Key,
+              K2: Fn(&P)->Key,
+              P: Indexable<Key>,
+    {
+        self.intersect_prefixes(data, func1, func2, is_forward, start_time, true);
+    }
+
+    // keeps the prefixes whose edges have strictly positive counts, or do not if `anti` is set.
+    fn intersect_prefixes<P,K1,K2,W>(&mut self, data: &mut Vec<(P, W)>, func1: &K1, func2: &K2, is_forward: bool, start_time: &T, anti: bool)
+        where K1: Fn(&P)->Key,
+              K2: Fn(&P)->Key,
+              P: Indexable<Key>,
+    {
 
         // sorting data by key allows us to re-use some work / compact representations.
         //data.sort_unstable_by(|x,y| func(&x.0).cmp(&(func(&y.0))));
@@ -760,6 +1449,9 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
         // counts for each value to validate
         let mut temp = Vec::new();
 
+        let mut reads = self.read_stats;
+        if let Some(ref mut reads) = reads { reads.operations += 1; }
+
         // fingers into compacted data and uncommited updates.
         let mut offset_cursor = 0;
         let mut diffs_cursor = 0;
@@ -775,19 +1467,32 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             // consider the amount of effort we are about to invest:
             let mut effort = 16;
 
-            let temp_index = index + advance(&data[index..],|x|func1(&x.0)<= key);
+            let temp_index = advance_from(&data[..], index, |x| func1(&x.0) <= key);
+
+            // skip prefixes whose key certainly has no updates, dropping them, or keeping them all if anti-intersecting.
+            if self.filter.as_ref().map(|x| !x.0.contains(&key)).unwrap_or(false) {
+                while anti && index < temp_index {
+                    data.swap(r_cursor, index);
+                    r_cursor += 1;
+                    index += 1;
+                }
+                index = temp_index;
+                continue;
+            }
+
             let mut idx = index;
             effort += temp_index - index;
 
             // (i) position `self.compact` cursor so that we can re-use it.
-            let compact_slice = self.compact.values_from(&key, &mut offset_cursor);
+            let compact_slice = compact_values(&self.compact, &key, &mut offset_cursor, &mut reads);
 
             // (ii) prepare non-compact updates. if our effort level is large, consolidate.
             let mut entry = self.edges.get_mut(&key);
-            entry.as_mut().map(|x| x.expend(effort as u32));
+            if let Some(ref mut entry) = entry { entry.expend(effort as u32, &*self.policy, &mut self.merge_stats); }
 
             // (iii) position `self.diffs` cursor so that we can re-use it.
             let diffs_slice = self.diffs.values_from(&key, &mut diffs_cursor);
+            if let Some(ref mut reads) = reads { reads.visit(entry.as_ref().map(|x| x.runs()).unwrap_or(0), diffs_slice.len()); }
 
             let mut proposals = Vec::new();
             for i in index .. temp_index{
@@ -801,25 +1506,18 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             // (ia) update `temp` counts based on `self.edges[key]`, if it exists.
             entry.as_mut().map(|x| x.intersect(&proposals[..], &mut temp));
 
-            // (ib, ic) update `temp` counts based on `self.compact` and `self.diffs`.
-            let mut c_cursor = 0;
+            // (ib) update `temp` counts based on `self.compact`, with a kernel suited to the lengths.
+            let chosen = self.kernel.choose(proposals.len(), compact_slice.len(), proposals.len());
+            self.kernel_stats.record(chosen);
+            kernel::count_occurrences(chosen, &proposals[..], compact_slice, &mut temp[..]);
+
+            // (ic) update `temp` counts based on `self.diffs`.
             let mut d_cursor = 0;
 
-            // walk proposals linearly (could gallop, if we felt strongly enough).
             for (proposal, count) in proposals.iter().zip(temp.iter_mut()) {
 
-                // move c_cursor to where `proposal` would start ..
-                c_cursor += advance(&compact_slice[c_cursor..], |x| x < proposal);
-                let prev_c_cursor = c_cursor;
-
-                while compact_slice.get(c_cursor) == Some(proposal) {
-                    *count += 1;
-                    c_cursor += 1;
-                }
-                c_cursor = prev_c_cursor;
-
                 // move d_cursor to where `proposal` would start ..
-                d_cursor += advance(&diffs_slice[d_cursor..], |x| &x.1 < proposal);
+                d_cursor = advance_from(diffs_slice, d_cursor, |x| &x.1 < proposal);
                 let prev_d_cursor = d_cursor;
 
                 let src = data[idx].0.get_src();
@@ -841,7 +1539,7 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             let mut t_cursor = 0;
 
             while index < temp_index{
-                if temp[t_cursor] > 0 {
+                if (temp[t_cursor] > 0) != anti {
                     data.swap(r_cursor,index);
                     r_cursor += 1;
                 }
@@ -864,6 +1562,27 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
     /// if we have updates which update the same value (potentially cancelling).
     #[inline(never)]
     pub fn merge_to(&mut self, time: &T) {
+        self.merge_where(|x| x.le(time));
+    }
+
+    /// Commits updates at times strictly before `frontier`.
+    ///
+    /// This is `merge_to` for drivers that know the times that are complete only by their frontier,
+    /// for example because updates arrived at several times between two frontiers.
+    pub fn merge_before(&mut self, frontier: &T) {
+        self.merge_where(|x| x < frontier);
+    }
+
+    /// Commits every update, whatever its time.
+    ///
+    /// This is `merge_to` for drivers whose computation has completed, so that no time remains to
+    /// be distinguished from any other.
+    pub fn merge_all(&mut self) {
+        self.merge_where(|_| true);
+    }
+
+    // commits updates whose times satisfy `committed`.
+    fn merge_where<F: Fn(&T)->bool>(&mut self, committed: F) {
 
         let mut index = 0;
         while index < self.diffs.updates.len() {
@@ -873,14 +1592,18 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
             let prior_position = entry.position();
 
             while self.diffs.updates.get(index).map(|x| &x.0) == self.diffs.updates.get(key_index).map(|x| &x.0) {
-                if self.diffs.updates[index].2.le(time) {
+                if committed(&self.diffs.updates[index].2) {
                     entry.push((self.diffs.updates[index].1.clone(), self.diffs.updates[index].3));
+                    if let Some((_, ref mut committed)) = self.rebuilding {
+                        let update = &self.diffs.updates[index];
+                        committed.push(((update.0.clone(), update.1.clone()), update.3));
+                    }
                     self.diffs.updates[index].3 = 0;
                 }
                 index += 1;
             }
 
-            entry.seal_from(prior_position);
+            entry.seal_from(prior_position, &*self.policy, &mut self.merge_stats);
         }
 
         // remove committed updates
@@ -891,17 +1614,349 @@ impl<Key: Ord+Hash+Clone, T: Ord+Clone> Index<Key, T> {
     /// Introduces a collection of updates at various times.
     ///
     /// These updates will now be reflected in all queries against the index, at or after the
-    /// indicated logical time.
+    /// indicated logical time. Buffered updates to the same key and value at the same time are
+    /// held as one, with their diffs summed, and are dropped if their diffs cancel, as when an edge
+    /// is inserted and deleted within a batch: reads then scan one update, or none, for each, and
+    /// read the same values as they would from the updates held apart.
     #[inline(never)]
     pub fn update(&mut self, time: T, updates: &mut Vec<((Key, Key), i32)>) {
-        self.diffs.extend(time, updates.drain(..));
+        if let Some((ref mut bloom, _)) = self.filter {
+            for update in updates.iter() { bloom.insert(&(update.0).0); }
+        }
+        if self.maintenance == Maintenance::Direct && self.diffs.updates.is_empty() {
+            self.commit_by_key(updates);
+        }
+        else {
+            self.diffs.extend(time, updates.drain(..));
+        }
+    }
+
+    // appends `updates` to the committed updates of their keys, as one sorted run per key.
+    fn commit_by_key(&mut self, updates: &mut Vec<((Key, Key), i32)>) {
+        updates.sort_by(|x, y| x.0.cmp(&y.0));
+        let mut updates = updates.drain(..).peekable();
+        while let Some(((key, val), diff)) = updates.next() {
+            let entry = self.edges.entry(key.clone()).or_insert(EdgeList::new());
+            let prior_position = entry.position();
+            entry.push((val.clone(), diff));
+            if let Some((_, ref mut committed)) = self.rebuilding { committed.push(((key.clone(), val), diff)); }
+            while updates.peek().map(|x| &(x.0).0 == &key).unwrap_or(false) {
+                let ((_, val), diff) = updates.next().unwrap();
+                entry.push((val.clone(), diff));
+                if let Some((_, ref mut committed)) = self.rebuilding { committed.push(((key.clone(), val), diff)); }
+            }
+            entry.seal_from(prior_position, &*self.policy, &mut self.merge_stats);
+        }
+    }
+
+    /// Constructs an index from a snapshot of its contents, as produced by `snapshot`.
+    pub fn from_snapshot(mut snapshot: Vec<(Key, Key)>) -> Self {
+        snapshot.sort();
+        let mut index = Index::new();
+        index.initialize(&mut vec![snapshot]);
+        index
+    }
+
+    /// As `from_snapshot`, but checking that worker `worker` of `peers` owns every key; see `initialize_owned`.
+    pub fn from_snapshot_owned<H: Fn(Key)->u64>(mut snapshot: Vec<(Key, Key)>, hash: &H, worker: usize, peers: usize, foreign: ForeignKeys) -> Result<Self, ForeignKey<Key>> {
+        snapshot.sort();
+        let mut index = Index::new();
+        index.initialize_owned(&mut vec![snapshot], hash, worker, peers, foreign)?;
+        Ok(index)
+    }
+
+    /// The number of entries held by the index.
+    ///
+    /// Entries are compacted values and the updates not yet merged into them, so the number may
+    /// exceed the number of distinct `(key, val)` pairs until the index is merged.
+    pub fn len(&self) -> usize {
+        self.compact.len() + self.edges.values().map(|entry| entry.updates().len()).sum::<usize>() + self.diffs.updates.len()
+    }
+
+    /// The number of updates the index holds uncommitted, waiting for their times to complete.
+    pub fn uncommitted(&self) -> usize {
+        self.diffs.updates.len()
+    }
+
+    /// The earliest time of the updates the index holds uncommitted, if it holds any.
+    pub fn oldest_uncommitted(&self) -> Option<&T> {
+        self.diffs.min_time.as_ref()
+    }
+
+    /// An estimate of the bytes allocated by the index.
+    ///
+    /// This counts the allocations of the compact index, of each key's committed updates and the
+    /// map holding them, and of the uncommitted updates, but not allocator overhead.
+    pub fn heap_size(&self) -> usize {
+        let map = self.edges.capacity() * (::std::mem::size_of::<Key>() + ::std::mem::size_of::<EdgeList<Key>>());
+        let lists = self.edges.values().map(|entry| entry.heap_size()).sum::<usize>();
+        let diffs = self.diffs.updates.capacity() * ::std::mem::size_of::<(Key, Key, T, i32)>();
+        self.compact.heap_size() + map + lists + diffs
+    }
+
+    /// The number of committed `(key, val)` pairs, counted with multiplicity.
+    ///
+    /// Uncommitted updates are not counted; after `merge_to` with the last complete time, this is
+    /// the size of the relation the index represents.
+    pub fn committed_len(&self) -> usize {
+        let edges = self.edges.values().map(|entry| entry.count()).sum::<i64>();
+        ::std::cmp::max(self.compact.len() as i64 + edges, 0) as usize
+    }
+
+    /// True if the index holds no entries.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// An upper bound on the number of values associated with `key`.
+    ///
+    /// This counts committed values, and all uncommitted updates as if they were insertions.
+    pub fn degree(&self, key: &Key) -> usize {
+        let mut c_cursor = 0;
+        let mut d_cursor = 0;
+        let compact = self.compact.values_from(key, &mut c_cursor).len();
+        let edges = self.edges.get(key).map(|entry| ::std::cmp::max(entry.count(), 0) as usize).unwrap_or(0);
+        let diffs = self.diffs.values_from(key, &mut d_cursor).len();
+        compact + edges + diffs
+    }
+
+    /// The number of values of each key at `time`, counted with multiplicity, in order of key.
+    ///
+    /// This counts committed values and the uncommitted updates at times less or equal to `time`,
+    /// so it describes the index at `time` as long as no later updates have been committed. Keys
+    /// without values are omitted.
+    pub fn degrees_at(&self, time: &T) -> Vec<(Key, i64)> {
+        let mut degrees = HashMap::new();
+        for (key, _) in self.compact.pairs() { *degrees.entry(key.clone()).or_insert(0) += 1; }
+        for (key, entry) in self.edges.iter() { *degrees.entry(key.clone()).or_insert(0) += entry.count(); }
+        for update in self.diffs.updates.iter().filter(|x| &x.2 <= time) {
+            *degrees.entry(update.0.clone()).or_insert(0) += update.3 as i64;
+        }
+        let mut degrees = degrees.into_iter().filter(|x| x.1 != 0).collect::<Vec<_>>();
+        degrees.sort();
+        degrees
+    }
+
+    /// The values associated with `key` and their counts, in sorted order.
+    ///
+    /// This reflects all updates introduced to the index, whether committed or not.
+    pub fn values(&self, key: &Key) -> Vec<(Key, i32)> {
+        let mut cursor = 0;
+        let mut values = self.compact.values_from(key, &mut cursor).iter().map(|v| (v.clone(), 1)).collect::<Vec<_>>();
+        if let Some(entry) = self.edges.get(key) { values.extend(entry.updates().iter().cloned()); }
+        let mut cursor = 0;
+        values.extend(self.diffs.values_from(key, &mut cursor).iter().map(|x| (x.1.clone(), x.3)));
+        consolidate_proposals(&mut values);
+        values
+    }
+
+    /// The values associated with `key` at `time` and their counts, in sorted order.
+    ///
+    /// This reflects committed values and the uncommitted updates at times less or equal to `time`,
+    /// so it describes the index at `time` as long as no later updates have been committed.
+    pub fn values_at(&self, key: &Key, time: &T) -> Vec<(Key, i32)> {
+        let mut cursor = 0;
+        let mut values = self.compact.values_from(key, &mut cursor).iter().map(|v| (v.clone(), 1)).collect::<Vec<_>>();
+        if let Some(entry) = self.edges.get(key) { values.extend(entry.updates().iter().cloned()); }
+        let mut cursor = 0;
+        values.extend(self.diffs.values_from(key, &mut cursor).iter().filter(|x| &x.2 <= time).map(|x| (x.1.clone(), x.3)));
+        consolidate_proposals(&mut values);
+        values
+    }
+
+    /// The `(key, val)` pairs present in the index, in sorted order.
+    ///
+    /// Pairs are repeated according to their multiplicity. This method panics if the index has
+    /// uncommitted updates; call `merge_to` with the last complete time first.
+    pub fn snapshot(&self) -> Vec<(Key, Key)> {
+        assert!(self.diffs.updates.is_empty(), "snapshot of an index with uncommitted updates");
+        let mut result = Vec::new();
+        let mut cursor = 0;
+        let mut keys = self.compact.keys().cloned().collect::<Vec<_>>();
+        keys.extend(self.edges.keys().cloned());
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let mut values = self.compact.values_from(&key, &mut cursor).iter().map(|v| (v.clone(), 1)).collect::<Vec<_>>();
+            if let Some(entry) = self.edges.get(&key) { values.extend(entry.updates().iter().cloned()); }
+            consolidate_proposals(&mut values);
+            for (val, count) in values {
+                for _ in 0 .. count { result.push((key.clone(), val.clone())); }
+            }
+        }
+        result
+    }
+
+    /// Rebuilds the compact index from the committed contents of the index.
+    ///
+    /// After much churn, committed updates accumulate in per-key lists beside the compact index,
+    /// and values deleted from the compact index are still stored and visited. Rebuilding folds
+    /// all committed updates into a fresh compact index, reclaiming their memory and restoring
+    /// the locality of `propose` and `intersect`. Uncommitted updates are unaffected, and the
+    /// contents of the index are unchanged. Any rebuild in the background is abandoned.
+    pub fn rebuild(&mut self) {
+        self.rebuilding = None;
+        let mut pairs = self.committed();
+        let merged = merge_pairs(&mut pairs);
+        self.compact.load(merged.len(), merged.into_iter());
+        self.edges = HashMap::new();
+        self.rebuild_filter();
+    }
+
+    /// Starts rebuilding the compact index on a background thread.
+    ///
+    /// This gathers the committed contents of the index, which is cheap compared with merging
+    /// them, and leaves the merging and compaction to another thread. The index continues to be
+    /// used as before, and updates committed meanwhile are recorded, until `finish_rebuild` swaps
+    /// the new compact index in. This method does nothing if a rebuild is already in progress.
+    pub fn rebuild_in_background(&mut self) where Key: Send+'static {
+        if self.rebuilding.is_none() {
+            let mut pairs = self.committed();
+            let (sender, receiver) = channel();
+            thread::spawn(move || {
+                let merged = merge_pairs(&mut pairs);
+                let mut compact = CompactIndex::new();
+                compact.load(merged.len(), merged.into_iter());
+                // the receiver may have been dropped, if the rebuild was abandoned.
+                let _ = sender.send(compact);
+            });
+            self.rebuilding = Some((receiver, Vec::new()));
+        }
+    }
+
+    /// True if a rebuild started by `rebuild_in_background` has not yet been swapped in.
+    pub fn is_rebuilding(&self) -> bool {
+        self.rebuilding.is_some()
+    }
+
+    /// Swaps in the compact index rebuilt in the background, if there is one.
+    ///
+    /// If `block` is set this waits for the rebuild to complete, and otherwise returns false if it
+    /// has not. Updates committed since the rebuild started are reintroduced as the only updates
+    /// beside the new compact index. This should be called between epochs, when the index is not
+    /// borrowed; the contents of the index are unchanged either way.
+    pub fn finish_rebuild(&mut self, block: bool) -> bool {
+        let compact = match self.rebuilding {
+            Some((ref receiver, _)) => {
+                if block { receiver.recv().ok() }
+                else { receiver.try_recv().ok() }
+            },
+            None => None,
+        };
+        match compact {
+            Some(compact) => {
+                let (_, mut committed) = self.rebuilding.take().unwrap();
+                committed.sort_by(|x, y| (x.0).0.cmp(&(y.0).0));
+                self.compact = compact;
+                self.edges = HashMap::new();
+                let mut index = 0;
+                while index < committed.len() {
+                    let entry = self.edges.entry((committed[index].0).0.clone()).or_insert(EdgeList::new());
+                    let prior_position = entry.position();
+                    let key_index = index;
+                    while index < committed.len() && (committed[index].0).0 == (committed[key_index].0).0 {
+                        entry.push(((committed[index].0).1.clone(), committed[index].1));
+                        index += 1;
+                    }
+                    entry.seal_from(prior_position, &*self.policy, &mut self.merge_stats);
+                }
+                self.rebuild_filter();
+                true
+            },
+            None => false,
+        }
+    }
+
+    // the committed contents of the index, as unconsolidated updates.
+    fn committed(&self) -> Vec<((Key, Key), i32)> {
+        let mut pairs = Vec::with_capacity(self.compact.len() + self.edges.values().map(|entry| entry.updates().len()).sum::<usize>());
+        pairs.extend(self.compact.pairs().map(|(key, val)| ((key.clone(), val.clone()), 1)));
+        for (key, entry) in self.edges.iter() {
+            pairs.extend(entry.updates().iter().map(|&(ref val, diff)| ((key.clone(), val.clone()), diff)));
+        }
+        pairs
     }
 
     /// Sets an initial collection of positive counts, which we can compact.
+    ///
+    /// The collection must hold only keys this worker owns, which the index can not check; an
+    /// index given keys of other workers produces duplicate matches, and one missing its keys
+    /// produces too few. `initialize_owned` checks ownership.
     #[inline(never)]
     pub fn initialize(&mut self, initial: &mut Vec<Vec<(Key, Key)>>) {
         let length = initial.iter().map(|x| x.len()).sum();
         self.compact.load(length, initial.drain(..).flat_map(|x| x.into_iter()));
+        self.rebuild_filter();
+    }
+
+    /// As `initialize`, but checking that worker `worker` of `peers` owns every key.
+    ///
+    /// A worker owns the keys `hash` routes to it, `hash(key) % peers`, as the exchanges feeding
+    /// the index route them. Pairs with keys other workers own are dropped or rejected, as `foreign`
+    /// says. Returns the number of pairs dropped, or the first foreign key if they are rejected, in
+    /// which case nothing is loaded.
+    pub fn initialize_owned<H: Fn(Key)->u64>(&mut self, initial: &mut Vec<Vec<(Key, Key)>>, hash: &H, worker: usize, peers: usize, foreign: ForeignKeys) -> Result<usize, ForeignKey<Key>> {
+        let owner = |key: &Key| (hash(key.clone()) % peers as u64) as usize;
+        let mut dropped = 0;
+        match foreign {
+            ForeignKeys::Drop => {
+                for part in initial.iter_mut() {
+                    let length = part.len();
+                    part.retain(|pair| owner(&pair.0) == worker);
+                    dropped += length - part.len();
+                }
+            },
+            ForeignKeys::Reject => {
+                if let Some(pair) = initial.iter().flat_map(|part| part.iter()).find(|pair| owner(&pair.0) != worker) {
+                    return Err(ForeignKey { key: pair.0.clone(), owner: owner(&pair.0), worker });
+                }
+            },
+        }
+        self.initialize(initial);
+        Ok(dropped)
+    }
+}
+
+// consolidates updates to pairs, and lists each pair with positive count as often as its count.
+fn merge_pairs<Key: Ord+Clone>(pairs: &mut Vec<((Key, Key), i32)>) -> Vec<(Key, Key)> {
+    pairs.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut merged = Vec::new();
+    let mut index = 0;
+    while index < pairs.len() {
+        let start = index;
+        let mut count = 0;
+        while index < pairs.len() && pairs[index].0 == pairs[start].0 {
+            count += pairs[index].1;
+            index += 1;
+        }
+        for _ in 0 .. count { merged.push(pairs[start].0.clone()); }
+    }
+    merged
+}
+
+// proposes each of `proposals` to each record of `data` whose prefix does not already hold it.
+//
+// the records' proposals are staged as `(record, proposal)` pairs in one pass over `data`, and
+// then moved to the extensions of each record, reserved to their final length, so that records
+// of keys with many values are extended without repeated reallocation.
+fn propose_each<P, X, W, V>(data: &mut [(P, X, W)], proposals: &[(V, i32)], staged: &mut Vec<(usize, usize)>)
+    where P: Indexable<V>,
+          X: Extensions<V>,
+{
+    staged.clear();
+    for (record, entry) in data.iter().enumerate() {
+        staged.extend(proposals.iter().enumerate().filter(|x| !entry.0.find(&(x.1).0)).map(|x| (record, x.0)));
+    }
+
+    let mut cursor = 0;
+    while cursor < staged.len() {
+        let record = staged[cursor].0;
+        let end = advance_from(&staged[..], cursor, |x| x.0 == record);
+        let total = staged[cursor .. end].iter().map(|x| proposals[x.1].1 as usize).sum::<usize>();
+        let extensions = &mut data[record].1;
+        extensions.reserve_proposals(end - cursor, total);
+        for &(_, proposal) in staged[cursor .. end].iter() {
+            extensions.propose(&proposals[proposal].0, proposals[proposal].1);
+        }
+        cursor = end;
     }
 }
 
@@ -8,13 +8,14 @@ use std::hash::Hash;
 
 use timely::ExchangeData;
 use timely::dataflow::{Stream, Scope};
-use timely::dataflow::operators::Operator;
+use timely::dataflow::operators::{Concat, Filter, Map, Operator};
 use timely::dataflow::channels::pact::Exchange;
 use timely::progress::Timestamp;
 use timely::dataflow::operators::probe::Handle as ProbeHandle;
 
-use super::{Index, IndexStream};
+use super::{Index, IndexStream, read_index, write_index};
 use super::StreamPrefixIntersector;
+use super::order::{ready_entries, sort_prefixes};
 use ::Indexable;
 
 pub struct IndexIntersector<K, T, P, L, L1, H>
@@ -31,6 +32,8 @@ pub struct IndexIntersector<K, T, P, L, L1, H>
     logic1: Rc<L>,
     logic2: Rc<L1>,
     is_forward: bool,
+    // whether prefixes are kept if their edge is absent, rather than present.
+    anti: bool,
     phantom: PhantomData<P>,
 }
 
@@ -41,6 +44,14 @@ pub trait IntersectOnly<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord>
             L: Fn(&P)->K+'static,
             L1: Fn(&P)->K+'static,
             P: Indexable<K>;
+
+    /// As `intersect_using`, but keeping the prefixes whose edge from `logic1` to `logic2` is
+    /// absent from the index; see `Index::anti_intersect_only`.
+    fn anti_intersect_using<P, L, L1>(&self, logic1: L, logic2: L1) -> Rc<IndexIntersector<K, T, P, L, L1, H>>
+        where
+            L: Fn(&P)->K+'static,
+            L1: Fn(&P)->K+'static,
+            P: Indexable<K>;
 }
 
 impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IntersectOnly<K, H, T> for IndexStream<K, H, T> {
@@ -57,14 +68,34 @@ impl<K: Ord+Hash+Clone, H: Fn(K)->u64, T: Timestamp+Ord> IntersectOnly<K, H, T>
             is_forward: self.is_forward,
             logic1: Rc::new(logic1),
             logic2: Rc::new(logic2),
+            anti: false,
+            phantom: PhantomData,
+        })
+    }
+
+    fn anti_intersect_using<P, L, L1>(&self, logic1: L, logic2: L1) -> Rc<IndexIntersector<K, T, P, L, L1, H>>
+        where
+            L: Fn(&P)->K+'static,
+            L1: Fn(&P)->K+'static,
+            P: Indexable<K>,
+    {
+        Rc::new(IndexIntersector {
+            handle: self.handle.clone(),
+            index: self.index.clone(),
+            hash: self.hash.clone(),
+            is_forward: self.is_forward,
+            logic1: Rc::new(logic1),
+            logic2: Rc::new(logic2),
+            anti: true,
             phantom: PhantomData,
         })
     }
 }
 
-impl<K, G, P, L, L1, H> StreamPrefixIntersector<G> for Rc<IndexIntersector<K, G::Timestamp, P, L, L1, H>>
+impl<K, G, P, L, L1, H, W> StreamPrefixIntersector<G, W> for Rc<IndexIntersector<K, G::Timestamp, P, L, L1, H>>
     where
         K: Ord+Hash+Clone+ExchangeData,
+        W: ExchangeData,
         G: Scope,
         G::Timestamp: Timestamp+Ord+Clone,//+::std::hash::Hash+Ord,
         P: ExchangeData+Debug+Indexable<K>,
@@ -74,12 +105,13 @@ impl<K, G, P, L, L1, H> StreamPrefixIntersector<G> for Rc<IndexIntersector<K, G:
 {
     type Prefix = P;
 
-    fn intersect_only(&self, stream: Stream<G, (Self::Prefix, i32)>) -> Stream<G, (Self::Prefix, i32)>{
+    fn intersect_only(&self, stream: Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, W)>{
         let hash = self.hash.clone();
         let logic1 = self.logic1.clone();
         let logic1_2 = self.logic1.clone();
         let logic2 = self.logic2.clone();
         let is_forward = self.is_forward;
+        let anti = self.anti;
         let index = self.index.clone();
         let handle = self.handle.clone();
 
@@ -87,7 +119,7 @@ impl<K, G, P, L, L1, H> StreamPrefixIntersector<G> for Rc<IndexIntersector<K, G:
         let mut blocked = HashMap::new();
         let exch = Exchange::new(move |&(ref x,_)| (*hash)((*logic1_2)(x)));
 
-        stream.unary(exch, "Intersect_only", move |_,_| move |input, output| {
+        stream.unary_frontier(exch, "Intersect_only", move |_,_| move |input, output| {
 
             input.for_each(|time, data| {
                 data.swap(&mut buffer);
@@ -96,16 +128,110 @@ impl<K, G, P, L, L1, H> StreamPrefixIntersector<G> for Rc<IndexIntersector<K, G:
                     .extend(buffer.drain(..))
             });
 
-            for (time, data) in blocked.iter_mut() {
-
-                // ok to process if no further updates less or equal to `time`.
-                if !handle.less_equal(time.time()) {
-                    (*index).borrow_mut().intersect_only(data, &*logic1, &*logic2, is_forward, &time.time());
-                    output.session(&time).give_iterator(data.drain(..));
-                }
+            // process each stashed element once no further updates are less or equal to its time.
+            let deterministic = read_index(&index).is_deterministic();
+            for (time, data) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+                if read_index(&index).breaker().discard(data.len()) { data.clear(); continue; }
+                if deterministic { sort_prefixes(data, |x| &x.0); }
+                if anti { write_index(&index).anti_intersect_only(data, &*logic1, &*logic2, is_forward, &time.time()); }
+                else { write_index(&index).intersect_only(data, &*logic1, &*logic2, is_forward, &time.time()); }
+                output.session(&time).give_iterator(data.drain(..));
             }
 
             blocked.retain(|_, data| data.len() > 0);
         })
     }
 }
+
+
+/// Restricts prefixes to those where the edge from `logic1` to `logic2` exists, probing whichever
+/// index holds fewer values for the prefix.
+///
+/// Probing the forward index at `logic1` and the reverse index at `logic2` give the same result, but
+/// search adjacencies of different sizes. Each prefix is first routed to the owner of its `logic1`
+/// key to learn that key's forward degree, then to the owner of its `logic2` key to compare it with
+/// that key's reverse degree, and is finally intersected using the index with the smaller degree.
+pub fn intersect_adaptive<G, K, P, W, L1, L2, H1, H2>(stream: &Stream<G, (P, W)>,
+                                                     forward: &IndexStream<K, H1, G::Timestamp>,
+                                                     reverse: &IndexStream<K, H2, G::Timestamp>,
+                                                     logic1: L1, logic2: L2) -> Stream<G, (P, W)>
+    where
+        G: Scope,
+        W: ExchangeData,
+        G::Timestamp: Timestamp+Ord+Clone,
+        K: Ord+Hash+Clone+ExchangeData,
+        P: ExchangeData+Debug+Indexable<K>,
+        L1: Fn(&P)->K+'static,
+        L2: Fn(&P)->K+'static,
+        H1: Fn(K)->u64+'static,
+        H2: Fn(K)->u64+'static,
+{
+    let logic1 = Rc::new(logic1);
+    let logic2 = Rc::new(logic2);
+
+    // annotate each prefix with the forward degree of its `logic1` key.
+    let logic = logic1.clone();
+    let route = logic1.clone();
+    let hash = forward.hash.clone();
+    let index = forward.index.clone();
+    let handle = forward.handle.clone();
+    let mut buffer = Vec::new();
+    let mut blocked = HashMap::new();
+    let exch = Exchange::new(move |&(ref x,_)| (*hash)((*route)(x)));
+    let degrees = stream.unary_frontier(exch, "ForwardDegree", move |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            blocked.entry(time.retain())
+                .or_insert(Vec::new())
+                .extend(buffer.drain(..))
+        });
+        // process each stashed element once no further updates are less or equal to its time.
+        let deterministic = read_index(&index).is_deterministic();
+        for (time, data) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+            if deterministic { sort_prefixes(data, |x: &(P, W)| &x.0); }
+            let index = read_index(&index);
+            output.session(&time).give_iterator(data.drain(..).map(|(p, w)| {
+                let degree = index.degree(&(*logic)(&p));
+                (p, w, degree)
+            }));
+        }
+        blocked.retain(|_, data: &mut Vec<_>| data.len() > 0);
+    });
+
+    // compare with the reverse degree of its `logic2` key, noting whether to probe in reverse.
+    let logic = logic2.clone();
+    let route = logic2.clone();
+    let hash = reverse.hash.clone();
+    let index = reverse.index.clone();
+    let handle = reverse.handle.clone();
+    let mut buffer = Vec::new();
+    let mut blocked = HashMap::new();
+    let exch = Exchange::new(move |&(ref x,_,_)| (*hash)((*route)(x)));
+    let choices = degrees.unary_frontier(exch, "ChooseDirection", move |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            data.swap(&mut buffer);
+            blocked.entry(time.retain())
+                .or_insert(Vec::new())
+                .extend(buffer.drain(..))
+        });
+        let deterministic = read_index(&index).is_deterministic();
+        for (time, data) in ready_entries(&mut blocked, &handle, input.frontier(), deterministic) {
+            if deterministic { sort_prefixes(data, |x: &(P, W, usize)| &x.0); }
+            let index = read_index(&index);
+            output.session(&time).give_iterator(data.drain(..).map(|(p, w, degree): (P, W, usize)| {
+                let use_reverse = index.degree(&(*logic)(&p)) < degree;
+                (p, w, use_reverse)
+            }));
+        }
+        blocked.retain(|_, data: &mut Vec<_>| data.len() > 0);
+    });
+
+    let (l1, l2) = (logic1.clone(), logic2.clone());
+    let forwards = forward.intersect_using(move |x: &P| (*l1)(x), move |x: &P| (*l2)(x))
+        .intersect_only(choices.filter(|x| !x.2).map(|(p, w, _)| (p, w)));
+    let (l1, l2) = (logic1.clone(), logic2.clone());
+    let reverses = reverse.intersect_using(move |x: &P| (*l2)(x), move |x: &P| (*l1)(x))
+        .intersect_only(choices.filter(|x| x.2).map(|(p, w, _)| (p, w)));
+
+    forwards.concat(&reverses)
+}
@@ -0,0 +1,147 @@
+//! Kernels counting the occurrences of proposed values in a sorted list of values.
+//!
+//! Intersection checks each prefix's sorted proposals against the sorted values of one key of an
+//! index. How best to do that depends on the lengths of the two lists: a linear merge is best
+//! when they are similar, galloping when one is much longer, and binary searching each proposal
+//! when there are very few proposals. When many prefixes share a key whose values are long, a
+//! hash table of the values built once for the key answers each prefix's proposals in time
+//! proportional to their number alone.
+//!
+//! `IntersectKernel::Adaptive` chooses among these for each prefix from the lengths involved, and
+//! the other variants force one kernel, for comparing them (as `examples/intersect_kernels.rs`
+//! does on skewed data).
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use search::{equal_range, partition_point_from};
+
+/// A strategy for counting the occurrences of proposals in a list of values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntersectKernel {
+    /// Choose a kernel for each list of proposals, from its length and that of the values.
+    Adaptive,
+    /// Walk both lists linearly.
+    Merge,
+    /// Gallop through whichever list is behind.
+    Gallop,
+    /// Binary search the values for each proposal.
+    Search,
+    /// Probe a hash table of the values, built once for all proposals against them.
+    Hash,
+}
+
+impl Default for IntersectKernel {
+    fn default() -> Self { IntersectKernel::Adaptive }
+}
+
+impl IntersectKernel {
+    /// The kernel to use for `proposals` proposals against `values` values, where `shared`
+    /// proposals in all will be checked against the same values.
+    ///
+    /// Forced kernels are returned as they are.
+    pub fn choose(&self, proposals: usize, values: usize, shared: usize) -> IntersectKernel {
+        match *self {
+            IntersectKernel::Adaptive => {
+                if proposals == 0 || values == 0 { IntersectKernel::Merge }
+                else if 16 * proposals <= values {
+                    // a table pays for itself once the shared proposals outnumber the values.
+                    if shared >= values { IntersectKernel::Hash } else { IntersectKernel::Search }
+                }
+                else if proposals <= 4 * values && values <= 4 * proposals { IntersectKernel::Merge }
+                else { IntersectKernel::Gallop }
+            },
+            kernel => kernel,
+        }
+    }
+}
+
+/// The number of times each kernel was used, as chosen for each list of proposals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KernelStats {
+    pub merge: usize,
+    pub gallop: usize,
+    pub search: usize,
+    pub hash: usize,
+    /// The number of hash tables built.
+    pub tables: usize,
+}
+
+impl KernelStats {
+    /// Counts a use of `kernel`.
+    pub fn record(&mut self, kernel: IntersectKernel) {
+        match kernel {
+            IntersectKernel::Merge => self.merge += 1,
+            IntersectKernel::Gallop => self.gallop += 1,
+            IntersectKernel::Search => self.search += 1,
+            IntersectKernel::Hash => self.hash += 1,
+            IntersectKernel::Adaptive => { },
+        }
+    }
+}
+
+/// The multiplicities of values, as probed by the `Hash` kernel.
+pub type Table<V> = HashMap<V, i32>;
+
+/// Builds the table of the multiplicities of `values`.
+pub fn tabulate<V: Hash+Eq+Clone>(values: &[V]) -> Table<V> {
+    let mut table = HashMap::with_capacity(values.len());
+    for value in values.iter() {
+        *table.entry(value.clone()).or_insert(0) += 1;
+    }
+    table
+}
+
+/// Adds the multiplicity in `table` of each proposal to its count.
+pub fn probe<V: Hash+Eq>(table: &Table<V>, proposals: &[V], counts: &mut [i32]) {
+    for (proposal, count) in proposals.iter().zip(counts.iter_mut()) {
+        if let Some(&multiplicity) = table.get(proposal) { *count += multiplicity; }
+    }
+}
+
+/// Adds the number of occurrences in `values` of each proposal to its count, using `kernel`.
+///
+/// Both lists must be sorted. The `Hash` kernel builds a table of `values` for this call alone;
+/// callers checking many lists against the same values should build one with `tabulate`.
+pub fn count_occurrences<V: Ord+Hash+Clone>(kernel: IntersectKernel, proposals: &[V], values: &[V], counts: &mut [i32]) {
+    match kernel.choose(proposals.len(), values.len(), proposals.len()) {
+        IntersectKernel::Merge => merge(proposals, values, counts),
+        IntersectKernel::Search => search(proposals, values, counts),
+        IntersectKernel::Hash => probe(&tabulate(values), proposals, counts),
+        _ => gallop(proposals, values, counts),
+    }
+}
+
+fn merge<V: Ord>(proposals: &[V], values: &[V], counts: &mut [i32]) {
+    let mut cursor = 0;
+    for (proposal, count) in proposals.iter().zip(counts.iter_mut()) {
+        while cursor < values.len() && &values[cursor] < proposal { cursor += 1; }
+        let mut matched = cursor;
+        while matched < values.len() && &values[matched] == proposal {
+            *count += 1;
+            matched += 1;
+        }
+    }
+}
+
+fn gallop<V: Ord>(proposals: &[V], values: &[V], counts: &mut [i32]) {
+    let mut cursor = 0;
+    for (proposal, count) in proposals.iter().zip(counts.iter_mut()) {
+        let matched = equal_range(values, cursor, proposal);
+        cursor = matched.start;
+        *count += matched.len() as i32;
+    }
+}
+
+fn search<V: Ord>(proposals: &[V], values: &[V], counts: &mut [i32]) {
+    let mut lower = 0;
+    for (proposal, count) in proposals.iter().zip(counts.iter_mut()) {
+        // the first position at or after `lower` holding a value not less than `proposal`.
+        lower = partition_point_from(values, lower, |x| x < proposal);
+        let mut matched = lower;
+        while values.get(matched) == Some(proposal) {
+            *count += 1;
+            matched += 1;
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Choosing, for each time, between eager and chunked materialization of proposals.
+//!
+//! Proposing extensions materializes the candidates of each prefix from the index. When a time has
+//! few prefixes, or their keys have few values, materializing every candidate at once is fastest.
+//! When updates touch vertices of high degree, the candidates of a time can be far too many to
+//! hold, and are better produced in chunks: each invocation of a propose operator materializes
+//! about a chunk of candidates and leaves the rest for later, so that downstream operators
+//! intersect and count each chunk before the next is produced, and a `Breaker` can trip between
+//! chunks rather than after the whole time.
+//!
+//! Propose operators consult a `Materializer` once the prefixes of a time are ready. Under the
+//! adaptive policy, they bound the candidates of the time's prefixes by the degrees of the keys
+//! the prefixes probe, as count operators do, and materialize eagerly if the bound is at most the
+//! policy's threshold. Times are chunked regardless while a breaker is armed, as enumeration under
+//! a time budget should stop promptly once it is exhausted.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// How propose operators materialize the candidates of a time's prefixes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Materialization {
+    /// Propose every candidate of a time's prefixes at once.
+    Eager,
+    /// Propose about this many candidates in each invocation of an operator.
+    Chunked(usize),
+    /// Propose eagerly if a time's prefixes have at most `threshold` candidates, and in chunks
+    /// of about `chunk` candidates otherwise.
+    Adaptive {
+        /// The bound on a time's candidates up to which they are proposed eagerly.
+        threshold: usize,
+        /// The number of candidates proposed in each invocation otherwise.
+        chunk: usize,
+    },
+}
+
+impl Default for Materialization {
+    fn default() -> Self { Materialization::Adaptive { threshold: 1 << 20, chunk: 1 << 16 } }
+}
+
+/// A shared policy for materializing proposals, with counts of the choices made under it.
+#[derive(Clone, Default)]
+pub struct Materializer {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    policy: Materialization,
+    eager: usize,
+    chunked: usize,
+}
+
+impl Materializer {
+    /// Allocates a materializer with the default adaptive policy.
+    pub fn new() -> Self { Materializer::default() }
+
+    /// Sets the policy for times whose prefixes become ready from now on.
+    pub fn set_policy(&self, policy: Materialization) {
+        match policy {
+            Materialization::Chunked(0) | Materialization::Adaptive { chunk: 0, .. } => panic!("chunks must hold at least one candidate"),
+            _ => { },
+        }
+        self.state.borrow_mut().policy = policy;
+    }
+
+    /// The policy for times whose prefixes become ready from now on.
+    pub fn policy(&self) -> Materialization {
+        self.state.borrow().policy
+    }
+
+    /// Chooses how to materialize the candidates of a time's prefixes, returning the number of
+    /// candidates to propose in each invocation, or `None` to propose them all at once.
+    ///
+    /// `bound` bounds the candidates of the time's prefixes, and is only called if the policy
+    /// is adaptive and `armed`, whether a breaker is armed, is false.
+    pub fn choose<F: FnOnce()->usize>(&self, armed: bool, bound: F) -> Option<usize> {
+        let mut state = self.state.borrow_mut();
+        let choice = match state.policy {
+            Materialization::Eager => None,
+            Materialization::Chunked(chunk) => Some(chunk),
+            Materialization::Adaptive { threshold, chunk } => {
+                if armed || bound() > threshold { Some(chunk) } else { None }
+            },
+        };
+        if choice.is_some() { state.chunked += 1; } else { state.eager += 1; }
+        choice
+    }
+
+    /// The numbers of times materialized eagerly and in chunks since the last call, resetting them.
+    ///
+    /// Each operator choosing for a time counts once.
+    pub fn take_choices(&self) -> (usize, usize) {
+        let mut state = self.state.borrow_mut();
+        let choices = (state.eager, state.chunked);
+        state.eager = 0;
+        state.chunked = 0;
+        choices
+    }
+}
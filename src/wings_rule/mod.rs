@@ -5,15 +5,36 @@ use timely::Data;
 mod index;
 mod extender;
 mod intersector;
-
-pub use self::index::Index;
+mod breaker;
+mod materialize;
+mod combinators;
+mod kernel;
+mod registry;
+mod order;
+
+pub use self::index::{Index, Extensions, read_index, write_index, MergePolicy, DefaultMergePolicy, MergeStats, Maintenance, ReadStats, ForeignKeys, ForeignKey};
 pub use self::extender::IndexStream;
-pub use self::intersector::IntersectOnly;
+pub use self::intersector::{IntersectOnly, intersect_adaptive};
+pub use self::breaker::Breaker;
+pub use self::materialize::{Materialization, Materializer};
+pub use self::combinators::{ExtenderCombinators, Filtered, Mapped};
+pub use self::kernel::{IntersectKernel, KernelStats, count_occurrences};
+pub use self::registry::IndexRegistry;
+pub use search::advance;
 //use ::Indexable;
 
 /// Functionality used by GenericJoin to extend prefixes with new attributes.
 ///
 /// These methods are used in `GenericJoin`'s `extend` method, and may not be broadly useful elsewhere.
+///
+/// Extenders may read any relation, not only an index (see `wings_plan::sources`), but generic
+/// join is worst-case optimal only if each keeps to the following contract. `count` lowers the
+/// count of a prefix to its own, and sets the index to `ident`, only when its own is smaller, and
+/// its own is the number of extensions it would propose, or exceeds it by at most a constant
+/// factor. `propose` proposes extensions in increasing order without repeats, in time proportional
+/// to their number. `intersect` keeps the proposals it would itself propose, in time proportional
+/// to the number of proposals, up to a logarithmic factor, and not to the number of extensions it
+/// holds. Prefixes an extender would not extend may be dropped by any of the three.
 pub trait StreamPrefixExtender<G: Scope, W: Data> {
     /// The type of data to extend.
     type Prefix: Data;
@@ -25,6 +46,10 @@ pub trait StreamPrefixExtender<G: Scope, W: Data> {
     fn propose(&self, Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>;
     /// Restricts proposals by those this relation would propose.
     fn intersect(&self, Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>) -> Stream<G, (Self::Prefix, Vec<Self::Extension>, W)>;
+    /// Proposes each extension from this relation once, with its multiplicity.
+    fn propose_counted(&self, Stream<G, (Self::Prefix, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>;
+    /// Restricts proposals with multiplicities by those this relation would propose.
+    fn intersect_counted(&self, Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>) -> Stream<G, (Self::Prefix, Vec<(Self::Extension, i32)>, W)>;
 }
 
 /// Extension method for generic join functionality.
@@ -32,21 +57,40 @@ pub trait GenericJoin<G:Scope, P:Data, W: Data> {
     /// Extends a stream of prefixes using the supplied prefix extenders.
     fn extend<'a, E: Data>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>)
                            -> Stream<G, (P, Vec<E>, W)>;
+    /// As `extend`, but reporting each extension once with its multiplicity.
+    ///
+    /// Multiplicities are those of the proposing relation; `expand_counted` converts the
+    /// result to the form `extend` produces.
+    fn extend_counted<'a, E: Data>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>)
+                                   -> Stream<G, (P, Vec<(E, i32)>, W)>;
+}
+
+/// Extension method for generic join with a fixed proposer.
+///
+/// Rather than counting the candidates each extender would propose and letting the smallest
+/// propose, the first extender always proposes, and the proposals are restricted by the remaining
+/// extenders. This skips the count and partition steps, which dominate when the first relation is
+/// known to be highly selective, and costs more than `extend` when it is not. Extenders apply the
+/// same time validation as in `extend`, so the result is the same.
+pub trait ProposeFirst<G:Scope, P:Data, W: Data> {
+    /// Extends a stream of prefixes with the proposals of the first extender that the rest would propose.
+    fn propose_first<'a, E: Data>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>)
+                                  -> Stream<G, (P, Vec<E>, W)>;
 }
 
-pub trait StreamPrefixIntersector<G:Scope>{
+pub trait StreamPrefixIntersector<G:Scope, W: Data>{
     type Prefix: Data;
 
-    fn intersect_only(&self,Stream<G, (Self::Prefix, i32)>) -> Stream<G,(Self::Prefix, i32)>;
+    fn intersect_only(&self,Stream<G, (Self::Prefix, W)>) -> Stream<G,(Self::Prefix, W)>;
 }
 
-pub trait Intersection<G:Scope, P:Data>{
-    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P>+'a>>)
-                          -> Stream<G, (P, i32)>;
+pub trait Intersection<G:Scope, P:Data, W: Data>{
+    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, W, Prefix=P>+'a>>)
+                          -> Stream<G, (P, W)>;
 }
 
-impl<G: Scope, P:Data> Intersection<G, P> for Stream<G, (P, i32)> {
-    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, Prefix=P> + 'a>>) -> Stream<G, (P, i32)>
+impl<G: Scope, P:Data, W: Data> Intersection<G, P, W> for Stream<G, (P, W)> {
+    fn intersect_only<'a>(&self, intersectors: Vec<Box<StreamPrefixIntersector<G, W, Prefix=P> + 'a>>) -> Stream<G, (P, W)>
     {
 
         let mut results = self.clone();
@@ -86,39 +130,57 @@ impl<G: Scope, P:Data, W: Data> GenericJoin<G, P, W> for Stream<G, (P, W)> {
             self.scope().concatenate(results).map(|(p,es,w)| (p,es,w))
         }
     }
-}
 
-/// Reports the number of elements satisfing the predicate.
-///
-/// This methods *relies strongly* on the assumption that the predicate
-/// stays false once it becomes false, a joint property of the predicate
-/// and the slice. This allows `advance` to use exponential search to
-/// count the number of elements in time logarithmic in the result.
-// #[inline(never)]
-pub fn advance<T, F: Fn(&T)->bool>(slice: &[T], function: F) -> usize {
-
-    // start with no advance
-    let mut index = 0;
-    if index < slice.len() && function(&slice[index]) {
-
-        // advance in exponentially growing steps.
-        let mut step = 1;
-        while index + step < slice.len() && function(&slice[index + step]) {
-            index += step;
-            step = step << 1;
+    fn extend_counted<'a, E>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>) -> Stream<G, (P, Vec<(E, i32)>, W)>
+        where E: Data {
+
+        if extenders.len() == 1 {
+            extenders[0].propose_counted(self.clone())
         }
+        else {
+            let mut counts = self.map(|(p,s)| (p, 1 << 31, 0, s));
+            for (index,extender) in extenders.iter().enumerate() {
+                counts = extender.count(counts, index as u64);
+            }
+
+            let parts = counts.partition(extenders.len() as u64, |(p, _, i, w)| (i, (p, w)));
 
-        // advance in exponentially shrinking steps.
-        step = step >> 1;
-        while step > 0 {
-            if index + step < slice.len() && function(&slice[index + step]) {
-                index += step;
+            let mut results = Vec::new();
+            for (index, nominations) in parts.into_iter().enumerate() {
+                let mut extensions = extenders[index].propose_counted(nominations);
+                for other in (0..extenders.len()).filter(|&x| x != index) {
+                    extensions = extenders[other].intersect_counted(extensions);
+                }
+
+                results.push(extensions);    // save extensions
             }
-            step = step >> 1;
-        }
 
-        index += 1;
+            self.scope().concatenate(results)
+        }
     }
+}
 
-    index
+/// Converts extensions with multiplicities to extensions repeated according to their multiplicity.
+///
+/// This lets consumers of `extend` read the output of `extend_counted`.
+pub fn expand_counted<G: Scope, P: Data, E: Data, W: Data>(stream: &Stream<G, (P, Vec<(E, i32)>, W)>) -> Stream<G, (P, Vec<E>, W)> {
+    stream.map(|(p, es, w)| {
+        let mut expanded = Vec::with_capacity(es.iter().map(|x| x.1 as usize).sum());
+        for (e, count) in es {
+            for _ in 0 .. count { expanded.push(e.clone()); }
+        }
+        (p, expanded, w)
+    })
+}
+
+impl<G: Scope, P:Data, W: Data> ProposeFirst<G, P, W> for Stream<G, (P, W)> {
+    fn propose_first<'a, E>(&self, extenders: Vec<Box<StreamPrefixExtender<G, W, Prefix=P, Extension=E>+'a>>) -> Stream<G, (P, Vec<E>, W)>
+        where E: Data {
+
+        let mut extensions = extenders[0].propose(self.clone());
+        for extender in extenders.iter().skip(1) {
+            extensions = extender.intersect(extensions);
+        }
+        extensions
+    }
 }
\ No newline at end of file
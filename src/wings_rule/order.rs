@@ -0,0 +1,46 @@
+//! The order in which operators reading an index process the prefixes they hold.
+//!
+//! Operators reading an index hold the prefixes of each time until the index has absorbed the
+//! updates of that time, and then extend them. By default they extend whatever prefixes have
+//! arrived, in the order they arrived, and visit their times in the order of a hash map: both
+//! depend on how the workers sending the prefixes were scheduled, so that two runs over the same
+//! input produce the same changes in different orders. This makes traces of the two runs hard to
+//! compare when chasing a discrepancy between them.
+//!
+//! Indices set to process deterministically (see `Index::set_deterministic`) have their operators
+//! also wait for their input to pass a time, so that every prefix of the time has arrived, sort
+//! the prefixes by their vertices, and process their times in order. Prefixes are routed to
+//! workers by key alone, so each worker then processes the same prefixes in the same order in
+//! every run, but for the order among prefixes binding the same vertices.
+
+use std::collections::HashMap;
+
+use timely::dataflow::operators::Capability;
+use timely::dataflow::operators::probe::Handle as ProbeHandle;
+use timely::progress::Timestamp;
+use timely::progress::frontier::MutableAntichain;
+
+use ::Indexable;
+
+/// The entries of `blocked` ready to process, in order of time.
+///
+/// An entry is ready once the index `handle` observes has absorbed the updates of its time, and,
+/// if `deterministic`, once the operator's `input` has passed its time too.
+pub fn ready_entries<'a, T: Timestamp+Ord, V>(blocked: &'a mut HashMap<Capability<T>, V>, handle: &ProbeHandle<T>, input: &MutableAntichain<T>, deterministic: bool) -> Vec<(&'a Capability<T>, &'a mut V)> {
+    let mut ready = blocked.iter_mut()
+        .filter(|&(ref time, _)| !handle.less_equal(time.time()) && !(deterministic && input.less_equal(time.time())))
+        .collect::<Vec<_>>();
+    ready.sort_by(|x, y| x.0.time().cmp(y.0.time()));
+    ready
+}
+
+/// Sorts `data` by the vertices of the prefix `prefix` reads from each entry.
+///
+/// The sort is stable, so entries with the same prefix keep the order they arrived in.
+pub fn sort_prefixes<D, P, K, F>(data: &mut Vec<D>, prefix: F)
+    where P: Indexable<K>, K: Ord, F: Fn(&D)->&P {
+    data.sort_by(|x, y| {
+        let (x, y) = (prefix(x), prefix(y));
+        (0 .. x.length()).map(|i| x.index(i)).cmp((0 .. y.length()).map(|i| y.index(i)))
+    });
+}
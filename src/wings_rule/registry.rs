@@ -0,0 +1,122 @@
+//! Maintaining every index of a dataflow together.
+//!
+//! Each index holds the updates it receives uncommitted until a driver merges them, with
+//! `Index::merge_to` or `Index::merge_before`, once no read will distinguish their times again.
+//! A dataflow reading several relations holds several indices, and an index a driver forgets to
+//! merge keeps its updates uncommitted: its reads scan more of them at every time, and its
+//! snapshots, which read only committed updates, miss them. An `IndexRegistry` collects the
+//! indices of a dataflow as they are built, so that a driver merges all of them with one call,
+//! and can ask which of them it has left behind.
+//!
+//! Registries are shared: clones refer to the same indices, so that a clone moved into the closure
+//! building a dataflow registers the indices the driver's copy merges. Like the indices, a registry
+//! belongs to the worker that built them.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use timely::progress::Timestamp;
+use timely::dataflow::operators::probe::Handle as ProbeHandle;
+
+use super::{Index, IndexStream, MergePolicy, read_index, write_index};
+
+/// The indices of a dataflow, by name, merged together.
+pub struct IndexRegistry<K: Ord+Hash, T> {
+    indices: Rc<RefCell<Vec<(String, Rc<RefCell<Index<K, T>>>)>>>,
+    policy: Rc<RefCell<Option<Rc<Fn(&mut Index<K, T>)>>>>,
+}
+
+impl<K: Ord+Hash, T> Clone for IndexRegistry<K, T> {
+    fn clone(&self) -> Self {
+        IndexRegistry { indices: self.indices.clone(), policy: self.policy.clone() }
+    }
+}
+
+impl<K: Ord+Hash+Clone+'static, T: Ord+Clone+'static> Default for IndexRegistry<K, T> {
+    fn default() -> Self { IndexRegistry::new() }
+}
+
+impl<K: Ord+Hash+Clone+'static, T: Ord+Clone+'static> IndexRegistry<K, T> {
+    /// Allocates a registry holding no indices.
+    pub fn new() -> Self {
+        IndexRegistry { indices: Rc::new(RefCell::new(Vec::new())), policy: Rc::new(RefCell::new(None)) }
+    }
+
+    /// Registers the index of `stream` under `name`.
+    pub fn register<H: Fn(K)->u64>(&self, name: &str, stream: &IndexStream<K, H, T>) where T: Timestamp {
+        self.register_index(name, stream.index.clone());
+    }
+
+    /// Registers `index` under `name`.
+    ///
+    /// Names need not be distinct, but are how `lagging` reports indices. The index adopts the
+    /// merge policy of the registry, if one was set.
+    pub fn register_index(&self, name: &str, index: Rc<RefCell<Index<K, T>>>) {
+        if let Some(ref policy) = *self.policy.borrow() { (**policy)(&mut write_index(&index)); }
+        self.indices.borrow_mut().push((name.to_owned(), index));
+    }
+
+    /// The number of indices registered.
+    pub fn len(&self) -> usize {
+        self.indices.borrow().len()
+    }
+
+    /// True if no index is registered.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The names of the indices registered, in the order they were registered.
+    pub fn names(&self) -> Vec<String> {
+        self.indices.borrow().iter().map(|&(ref name, _)| name.clone()).collect()
+    }
+
+    /// Merges every index up to and including `time`; see `Index::merge_to`.
+    pub fn merge_all_to(&self, time: &T) {
+        for &(_, ref index) in self.indices.borrow().iter() { write_index(index).merge_to(time); }
+    }
+
+    /// Merges every index at times strictly before `frontier`; see `Index::merge_before`.
+    pub fn merge_all_before(&self, frontier: &T) {
+        for &(_, ref index) in self.indices.borrow().iter() { write_index(index).merge_before(frontier); }
+    }
+
+    /// Merges every index at the times `probe` has passed, returning true if its frontier is empty
+    /// and every update was merged.
+    ///
+    /// `probe` should observe the outputs of the dataflow, not the maintenance of the indices: an
+    /// index may only be merged at a time once every read at that time has completed, which the
+    /// indices' own probes do not tell. Calling this after each step of the worker keeps every
+    /// index merged as far as it can be, without the driver tracking times.
+    pub fn merge_completed(&self, probe: &ProbeHandle<T>) -> bool where T: Timestamp {
+        // the least time of the frontier, under the total order, is at most every time not yet passed.
+        match probe.with_frontier(|frontier| frontier.iter().min().cloned()) {
+            Some(frontier) => { self.merge_all_before(&frontier); false }
+            None => {
+                for &(_, ref index) in self.indices.borrow().iter() { write_index(index).merge_all(); }
+                true
+            }
+        }
+    }
+
+    /// The names of the indices holding uncommitted updates at or before `time`, which a driver
+    /// that believes it has merged to `time` has left behind.
+    pub fn lagging(&self, time: &T) -> Vec<String> {
+        self.indices.borrow().iter()
+            .filter(|&&(_, ref index)| read_index(index).oldest_uncommitted().map(|oldest| oldest <= time).unwrap_or(false))
+            .map(|&(ref name, _)| name.clone())
+            .collect()
+    }
+
+    /// The number of updates each index holds uncommitted, by name.
+    pub fn uncommitted(&self) -> Vec<(String, usize)> {
+        self.indices.borrow().iter().map(|&(ref name, ref index)| (name.clone(), read_index(index).uncommitted())).collect()
+    }
+
+    /// Sets the policy deciding when committed updates are merged, in every index registered and
+    /// every index registered later.
+    pub fn set_merge_policy<P: MergePolicy+Clone+'static>(&self, policy: P) {
+        let apply: Rc<Fn(&mut Index<K, T>)> = Rc::new(move |index: &mut Index<K, T>| index.set_merge_policy(policy.clone()));
+        for &(_, ref index) in self.indices.borrow().iter() { (*apply)(&mut write_index(index)); }
+        *self.policy.borrow_mut() = Some(apply);
+    }
+}
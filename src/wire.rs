@@ -0,0 +1,93 @@
+//! The encoding of data exchanged between workers, and checks that peers agree on it.
+//!
+//! Exchanged data are tuples of vertices, vectors of vertices, and diffs, which timely 0.7 encodes
+//! with abomonation. The encoding does not describe itself, so processes built from different
+//! versions of the crate would silently misinterpret each other's data. Each process therefore
+//! carries a wire version, bumped whenever the layout of exchanged data changes, and `check_peers`
+//! has every worker exchange its version and encoding before any other data, failing with a clear
+//! error if any two workers disagree.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+use timely::dataflow::ProbeHandle;
+use timely::dataflow::operators::{Input, Exchange, Inspect, Probe};
+
+/// The version of the layout of exchanged data.
+pub const WIRE_VERSION: u32 = 1;
+
+/// An encoding of exchanged data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Abomonation, which timely 0.7 requires of all exchanged data.
+    Abomonation,
+}
+
+impl Encoding {
+    /// The encoding this crate was built with.
+    pub fn current() -> Encoding { Encoding::Abomonation }
+
+    fn tag(&self) -> u32 {
+        match *self {
+            Encoding::Abomonation => 0x41424F4D,
+        }
+    }
+
+    fn from_tag(tag: u32) -> Option<Encoding> {
+        match tag {
+            0x41424F4D => Some(Encoding::Abomonation),
+            _ => None,
+        }
+    }
+}
+
+/// Exchanges the wire version and encoding of every worker, and checks that they all agree.
+///
+/// Every worker must call this method, before building any other dataflow. It returns an error
+/// naming a worker whose version or encoding differs from this worker's.
+pub fn check_peers<A: Allocate>(worker: &mut Worker<A>) -> Result<(), String> {
+
+    let index = worker.index();
+    let peers = worker.peers();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let received2 = received.clone();
+
+    let mut probe = ProbeHandle::new();
+    let mut input = worker.dataflow::<u32,_,_>(|scope| {
+        let (input, stream) = scope.new_input::<(usize, usize, u32, u32)>();
+        stream
+            .exchange(|x| x.0 as u64)
+            .inspect(move |x| received2.borrow_mut().push((x.1, x.2, x.3)))
+            .probe_with(&mut probe);
+        input
+    });
+
+    // (recipient, sender, version, encoding tag), sent to each peer.
+    for peer in 0 .. peers {
+        input.send((peer, index, WIRE_VERSION, Encoding::current().tag()));
+    }
+    input.close();
+    worker.step_while(|| !probe.done());
+
+    let mut received = received.borrow_mut();
+    received.sort();
+    if received.len() != peers {
+        return Err(format!("worker {} heard from {} of {} workers; peers may use a different encoding", index, received.len(), peers));
+    }
+    for &(sender, version, tag) in received.iter() {
+        match Encoding::from_tag(tag) {
+            Some(encoding) if encoding == Encoding::current() && version == WIRE_VERSION => { },
+            Some(encoding) => {
+                return Err(format!("worker {} uses wire version {} with {:?} encoding, but worker {} uses wire version {} with {:?} encoding",
+                                   sender, version, encoding, index, WIRE_VERSION, Encoding::current()));
+            },
+            None => {
+                return Err(format!("worker {} sent an unrecognized encoding; it may use a different encoding than worker {} ({:?})",
+                                   sender, index, Encoding::current()));
+            },
+        }
+    }
+    Ok(())
+}
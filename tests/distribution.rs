@@ -0,0 +1,208 @@
+//! Checks that the results of a plan do not depend on the number of workers executing it.
+//!
+//! Edges are routed to workers by key, and each new match is attributed to one of the updates
+//! that created it by comparing update positions, so bugs in routing or tie-breaking tend to
+//! surface as results that change with the number of workers. Each test runs the same plan and
+//! updates with 1, 2, and 4 workers, and compares the changes in matches of every epoch.
+//!
+//! Cluster-wide totals are also checked across processes, each running its workers in its own
+//! timely instance and exchanging data with the others over TCP on localhost.
+
+extern crate alg3_dynamic;
+extern crate timely;
+
+use std::collections::HashMap;
+use std::thread;
+
+use alg3_dynamic::Edge;
+use alg3_dynamic::io::rmat;
+use alg3_dynamic::runtime::{run_distributed, BatchStats, Engine};
+use alg3_dynamic::wings_plan::{GlobalCounts, PrefixEncoding};
+use alg3_dynamic::wings_plan::packed::PackedPrefix;
+use alg3_dynamic::wings_plan::plan::{read_plan_from, Plan};
+
+static TRIANGLE_PLAN: &'static str = include_str!("../triangle_plan.txt");
+
+fn triangles() -> Plan {
+    read_plan_from(TRIANGLE_PLAN.as_bytes())
+}
+
+// a base graph, batches of insertions, and a batch deleting some of the inserted edges.
+fn workload(seed: u64) -> (Vec<Edge>, Vec<Vec<(Edge, i32)>>) {
+    let edges = rmat(7, 1200, seed);
+    let base = edges[.. 600].to_vec();
+    let mut batches = edges[600 ..].chunks(150).map(|chunk| chunk.iter().map(|&edge| (edge, 1)).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let deletions = edges[600 ..].iter().step_by(3).map(|&edge| (edge, -1)).collect();
+    batches.push(deletions);
+    (base, batches)
+}
+
+fn assert_invariant(runs: &[(usize, Vec<BatchStats>)]) {
+    let (first, ref expected) = runs[0];
+    for &(workers, ref results) in runs[1 ..].iter() {
+        assert_eq!(expected.len(), results.len());
+        for (epoch, (x, y)) in expected.iter().zip(results.iter()).enumerate() {
+            assert_eq!(x.matches, y.matches, "match counts of epoch {} differ between {} and {} workers", epoch, first, workers);
+            assert_eq!(x.embeddings, y.embeddings, "matches of epoch {} differ between {} and {} workers", epoch, first, workers);
+        }
+    }
+}
+
+#[test]
+fn triangles_are_distribution_invariant() {
+    let (base, batches) = workload(0);
+    let runs = [1, 2, 4].iter().map(|&workers| {
+        (workers, run_distributed(workers, triangles, base.clone(), batches.clone()))
+    }).collect::<Vec<_>>();
+    assert!(runs[0].1.iter().any(|stats| !stats.embeddings.is_empty()), "workload produces no matches");
+    assert_invariant(&runs);
+}
+
+#[test]
+fn simultaneous_updates_are_distribution_invariant() {
+    // a single batch creating many triangles at once exercises the attribution of each match to
+    // exactly one of the updates that created it.
+    let edges = rmat(6, 800, 1);
+    let batches = vec![edges.iter().map(|&edge| (edge, 1)).collect(), edges.iter().map(|&edge| (edge, -1)).collect()];
+    let runs = [1, 2, 4].iter().map(|&workers| {
+        (workers, run_distributed(workers, triangles, Vec::new(), batches.clone()))
+    }).collect::<Vec<_>>();
+    assert_invariant(&runs);
+}
+
+#[test]
+fn packed_prefixes_are_distribution_invariant() {
+    let (base, batches) = workload(3);
+    let packed = || { let mut plan = triangles(); plan.set_prefix_encoding(PrefixEncoding::Packed); plan };
+    let mut runs = vec![(1, run_distributed(1, triangles, base.clone(), batches.clone()))];
+    runs.extend([1, 2, 4].iter().map(|&workers| (workers, run_distributed(workers, packed, base.clone(), batches.clone()))));
+    assert!(runs[0].1.iter().any(|stats| !stats.embeddings.is_empty()), "workload produces no matches");
+    assert_invariant(&runs);
+
+    // prefixes widen as larger vertices are pushed, and read back as they were packed.
+    let mut prefix = PackedPrefix::pack(&[3, 1]);
+    assert_eq!(prefix.width(), 2);
+    prefix.push(1 << 20);
+    prefix.push(0);
+    assert_eq!(prefix.unpack(), vec![3, 1, 1 << 20, 0]);
+    assert_eq!((prefix.width(), prefix.packed_bytes()), (21, 16));
+}
+
+// the totals of matches after each epoch, accumulated from the changes a run reports.
+fn running_totals(results: &[BatchStats]) -> Vec<Vec<(usize, i64)>> {
+    let mut totals = HashMap::new();
+    results.iter().map(|stats| {
+        for &(query, diff) in stats.matches.iter() { *totals.entry(query).or_insert(0) += diff; }
+        let mut current = totals.iter().filter(|x| *x.1 != 0).map(|(&query, &total)| (query, total)).collect::<Vec<_>>();
+        current.sort();
+        current
+    }).collect()
+}
+
+#[test]
+fn gathered_totals_are_distribution_invariant() {
+    let (base, batches) = workload(2);
+    let expected = running_totals(&run_distributed(1, triangles, base.clone(), batches.clone()));
+    assert!(expected.iter().any(|totals| !totals.is_empty()), "workload produces no matches");
+    for &global in [GlobalCounts::First, GlobalCounts::Replicated].iter() {
+        for &workers in [1, 2, 4].iter() {
+            let plan = move || { let mut plan = triangles(); plan.set_global_counts(global); plan };
+            let results = run_distributed(workers, plan, base.clone(), batches.clone());
+            let totals = results.into_iter().map(|stats| stats.totals).collect::<Vec<_>>();
+            assert_eq!(totals, expected, "totals gathered at {:?} differ with {} workers", global, workers);
+        }
+    }
+}
+
+#[test]
+fn totals_are_gathered_across_processes() {
+    let (processes, threads) = (2, 2);
+    let (base, batches) = workload(3);
+    let expected = running_totals(&run_distributed(1, triangles, base.clone(), batches.clone()));
+    let addresses = (0 .. processes).map(|process| format!("localhost:{}", 2101 + process)).collect::<Vec<_>>();
+
+    let handles = (0 .. processes).map(|process| {
+        let (base, batches, addresses) = (base.clone(), batches.clone(), addresses.clone());
+        thread::spawn(move || {
+            let configuration = timely::Configuration::Cluster(threads, process, addresses, false, Box::new(|_| None));
+            let guards = timely::execute(configuration, move |worker| {
+                let mut plan = triangles();
+                plan.set_global_counts(GlobalCounts::Replicated);
+                let (index, peers) = (worker.index(), worker.peers());
+                let mut engine: Engine<_> = Engine::new(worker, &plan);
+                let part = base.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect();
+                let mut totals = vec![engine.load_base(part).totals];
+                for batch in batches.iter() {
+                    let part = batch.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect();
+                    totals.push(engine.step(part).totals);
+                }
+                engine.finish();
+                totals
+            }).expect("failed to start process");
+            guards.join().into_iter().map(|result| result.expect("worker failed")).collect::<Vec<_>>()
+        })
+    }).collect::<Vec<_>>();
+
+    // every worker of every process reports the totals of the whole cluster.
+    for (process, handle) in handles.into_iter().enumerate() {
+        for totals in handle.join().expect("process failed") {
+            assert_eq!(totals, expected, "totals reported in process {} differ", process);
+        }
+    }
+}
+
+// writes `edges` in graph_map's layout, as `prefix.offsets` and `prefix.targets`.
+#[cfg(feature = "csr")]
+fn write_csr(prefix: &str, edges: &[Edge]) {
+    use std::io::Write;
+    let mut edges = edges.to_vec();
+    edges.sort();
+    let nodes = edges.iter().map(|&(src, dst)| ::std::cmp::max(src, dst) as usize + 1).max().unwrap_or(0);
+    let mut offsets = vec![0u64; nodes + 1];
+    for &(src, _) in edges.iter() { offsets[src as usize + 1] += 1; }
+    for node in 1 .. offsets.len() { offsets[node] += offsets[node - 1]; }
+    let mut file = ::std::fs::File::create(format!("{}.offsets", prefix)).expect("failed to write offsets");
+    for offset in offsets { file.write_all(&offset.to_ne_bytes()).unwrap(); }
+    let mut file = ::std::fs::File::create(format!("{}.targets", prefix)).expect("failed to write targets");
+    for (_, dst) in edges { file.write_all(&dst.to_ne_bytes()).unwrap(); }
+}
+
+#[cfg(feature = "csr")]
+#[test]
+fn mapped_base_graphs_are_distribution_invariant() {
+    use alg3_dynamic::io::CsrGraph;
+
+    let (base, batches) = workload(4);
+    let expected = running_totals(&run_distributed(1, triangles, base.clone(), batches.clone()));
+    assert!(expected.iter().any(|totals| !totals.is_empty()), "workload produces no matches");
+    let prefix = format!("{}/alg3-csr-{}", ::std::env::temp_dir().display(), ::std::process::id());
+    write_csr(&prefix, &base);
+    assert!(CsrGraph::open(&format!("{}-missing", prefix)).is_err());
+
+    // counts of triangles do not depend on the names of nodes, so renaming by degree changes nothing.
+    for &relabel in [false, true].iter() {
+        for &workers in [1, 2, 4].iter() {
+            let (prefix, batches) = (prefix.clone(), batches.clone());
+            let guards = timely::execute(timely::Configuration::Process(workers), move |worker| {
+                let mut plan = triangles();
+                plan.set_global_counts(GlobalCounts::Replicated);
+                let mut graph = CsrGraph::open(&prefix).expect("failed to map graph");
+                if relabel { graph.relabel_by_degree(); }
+                let (index, peers) = (worker.index(), worker.peers());
+                let mut engine = Engine::from_csr(worker, &plan, &graph);
+                let mut totals = Vec::new();
+                for batch in batches.iter() {
+                    let mut part = batch.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect::<Vec<_>>();
+                    graph.rename_updates(&mut part);
+                    totals.push(engine.step(part).totals);
+                }
+                engine.finish();
+                totals
+            }).expect("failed to start workers");
+            for totals in guards.join().into_iter().map(|result| result.expect("worker failed")) {
+                assert_eq!(&totals[..], &expected[1 ..], "totals over a mapped base graph differ with {} workers, relabeled: {}", workers, relabel);
+            }
+        }
+    }
+    for suffix in ["offsets", "targets"].iter() { let _ = ::std::fs::remove_file(format!("{}.{}", prefix, suffix)); }
+}
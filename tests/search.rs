@@ -0,0 +1,89 @@
+//! Checks the searches of `alg3_dynamic::search` against linear scans.
+//!
+//! Galloping and bisection invite off-by-one errors at the boundaries of their steps, which fall
+//! at powers of two, and at the ends of the slice. Each test compares a search with the position a
+//! linear scan finds, over many pseudo-random sorted slices with repeated values and every cursor,
+//! and over slices of every length up to a few powers of two with every split of the predicate.
+
+extern crate alg3_dynamic;
+
+use alg3_dynamic::search::{advance, advance_from, partition_point, partition_point_from, gallop, equal_range};
+
+// a small xorshift generator, so that failures reproduce.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+
+    // a sorted slice of up to `length` values, drawn from fewer values than positions so that they repeat.
+    fn sorted(&mut self, length: u64) -> Vec<u32> {
+        let length = self.next(length + 1);
+        let mut values = (0 .. length).map(|_| self.next(length / 2 + 1) as u32).collect::<Vec<_>>();
+        values.sort();
+        values
+    }
+}
+
+// the position of the first element at or after `start` not satisfying `predicate`.
+fn scan<T, F: Fn(&T)->bool>(slice: &[T], start: usize, predicate: F) -> usize {
+    start + slice[start ..].iter().take_while(|x| predicate(x)).count()
+}
+
+#[test]
+fn searches_find_the_end_of_every_prefix() {
+    // every length around the first few powers of two, and every split of a predicate over it.
+    for length in 0 .. 70 {
+        let slice = (0 .. length).collect::<Vec<usize>>();
+        for split in 0 .. length + 1 {
+            for start in 0 .. length + 1 {
+                let expected = if start < split { split } else { start };
+                assert_eq!(advance_from(&slice, start, |&x| x < split), expected, "length {}, split {}, start {}", length, split, start);
+                assert_eq!(partition_point_from(&slice, start, |&x| x < split), expected, "length {}, split {}, start {}", length, split, start);
+            }
+            assert_eq!(advance(&slice, |&x| x < split), split);
+            assert_eq!(partition_point(&slice, |&x| x < split), split);
+        }
+    }
+}
+
+#[test]
+fn searches_match_linear_scans() {
+    let mut random = Random(0x2545F4914F6CDD1D);
+    for _ in 0 .. 2000 {
+        let slice = random.sorted(100);
+        let value = random.next(slice.len() as u64 / 2 + 2) as u32;
+        for start in 0 .. slice.len() + 1 {
+            let lower = scan(&slice, start, |&x| x < value);
+            let upper = scan(&slice, lower, |&x| x == value);
+            assert_eq!(gallop(&slice, start, &value), lower, "{:?} from {} for {}", slice, start, value);
+            assert_eq!(advance_from(&slice, start, |&x| x <= value), scan(&slice, start, |&x| x <= value));
+            assert_eq!(partition_point_from(&slice, start, |&x| x < value), lower);
+            assert_eq!(equal_range(&slice, start, &value), lower .. upper, "{:?} from {} for {}", slice, start, value);
+        }
+        assert_eq!(advance(&slice, |&x| x < value), partition_point(&slice, |&x| x < value));
+    }
+}
+
+#[test]
+fn searches_never_leave_the_slice() {
+    // a predicate that holds again after failing has no single answer, but the answer is in bounds.
+    let mut random = Random(0x9E3779B97F4A7C15);
+    for _ in 0 .. 2000 {
+        let slice = (0 .. random.next(64)).map(|_| random.next(2) == 0).collect::<Vec<_>>();
+        for start in 0 .. slice.len() + 1 {
+            assert!(advance_from(&slice, start, |&x| x) <= slice.len());
+            assert!(partition_point_from(&slice, start, |&x| x) <= slice.len());
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "search from position 4 of a slice of length 3")]
+fn searches_past_the_end_panic() {
+    advance_from(&[1, 2, 3], 4, |&x| x < 2);
+}
@@ -0,0 +1,146 @@
+//! Checks that the indices reach a steady size under continuous churn with compaction enabled.
+//!
+//! Each epoch deletes some live edges at random and inserts as many new ones, so the number of
+//! live edges stays constant while the indices absorb an unbounded number of updates. The test
+//! records the bytes the indices hold per live edge after each epoch, and checks that they stop
+//! growing. Set `SOAK_EPOCHS` to run for longer than the default.
+//!
+//! Churn within a batch, edges inserted and deleted before the batch is merged, should cost the
+//! indices nothing once it cancels: the uncommitted updates to an edge at one time are held as
+//! one, and dropped if they cancel. The other tests check that the indices hold no more updates
+//! than the net changes, and that batches full of cancelling churn report the matches of their
+//! net changes.
+
+extern crate timely;
+extern crate alg3_dynamic;
+
+use timely::communication::Configuration;
+
+use alg3_dynamic::Edge;
+use alg3_dynamic::runtime::{run_deterministic, Engine};
+use alg3_dynamic::wings_plan::Index;
+use alg3_dynamic::wings_plan::plan::read_plan_from;
+
+static TRIANGLE_PLAN: &'static str = include_str!("../triangle_plan.txt");
+
+// a small xorshift generator, so that the workload is the same on every run.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn edge(&mut self, nodes: u64) -> Edge {
+        ((self.next() % nodes) as u32, (self.next() % nodes) as u32)
+    }
+}
+
+#[test]
+fn indices_reach_steady_state_under_churn() {
+
+    let epochs = ::std::env::var("SOAK_EPOCHS").ok().and_then(|x| x.parse().ok()).unwrap_or(200);
+    let live = 2000;
+    let churn = 200;
+    let nodes = 500;
+
+    let guards = timely::execute(Configuration::Thread, move |worker| {
+
+        let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        let mut engine: Engine<_> = Engine::new(worker, &plan);
+        engine.set_compaction(Some(2.0));
+
+        let mut random = Random(0x2545F4914F6CDD1D);
+        let mut edges = (0 .. live).map(|_| random.edge(nodes)).collect::<Vec<_>>();
+        engine.load_base(edges.clone());
+
+        let mut overheads = Vec::new();
+        for _ in 0 .. epochs {
+            let mut batch = Vec::new();
+            for _ in 0 .. churn {
+                let position = (random.next() % edges.len() as u64) as usize;
+                batch.push((edges[position], -1));
+                edges[position] = random.edge(nodes);
+                batch.push((edges[position], 1));
+            }
+            let stats = engine.step(batch);
+            assert_eq!(stats.edges, live);
+            overheads.push(stats.bytes_per_edge().expect("no live edges"));
+        }
+        engine.finish();
+        overheads
+    }).expect("failed to start worker");
+
+    let overheads = guards.join().pop().unwrap().expect("worker failed");
+
+    // the largest overhead in the last half of the run should not exceed that of the first half
+    // by more than a little, as it would if the indices grew with the number of updates.
+    let half = overheads.len() / 2;
+    let early = overheads[.. half].iter().cloned().fold(0.0, f64::max);
+    let late = overheads[half ..].iter().cloned().fold(0.0, f64::max);
+    assert!(late <= early * 1.25, "bytes per live edge grew from {:.1} to {:.1}", early, late);
+}
+
+#[test]
+fn uncommitted_updates_are_consolidated() {
+    let mut index = Index::<u32, u32>::new();
+
+    // an edge inserted and deleted at one time leaves nothing; one inserted twice is held once.
+    index.update(1, &mut vec![((1, 2), 1), ((1, 3), 1), ((2, 3), 1)]);
+    index.update(1, &mut vec![((1, 2), -1), ((1, 3), 1), ((3, 1), 1), ((3, 1), -1)]);
+    assert_eq!(index.uncommitted(), 2);
+    assert_eq!(index.values(&1), vec![(3, 2)]);
+    assert_eq!(index.values(&3), vec![]);
+
+    // updates at other times are held apart, so that reads can tell the times apart.
+    index.update(2, &mut vec![((1, 3), -2), ((1, 2), 1)]);
+    assert_eq!(index.uncommitted(), 4);
+    assert_eq!(index.values_at(&1, &1), vec![(3, 2)]);
+    assert_eq!(index.values_at(&1, &2), vec![(2, 1)]);
+    index.update(2, &mut vec![((1, 2), -1)]);
+    assert_eq!(index.uncommitted(), 3);
+
+    index.merge_to(&2);
+    assert_eq!(index.uncommitted(), 0);
+    assert_eq!(index.values(&1), vec![]);
+    assert_eq!(index.values(&2), vec![(3, 1)]);
+}
+
+#[test]
+fn cancelling_churn_preserves_results() {
+    let nodes = 40;
+    let mut random = Random(0x9E3779B97F4A7C15);
+    let base = (0 .. 300).map(|_| random.edge(nodes)).collect::<Vec<_>>();
+
+    // each batch makes a few net changes, among many insertions and deletions that cancel.
+    let mut net = Vec::new();
+    let mut churned = Vec::new();
+    for _ in 0 .. 10 {
+        let changes = (0 .. 20).map(|_| (random.edge(nodes), 1)).collect::<Vec<_>>();
+        let mut batch = changes.clone();
+        for _ in 0 .. 200 {
+            let edge = random.edge(nodes);
+            batch.push((edge, 1));
+            batch.push((edge, -1));
+        }
+        // cancelling updates are spread through the batch rather than adjacent.
+        for position in (1 .. batch.len()).rev() {
+            let other = (random.next() % (position as u64 + 1)) as usize;
+            batch.swap(position, other);
+        }
+        net.push(changes);
+        churned.push(batch);
+    }
+
+    let plan = || read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let expected = run_deterministic(plan, base.clone(), net);
+    let results = run_deterministic(plan, base, churned);
+    assert_eq!(expected.len(), results.len());
+    for (epoch, (x, y)) in expected.iter().zip(results.iter()).enumerate() {
+        let nonzero = |stats: &[(usize, i64)]| stats.iter().filter(|x| x.1 != 0).cloned().collect::<Vec<_>>();
+        assert_eq!(nonzero(&x.matches), nonzero(&y.matches), "changes in matches of epoch {} differ under churn", epoch);
+        assert_eq!(x.edges, y.edges, "indices of epoch {} hold different edges under churn", epoch);
+    }
+}
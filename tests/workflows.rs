@@ -0,0 +1,2489 @@
+//! Runs the workflows of the examples end to end, over the small graphs in `tests/fixtures`.
+//!
+//! Each test reads its graph, updates, and plan from files as a driver would, and checks the
+//! changes in matches of every epoch against matches enumerated by brute force over the graph
+//! before and after the epoch. The fixtures are small enough that brute force is immediate, and
+//! are committed alongside the tests so that failures can be reproduced with the examples.
+
+extern crate timely;
+extern crate alg3_dynamic;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+
+use timely::communication::{Allocator, Configuration};
+use timely::dataflow::ProbeHandle;
+use timely::dataflow::operators::{Concat, Input, Inspect, Map, Probe};
+
+use alg3_dynamic::prelude::{Node, Edge, LabeledEdge, Engine, Orientation, BatchStats, Bundle, CursorExpired, TimedInputError, GraphStreamIndex, IntersectDirection, JoinHint, ErrorEvents, run_deterministic, run_distributed, read_plan, read_plan_from, parse_query, parse_query_with, plan_motif, Schema, AttributeType, anchor_pairs, pair_existence, Plan, PlanBuilder, NodeId, Vertex, Optimizer, DegreeStatistics, OperationDocument, OperationKind, Introspection, PlanError, ExtensionLimit, LimitOrder, ExtensionSource, ListSource, check_source, AttributeFilter, Comparison, VertexAttributes, IndexRegistry, DefaultMergePolicy, Aggregation, GroupKey};
+#[cfg(feature = "json")] use alg3_dynamic::prelude::PlanDocument;
+use alg3_dynamic::io::{Columns, EdgeReader, Strictness, read_snapshot, read_vertex_labels, write_snapshot};
+use alg3_dynamic::estimate::{estimate_counts, Estimate};
+use alg3_dynamic::sample::{Sample, SamplingRate};
+use alg3_dynamic::wings_plan::count_vertex_labeled_query_plan;
+use alg3_dynamic::timely_rule::motif;
+
+static TRIANGLE_PLAN: &'static str = include_str!("../triangle_plan.txt");
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn read_edges(name: &str) -> Vec<Edge> {
+    EdgeReader::open(&fixture(name)).expect("missing fixture").read_edges(usize::max_value()).expect("malformed fixture")
+}
+
+// the batches of updates in a change log, `size` updates each.
+fn read_batches(name: &str, size: usize) -> Vec<Vec<(Edge, i32)>> {
+    let mut reader = EdgeReader::open(&fixture(name)).expect("missing fixture");
+    let mut batches = Vec::new();
+    loop {
+        let batch = reader.read_updates(size).expect("malformed fixture");
+        if batch.is_empty() { return batches; }
+        batches.push(batch);
+    }
+}
+
+// a multiset of edges, which updates are applied to.
+#[derive(Default)]
+struct Graph(HashMap<Edge, i64>);
+
+impl Graph {
+    fn from(edges: &[Edge]) -> Self {
+        let mut graph = Graph::default();
+        graph.apply(&edges.iter().map(|&edge| (edge, 1)).collect::<Vec<_>>());
+        graph
+    }
+
+    fn apply(&mut self, updates: &[(Edge, i32)]) {
+        for &(edge, diff) in updates.iter() {
+            *self.0.entry(edge).or_insert(0) += diff as i64;
+            if self.0[&edge] == 0 { self.0.remove(&edge); }
+        }
+    }
+
+    // each embedding of the pattern with `vertices` vertices and `edges` edges, binding distinct
+    // vertices, with its multiplicity.
+    fn embeddings(&self, vertices: usize, edges: &[(usize, usize)]) -> Vec<(Vec<Node>, i64)> {
+        let mut nodes = self.0.keys().flat_map(|&(src, dst)| vec![src, dst]).collect::<Vec<_>>();
+        nodes.sort();
+        nodes.dedup();
+        let mut results = Vec::new();
+        let mut assignment = Vec::new();
+        self.extend(&nodes, vertices, edges, &mut assignment, &mut results);
+        results
+    }
+
+    fn extend(&self, nodes: &[Node], vertices: usize, edges: &[(usize, usize)], assignment: &mut Vec<Node>, results: &mut Vec<(Vec<Node>, i64)>) {
+        if assignment.len() == vertices {
+            let weight = edges.iter().map(|&(src, dst)| *self.0.get(&(assignment[src], assignment[dst])).unwrap_or(&0)).product::<i64>();
+            if weight != 0 { results.push((assignment.clone(), weight)); }
+            return;
+        }
+        for &node in nodes.iter() {
+            if assignment.contains(&node) { continue; }
+            assignment.push(node);
+            self.extend(nodes, vertices, edges, assignment, results);
+            assignment.pop();
+        }
+    }
+
+    fn count(&self, vertices: usize, edges: &[(usize, usize)]) -> i64 {
+        self.embeddings(vertices, edges).iter().map(|&(_, weight)| weight).sum()
+    }
+}
+
+fn total(stats: &BatchStats) -> i64 {
+    stats.matches.iter().map(|&(_, diff)| diff).sum()
+}
+
+// checks the changes of each epoch after the base graph against brute force over the pattern of `query`.
+fn assert_counts(results: &[BatchStats], base: &[Edge], batches: &[Vec<(Edge, i32)>], vertices: usize, pattern: &[(usize, usize)]) {
+    assert_eq!(results.len(), batches.len() + 1);
+    assert_eq!(total(&results[0]), 0, "loading the base graph produced matches");
+    let mut graph = Graph::from(base);
+    for (epoch, batch) in batches.iter().enumerate() {
+        let before = graph.count(vertices, pattern);
+        graph.apply(batch);
+        let after = graph.count(vertices, pattern);
+        assert_eq!(total(&results[epoch + 1]), after - before, "changes in matches of batch {} differ from brute force", epoch);
+    }
+}
+
+#[test]
+fn base_load_batches_and_merges() {
+    let guards = timely::execute(Configuration::Thread, |worker| {
+        let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        let query = plan.queries()[0];
+        let pattern = plan.pattern_edges(query).expect("query unreachable");
+
+        let base = read_edges("base.txt");
+        let batches = read_batches("updates.log", 4);
+
+        let mut engine = Engine::new(worker, &plan);
+        let mut results = vec![engine.load_base(base.clone())];
+        for batch in batches.iter() {
+            results.push(engine.step(batch.clone()));
+            // rebuilding the indices between epochs must not change later results.
+            engine.rebuild();
+        }
+        engine.finish();
+        assert_counts(&results, &base, &batches, 3, &pattern);
+    }).expect("failed to start worker");
+
+    for result in guards.join() { result.expect("worker failed"); }
+}
+
+// runs `drive` on two workers, each with an engine for the triangle plan, and sums the changes in
+// matches of the statistics the workers return, position by position.
+fn run_triangles<F>(drive: F) -> Vec<i64>
+    where F: Fn(&mut Engine<Allocator>, usize)->Vec<BatchStats>+Send+Sync+'static {
+    let guards = timely::execute(Configuration::Process(2), move |worker| {
+        let index = worker.index();
+        let mut engine: Engine<_> = Engine::new(worker, &read_plan_from(TRIANGLE_PLAN.as_bytes()));
+        let results = drive(&mut engine, index);
+        engine.finish();
+        results.iter().map(total).collect::<Vec<_>>()
+    }).expect("failed to start workers");
+    let totals = guards.join().into_iter().map(|x| x.expect("worker failed")).collect::<Vec<_>>();
+    (0 .. totals[0].len()).map(|position| totals.iter().map(|x| x[position]).sum::<i64>()).collect()
+}
+
+// the changes in triangles each of `batches` makes, applied in turn to `base`, by brute force.
+fn triangle_changes(base: &[Edge], batches: &[Vec<(Edge, i32)>]) -> Vec<i64> {
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let pattern = plan.pattern_edges(plan.queries()[0]).expect("query unreachable");
+    let mut graph = Graph::from(base);
+    batches.iter().map(|batch| {
+        let before = graph.count(3, &pattern);
+        graph.apply(batch);
+        graph.count(3, &pattern) - before
+    }).collect()
+}
+
+// the updates of `batch` the worker `index` of two introduces.
+fn share<D: Clone>(batch: &[D], index: usize) -> Vec<D> {
+    batch.iter().enumerate().filter(|x| x.0 % 2 == index).map(|x| x.1.clone()).collect()
+}
+
+#[test]
+fn plan_file_execution() {
+    let plan = || read_plan(&fixture("two_paths_plan.txt"));
+    let queries = plan().queries();
+    assert_eq!(queries.len(), 2);
+    let pattern = plan().pattern_edges(queries[0]).expect("query unreachable");
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 3);
+    let results = run_deterministic(plan, base.clone(), batches.clone());
+    assert_counts(&results, &base, &batches, 3, &pattern);
+}
+
+#[test]
+fn join_hints_preserve_results() {
+    // the triangle plan's second edge extends by two operations; check one of them instead.
+    let hinted = || {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        plan.set_join_hint(1, 0, Some(JoinHint::Intersect)).expect("invalid hint");
+        plan
+    };
+    assert!(hinted().set_join_hint(1, 1, Some(JoinHint::Intersect)).is_err(), "hints left no extension");
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base.clone(), batches.clone());
+    let results = run_deterministic(hinted, base, batches);
+    for (x, y) in expected.iter().zip(results.iter()) {
+        assert_eq!(x.matches, y.matches);
+        assert_eq!(x.embeddings, y.embeddings);
+    }
+}
+
+#[test]
+fn operation_directions_preserve_results() {
+    // the triangle plan's last edge closes the triangle of node 1 with an intersection; check it in reverse.
+    let directed = || {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        plan.set_operation_direction(3, 0, Some(IntersectDirection::Reverse)).expect("invalid direction");
+        plan
+    };
+    assert_eq!(directed().operation_direction(3, 0), Some(IntersectDirection::Reverse));
+    assert!(directed().set_operation_direction(1, 0, Some(IntersectDirection::Reverse)).is_err(), "extensions accepted a direction");
+    let from_file = TRIANGLE_PLAN.replace("  1 2 1", "  1 2 1 reverse");
+    assert_eq!(read_plan_from(from_file.as_bytes()).fingerprint(), directed().fingerprint());
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base.clone(), batches.clone());
+    let results = run_deterministic(directed, base, batches);
+    for (x, y) in expected.iter().zip(results.iter()) {
+        assert_eq!(x.matches, y.matches);
+    }
+}
+
+#[test]
+fn forward_only_operations_are_reversed() {
+    // the triangle plan with the edges into x2 written forward from x2, as some generators write them.
+    let forward_only = TRIANGLE_PLAN.replace("  0 2 0\n", "  2 0 1\n").replace("  1 2 0\n", "  2 1 1\n");
+    assert!(forward_only != TRIANGLE_PLAN);
+    let (plan, warnings) = Plan::from_reader_with_warnings(forward_only.as_bytes()).expect("plan not read");
+    assert_eq!(warnings.len(), 3);
+    assert!(Plan::from_reader_with_warnings(TRIANGLE_PLAN.as_bytes()).expect("plan not read").1.is_empty());
+    assert!(plan.check_operations().is_ok());
+    assert_eq!(plan.fingerprint(), read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base.clone(), batches.clone());
+    let results = run_deterministic(move || read_plan_from(forward_only.as_bytes()), base, batches);
+    for (x, y) in expected.iter().zip(results.iter()) {
+        assert_eq!(x.matches, y.matches);
+    }
+}
+
+#[test]
+fn hash_joins_match_generic_join() {
+    // the triangle plan with every extending edge proposing from its first extension by hash join.
+    let hashed = TRIANGLE_PLAN.replace("0 1 1\n", "0 1 1 0 hash_join\n").replace("0 2 2\n", "0 2 2 0 hash_join\n").replace("0 3 2\n", "0 3 2 0 hash_join\n");
+    let plan = read_plan_from(hashed.as_bytes());
+    let built = Plan::builder()
+        .add_node(NodeId(1), false)
+        .add_node(NodeId(2), true)
+        .add_node(NodeId(3), true)
+        .add_node(NodeId(4), true)
+        .add_edge(NodeId::ROOT, NodeId(1)).hash_join()
+        .extension(Vertex(0), Vertex(2))
+        .add_edge(NodeId::ROOT, NodeId(2)).hash_join()
+        .extension(Vertex(2), Vertex(0))
+        .extension(Vertex(2), Vertex(1))
+        .add_edge(NodeId::ROOT, NodeId(3)).hash_join()
+        .extension(Vertex(0), Vertex(2))
+        .extension(Vertex(2), Vertex(1))
+        .add_edge(NodeId(1), NodeId(4))
+        .intersection(Vertex(1), Vertex(2))
+        .build()
+        .expect("malformed plan");
+    assert_eq!(built.fingerprint(), plan.fingerprint());
+    assert!(plan.fingerprint() != read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint(), "hash joins left the plan unchanged");
+    let labeled = Plan::builder().add_node(NodeId(1), true).add_edge(NodeId::ROOT, NodeId(1)).hash_join().extension(Vertex(0), Vertex(2)).label(1).extension(Vertex(1), Vertex(2));
+    assert!(labeled.build().is_err(), "hash join read a vertex label");
+    let pattern = plan.pattern_edges(plan.queries()[0]).expect("query unreachable");
+
+    // batches of one update, of several, and of every update, which share keys within an epoch.
+    let base = read_edges("base.txt");
+    for &size in [1, 4, usize::max_value()].iter() {
+        let batches = read_batches("updates.log", size);
+        let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base.clone(), batches.clone());
+        let text = hashed.clone();
+        let results = run_deterministic(move || read_plan_from(text.as_bytes()), base.clone(), batches.clone());
+        for (x, y) in expected.iter().zip(results.iter()) {
+            assert_eq!(x.matches, y.matches, "hash joins changed the matches of batches of {}", size);
+            assert_eq!(x.embeddings, y.embeddings, "hash joins changed the embeddings of batches of {}", size);
+        }
+        assert_counts(&results, &base, &batches, 3, &pattern);
+
+        let text = hashed.clone();
+        let distributed = run_distributed(2, move || read_plan_from(text.as_bytes()), base.clone(), batches.clone());
+        assert_counts(&distributed, &base, &batches, 3, &pattern);
+    }
+}
+
+#[test]
+fn parsed_queries_match_brute_force() {
+    let rules: &[(&'static str, usize, &[(usize, usize)])] = &[
+        ("triangle(a, b, c) :- edge(a, b), edge(a, c), edge(b, c).", 3, &[(0, 1), (0, 2), (1, 2)]),
+        ("cycle4(a,b,c,d) :- edge(a,b), edge(b,c), edge(c,d), edge(d,a)", 4, &[(0, 1), (1, 2), (2, 3), (3, 0)]),
+        ("mutual(a, b) :- edge(a, b), edge(b, a).", 2, &[(0, 1), (1, 0)]),
+        ("back(x, y, z) :- edge(x, y), edge(y, z), edge(y, x).", 3, &[(0, 1), (1, 2), (1, 0)]),
+    ];
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    for &(rule, vertices, pattern) in rules.iter() {
+        let query = parse_query(rule).unwrap_or_else(|error| panic!("couldn't parse {}: {}", rule, error));
+        assert_eq!(query.variables.len(), vertices);
+        assert_eq!(query.plan.queries().len(), pattern.len());
+
+        let results = run_deterministic(move || parse_query(rule).unwrap().plan, base.clone(), batches.clone());
+        assert_counts(&results, &base, &batches, vertices, pattern);
+
+        // matches read in the order of the head are embeddings of the head's pattern. a batch may
+        // add a match through one query node and retract it through another, as the updates of
+        // the batch see each other, so only the net additions of each batch are checked.
+        let mut graph = Graph::from(&base);
+        for (stats, batch) in results[1 ..].iter().zip(batches.iter()) {
+            graph.apply(batch);
+            let mut net = BTreeMap::new();
+            for &(node, ref embedding, diff) in stats.embeddings.iter() {
+                *net.entry(query.project(node, embedding)).or_insert(0) += diff;
+            }
+            for (projected, diff) in net.into_iter().filter(|x| x.1 > 0) {
+                assert!(pattern.iter().all(|&(src, dst)| graph.0.contains_key(&(projected[src], projected[dst]))),
+                        "{:?} is not a match of {} ({})", projected, rule, diff);
+            }
+        }
+    }
+}
+
+#[test]
+fn anti_edges_keep_induced_matches() {
+    let rule = "wedge(a, b, c) :- edge(a, b), edge(a, c), !edge(b, c), !edge(c, b).";
+    let query = parse_query(rule).unwrap_or_else(|error| panic!("couldn't parse {}: {}", rule, error));
+    assert_eq!(query.plan.queries().len(), 4);
+    assert_eq!(query.plan.queries().into_iter().filter(|&node| query.plan.negated(node)).count(), 2);
+
+    // the fixture holds each edge at most once, as anti-edges assume.
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let wedges = |graph: &Graph| graph.embeddings(3, &[(0, 1), (0, 2)]).into_iter()
+        .filter(|x| !graph.0.contains_key(&(x.0[1], x.0[2])) && !graph.0.contains_key(&(x.0[2], x.0[1])))
+        .map(|x| x.1)
+        .sum::<i64>();
+
+    let results = run_deterministic(move || parse_query(rule).unwrap().plan, base.clone(), batches.clone());
+    assert_eq!(total(&results[0]), 0, "loading the base graph produced matches");
+    let mut graph = Graph::from(&base);
+    for (epoch, (stats, batch)) in results[1 ..].iter().zip(batches.iter()).enumerate() {
+        let before = wedges(&graph);
+        graph.apply(batch);
+        assert_eq!(total(stats), wedges(&graph) - before, "changes in open wedges differ in batch {}", epoch);
+    }
+
+    // negated nodes and anti-edges are part of the document.
+    let document = query.plan.to_document();
+    assert_eq!(document.nodes.iter().filter(|node| node.negated).count(), 2);
+    assert_eq!(document.build().expect("malformed document").fingerprint(), query.plan.fingerprint());
+
+    assert!(parse_query("w(a, b) :- edge(a, b), edge(b, a), !edge(a, b)").is_err());
+    assert!(parse_query("w(a, b) :- edge(a, b), !edge(b, c)").is_err());
+    assert!(parse_query("w(a) :- edge(a, a), !edge(a, a)").is_err());
+}
+
+#[test]
+fn vertex_labels_restrict_extensions() {
+    // the triangle plan, whose every extension binds x2, and requires it to have `label` if any.
+    fn labeled(label: Option<u32>) -> Plan {
+        let with = |builder: PlanBuilder| match label { Some(label) => builder.label(label), None => builder };
+        let builder = Plan::builder()
+            .add_node(NodeId(1), false)
+            .add_node(NodeId(2), true)
+            .add_node(NodeId(3), true)
+            .add_node(NodeId(4), true)
+            .add_edge(NodeId::ROOT, NodeId(1));
+        let builder = with(builder.extension(Vertex(0), Vertex(2))).add_edge(NodeId::ROOT, NodeId(2));
+        let builder = with(with(builder.extension(Vertex(2), Vertex(0))).extension(Vertex(2), Vertex(1))).add_edge(NodeId::ROOT, NodeId(3));
+        let builder = with(with(builder.extension(Vertex(0), Vertex(2))).extension(Vertex(2), Vertex(1)));
+        builder.add_edge(NodeId(1), NodeId(4)).intersection(Vertex(1), Vertex(2)).build().expect("malformed plan")
+    }
+    assert_eq!(labeled(None).fingerprint(), read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+
+    let labels = Arc::new(read_vertex_labels(&fixture("labels.txt")).expect("malformed labels"));
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    // the recorded changes of each epoch, with and without the labels.
+    let run = |label: Option<u32>| {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        {
+            let (results, labels, base, batches) = (results.clone(), labels.clone(), base.clone(), batches.clone());
+            let guards = timely::execute(Configuration::Thread, move |worker| {
+                let plan = labeled(label);
+                let mut engine: Engine<_> = Engine::with_vertex_labels(worker, &plan, labels.clone(), true);
+                let mut stats = vec![engine.load_base(base.clone())];
+                for batch in batches.iter() {
+                    stats.push(engine.step(batch.clone()));
+                }
+                engine.finish();
+                *results.lock().unwrap() = stats;
+            }).expect("failed to start worker");
+            for result in guards.join() { result.expect("worker failed"); }
+        }
+        let results = results.lock().unwrap().clone();
+        results
+    };
+
+    // every node's matches are those of the unlabeled plan whose x2 has the label.
+    let unlabeled = run(None);
+    let restricted = run(Some(1));
+    assert_eq!(unlabeled.len(), restricted.len());
+    for (epoch, (all, some)) in unlabeled.iter().zip(restricted.iter()).enumerate() {
+        let expected = all.embeddings.iter().filter(|x| labels.get(&x.1[2]) == Some(&1)).cloned().collect::<Vec<_>>();
+        assert_eq!(some.embeddings, expected, "labeled matches of epoch {} differ", epoch);
+    }
+    assert!(restricted.iter().any(|stats| !stats.embeddings.is_empty()), "no labeled triangles change in the fixture");
+    assert!(unlabeled.iter().zip(restricted.iter()).any(|(all, some)| all.embeddings.len() > some.embeddings.len()), "the label restricted nothing");
+
+    // labels are part of the document, and only apply to operations that bind a vertex.
+    let mut plan = labeled(Some(1));
+    assert_eq!(plan.operation_label(1, 1), Some(1));
+    assert!(plan.to_dot().contains("(label 1)"));
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.set_operation_label(1, 0, Some(2)).is_err(), "operations binding x2 require different labels");
+    assert!(plan.set_operation_label(3, 0, Some(1)).is_err(), "an intersection requires a label");
+    assert_eq!(plan.operation_label(1, 0), Some(1));
+    plan.set_operation_label(0, 0, None).expect("label not lifted");
+    assert_eq!(plan.operation_label(0, 0), None);
+}
+
+#[test]
+fn edge_labels_restrict_operations() {
+    // triangles a->b, a->c, b->c whose edges are labeled 1, 0, and 1, where each edge is labeled
+    // by the parity of its endpoints' sum; each edge leaving the root takes the update for one of them.
+    let label = |(src, dst): Edge| (src + dst) % 2;
+    let plan_file = "0 0 0\n0\n5\n0 3 2 0\n3 1 3 0\n4 0 3 1\n4 0 3 1\n4 0 3 1\n4\n\
+                     0 1 1 0 edge_label=1\n  0 2 1 edge_label=0\n\
+                     0 2 2 0 edge_label=1\n  0 2 0 edge_label=1\n  1 2 0 edge_label=0\n\
+                     0 3 2 0 edge_label=0\n  0 2 1 edge_label=1\n  1 2 0 edge_label=1\n\
+                     1 4 1\n  1 2 1 edge_label=1\n";
+    let plan = Plan::builder()
+        .add_node(NodeId(1), false)
+        .add_node(NodeId(2), true)
+        .add_node(NodeId(3), true)
+        .add_node(NodeId(4), true)
+        .add_edge(NodeId::ROOT, NodeId(1)).update_label(1)
+        .extension(Vertex(0), Vertex(2)).edge_label(0)
+        .add_edge(NodeId::ROOT, NodeId(2)).update_label(1)
+        .extension(Vertex(2), Vertex(0)).edge_label(1)
+        .extension(Vertex(2), Vertex(1)).edge_label(0)
+        .add_edge(NodeId::ROOT, NodeId(3)).update_label(0)
+        .extension(Vertex(0), Vertex(2)).edge_label(1)
+        .extension(Vertex(2), Vertex(1)).edge_label(1)
+        .add_edge(NodeId(1), NodeId(4))
+        .intersection(Vertex(1), Vertex(2)).edge_label(1)
+        .build()
+        .expect("malformed plan");
+    assert_eq!(read_plan_from(plan_file.as_bytes()).fingerprint(), plan.fingerprint());
+    assert_ne!(read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint(), plan.fingerprint());
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (reported, base, batches) = (reported.clone(), base.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let plan = read_plan_from(plan_file.as_bytes());
+            let reported = reported.clone();
+            let (mut initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<LabeledEdge>();
+                let (updates, changes) = builder.new_input::<(LabeledEdge, i32)>();
+                let (graph, handles) = GraphStreamIndex::from_labeled(graph, changes, &[0, 1], |k| k as u64, |k| k as u64);
+                let mut probe = ProbeHandle::new();
+                for (_, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                    let reported = reported.clone();
+                    matches.inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|x| (time.inner, x.1))))
+                           .probe_with(&mut probe);
+                }
+                (initially, updates, probe, handles)
+            });
+
+            // the base graph is indexed in the first epoch, and matches are found for each batch after it.
+            initially.send_batch(&mut base.iter().map(|&(src, dst)| (src, dst, label((src, dst)))).collect());
+            initially.close();
+            for batch in batches.iter() {
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+                updates.send_batch(&mut batch.iter().map(|&((src, dst), diff)| ((src, dst, label((src, dst))), diff)).collect());
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    // the labeled triangles, counted over the edges with each label.
+    let triangles = |graph: &Graph| graph.embeddings(3, &[(0, 1), (0, 2), (1, 2)]).into_iter()
+        .filter(|x| label((x.0[0], x.0[1])) == 1 && label((x.0[0], x.0[2])) == 0 && label((x.0[1], x.0[2])) == 1)
+        .map(|x| x.1)
+        .sum::<i64>();
+    let reported = reported.lock().unwrap();
+    let mut graph = Graph::from(&base);
+    let mut changed = false;
+    for (epoch, batch) in batches.iter().enumerate() {
+        let before = triangles(&graph);
+        graph.apply(batch);
+        let expected = triangles(&graph) - before;
+        let total = reported.iter().filter(|x| x.0 == epoch as u32 + 1).map(|x| x.1).sum::<i64>();
+        assert_eq!(total, expected, "changes in labeled triangles differ in batch {}", epoch);
+        changed |= expected != 0;
+    }
+    assert!(changed, "no labeled triangles change in the fixture");
+
+    // labels are part of the document, and updates are labeled only where the plan reads them.
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.to_dot().contains("x0-[1]->x2") && plan.to_dot().contains("updates [0]"));
+    let mut plan = plan;
+    assert_eq!((plan.edge_label(3, 0), plan.update_label(0), plan.update_label(3)), (Some(1), Some(1), None));
+    assert!(plan.set_update_label(3, Some(1)).is_err());
+    assert!(plan.set_extension_limit(0, 0, Some(ExtensionLimit { k: 1, order: LimitOrder::Id })).is_err());
+    plan.set_edge_label(0, 0, None).expect("label not lifted");
+    assert_eq!(plan.edge_label(0, 0), None);
+    assert!(Plan::builder().add_node(NodeId(1), false).add_node(NodeId(2), true)
+        .add_edge(NodeId::ROOT, NodeId(1)).extension(Vertex(0), Vertex(2))
+        .add_edge(NodeId(1), NodeId(2)).update_label(1).intersection(Vertex(1), Vertex(2))
+        .build().is_err());
+}
+
+// a source whose lists are read back to front, proposing out of order, or counted at half their length.
+struct Broken(ListSource, bool);
+
+impl ExtensionSource for Broken {
+    fn count(&self, key: Node) -> usize { if self.1 { self.0.count(key) } else { self.0.count(key) / 2 } }
+    fn propose(&self, key: Node, values: &mut Vec<Node>) {
+        let start = values.len();
+        self.0.propose(key, values);
+        if self.1 { values[start ..].reverse(); }
+    }
+}
+
+#[test]
+fn sources_extend_alongside_the_graph() {
+    // paths x0->x1->x2 whose ends are similar, as a source other than the graph relates them;
+    // the edge for an update to x1->x2 binds x0 from the graph, and then checks the source.
+    let similar = |src: Node, dst: Node| src != dst && (src + dst) % 3 == 0;
+    let plan_file = "0 0 0\n0\n3\n0 2 2 0\n2 0 3 1\n2 0 3 1\n2\n\
+                     0 1 2\n  1 2 1\n  0 2 1 source=7\n\
+                     0 2 2\n  0 2 0\n  1 2 0 intersect source=7\n";
+    let plan = Plan::builder()
+        .add_node(NodeId(1), true)
+        .add_node(NodeId(2), true)
+        .add_edge(NodeId::ROOT, NodeId(1))
+        .extension(Vertex(1), Vertex(2))
+        .extension(Vertex(0), Vertex(2)).source(7)
+        .add_edge(NodeId::ROOT, NodeId(2))
+        .extension(Vertex(2), Vertex(0))
+        .extension(Vertex(2), Vertex(1)).source(7).hint(JoinHint::Intersect)
+        .build()
+        .expect("malformed plan");
+    assert_eq!(read_plan_from(plan_file.as_bytes()).fingerprint(), plan.fingerprint());
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.to_dot().contains("x0-{7}->x2"));
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut vertices = base.iter().chain(batches.iter().flat_map(|batch| batch.iter().map(|x| &x.0)))
+        .flat_map(|&(src, dst)| vec![src, dst]).collect::<Vec<_>>();
+    vertices.sort();
+    vertices.dedup();
+    let pairs = vertices.iter().flat_map(|&src| vertices.iter().map(move |&dst| (src, dst))).filter(|&(src, dst)| similar(src, dst)).collect::<Vec<_>>();
+    let source = Arc::new(ListSource::from_pairs(pairs.clone()));
+
+    // the source keeps the contract, and sources that do not are caught.
+    let report = check_source(&*source, &vertices, 1.0).expect("list sources keep the contract");
+    assert_eq!((report.keys, report.proposed, report.overcount), (vertices.len(), pairs.len(), 1.0));
+    assert!(check_source(&Broken((*source).clone(), true), &vertices, 1.0).is_err());
+    assert!(check_source(&Broken((*source).clone(), false), &vertices, 1.0).is_err());
+
+    let results = {
+        let source = source.clone();
+        run_deterministic(move || {
+            let mut plan = read_plan_from(plan_file.as_bytes());
+            plan.set_source(7, source.clone());
+            plan
+        }, base.clone(), batches.clone())
+    };
+    assert_eq!(total(&results[0]), 0, "loading the base graph produced matches");
+    let paths = |graph: &Graph| graph.embeddings(3, &[(0, 1), (1, 2)]).into_iter()
+        .filter(|x| similar(x.0[0], x.0[2]))
+        .map(|x| x.1)
+        .sum::<i64>();
+    let mut graph = Graph::from(&base);
+    let mut changed = false;
+    for (epoch, batch) in batches.iter().enumerate() {
+        let before = paths(&graph);
+        graph.apply(batch);
+        let expected = paths(&graph) - before;
+        assert_eq!(total(&results[epoch + 1]), expected, "changes in similar paths differ in batch {}", epoch);
+        changed |= expected != 0;
+    }
+    assert!(changed, "no similar paths change in the fixture");
+
+    // sources hold neither absent, limited, nor labeled edges, and propose only from bound vertices.
+    let mut plan = plan;
+    assert_eq!((plan.operation_source(0, 1), plan.operation_source(0, 0)), (Some(7), None));
+    assert!(plan.set_extension_limit(0, 1, Some(ExtensionLimit { k: 1, order: LimitOrder::Id })).is_err());
+    assert!(plan.set_edge_label(0, 1, Some(1)).is_err());
+    assert!(plan.set_operation_source(1, 0, Some(7)).is_err());
+    assert!(plan.set_join_hint(1, 1, None).is_err());
+    plan.set_operation_source(0, 1, None).expect("source not lifted");
+    assert_eq!(plan.operation_source(0, 1), None);
+}
+
+#[test]
+fn attribute_filters_restrict_vertices() {
+    // triangles whose vertices all have a value of at least 2, other than 4, where each vertex's
+    // value is its id modulo 7; each edge binding x2 filters the vertices it reads and the one it binds.
+    let value = |node: Node| (node % 7) as f64;
+    let accepted = |node: Node| value(node) >= 2.0 && value(node) != 4.0;
+    let filters = (0 .. 3).flat_map(|vertex| vec![
+        AttributeFilter { vertex, attribute: 0, comparison: Comparison::GreaterEqual, value: 2.0 },
+        AttributeFilter { vertex, attribute: 0, comparison: Comparison::NotEqual, value: 4.0 },
+    ]).collect::<Vec<_>>();
+    let filtered = move || {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        for edge in 0 .. 3 { plan.set_vertex_filters(edge, filters.clone()).expect("malformed filters"); }
+        plan
+    };
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut attributes = VertexAttributes::new();
+    for &(src, dst) in base.iter().chain(batches.iter().flat_map(|batch| batch.iter().map(|x| &x.0))) {
+        attributes.set(0, src, value(src));
+        attributes.set(0, dst, value(dst));
+    }
+    let attributes = Arc::new(attributes);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (reported, base, batches, filtered) = (reported.clone(), base.clone(), batches.clone(), filtered.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let plan = filtered();
+            let (reported, attributes) = (reported.clone(), attributes.clone());
+            let (mut initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (mut graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+                graph.set_vertex_attributes(attributes);
+                let mut probe = ProbeHandle::new();
+                for (_, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                    let reported = reported.clone();
+                    matches.inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|x| (time.inner, x.0.clone(), x.1))))
+                           .probe_with(&mut probe);
+                }
+                (initially, updates, probe, handles)
+            });
+
+            initially.send_batch(&mut base.clone());
+            initially.close();
+            for batch in batches.iter() {
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+                updates.send_batch(&mut batch.clone());
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let triangles = |graph: &Graph| graph.embeddings(3, &[(0, 1), (0, 2), (1, 2)]).into_iter()
+        .filter(|x| x.0.iter().all(|&node| accepted(node)))
+        .map(|x| x.1)
+        .sum::<i64>();
+    let reported = reported.lock().unwrap();
+    assert!(reported.iter().all(|x| x.1.iter().all(|&node| accepted(node))), "a filtered vertex was matched");
+    let mut graph = Graph::from(&base);
+    let mut changed = false;
+    for (epoch, batch) in batches.iter().enumerate() {
+        let before = triangles(&graph);
+        graph.apply(batch);
+        let expected = triangles(&graph) - before;
+        let total = reported.iter().filter(|x| x.0 == epoch as u32 + 1).map(|x| x.2).sum::<i64>();
+        assert_eq!(total, expected, "changes in filtered triangles differ in batch {}", epoch);
+        changed |= expected != 0;
+    }
+    assert!(changed, "no filtered triangles change in the fixture");
+
+    // filters are written as plan files and documents write them, and read only bound vertices.
+    let filter = AttributeFilter::parse("x2.0>=2").expect("malformed filter");
+    assert_eq!(filter, AttributeFilter { vertex: 2, attribute: 0, comparison: Comparison::GreaterEqual, value: 2.0 });
+    assert_eq!(AttributeFilter::parse(&filter.to_string()), Ok(filter));
+    assert!(AttributeFilter::parse("x2.0=>2").is_err() && AttributeFilter::parse("y2.0>2").is_err() && AttributeFilter::parse("x2.0>NaN").is_err());
+    let plan = Plan::builder()
+        .add_node(NodeId(1), true)
+        .add_edge(NodeId::ROOT, NodeId(1))
+        .extension(Vertex(0), Vertex(2))
+        .filter(filter)
+        .filter(AttributeFilter::parse("x0.1!=4.5").expect("malformed filter"))
+        .build()
+        .expect("malformed plan");
+    let plan_file = "0 0 0\n0\n2\n0 1 2 0\n1 0 3 1\n1\n0 1 1 0 filter=x2.0>=2 filter=x0.1!=4.5\n  0 2 1\n";
+    assert_eq!(read_plan_from(plan_file.as_bytes()).fingerprint(), plan.fingerprint());
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.to_dot().contains("where x2.0>=2"));
+    assert_ne!(filtered().fingerprint(), read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+    let mut plan = plan;
+    assert!(plan.set_vertex_filters(0, vec![AttributeFilter { vertex: 3, ..filter }]).is_err());
+    assert!(plan.set_vertex_filters(0, vec![AttributeFilter { value: ::std::f64::NAN, ..filter }]).is_err());
+    assert_eq!(plan.vertex_filters(0).len(), 2);
+    plan.set_vertex_filters(0, Vec::new()).expect("filters not lifted");
+    assert!(plan.vertex_filters(0).is_empty());
+}
+
+#[test]
+fn registered_indices_merge_together() {
+    // a triangle plan reads one graph, while a second index of the same updates is kept beside it;
+    // merging through the registry keeps both merged, without the driver tracking either.
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (reported, base, batches) = (reported.clone(), base.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+            let reported = reported.clone();
+            let registry = IndexRegistry::new();
+            registry.set_merge_policy(DefaultMergePolicy);
+            let (mut initially, mut updates, probe, handles, other) = {
+                let registry = registry.clone();
+                worker.dataflow::<u32,_,_>(move |builder| {
+                    let (initially, edges) = builder.new_input::<Edge>();
+                    let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                    let (graph, handles) = GraphStreamIndex::from(edges.clone(), changes.clone(), |k| k as u64, |k| k as u64);
+                    let (_, other) = GraphStreamIndex::from(edges, changes, |k| k as u64, |k| k as u64);
+                    handles.register(&registry, "graph");
+                    other.register(&registry, "other");
+                    let mut probe = ProbeHandle::new();
+                    for (_, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                        let reported = reported.clone();
+                        matches.inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|x| (time.inner, x.1))))
+                               .probe_with(&mut probe);
+                    }
+                    (initially, updates, probe, handles, other)
+                })
+            };
+            assert_eq!(registry.names(), vec!["graph.forward[0]", "graph.reverse[0]", "other.forward[0]", "other.reverse[0]"]);
+
+            initially.send_batch(&mut base.clone());
+            initially.close();
+            for batch in batches.iter() {
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                if time.inner == 1 {
+                    // merging one index by hand leaves the other behind, which the registry reports.
+                    handles.merge_to(&time);
+                    assert_eq!(registry.lagging(&time), vec!["other.forward[0]", "other.reverse[0]"]);
+                }
+                registry.merge_completed(&probe);
+                assert!(registry.lagging(&time).is_empty(), "an index was left unmerged at {:?}", time);
+                updates.send_batch(&mut batch.clone());
+            }
+            updates.close();
+            while worker.step() { }
+            assert!(registry.merge_completed(&probe), "the dataflow did not complete");
+            assert!(registry.uncommitted().iter().all(|x| x.1 == 0));
+
+            // both indices hold the final graph, which snapshots read once merged.
+            let (mut forward, mut reverse) = handles.snapshot();
+            let (mut other_forward, mut other_reverse) = other.snapshot();
+            forward.sort(); reverse.sort(); other_forward.sort(); other_reverse.sort();
+            assert!(!forward.is_empty());
+            assert_eq!((other_forward, other_reverse), (forward, reverse));
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let reported = reported.lock().unwrap();
+    let mut graph = Graph::from(&base);
+    for (epoch, batch) in batches.iter().enumerate() {
+        let before = graph.count(3, &[(0, 1), (0, 2), (1, 2)]);
+        graph.apply(batch);
+        let expected = graph.count(3, &[(0, 1), (0, 2), (1, 2)]) - before;
+        let total = reported.iter().filter(|x| x.0 == epoch as u32 + 1).map(|x| x.1).sum::<i64>();
+        assert_eq!(total, expected, "changes in triangles differ in batch {}", epoch);
+    }
+}
+
+#[test]
+fn projected_matches_sum_their_multiplicities() {
+    // every query node of the triangle plan reports only its first two vertices, the updated edge.
+    let projected = || {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        for query in plan.queries() { plan.set_projection(query, Some(vec![1, 0])).expect("malformed projection"); }
+        plan
+    };
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base.clone(), batches.clone());
+    let results = run_distributed(2, projected, base, batches);
+    let mut projections = 0;
+    for (x, y) in expected.iter().zip(results.iter()) {
+        assert_eq!(x.matches, y.matches);
+        let mut sums = BTreeMap::new();
+        for &(query, ref embedding, diff) in x.embeddings.iter() {
+            *sums.entry((query, vec![embedding[1], embedding[0]])).or_insert(0) += diff;
+        }
+        let sums = sums.into_iter().filter(|x| x.1 != 0).map(|((query, tuple), diff)| (query, tuple, diff)).collect::<Vec<_>>();
+        assert_eq!(y.embeddings, sums, "projected changes differ in epoch {}", x.epoch);
+        projections += sums.len();
+    }
+    assert!(projections > 0, "no projected changes in the fixture");
+
+    // projections are kept by documents and the builder, and name positions of query nodes once.
+    let plan = projected();
+    let query = plan.queries()[0];
+    assert_eq!(plan.projection(query), Some(&[1, 0][..]));
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.to_dot().contains("project x1,x0"));
+    let built = Plan::builder()
+        .add_node(NodeId(1), true)
+        .add_edge(NodeId::ROOT, NodeId(1))
+        .extension(Vertex(0), Vertex(2))
+        .project(NodeId(1), &[Vertex(2)])
+        .build()
+        .expect("malformed plan");
+    assert_eq!(built.projection(1), Some(&[2][..]));
+    let mut plan = plan;
+    assert!(plan.set_projection(query, Some(vec![0, 0])).is_err());
+    assert!(plan.set_projection(query, Some(vec![3])).is_err());
+    assert!(plan.set_projection(0, Some(vec![0])).is_err());
+    plan.set_projection(query, None).expect("projection not lifted");
+    assert_eq!(plan.projection(query), None);
+}
+
+#[test]
+fn aggregates_follow_brute_force() {
+    // directed triangles a->b, b->c, a->c: group 0 counts them by `a`, and group 1 sums the value
+    // of `c` over them by the label of `a`, where labels are ids modulo 2 and values ids modulo 5.
+    let parsed = || {
+        let mut query = parse_query("tri(a, b, c) :- edge(a, b), edge(b, c), edge(a, c)").expect("malformed query");
+        for &(node, ref positions) in query.positions.iter() {
+            query.plan.set_aggregation(node, Some(Aggregation::count(0, vec![GroupKey::Vertex(positions[0])]))).expect("malformed aggregation");
+        }
+        query
+    };
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut labels = HashMap::new();
+    let mut attributes = VertexAttributes::new();
+    for &(src, dst) in base.iter().chain(batches.iter().flat_map(|batch| batch.iter().map(|x| &x.0))) {
+        for &node in [src, dst].iter() {
+            labels.insert(node, node % 2);
+            attributes.set(0, node, (node % 5) as f64);
+        }
+    }
+    let (labels, attributes) = (Arc::new(labels), Arc::new(attributes));
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (reported, base, batches, labels, attributes) = (reported.clone(), base.clone(), batches.clone(), labels.clone(), attributes.clone());
+        let guards = timely::execute(Configuration::Process(2), move |worker| {
+            // a second query contributes to group 1, through a plan combining it with the first.
+            let (first, second) = (parsed(), parsed());
+            let (mut plan, indices) = Plan::combine(&[&first.plan, &second.plan]).expect("plans not combined");
+            for &(node, ref positions) in second.positions.iter() {
+                let aggregation = Aggregation::sum(1, vec![GroupKey::Label(positions[0])], positions[2], 0);
+                plan.set_aggregation(indices[1][node], Some(aggregation)).expect("malformed aggregation");
+            }
+            let (reported, labels, attributes) = (reported.clone(), labels.clone(), attributes.clone());
+            let (mut initially, mut updates) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (mut graph, _) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| (k as u64) * 7);
+                graph.set_vertex_labels(labels);
+                graph.set_vertex_attributes(attributes);
+                let matches = plan.track_motif::<i64, _, _, _>(&graph);
+                let mut probe = ProbeHandle::new();
+                for (group, aggregates) in plan.track_aggregates(&graph, &matches) {
+                    let reported = reported.clone();
+                    aggregates.inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|x| (time.inner, group, x.0.clone(), x.1, x.2))))
+                              .probe_with(&mut probe);
+                }
+                (initially, updates)
+            });
+
+            if worker.index() == 0 { initially.send_batch(&mut base.clone()); }
+            initially.close();
+            for batch in batches.iter() {
+                updates.advance_to(updates.time().inner + 1);
+                if worker.index() == 0 { updates.send_batch(&mut batch.clone()); }
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start workers");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    // the aggregates of the matches of `graph`, less those of the base graph, whose matches are never reported.
+    let aggregates = |graph: &Graph| {
+        let mut groups = BTreeMap::new();
+        for (embedding, weight) in graph.embeddings(3, &[(0, 1), (1, 2), (0, 2)]) {
+            let count = groups.entry((0, vec![embedding[0]])).or_insert((0, 0.0));
+            *count = (count.0 + weight, count.1 + weight as f64);
+            let sum = groups.entry((1, vec![labels[&embedding[0]]])).or_insert((0, 0.0));
+            *sum = (sum.0 + weight, sum.1 + weight as f64 * attributes.get(0, embedding[2]).unwrap());
+        }
+        groups
+    };
+    let initial = aggregates(&Graph::from(&base));
+    let mut reported = reported.lock().unwrap().clone();
+    reported.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut graph = Graph::from(&base);
+    let mut state = BTreeMap::new();
+    for (epoch, batch) in batches.iter().enumerate() {
+        graph.apply(batch);
+        for &(_, group, ref key, matches, value) in reported.iter().filter(|x| x.0 == epoch as u32 + 1) {
+            if (matches, value) == (0, 0.0) { state.remove(&(group, key.clone())); }
+            else { state.insert((group, key.clone()), (matches, value)); }
+        }
+        let mut expected = aggregates(&graph);
+        for (key, &(matches, value)) in initial.iter() {
+            let entry = expected.entry(key.clone()).or_insert((0, 0.0));
+            *entry = (entry.0 - matches, entry.1 - value);
+        }
+        let expected = expected.into_iter().filter(|x| x.1 != (0, 0.0)).collect::<BTreeMap<_, _>>();
+        assert_eq!(state, expected, "aggregates differ from brute force after batch {}", epoch);
+    }
+    assert!(!state.is_empty(), "no aggregates in the fixture");
+
+    // aggregations are kept by documents, and must combine with the others of their group.
+    let parsed = parsed();
+    let (query, ref positions) = parsed.positions[0];
+    let mut plan = parsed.plan;
+    assert_eq!(plan.to_document().build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert_eq!(plan.aggregation(query), Some(&Aggregation::count(0, vec![GroupKey::Vertex(positions[0])])));
+    assert!(plan.to_dot().contains("group 0: count by x"));
+    assert!(plan.set_aggregation(query, Some(Aggregation::sum(0, vec![GroupKey::Vertex(0)], 1, 0))).is_err());
+    assert!(plan.set_aggregation(query, Some(Aggregation::count(0, vec![GroupKey::Label(0)]))).is_err());
+    assert!(plan.set_aggregation(query, Some(Aggregation::count(0, vec![GroupKey::Vertex(3)]))).is_err());
+    assert!(plan.set_projection(query, Some(vec![0])).is_err());
+    plan.set_aggregation(query, None).expect("aggregation not lifted");
+    assert_eq!(plan.aggregation(query), None);
+    plan.set_projection(query, Some(vec![0])).expect("malformed projection");
+}
+
+#[test]
+fn deterministic_plans_report_changes_in_the_same_order() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    // the changes each worker observes to the matches of each query node, in the order it observes them.
+    let trace = |base: Vec<Edge>, batches: Vec<Vec<(Edge, i32)>>| {
+        let observed = Arc::new(Mutex::new(BTreeMap::new()));
+        let shared = observed.clone();
+        let guards = timely::execute(Configuration::Process(2), move |worker| {
+            let index = worker.index();
+            let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+            plan.set_deterministic(true);
+            let observed = shared.clone();
+            let (mut initially, mut updates) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (graph, _) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| (k as u64) * 7);
+                for (query, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                    let observed = observed.clone();
+                    matches.inspect_batch(move |time, xs| {
+                        let mut observed = observed.lock().unwrap();
+                        observed.entry((index, query)).or_insert(Vec::new()).extend(xs.iter().map(|x| (time.inner, x.0.clone(), x.1)));
+                    });
+                }
+                (initially, updates)
+            });
+            if index == 0 { initially.send_batch(&mut base.clone()); }
+            initially.close();
+            for batch in batches.iter() {
+                updates.advance_to(updates.time().inner + 1);
+                // each worker introduces half of the batch, as drivers reading partitioned input would.
+                let mut mine = batch.iter().enumerate().filter(|x| x.0 % 2 == index).map(|x| *x.1).collect::<Vec<_>>();
+                updates.send_batch(&mut mine);
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start workers");
+        for result in guards.join() { result.expect("worker failed"); }
+        let observed = observed.lock().unwrap().clone();
+        observed
+    };
+
+    let first = trace(base.clone(), batches.clone());
+    let second = trace(base.clone(), batches.clone());
+    assert_eq!(first, second, "deterministic runs observed changes in different orders");
+
+    // the changes are those a single worker reports, in whatever order.
+    let mut observed = first.into_iter().flat_map(|((_, query), changes)| changes.into_iter().map(move |x| (x.0, query, x.1, x.2))).collect::<Vec<_>>();
+    observed.sort();
+    let mut consolidated: Vec<(u32, usize, Vec<Node>, i64)> = Vec::new();
+    for (epoch, query, embedding, diff) in observed {
+        let merge = match consolidated.last_mut() {
+            Some(&mut (e, q, ref m, ref mut d)) if e == epoch && q == query && *m == embedding => { *d += diff; true },
+            _ => false,
+        };
+        if !merge { consolidated.push((epoch, query, embedding, diff)); }
+    }
+    consolidated.retain(|x| x.3 != 0);
+    let expected = run_deterministic(|| read_plan_from(TRIANGLE_PLAN.as_bytes()), base, batches);
+    let expected = expected.iter().flat_map(|stats| stats.embeddings.iter().map(move |x| (stats.epoch, x.0, x.1.clone(), x.2))).collect::<Vec<_>>();
+    assert!(!expected.is_empty(), "no changes in the fixture");
+    assert_eq!(consolidated, expected);
+}
+
+#[test]
+fn standing_matches_sum_reported_changes() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let results = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (results, base, batches) = (results.clone(), base.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Process(2), move |worker| {
+            let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+            plan.set_standing_matches(true);
+            let index = worker.index();
+            let mut engine: Engine<_> = Engine::recording(worker, &plan);
+            engine.load_base(if index == 0 { base.clone() } else { Vec::new() });
+            for batch in batches.iter() {
+                let stats = engine.step(if index == 0 { batch.clone() } else { Vec::new() });
+                let standing = engine.standing_matches().expect("standing matches not held").dump();
+                results.lock().unwrap().push((stats, standing));
+            }
+            engine.finish();
+        }).expect("failed to start workers");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    // between epochs, the matches the workers hold are the sums of the changes reported so far.
+    let results = results.lock().unwrap();
+    let mut expected = BTreeMap::new();
+    for (epoch, _) in batches.iter().enumerate() {
+        let epoch = epoch as u32 + 1;
+        let mut standing = BTreeMap::new();
+        for &(ref stats, ref held) in results.iter().filter(|x| x.0.epoch == epoch) {
+            for &(query, ref embedding, diff) in stats.embeddings.iter() {
+                *expected.entry((query, embedding.clone())).or_insert(0) += diff;
+            }
+            assert!(held.is_empty() || stats.standing_bytes > 0, "standing matches take no memory");
+            for &(query, ref embedding, count) in held.iter() {
+                assert!(standing.insert((query, embedding.clone()), count).is_none(), "{:?} held by both workers", embedding);
+            }
+        }
+        expected.retain(|_, diff| *diff != 0);
+        assert_eq!(standing, expected, "standing matches differ after epoch {}", epoch);
+    }
+    assert!(!expected.is_empty(), "no standing matches in the fixture");
+}
+
+#[test]
+fn motifs_are_planned_from_their_edges() {
+    let patterns: &[&'static [(usize, usize)]] = &[
+        &[(0, 1), (1, 2), (2, 0)],
+        &[(0, 1), (0, 2), (1, 3), (2, 3)],
+    ];
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    for &pattern in patterns.iter() {
+        let query = plan_motif(pattern).unwrap_or_else(|error| panic!("couldn't plan {:?}: {}", pattern, error));
+        let vertices = query.variables.len();
+        assert_eq!(query.plan.queries().len(), pattern.len());
+
+        let results = run_deterministic(move || plan_motif(pattern).unwrap().plan, base.clone(), batches.clone());
+        assert_counts(&results, &base, &batches, vertices, pattern);
+    }
+
+    // a motif is planned as the rule listing its edges.
+    let rule = parse_query("motif(x0, x1, x2) :- edge(x0, x1), edge(x1, x2), edge(x2, x0)").unwrap();
+    assert_eq!(plan_motif(&[(0, 1), (1, 2), (2, 0)]).unwrap().plan.fingerprint(), rule.plan.fingerprint());
+
+    for pattern in [&[(0, 1)][..], &[(0, 1), (1, 1)], &[(0, 1), (0, 1)], &[(0, 1), (2, 3)], &[(0, 2), (2, 0)]].iter() {
+        assert!(plan_motif(pattern).is_err(), "{:?} was planned", pattern);
+    }
+}
+
+#[test]
+fn combined_plans_share_stages() {
+    let triangles = || plan_motif(&[(0, 1), (0, 2), (1, 2)]).unwrap().plan;
+    let cliques = || plan_motif(&[(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]).unwrap().plan;
+    let combined = move || Plan::combine(&[&triangles(), &cliques()]).expect("plans not combined").0;
+
+    // a plan combined with itself executes each of its edges once for both copies.
+    let (twice, indices) = Plan::combine(&[&triangles(), &triangles()]).expect("plans not combined");
+    let edges = triangles().to_document().edges.len();
+    assert_eq!(twice.shared_stages().len(), edges);
+    assert!(twice.shared_stages().iter().all(|stage| stage.len() == 2));
+    assert_eq!(twice.queries().len(), 2 * triangles().queries().len());
+    assert!(indices[0].iter().zip(indices[1].iter()).skip(1).all(|(x, y)| x != y), "nodes of the two plans coincide");
+    assert!(triangles().shared_stages().is_empty());
+
+    let (_, indices) = Plan::combine(&[&triangles(), &cliques()]).expect("plans not combined");
+    assert!(!combined().shared_stages().is_empty(), "triangles and 4-cliques share no stages");
+    let mut undirected = cliques();
+    undirected.set_orientation(Orientation::Undirected);
+    assert!(Plan::combine(&[&triangles(), &undirected]).is_err());
+    assert!(Plan::combine(&[]).is_err());
+
+    // each query node of the combined plan reports what it did in its own plan.
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let results = run_deterministic(combined, base.clone(), batches.clone());
+    let alone = vec![run_deterministic(triangles, base.clone(), batches.clone()), run_deterministic(cliques, base, batches)];
+    for (plan, expected) in alone.iter().enumerate() {
+        for (epoch, (x, y)) in expected.iter().zip(results.iter()).enumerate() {
+            let renamed = x.matches.iter().map(|&(query, diff)| (indices[plan][query], diff)).collect::<Vec<_>>();
+            let found = y.matches.iter().filter(|x| renamed.iter().any(|y| y.0 == x.0)).cloned().collect::<Vec<_>>();
+            assert_eq!(found, renamed, "plan {} differs when combined, in epoch {}", plan, epoch);
+        }
+    }
+}
+
+#[test]
+fn pinned_vertices_restrict_matches() {
+    let triangle = [(0, 1), (0, 2), (1, 2)];
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    // the triangles through `vertex`, as the changes each batch makes to them.
+    let changes = |vertex: Node| {
+        let mut graph = Graph::from(&base);
+        let mut changes = Vec::new();
+        for batch in batches.iter() {
+            let count = |graph: &Graph| graph.embeddings(3, &triangle).iter().filter(|x| x.0[0] == vertex).map(|x| x.1).sum::<i64>();
+            let before = count(&graph);
+            graph.apply(batch);
+            changes.push(count(&graph) - before);
+        }
+        changes
+    };
+    // the vertex whose triangles change most, so that the pin has something to keep.
+    let vertex = base.iter().map(|edge| edge.0)
+        .max_by_key(|&vertex| (changes(vertex).iter().map(|x| x.abs()).sum::<i64>(), ::std::cmp::Reverse(vertex)))
+        .expect("empty fixture");
+    assert!(changes(vertex).iter().any(|&x| x != 0), "no triangles change in the fixture");
+
+    let rule = format!("through(b, c) :- edge({0}, b), edge({0}, c), edge(b, c).", vertex);
+    let query = parse_query(&rule).unwrap_or_else(|error| panic!("couldn't parse {}: {}", rule, error));
+    assert_eq!(query.variables, vec!["b".to_owned(), "c".to_owned()]);
+    assert!(query.plan.queries().into_iter().all(|node| query.positions.iter().any(|x| x.0 == node && x.1.len() == 2)));
+
+    let results = {
+        let rule = rule.clone();
+        run_deterministic(move || parse_query(&rule).unwrap().plan, base.clone(), batches.clone())
+    };
+    assert_eq!(total(&results[0]), 0, "loading the base graph produced matches");
+    let mut graph = Graph::from(&base);
+    for (epoch, (stats, batch)) in results[1 ..].iter().zip(batches.iter()).enumerate() {
+        graph.apply(batch);
+        assert_eq!(total(stats), changes(vertex)[epoch], "changes in triangles through {} differ in batch {}", vertex, epoch);
+        for &(node, ref embedding, _) in stats.embeddings.iter().filter(|x| x.2 > 0) {
+            let projected = query.project(node, embedding);
+            assert!(graph.0.contains_key(&(vertex, projected[0])) && graph.0.contains_key(&(vertex, projected[1])) && graph.0.contains_key(&(projected[0], projected[1])),
+                    "{:?} of {} is not a triangle through {}", projected, node, vertex);
+        }
+    }
+
+    // pins are part of the document, and only name positions their node binds.
+    let mut plan = plan_motif(&triangle).unwrap().plan;
+    let query = plan.queries()[0];
+    plan.set_constant(query, 0, Some(vertex)).expect("vertex not pinned");
+    assert_eq!(plan.constants(query), vec![(0, vertex)]);
+    let document = plan.to_document();
+    assert_eq!(document.nodes[query].constants, vec![(0, vertex)]);
+    assert_eq!(document.build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.set_constant(query, 3, Some(vertex)).is_err());
+    assert!(plan.set_constant(99, 0, Some(vertex)).is_err());
+    plan.set_constant(query, 0, None).expect("pin not lifted");
+    assert!(plan.constants(query).is_empty());
+    assert!(parse_query("through(b, c) :- edge(4x, b), edge(b, c)").is_err());
+}
+
+#[test]
+fn parameters_are_bound_as_the_plan_runs() {
+    let rule = "through(b, c) :- edge($v, b), edge($v, c), edge(b, c).";
+    let query = parse_query(rule).expect("rule not parsed");
+    assert_eq!(query.parameters, vec!["v".to_owned()]);
+    assert_eq!(query.plan.parameter_slots(), 1);
+    assert!(query.plan.queries().into_iter().all(|node| query.plan.constants(node).is_empty()));
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    // each batch is run with its own anchor, the sources of the base graph in turn.
+    let mut anchors = base.iter().map(|edge| edge.0).collect::<Vec<_>>();
+    anchors.sort();
+    anchors.dedup();
+    let anchors = batches.iter().enumerate().map(|(epoch, _)| anchors[epoch % anchors.len()]).collect::<Vec<_>>();
+
+    let results = Arc::new(Mutex::new(Vec::new()));
+    {
+        let (results, base, batches, anchors) = (results.clone(), base.clone(), batches.clone(), anchors.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let plan = parse_query(rule).unwrap().plan;
+            let mut engine = Engine::recording(worker, &plan);
+            engine.load_base(base.clone());
+            for (batch, &anchor) in batches.iter().zip(anchors.iter()) {
+                engine.bind(vec![anchor]);
+                let stats = engine.step(batch.clone());
+                engine.unbind(vec![anchor]);
+                results.lock().unwrap().push(stats);
+            }
+            engine.finish();
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let results = results.lock().unwrap();
+    let mut graph = Graph::from(&base);
+    for (epoch, (stats, batch)) in results.iter().zip(batches.iter()).enumerate() {
+        let anchor = anchors[epoch];
+        let count = |graph: &Graph| graph.embeddings(3, &[(0, 1), (0, 2), (1, 2)]).iter().filter(|x| x.0[0] == anchor).map(|x| x.1).sum::<i64>();
+        let before = count(&graph);
+        graph.apply(batch);
+        assert_eq!(total(stats), count(&graph) - before, "changes in triangles through {} differ in batch {}", anchor, epoch);
+        for &(node, ref embedding, _) in stats.embeddings.iter() {
+            assert!(embedding.contains(&anchor), "{:?} of {} does not hold anchor {}", embedding, node, anchor);
+            assert_eq!(query.project(node, embedding).len(), 2);
+        }
+    }
+
+    // parameters are part of the document, and only name positions their node binds.
+    let mut plan = plan_motif(&[(0, 1), (0, 2), (1, 2)]).unwrap().plan;
+    let node = plan.queries()[0];
+    plan.set_parameter(node, 2, Some(1)).expect("vertex not pinned");
+    assert_eq!(plan.parameters(node), vec![(2, 1)]);
+    assert_eq!(plan.parameter_slots(), 2);
+    let document = plan.to_document();
+    assert_eq!(document.nodes[node].parameters, vec![(2, 1)]);
+    assert_eq!(document.build().expect("malformed document").fingerprint(), plan.fingerprint());
+    assert!(plan.set_parameter(node, 3, Some(0)).is_err());
+    plan.set_parameter(node, 2, None).expect("pin not lifted");
+    assert_eq!(plan.parameter_slots(), 0);
+}
+
+#[test]
+fn optimized_plans_match_brute_force() {
+    let patterns: &[(usize, &'static [(usize, usize)])] = &[
+        (3, &[(0, 1), (0, 2), (2, 1)]),
+        (4, &[(0, 1), (1, 2), (2, 3), (3, 0)]),
+        (4, &[(0, 1), (0, 2), (1, 3), (2, 3), (1, 2)]),
+    ];
+
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let measured = DegreeStatistics::from_edges(&base);
+    assert_eq!(measured.edges, base.len() as u64);
+    // a few sources of high out-degree, whose out-going edges should be checked rather than followed.
+    let skewed = DegreeStatistics { edges: 1000, sources: 10, destinations: 1000, out_squares: 100_000, in_squares: 1000 };
+
+    for &statistics in [measured, skewed].iter() {
+        let optimizer = Optimizer::new(statistics);
+        for &(vertices, pattern) in patterns.iter() {
+            let optimized = optimizer.optimize(vertices, pattern).expect("pattern rejected");
+            assert_eq!(optimized.plan.queries().len(), pattern.len());
+
+            // no binding order the parser might choose is estimated to be cheaper.
+            for (chain, &(src, dst)) in optimized.chains.iter().zip(pattern.iter()) {
+                assert_eq!(&chain.order[.. 2], &[src, dst]);
+                let mut reversed = chain.order.clone();
+                reversed[2 ..].reverse();
+                if reversed[2 ..].iter().enumerate().all(|(index, &vertex)| pattern.iter().any(|&(from, to)| {
+                    (from == vertex && reversed[.. index + 2].contains(&to)) || (to == vertex && reversed[.. index + 2].contains(&from))
+                })) {
+                    assert!(optimizer.cost(pattern, &chain.order) <= optimizer.cost(pattern, &reversed));
+                }
+            }
+
+            let optimizer = optimizer.clone();
+            let results = run_deterministic(move || optimizer.optimize(vertices, pattern).unwrap().plan, base.clone(), batches.clone());
+            assert_counts(&results, &base, &batches, vertices, pattern);
+        }
+    }
+
+    let optimized = Optimizer::new(skewed).optimize(3, &[(0, 1), (0, 2), (2, 1)]).unwrap();
+    assert_eq!(optimized.chains[0].checked, vec![(0, 2)]);
+    assert!(Optimizer::new(skewed).optimize(4, &[(0, 1), (2, 3)]).is_err());
+    assert!(Optimizer::new(skewed).optimize(3, &[(0, 1), (1, 1)]).is_err());
+}
+
+#[test]
+fn malformed_queries_are_rejected() {
+    for rule in ["tri(a,b,c) :- edge(a,b), edge(b,c), edge(c,a) extra",
+                 "tri(a,b,c) :- edge(a,b), edge(b,c), node(c)",
+                 "tri(a,b,c) :- edge(a,b), edge(b,c,a)",
+                 "tri(a,b) :- edge(a,b), edge(b,c)",
+                 "tri(a,b,c,d) :- edge(a,b), edge(b,c)",
+                 "tri(a,b,a) :- edge(a,b), edge(b,a)",
+                 "loop(a,b) :- edge(a,b), edge(b,b)",
+                 "twice(a,b,c) :- edge(a,b), edge(a,b), edge(b,c)",
+                 "apart(a,b,c,d) :- edge(a,b), edge(c,d)",
+                 "one(a,b) :- edge(a,b)",
+                 "tri(a,b,c) edge(a,b), edge(b,c)"].iter() {
+        assert!(parse_query(rule).is_err(), "{} was accepted", rule);
+    }
+    let error = parse_query("tri(a,b,c) :- edge(a,b), edge(b,c), node(c)").unwrap_err();
+    assert!(error.starts_with("column 37"), "{}", error);
+}
+
+#[test]
+fn schemas_check_queries() {
+    let schema = Schema::parse("# a social graph\nrelation follows\nrelation likes\n\nvertex_label person 1\nedge_label knows 3\nattribute age int\n").expect("malformed schema");
+    assert_eq!(schema.relations(), &["follows".to_owned(), "likes".to_owned()]);
+    assert_eq!(schema.vertex_label("person"), Ok(1));
+    assert_eq!(schema.edge_label_name(3), Some("knows"));
+    assert_eq!(schema.attribute("age"), Ok(AttributeType::Int));
+    assert!(schema.vertex_label("place").is_err());
+    assert!(schema.attribute("height").is_err());
+
+    // a query over a declared relation plans as the same query over `edge` does.
+    let rule = "tri(a, b, c) :- follows(a, b), follows(a, c), follows(b, c)";
+    let query = parse_query_with(rule, &schema).expect("query rejected");
+    assert_eq!(query.relation, "follows");
+    let expected = parse_query("tri(a, b, c) :- edge(a, b), edge(a, c), edge(b, c)").unwrap();
+    assert_eq!(query.plan.fingerprint(), expected.plan.fingerprint());
+
+    assert!(parse_query(rule).is_err(), "the default schema declares only edge");
+    assert!(parse_query_with("tri(a, b, c) :- edge(a, b), edge(a, c), edge(b, c)", &schema).is_err());
+    assert!(parse_query_with("tri(a, b, c) :- follows(a, b), likes(a, c), follows(b, c)", &schema).is_err());
+
+    for text in ["relation edge\nrelation edge", "vertex_label person 1\nvertex_label place 1", "attribute age integer",
+                 "relation 2hop", "edge_label knows three", "relation"].iter() {
+        assert!(Schema::parse(text).is_err(), "{:?} was accepted", text);
+    }
+}
+
+#[test]
+fn pair_existence_follows_brute_force() {
+    let pattern = [(0, 1), (0, 2), (1, 2)];
+    let mut batches = vec![read_edges("base.txt").into_iter().map(|edge| (edge, 1)).collect::<Vec<_>>()];
+    batches.extend(read_batches("updates.log", 4));
+    let reported = Arc::new(Mutex::new(Vec::new()));
+
+    {
+        let (reported, batches) = (reported.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let query = parse_query("tri(a, b, c) :- edge(a, b), edge(a, c), edge(b, c)").unwrap();
+            let reported = reported.clone();
+
+            let (initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+
+                // the pair (a, b) of each query node's matches, wherever the node binds them.
+                let mut pairs = None;
+                for (node, matches) in query.plan.track_motif::<i64, _, _, _>(&graph) {
+                    let positions = &query.positions.iter().find(|x| x.0 == node).expect("query node without positions").1;
+                    let anchored = anchor_pairs(&matches, (positions[0], positions[1]));
+                    pairs = Some(match pairs { Some(pairs) => anchored.concat(&pairs), None => anchored });
+                }
+
+                let mut probe = ProbeHandle::new();
+                pair_existence(&pairs.expect("plan without query nodes"))
+                    .inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|&(pair, diff)| (time.inner, pair, diff))))
+                    .probe_with(&mut probe);
+                (initially, updates, probe, handles)
+            });
+
+            // every edge is an update, so that the pairs the first batch supports are reported.
+            initially.close();
+            for batch in batches.iter() {
+                updates.send_batch(&mut batch.clone());
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let reported = reported.lock().unwrap();
+    let mut graph = Graph::default();
+    let mut present = BTreeMap::new();
+    let mut supported = false;
+    for (epoch, batch) in batches.iter().enumerate() {
+        graph.apply(batch);
+        for &(_, pair, diff) in reported.iter().filter(|x| x.0 == epoch as u32) {
+            assert!(diff == 1 || diff == -1, "pair {:?} changed by {} in epoch {}", pair, diff, epoch);
+            *present.entry(pair).or_insert(0) += diff;
+        }
+        present.retain(|_, count| *count != 0);
+        let expected = graph.embeddings(3, &pattern).into_iter().map(|(embedding, _)| ((embedding[0], embedding[1]), 1)).collect::<BTreeMap<_, _>>();
+        assert_eq!(present, expected, "supported pairs differ from brute force after epoch {}", epoch);
+        supported |= !present.is_empty();
+    }
+    assert!(supported, "workload supports no pairs");
+}
+
+#[test]
+fn sampled_counts_carry_error_bars() {
+    let pattern = [(0, 1), (0, 2), (1, 2)];
+    let mut batches = vec![read_edges("base.txt").into_iter().map(|edge| (edge, 1)).collect::<Vec<_>>()];
+    batches.extend(read_batches("updates.log", 4));
+    let reported = Arc::new(Mutex::new(Vec::new()));
+
+    {
+        let (reported, batches) = (reported.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let plan = parse_query("tri(a, b, c) :- edge(a, b), edge(a, c), edge(b, c)").unwrap().plan;
+            let reported = reported.clone();
+            let rate = SamplingRate::new(0.5);
+
+            let (initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+
+                // each query node's matches counted in full, as `(0, node)`, and sampled, as `(1, node)`.
+                let mut counted = None;
+                for (node, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                    let full = matches.map(move |(_, diff)| ((0, node), diff, 1.0));
+                    let sampled = matches.sample_tracked(&rate, 7).map(move |(_, diff, probability)| ((1, node), diff, probability));
+                    let both = full.concat(&sampled);
+                    counted = Some(match counted { Some(counted) => both.concat(&counted), None => both });
+                }
+
+                let mut probe = ProbeHandle::new();
+                estimate_counts(&counted.expect("plan without query nodes"))
+                    .inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|&(key, estimate)| (time.inner, key, estimate))))
+                    .probe_with(&mut probe);
+                (initially, updates, probe, handles)
+            });
+
+            // every edge is an update, and load is shed by sampling more sparsely after a few epochs.
+            initially.close();
+            for (epoch, batch) in batches.iter().enumerate() {
+                if epoch == 3 { rate.set(0.25); }
+                updates.send_batch(&mut batch.clone());
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let reported = reported.lock().unwrap();
+    let mut graph = Graph::default();
+    let mut totals = BTreeMap::new();
+    let mut uncertain = false;
+    for (epoch, batch) in batches.iter().enumerate() {
+        graph.apply(batch);
+        for &(_, key, estimate) in reported.iter().filter(|x| x.0 == epoch as u32) {
+            totals.insert(key, estimate.total);
+        }
+        let sum = |tag| totals.iter().filter(|x| (x.0).0 == tag).fold(Estimate::exact(0.0), |sum, x| sum.add(x.1));
+        let (full, sampled) = (sum(0), sum(1));
+
+        // counts retained with probability one are exact, and sampled counts within their error bars.
+        let expected = graph.embeddings(3, &pattern).len() as f64;
+        assert_eq!(full, Estimate::exact(expected), "full count differs from brute force after epoch {}", epoch);
+        let (low, high) = sampled.interval(4.0);
+        assert!(low <= expected && expected <= high, "estimate {:?} far from {} after epoch {}", sampled, expected, epoch);
+        uncertain |= sampled.stderr > 0.0;
+    }
+    assert!(uncertain, "sampled counts reported no uncertainty");
+}
+
+#[test]
+fn degrees_follow_the_graph() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+
+    {
+        let (reported, base, batches) = (reported.clone(), base.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let reported = reported.clone();
+
+            let (mut initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+
+                let mut probe = ProbeHandle::new();
+                graph.degrees()
+                    .inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|&(degrees, diff)| (time.inner, degrees, diff))))
+                    .probe_with(&mut probe);
+                (initially, updates, probe, handles)
+            });
+
+            // the base is loaded in the first epoch, and each batch of updates in an epoch after it.
+            initially.send_batch(&mut base.clone());
+            initially.close();
+            for mut batch in Some(Vec::new()).into_iter().chain(batches.iter().cloned()) {
+                updates.send_batch(&mut batch);
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    let reported = reported.lock().unwrap();
+    let mut graph = Graph::from(&base);
+    let mut degrees = BTreeMap::new();
+    for epoch in 0 .. batches.len() + 1 {
+        if epoch > 0 { graph.apply(&batches[epoch - 1]); }
+        for &(_, entry, diff) in reported.iter().filter(|x| x.0 == epoch as u32) {
+            *degrees.entry(entry).or_insert(0) += diff;
+        }
+        degrees.retain(|_, count| *count != 0);
+
+        let mut counts = BTreeMap::new();
+        for (&(src, dst), &count) in graph.0.iter() {
+            counts.entry(src).or_insert((0, 0)).0 += count as u64;
+            counts.entry(dst).or_insert((0, 0)).1 += count as u64;
+        }
+        let expected = counts.into_iter().map(|(node, (out, into))| ((node, out, into), 1)).collect::<BTreeMap<_, _>>();
+        assert_eq!(degrees, expected, "degrees differ from the graph after epoch {}", epoch);
+    }
+}
+
+#[test]
+fn limited_extensions_keep_the_first_neighbors() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    for &limit in [ExtensionLimit { k: 2, order: LimitOrder::Id }, ExtensionLimit { k: 1, order: LimitOrder::Weight }].iter() {
+        // the path `x0 -> x1 -> x2`, with every operation binding a vertex limited if `limit` is some.
+        fn path(limit: Option<ExtensionLimit>) -> Plan {
+            let mut plan = plan_motif(&[(0, 1), (1, 2)]).unwrap().plan;
+            let document = plan.to_document();
+            for (index, edge) in document.edges.iter().enumerate() {
+                for (position, operation) in edge.operations.iter().enumerate() {
+                    if operation.kind == OperationKind::Extension {
+                        plan.set_extension_limit(index, position, limit).expect("extension not limited");
+                        assert_eq!(plan.extension_limit(index, position), limit);
+                    }
+                }
+            }
+            plan
+        }
+        let plan = path(None);
+        let document = plan.to_document();
+        assert!(path(Some(limit)).set_extension_limit(0, 0, Some(ExtensionLimit { k: 0, order: LimitOrder::Id })).is_err());
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        {
+            let (reported, base, batches) = (reported.clone(), base.clone(), batches.clone());
+            let guards = timely::execute(Configuration::Thread, move |worker| {
+                let reported = reported.clone();
+                let (plan, limited) = (path(None), path(Some(limit)));
+                let (mut initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                    let (initially, graph) = builder.new_input::<Edge>();
+                    let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                    let (graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+
+                    // the matches of each query node, as `(false, node)` in full and `(true, node)` limited.
+                    let mut probe = ProbeHandle::new();
+                    for (tag, plan) in vec![(false, &plan), (true, &limited)] {
+                        for (node, matches) in plan.track_motif::<i64, _, _, _>(&graph) {
+                            let reported = reported.clone();
+                            matches
+                                .inspect_batch(move |time, xs| reported.lock().unwrap().extend(xs.iter().map(|&(ref embedding, diff)| (time.inner, (tag, node), embedding.clone(), diff))))
+                                .probe_with(&mut probe);
+                        }
+                    }
+                    (initially, updates, probe, handles)
+                });
+
+                initially.send_batch(&mut base.clone());
+                initially.close();
+                for mut batch in Some(Vec::new()).into_iter().chain(batches.iter().cloned()) {
+                    updates.send_batch(&mut batch);
+                    let time = updates.time().clone();
+                    updates.advance_to(time.inner + 1);
+                    worker.step_while(|| probe.less_than(updates.time()));
+                    handles.merge_to(&time);
+                }
+                updates.close();
+                while worker.step() { }
+            }).expect("failed to start worker");
+            for result in guards.join() { result.expect("worker failed"); }
+        }
+
+        // the limited operations on the way to each query node, as the positions they read and bind.
+        let limits = |node: usize| {
+            let mut limits = Vec::new();
+            let mut current = node;
+            while let Some(edge) = document.edges.iter().find(|edge| edge.to == current) {
+                for operation in edge.operations.iter().filter(|operation| operation.kind == OperationKind::Extension) {
+                    limits.push((operation.src, operation.dst));
+                }
+                current = edge.from;
+            }
+            limits
+        };
+
+        let reported = reported.lock().unwrap();
+        let mut graph = Graph::from(&base);
+        let mut accumulated = BTreeMap::new();
+        for epoch in 0 .. batches.len() + 1 {
+            if epoch > 0 { graph.apply(&batches[epoch - 1]); }
+            for &(_, key, ref embedding, diff) in reported.iter().filter(|x| x.0 == epoch as u32) {
+                *accumulated.entry((key, embedding.clone())).or_insert(0) += diff;
+            }
+            accumulated.retain(|_, count| *count != 0);
+
+            // the first neighbors of each vertex, out-going and in-coming, under the limit.
+            let first = |vertex: Node, outgoing: bool| {
+                let mut neighbors = graph.0.iter()
+                    .filter(|&(&(src, dst), &count)| count > 0 && if outgoing { src == vertex } else { dst == vertex })
+                    .map(|(&(src, dst), &count)| (if outgoing { dst } else { src }, count))
+                    .collect::<Vec<_>>();
+                match limit.order {
+                    LimitOrder::Id => neighbors.sort(),
+                    LimitOrder::Weight => neighbors.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0))),
+                }
+                neighbors.into_iter().take(limit.k).map(|x| x.0).collect::<Vec<_>>()
+            };
+
+            // the limited matches are the full matches whose limited pairs are among the first neighbors.
+            for node in plan.queries() {
+                let full = accumulated.iter().filter(|x| (x.0).0 == (false, node)).map(|x| ((x.0).1.clone(), *x.1));
+                let expected = full.filter(|&(ref embedding, _)| limits(node).into_iter().all(|(src, dst)| {
+                    if src < dst { first(embedding[src], true).contains(&embedding[dst]) }
+                    else { first(embedding[dst], false).contains(&embedding[src]) }
+                })).collect::<Vec<_>>();
+                let found = accumulated.iter().filter(|x| (x.0).0 == (true, node)).map(|x| ((x.0).1.clone(), *x.1)).collect::<Vec<_>>();
+                assert_eq!(found, expected, "query node {} limited to {:?} differs after epoch {}", node, limit, epoch);
+            }
+        }
+    }
+
+    // plan files limit operations by id or by weight, and only those binding a vertex.
+    let lines = TRIANGLE_PLAN.lines().collect::<Vec<_>>();
+    let edit = |line: usize, text: &'static str| {
+        let mut lines = lines.clone();
+        lines[line - 1] = text;
+        lines.join("\n")
+    };
+    let plan = Plan::from_reader(edit(11, "  0 2 1 first=2").as_bytes()).expect("limited plan not read");
+    assert_eq!(plan.extension_limit(0, 0), Some(ExtensionLimit { k: 2, order: LimitOrder::Id }));
+    let plan = Plan::from_reader(edit(11, "  0 2 1 heaviest=3").as_bytes()).expect("limited plan not read");
+    assert_eq!(plan.extension_limit(0, 0), Some(ExtensionLimit { k: 3, order: LimitOrder::Weight }));
+    assert_eq!(Plan::from_reader(edit(11, "  0 2 1 first=some").as_bytes()).err().and_then(|error| error.line()), Some(11));
+    assert_eq!(Plan::from_reader(edit(19, "  1 2 1 first=2").as_bytes()).err().and_then(|error| error.line()), Some(18));
+}
+
+#[test]
+fn built_plans_match_plan_files() {
+    // the triangle plan, node for node and edge for edge.
+    let plan = Plan::builder()
+        .add_node(NodeId(1), false)
+        .add_node(NodeId(2), true)
+        .add_node(NodeId(3), true)
+        .add_node(NodeId(4), true)
+        .add_edge(NodeId::ROOT, NodeId(1))
+        .extension(Vertex(0), Vertex(2))
+        .add_edge(NodeId::ROOT, NodeId(2))
+        .extension(Vertex(2), Vertex(0))
+        .extension(Vertex(2), Vertex(1))
+        .add_edge(NodeId::ROOT, NodeId(3))
+        .extension(Vertex(0), Vertex(2))
+        .extension(Vertex(2), Vertex(1))
+        .add_edge(NodeId(1), NodeId(4))
+        .intersection(Vertex(1), Vertex(2))
+        .build()
+        .expect("malformed plan");
+    assert_eq!(plan.fingerprint(), read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+
+    let triangle = || Plan::builder().add_node(NodeId(1), true).add_edge(NodeId::ROOT, NodeId(1));
+    assert!(triangle().extension(Vertex(0), Vertex(2)).extension(Vertex(1), Vertex(2)).build().is_ok());
+    assert!(triangle().extension(Vertex(0), Vertex(2)).hint(JoinHint::Intersect).extension(Vertex(1), Vertex(2)).build().is_ok());
+    let rejected = vec![
+        ("no operations", triangle()),
+        ("extension missing the new vertex", triangle().extension(Vertex(0), Vertex(1))),
+        ("extension past the new vertex", triangle().extension(Vertex(0), Vertex(3))),
+        ("intersection of an unbound vertex", triangle().extension(Vertex(0), Vertex(2)).intersection(Vertex(1), Vertex(2))),
+        ("self-join", triangle().extension(Vertex(2), Vertex(2))),
+        ("every extension checked", triangle().extension(Vertex(0), Vertex(2)).hint(JoinHint::Intersect)),
+        ("extension hinted to extend through a direction", triangle().extension(Vertex(0), Vertex(2)).direction(IntersectDirection::Reverse)),
+        ("node out of order", Plan::builder().add_node(NodeId(2), true)),
+        ("edge to an undeclared node", Plan::builder().add_edge(NodeId::ROOT, NodeId(1))),
+        ("operation before any edge", Plan::builder().add_node(NodeId(1), true).extension(Vertex(0), Vertex(2))),
+        ("no query nodes", Plan::builder().add_node(NodeId(1), false).add_edge(NodeId::ROOT, NodeId(1)).extension(Vertex(0), Vertex(2))),
+        ("unreachable node", Plan::builder().add_node(NodeId(1), true)),
+        ("node reached twice", triangle().extension(Vertex(0), Vertex(2)).add_edge(NodeId::ROOT, NodeId(1)).extension(Vertex(1), Vertex(2))),
+    ];
+    for (problem, builder) in rejected {
+        assert!(builder.build().is_err(), "plan with {} was built", problem);
+    }
+}
+
+#[test]
+fn introspection_reports_stages_and_buffers() {
+    let introspection = Introspection::new();
+    let registry = introspection.clone();
+    let guards = timely::execute(Configuration::Thread, move |worker| {
+        let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        introspection.register_plan(&plan);
+        let mut engine: Engine<_> = Engine::new(worker, &plan);
+        engine.set_introspection(Some(introspection.clone()));
+        engine.load_base(read_edges("base.txt"));
+
+        // a completed epoch leaves no stage behind, and each query node names its operator.
+        let report = engine.introspect();
+        assert_eq!(report.stages.len(), 3 + plan.queries().len());
+        assert!(report.holding_back().is_none(), "stage behind after completing an epoch: {:?}", report.holding_back());
+        let operators = report.stages.iter().filter_map(|stage| stage.operator.clone()).collect::<Vec<_>>();
+        assert_eq!(operators.len(), plan.queries().len());
+        // timely addresses operators by worker, dataflow, and position within the dataflow.
+        assert!(operators.iter().all(|address| address.len() == 3 && address[.. 2] == operators[0][.. 2]), "operators not addressed within their dataflow: {:?}", operators);
+
+        // updates of an uncommitted batch wait in the engine.
+        engine.push_updates(&[((1, 2), 1), ((2, 3), 1)]);
+        let pending = engine.introspect().buffers.into_iter().find(|x| x.0 == "pending").expect("no pending buffer");
+        assert_eq!(pending.1, 2);
+        engine.commit_batch();
+        engine.finish();
+    }).expect("failed to start worker");
+    for result in guards.join() { result.expect("worker failed"); }
+
+    let plan = registry.plan().expect("no plan registered");
+    assert_eq!(plan.fingerprint, read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+    assert_eq!(plan.queries.iter().map(|query| query.node).collect::<Vec<_>>(), vec![2, 3, 4]);
+    let workers = registry.workers();
+    assert_eq!(workers.len(), 1);
+    assert!(workers[0].buffers.iter().all(|x| x.1 == 0), "buffers left after the last epoch: {:?}", workers[0].buffers);
+    let rendered = registry.render();
+    assert!(rendered.contains("\"holding_back\":null") && rendered.contains("\"name\":\"query 2\""), "unexpected rendering: {}", rendered);
+}
+
+#[test]
+fn malformed_plan_files_are_reported() {
+    let plan = Plan::from_reader(TRIANGLE_PLAN.as_bytes()).expect("malformed plan");
+    assert_eq!(plan.fingerprint(), read_plan_from(TRIANGLE_PLAN.as_bytes()).fingerprint());
+
+    let lines = TRIANGLE_PLAN.lines().collect::<Vec<_>>();
+    let edit = |line: usize, text: &'static str| {
+        let mut lines = lines.clone();
+        lines[line - 1] = text;
+        lines.join("\n")
+    };
+    let error = Plan::from_reader(edit(3, "five").as_bytes()).err().expect("malformed plan was read");
+    assert_eq!(error, PlanError::Syntax { line: 3, expected: "the number of nodes".to_owned(), found: Some("five".to_owned()) });
+    assert_eq!(error.to_string(), "line 3: expected the number of nodes, found \"five\"");
+
+    // each problem is reported on its line, as a syntax error or as an invalid plan.
+    let problems = vec![
+        ("a query flag of 2", edit(5, "3 1 3 2"), 5, true),
+        ("an unknown operation field", edit(11, "  0 2 1 sideways"), 11, true),
+        ("a missing operation", lines[.. 18].join("\n"), 19, true),
+        ("a root past the nodes", edit(2, "9"), 2, false),
+        ("an edge to a missing node", edit(18, "1 7 1"), 18, false),
+        ("an operation past the new vertex", edit(11, "  0 3 1"), 11, false),
+        ("a self-join", edit(11, "  2 2 1"), 11, false),
+        ("an edge left nothing to extend with", edit(11, "  0 2 1 intersect"), 10, false),
+    ];
+    for (problem, text, line, syntax) in problems {
+        let error = Plan::from_reader(text.as_bytes()).err().unwrap_or_else(|| panic!("plan with {} was read", problem));
+        assert_eq!(error.line(), Some(line), "plan with {}: {}", problem, error);
+        let is_syntax = match error { PlanError::Syntax { .. } => true, _ => false };
+        assert_eq!(is_syntax, syntax, "plan with {}: {}", problem, error);
+    }
+
+    match Plan::from_file(&fixture("no_such_plan.txt")) {
+        Err(PlanError::Io(_)) => { },
+        other => panic!("missing plan file read as {:?}", other.map(|plan| plan.fingerprint())),
+    }
+}
+
+#[test]
+fn inconsistent_plans_are_reported_whole() {
+    assert!(read_plan_from(TRIANGLE_PLAN.as_bytes()).validate().is_empty());
+    assert!(read_plan(&fixture("two_paths_plan.txt")).validate().is_empty());
+
+    let lines = TRIANGLE_PLAN.lines().collect::<Vec<_>>();
+    let edit = |line: usize, text: &'static str| {
+        let mut lines = lines.clone();
+        lines[line - 1] = text;
+        lines.join("\n")
+    };
+
+    // each plan reads line by line, but its parts do not fit together.
+    let problems = vec![
+        ("a node binding too many vertices", edit(8, "4 0 4 1"), vec!["plan edge 3 leads from node 1, binding 3 vertices, to node 4, which should bind 3 but binds 4"]),
+        ("edges past the last", edit(5, "3 2 3 0"), vec!["node 1 has edges 3 to 5, but the plan has 4 edges"]),
+        ("a node reached twice", edit(18, "1 3 1"), vec!["node 3 is reached by 2 edges, and its matches are produced for each", "node 4 is unreachable from the root"]),
+    ];
+    for (problem, text, expected) in problems {
+        let violations = match Plan::from_reader(text.as_bytes()) {
+            Err(PlanError::Inconsistent(violations)) => violations,
+            other => panic!("plan with {} read as {:?}", problem, other.map(|plan| plan.fingerprint())),
+        };
+        for violation in expected {
+            assert!(violations.iter().any(|x| x == violation), "plan with {} reported {:?}", problem, violations);
+        }
+    }
+}
+
+#[test]
+fn plan_documents_rebuild_their_plans() {
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let document = plan.to_document();
+    assert_eq!(document.nodes.len(), 5);
+    assert_eq!(document.edges.len(), 4);
+    assert_eq!(document.edges[3].operations[0], OperationDocument { src: 1, dst: 2, kind: OperationKind::Intersection, hint: None, direction: None, limit: None, anti: false, label: None, edge_label: None, source: None });
+    assert_eq!(document.build().expect("malformed document").fingerprint(), plan.fingerprint());
+
+    // hints, directions, and fixed proposers are part of the document.
+    let tuned = Plan::builder()
+        .add_node(NodeId(1), false)
+        .add_node(NodeId(2), true)
+        .add_edge(NodeId::ROOT, NodeId(1))
+        .extension(Vertex(0), Vertex(2))
+        .propose_first()
+        .add_edge(NodeId(1), NodeId(2))
+        .intersection(Vertex(1), Vertex(2))
+        .direction(IntersectDirection::Adaptive)
+        .build()
+        .expect("malformed plan");
+    assert_eq!(tuned.to_document().build().expect("malformed document").fingerprint(), tuned.fingerprint());
+
+    let mut rootless = document.clone();
+    rootless.nodes.clear();
+    let mut query_root = document.clone();
+    query_root.nodes[0].query = true;
+    let mut misclassified = document.clone();
+    misclassified.edges[0].operations[0].kind = OperationKind::Intersection;
+    let mut dangling = document.clone();
+    dangling.edges[3].to = 7;
+    for (problem, document) in vec![("no root", rootless), ("a query root", query_root), ("an extension read as an intersection", misclassified), ("an edge to no node", dangling)] {
+        assert!(document.build().is_err(), "document with {} was built", problem);
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn plans_round_trip_through_json() {
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let json = plan.to_json();
+    assert_eq!(PlanDocument::from_json(&json).expect("malformed JSON"), plan.to_document());
+    assert_eq!(Plan::from_json(&json).expect("malformed JSON").fingerprint(), plan.fingerprint());
+
+    // the example of the format's documentation, with defaulted fields left out.
+    let written = r#"{
+        "nodes": [{ "query": false }, { "query": true }],
+        "edges": [{
+            "from": 0, "to": 1,
+            "operations": [
+                { "src": 0, "dst": 2, "kind": "extension" },
+                { "src": 1, "dst": 2, "kind": "extension", "hint": "intersect" }
+            ]
+        }]
+    }"#;
+    let written = Plan::from_json(written).expect("malformed JSON");
+    assert_eq!(written.queries(), vec![1]);
+    assert_eq!(written.join_hint(0, 1), Some(JoinHint::Intersect));
+
+    assert!(Plan::from_json("{ \"nodes\": [] }").is_err(), "document without edges was read");
+    assert!(Plan::from_json(&json.replace("extension", "proposal")).is_err(), "unknown operation kind was read");
+}
+
+#[test]
+fn labeled_counting() {
+    let labels = Arc::new(read_vertex_labels(&fixture("labels.txt")).expect("malformed labels"));
+    let counters = Arc::new(RwLock::new(HashMap::new()));
+    let total = Arc::new(Mutex::new(0u64));
+    let errors = ErrorEvents::new();
+
+    let base = read_edges("base.txt");
+    let insertions = read_batches("insertions.txt", 2);
+
+    {
+        let (labels, counters, total, errors) = (labels.clone(), counters.clone(), total.clone(), errors.clone());
+        let (base, insertions) = (base.clone(), insertions.clone());
+        let guards = timely::execute(Configuration::Thread, move |worker| {
+            let mut plan = count_vertex_labeled_query_plan::read_plan_from(TRIANGLE_PLAN.as_bytes());
+            plan.set_error_events(errors.clone());
+
+            let (mut initially, mut updates, probe, handles) = worker.dataflow::<u32,_,_>(|builder| {
+                let (initially, graph) = builder.new_input::<Edge>();
+                let (updates, changes) = builder.new_input::<(Edge, i32)>();
+                let (graph, handles) = GraphStreamIndex::from(graph, changes, |k| k as u64, |k| k as u64);
+                let mut probe = ProbeHandle::new();
+                plan.track_motif(&graph, &mut probe, total.clone(), counters.clone(), labels.clone());
+                (initially, updates, probe, handles)
+            });
+
+            // the base graph is indexed in the first epoch, and matches are found for each batch after it.
+            initially.send_batch(&mut base.clone());
+            initially.close();
+            for batch in insertions.iter() {
+                let time = updates.time().clone();
+                updates.advance_to(time.inner + 1);
+                worker.step_while(|| probe.less_than(updates.time()));
+                handles.merge_to(&time);
+                updates.send_batch(&mut batch.clone());
+            }
+            updates.close();
+            while worker.step() { }
+        }).expect("failed to start worker");
+        for result in guards.join() { result.expect("worker failed"); }
+    }
+
+    assert!(errors.is_empty(), "operators reported errors: {:?}", errors.drain());
+
+    // the matches each query node reports list their vertices in different orders, so compare
+    // the sorted labels of each triangle.
+    let mut reported = BTreeMap::new();
+    for (&(_, ref labeled), count) in counters.read().unwrap().iter() {
+        let mut labeled = labeled.clone();
+        labeled.sort();
+        *reported.entry(labeled).or_insert(0) += *count.lock().unwrap();
+    }
+
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let pattern = plan.pattern_edges(plan.queries()[0]).expect("query unreachable");
+    let mut graph = Graph::from(&base);
+    let mut expected = BTreeMap::new();
+    for (embedding, weight) in graph.embeddings(3, &pattern) {
+        let mut labeled = embedding.iter().map(|node| labels[node]).collect::<Vec<_>>();
+        labeled.sort();
+        *expected.entry(labeled).or_insert(0) -= weight;
+    }
+    for batch in insertions.iter() { graph.apply(batch); }
+    for (embedding, weight) in graph.embeddings(3, &pattern) {
+        let mut labeled = embedding.iter().map(|node| labels[node]).collect::<Vec<_>>();
+        labeled.sort();
+        *expected.entry(labeled).or_insert(0) += weight;
+    }
+    let expected = expected.into_iter().filter(|x| x.1 != 0).map(|(labeled, count)| (labeled, count as u64)).collect::<BTreeMap<_, _>>();
+
+    assert!(!expected.is_empty(), "insertions close no triangles");
+    assert_eq!(reported, expected);
+    assert_eq!(*total.lock().unwrap(), expected.values().sum::<u64>());
+}
+
+#[test]
+fn dot_output_estimates_costs_from_degree_statistics() {
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    assert!(!plan.to_dot().contains("prefixes"));
+
+    let statistics = DegreeStatistics { edges: 64, sources: 16, destinations: 16, out_squares: 256, in_squares: 256 };
+    let dot = plan.to_dot_with_costs(&statistics);
+    let nodes = dot.lines().filter(|line| line.contains("shape=")).collect::<Vec<_>>();
+    let edges = dot.lines().filter(|line| line.contains(" -> ")).collect::<Vec<_>>();
+    assert!(nodes.iter().all(|line| line.contains(" prefixes")));
+    assert!(edges.iter().all(|line| line.contains(" work")));
+    // each update is one prefix of the root.
+    assert!(nodes.iter().any(|line| line.contains("shape=box") && line.contains("~1.00 prefixes")));
+
+    // on a graph without edges, no update extends to a triangle.
+    let dot = plan.to_dot_with_costs(&DegreeStatistics::default());
+    assert!(dot.lines().filter(|line| line.contains("shape=doublecircle")).all(|line| line.contains("~0.00 prefixes")));
+}
+
+#[test]
+fn partitioned_snapshots_of_undirected_plans_resume() {
+    let undirected = || {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        plan.set_orientation(Orientation::Undirected);
+        plan
+    };
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let expected = run_deterministic(undirected, base.clone(), batches.clone());
+
+    // the forward indices of two workers, once the base graph is loaded.
+    let guards = timely::execute(Configuration::Process(2), move |worker| {
+        let (index, peers) = (worker.index(), worker.peers());
+        let plan = undirected();
+        let mut engine: Engine<_> = Engine::new(worker, &plan);
+        engine.load_base(base.iter().cloned().enumerate().filter(|x| x.0 % peers == index).map(|x| x.1).collect());
+        let forward = engine.snapshot().0;
+        engine.finish();
+        forward
+    }).expect("failed to start workers");
+    let forward = guards.join().into_iter().flat_map(|x| x.expect("worker failed")).collect::<Vec<_>>();
+
+    // one worker resumes from both, which already hold each edge both ways.
+    let guards = timely::execute(Configuration::Thread, move |worker| {
+        let plan = undirected();
+        let mut engine = Engine::from_partitioned_snapshot(worker, &plan, forward.clone());
+        let results = batches.iter().map(|batch| engine.step(batch.clone())).collect::<Vec<_>>();
+        engine.finish();
+        results
+    }).expect("failed to start worker");
+    let results = guards.join().pop().unwrap().expect("worker failed");
+
+    assert_eq!(results.len(), expected.len() - 1);
+    for (epoch, (x, y)) in expected[1 ..].iter().zip(results.iter()).enumerate() {
+        let nonzero = |stats: &BatchStats| stats.matches.iter().filter(|x| x.1 != 0).cloned().collect::<Vec<_>>();
+        assert_eq!(nonzero(x), nonzero(y), "changes in matches of epoch {} differ after resuming", epoch + 1);
+        assert_eq!(x.edges, y.edges, "indices of epoch {} hold different edges after resuming", epoch + 1);
+    }
+}
+
+#[test]
+fn unreadable_lines_are_reported() {
+    let path = format!("{}/alg3-unreadable-{}.txt", ::std::env::temp_dir().display(), ::std::process::id());
+    ::std::fs::write(&path, b"1 2\n3 \xff\n5 6\n").expect("couldn't write input");
+
+    // a line that is not UTF-8 is an error naming it, or malformed and skipped if allowed.
+    let error = EdgeReader::open(&path).expect("couldn't open input").read_edges(10).expect_err("unreadable line accepted");
+    assert_eq!((error.path.as_str(), error.line), (path.as_str(), 2));
+    let mut reader = EdgeReader::open(&path).expect("couldn't open input");
+    reader.set_strictness(Strictness::Skip);
+    assert_eq!(reader.read_edges(10).expect("unreadable line not skipped"), vec![(1, 2), (5, 6)]);
+    assert_eq!(reader.stats().skipped, 1);
+
+    ::std::fs::remove_file(&path).expect("couldn't remove input");
+}
+
+#[test]
+fn corrupt_snapshots_are_rejected() {
+    let path = format!("{}/alg3-snapshot-{}.bin", ::std::env::temp_dir().display(), ::std::process::id());
+    let pairs = vec![(1, 2), (1, 3), (4, 0)];
+    write_snapshot(&path, &pairs).expect("couldn't write snapshot");
+    assert_eq!(read_snapshot(&path).expect("couldn't read snapshot"), pairs);
+
+    // a header counting more pairs than the file holds, whether by a little or absurdly many.
+    let bytes = ::std::fs::read(&path).expect("couldn't read snapshot");
+    ::std::fs::write(&path, &bytes[.. bytes.len() - 4]).expect("couldn't write snapshot");
+    assert_eq!(read_snapshot(&path).expect_err("truncated snapshot read").kind(), ::std::io::ErrorKind::InvalidData);
+    let mut corrupt = bytes.clone();
+    corrupt[8 .. 16].copy_from_slice(&u64::max_value().to_le_bytes());
+    ::std::fs::write(&path, &corrupt).expect("couldn't write snapshot");
+    assert_eq!(read_snapshot(&path).expect_err("corrupt snapshot read").kind(), ::std::io::ErrorKind::InvalidData);
+
+    ::std::fs::remove_file(&path).expect("couldn't remove snapshot");
+}
+
+#[test]
+fn contraction_matches_brute_force() {
+    let base = read_edges("base.txt");
+    // 7 is contracted into 0 before the updates, which then name 7, and 8 into 1 after them;
+    // neither adds an edge the graph already has, which indices would read only once.
+    let (first, last) = (vec![(7, 0)], vec![(8, 1)]);
+
+    for &orientation in [Orientation::Directed, Orientation::Undirected].iter() {
+        // undirected graphs list each edge once, and the log adds 3 5 alongside the base graph's 5 3.
+        let batches = read_batches("updates.log", 4).into_iter().map(|batch| {
+            batch.into_iter().filter(|update| orientation == Orientation::Directed || update.0 != (3, 5)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let plan = move || {
+            let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+            plan.set_orientation(orientation);
+            plan
+        };
+        let pattern = plan().pattern_edges(plan().queries()[0]).expect("query unreachable");
+
+        let (base2, batches2, first2, last2) = (base.clone(), batches.clone(), first.clone(), last.clone());
+        let guards = timely::execute(Configuration::Process(2), move |worker| {
+            let index = worker.index();
+            let mut engine: Engine<_> = Engine::new(worker, &plan());
+            let mut results = vec![engine.load_base(if index == 0 { base2.clone() } else { Vec::new() })];
+            results.push(engine.contract(&first2));
+            for batch in batches2.iter() {
+                results.push(engine.step(if index == 0 { batch.clone() } else { Vec::new() }));
+            }
+            results.push(engine.contract(&last2));
+            engine.finish();
+            results.iter().map(total).collect::<Vec<_>>()
+        }).expect("failed to start workers");
+        let totals = guards.join().into_iter().map(|x| x.expect("worker failed")).collect::<Vec<_>>();
+        let changes = (0 .. totals[0].len()).map(|epoch| totals.iter().map(|x| x[epoch]).sum::<i64>()).collect::<Vec<_>>();
+
+        // the graph of every update so far, with contracted vertices renamed, read both ways if undirected.
+        let count = |updates: &[(Edge, i32)], aliases: &HashMap<Node, Node>| {
+            let resolve = |node: Node| *aliases.get(&node).unwrap_or(&node);
+            let mut renamed = Vec::new();
+            for &((src, dst), diff) in updates.iter() {
+                let (src, dst) = (resolve(src), resolve(dst));
+                renamed.push(((src, dst), diff));
+                if orientation == Orientation::Undirected && src != dst { renamed.push(((dst, src), diff)); }
+            }
+            let mut graph = Graph::default();
+            graph.apply(&renamed);
+            graph.count(3, &pattern)
+        };
+        let mut updates = base.iter().map(|&edge| (edge, 1)).collect::<Vec<_>>();
+        let mut aliases = HashMap::new();
+        let mut expected = vec![0];
+        let before = count(&updates, &aliases);
+        aliases.insert(first[0].0, first[0].1);
+        expected.push(count(&updates, &aliases) - before);
+        for batch in batches.iter() {
+            let before = count(&updates, &aliases);
+            updates.extend(batch.iter().cloned());
+            expected.push(count(&updates, &aliases) - before);
+        }
+        let before = count(&updates, &aliases);
+        aliases.insert(last[0].0, last[0].1);
+        expected.push(count(&updates, &aliases) - before);
+
+        assert_eq!(changes, expected, "changes in matches of {:?} contractions differ from brute force", orientation);
+    }
+}
+
+#[test]
+fn contraction_is_rejected_under_degree_filters() {
+    let guards = timely::execute(Configuration::Thread, move |worker| {
+        let mut plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+        plan.set_min_degree(Some(2));
+        let mut engine: Engine<_> = Engine::new(worker, &plan);
+        engine.load_base(read_edges("base.txt"));
+        engine.contract(&[(7, 0)]);
+    }).expect("failed to start worker");
+    assert!(guards.join().into_iter().all(|result| result.is_err()), "vertices were contracted under a degree filter");
+}
+
+#[test]
+fn columnar_batches_match_brute_force() {
+    let plan = || read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let pattern = plan().pattern_edges(plan().queries()[0]).expect("query unreachable");
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    for &workers in [1, 2].iter() {
+        // each worker introduces every other update of each batch, as columns for every worker.
+        let (base2, batches2) = (base.clone(), batches.clone());
+        let guards = timely::execute(Configuration::Process(workers), move |worker| {
+            let (index, peers) = (worker.index(), worker.peers());
+            let mut engine: Engine<_> = Engine::new(worker, &plan());
+            let mut results = vec![engine.load_base(if index == 0 { base2.clone() } else { Vec::new() })];
+            for batch in batches2.iter() {
+                let mine = batch.iter().enumerate().filter(|x| x.0 % peers == index).map(|x| *x.1).collect::<Vec<_>>();
+                let columns = Columns::from_updates(&mine);
+                assert_eq!(columns.to_updates(), mine);
+                results.push(engine.step_columns(&columns.src, &columns.dst, &columns.diff));
+            }
+            engine.finish();
+            results.iter().map(total).collect::<Vec<_>>()
+        }).expect("failed to start workers");
+        let totals = guards.join().into_iter().map(|x| x.expect("worker failed")).collect::<Vec<_>>();
+        let changes = (0 .. totals[0].len()).map(|epoch| totals.iter().map(|x| x[epoch]).sum::<i64>()).collect::<Vec<_>>();
+
+        let mut graph = Graph::from(&base);
+        let mut expected = vec![0];
+        for batch in batches.iter() {
+            let before = graph.count(3, &pattern);
+            graph.apply(batch);
+            expected.push(graph.count(3, &pattern) - before);
+        }
+        assert_eq!(changes, expected, "changes in matches of columnar batches on {} workers differ from brute force", workers);
+    }
+}
+
+#[test]
+fn attributed_motif_changes_sum_to_the_motif() {
+    let triangle = vec![(0, 1), (0, 2), (1, 2)];
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+
+    // the changes of each epoch to the motif, and to each motif edge's stream, as (epoch, stream, embedding, diff).
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let (changes2, triangle2, base2, batches2) = (changes.clone(), triangle.clone(), base.clone(), batches.clone());
+    timely::execute(Configuration::Process(2), move |worker| {
+        let (index, peers) = (worker.index(), worker.peers());
+        let (changes, triangle) = (changes2.clone(), triangle2.clone());
+        let (mut graph, mut delta, probe) = worker.dataflow::<Node,_,_>(move |builder| {
+            let (graph_input, graph) = builder.new_input::<Edge>();
+            let (delta_input, delta) = builder.new_input::<(Edge, i32)>();
+            let (index, _) = motif::GraphStreamIndex::from(graph, delta, |k| k as u64, |k| k as u64);
+            let (motifs, attributed) = index.track_motif_attributed(&triangle);
+            assert_eq!(attributed.len(), triangle.len());
+            let mut probe = ProbeHandle::new();
+            for (stream, changes_of) in Some(motifs).into_iter().chain(attributed.into_iter()).enumerate() {
+                let changes = changes.clone();
+                changes_of.inspect_batch(move |time, data| {
+                    let mut changes = changes.lock().unwrap();
+                    changes.extend(data.iter().map(|&(ref embedding, diff)| (time.inner, stream, embedding.clone(), diff as i64)));
+                }).probe_with(&mut probe);
+            }
+            (graph_input, delta_input, probe)
+        });
+
+        for (position, &edge) in base2.iter().enumerate() {
+            if position % peers == index { graph.send(edge); }
+        }
+        for (epoch, batch) in batches2.iter().enumerate() {
+            graph.advance_to(epoch as Node + 1);
+            delta.advance_to(epoch as Node + 1);
+            for (position, &update) in batch.iter().enumerate() {
+                if position % peers == index { delta.send(update); }
+            }
+        }
+        graph.close();
+        delta.close();
+        while worker.step() { }
+        assert!(probe.done());
+    }).expect("failed to start workers").join().into_iter().for_each(|result| { result.expect("worker failed"); });
+
+    let changes = changes.lock().unwrap();
+    assert_eq!(changes.iter().filter(|change| change.0 == 0).count(), 0, "loading the base graph produced changes");
+    let mut graph = Graph::from(&base);
+    for (epoch, batch) in batches.iter().enumerate() {
+        let epoch = epoch as Node + 1;
+        let updated = batch.iter().map(|update| update.0).collect::<Vec<_>>();
+
+        // each change is caused by an update to the edge of the stream it is attributed to.
+        for &(_, stream, ref embedding, _) in changes.iter().filter(|change| change.0 == epoch && change.1 > 0) {
+            let (src, dst) = triangle[stream - 1];
+            assert!(updated.contains(&(embedding[src], embedding[dst])), "change {:?} attributed to motif edge {} not updated in batch {}", embedding, stream - 1, epoch);
+        }
+
+        // the motif's changes, and the sum of the attributed changes, are the changes in embeddings.
+        let mut expected = BTreeMap::new();
+        for (embedding, weight) in graph.embeddings(3, &triangle) { *expected.entry(embedding).or_insert(0) -= weight; }
+        graph.apply(batch);
+        for (embedding, weight) in graph.embeddings(3, &triangle) { *expected.entry(embedding).or_insert(0) += weight; }
+        expected.retain(|_, diff| *diff != 0);
+        for &attributed in [false, true].iter() {
+            let mut found = BTreeMap::new();
+            for &(_, _, ref embedding, diff) in changes.iter().filter(|change| change.0 == epoch && (change.1 > 0) == attributed) {
+                *found.entry(embedding.clone()).or_insert(0) += diff;
+            }
+            found.retain(|_, diff| *diff != 0);
+            assert_eq!(found, expected, "changes of batch {} differ from brute force (attributed: {})", epoch, attributed);
+        }
+    }
+}
+
+#[test]
+fn pipelined_batches_match_brute_force() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 6);
+    let mut expected = vec![0];
+    expected.extend(triangle_changes(&base, &batches));
+
+    for &chunks in [1, 2, 3, 8].iter() {
+        let (base2, batches2) = (base.clone(), batches.clone());
+        let changes = run_triangles(move |engine, index| {
+            let mut results = vec![engine.load_base(share(&base2, index))];
+            for batch in batches2.iter() {
+                let first = engine.epoch();
+                results.push(engine.step_pipelined(share(batch, index), chunks));
+                assert_eq!(engine.epoch(), first + chunks as u32, "a batch of {} chunks did not consume one epoch per chunk", chunks);
+            }
+            results
+        });
+        assert_eq!(changes, expected, "changes in matches of batches of {} chunks differ from brute force", chunks);
+    }
+}
+
+#[test]
+fn bulk_loads_match_brute_force() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let (parts, seeded) = (base[.. base.len() - 3].chunks(3).map(|part| part.to_vec()).collect::<Vec<_>>(), base[base.len() - 3 ..].to_vec());
+
+    // the seed epoch reports the matches its edges complete, and later epochs the changes of each batch.
+    let mut expected = vec![0; parts.len()];
+    expected.extend(triangle_changes(&base[.. base.len() - 3], &[seeded.iter().map(|&edge| (edge, 1)).collect()]));
+    expected.extend(triangle_changes(&base, &batches));
+    assert!(expected[parts.len()] != 0, "the seeded edges complete no matches");
+
+    let (parts2, seeded2, batches2) = (parts.clone(), seeded.clone(), batches.clone());
+    let changes = run_triangles(move |engine, index| {
+        let mut results = parts2.iter().map(|part| engine.bulk_load(share(part, index))).collect::<Vec<_>>();
+        results.push(engine.seed(share(&seeded2, index)));
+        for batch in batches2.iter() {
+            results.push(engine.step(share(batch, index)));
+        }
+        results
+    });
+    assert_eq!(changes, expected, "changes in matches of a bulk load differ from brute force");
+
+    // seeding with no edges skips the base graph's matches.
+    let (base2, batches2) = (base.clone(), batches.clone());
+    let changes = run_triangles(move |engine, index| {
+        let mut results = base2.chunks(4).map(|part| engine.bulk_load(share(part, index))).collect::<Vec<_>>();
+        results.push(engine.seed(Vec::new()));
+        results.extend(batches2.iter().map(|batch| engine.step(share(batch, index))));
+        results
+    });
+    let mut expected = vec![0; (base.len() + 3) / 4 + 1];
+    expected.extend(triangle_changes(&base, &batches));
+    assert_eq!(changes, expected, "changes in matches of a bulk load seeded with no edges differ from brute force");
+}
+
+#[test]
+fn cursors_page_through_retained_changes() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 3);
+
+    let (base2, batches2) = (base.clone(), batches.clone());
+    let guards = timely::execute(Configuration::Process(2), move |worker| {
+        let index = worker.index();
+        let mut engine: Engine<_> = Engine::recording(worker, &read_plan_from(TRIANGLE_PLAN.as_bytes()));
+        engine.load_base(share(&base2, index));
+        engine.set_retention(Some(2));
+
+        // the changes of each batch, read in pages, and unread cursors for the first two batches.
+        let mut pages = Vec::new();
+        let mut held = Vec::new();
+        for (position, batch) in batches2.iter().enumerate() {
+            let epoch = engine.epoch();
+            let stats = engine.step(share(batch, index));
+            assert!(stats.embeddings.is_empty(), "retained changes were reported in the statistics of epoch {}", epoch);
+            assert_eq!(engine.retained_epochs().last(), Some(&epoch));
+            // the first two batches' changes are held by their unread cursors.
+            if position > 2 {
+                assert!(!engine.retained_epochs().contains(&(epoch - 1)), "changes of epoch {} retained after their cursor was dropped", epoch - 1);
+            }
+            if position < 2 { held.push(engine.cursor(epoch).expect("changes of a completed epoch not retained")); }
+            if position == 2 {
+                assert_eq!(held[0].next_page(1), Err(CursorExpired), "changes of epoch {} retained past the limit", held[0].epoch());
+                assert_eq!(held[0].remaining(), Err(CursorExpired));
+                assert!(held[1].remaining().is_ok(), "changes of epoch {} expired within the limit", held[1].epoch());
+            }
+
+            let mut cursor = engine.cursor(epoch).expect("changes of a completed epoch not retained");
+            assert_eq!(cursor.epoch(), epoch);
+            let remaining = cursor.remaining().expect("retained changes expired");
+            let mut changes = Vec::new();
+            loop {
+                let page = cursor.next_page(2).expect("retained changes expired");
+                if page.is_empty() { break; }
+                assert!(page.len() <= 2, "page of {} changes", page.len());
+                changes.extend(page);
+            }
+            assert_eq!(changes.len(), remaining);
+            pages.push(changes);
+        }
+        engine.finish();
+        pages
+    }).expect("failed to start workers");
+    let pages = guards.join().into_iter().map(|x| x.expect("worker failed")).collect::<Vec<_>>();
+
+    // the changes of each batch, summed over workers and query nodes, are the changes in triangles,
+    // each named by its edges, as query nodes bind the vertices of a triangle in different orders.
+    let plan = read_plan_from(TRIANGLE_PLAN.as_bytes());
+    let patterns = plan.queries().into_iter().map(|query| (query, plan.pattern_edges(query).expect("query unreachable"))).collect::<HashMap<_,_>>();
+    let pattern = &patterns[&plan.queries()[0]];
+    let edges = |pattern: &[(usize, usize)], embedding: &[Node]| {
+        let mut edges = pattern.iter().map(|&(src, dst)| (embedding[src], embedding[dst])).collect::<Vec<_>>();
+        edges.sort();
+        edges
+    };
+    let mut graph = Graph::from(&base);
+    for (position, batch) in batches.iter().enumerate() {
+        let mut expected = BTreeMap::new();
+        for (embedding, weight) in graph.embeddings(3, pattern) { *expected.entry(edges(pattern, &embedding)).or_insert(0) -= weight; }
+        graph.apply(batch);
+        for (embedding, weight) in graph.embeddings(3, pattern) { *expected.entry(edges(pattern, &embedding)).or_insert(0) += weight; }
+        expected.retain(|_, diff| *diff != 0);
+        let mut found = BTreeMap::new();
+        for &(query, ref embedding, diff) in pages.iter().flat_map(|worker| worker[position].iter()) {
+            *found.entry(edges(&patterns[&query], embedding)).or_insert(0) += diff;
+        }
+        found.retain(|_, diff| *diff != 0);
+        assert_eq!(found, expected, "changes read through cursors for batch {} differ from brute force", position);
+    }
+}
+
+#[test]
+fn staged_updates_match_brute_force() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut expected = vec![0];
+    expected.extend(triangle_changes(&base, &batches));
+
+    // the base graph as timed updates, one edge inserted twice and retracted once.
+    let mut timed_base = base.iter().map(|&edge| (edge, 0, 1)).collect::<Vec<_>>();
+    timed_base.push((base[0], 3, 1));
+    timed_base.push((base[0], 5, -1));
+
+    // the batches staged latest first, each at its own time, and introduced one or two at a time.
+    for &step in [1, 2].iter() {
+        let (timed_base2, batches2) = (timed_base.clone(), batches.clone());
+        let changes = run_triangles(move |engine, index| {
+            // the updates to an edge accumulate at the worker loading them, so one worker loads them all.
+            let timed_base = if index == 0 { timed_base2.clone() } else { Vec::new() };
+            let mut results = vec![engine.load_base_timed(timed_base).expect("base graph not loaded")];
+            let first = engine.epoch();
+            for (position, batch) in batches2.iter().enumerate().rev() {
+                let time = first + position as u32;
+                engine.stage(share(batch, index).into_iter().map(|(edge, diff)| (edge, time, diff)).collect()).expect("update staged late");
+            }
+            assert_eq!(engine.staged(), batches2.iter().map(|batch| share(batch, index).len()).sum::<usize>());
+            let end = first + batches2.len() as u32;
+            let mut frontier = first;
+            while frontier < end {
+                frontier = ::std::cmp::min(frontier + step, end);
+                results.push(engine.advance_frontier(frontier));
+            }
+            assert_eq!(engine.staged(), 0);
+            match engine.stage(vec![((0, 1), first, 1)]) {
+                Err(TimedInputError::Late { time, frontier }) => assert_eq!((time, frontier), (first, end)),
+                other => panic!("update staged before the frontier: {:?}", other),
+            }
+            assert_eq!(engine.staged(), 0, "a late update was staged");
+            results
+        });
+        let mut grouped = vec![0];
+        grouped.extend(expected[1 ..].chunks(step as usize).map(|changes| changes.iter().sum::<i64>()));
+        assert_eq!(changes, grouped, "changes in matches of staged updates introduced {} times at once differ from brute force", step);
+    }
+
+    let guards = timely::execute(Configuration::Thread, move |worker| {
+        let mut engine: Engine<_> = Engine::new(worker, &read_plan_from(TRIANGLE_PLAN.as_bytes()));
+        engine.load_base_timed(vec![((0, 1), 0, 1), ((0, 1), 1, -2)]).map(|_| ())
+    }).expect("failed to start worker");
+    for result in guards.join() {
+        assert_eq!(result.expect("worker failed"), Err(TimedInputError::NegativeBase((0, 1))));
+    }
+}
+
+#[test]
+fn committed_batches_match_brute_force() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut expected = vec![0];
+    expected.extend(triangle_changes(&base, &batches));
+
+    let (base2, batches2) = (base.clone(), batches.clone());
+    let changes = run_triangles(move |engine, index| {
+        let mut results = vec![engine.load_base(share(&base2, index))];
+        for batch in batches2.iter() {
+            // a batch retracting every base edge is assembled and aborted, consuming no epoch.
+            let epoch = engine.epoch();
+            let retractions = share(&base2, index).into_iter().map(|edge| (edge, -1)).collect::<Vec<_>>();
+            engine.push_updates(&retractions);
+            assert_eq!(engine.abort_batch(), retractions.len());
+            assert_eq!((engine.pending(), engine.epoch()), (0, epoch));
+
+            // as is one rejected by validation, before the batch itself is assembled and committed.
+            engine.push_updates(&retractions);
+            assert_eq!(engine.commit_batch_with(|updates| if updates.len() == retractions.len() { Err(()) } else { Ok(()) }).err(), Some(()));
+            assert_eq!((engine.pending(), engine.epoch()), (0, epoch));
+            for update in share(batch, index) {
+                engine.push_updates(&[update]);
+            }
+            results.push(engine.commit_batch());
+        }
+        results
+    });
+    assert_eq!(changes, expected, "changes in matches of committed batches differ from brute force");
+}
+
+#[test]
+fn bundled_updates_match_brute_force() {
+    let base = read_edges("base.txt");
+    let batches = read_batches("updates.log", 4);
+    let mut expected = vec![0];
+    expected.extend(triangle_changes(&base, &batches));
+
+    // the updates of each batch grouped by source and diff, as upstream aggregation bundles them.
+    let bundle = |updates: &[(Edge, i32)]| {
+        let mut bundles: Vec<Bundle> = Vec::new();
+        for &((src, dst), diff) in updates.iter() {
+            match bundles.iter_mut().find(|bundle| bundle.0 == src && bundle.2 == diff) {
+                Some(bundle) => bundle.1.push(dst),
+                None => bundles.push((src, vec![dst], diff)),
+            }
+        }
+        bundles
+    };
+    for &pushed in [false, true].iter() {
+        let (base2, batches2) = (base.clone(), batches.clone());
+        let changes = run_triangles(move |engine, index| {
+            let mut results = vec![engine.load_base(share(&base2, index))];
+            for batch in batches2.iter() {
+                let bundles = bundle(&share(batch, index));
+                assert_eq!(bundles.iter().map(|bundle| bundle.1.len()).sum::<usize>(), share(batch, index).len());
+                if pushed {
+                    engine.push_bundles(&bundles);
+                    results.push(engine.commit_batch());
+                } else {
+                    results.push(engine.step_bundles(&bundles));
+                }
+            }
+            results
+        });
+        assert_eq!(changes, expected, "changes in matches of bundled updates differ from brute force (pushed: {})", pushed);
+    }
+}